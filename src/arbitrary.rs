@@ -0,0 +1,373 @@
+//! `Arbitrary` implementations for options and the blocks read by
+//! `reader`, for fuzzing and property tests.
+//!
+//! The values are valid: they encode without errors, and the blocks
+//! are read back as the same bytes. Each block only gets the options
+//! of its type, along with comments, raw options and possibly an
+//! `opt_endofopt` at the end.
+
+use crate::blocks::options::*;
+use crate::blocks::NameRecord;
+use crate::constants::OPTION_MAX_LEN;
+use crate::enums::BlockType;
+use crate::utils::Timestamp;
+use crate::writer::Endianness;
+use ::arbitrary::{Arbitrary, Result, Unstructured};
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Returns a string of at most `max_len` bytes.
+fn text(u: &mut Unstructured<'_>, max_len: usize) -> Result<String> {
+    let s: &str = u.arbitrary()?;
+    let mut end = s.len().min(max_len);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    Ok(s[..end].to_string())
+}
+
+/// Returns at most `max_len` bytes.
+fn bytes<'a>(u: &mut Unstructured<'a>, max_len: usize) -> Result<&'a [u8]> {
+    let b: &[u8] = u.arbitrary()?;
+    Ok(&b[..b.len().min(max_len)])
+}
+
+/// Implements `Arbitrary` for options holding a string.
+macro_rules! arbitrary_text {
+    ($($name:ident),*) => {
+        $(
+            impl<'a> Arbitrary<'a> for $name {
+                fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+                    Ok(Self::new(&text(u, OPTION_MAX_LEN)?))
+                }
+            }
+        )*
+    };
+}
+
+arbitrary_text!(
+    OptionComment,
+    OptionShbHardware,
+    OptionShbOs,
+    OptionShbUserAppl,
+    OptionIfName,
+    OptionIfDescription
+);
+
+/// Implements `Arbitrary` for options whose `new` takes a value that
+/// is always valid.
+macro_rules! arbitrary_value {
+    ($($name:ident($value:ty)),*) => {
+        $(
+            impl<'a> Arbitrary<'a> for $name {
+                fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+                    Ok(Self::new(<$value>::arbitrary(u)?))
+                }
+            }
+        )*
+    };
+}
+
+arbitrary_value!(
+    OptionIfSpeed(u64),
+    OptionIfTsResol(u8),
+    OptionIfFcsLen(FcsLen),
+    OptionEpbQueue(u32),
+    OptionEpbVerdict(VerdictType),
+    OptionEpbDropCount(u64),
+    OptionIsbStartTime(Timestamp),
+    OptionIsbEndTime(Timestamp),
+    OptionIsbIfRecv(u64),
+    OptionIsbIfDrop(u64),
+    OptionIsbFilterAccept(u64),
+    OptionIsbOsDrop(u64),
+    OptionIsbUsrDeliv(u64)
+);
+
+impl<'a> Arbitrary<'a> for Timestamp {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self::new(u.arbitrary()?))
+    }
+}
+
+impl<'a> Arbitrary<'a> for Endianness {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(if u.arbitrary()? {
+            Endianness::Big
+        } else {
+            Endianness::Little
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for OptionEndOfOpt {
+    fn arbitrary(_u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self::new())
+    }
+}
+
+impl<'a> Arbitrary<'a> for OptionIfIpv4Addr {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let ip: [u8; 4] = u.arbitrary()?;
+        let netmask: [u8; 4] = u.arbitrary()?;
+        Ok(Self::from_addrs(
+            Ipv4Addr::from(ip),
+            Ipv4Addr::from(netmask),
+        ))
+    }
+}
+
+impl<'a> Arbitrary<'a> for OptionIfIpv6Addr {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let ip: [u8; 16] = u.arbitrary()?;
+        let prefix_len = u.int_in_range(0..=128)?;
+        Ok(Self::from_addr(Ipv6Addr::from(ip), prefix_len).unwrap())
+    }
+}
+
+impl<'a> Arbitrary<'a> for OptionIfMacAddr {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self::from_bytes(u.arbitrary()?))
+    }
+}
+
+impl<'a> Arbitrary<'a> for FcsLen {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self::new(u.int_in_range(0..=0b1111)?).unwrap())
+    }
+}
+
+impl<'a> Arbitrary<'a> for FilterType {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&[FilterType::Libpcap, FilterType::Bpf])?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for OptionIfFilter {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let filter_type = u.arbitrary()?;
+        Ok(Self::with_data(filter_type, bytes(u, OPTION_MAX_LEN - 1)?))
+    }
+}
+
+impl<'a> Arbitrary<'a> for OptionEpbFlags {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self::from_u32(u.arbitrary()?))
+    }
+}
+
+impl<'a> Arbitrary<'a> for HashAlgorithm {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(HashAlgorithm::from_code(u.int_in_range(0..=5)?).unwrap())
+    }
+}
+
+impl<'a> Arbitrary<'a> for OptionEpbHash {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let algorithm: HashAlgorithm = u.arbitrary()?;
+        let digest = match algorithm.digest_len() {
+            Some(len) => {
+                let mut digest = vec![0; len];
+                u.fill_buffer(&mut digest)?;
+                digest
+            }
+            None => bytes(u, OPTION_MAX_LEN - 1)?.to_vec(),
+        };
+        Ok(Self::new(algorithm, &digest))
+    }
+}
+
+impl<'a> Arbitrary<'a> for VerdictType {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=2)? {
+            0 => Self::Hardware(bytes(u, OPTION_MAX_LEN - 1)?.to_vec()),
+            1 => Self::LinuxEbpfTc(u.arbitrary()?),
+            _ => Self::LinuxEbpfXdp(u.arbitrary()?),
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for RawOption {
+    /// Creates a raw option with any code but that of
+    /// `opt_endofopt`.
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let code = u.int_in_range(1..=u16::MAX)?;
+        let value = bytes(u, OPTION_MAX_LEN)?;
+        Ok(Self::new(code, value.len() as u16, value.to_vec()))
+    }
+}
+
+impl<'a> Arbitrary<'a> for NameRecord {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let addr = if u.arbitrary()? {
+            IpAddr::V4(Ipv4Addr::from(u.arbitrary::<[u8; 4]>()?))
+        } else {
+            IpAddr::V6(Ipv6Addr::from(u.arbitrary::<[u8; 16]>()?))
+        };
+        let mut names = Vec::new();
+        let mut len = 16;
+        for _ in 0..u.arbitrary_len::<u8>()? {
+            // each name is followed by a zero byte
+            let name = text(u, OPTION_MAX_LEN - len - 1)?.replace('\0', "");
+            len += name.len() + 1;
+            names.push(name);
+            if len >= OPTION_MAX_LEN {
+                break;
+            }
+        }
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        Ok(Self::new(addr, &names))
+    }
+}
+
+impl<'a> Arbitrary<'a> for BlockOption {
+    /// Creates an option of any of the block types, a comment or a
+    /// raw option.
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let block_type = u.choose(&[
+            BlockType::SectionHeader,
+            BlockType::InterfaceDescription,
+            BlockType::EnhancedPacket,
+            BlockType::InterfaceStatistics,
+        ])?;
+        block_option(u, block_type)
+    }
+}
+
+/// Returns an option of `block_type`, a comment or a raw option.
+fn block_option(u: &mut Unstructured<'_>, block_type: &BlockType) -> Result<BlockOption> {
+    match u.int_in_range(0..=5)? {
+        0 => return Ok(BlockOption::OptComment(u.arbitrary()?)),
+        1 => return Ok(BlockOption::Raw(u.arbitrary()?)),
+        _ => (),
+    }
+    Ok(match block_type {
+        BlockType::SectionHeader => match u.int_in_range(0..=2)? {
+            0 => BlockOption::ShbHardware(u.arbitrary()?),
+            1 => BlockOption::ShbOs(u.arbitrary()?),
+            _ => BlockOption::ShbUserAppl(u.arbitrary()?),
+        },
+        BlockType::InterfaceDescription => match u.int_in_range(0..=8)? {
+            0 => BlockOption::IfName(u.arbitrary()?),
+            1 => BlockOption::IfDescription(u.arbitrary()?),
+            2 => BlockOption::IfIpv4Addr(u.arbitrary()?),
+            3 => BlockOption::IfIpv6Addr(u.arbitrary()?),
+            4 => BlockOption::IfMacAddr(u.arbitrary()?),
+            5 => BlockOption::IfSpeed(u.arbitrary()?),
+            6 => BlockOption::IfTsResol(u.arbitrary()?),
+            7 => BlockOption::IfFcsLen(u.arbitrary()?),
+            _ => BlockOption::IfFilter(u.arbitrary()?),
+        },
+        BlockType::EnhancedPacket => match u.int_in_range(0..=4)? {
+            0 => BlockOption::EpbFlags(u.arbitrary()?),
+            1 => BlockOption::EpbHash(u.arbitrary()?),
+            2 => BlockOption::EpbDropCount(u.arbitrary()?),
+            3 => BlockOption::EpbQueue(u.arbitrary()?),
+            _ => BlockOption::EpbVerdict(u.arbitrary()?),
+        },
+        BlockType::InterfaceStatistics => match u.int_in_range(0..=6)? {
+            0 => BlockOption::IsbStartTime(u.arbitrary()?),
+            1 => BlockOption::IsbEndTime(u.arbitrary()?),
+            2 => BlockOption::IsbIfRecv(u.arbitrary()?),
+            3 => BlockOption::IsbIfDrop(u.arbitrary()?),
+            4 => BlockOption::IsbFilterAccept(u.arbitrary()?),
+            5 => BlockOption::IsbOsDrop(u.arbitrary()?),
+            _ => BlockOption::IsbUsrDeliv(u.arbitrary()?),
+        },
+        _ => BlockOption::OptComment(u.arbitrary()?),
+    })
+}
+
+/// Returns the options of a block of `block_type`, possibly ended by
+/// an `opt_endofopt`.
+pub(crate) fn block_options(
+    u: &mut Unstructured<'_>,
+    block_type: BlockType,
+) -> Result<Vec<BlockOption>> {
+    let mut options = Vec::new();
+    for _ in 0..u.int_in_range(0..=8)? {
+        options.push(block_option(u, &block_type)?);
+    }
+    if !options.is_empty() && u.arbitrary()? {
+        options.push(OptionEndOfOpt::new_option());
+    }
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{Block, SliceReader};
+    use crate::writer::{Encodable, EncodedLen, PcapNgWriter};
+
+    /// Returns pseudo-random bytes for `Unstructured`.
+    fn data(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn options_encode() {
+        for seed in 0..500 {
+            let data = data(seed, 256);
+            let option: BlockOption = Unstructured::new(&data).arbitrary().unwrap();
+            let mut buf = vec![];
+            option.encode::<byteorder::LittleEndian>(&mut buf).unwrap();
+            assert_eq!(buf.len(), option.encoded_len());
+        }
+    }
+
+    #[test]
+    fn blocks_round_trip() {
+        for seed in 0..500 {
+            let data = data(seed, 512);
+            let mut u = Unstructured::new(&data);
+            let shb: Block = u.arbitrary().unwrap();
+            let block: Block = u.arbitrary().unwrap();
+            let endianness = match &shb {
+                Block::SectionHeader(shb) => shb.endianness(),
+                _ => Endianness::Little,
+            };
+            let mut writer = PcapNgWriter::new(endianness, vec![]);
+            if !matches!(shb, Block::SectionHeader(_)) {
+                writer.start_new_section(&Options::new()).unwrap();
+            }
+            writer.write(&shb).unwrap();
+            writer.write(&block).unwrap();
+            let written = writer.get_writer();
+
+            let mut rewriter = PcapNgWriter::new(endianness, vec![]);
+            for block in SliceReader::new(written) {
+                rewriter.write(&block.unwrap()).unwrap();
+            }
+            assert_eq!(rewriter.get_writer(), written, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn name_records() {
+        for seed in 0..100 {
+            let data = data(seed, 256);
+            let record: NameRecord = Unstructured::new(&data).arbitrary().unwrap();
+            assert!(NameRecord::try_new(
+                record.addr(),
+                &record
+                    .names()
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+            )
+            .is_ok());
+            assert!(record.names().iter().all(|name| !name.contains('\0')));
+        }
+    }
+}
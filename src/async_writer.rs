@@ -0,0 +1,207 @@
+//! Asynchronous writing on top of `futures::io::AsyncWrite`.
+//!
+//! Blocks are encoded into a reusable buffer, which is then written
+//! to the `AsyncWrite`. This works with any runtime that implements
+//! the `futures-io` traits, e.g. async-std, smol, or tokio through
+//! `tokio-util`'s compat layer.
+
+use crate::error::Result;
+use crate::writer::{Encodable, Endianness};
+use byteorder::{BigEndian, LittleEndian};
+use futures_io::AsyncWrite;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// The asynchronous counterpart of `writer::PcapNgWriter`.
+///
+/// ```
+/// # futures_executor::block_on(async {
+/// use pcapng_writer::async_writer::AsyncPcapNgWriter;
+/// use pcapng_writer::blocks::options::Options;
+/// use pcapng_writer::blocks::SectionHeaderBlock;
+///
+/// let opts = Options::new();
+/// let mut writer = AsyncPcapNgWriter::new_le(vec![]);
+/// writer
+///     .write(&SectionHeaderBlock::new_with_defaults(&opts))
+///     .await
+///     .unwrap();
+/// assert_eq!(writer.get_writer().len(), 28);
+/// # });
+/// ```
+#[derive(Debug)]
+pub struct AsyncPcapNgWriter<W: AsyncWrite + Unpin> {
+    endianness: Endianness,
+    writer: W,
+    buf: Vec<u8>,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncPcapNgWriter<W> {
+    /// Creates a new asynchronous pcapng writer.
+    pub fn new(endianness: Endianness, writer: W) -> Self {
+        Self {
+            endianness,
+            writer,
+            buf: vec![],
+        }
+    }
+
+    /// Creates a new little-endian asynchronous pcapng writer.
+    pub fn new_le(writer: W) -> Self {
+        Self::new(Endianness::Little, writer)
+    }
+
+    /// Creates a new big-endian asynchronous pcapng writer.
+    pub fn new_be(writer: W) -> Self {
+        Self::new(Endianness::Big, writer)
+    }
+
+    /// Creates a new asynchronous pcapng writer with the byte order of
+    /// the host.
+    pub fn new_native(writer: W) -> Self {
+        Self::new(Endianness::native(), writer)
+    }
+
+    /// Serializes a block and writes it to the underlying writer.
+    pub async fn write<T: Encodable<Vec<u8>>>(&mut self, block: &T) -> Result<()> {
+        self.buf.clear();
+        match self.endianness {
+            Endianness::Little => block.encode::<LittleEndian>(&mut self.buf)?,
+            Endianness::Big => block.encode::<BigEndian>(&mut self.buf)?,
+        }
+        WriteAll {
+            writer: &mut self.writer,
+            buf: &self.buf,
+        }
+        .await?;
+        Ok(())
+    }
+
+    /// Flushes the underlying writer.
+    pub async fn flush(&mut self) -> Result<()> {
+        Flush {
+            writer: &mut self.writer,
+        }
+        .await?;
+        Ok(())
+    }
+
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    /// Returns an immutable reference to the underlying writer.
+    pub fn get_writer(&self) -> &W {
+        &self.writer
+    }
+
+    /// Returns a mutable reference to the underlying writer.
+    pub fn get_writer_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+struct WriteAll<'a, W> {
+    writer: &'a mut W,
+    buf: &'a [u8],
+}
+
+impl<W: AsyncWrite + Unpin> Future for WriteAll<'_, W> {
+    type Output = io::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        while !this.buf.is_empty() {
+            match Pin::new(&mut *this.writer).poll_write(cx, this.buf) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Poll::Ready(Ok(n)) => this.buf = &this.buf[n..],
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+struct Flush<'a, W> {
+    writer: &'a mut W,
+}
+
+impl<W: AsyncWrite + Unpin> Future for Flush<'_, W> {
+    type Output = io::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut *self.writer).poll_flush(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::Options;
+    use crate::blocks::{EnhancedPacketBlock, SectionHeaderBlock};
+    use crate::writer::PcapNgWriter;
+    use futures_executor::block_on;
+
+    /// Accepts at most 3 bytes per call and is pending every other
+    /// call.
+    #[derive(Default)]
+    struct Trickle {
+        data: Vec<u8>,
+        pending: bool,
+    }
+
+    impl AsyncWrite for Trickle {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.pending = !self.pending;
+            if self.pending {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let n = buf.len().min(3);
+            self.data.extend_from_slice(&buf[..n]);
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn same_as_sync() {
+        for endianness in [Endianness::Big, Endianness::Little] {
+            let opts = Options::new();
+            let shb = SectionHeaderBlock::new_with_defaults(&opts);
+            let epb = EnhancedPacketBlock::new(0, 1, 2, 5, 5, &[1; 5], &opts);
+
+            let mut expected = vec![];
+            let mut writer = PcapNgWriter::new(endianness, &mut expected);
+            writer.write(&shb).unwrap();
+            writer.write(&epb).unwrap();
+            drop(writer);
+
+            let mut writer = AsyncPcapNgWriter::new(endianness, Trickle::default());
+            block_on(async {
+                writer.write(&shb).await.unwrap();
+                writer.write(&epb).await.unwrap();
+                writer.flush().await.unwrap();
+            });
+            assert_eq!(writer.into_inner().data, expected);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Prints a summary of pcapng files, like capinfos.
+//!
+//! Usage: `pcapnginfo FILE...`
+//!
+//! `-` reads from stdin.
+
+use pcapng_writer::info::capture_info;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::process;
+
+const USAGE: &str = "usage: pcapnginfo FILE...";
+
+fn main() {
+    let paths: Vec<String> = std::env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        process::exit(1);
+    }
+    if paths.iter().any(|p| p == "-h" || p == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    let mut failed = false;
+    for path in &paths {
+        if let Err(e) = print_info(path) {
+            eprintln!("pcapnginfo: {}: {}", path, e);
+            failed = true;
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn print_info(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let input: Box<dyn Read> = match path {
+        "-" => Box::new(io::stdin().lock()),
+        path => Box::new(File::open(path)?),
+    };
+    let info = capture_info(BufReader::new(input))?;
+    println!("File:           {}", path);
+    print!("{}", info);
+    Ok(())
+}
@@ -0,0 +1,62 @@
+//! Converts a text hex dump to a pcapng file.
+//!
+//! Usage: `text2pcapng [-l LINKTYPE] [-t START_NS] [-i INTERVAL_NS] INPUT OUTPUT`
+//!
+//! `-` reads from stdin or writes to stdout. The link type defaults
+//! to Ethernet (1).
+
+use pcapng_writer::enums::LinkType;
+use pcapng_writer::text2pcap::Text2Pcapng;
+use pcapng_writer::writer::PcapNgWriter;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::process;
+use std::time::Duration;
+
+const USAGE: &str = "usage: text2pcapng [-l LINKTYPE] [-t START_NS] [-i INTERVAL_NS] INPUT OUTPUT";
+
+fn main() {
+    if let Err(e) = run(std::env::args().skip(1)) {
+        eprintln!("text2pcapng: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run<I: Iterator<Item = String>>(mut args: I) -> Result<(), Box<dyn std::error::Error>> {
+    let mut link_type = LinkType::Ethernet;
+    let mut start = 0;
+    let mut interval = Duration::from_micros(1);
+    let mut paths = Vec::new();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(USAGE);
+        match arg.as_str() {
+            "-l" => link_type = value()?.parse::<u16>()?.into(),
+            "-t" => start = value()?.parse()?,
+            "-i" => interval = Duration::from_nanos(value()?.parse()?),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => paths.push(arg),
+        }
+    }
+    if paths.len() != 2 {
+        return Err(USAGE.into());
+    }
+    let input: Box<dyn BufRead> = match paths[0].as_str() {
+        "-" => Box::new(io::stdin().lock()),
+        path => Box::new(BufReader::new(File::open(path)?)),
+    };
+    let output: Box<dyn Write> = match paths[1].as_str() {
+        "-" => Box::new(io::stdout().lock()),
+        path => Box::new(File::create(path)?),
+    };
+    let mut writer = PcapNgWriter::new_le(BufWriter::new(output));
+    let packets = Text2Pcapng::new(link_type)
+        .set_start(start)
+        .set_interval(interval)
+        .convert(input, &mut writer)?;
+    writer.close()?;
+    eprintln!("{} packets written", packets);
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use crate::blocks::options::Options;
+use crate::blocks::{write_all_vectored, Block, PADDING};
+use crate::constants::*;
+use crate::enums::*;
+use crate::error::Result;
+use crate::io::Write;
+use crate::utils::pad_to_32;
+use crate::writer::{Encodable, EncodedLen};
+use alloc::borrow::Cow;
+use byteorder::ByteOrder;
+
+/*
+        0                   1                   2                   3
+        0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+       +---------------------------------------------------------------+
+     0 |             Block Type = 0x00000BAD or 0x40000BAD             |
+       +---------------------------------------------------------------+
+     4 |                      Block Total Length                       |
+       +---------------------------------------------------------------+
+     8 |                Private Enterprise Number (PEN)                |
+       +---------------------------------------------------------------+
+    12 /                                                               /
+       /                          Custom Data                          /
+       /              variable length, padded to 32 bits               /
+       /                                                               /
+       +---------------------------------------------------------------+
+       /                                                               /
+       /                      Options (variable)                       /
+       /                                                               /
+       +---------------------------------------------------------------+
+       |                      Block Total Length                       |
+       +---------------------------------------------------------------+
+
+                      Figure 15: Custom Block Format
+*/
+
+/// Represents a [Custom Block](https://www.ietf.org/archive/id/draft-ietf-opsawg-pcapng-01.html#name-custom-block).
+///
+/// The length of the custom data is not encoded, so data whose length
+/// is not a multiple of 4 is read back with its padding.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CustomBlock<'a> {
+    copyable: bool,
+    pen: u32,
+    custom_data: &'a [u8],
+    options: Cow<'a, Options<'a>>,
+}
+
+impl<'a> CustomBlock<'a> {
+    /// Creates a Custom Block of the organization with the Private
+    /// Enterprise Number `pen`. Tools that rewrite captures may copy
+    /// the block only if it is `copyable`, i.e. if its data does not
+    /// depend on the other blocks of the file.
+    pub fn new(
+        copyable: bool,
+        pen: u32,
+        custom_data: &'a [u8],
+        options: &'a Options,
+    ) -> CustomBlock<'a> {
+        CustomBlock {
+            copyable,
+            pen,
+            custom_data,
+            options: Cow::Borrowed(options),
+        }
+    }
+
+    pub fn copyable(&self) -> bool {
+        self.copyable
+    }
+
+    pub fn pen(&self) -> u32 {
+        self.pen
+    }
+
+    pub fn custom_data(&self) -> &'a [u8] {
+        self.custom_data
+    }
+
+    fn block_type_value(&self) -> u32 {
+        if self.copyable {
+            Self::TYPE.value()
+        } else {
+            BlockType::CustomNoCopy.value()
+        }
+    }
+}
+
+push_option!(CustomBlock);
+
+impl Block for CustomBlock<'_> {
+    const TYPE: BlockType = BlockType::Custom;
+
+    fn length(&self) -> usize {
+        BLOCK_COMMON_LEN
+            + 4
+            + self.custom_data.len()
+            + pad_to_32(self.custom_data.len())
+            + self.options.encoded_len()
+    }
+}
+
+impl EncodedLen for CustomBlock<'_> {
+    fn encoded_len(&self) -> usize {
+        self.length()
+    }
+}
+
+impl<W: Write> Encodable<W> for CustomBlock<'_> {
+    fn encode<B: ByteOrder>(&self, w: &mut W) -> Result<()> {
+        let total_length = self.total_length()?;
+        self.options.check_lengths()?;
+        let mut header = [0u8; 12];
+        B::write_u32_into(
+            &[self.block_type_value(), total_length, self.pen],
+            &mut header,
+        );
+        write_all_vectored(
+            w,
+            [
+                &header,
+                self.custom_data,
+                &PADDING[..pad_to_32(self.custom_data.len())],
+            ],
+        )?;
+        self.options.encode::<B>(w)?;
+        let mut trailer = [0u8; 4];
+        B::write_u32(&mut trailer, total_length);
+        w.write_all(&trailer)?;
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.options.check_lengths()?;
+        Ok(self.options.check_order()?)
+    }
+
+    fn block_type(&self) -> Option<u32> {
+        Some(self.block_type_value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+    use byteorder::{BigEndian, LittleEndian};
+
+    #[test]
+    fn new_cb() {
+        let options = Options::new();
+        let cb = CustomBlock::new(true, 32473, &[1, 2, 3, 4, 5], &options);
+        let mut buf = vec![];
+        cb.encode::<BigEndian>(&mut buf).unwrap();
+        assert_eq!(buf.len(), cb.encoded_len());
+        assert_eq!(&buf[..4], &[0, 0, 0x0b, 0xad]);
+        assert_eq!(&buf[4..8], &[0, 0, 0, 24]);
+        assert_eq!(&buf[8..12], &32473u32.to_be_bytes());
+        assert_eq!(&buf[12..20], &[1, 2, 3, 4, 5, 0, 0, 0]);
+        assert_eq!(&buf[20..], &[0, 0, 0, 24]);
+    }
+
+    #[test]
+    fn not_copyable() {
+        let options = Options::new();
+        let cb = CustomBlock::new(false, 1, &[], &options);
+        let mut buf = vec![];
+        cb.encode::<LittleEndian>(&mut buf).unwrap();
+        assert_eq!(&buf[..4], &[0xad, 0x0b, 0, 0x40]);
+        assert_eq!(buf.len(), 16);
+        assert_eq!(
+            Encodable::<Vec<u8>>::block_type(&cb),
+            Some(BlockType::CustomNoCopy.value())
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use crate::blocks::options::Options;
+use crate::blocks::{write_all_vectored, Block, PADDING};
+use crate::constants::*;
+use crate::enums::*;
+use crate::error::Result;
+use crate::io::Write;
+use crate::utils::pad_to_32;
+use crate::writer::{Encodable, EncodedLen};
+use alloc::borrow::Cow;
+use byteorder::ByteOrder;
+
+/*
+        0                   1                   2                   3
+        0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+       +---------------------------------------------------------------+
+     0 |                   Block Type = 0x0000000A                     |
+       +---------------------------------------------------------------+
+     4 |                      Block Total Length                       |
+       +---------------------------------------------------------------+
+     8 |                          Secrets Type                         |
+       +---------------------------------------------------------------+
+    12 |                         Secrets Length                        |
+       +---------------------------------------------------------------+
+    16 /                                                               /
+       /                          Secrets Data                         /
+       /              (variable length, padded to 32 bits)             /
+       /                                                               /
+       +---------------------------------------------------------------+
+       /                                                               /
+       /                       Options (variable)                      /
+       /                                                               /
+       +---------------------------------------------------------------+
+       /                       Block Total Length                      /
+       +---------------------------------------------------------------+
+
+                 Figure 16: Decryption Secrets Block Format
+*/
+
+/// Represents a [Decryption Secrets Block](https://www.ietf.org/archive/id/draft-ietf-opsawg-pcapng-01.html#name-decryption-secrets-block).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DecryptionSecretsBlock<'a> {
+    secrets_type: SecretsType,
+    secrets_data: &'a [u8],
+    options: Cow<'a, Options<'a>>,
+}
+
+impl<'a> DecryptionSecretsBlock<'a> {
+    pub fn new(
+        secrets_type: SecretsType,
+        secrets_data: &'a [u8],
+        options: &'a Options,
+    ) -> DecryptionSecretsBlock<'a> {
+        DecryptionSecretsBlock {
+            secrets_type,
+            secrets_data,
+            options: Cow::Borrowed(options),
+        }
+    }
+
+    pub fn secrets_type(&self) -> SecretsType {
+        self.secrets_type
+    }
+
+    pub fn secrets_data(&self) -> &'a [u8] {
+        self.secrets_data
+    }
+}
+
+push_option!(DecryptionSecretsBlock);
+
+impl Block for DecryptionSecretsBlock<'_> {
+    const TYPE: BlockType = BlockType::DecryptionSecrets;
+
+    fn length(&self) -> usize {
+        BLOCK_COMMON_LEN
+            + 4
+            + 4
+            + self.secrets_data.len()
+            + pad_to_32(self.secrets_data.len())
+            + self.options.encoded_len()
+    }
+}
+
+impl EncodedLen for DecryptionSecretsBlock<'_> {
+    fn encoded_len(&self) -> usize {
+        self.length()
+    }
+}
+
+impl<W: Write> Encodable<W> for DecryptionSecretsBlock<'_> {
+    fn encode<B: ByteOrder>(&self, w: &mut W) -> Result<()> {
+        let total_length = self.total_length()?;
+        self.options.check_lengths()?;
+        let mut header = [0u8; 16];
+        B::write_u32_into(
+            &[
+                Self::TYPE.value(),
+                total_length,
+                self.secrets_type.value(),
+                self.secrets_data.len() as u32,
+            ],
+            &mut header,
+        );
+        write_all_vectored(
+            w,
+            [
+                &header,
+                self.secrets_data,
+                &PADDING[..pad_to_32(self.secrets_data.len())],
+            ],
+        )?;
+        self.options.encode::<B>(w)?;
+        let mut trailer = [0u8; 4];
+        B::write_u32(&mut trailer, total_length);
+        w.write_all(&trailer)?;
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.options.check_lengths()?;
+        Ok(self.options.check_order()?)
+    }
+
+    fn block_type(&self) -> Option<u32> {
+        Some(Self::TYPE.value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::OptionComment;
+    use byteorder::{BigEndian, LittleEndian};
+
+    #[test]
+    fn new_dsb() {
+        let options = Options::new();
+        let dsb = DecryptionSecretsBlock::new(SecretsType::TlsKeyLog, b"CLIENT_RANDOM", &options);
+        let mut buf = vec![];
+        dsb.encode::<BigEndian>(&mut buf).unwrap();
+        assert_eq!(buf.len(), dsb.encoded_len());
+        assert_eq!(&buf[..4], &[0, 0, 0, 0xa]);
+        assert_eq!(&buf[4..8], &[0, 0, 0, 36]);
+        // secrets type and length
+        assert_eq!(&buf[8..12], b"TLSK");
+        assert_eq!(&buf[12..16], &[0, 0, 0, 13]);
+        assert_eq!(&buf[16..29], b"CLIENT_RANDOM");
+        // padding
+        assert_eq!(&buf[29..32], &[0, 0, 0]);
+        assert_eq!(&buf[32..], &[0, 0, 0, 36]);
+    }
+
+    #[test]
+    fn options() {
+        let comment = [OptionComment::new_option("keys")];
+        let mut options = Options::new();
+        options.add_options(&comment);
+        let dsb = DecryptionSecretsBlock::new(SecretsType::Custom(1), &[1, 2, 3, 4], &options);
+        let mut buf = vec![];
+        dsb.encode::<LittleEndian>(&mut buf).unwrap();
+        assert_eq!(buf.len(), 12 + 8 + 4 + 8);
+        assert_eq!(&buf[8..12], &[1, 0, 0, 0]);
+        assert_eq!(&buf[20..28], &[1, 0, 4, 0, b'k', b'e', b'y', b's']);
+        assert_eq!(&buf[buf.len() - 4..], &[32, 0, 0, 0]);
+    }
+}
@@ -1,12 +1,19 @@
 use crate::blocks::options::Options;
-use crate::blocks::Block;
+use crate::blocks::{write_all_vectored, Block, INLINE_OPTIONS_LEN, PADDING};
 use crate::constants::*;
 use crate::enums::*;
-use crate::utils::{pad_to_32, TimestampResolution};
-use crate::writer::Encodable;
-use byteorder::{ByteOrder, WriteBytesExt};
-use std::io;
-use std::io::Write;
+use crate::error::{Error, Result};
+use crate::io::Write;
+use crate::utils::{pad_to_32, Timestamp, TimestampResolution};
+use crate::writer::{Encodable, EncodedLen};
+use alloc::borrow::Cow;
+use byteorder::ByteOrder;
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+#[cfg(feature = "std")]
+use std::time::SystemTime;
+#[cfg(feature = "time")]
+use time::OffsetDateTime;
 
 /*
        0                   1                   2                   3
@@ -42,7 +49,8 @@ use std::io::Write;
 */
 
 /// Represents an [Enhanced Packet Block](https://tools.ietf.org/html/draft-tuexen-opsawg-pcapng-02#section-4.3).
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct EnhancedPacketBlock<'a> {
     interface_id: u32,
     ts_high: u32,
@@ -50,7 +58,7 @@ pub struct EnhancedPacketBlock<'a> {
     cap_packet_len: u32,
     orig_packet_len: u32,
     packet_data: &'a [u8],
-    options: &'a Options<'a>,
+    options: Cow<'a, Options<'a>>,
 }
 
 impl<'a> EnhancedPacketBlock<'a> {
@@ -71,8 +79,64 @@ impl<'a> EnhancedPacketBlock<'a> {
             cap_packet_len: cap_len,
             orig_packet_len: orig_len,
             packet_data,
+            options: Cow::Borrowed(options),
+        }
+    }
+
+    /// Like `new`, but checks that `cap_len` is the length of
+    /// `packet_data`.
+    pub fn try_new(
+        interface_id: u32,
+        ts_high: u32,
+        ts_low: u32,
+        cap_len: u32,
+        orig_len: u32,
+        packet_data: &'a [u8],
+        options: &'a Options,
+    ) -> Result<EnhancedPacketBlock<'a>> {
+        let epb = Self::new(
+            interface_id,
+            ts_high,
+            ts_low,
+            cap_len,
+            orig_len,
+            packet_data,
+            options,
+        );
+        epb.check_cap_len()?;
+        Ok(epb)
+    }
+
+    /// Creates a new `EnhancedPacketBlock` whose captured length is
+    /// the length of `packet_data`.
+    pub fn from_data(
+        interface_id: u32,
+        ts_high: u32,
+        ts_low: u32,
+        orig_len: u32,
+        packet_data: &'a [u8],
+        options: &'a Options,
+    ) -> Result<EnhancedPacketBlock<'a>> {
+        let cap_len = packet_data.len() as u32;
+        Self::try_new(
+            interface_id,
+            ts_high,
+            ts_low,
+            cap_len,
+            orig_len,
+            packet_data,
             options,
+        )
+    }
+
+    fn check_cap_len(&self) -> Result<()> {
+        if self.cap_packet_len as usize != self.packet_data.len() {
+            return Err(Error::CapLenMismatch {
+                declared: self.cap_packet_len,
+                actual: self.packet_data.len(),
+            });
         }
+        Ok(())
     }
 
     /// Create a new `EnhancedPacketBlock`. Uses a
@@ -99,52 +163,202 @@ impl<'a> EnhancedPacketBlock<'a> {
         )
     }
 
-    fn data_padding(&self) -> Vec<u8> {
-        let n = pad_to_32(self.packet_data.len());
-        vec![0u8; n]
+    /// Like `from_data`, but takes the timestamp as a `Timestamp`
+    /// already in units of the interface's timestamp resolution.
+    pub fn from_timestamp(
+        interface_id: u32,
+        timestamp: Timestamp,
+        orig_len: u32,
+        packet_data: &'a [u8],
+        options: &'a Options,
+    ) -> Result<EnhancedPacketBlock<'a>> {
+        Self::from_data(
+            interface_id,
+            timestamp.high(),
+            timestamp.low(),
+            orig_len,
+            packet_data,
+            options,
+        )
+    }
+
+    /// Creates a new `EnhancedPacketBlock` for a packet captured at
+    /// `timestamp`, whose captured length is the length of
+    /// `packet_data`.
+    #[cfg(feature = "std")]
+    pub fn from_systemtime(
+        interface_id: u32,
+        ts_res: &TimestampResolution,
+        timestamp: SystemTime,
+        orig_len: u32,
+        packet_data: &'a [u8],
+        options: &'a Options,
+    ) -> Result<EnhancedPacketBlock<'a>> {
+        let (ts_high, ts_low) = ts_res.ts_from_systemtime(timestamp)?;
+        Self::from_data(
+            interface_id,
+            ts_high,
+            ts_low,
+            orig_len,
+            packet_data,
+            options,
+        )
+    }
+
+    /// Like `from_systemtime`, but takes a `chrono` `DateTime`.
+    #[cfg(feature = "chrono")]
+    pub fn from_datetime(
+        interface_id: u32,
+        ts_res: &TimestampResolution,
+        timestamp: &DateTime<Utc>,
+        orig_len: u32,
+        packet_data: &'a [u8],
+        options: &'a Options,
+    ) -> Result<EnhancedPacketBlock<'a>> {
+        let (ts_high, ts_low) = ts_res.ts_from_datetime(timestamp)?;
+        Self::from_data(
+            interface_id,
+            ts_high,
+            ts_low,
+            orig_len,
+            packet_data,
+            options,
+        )
+    }
+
+    /// Like `from_systemtime`, but takes a `time` `OffsetDateTime`.
+    #[cfg(feature = "time")]
+    pub fn from_offset_datetime(
+        interface_id: u32,
+        ts_res: &TimestampResolution,
+        timestamp: OffsetDateTime,
+        orig_len: u32,
+        packet_data: &'a [u8],
+        options: &'a Options,
+    ) -> Result<EnhancedPacketBlock<'a>> {
+        let (ts_high, ts_low) = ts_res.ts_from_offset_datetime(timestamp)?;
+        Self::from_data(
+            interface_id,
+            ts_high,
+            ts_low,
+            orig_len,
+            packet_data,
+            options,
+        )
+    }
+
+    pub fn interface_id(&self) -> u32 {
+        self.interface_id
+    }
+
+    /// Returns the timestamp, in units of the interface's timestamp
+    /// resolution.
+    pub fn timestamp(&self) -> u64 {
+        (self.ts_high as u64) << 32 | self.ts_low as u64
+    }
+
+    pub fn cap_len(&self) -> u32 {
+        self.cap_packet_len
+    }
+
+    pub fn orig_len(&self) -> u32 {
+        self.orig_packet_len
+    }
+
+    pub fn packet_data(&self) -> &'a [u8] {
+        self.packet_data
+    }
+
+    pub fn options(&self) -> &Options<'a> {
+        &self.options
     }
 }
 
+push_option!(EnhancedPacketBlock);
+
 impl Block for EnhancedPacketBlock<'_> {
     const TYPE: BlockType = BlockType::EnhancedPacket;
 
-    fn length(&self) -> u32 {
+    fn length(&self) -> usize {
         BLOCK_COMMON_LEN
             + 4
             + 4
             + 4
             + 4
             + 4
-            + self.packet_data.len() as u32
-            + self.data_padding().len() as u32
-            + self.options.length()
+            + self.packet_data.len()
+            + pad_to_32(self.packet_data.len())
+            + self.options.encoded_len()
+    }
+}
+
+impl EncodedLen for EnhancedPacketBlock<'_> {
+    fn encoded_len(&self) -> usize {
+        self.length()
     }
 }
 
 impl<W: Write> Encodable<W> for EnhancedPacketBlock<'_> {
-    fn encode<B: ByteOrder>(&self, w: &mut W) -> io::Result<()> {
-        let total_length = self.length();
-        w.write_u32::<B>(Self::TYPE.value())?;
-        w.write_u32::<B>(total_length)?;
-        w.write_u32::<B>(self.interface_id)?;
-        w.write_u32::<B>(self.ts_high)?;
-        w.write_u32::<B>(self.ts_low)?;
-        w.write_u32::<B>(self.cap_packet_len)?;
-        w.write_u32::<B>(self.orig_packet_len)?;
-        w.write_all(self.packet_data)?;
-        w.write_all(&self.data_padding())?;
-        self.options.encode::<B>(w)?;
-        w.write_u32::<B>(total_length)?;
+    /// The block is written with `write_vectored`, so the packet data
+    /// is not copied, and writers that support vectored I/O (e.g.
+    /// `File`) receive the whole block in a single call. Encoding
+    /// does not allocate.
+    fn encode<B: ByteOrder>(&self, w: &mut W) -> Result<()> {
+        let total_length = self.total_length()?;
+        self.options.check_lengths()?;
+        let mut header = [0u8; 28];
+        B::write_u32_into(
+            &[
+                Self::TYPE.value(),
+                total_length,
+                self.interface_id,
+                self.ts_high,
+                self.ts_low,
+                self.cap_packet_len,
+                self.orig_packet_len,
+            ],
+            &mut header,
+        );
+        let mut trailer = [0u8; 4];
+        B::write_u32(&mut trailer, total_length);
+        let data = self.packet_data;
+        let padding = &PADDING[..pad_to_32(self.packet_data.len())];
+        let options_len = self.options.encoded_len();
+        let mut options = [0u8; INLINE_OPTIONS_LEN];
+        if options_len <= INLINE_OPTIONS_LEN {
+            self.options.encode::<B>(&mut &mut options[..])?;
+            let options = &options[..options_len];
+            write_all_vectored(w, [&header, data, padding, options, &trailer])?;
+        } else {
+            write_all_vectored(w, [&header, data, padding])?;
+            self.options.encode::<B>(w)?;
+            w.write_all(&trailer)?;
+        }
         Ok(())
     }
+
+    fn validate(&self) -> Result<()> {
+        self.check_cap_len()?;
+        self.options.check_lengths()?;
+        Ok(self.options.check_order()?)
+    }
+
+    fn block_type(&self) -> Option<u32> {
+        Some(Self::TYPE.value())
+    }
+
+    fn interface_id(&self) -> Option<u32> {
+        Some(self.interface_id)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::blocks::SectionHeaderBlock;
+    use crate::reader::{Block, SliceReader};
+    use alloc::vec::Vec;
     use byteorder::{BigEndian, LittleEndian};
-    use nom::IResult;
-    use pcapng;
 
     #[test]
     fn new_epb() {
@@ -168,30 +382,178 @@ mod tests {
         assert_eq!(&buf[38..40], &[0, 0]);
     }
 
+    #[test]
+    fn checked_cap_len() {
+        let opts = Options::new();
+        assert!(EnhancedPacketBlock::try_new(1, 1, 2, 10, 10, &[9; 10], &opts).is_ok());
+        assert!(matches!(
+            EnhancedPacketBlock::try_new(1, 1, 2, 10, 10, &[9; 4], &opts),
+            Err(Error::CapLenMismatch {
+                declared: 10,
+                actual: 4
+            })
+        ));
+        let epb = EnhancedPacketBlock::from_data(1, 1, 2, 60, &[9; 4], &opts).unwrap();
+        let mut buf = vec![];
+        epb.encode::<BigEndian>(&mut buf).unwrap();
+        assert_eq!(&buf[20..28], &[0, 0, 0, 4, 0, 0, 0, 60]);
+    }
+
+    #[test]
+    fn push_option() {
+        use crate::blocks::options::{OptionComment, OptionEndOfOpt, OptionEpbQueue};
+
+        let end = OptionEndOfOpt::new_option();
+        let mut opts = Options::new();
+        opts.add_option(&end);
+        let mut epb = EnhancedPacketBlock::new(1, 1, 2, 4, 4, &[9; 4], &opts)
+            .with_option(OptionComment::new_option("abc"));
+        epb.push_option(OptionEpbQueue::new_option(3));
+        assert_eq!(opts.iter().count(), 1);
+        let codes: Vec<_> = epb.options().iter().map(|opt| opt.code()).collect();
+        assert_eq!(codes, [1, 6, 0]);
+        assert!(Encodable::<Vec<u8>>::validate(&epb).is_ok());
+        let mut buf = vec![];
+        epb.encode::<BigEndian>(&mut buf).unwrap();
+        assert_eq!(buf.len(), 32 + 8 + 8 + 4 + 4);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn single_write() {
+        struct Calls(Vec<u8>, usize);
+
+        impl Write for Calls {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.1 += 1;
+                self.0.write(buf)
+            }
+
+            fn write_vectored(&mut self, bufs: &[std::io::IoSlice<'_>]) -> std::io::Result<usize> {
+                self.1 += 1;
+                self.0.write_vectored(bufs)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let comment = crate::blocks::options::OptionComment::new_option("abc");
+        let mut opts = Options::new();
+        opts.add_option(&comment);
+        let epb = EnhancedPacketBlock::new(1, 1, 2, 10, 10, &[9; 10], &opts);
+        let mut expected = vec![];
+        epb.encode::<BigEndian>(&mut expected).unwrap();
+        let mut calls = Calls(vec![], 0);
+        epb.encode::<BigEndian>(&mut calls).unwrap();
+        assert_eq!(calls.1, 1);
+        assert_eq!(calls.0, expected);
+        assert_eq!(calls.0.len(), 28 + 12 + 8 + 4);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn from_systemtime() {
+        use crate::utils::MICRO_SECOND_TSRES;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let opts = Options::new();
+        let time = UNIX_EPOCH + Duration::new(0x1_0000_0000, 2_000);
+        let epb =
+            EnhancedPacketBlock::from_systemtime(0, MICRO_SECOND_TSRES, time, 20, &[1; 10], &opts)
+                .unwrap();
+        assert_eq!(epb.timestamp(), 0x1_0000_0000 * 1_000_000 + 2);
+        assert_eq!(epb.cap_len(), 10);
+        assert_eq!(epb.orig_len(), 20);
+        let before_epoch = UNIX_EPOCH - Duration::from_secs(1);
+        assert!(matches!(
+            EnhancedPacketBlock::from_systemtime(
+                0,
+                MICRO_SECOND_TSRES,
+                before_epoch,
+                0,
+                &[],
+                &opts
+            ),
+            Err(Error::TimestampBeforeEpoch)
+        ));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn from_datetime() {
+        use crate::utils::NANO_SECOND_TSRES;
+
+        let opts = Options::new();
+        let time = DateTime::from_timestamp(1_600_000_000, 123).unwrap();
+        let epb =
+            EnhancedPacketBlock::from_datetime(0, NANO_SECOND_TSRES, &time, 4, &[1; 4], &opts)
+                .unwrap();
+        assert_eq!(epb.timestamp(), 1_600_000_000_000_000_123);
+        assert_eq!(
+            NANO_SECOND_TSRES.datetime_from_ticks(epb.timestamp()),
+            Some(time)
+        );
+        let before_epoch = DateTime::from_timestamp(-1, 0).unwrap();
+        assert!(matches!(
+            EnhancedPacketBlock::from_datetime(0, NANO_SECOND_TSRES, &before_epoch, 0, &[], &opts),
+            Err(Error::TimestampBeforeEpoch)
+        ));
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn from_offset_datetime() {
+        use crate::utils::NANO_SECOND_TSRES;
+
+        let opts = Options::new();
+        let time = OffsetDateTime::from_unix_timestamp_nanos(1_600_000_000_000_000_123).unwrap();
+        let epb = EnhancedPacketBlock::from_offset_datetime(
+            0,
+            NANO_SECOND_TSRES,
+            time,
+            4,
+            &[1; 4],
+            &opts,
+        )
+        .unwrap();
+        assert_eq!(epb.timestamp(), 1_600_000_000_000_000_123);
+        assert_eq!(
+            NANO_SECOND_TSRES.offset_datetime_from_ticks(epb.timestamp()),
+            Some(time)
+        );
+        let before_epoch = OffsetDateTime::from_unix_timestamp(-1).unwrap();
+        assert!(matches!(
+            EnhancedPacketBlock::from_offset_datetime(
+                0,
+                NANO_SECOND_TSRES,
+                before_epoch,
+                0,
+                &[],
+                &opts
+            ),
+            Err(Error::TimestampBeforeEpoch)
+        ));
+    }
+
     #[test]
     fn round_trip() {
         let opts = Options::new();
         let epb = EnhancedPacketBlock::new(1, 1, 2, 10, 20, &[9; 10], &opts);
         let mut buf = vec![];
+        SectionHeaderBlock::new_with_defaults(&Options::new())
+            .encode::<LittleEndian>(&mut buf)
+            .unwrap();
         epb.encode::<LittleEndian>(&mut buf).unwrap();
-        if let IResult::Done(_, blocks) = pcapng::block::parse_blocks(&buf[..]) {
-            for raw in blocks {
-                if let IResult::Done(_, block) = raw.parse() {
-                    if let pcapng::block::Block::EnhancedPacket(parsed_epb) = block {
-                        assert_eq!(parsed_epb.captured_len, 10);
-                        assert_eq!(parsed_epb.packet_len, 20);
-                        assert_eq!(parsed_epb.timestamp_hi, 1);
-                        assert_eq!(parsed_epb.timestamp_lo, 2);
-                        assert_eq!(parsed_epb.data, &[9; 10]);
-                    } else {
-                        panic!()
-                    }
-                } else {
-                    panic!();
-                }
+        match SliceReader::new(&buf).nth(1).unwrap().unwrap() {
+            Block::EnhancedPacket(parsed_epb) => {
+                assert_eq!(parsed_epb.interface_id(), 1);
+                assert_eq!(parsed_epb.timestamp(), 1 << 32 | 2);
+                assert_eq!(parsed_epb.orig_len(), 20);
+                assert_eq!(parsed_epb.packet_data(), &[9; 10]);
             }
-        } else {
-            panic!();
+            _ => panic!(),
         }
     }
 }
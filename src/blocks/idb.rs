@@ -2,10 +2,11 @@ use crate::blocks::options::Options;
 use crate::blocks::Block;
 use crate::constants::*;
 use crate::enums::*;
-use crate::writer::Encodable;
-use byteorder::{ByteOrder, WriteBytesExt};
-use std::io;
-use std::io::Write;
+use crate::error::Result;
+use crate::io::{Write, WriteBytesExt};
+use crate::writer::{Encodable, EncodedLen};
+use alloc::borrow::Cow;
+use byteorder::ByteOrder;
 
 /*
         0                   1                   2                   3
@@ -30,11 +31,12 @@ use std::io::Write;
 */
 
 /// Represents an [Interface Description Block](https://tools.ietf.org/html/draft-tuexen-opsawg-pcapng-02#section-4.2).
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct InterfaceDescriptionBlock<'a> {
     link_type: u16,
     snap_len: u32,
-    options: &'a Options<'a>,
+    options: Cow<'a, Options<'a>>,
 }
 
 impl<'a> InterfaceDescriptionBlock<'a> {
@@ -46,22 +48,54 @@ impl<'a> InterfaceDescriptionBlock<'a> {
         InterfaceDescriptionBlock {
             link_type: link_type.value(),
             snap_len,
-            options,
+            options: Cow::Borrowed(options),
         }
     }
+
+    pub fn link_type(&self) -> u16 {
+        self.link_type
+    }
+
+    pub fn snap_len(&self) -> u32 {
+        self.snap_len
+    }
+
+    pub fn options(&self) -> &Options<'a> {
+        &self.options
+    }
+}
+
+#[cfg(feature = "netif")]
+impl InterfaceDescriptionBlock<'_> {
+    /// Reads the network interface `name` from the operating system,
+    /// see `netif::SystemInterface::lookup`. Its `idb_options` describe
+    /// the interface.
+    #[cfg(any(unix, windows))]
+    pub fn from_system(name: &str) -> std::io::Result<crate::netif::SystemInterface> {
+        crate::netif::SystemInterface::lookup(name)
+    }
 }
 
+push_option!(InterfaceDescriptionBlock);
+
 impl Block for InterfaceDescriptionBlock<'_> {
     const TYPE: BlockType = BlockType::InterfaceDescription;
 
-    fn length(&self) -> u32 {
-        BLOCK_COMMON_LEN + 2 + 2 + 4 + self.options.length()
+    fn length(&self) -> usize {
+        BLOCK_COMMON_LEN + 2 + 2 + 4 + self.options.encoded_len()
+    }
+}
+
+impl EncodedLen for InterfaceDescriptionBlock<'_> {
+    fn encoded_len(&self) -> usize {
+        self.length()
     }
 }
 
 impl<W: Write> Encodable<W> for InterfaceDescriptionBlock<'_> {
-    fn encode<B: ByteOrder>(&self, w: &mut W) -> io::Result<()> {
-        let total_length = self.length();
+    fn encode<B: ByteOrder>(&self, w: &mut W) -> Result<()> {
+        let total_length = self.total_length()?;
+        self.options.check_lengths()?;
         w.write_u32::<B>(Self::TYPE.value())?;
         w.write_u32::<B>(total_length)?;
         w.write_u16::<B>(self.link_type)?;
@@ -71,14 +105,23 @@ impl<W: Write> Encodable<W> for InterfaceDescriptionBlock<'_> {
         w.write_u32::<B>(total_length)?;
         Ok(())
     }
+
+    fn validate(&self) -> Result<()> {
+        self.options.check_lengths()?;
+        Ok(self.options.check_order()?)
+    }
+
+    fn block_type(&self) -> Option<u32> {
+        Some(Self::TYPE.value())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::blocks::SectionHeaderBlock;
+    use crate::reader::{Block, SliceReader};
     use byteorder::{BigEndian, LittleEndian};
-    use nom::IResult;
-    use pcapng;
 
     #[test]
     fn new_idb() {
@@ -99,22 +142,16 @@ mod tests {
         let opts = Options::new();
         let idb = InterfaceDescriptionBlock::new(LinkType::Ethernet, 1500, &opts);
         let mut buf = vec![];
+        SectionHeaderBlock::new_with_defaults(&Options::new())
+            .encode::<LittleEndian>(&mut buf)
+            .unwrap();
         idb.encode::<LittleEndian>(&mut buf).unwrap();
-        if let IResult::Done(_, blocks) = pcapng::block::parse_blocks(&buf[..]) {
-            for raw in blocks {
-                if let IResult::Done(_, block) = raw.parse() {
-                    if let pcapng::block::Block::InterfaceDescription(parsed_idb) = block {
-                        assert_eq!(parsed_idb.link_type, 1);
-                        assert_eq!(parsed_idb.snap_len, 1500);
-                    } else {
-                        panic!()
-                    }
-                } else {
-                    panic!();
-                }
+        match SliceReader::new(&buf).nth(1).unwrap().unwrap() {
+            Block::InterfaceDescription(parsed_idb) => {
+                assert_eq!(parsed_idb.link_type(), 1);
+                assert_eq!(parsed_idb.snap_len(), 1500);
             }
-        } else {
-            panic!();
+            _ => panic!(),
         }
     }
 }
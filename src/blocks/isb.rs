@@ -2,10 +2,19 @@ use crate::blocks::options::Options;
 use crate::blocks::Block;
 use crate::constants::*;
 use crate::enums::*;
-use crate::writer::Encodable;
-use byteorder::{ByteOrder, WriteBytesExt};
-use std::io;
-use std::io::Write;
+use crate::error::Result;
+use crate::io::{Write, WriteBytesExt};
+#[cfg(any(feature = "chrono", feature = "time", feature = "std"))]
+use crate::utils::TimestampResolution;
+use crate::writer::{Encodable, EncodedLen};
+use alloc::borrow::Cow;
+use byteorder::ByteOrder;
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+#[cfg(feature = "std")]
+use std::time::SystemTime;
+#[cfg(feature = "time")]
+use time::OffsetDateTime;
 
 /*
         0                   1                   2                   3
@@ -32,12 +41,13 @@ use std::io::Write;
 */
 
 /// Represents an [Interface Statistics Block](https://tools.ietf.org/html/draft-tuexen-opsawg-pcapng-02#section-4.6).
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct InterfaceStatisticsBlock<'a> {
     interface_id: u32,
     ts_high: u32,
     ts_low: u32,
-    options: &'a Options<'a>,
+    options: Cow<'a, Options<'a>>,
 }
 
 impl<'a> InterfaceStatisticsBlock<'a> {
@@ -51,22 +61,70 @@ impl<'a> InterfaceStatisticsBlock<'a> {
             interface_id,
             ts_high,
             ts_low,
-            options,
+            options: Cow::Borrowed(options),
         }
     }
+
+    /// Creates a new `InterfaceStatisticsBlock` with a `SystemTime`
+    /// timestamp.
+    #[cfg(feature = "std")]
+    pub fn from_systemtime(
+        interface_id: u32,
+        ts_res: &TimestampResolution,
+        timestamp: SystemTime,
+        options: &'a Options,
+    ) -> Result<InterfaceStatisticsBlock<'a>> {
+        let (ts_high, ts_low) = ts_res.ts_from_systemtime(timestamp)?;
+        Ok(Self::new(interface_id, ts_high, ts_low, options))
+    }
+
+    /// Creates a new `InterfaceStatisticsBlock` with a `chrono`
+    /// `DateTime` timestamp.
+    #[cfg(feature = "chrono")]
+    pub fn from_datetime(
+        interface_id: u32,
+        ts_res: &TimestampResolution,
+        timestamp: &DateTime<Utc>,
+        options: &'a Options,
+    ) -> Result<InterfaceStatisticsBlock<'a>> {
+        let (ts_high, ts_low) = ts_res.ts_from_datetime(timestamp)?;
+        Ok(Self::new(interface_id, ts_high, ts_low, options))
+    }
+
+    /// Creates a new `InterfaceStatisticsBlock` with a `time`
+    /// `OffsetDateTime` timestamp.
+    #[cfg(feature = "time")]
+    pub fn from_offset_datetime(
+        interface_id: u32,
+        ts_res: &TimestampResolution,
+        timestamp: OffsetDateTime,
+        options: &'a Options,
+    ) -> Result<InterfaceStatisticsBlock<'a>> {
+        let (ts_high, ts_low) = ts_res.ts_from_offset_datetime(timestamp)?;
+        Ok(Self::new(interface_id, ts_high, ts_low, options))
+    }
 }
 
+push_option!(InterfaceStatisticsBlock);
+
 impl Block for InterfaceStatisticsBlock<'_> {
     const TYPE: BlockType = BlockType::InterfaceStatistics;
 
-    fn length(&self) -> u32 {
-        BLOCK_COMMON_LEN + 4 + 4 + 4 + self.options.length()
+    fn length(&self) -> usize {
+        BLOCK_COMMON_LEN + 4 + 4 + 4 + self.options.encoded_len()
+    }
+}
+
+impl EncodedLen for InterfaceStatisticsBlock<'_> {
+    fn encoded_len(&self) -> usize {
+        self.length()
     }
 }
 
 impl<W: Write> Encodable<W> for InterfaceStatisticsBlock<'_> {
-    fn encode<B: ByteOrder>(&self, w: &mut W) -> io::Result<()> {
-        let total_length = self.length();
+    fn encode<B: ByteOrder>(&self, w: &mut W) -> Result<()> {
+        let total_length = self.total_length()?;
+        self.options.check_lengths()?;
         w.write_u32::<B>(Self::TYPE.value())?;
         w.write_u32::<B>(total_length)?;
         w.write_u32::<B>(self.interface_id)?;
@@ -76,14 +134,27 @@ impl<W: Write> Encodable<W> for InterfaceStatisticsBlock<'_> {
         w.write_u32::<B>(total_length)?;
         Ok(())
     }
+
+    fn validate(&self) -> Result<()> {
+        self.options.check_lengths()?;
+        Ok(self.options.check_order()?)
+    }
+
+    fn block_type(&self) -> Option<u32> {
+        Some(Self::TYPE.value())
+    }
+
+    fn interface_id(&self) -> Option<u32> {
+        Some(self.interface_id)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::blocks::SectionHeaderBlock;
+    use crate::reader::{Block, SliceReader};
     use byteorder::{BigEndian, LittleEndian};
-    use nom::IResult;
-    use pcapng;
 
     #[test]
     fn new_isb() {
@@ -99,28 +170,54 @@ mod tests {
         assert_eq!(&buf[8..12], &[1, 0, 0, 0]);
     }
 
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn from_datetime() {
+        let opts = Options::new();
+        let time = DateTime::from_timestamp(0x1_0000_0000, 1_000).unwrap();
+        let isb =
+            InterfaceStatisticsBlock::from_datetime(1, crate::utils::DEFAULT_TSRES, &time, &opts)
+                .unwrap();
+        let mut buf = vec![];
+        isb.encode::<BigEndian>(&mut buf).unwrap();
+        let ticks = 0x1_0000_0000u64 * 1_000_000 + 1;
+        assert_eq!(&buf[12..20], &ticks.to_be_bytes());
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn from_offset_datetime() {
+        let opts = Options::new();
+        let time = OffsetDateTime::from_unix_timestamp_nanos(0x1_0000_0000 * 1_000_000_000 + 1_000)
+            .unwrap();
+        let isb = InterfaceStatisticsBlock::from_offset_datetime(
+            1,
+            crate::utils::DEFAULT_TSRES,
+            time,
+            &opts,
+        )
+        .unwrap();
+        let mut buf = vec![];
+        isb.encode::<BigEndian>(&mut buf).unwrap();
+        let ticks = 0x1_0000_0000u64 * 1_000_000 + 1;
+        assert_eq!(&buf[12..20], &ticks.to_be_bytes());
+    }
+
     #[test]
     fn round_trip() {
         let opts = Options::new();
         let isb = InterfaceStatisticsBlock::new(1, 100, 200, &opts);
         let mut buf = vec![];
+        SectionHeaderBlock::new_with_defaults(&Options::new())
+            .encode::<LittleEndian>(&mut buf)
+            .unwrap();
         isb.encode::<LittleEndian>(&mut buf).unwrap();
-        if let IResult::Done(_, blocks) = pcapng::block::parse_blocks(&buf[..]) {
-            for raw in blocks {
-                if let IResult::Done(_, block) = raw.parse() {
-                    if let pcapng::block::Block::InterfaceStatistics(parsed_isb) = block {
-                        assert_eq!(parsed_isb.interface_id, 1);
-                        assert_eq!(parsed_isb.timestamp_high, 100);
-                        assert_eq!(parsed_isb.timestamp_low, 200);
-                    } else {
-                        panic!()
-                    }
-                } else {
-                    panic!();
-                }
+        match SliceReader::new(&buf).nth(1).unwrap().unwrap() {
+            Block::InterfaceStatistics(parsed_isb) => {
+                assert_eq!(parsed_isb.interface_id(), 1);
+                assert_eq!(parsed_isb.timestamp(), 100 << 32 | 200);
             }
-        } else {
-            panic!();
+            _ => panic!(),
         }
     }
 }
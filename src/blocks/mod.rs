@@ -1,8 +1,10 @@
-use crate::writer::Encodable;
-use crate::{enums::BlockType, utils::pad_to_32};
-use byteorder::{ByteOrder, WriteBytesExt};
-use std::io::Write;
-use std::{convert::TryInto, io};
+use crate::constants::BLOCK_COMMON_LEN;
+use crate::enums::BlockType;
+use crate::error::{Error, Result};
+use crate::io::{self, Write, WriteBytesExt};
+use crate::writer::{Encodable, EncodedLen};
+use byteorder::ByteOrder;
+use core::convert::TryInto;
 
 /*
     Based on the draft standard:
@@ -33,16 +35,61 @@ use std::{convert::TryInto, io};
 trait Block {
     const TYPE: BlockType;
 
-    fn length(&self) -> u32;
+    fn length(&self) -> usize;
 
-    fn padding(&self) -> Vec<u8> {
-        let n = pad_to_32(self.length().try_into().unwrap());
-        vec![0u8; n]
+    /// Returns the value of the Block Total Length field, or an
+    /// error if the block is too long to be encoded.
+    fn total_length(&self) -> Result<u32> {
+        let length = self.length();
+        length.try_into().map_err(|_| Error::BlockTooLong(length))
     }
 }
 
+/// Zeros for padding data to 32 bits.
+const PADDING: [u8; 3] = [0; 3];
+
+/// Options up to this length are encoded on the stack, so that
+/// packet blocks can be written with a single vectored write.
+const INLINE_OPTIONS_LEN: usize = 256;
+
+/// Writes all the slices, with as few calls to `write_vectored` as
+/// the writer allows.
+#[cfg(feature = "std")]
+pub(crate) fn write_all_vectored<W: Write, const N: usize>(
+    w: &mut W,
+    bufs: [&[u8]; N],
+) -> io::Result<()> {
+    use std::io::IoSlice;
+
+    let mut slices = bufs.map(IoSlice::new);
+    let mut bufs = &mut slices[..];
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        match w.write_vectored(bufs) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => IoSlice::advance_slices(&mut bufs, n),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Writes all the slices, one after the other.
+#[cfg(not(feature = "std"))]
+pub(crate) fn write_all_vectored<W: Write, const N: usize>(
+    w: &mut W,
+    bufs: [&[u8]; N],
+) -> io::Result<()> {
+    for buf in bufs.iter() {
+        w.write_all(buf)?;
+    }
+    Ok(())
+}
+
 /// A raw pcapng block.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct RawBlock<'a> {
     block_type: u32,
     total_length1: u32,
@@ -59,30 +106,85 @@ impl<'a> RawBlock<'a> {
             body,
         }
     }
+
+    pub fn block_type(&self) -> u32 {
+        self.block_type
+    }
+
+    pub fn body(&self) -> &'a [u8] {
+        self.body
+    }
+}
+
+impl EncodedLen for RawBlock<'_> {
+    fn encoded_len(&self) -> usize {
+        BLOCK_COMMON_LEN + self.body.len()
+    }
 }
 
 impl<'a, W: Write> Encodable<W> for RawBlock<'a> {
     /// For raw blocks, the total length fields are not automatically
     /// calculated.
-    fn encode<B: ByteOrder>(&self, w: &mut W) -> io::Result<()> {
+    fn encode<B: ByteOrder>(&self, w: &mut W) -> Result<()> {
         w.write_u32::<B>(self.block_type)?;
         w.write_u32::<B>(self.total_length1)?;
         w.write_all(self.body)?;
         w.write_u32::<B>(self.total_length2)?;
         Ok(())
     }
+
+    fn validate(&self) -> Result<()> {
+        for &len in &[self.total_length1, self.total_length2] {
+            if len as usize != self.encoded_len() || len & 3 != 0 {
+                return Err(Error::InvalidBlockLength(len));
+            }
+        }
+        Ok(())
+    }
+
+    fn block_type(&self) -> Option<u32> {
+        Some(self.block_type)
+    }
+}
+
+/// Implements `push_option` and `with_option` for a block type with
+/// an `options: Cow<Options>` field.
+macro_rules! push_option {
+    ($block:ident) => {
+        impl $block<'_> {
+            /// Appends `opt` to the options of the block, before
+            /// `opt_endofopt` if it is the last option. The options
+            /// passed to the constructor are not changed: the block
+            /// copies them the first time.
+            pub fn push_option(&mut self, opt: crate::blocks::options::BlockOption) {
+                self.options.to_mut().push_before_end(opt);
+            }
+
+            /// Like `push_option`, but consumes and returns the block.
+            pub fn with_option(mut self, opt: crate::blocks::options::BlockOption) -> Self {
+                self.push_option(opt);
+                self
+            }
+        }
+    };
 }
 
+mod cb;
+mod dsb;
 mod epb;
 mod idb;
 mod isb;
+mod nrb;
 pub mod options;
 mod shb;
 mod spb;
 
+pub use crate::blocks::cb::CustomBlock;
+pub use crate::blocks::dsb::DecryptionSecretsBlock;
 pub use crate::blocks::epb::EnhancedPacketBlock;
 pub use crate::blocks::idb::InterfaceDescriptionBlock;
 pub use crate::blocks::isb::InterfaceStatisticsBlock;
+pub use crate::blocks::nrb::{NameRecord, NameResolutionBlock};
 pub use crate::blocks::shb::SectionHeaderBlock;
 pub use crate::blocks::spb::SimplePacketBlock;
 
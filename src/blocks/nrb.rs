@@ -0,0 +1,230 @@
+use crate::blocks::options::{check_len, OptionError, Options};
+use crate::blocks::{Block, PADDING};
+use crate::constants::*;
+use crate::enums::*;
+use crate::error::Result;
+use crate::io::{self, Write, WriteBytesExt};
+use crate::utils::pad_to_32;
+use crate::writer::{Encodable, EncodedLen};
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use byteorder::ByteOrder;
+use core::net::IpAddr;
+
+/*
+        0                   1                   2                   3
+        0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+       +---------------------------------------------------------------+
+     0 |                    Block Type = 0x00000004                    |
+       +---------------------------------------------------------------+
+     4 |                      Block Total Length                       |
+       +---------------------------------------------------------------+
+     8 |      Record Type              |      Record Value Length      |
+       +---------------------------------------------------------------+
+    12 /                       Record Value                            /
+       /              variable length, padded to 32 bits               /
+       +---------------------------------------------------------------+
+       .                                                               .
+       .                  . . . other records . . .                    .
+       .                                                               .
+       +---------------------------------------------------------------+
+       |  Record Type = nrb_record_end |   Record Value Length = 0     |
+       +---------------------------------------------------------------+
+       /                                                               /
+       /                      Options (variable)                       /
+       /                                                               /
+       +---------------------------------------------------------------+
+       |                      Block Total Length                       |
+       +---------------------------------------------------------------+
+
+                 Figure 13: Name Resolution Block Format
+*/
+
+/// A record of a Name Resolution Block: an IP address and the names
+/// it resolves to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct NameRecord {
+    addr: IpAddr,
+    names: Vec<String>,
+}
+
+impl NameRecord {
+    /// Creates a new record.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the record value is longer than 65535 bytes; see
+    /// `try_new`.
+    pub fn new(addr: IpAddr, names: &[&str]) -> Self {
+        Self::try_new(addr, names).unwrap()
+    }
+
+    /// Creates a new record. Each name is written followed by a zero
+    /// byte, so names should not contain one.
+    pub fn try_new(addr: IpAddr, names: &[&str]) -> core::result::Result<Self, OptionError> {
+        let record = Self {
+            addr,
+            names: names.iter().map(|name| name.to_string()).collect(),
+        };
+        check_len(record.value_len())?;
+        Ok(record)
+    }
+
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    fn record_type(&self) -> u16 {
+        match self.addr {
+            IpAddr::V4(_) => 1,
+            IpAddr::V6(_) => 2,
+        }
+    }
+
+    fn value_len(&self) -> usize {
+        let addr_len = match self.addr {
+            IpAddr::V4(_) => 4,
+            IpAddr::V6(_) => 16,
+        };
+        addr_len + self.names.iter().map(|name| name.len() + 1).sum::<usize>()
+    }
+
+    fn write<B: ByteOrder, W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let len = self.value_len();
+        w.write_u16::<B>(self.record_type())?;
+        w.write_u16::<B>(len as u16)?;
+        match self.addr {
+            IpAddr::V4(ip) => w.write_all(&ip.octets())?,
+            IpAddr::V6(ip) => w.write_all(&ip.octets())?,
+        }
+        for name in &self.names {
+            w.write_all(name.as_bytes())?;
+            w.write_all(&[0])?;
+        }
+        w.write_all(&PADDING[..pad_to_32(len)])
+    }
+}
+
+impl EncodedLen for NameRecord {
+    /// Number of bytes in the encoded record, including the type and
+    /// length fields and the padding.
+    fn encoded_len(&self) -> usize {
+        4 + self.value_len() + pad_to_32(self.value_len())
+    }
+}
+
+/// Represents a [Name Resolution Block](https://www.ietf.org/archive/id/draft-ietf-opsawg-pcapng-01.html#name-name-resolution-block).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct NameResolutionBlock<'a> {
+    records: &'a [NameRecord],
+    options: Cow<'a, Options<'a>>,
+}
+
+impl<'a> NameResolutionBlock<'a> {
+    pub fn new(records: &'a [NameRecord], options: &'a Options) -> NameResolutionBlock<'a> {
+        NameResolutionBlock {
+            records,
+            options: Cow::Borrowed(options),
+        }
+    }
+
+    pub fn records(&self) -> &'a [NameRecord] {
+        self.records
+    }
+}
+
+push_option!(NameResolutionBlock);
+
+impl Block for NameResolutionBlock<'_> {
+    const TYPE: BlockType = BlockType::NameResolution;
+
+    fn length(&self) -> usize {
+        // the records are followed by an nrb_record_end record
+        BLOCK_COMMON_LEN
+            + self.records.iter().map(|r| r.encoded_len()).sum::<usize>()
+            + 4
+            + self.options.encoded_len()
+    }
+}
+
+impl EncodedLen for NameResolutionBlock<'_> {
+    fn encoded_len(&self) -> usize {
+        self.length()
+    }
+}
+
+impl<W: Write> Encodable<W> for NameResolutionBlock<'_> {
+    fn encode<B: ByteOrder>(&self, w: &mut W) -> Result<()> {
+        let total_length = self.total_length()?;
+        self.options.check_lengths()?;
+        w.write_u32::<B>(Self::TYPE.value())?;
+        w.write_u32::<B>(total_length)?;
+        for record in self.records {
+            record.write::<B, W>(w)?;
+        }
+        w.write_u32::<B>(0)?;
+        self.options.encode::<B>(w)?;
+        w.write_u32::<B>(total_length)?;
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.options.check_lengths()?;
+        Ok(self.options.check_order()?)
+    }
+
+    fn block_type(&self) -> Option<u32> {
+        Some(Self::TYPE.value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::{BigEndian, LittleEndian};
+    use core::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn new_nrb() {
+        let records = [
+            NameRecord::new(Ipv4Addr::new(192, 0, 2, 1).into(), &["example.com", "www"]),
+            NameRecord::new(Ipv6Addr::LOCALHOST.into(), &["localhost"]),
+        ];
+        let options = Options::new();
+        let nrb = NameResolutionBlock::new(&records, &options);
+        let mut buf = vec![];
+        nrb.encode::<BigEndian>(&mut buf).unwrap();
+        assert_eq!(buf.len(), nrb.encoded_len());
+        assert_eq!(buf.len(), 8 + 24 + 32 + 4 + 4);
+        assert_eq!(&buf[..8], &[0, 0, 0, 4, 0, 0, 0, 72]);
+        // ipv4 record of 20 bytes
+        assert_eq!(&buf[8..16], &[0, 1, 0, 20, 192, 0, 2, 1]);
+        assert_eq!(&buf[16..32], b"example.com\0www\0");
+        // ipv6 record of 26 bytes, padded
+        assert_eq!(&buf[32..36], &[0, 2, 0, 26]);
+        assert_eq!(&buf[52..64], b"localhost\0\0\0");
+        // nrb_record_end
+        assert_eq!(&buf[64..68], &[0; 4]);
+        assert_eq!(&buf[68..], &[0, 0, 0, 72]);
+
+        let mut buf = vec![];
+        nrb.encode::<LittleEndian>(&mut buf).unwrap();
+        assert_eq!(&buf[8..12], &[1, 0, 20, 0]);
+    }
+
+    #[test]
+    fn record_too_long() {
+        let name = "a".repeat(0xFFFF);
+        assert_eq!(
+            NameRecord::try_new(Ipv4Addr::LOCALHOST.into(), &[&name]),
+            Err(OptionError::ValueTooLong(4 + 0x10000))
+        );
+    }
+}
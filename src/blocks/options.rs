@@ -1,14 +1,22 @@
-use crate::utils::TimestampResolution;
-use crate::writer::Encodable;
+use crate::blocks::PADDING;
+use crate::constants::OPTION_MAX_LEN;
+use crate::error;
+use crate::io::{self, Write, WriteBytesExt};
+use crate::utils::{Timestamp, TimestampResolution};
+use crate::writer::{Encodable, EncodedLen};
 use crate::{
-    enums::{PacketDirection, ReceptionType},
+    enums::{EpbErrorFlags, PacketDirection, ReceptionType},
     utils::pad_to_32,
 };
-use byteorder::{ByteOrder, WriteBytesExt};
-use std::convert::TryInto;
-use std::io;
-use std::io::Write;
-use std::net::{Ipv4Addr, Ipv6Addr};
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use byteorder::ByteOrder;
+use core::convert::{TryFrom, TryInto};
+use core::fmt;
+use core::iter::FromIterator;
+use core::net::{Ipv4Addr, Ipv6Addr};
+use core::str::FromStr;
 
 /*
         0                   1                   2                   3
@@ -29,31 +37,107 @@ use std::net::{Ipv4Addr, Ipv6Addr};
                          Figure 7: Options Format
 */
 
-#[derive(Debug)]
+/// Errors that can occur when constructing or checking options.
+#[derive(Debug, PartialEq)]
+pub enum OptionError {
+    /// The option value is longer than the 65535 bytes allowed by
+    /// the Option Length field. Contains the actual length.
+    ValueTooLong(usize),
+    /// The declared length of a raw option does not match the
+    /// length of its value.
+    LengthMismatch { declared: u16, actual: usize },
+    /// The string could not be parsed as an IP address.
+    InvalidIpAddr(String),
+    /// The prefix length is larger than the number of bits in the
+    /// address (32 for IPv4, 128 for IPv6).
+    InvalidPrefixLen(u8),
+    /// The string could not be parsed as a MAC address.
+    InvalidMacAddr(String),
+    /// An `opt_endofopt` option is followed by other options.
+    EndOfOptNotLast,
+    /// The FCS length does not fit in the 4 bits of `epb_flags`.
+    InvalidFcsLen(u8),
+    /// The digest of an `epb_hash` option is not as long as the
+    /// algorithm's digests.
+    InvalidDigestLen { expected: usize, actual: usize },
+    /// The option has no value type in this crate yet, so it cannot
+    /// be encoded. Contains the option code.
+    Unsupported(u16),
+}
+
+pub(crate) fn check_len(len: usize) -> Result<(), OptionError> {
+    if len > OPTION_MAX_LEN {
+        return Err(OptionError::ValueTooLong(len));
+    }
+    Ok(())
+}
+
+impl fmt::Display for OptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ValueTooLong(len) => write!(
+                f,
+                "option value is {} bytes long (maximum is {})",
+                len, OPTION_MAX_LEN
+            ),
+            Self::LengthMismatch { declared, actual } => write!(
+                f,
+                "option length is declared as {} but the value is {} bytes long",
+                declared, actual
+            ),
+            Self::InvalidIpAddr(s) => write!(f, "invalid IP address: {:?}", s),
+            Self::InvalidPrefixLen(n) => write!(f, "invalid prefix length: {}", n),
+            Self::InvalidMacAddr(s) => write!(f, "invalid MAC address: {:?}", s),
+            Self::EndOfOptNotLast => write!(f, "opt_endofopt is not the last option"),
+            Self::InvalidFcsLen(n) => write!(f, "invalid FCS length: {}", n),
+            Self::InvalidDigestLen { expected, actual } => write!(
+                f,
+                "digest is {} bytes long but the algorithm's digests are {}",
+                actual, expected
+            ),
+            Self::Unsupported(code) => write!(f, "option {} cannot be encoded", code),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for OptionError {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BlockOption {
     OptEndOfOpt(OptionEndOfOpt),
     OptComment(OptionComment),
     OptCustom(u16),
-    ShbHardware,
-    ShbOs,
-    ShbUserAppl,
+    ShbHardware(OptionShbHardware),
+    ShbOs(OptionShbOs),
+    ShbUserAppl(OptionShbUserAppl),
     IfName(OptionIfName),
     IfDescription(OptionIfDescription),
     IfIpv4Addr(OptionIfIpv4Addr),
     IfIpv6Addr(OptionIfIpv6Addr),
     IfMacAddr(OptionIfMacAddr),
     IfEuiAddr,
-    IfSpeed,
+    IfSpeed(OptionIfSpeed),
     IfTsResol(OptionIfTsResol),
     IfTZone,
-    IfFilter,
+    IfFilter(OptionIfFilter),
     IfOs,
-    IfFcsLen,
+    IfFcsLen(OptionIfFcsLen),
     IfTsOffset,
     IfHardware,
     EpbFlags(OptionEpbFlags),
-    EpbHash,
-    EpbDropCount,
+    EpbHash(OptionEpbHash),
+    EpbDropCount(OptionEpbDropCount),
+    EpbQueue(OptionEpbQueue),
+    EpbVerdict(OptionEpbVerdict),
+    IsbStartTime(OptionIsbStartTime),
+    IsbEndTime(OptionIsbEndTime),
+    IsbIfRecv(OptionIsbIfRecv),
+    IsbIfDrop(OptionIsbIfDrop),
+    IsbFilterAccept(OptionIsbFilterAccept),
+    IsbOsDrop(OptionIsbOsDrop),
+    IsbUsrDeliv(OptionIsbUsrDeliv),
     Raw(RawOption),
 }
 
@@ -63,89 +147,183 @@ impl BlockOption {
             Self::OptEndOfOpt(_) => 0,
             Self::OptComment(_) => 1,
             Self::OptCustom(x) => *x,
-            Self::ShbHardware => 2,
-            Self::ShbOs => 3,
-            Self::ShbUserAppl => 4,
+            Self::ShbHardware(_) => 2,
+            Self::ShbOs(_) => 3,
+            Self::ShbUserAppl(_) => 4,
             Self::IfName(_) => 2,
             Self::IfDescription(_) => 3,
             Self::IfIpv4Addr(_) => 4,
             Self::IfIpv6Addr(_) => 5,
             Self::IfMacAddr(_) => 6,
             Self::IfEuiAddr => 7,
-            Self::IfSpeed => 8,
+            Self::IfSpeed(_) => 8,
             Self::IfTsResol(_) => 9,
             Self::IfTZone => 10,
-            Self::IfFilter => 11,
+            Self::IfFilter(_) => 11,
             Self::IfOs => 12,
-            Self::IfFcsLen => 13,
+            Self::IfFcsLen(_) => 13,
             Self::IfTsOffset => 14,
             Self::IfHardware => 15,
             Self::EpbFlags(_) => 2,
-            Self::EpbHash => 3,
-            Self::EpbDropCount => 4,
-            Self::Raw(_) => unimplemented!(),
+            Self::EpbHash(_) => 3,
+            Self::EpbDropCount(_) => 4,
+            Self::EpbQueue(_) => 6,
+            Self::EpbVerdict(_) => 7,
+            Self::IsbStartTime(_) => 2,
+            Self::IsbEndTime(_) => 3,
+            Self::IsbIfRecv(_) => 4,
+            Self::IsbIfDrop(_) => 5,
+            Self::IsbFilterAccept(_) => 6,
+            Self::IsbOsDrop(_) => 7,
+            Self::IsbUsrDeliv(_) => 8,
+            Self::Raw(r) => r.code,
         }
     }
 
-    fn bytes<B: ByteOrder>(&self) -> Vec<u8> {
+    /// Writes the option value, without the code, length and
+    /// padding. Raw options write their code and length too.
+    fn write_value<B: ByteOrder, W: Write>(&self, w: &mut W) -> io::Result<()> {
         match self {
-            Self::OptEndOfOpt(o) => o.bytes::<B>(),
-            Self::OptComment(o) => o.bytes::<B>(),
-            Self::IfName(o) => o.bytes::<B>(),
-            Self::IfDescription(o) => o.bytes::<B>(),
-            Self::IfIpv4Addr(o) => o.bytes::<B>(),
-            Self::IfIpv6Addr(o) => o.bytes::<B>(),
-            Self::IfMacAddr(o) => o.bytes::<B>(),
-            Self::IfTsResol(o) => o.bytes::<B>(),
-            Self::EpbFlags(o) => o.bytes::<B>(),
-            Self::Raw(r) => r.bytes::<B>(),
-            _ => unimplemented!(),
+            Self::OptEndOfOpt(_) => Ok(()),
+            Self::OptComment(o) => o.write_value(w),
+            Self::ShbHardware(o) => o.write_value(w),
+            Self::ShbOs(o) => o.write_value(w),
+            Self::ShbUserAppl(o) => o.write_value(w),
+            Self::IfName(o) => o.write_value(w),
+            Self::IfDescription(o) => o.write_value(w),
+            Self::IfIpv4Addr(o) => o.write_value(w),
+            Self::IfIpv6Addr(o) => o.write_value(w),
+            Self::IfMacAddr(o) => o.write_value(w),
+            Self::IfSpeed(o) => o.write_value::<B, W>(w),
+            Self::IfTsResol(o) => o.write_value(w),
+            Self::IfFilter(o) => o.write_value(w),
+            Self::IfFcsLen(o) => o.write_value(w),
+            Self::EpbFlags(o) => o.write_value::<B, W>(w),
+            Self::EpbHash(o) => o.write_value(w),
+            Self::EpbDropCount(o) => o.write_value::<B, W>(w),
+            Self::EpbQueue(o) => o.write_value::<B, W>(w),
+            Self::EpbVerdict(o) => o.write_value::<B, W>(w),
+            Self::IsbStartTime(o) => o.write_value::<B, W>(w),
+            Self::IsbEndTime(o) => o.write_value::<B, W>(w),
+            Self::IsbIfRecv(o) => o.write_value::<B, W>(w),
+            Self::IsbIfDrop(o) => o.write_value::<B, W>(w),
+            Self::IsbFilterAccept(o) => o.write_value::<B, W>(w),
+            Self::IsbOsDrop(o) => o.write_value::<B, W>(w),
+            Self::IsbUsrDeliv(o) => o.write_value::<B, W>(w),
+            Self::Raw(r) => r.write_value::<B, W>(w),
+            // Refused by `check_supported` before anything is written.
+            Self::OptCustom(_)
+            | Self::IfEuiAddr
+            | Self::IfTZone
+            | Self::IfOs
+            | Self::IfTsOffset
+            | Self::IfHardware => Ok(()),
         }
     }
 
-    fn length(&self) -> u16 {
+    fn length(&self) -> usize {
         match self {
             Self::OptEndOfOpt(o) => o.length(),
             Self::OptComment(o) => o.length(),
+            Self::ShbHardware(o) => o.length(),
+            Self::ShbOs(o) => o.length(),
+            Self::ShbUserAppl(o) => o.length(),
             Self::IfName(o) => o.length(),
             Self::IfDescription(o) => o.length(),
             Self::IfIpv4Addr(o) => o.length(),
             Self::IfIpv6Addr(o) => o.length(),
             Self::IfMacAddr(o) => o.length(),
+            Self::IfSpeed(o) => o.length(),
             Self::IfTsResol(o) => o.length(),
+            Self::IfFilter(o) => o.length(),
+            Self::IfFcsLen(o) => o.length(),
             Self::EpbFlags(o) => o.length(),
-            Self::Raw(r) => r.length,
-            _ => unimplemented!(),
+            Self::EpbHash(o) => o.length(),
+            Self::EpbDropCount(o) => o.length(),
+            Self::EpbQueue(o) => o.length(),
+            Self::EpbVerdict(o) => o.length(),
+            Self::IsbStartTime(o) => o.length(),
+            Self::IsbEndTime(o) => o.length(),
+            Self::IsbIfRecv(o) => o.length(),
+            Self::IsbIfDrop(o) => o.length(),
+            Self::IsbFilterAccept(o) => o.length(),
+            Self::IsbOsDrop(o) => o.length(),
+            Self::IsbUsrDeliv(o) => o.length(),
+            Self::Raw(r) => r.length.into(),
+            Self::OptCustom(_)
+            | Self::IfEuiAddr
+            | Self::IfTZone
+            | Self::IfOs
+            | Self::IfTsOffset
+            | Self::IfHardware => 0,
+        }
+    }
+
+    /// Checks that the option has a value type and can be encoded.
+    /// Some option codes are listed without one, e.g. `if_tzone`.
+    fn check_supported(&self) -> Result<(), OptionError> {
+        match self {
+            Self::OptCustom(_)
+            | Self::IfEuiAddr
+            | Self::IfTZone
+            | Self::IfOs
+            | Self::IfTsOffset
+            | Self::IfHardware => Err(OptionError::Unsupported(self.code())),
+            _ => Ok(()),
         }
     }
 
-    fn padding(&self) -> Vec<u8> {
-        let n = pad_to_32(self.length().into());
-        vec![0u8; n]
+    fn padding(&self) -> &'static [u8] {
+        &PADDING[..pad_to_32(self.length())]
+    }
+}
+
+impl EncodedLen for BlockOption {
+    /// Number of bytes in the encoded option, including the code and
+    /// length fields and the padding.
+    fn encoded_len(&self) -> usize {
+        4 + self.length() + pad_to_32(self.length())
     }
 }
 
 impl<W: Write> Encodable<W> for BlockOption {
-    fn encode<B: ByteOrder>(&self, w: &mut W) -> io::Result<()> {
+    fn encode<B: ByteOrder>(&self, w: &mut W) -> error::Result<()> {
+        self.check_supported()?;
         if let Self::Raw(_) = self {
-            w.write_all(&self.bytes::<B>())?;
-            w.write_all(&self.padding())?;
+            self.write_value::<B, W>(w)?;
+            w.write_all(self.padding())?;
             Ok(())
         } else {
+            let length: u16 = self
+                .length()
+                .try_into()
+                .map_err(|_| OptionError::ValueTooLong(self.length()))?;
             w.write_u16::<B>(self.code())?;
-            w.write_u16::<B>(self.length())?;
-            w.write_all(&self.bytes::<B>())?;
-            w.write_all(&self.padding())?;
+            w.write_u16::<B>(length)?;
+            self.write_value::<B, W>(w)?;
+            w.write_all(self.padding())?;
             Ok(())
         }
     }
 }
 
-#[derive(Debug, Default)]
-pub struct Options<'a>(Vec<&'a BlockOption>);
+/// The options of a block.
+///
+/// Options are usually borrowed, so the same options can be shared by
+/// many blocks, but they can also be owned, e.g. options created in a
+/// loop, with `add_owned_option`. Both can be mixed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Options<'a>(Vec<Cow<'a, BlockOption>>);
+
+impl EncodedLen for Options<'_> {
+    fn encoded_len(&self) -> usize {
+        self.0.iter().map(|opt| opt.encoded_len()).sum()
+    }
+}
 
 impl<'a, W: Write> Encodable<W> for Options<'a> {
-    fn encode<B: ByteOrder>(&self, w: &mut W) -> io::Result<()> {
+    fn encode<B: ByteOrder>(&self, w: &mut W) -> error::Result<()> {
         for opt in &self.0 {
             opt.encode::<B>(w)?;
         }
@@ -159,22 +337,109 @@ impl<'a> Options<'a> {
     }
 
     pub fn add_option(&mut self, opt: &'a BlockOption) {
-        self.0.push(opt);
+        self.0.push(Cow::Borrowed(opt));
+    }
+
+    /// Adds all the options in `opts`, e.g. the result of
+    /// `OptionComment::new_options`.
+    pub fn add_options(&mut self, opts: &'a [BlockOption]) {
+        self.0.extend(opts.iter().map(Cow::Borrowed));
+    }
+
+    /// Adds an option that the container owns, so it does not need
+    /// to outlive the container.
+    pub fn add_owned_option(&mut self, opt: BlockOption) {
+        self.0.push(Cow::Owned(opt));
+    }
+
+    /// Adds an owned option, before `opt_endofopt` if it is the last
+    /// option.
+    pub(crate) fn push_before_end(&mut self, opt: BlockOption) {
+        let end = match self.0.last() {
+            Some(last) if matches!(**last, BlockOption::OptEndOfOpt(_)) => self.0.len() - 1,
+            _ => self.0.len(),
+        };
+        self.0.insert(end, Cow::Owned(opt));
+    }
+
+    /// Returns options that own all their options, e.g. to keep
+    /// them beyond the lifetime of the borrowed ones.
+    pub fn into_owned(self) -> Options<'static> {
+        Options(
+            self.0
+                .into_iter()
+                .map(|opt| Cow::Owned(opt.into_owned()))
+                .collect(),
+        )
     }
 
+    /// Returns the number of bytes in the encoded options.
+    /// Saturates at `u32::MAX`, which no valid block can hold.
     pub fn length(&self) -> u32 {
-        self.0
-            .iter()
-            .map(|opt| opt.length() as u32 + opt.padding().len() as u32 + 4)
-            .sum()
+        self.encoded_len().try_into().unwrap_or(u32::MAX)
     }
 
     pub fn clear(&mut self) {
         self.0.clear();
     }
+
+    /// Checks that every option can be encoded and fits in the 16-bit
+    /// length field, so that a block can be refused before any of it
+    /// is written.
+    pub fn check_lengths(&self) -> Result<(), OptionError> {
+        self.iter().try_for_each(|opt| {
+            opt.check_supported()?;
+            match opt {
+                BlockOption::Raw(_) => Ok(()),
+                _ => check_len(opt.length()),
+            }
+        })
+    }
+
+    /// Checks that `opt_endofopt`, if present, is the last option.
+    pub fn check_order(&self) -> Result<(), OptionError> {
+        let last = self.0.len().saturating_sub(1);
+        match self
+            .iter()
+            .position(|opt| matches!(opt, BlockOption::OptEndOfOpt(_)))
+        {
+            Some(i) if i != last => Err(OptionError::EndOfOptNotLast),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns an iterator over the options.
+    pub fn iter(&self) -> impl Iterator<Item = &BlockOption> {
+        self.0.iter().map(|opt| &**opt)
+    }
+}
+
+impl<'a> FromIterator<&'a BlockOption> for Options<'a> {
+    fn from_iter<I: IntoIterator<Item = &'a BlockOption>>(iter: I) -> Self {
+        Self(iter.into_iter().map(Cow::Borrowed).collect())
+    }
+}
+
+impl<'a> Extend<&'a BlockOption> for Options<'a> {
+    fn extend<I: IntoIterator<Item = &'a BlockOption>>(&mut self, iter: I) {
+        self.0.extend(iter.into_iter().map(Cow::Borrowed));
+    }
+}
+
+impl FromIterator<BlockOption> for Options<'_> {
+    fn from_iter<I: IntoIterator<Item = BlockOption>>(iter: I) -> Self {
+        Self(iter.into_iter().map(Cow::Owned).collect())
+    }
+}
+
+impl Extend<BlockOption> for Options<'_> {
+    fn extend<I: IntoIterator<Item = BlockOption>>(&mut self, iter: I) {
+        self.0.extend(iter.into_iter().map(Cow::Owned));
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RawOption {
     code: u16,
     length: u16,
@@ -190,12 +455,30 @@ impl RawOption {
         }
     }
 
-    fn bytes<B: ByteOrder>(&self) -> Vec<u8> {
-        let mut buf: Vec<u8> = vec![];
-        buf.write_u16::<B>(self.code).unwrap();
-        buf.write_u16::<B>(self.length).unwrap();
-        buf.write_all(&self.value).unwrap();
-        buf
+    /// Creates a new raw option, checking that `length` matches the
+    /// length of `value`.
+    pub fn try_new(code: u16, length: u16, value: Vec<u8>) -> Result<RawOption, OptionError> {
+        if length as usize != value.len() {
+            return Err(OptionError::LengthMismatch {
+                declared: length,
+                actual: value.len(),
+            });
+        }
+        Ok(Self::new(code, length, value))
+    }
+
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    fn write_value<B: ByteOrder, W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u16::<B>(self.code)?;
+        w.write_u16::<B>(self.length)?;
+        w.write_all(&self.value)
     }
 }
 
@@ -205,7 +488,8 @@ impl RawOption {
       list of options.
 */
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OptionEndOfOpt;
 
 impl OptionEndOfOpt {
@@ -217,13 +501,9 @@ impl OptionEndOfOpt {
         BlockOption::OptEndOfOpt(Self::new())
     }
 
-    fn length(&self) -> u16 {
+    fn length(&self) -> usize {
         0
     }
-
-    fn bytes<B: ByteOrder>(&self) -> Vec<u8> {
-        vec![]
-    }
 }
 
 /*
@@ -234,12 +514,18 @@ impl OptionEndOfOpt {
       considered a line separator.  The string is not zero-terminated.
 */
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OptionComment {
     comment: String,
 }
 
 impl OptionComment {
+    /// Creates a new comment option.
+    ///
+    /// Encoding fails if `comment` is longer than 65535 bytes. Use
+    /// `try_new` to catch this early, or `new_options` for comments
+    /// of arbitrary length.
     pub fn new(comment: &str) -> Self {
         Self {
             comment: comment.to_string(),
@@ -250,13 +536,171 @@ impl OptionComment {
         BlockOption::OptComment(Self::new(comment))
     }
 
-    fn length(&self) -> u16 {
-        self.comment.len().try_into().unwrap()
+    /// Creates a new comment option. Returns an error if `comment`
+    /// does not fit in a single option.
+    pub fn try_new(comment: &str) -> Result<Self, OptionError> {
+        check_len(comment.len())?;
+        Ok(Self::new(comment))
     }
 
-    fn bytes<B: ByteOrder>(&self) -> Vec<u8> {
-        self.comment.as_bytes().to_vec()
+    pub fn try_new_option(comment: &str) -> Result<BlockOption, OptionError> {
+        Self::try_new(comment).map(BlockOption::OptComment)
     }
+
+    /// Creates as many `opt_comment` options as needed to hold
+    /// `comment`. Long comments are split on UTF-8 character
+    /// boundaries, so every part is a valid string on its own.
+    pub fn new_options(comment: &str) -> Vec<BlockOption> {
+        let mut opts = Vec::new();
+        let mut rest = comment;
+        while rest.len() > OPTION_MAX_LEN {
+            let mut end = OPTION_MAX_LEN;
+            while !rest.is_char_boundary(end) {
+                end -= 1;
+            }
+            let (part, tail) = rest.split_at(end);
+            opts.push(Self::new_option(part));
+            rest = tail;
+        }
+        if !rest.is_empty() || opts.is_empty() {
+            opts.push(Self::new_option(rest));
+        }
+        opts
+    }
+
+    pub fn comment(&self) -> &str {
+        &self.comment
+    }
+
+    fn length(&self) -> usize {
+        self.comment.len()
+    }
+
+    fn write_value<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(self.comment.as_bytes())
+    }
+}
+
+/// Defines an option holding a UTF-8 string, which is not
+/// zero-terminated.
+macro_rules! string_option {
+    ($(#[$doc:meta])* $name:ident, $variant:ident, $field:ident) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        pub struct $name {
+            $field: String,
+        }
+
+        impl $name {
+            /// Creates a new option.
+            ///
+            /// Encoding fails if the value is longer than 65535 bytes;
+            /// see `try_new`.
+            pub fn new($field: &str) -> Self {
+                Self {
+                    $field: $field.to_string(),
+                }
+            }
+
+            pub fn new_option($field: &str) -> BlockOption {
+                BlockOption::$variant(Self::new($field))
+            }
+
+            pub fn try_new($field: &str) -> Result<Self, OptionError> {
+                check_len($field.len())?;
+                Ok(Self::new($field))
+            }
+
+            pub fn try_new_option($field: &str) -> Result<BlockOption, OptionError> {
+                Self::try_new($field).map(BlockOption::$variant)
+            }
+
+            pub fn $field(&self) -> &str {
+                &self.$field
+            }
+
+            fn length(&self) -> usize {
+                self.$field.len()
+            }
+
+            fn write_value<W: Write>(&self, w: &mut W) -> io::Result<()> {
+                w.write_all(self.$field.as_bytes())
+            }
+        }
+    };
+}
+
+/*
+   shb_hardware:  The shb_hardware option is a UTF-8 string containing
+      the description of the hardware used to create this section.
+      The string is not zero-terminated.
+
+          Examples: "x86 Personal Computer", "Sun Sparc Workstation".
+*/
+
+string_option!(
+    /// The `shb_hardware` option.
+    OptionShbHardware,
+    ShbHardware,
+    hardware
+);
+
+/*
+   shb_os:  The shb_os option is a UTF-8 string containing the name of
+      the operating system used to create this section.  The string is
+      not zero-terminated.
+
+          Examples: "Windows XP SP2", "openSUSE 10.2".
+*/
+
+string_option!(
+    /// The `shb_os` option.
+    OptionShbOs,
+    ShbOs,
+    os
+);
+
+/*
+   shb_userappl:  The shb_userappl option is a UTF-8 string containing
+      the name of the application used to create this section.  The
+      string is not zero-terminated.
+
+          Examples: "dumpcap V0.99.7".
+*/
+
+string_option!(
+    /// The `shb_userappl` option. `shb_userappl!` creates one with the
+    /// name and version of the calling crate.
+    OptionShbUserAppl,
+    ShbUserAppl,
+    application
+);
+
+/// Creates an `shb_userappl` option with the name and version of the
+/// crate it is used in, from `CARGO_PKG_NAME` and `CARGO_PKG_VERSION`,
+/// e.g. "mytool 1.2.0". Returns a `BlockOption`.
+///
+/// ```
+/// use pcapng_writer::blocks::options::{BlockOption, Options};
+/// use pcapng_writer::shb_userappl;
+///
+/// let userappl = shb_userappl!();
+/// if let BlockOption::ShbUserAppl(o) = &userappl {
+///     assert_eq!(o.application(), concat!("pcapng-writer ", env!("CARGO_PKG_VERSION")));
+/// }
+/// let mut options = Options::new();
+/// options.add_option(&userappl);
+/// ```
+#[macro_export]
+macro_rules! shb_userappl {
+    () => {
+        $crate::blocks::options::OptionShbUserAppl::new_option(concat!(
+            env!("CARGO_PKG_NAME"),
+            " ",
+            env!("CARGO_PKG_VERSION")
+        ))
+    };
 }
 
 /*
@@ -268,12 +712,17 @@ impl OptionComment {
          "\Device\NPF\_{AD1CE675-96D0-47C5-ADD0-2504B9126B68}".
 */
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OptionIfName {
     if_name: String,
 }
 
 impl OptionIfName {
+    /// Creates a new `if_name` option.
+    ///
+    /// Encoding fails if `name` is longer than 65535 bytes; see
+    /// `try_new`.
     pub fn new(name: &str) -> Self {
         Self {
             if_name: name.to_string(),
@@ -284,12 +733,25 @@ impl OptionIfName {
         BlockOption::IfName(Self::new(name))
     }
 
-    fn length(&self) -> u16 {
-        self.if_name.len().try_into().unwrap()
+    pub fn try_new(name: &str) -> Result<Self, OptionError> {
+        check_len(name.len())?;
+        Ok(Self::new(name))
+    }
+
+    pub fn try_new_option(name: &str) -> Result<BlockOption, OptionError> {
+        Self::try_new(name).map(BlockOption::IfName)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.if_name
+    }
+
+    fn length(&self) -> usize {
+        self.if_name.len()
     }
 
-    fn bytes<B: ByteOrder>(&self) -> Vec<u8> {
-        self.if_name.as_bytes().to_vec()
+    fn write_value<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(self.if_name.as_bytes())
     }
 }
 
@@ -302,12 +764,17 @@ impl OptionIfName {
           Connection", "First Ethernet Interface".
 */
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OptionIfDescription {
     if_description: String,
 }
 
 impl OptionIfDescription {
+    /// Creates a new `if_description` option.
+    ///
+    /// Encoding fails if `description` is longer than 65535 bytes;
+    /// see `try_new`.
     pub fn new(description: &str) -> Self {
         Self {
             if_description: description.to_string(),
@@ -318,12 +785,25 @@ impl OptionIfDescription {
         BlockOption::IfDescription(Self::new(description))
     }
 
-    fn length(&self) -> u16 {
-        self.if_description.len().try_into().unwrap()
+    pub fn try_new(description: &str) -> Result<Self, OptionError> {
+        check_len(description.len())?;
+        Ok(Self::new(description))
+    }
+
+    pub fn try_new_option(description: &str) -> Result<BlockOption, OptionError> {
+        Self::try_new(description).map(BlockOption::IfDescription)
+    }
+
+    pub fn description(&self) -> &str {
+        &self.if_description
+    }
+
+    fn length(&self) -> usize {
+        self.if_description.len()
     }
 
-    fn bytes<B: ByteOrder>(&self) -> Vec<u8> {
-        self.if_description.as_bytes().to_vec()
+    fn write_value<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(self.if_description.as_bytes())
     }
 }
 
@@ -341,32 +821,108 @@ impl OptionIfDescription {
          Examples: '192 168 1 1 255 255 255 0'.
 */
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OptionIfIpv4Addr {
     ip: Ipv4Addr,
     netmask: Ipv4Addr,
 }
 
 impl OptionIfIpv4Addr {
+    /// Creates a new `if_IPv4addr` option.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ip` or `netmask` is not a valid IPv4 address; see
+    /// `try_new`.
     pub fn new(ip: &str, netmask: &str) -> Self {
-        Self {
-            ip: ip.parse().unwrap(),
-            netmask: netmask.parse().unwrap(),
-        }
+        Self::try_new(ip, netmask).unwrap()
     }
 
     pub fn new_option(ip: &str, netmask: &str) -> BlockOption {
         BlockOption::IfIpv4Addr(Self::new(ip, netmask))
     }
 
-    fn length(&self) -> u16 {
+    pub fn try_new(ip: &str, netmask: &str) -> Result<Self, OptionError> {
+        Ok(Self {
+            ip: parse_ip(ip)?,
+            netmask: parse_ip(netmask)?,
+        })
+    }
+
+    pub fn try_new_option(ip: &str, netmask: &str) -> Result<BlockOption, OptionError> {
+        Self::try_new(ip, netmask).map(BlockOption::IfIpv4Addr)
+    }
+
+    /// Creates a new `if_IPv4addr` option from an address and a
+    /// netmask.
+    pub fn from_addrs(ip: Ipv4Addr, netmask: Ipv4Addr) -> Self {
+        Self { ip, netmask }
+    }
+
+    pub fn from_addrs_option(ip: Ipv4Addr, netmask: Ipv4Addr) -> BlockOption {
+        BlockOption::IfIpv4Addr(Self::from_addrs(ip, netmask))
+    }
+
+    /// Creates a new `if_IPv4addr` option from an address and a
+    /// prefix length, deriving the netmask from the latter.
+    pub fn from_prefix_len(ip: Ipv4Addr, prefix_len: u8) -> Result<Self, OptionError> {
+        if prefix_len > 32 {
+            return Err(OptionError::InvalidPrefixLen(prefix_len));
+        }
+        let netmask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+        Ok(Self::from_addrs(ip, netmask.into()))
+    }
+
+    /// Creates a new `if_IPv4addr` option from the CIDR notation,
+    /// e.g. "192.168.1.1/24".
+    pub fn from_cidr(cidr: &str) -> Result<Self, OptionError> {
+        let invalid = || OptionError::InvalidIpAddr(cidr.to_string());
+        let mut parts = cidr.splitn(2, '/');
+        let ip = parse_ip(parts.next().unwrap_or_default())?;
+        let prefix_len = parts
+            .next()
+            .ok_or_else(invalid)?
+            .parse()
+            .map_err(|_| invalid())?;
+        Self::from_prefix_len(ip, prefix_len)
+    }
+
+    pub fn from_cidr_option(cidr: &str) -> Result<BlockOption, OptionError> {
+        Self::from_cidr(cidr).map(BlockOption::IfIpv4Addr)
+    }
+
+    pub fn ip(&self) -> Ipv4Addr {
+        self.ip
+    }
+
+    pub fn netmask(&self) -> Ipv4Addr {
+        self.netmask
+    }
+
+    fn length(&self) -> usize {
         4 + 4
     }
 
-    fn bytes<B: ByteOrder>(&self) -> Vec<u8> {
-        let mut buf = self.ip.octets().to_vec();
-        buf.extend(&self.netmask.octets());
-        buf
+    fn write_value<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.ip.octets())?;
+        w.write_all(&self.netmask.octets())
+    }
+}
+
+/// Parses an address in the CIDR notation; see `from_cidr`.
+impl FromStr for OptionIfIpv4Addr {
+    type Err = OptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_cidr(s)
+    }
+}
+
+#[cfg(feature = "ipnet")]
+impl From<ipnet::Ipv4Net> for OptionIfIpv4Addr {
+    fn from(net: ipnet::Ipv4Net) -> Self {
+        Self::from_addrs(net.addr(), net.netmask())
     }
 }
 
@@ -383,35 +939,119 @@ impl OptionIfIpv4Addr {
           70 73 44 40'.
 */
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "Ipv6AddrFields")
+)]
 pub struct OptionIfIpv6Addr {
     ip: Ipv6Addr,
     prefix_len: u8,
 }
 
+/// The fields of `OptionIfIpv6Addr`, checked when deserializing.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct Ipv6AddrFields {
+    ip: Ipv6Addr,
+    prefix_len: u8,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<Ipv6AddrFields> for OptionIfIpv6Addr {
+    type Error = OptionError;
+
+    fn try_from(fields: Ipv6AddrFields) -> Result<Self, Self::Error> {
+        Self::from_addr(fields.ip, fields.prefix_len)
+    }
+}
+
 impl OptionIfIpv6Addr {
+    /// Creates a new `if_IPv6addr` option.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ip` is not a valid IPv6 address or `prefix_len` is
+    /// larger than 128; see `try_new`.
     pub fn new(ip: &str, prefix_len: u8) -> Self {
-        Self {
-            ip: ip.parse().unwrap(),
-            prefix_len,
-        }
+        Self::try_new(ip, prefix_len).unwrap()
     }
 
     pub fn new_option(ip: &str, prefix_len: u8) -> BlockOption {
         BlockOption::IfIpv6Addr(Self::new(ip, prefix_len))
     }
 
-    fn length(&self) -> u16 {
+    pub fn try_new(ip: &str, prefix_len: u8) -> Result<Self, OptionError> {
+        Self::from_addr(parse_ip(ip)?, prefix_len)
+    }
+
+    pub fn try_new_option(ip: &str, prefix_len: u8) -> Result<BlockOption, OptionError> {
+        Self::try_new(ip, prefix_len).map(BlockOption::IfIpv6Addr)
+    }
+
+    /// Creates a new `if_IPv6addr` option from an address and a
+    /// prefix length.
+    pub fn from_addr(ip: Ipv6Addr, prefix_len: u8) -> Result<Self, OptionError> {
+        if prefix_len > 128 {
+            return Err(OptionError::InvalidPrefixLen(prefix_len));
+        }
+        Ok(Self { ip, prefix_len })
+    }
+
+    pub fn from_addr_option(ip: Ipv6Addr, prefix_len: u8) -> Result<BlockOption, OptionError> {
+        Self::from_addr(ip, prefix_len).map(BlockOption::IfIpv6Addr)
+    }
+
+    pub fn ip(&self) -> Ipv6Addr {
+        self.ip
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    fn length(&self) -> usize {
         16 + 1
     }
 
-    fn bytes<B: ByteOrder>(&self) -> Vec<u8> {
-        let mut buf = self.ip.octets().to_vec();
-        buf.push(self.prefix_len);
-        buf
+    fn write_value<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.ip.octets())?;
+        w.write_u8(self.prefix_len)
+    }
+}
+
+/// Parses an address in the "2001:db8::1/64" notation.
+impl FromStr for OptionIfIpv6Addr {
+    type Err = OptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '/');
+        let ip = parts.next().unwrap_or_default();
+        let prefix_len = parts
+            .next()
+            .ok_or_else(|| OptionError::InvalidIpAddr(s.to_string()))?
+            .parse()
+            .map_err(|_| OptionError::InvalidIpAddr(s.to_string()))?;
+        Self::try_new(ip, prefix_len)
+    }
+}
+
+#[cfg(feature = "ipnet")]
+impl From<ipnet::Ipv6Net> for OptionIfIpv6Addr {
+    fn from(net: ipnet::Ipv6Net) -> Self {
+        Self {
+            ip: net.addr(),
+            prefix_len: net.prefix_len(),
+        }
     }
 }
 
+fn parse_ip<T: FromStr>(ip: &str) -> Result<T, OptionError> {
+    ip.parse()
+        .map_err(|_| OptionError::InvalidIpAddr(ip.to_string()))
+}
+
 /*
    if_MACaddr:  The if_MACaddr option is the Interface Hardware MAC
       address (48 bits), if available.
@@ -419,32 +1059,129 @@ impl OptionIfIpv6Addr {
           Example: '00 01 02 03 04 05'.
 */
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OptionIfMacAddr {
     mac_addr: [u8; 6],
 }
 
 impl OptionIfMacAddr {
+    /// Creates a new `if_MACaddr` option from a string. Accepts the
+    /// usual hex notation with colon or hyphen separators
+    /// ("00:1a:2b:3c:4d:5e", "00-1A-2B-3C-4D-5E"), as well as six
+    /// dot-separated decimal octets ("0.26.43.60.77.94").
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mac_addr` is malformed; see `try_new`.
     pub fn new(mac_addr: &str) -> Self {
-        let split = mac_addr
-            .split('.')
-            .map(|x| x.parse().unwrap())
-            .collect::<Vec<u8>>();
-        Self {
-            mac_addr: split.try_into().unwrap(),
-        }
+        Self::try_new(mac_addr).unwrap()
     }
 
     pub fn new_option(mac_addr: &str) -> BlockOption {
         BlockOption::IfMacAddr(Self::new(mac_addr))
     }
 
-    fn length(&self) -> u16 {
+    pub fn try_new(mac_addr: &str) -> Result<Self, OptionError> {
+        let invalid = || OptionError::InvalidMacAddr(mac_addr.to_string());
+        let split = if mac_addr.contains([':', '-']) {
+            mac_addr
+                .split([':', '-'])
+                .map(|x| match x.len() {
+                    2 => u8::from_str_radix(x, 16).map_err(|_| invalid()),
+                    _ => Err(invalid()),
+                })
+                .collect::<Result<Vec<u8>, _>>()?
+        } else {
+            mac_addr
+                .split('.')
+                .map(|x| x.parse().map_err(|_| invalid()))
+                .collect::<Result<Vec<u8>, _>>()?
+        };
+        Ok(Self::from_bytes(split.try_into().map_err(|_| invalid())?))
+    }
+
+    pub fn try_new_option(mac_addr: &str) -> Result<BlockOption, OptionError> {
+        Self::try_new(mac_addr).map(BlockOption::IfMacAddr)
+    }
+
+    /// Creates a new `if_MACaddr` option from the six address octets.
+    pub fn from_bytes(mac_addr: [u8; 6]) -> Self {
+        Self { mac_addr }
+    }
+
+    pub fn from_bytes_option(mac_addr: [u8; 6]) -> BlockOption {
+        BlockOption::IfMacAddr(Self::from_bytes(mac_addr))
+    }
+
+    pub fn mac_addr(&self) -> [u8; 6] {
+        self.mac_addr
+    }
+
+    fn length(&self) -> usize {
         6
     }
 
-    fn bytes<B: ByteOrder>(&self) -> Vec<u8> {
-        self.mac_addr.to_vec()
+    fn write_value<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.mac_addr)
+    }
+}
+
+impl FromStr for OptionIfMacAddr {
+    type Err = OptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_new(s)
+    }
+}
+
+impl From<[u8; 6]> for OptionIfMacAddr {
+    fn from(mac_addr: [u8; 6]) -> Self {
+        Self::from_bytes(mac_addr)
+    }
+}
+
+#[cfg(feature = "macaddr")]
+impl From<macaddr::MacAddr6> for OptionIfMacAddr {
+    fn from(mac_addr: macaddr::MacAddr6) -> Self {
+        Self::from_bytes(mac_addr.into_array())
+    }
+}
+
+/*
+   if_speed:  The if_speed option is a 64-bit unsigned value indicating
+      the interface speed, in bits per second.
+
+          Example: the 64-bit decimal number 100000000 for 100Mbps.
+*/
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OptionIfSpeed {
+    speed: u64,
+}
+
+impl OptionIfSpeed {
+    /// Creates a new `if_speed` option, with the speed in bits per
+    /// second.
+    pub fn new(speed: u64) -> Self {
+        Self { speed }
+    }
+
+    pub fn new_option(speed: u64) -> BlockOption {
+        BlockOption::IfSpeed(Self::new(speed))
+    }
+
+    pub fn speed(&self) -> u64 {
+        self.speed
+    }
+
+    fn length(&self) -> usize {
+        8
+    }
+
+    fn write_value<B: ByteOrder, W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u64::<B>(self.speed)
     }
 }
 
@@ -463,7 +1200,8 @@ impl OptionIfMacAddr {
           Example: '6'.
 */
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OptionIfTsResol {
     tsresol: u8,
 }
@@ -477,74 +1215,757 @@ impl OptionIfTsResol {
         BlockOption::IfTsResol(Self::new(tsresol.to_tsresol()))
     }
 
-    fn length(&self) -> u16 {
-        1
+    pub fn tsresol(&self) -> u8 {
+        self.tsresol
     }
 
-    fn bytes<B: ByteOrder>(&self) -> Vec<u8> {
-        [self.tsresol].to_vec()
+    /// Returns the parsed resolution, see
+    /// `TimestampResolution::from_tsresol`.
+    pub fn resolution(&self) -> TimestampResolution {
+        TimestampResolution::from_tsresol(self.tsresol)
     }
-}
 
-/*
-   epb_flags:  The epb_flags option is a 32-bit flags word containing
-      link- layer information.  A complete specification of the allowed
-      flags can be found in Section 4.3.1.
-
-          Example: '0'.
-*/
+    fn length(&self) -> usize {
+        1
+    }
 
-#[derive(Debug)]
-pub struct OptionEpbFlags {
-    flags: u32,
+    fn write_value<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u8(self.tsresol)
+    }
 }
 
-impl OptionEpbFlags {
-    pub fn new(
-        dir: PacketDirection,
-        reception: ReceptionType,
-        fcs_length: Option<u8>,
-        error_flags: u16,
-    ) -> Self {
-        let dir_bits = dir.value() & 0b11;
-        let rec_bits = reception.value() & 0b111;
-        let fcs_bits = fcs_length.map_or(0, |x| x & 0b1111);
-        let flags: u32 = dir_bits as u32
-            | ((rec_bits as u32) << 2)
-            | ((fcs_bits as u32) << 5)
-            | ((error_flags as u32) << 16);
-        Self { flags }
+/// The length in bytes of the Frame Check Sequence at the end of
+/// packets, between 0 and 15 so that it fits in `epb_flags`. Where a
+/// length is optional, `None` means that it is unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "u8", into = "u8")
+)]
+pub struct FcsLen(u8);
+
+impl FcsLen {
+    /// No FCS is present.
+    pub const NONE: Self = Self(0);
+
+    pub fn new(len: u8) -> Result<Self, OptionError> {
+        if len > 0b1111 {
+            return Err(OptionError::InvalidFcsLen(len));
+        }
+        Ok(Self(len))
     }
 
-    pub fn new_option(
-        dir: PacketDirection,
-        reception: ReceptionType,
-        fcs_length: Option<u8>,
-        error_flags: u16,
-    ) -> BlockOption {
-        BlockOption::EpbFlags(Self::new(dir, reception, fcs_length, error_flags))
+    pub fn bytes(&self) -> u8 {
+        self.0
     }
 
-    pub fn from_u32(flags: u32) -> Self {
-        Self { flags }
+    pub fn is_none(&self) -> bool {
+        self.0 == 0
     }
+}
 
-    fn length(&self) -> u16 {
-        4
+impl TryFrom<u8> for FcsLen {
+    type Error = OptionError;
+
+    fn try_from(len: u8) -> Result<Self, Self::Error> {
+        Self::new(len)
     }
+}
 
-    fn bytes<B: ByteOrder>(&self) -> Vec<u8> {
-        let mut buf: Vec<u8> = vec![];
-        buf.write_u32::<B>(self.flags).unwrap();
-        buf
+impl From<FcsLen> for u8 {
+    fn from(fcs_len: FcsLen) -> Self {
+        fcs_len.0
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/*
+   if_fcslen:  The if_fcslen option is an 8-bit unsigned integer value
+      that specifies the length of the Frame Check Sequence for this
+      interface.  For link layers whose FCS length can change during
+      time, the Enhanced Packet Block epb_flags Option can be used in
+      each Enhanced Packet Block.
+
+          Example: '4'.
+*/
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OptionIfFcsLen {
+    fcs_len: FcsLen,
+}
+
+impl OptionIfFcsLen {
+    pub fn new(fcs_len: FcsLen) -> Self {
+        Self { fcs_len }
+    }
+
+    pub fn new_option(fcs_len: FcsLen) -> BlockOption {
+        BlockOption::IfFcsLen(Self::new(fcs_len))
+    }
+
+    pub fn fcs_len(&self) -> FcsLen {
+        self.fcs_len
+    }
+
+    fn length(&self) -> usize {
+        1
+    }
+
+    fn write_value<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u8(self.fcs_len.bytes())
+    }
+}
+
+/*
+   if_filter:  The if_filter option identifies the filter (e.g.  "capture
+      only TCP traffic") used to capture traffic.  The first octet of the
+      Option Data keeps a code of the filter used (e.g. if this is a
+      libpcap string, or BPF bytecode, and more).
+
+          Example: '00'"tcp port 23 and host 192.0.2.5".
+*/
+
+/// The kinds of filters of the `if_filter` option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FilterType {
+    /// A filter expression in libpcap syntax, e.g. `tcp port 80`.
+    Libpcap,
+    /// A classic BPF program, as an array of `struct bpf_insn` in the
+    /// byte order of the section.
+    Bpf,
+}
+
+impl FilterType {
+    /// Returns the value of the first octet of the option.
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Libpcap => 0,
+            Self::Bpf => 1,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::Libpcap),
+            1 => Some(Self::Bpf),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OptionIfFilter {
+    filter_type: FilterType,
+    data: Vec<u8>,
+}
+
+impl OptionIfFilter {
+    /// Creates a new `if_filter` option for a libpcap filter
+    /// expression.
+    ///
+    /// Encoding fails if `expression` is longer than 65534 bytes; see
+    /// `try_new`.
+    pub fn new(expression: &str) -> Self {
+        Self::with_data(FilterType::Libpcap, expression.as_bytes())
+    }
+
+    pub fn new_option(expression: &str) -> BlockOption {
+        BlockOption::IfFilter(Self::new(expression))
+    }
+
+    pub fn try_new(expression: &str) -> Result<Self, OptionError> {
+        check_len(1 + expression.len())?;
+        Ok(Self::new(expression))
+    }
+
+    pub fn try_new_option(expression: &str) -> Result<BlockOption, OptionError> {
+        Self::try_new(expression).map(BlockOption::IfFilter)
+    }
+
+    /// Creates a new `if_filter` option with the given filter data,
+    /// which is written as is.
+    pub fn with_data(filter_type: FilterType, data: &[u8]) -> Self {
+        Self {
+            filter_type,
+            data: data.to_vec(),
+        }
+    }
+
+    pub fn filter_type(&self) -> FilterType {
+        self.filter_type
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the expression of a libpcap filter, if it is valid
+    /// UTF-8.
+    pub fn expression(&self) -> Option<&str> {
+        match self.filter_type {
+            FilterType::Libpcap => core::str::from_utf8(&self.data).ok(),
+            FilterType::Bpf => None,
+        }
+    }
+
+    fn length(&self) -> usize {
+        1 + self.data.len()
+    }
+
+    fn write_value<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u8(self.filter_type.code())?;
+        w.write_all(&self.data)
+    }
+}
+
+/*
+   epb_flags:  The epb_flags option is a 32-bit flags word containing
+      link- layer information.  A complete specification of the allowed
+      flags can be found in Section 4.3.1.
+
+          Example: '0'.
+*/
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OptionEpbFlags {
+    flags: u32,
+}
+
+impl OptionEpbFlags {
+    /// Creates a new `epb_flags` option. The FCS length field cannot
+    /// express `FcsLen::NONE`, which is written as unknown.
+    pub fn new(
+        dir: PacketDirection,
+        reception: ReceptionType,
+        fcs_length: Option<FcsLen>,
+        error_flags: EpbErrorFlags,
+    ) -> Self {
+        let dir_bits = dir.value() & 0b11;
+        let rec_bits = reception.value() & 0b111;
+        let fcs_bits = fcs_length.map_or(0, |x| x.bytes());
+        let flags: u32 = dir_bits as u32
+            | ((rec_bits as u32) << 2)
+            | ((fcs_bits as u32) << 5)
+            | ((error_flags.bits() as u32) << 16);
+        Self { flags }
+    }
+
+    pub fn new_option(
+        dir: PacketDirection,
+        reception: ReceptionType,
+        fcs_length: Option<FcsLen>,
+        error_flags: EpbErrorFlags,
+    ) -> BlockOption {
+        BlockOption::EpbFlags(Self::new(dir, reception, fcs_length, error_flags))
+    }
+
+    pub fn from_u32(flags: u32) -> Self {
+        Self { flags }
+    }
+
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// Returns the direction in bits 0-1, or `None` if it has the
+    /// reserved value.
+    pub fn direction(&self) -> Option<PacketDirection> {
+        PacketDirection::from_value((self.flags & 0b11) as u8)
+    }
+
+    /// Returns the reception type in bits 2-4, or `None` if it has a
+    /// reserved value.
+    pub fn reception_type(&self) -> Option<ReceptionType> {
+        ReceptionType::from_value((self.flags >> 2 & 0b111) as u8)
+    }
+
+    /// Returns the FCS length in bits 5-8, or `None` if it is not
+    /// available.
+    pub fn fcs_length(&self) -> Option<FcsLen> {
+        match (self.flags >> 5 & 0b1111) as u8 {
+            0 => None,
+            n => Some(FcsLen(n)),
+        }
+    }
+
+    /// Returns the link-layer dependent errors in bits 16-31.
+    pub fn error_flags(&self) -> EpbErrorFlags {
+        EpbErrorFlags::from_bits((self.flags >> 16) as u16)
+    }
+
+    fn length(&self) -> usize {
+        4
+    }
+
+    fn write_value<B: ByteOrder, W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u32::<B>(self.flags)
+    }
+}
+
+/*
+   epb_hash:  The epb_hash option contains a hash of the packet.  The
+      first octet specifies the hashing algorithm, while the following
+      octets contain the actual hash, whose size depends on the hashing
+      algorithm, and hence from the value in the first octet.  The
+      hashing algorithm can be: 2s complement (algorithm octet = 0,
+      size = XXX), XOR (algorithm octet = 1, size=XXX), CRC32
+      (algorithm octet = 2, size = 4), MD-5 (algorithm octet = 3, size
+      = 16), SHA-1 (algorithm octet = 4, size = 20), Toeplitz
+      (algorithm octet = 5, size = 4).  The hash covers only the packet,
+      not the header added by the capture driver: this gives the
+      possibility to calculate it inside the network card.  The hash
+      allows easier comparison/merging of different capture files, and
+      reliable data transfer between the data acquisition system and
+      the capture library.
+*/
+
+/// The hashing algorithms of the `epb_hash` option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HashAlgorithm {
+    TwosComplement,
+    Xor,
+    Crc32,
+    Md5,
+    Sha1,
+    Toeplitz,
+}
+
+impl HashAlgorithm {
+    /// Returns the value of the first octet of the option.
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::TwosComplement => 0,
+            Self::Xor => 1,
+            Self::Crc32 => 2,
+            Self::Md5 => 3,
+            Self::Sha1 => 4,
+            Self::Toeplitz => 5,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::TwosComplement),
+            1 => Some(Self::Xor),
+            2 => Some(Self::Crc32),
+            3 => Some(Self::Md5),
+            4 => Some(Self::Sha1),
+            5 => Some(Self::Toeplitz),
+            _ => None,
+        }
+    }
+
+    /// Returns the length of the digests, or `None` for the
+    /// algorithms whose digest length the standard leaves open.
+    pub fn digest_len(&self) -> Option<usize> {
+        match self {
+            Self::TwosComplement | Self::Xor => None,
+            Self::Crc32 | Self::Toeplitz => Some(4),
+            Self::Md5 => Some(16),
+            Self::Sha1 => Some(20),
+        }
+    }
+
+    /// Checks that `digest` has the length of this algorithm's
+    /// digests.
+    pub fn check_digest(&self, digest: &[u8]) -> Result<(), OptionError> {
+        match self.digest_len() {
+            Some(expected) if expected != digest.len() => Err(OptionError::InvalidDigestLen {
+                expected,
+                actual: digest.len(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "EpbHashFields")
+)]
+pub struct OptionEpbHash {
+    algorithm: HashAlgorithm,
+    digest: Vec<u8>,
+}
+
+/// The fields of `OptionEpbHash`, checked when deserializing.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct EpbHashFields {
+    algorithm: HashAlgorithm,
+    digest: Vec<u8>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<EpbHashFields> for OptionEpbHash {
+    type Error = OptionError;
+
+    fn try_from(fields: EpbHashFields) -> Result<Self, Self::Error> {
+        Self::try_new(fields.algorithm, &fields.digest)
+    }
+}
+
+impl OptionEpbHash {
+    /// Creates a new `epb_hash` option.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `digest` does not have the length of the
+    /// algorithm's digests, or does not fit in an option; see
+    /// `try_new`.
+    pub fn new(algorithm: HashAlgorithm, digest: &[u8]) -> Self {
+        Self::try_new(algorithm, digest).unwrap()
+    }
+
+    pub fn new_option(algorithm: HashAlgorithm, digest: &[u8]) -> BlockOption {
+        BlockOption::EpbHash(Self::new(algorithm, digest))
+    }
+
+    pub fn try_new(algorithm: HashAlgorithm, digest: &[u8]) -> Result<Self, OptionError> {
+        algorithm.check_digest(digest)?;
+        check_len(1 + digest.len())?;
+        Ok(Self {
+            algorithm,
+            digest: digest.to_vec(),
+        })
+    }
+
+    pub fn try_new_option(
+        algorithm: HashAlgorithm,
+        digest: &[u8],
+    ) -> Result<BlockOption, OptionError> {
+        Self::try_new(algorithm, digest).map(BlockOption::EpbHash)
+    }
+
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    pub fn digest(&self) -> &[u8] {
+        &self.digest
+    }
+
+    fn length(&self) -> usize {
+        1 + self.digest.len()
+    }
+
+    fn write_value<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u8(self.algorithm.code())?;
+        w.write_all(&self.digest)
+    }
+}
+
+/*
+   epb_queue:  The epb_queue option contains the ID of the queue that
+      the packet was received on.
+
+          Example: '0'.
+*/
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OptionEpbQueue {
+    queue: u32,
+}
+
+impl OptionEpbQueue {
+    pub fn new(queue: u32) -> Self {
+        Self { queue }
+    }
+
+    pub fn new_option(queue: u32) -> BlockOption {
+        BlockOption::EpbQueue(Self::new(queue))
+    }
+
+    pub fn queue(&self) -> u32 {
+        self.queue
+    }
+
+    fn length(&self) -> usize {
+        4
+    }
+
+    fn write_value<B: ByteOrder, W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u32::<B>(self.queue)
+    }
+}
+
+/*
+   epb_verdict:  The epb_verdict option stores a verdict of the packet.
+      The verdict indicates what would be done with the packet after
+      processing it.  For example, a firewall could drop the packet.
+      This verdict can be set by various components, i.e. Hardware,
+      Linux's eBPF TC or XDP framework, etc.  etc.  The first octet
+      specifies the verdict type, while the following octets contain
+      the actual verdict data, whose size depends on the verdict type,
+      and hence from the value in the first octet.
+*/
+
+/// A verdict of the `epb_verdict` option, along with its data.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VerdictType {
+    /// A verdict in a hardware-specific format.
+    Hardware(Vec<u8>),
+    /// The `TC_ACT_*` value returned by a Linux eBPF TC program.
+    LinuxEbpfTc(u64),
+    /// The `XDP_*` action returned by a Linux eBPF XDP program.
+    LinuxEbpfXdp(u64),
+}
+
+impl VerdictType {
+    pub const fn xdp_aborted() -> Self {
+        Self::LinuxEbpfXdp(0)
+    }
+
+    pub const fn xdp_drop() -> Self {
+        Self::LinuxEbpfXdp(1)
+    }
+
+    pub const fn xdp_pass() -> Self {
+        Self::LinuxEbpfXdp(2)
+    }
+
+    pub const fn xdp_tx() -> Self {
+        Self::LinuxEbpfXdp(3)
+    }
+
+    pub const fn xdp_redirect() -> Self {
+        Self::LinuxEbpfXdp(4)
+    }
+
+    /// Returns the value of the first octet of the option.
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Hardware(_) => 0,
+            Self::LinuxEbpfTc(_) => 1,
+            Self::LinuxEbpfXdp(_) => 2,
+        }
+    }
+
+    /// Returns the option value: the code followed by the verdict
+    /// data, in the byte order `B`.
+    pub fn to_payload<B: ByteOrder>(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.length());
+        self.write::<B, _>(&mut buf)
+            .expect("writing to a Vec does not fail");
+        buf
+    }
+
+    /// Parses an option value, see `to_payload`. Returns `None` for
+    /// unknown verdict types and malformed values.
+    pub fn from_payload<B: ByteOrder>(value: &[u8]) -> Option<Self> {
+        let (code, data) = value.split_first()?;
+        match (code, data.len()) {
+            (0, _) => Some(Self::Hardware(data.to_vec())),
+            (1, 8) => Some(Self::LinuxEbpfTc(B::read_u64(data))),
+            (2, 8) => Some(Self::LinuxEbpfXdp(B::read_u64(data))),
+            _ => None,
+        }
+    }
+
+    fn length(&self) -> usize {
+        match self {
+            Self::Hardware(data) => 1 + data.len(),
+            Self::LinuxEbpfTc(_) | Self::LinuxEbpfXdp(_) => 1 + 8,
+        }
+    }
+
+    fn write<B: ByteOrder, W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u8(self.code())?;
+        match self {
+            Self::Hardware(data) => w.write_all(data),
+            Self::LinuxEbpfTc(v) | Self::LinuxEbpfXdp(v) => w.write_u64::<B>(*v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OptionEpbVerdict {
+    verdict: VerdictType,
+}
+
+impl OptionEpbVerdict {
+    /// Creates a new `epb_verdict` option.
+    ///
+    /// Encoding fails if the data of a hardware verdict is longer
+    /// than 65534 bytes; see `try_new`.
+    pub fn new(verdict: VerdictType) -> Self {
+        Self { verdict }
+    }
+
+    pub fn new_option(verdict: VerdictType) -> BlockOption {
+        BlockOption::EpbVerdict(Self::new(verdict))
+    }
+
+    pub fn try_new(verdict: VerdictType) -> Result<Self, OptionError> {
+        check_len(verdict.length())?;
+        Ok(Self::new(verdict))
+    }
+
+    pub fn try_new_option(verdict: VerdictType) -> Result<BlockOption, OptionError> {
+        Self::try_new(verdict).map(BlockOption::EpbVerdict)
+    }
+
+    pub fn verdict(&self) -> &VerdictType {
+        &self.verdict
+    }
+
+    fn length(&self) -> usize {
+        self.verdict.length()
+    }
+
+    fn write_value<B: ByteOrder, W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.verdict.write::<B, W>(w)
+    }
+}
+
+/// Defines an option holding a timestamp, written like the Timestamp
+/// (High) and (Low) fields of a block.
+macro_rules! timestamp_option {
+    ($(#[$doc:meta])* $name:ident, $variant:ident) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        pub struct $name {
+            timestamp: Timestamp,
+        }
+
+        impl $name {
+            pub fn new(timestamp: Timestamp) -> Self {
+                Self { timestamp }
+            }
+
+            pub fn new_option(timestamp: Timestamp) -> BlockOption {
+                BlockOption::$variant(Self::new(timestamp))
+            }
+
+            pub fn timestamp(&self) -> Timestamp {
+                self.timestamp
+            }
+
+            fn length(&self) -> usize {
+                8
+            }
+
+            fn write_value<B: ByteOrder, W: Write>(&self, w: &mut W) -> io::Result<()> {
+                w.write_u32::<B>(self.timestamp.high())?;
+                w.write_u32::<B>(self.timestamp.low())
+            }
+        }
+    };
+}
+
+timestamp_option!(
+    /// The `isb_starttime` option: the time the capture started, in
+    /// units of the interface's `if_tsresol`.
+    OptionIsbStartTime,
+    IsbStartTime
+);
+
+timestamp_option!(
+    /// The `isb_endtime` option: the time the capture ended, in units
+    /// of the interface's `if_tsresol`.
+    OptionIsbEndTime,
+    IsbEndTime
+);
+
+/// Defines an option holding a 64-bit counter.
+macro_rules! counter_option {
+    ($(#[$doc:meta])* $name:ident, $variant:ident) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        pub struct $name {
+            count: u64,
+        }
+
+        impl $name {
+            pub fn new(count: u64) -> Self {
+                Self { count }
+            }
+
+            pub fn new_option(count: u64) -> BlockOption {
+                BlockOption::$variant(Self::new(count))
+            }
+
+            pub fn count(&self) -> u64 {
+                self.count
+            }
+
+            fn length(&self) -> usize {
+                8
+            }
+
+            fn write_value<B: ByteOrder, W: Write>(&self, w: &mut W) -> io::Result<()> {
+                w.write_u64::<B>(self.count)
+            }
+        }
+    };
+}
+
+counter_option!(
+    /// The `epb_dropcount` option: the number of packets lost between
+    /// the packet and the preceding one on the same interface.
+    OptionEpbDropCount,
+    EpbDropCount
+);
+
+counter_option!(
+    /// The `isb_ifrecv` option: the number of packets received from
+    /// the physical interface since the beginning of the capture.
+    OptionIsbIfRecv,
+    IsbIfRecv
+);
+
+counter_option!(
+    /// The `isb_ifdrop` option: the number of packets dropped by the
+    /// interface due to lack of resources since the beginning of the
+    /// capture.
+    OptionIsbIfDrop,
+    IsbIfDrop
+);
+
+counter_option!(
+    /// The `isb_filteraccept` option: the number of packets accepted
+    /// by the filter since the beginning of the capture.
+    OptionIsbFilterAccept,
+    IsbFilterAccept
+);
+
+counter_option!(
+    /// The `isb_osdrop` option: the number of packets dropped by the
+    /// operating system since the beginning of the capture.
+    OptionIsbOsDrop,
+    IsbOsDrop
+);
+
+counter_option!(
+    /// The `isb_usrdeliv` option: the number of packets delivered to
+    /// the user since the beginning of the capture.
+    OptionIsbUsrDeliv,
+    IsbUsrDeliv
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
     use byteorder::{BigEndian, LittleEndian};
 
+    fn value(write: impl FnOnce(&mut Vec<u8>) -> io::Result<()>) -> Vec<u8> {
+        let mut buf = vec![];
+        write(&mut buf).unwrap();
+        buf
+    }
+
     #[test]
     fn option_encode() {
         let data = vec![9u8; 10];
@@ -585,4 +2006,458 @@ mod tests {
             [1, 0, 13, 0, 72, 101, 108, 108, 111, 32, 87, 111, 114, 108, 100, 33, 33, 0, 0, 0]
         );
     }
+
+    #[test]
+    fn fallible_constructors() {
+        assert!(OptionIfIpv4Addr::try_new("192.168.1.1", "255.255.255.0").is_ok());
+        assert_eq!(
+            OptionIfIpv4Addr::try_new("192.168.1.256", "255.255.255.0").unwrap_err(),
+            OptionError::InvalidIpAddr("192.168.1.256".to_string())
+        );
+        assert!(OptionIfIpv6Addr::try_new("2001:db8::1", 64).is_ok());
+        assert_eq!(
+            OptionIfIpv6Addr::try_new("2001:db8::1", 129).unwrap_err(),
+            OptionError::InvalidPrefixLen(129)
+        );
+        assert!(OptionIfIpv6Addr::try_new("192.168.1.1", 64).is_err());
+        let opt: OptionIfIpv6Addr = "2001:db8::1/64".parse().unwrap();
+        assert_eq!(opt.prefix_len, 64);
+        assert!("2001:db8::1".parse::<OptionIfIpv6Addr>().is_err());
+        assert_eq!(
+            "0.1.2.3.4.5".parse::<OptionIfMacAddr>().unwrap().mac_addr,
+            [0, 1, 2, 3, 4, 5]
+        );
+        for bad in &["0.1.2.3.4", "0.1.2.3.4.5.6", "0.1.2.3.4.256", "a.b.c.d.e.f"] {
+            assert_eq!(
+                OptionIfMacAddr::try_new(bad).unwrap_err(),
+                OptionError::InvalidMacAddr(bad.to_string())
+            );
+        }
+        assert!(OptionIfName::try_new(&"x".repeat(OPTION_MAX_LEN + 1)).is_err());
+        assert!(RawOption::try_new(2, 3, vec![0; 3]).is_ok());
+        assert_eq!(
+            RawOption::try_new(2, 4, vec![0; 3]).unwrap_err(),
+            OptionError::LengthMismatch {
+                declared: 4,
+                actual: 3
+            }
+        );
+    }
+
+    #[test]
+    fn ipv4_addr_constructors() {
+        let ip = Ipv4Addr::new(192, 168, 1, 1);
+        let opt = OptionIfIpv4Addr::from_addrs(ip, Ipv4Addr::new(255, 255, 255, 0));
+        assert_eq!(
+            value(|w| opt.write_value(w)),
+            [192, 168, 1, 1, 255, 255, 255, 0]
+        );
+        let opt = OptionIfIpv4Addr::from_cidr("192.168.1.1/24").unwrap();
+        assert_eq!(
+            value(|w| opt.write_value(w)),
+            [192, 168, 1, 1, 255, 255, 255, 0]
+        );
+        let opt: OptionIfIpv4Addr = "10.1.2.3/13".parse().unwrap();
+        assert_eq!(opt.netmask, Ipv4Addr::new(255, 248, 0, 0));
+        assert_eq!(
+            OptionIfIpv4Addr::from_prefix_len(ip, 0).unwrap().netmask,
+            Ipv4Addr::new(0, 0, 0, 0)
+        );
+        assert_eq!(
+            OptionIfIpv4Addr::from_prefix_len(ip, 32).unwrap().netmask,
+            Ipv4Addr::new(255, 255, 255, 255)
+        );
+        assert_eq!(
+            OptionIfIpv4Addr::from_prefix_len(ip, 33).unwrap_err(),
+            OptionError::InvalidPrefixLen(33)
+        );
+        for bad in &[
+            "192.168.1.1",
+            "192.168.1.1/",
+            "192.168.1.1/x",
+            "192.168.1/24",
+        ] {
+            assert!(OptionIfIpv4Addr::from_cidr(bad).is_err());
+        }
+    }
+
+    #[test]
+    fn ipv6_addr_constructors() {
+        let ip: Ipv6Addr = "2001:db8:85a3:8d3:1319:8a2e:370:7344".parse().unwrap();
+        let opt = OptionIfIpv6Addr::from_addr(ip, 64).unwrap();
+        assert_eq!(
+            value(|w| opt.write_value(w)),
+            [
+                0x20, 0x01, 0x0d, 0xb8, 0x85, 0xa3, 0x08, 0xd3, 0x13, 0x19, 0x8a, 0x2e, 0x03, 0x70,
+                0x73, 0x44, 0x40
+            ]
+        );
+        assert_eq!(
+            OptionIfIpv6Addr::from_addr(ip, 129).unwrap_err(),
+            OptionError::InvalidPrefixLen(129)
+        );
+    }
+
+    #[cfg(feature = "ipnet")]
+    #[test]
+    fn addrs_from_ipnet() {
+        let net: ipnet::Ipv6Net = "2001:db8::1/48".parse().unwrap();
+        let opt = OptionIfIpv6Addr::from(net);
+        assert_eq!(opt.ip, "2001:db8::1".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(opt.prefix_len, 48);
+        let net: ipnet::Ipv4Net = "192.168.1.1/24".parse().unwrap();
+        let opt = OptionIfIpv4Addr::from(net);
+        assert_eq!(
+            value(|w| opt.write_value(w)),
+            [192, 168, 1, 1, 255, 255, 255, 0]
+        );
+    }
+
+    #[test]
+    fn mac_addr_formats() {
+        let expected = [0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e];
+        for s in &["00:1a:2b:3c:4d:5e", "00-1A-2B-3C-4D-5E", "0.26.43.60.77.94"] {
+            assert_eq!(OptionIfMacAddr::new(s).mac_addr, expected);
+        }
+        for bad in &[
+            "00:1a:2b:3c:4d",
+            "0:1a:2b:3c:4d:5e",
+            "00:1a:2b:3c:4d:5g",
+            "00:1a-2b",
+        ] {
+            assert!(OptionIfMacAddr::try_new(bad).is_err());
+        }
+        assert_eq!(OptionIfMacAddr::from(expected).mac_addr, expected);
+        let mut buf = vec![];
+        OptionIfMacAddr::from_bytes_option(expected)
+            .encode::<BigEndian>(&mut buf)
+            .unwrap();
+        assert_eq!(buf, [0, 6, 0, 6, 0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e, 0, 0]);
+    }
+
+    #[cfg(feature = "macaddr")]
+    #[test]
+    fn mac_addr_from_macaddr() {
+        let mac = macaddr::MacAddr6::new(0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e);
+        assert_eq!(
+            OptionIfMacAddr::from(mac).mac_addr,
+            [0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]
+        );
+    }
+
+    #[test]
+    fn epb_flags() {
+        let flags = OptionEpbFlags::new(
+            PacketDirection::Outbound,
+            ReceptionType::Broadcast,
+            Some(FcsLen::new(4).unwrap()),
+            EpbErrorFlags::CRC | EpbErrorFlags::PREAMBLE,
+        );
+        assert_eq!(flags.flags(), 0x4100_008e);
+        assert_eq!(flags.direction(), Some(PacketDirection::Outbound));
+        assert_eq!(flags.reception_type(), Some(ReceptionType::Broadcast));
+        assert_eq!(flags.fcs_length(), Some(FcsLen::new(4).unwrap()));
+        assert!(flags.error_flags().contains(EpbErrorFlags::PREAMBLE));
+
+        // reserved direction and reception type
+        let flags = OptionEpbFlags::from_u32(0x1f);
+        assert_eq!(flags.direction(), None);
+        assert_eq!(flags.reception_type(), None);
+        assert_eq!(flags.fcs_length(), None);
+        assert!(flags.error_flags().is_empty());
+
+        let flags = OptionEpbFlags::new(
+            PacketDirection::Inbound,
+            ReceptionType::Unicast,
+            Some(FcsLen::NONE),
+            EpbErrorFlags::empty(),
+        );
+        assert_eq!(flags.fcs_length(), None);
+    }
+
+    #[test]
+    fn epb_hash() {
+        let opt = OptionEpbHash::new_option(HashAlgorithm::Crc32, &[0xde, 0xad, 0xbe, 0xef]);
+        let mut buf = vec![];
+        opt.encode::<BigEndian>(&mut buf).unwrap();
+        assert_eq!(buf, [0, 3, 0, 5, 2, 0xde, 0xad, 0xbe, 0xef, 0, 0, 0]);
+        assert_eq!(
+            OptionEpbHash::try_new(HashAlgorithm::Sha1, &[0; 16]).unwrap_err(),
+            OptionError::InvalidDigestLen {
+                expected: 20,
+                actual: 16
+            }
+        );
+        assert!(OptionEpbHash::try_new(HashAlgorithm::Xor, &[0; 3]).is_ok());
+        assert_eq!(HashAlgorithm::from_code(3), Some(HashAlgorithm::Md5));
+        assert_eq!(HashAlgorithm::from_code(6), None);
+    }
+
+    #[test]
+    fn epb_queue() {
+        let opt = OptionEpbQueue::new_option(3);
+        let mut buf = vec![];
+        opt.encode::<BigEndian>(&mut buf).unwrap();
+        assert_eq!(buf, [0, 6, 0, 4, 0, 0, 0, 3]);
+    }
+
+    #[test]
+    fn epb_verdict() {
+        let opt = OptionEpbVerdict::new_option(VerdictType::xdp_pass());
+        let mut buf = vec![];
+        opt.encode::<LittleEndian>(&mut buf).unwrap();
+        assert_eq!(buf, [7, 0, 9, 0, 2, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let tc = VerdictType::LinuxEbpfTc(2);
+        let payload = tc.to_payload::<BigEndian>();
+        assert_eq!(payload, [1, 0, 0, 0, 0, 0, 0, 0, 2]);
+        assert_eq!(VerdictType::from_payload::<BigEndian>(&payload), Some(tc));
+        let hw = VerdictType::Hardware(vec![1, 2, 3]);
+        assert_eq!(hw.to_payload::<BigEndian>(), [0, 1, 2, 3]);
+        assert_eq!(VerdictType::from_payload::<BigEndian>(&[2, 0]), None);
+        assert_eq!(VerdictType::from_payload::<BigEndian>(&[3]), None);
+        assert_eq!(
+            OptionEpbVerdict::try_new(VerdictType::Hardware(vec![0; 65535])).unwrap_err(),
+            OptionError::ValueTooLong(65536)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let opts: Vec<BlockOption> = serde_json::from_str(
+            r#"[
+                {"IfName": {"if_name": "eth0"}},
+                {"IfIpv6Addr": {"ip": "2001:db8::1", "prefix_len": 64}},
+                {"IfFcsLen": {"fcs_len": 4}},
+                {"EpbHash": {"algorithm": "Crc32", "digest": [1, 2, 3, 4]}},
+                {"OptEndOfOpt": null}
+            ]"#,
+        )
+        .unwrap();
+        let mut options = Options::new();
+        for opt in &opts {
+            options.add_option(opt);
+        }
+        let mut buf = vec![];
+        options.encode::<BigEndian>(&mut buf).unwrap();
+        assert_eq!(buf.len(), 8 + 24 + 8 + 12 + 4);
+        let json = serde_json::to_string(&options).unwrap();
+        let again: Vec<BlockOption> = serde_json::from_str(&json).unwrap();
+        assert_eq!(again.len(), 5);
+
+        // deserializing checks the same invariants as the constructors
+        for invalid in [
+            r#"{"IfIpv6Addr": {"ip": "2001:db8::1", "prefix_len": 129}}"#,
+            r#"{"IfFcsLen": {"fcs_len": 16}}"#,
+            r#"{"EpbHash": {"algorithm": "Md5", "digest": [1, 2, 3, 4]}}"#,
+        ] {
+            assert!(serde_json::from_str::<BlockOption>(invalid).is_err());
+        }
+
+        // options without a value type deserialize but are refused
+        // when encoded, before anything is written
+        let tzone: BlockOption = serde_json::from_str(r#""IfTZone""#).unwrap();
+        let mut buf = vec![];
+        assert!(matches!(
+            tzone.encode::<BigEndian>(&mut buf),
+            Err(crate::Error::Option(OptionError::Unsupported(10)))
+        ));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn isb_counters() {
+        let opt = OptionIsbIfDrop::new_option(0x0102);
+        assert_eq!(opt.code(), 5);
+        let mut buf = vec![];
+        opt.encode::<BigEndian>(&mut buf).unwrap();
+        assert_eq!(buf, [0, 5, 0, 8, 0, 0, 0, 0, 0, 0, 1, 2]);
+        let mut buf = vec![];
+        opt.encode::<LittleEndian>(&mut buf).unwrap();
+        assert_eq!(buf, [5, 0, 8, 0, 2, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(OptionIsbUsrDeliv::new(7).count(), 7);
+        assert_eq!(OptionEpbDropCount::new_option(3).code(), 4);
+    }
+
+    #[test]
+    fn if_speed() {
+        let opt = OptionIfSpeed::new_option(100_000_000);
+        assert_eq!(opt.code(), 8);
+        let mut buf = vec![];
+        opt.encode::<BigEndian>(&mut buf).unwrap();
+        assert_eq!(buf, [0, 8, 0, 8, 0, 0, 0, 0, 0x05, 0xf5, 0xe1, 0]);
+        assert_eq!(OptionIfSpeed::new(10).speed(), 10);
+    }
+
+    #[test]
+    fn shb_strings() {
+        let opt = OptionShbOs::new_option("Linux 6.1.0");
+        assert_eq!(opt.code(), 3);
+        let mut buf = vec![];
+        opt.encode::<BigEndian>(&mut buf).unwrap();
+        assert_eq!(buf, b"\0\x03\0\x0bLinux 6.1.0\0");
+        assert_eq!(OptionShbHardware::new_option("x86_64").code(), 2);
+        assert_eq!(OptionShbHardware::new("x86_64").hardware(), "x86_64");
+        let userappl = crate::shb_userappl!();
+        assert_eq!(userappl.code(), 4);
+        match userappl {
+            BlockOption::ShbUserAppl(o) => assert!(o.application().starts_with("pcapng-writer ")),
+            _ => panic!("not shb_userappl"),
+        }
+        assert!(matches!(
+            OptionShbOs::try_new(&"a".repeat(0x10000)),
+            Err(OptionError::ValueTooLong(_))
+        ));
+    }
+
+    #[test]
+    fn collect_and_extend() {
+        let comments: Vec<_> = ["a", "b"]
+            .iter()
+            .map(|c| OptionComment::new_option(c))
+            .collect();
+        let eoo = OptionEndOfOpt::new_option();
+        let mut options: Options = comments.iter().collect();
+        options.extend(Some(&eoo));
+        let mut expected = Options::new();
+        expected.add_options(&comments);
+        expected.add_option(&eoo);
+        assert_eq!(options, expected);
+        assert!(options.check_order().is_ok());
+    }
+
+    #[test]
+    fn owned_options() {
+        let comment = OptionComment::new_option("a");
+        let mut options = Options::new();
+        options.add_option(&comment);
+        for queue in 0..3 {
+            options.add_owned_option(OptionEpbQueue::new_option(queue));
+        }
+        options.extend(Some(OptionEndOfOpt::new_option()));
+        let options = options.into_owned();
+        drop(comment);
+
+        let codes: Vec<_> = options.iter().map(|opt| opt.code()).collect();
+        assert_eq!(codes, [1, 6, 6, 6, 0]);
+        let collected: Options = vec![OptionComment::new_option("a")].into_iter().collect();
+        let mut buf = vec![];
+        collected.encode::<BigEndian>(&mut buf).unwrap();
+        assert_eq!(buf, b"\0\x01\0\x01a\0\0\0");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn clone_and_hash() {
+        use std::collections::HashSet;
+
+        let comment = OptionComment::new_option("a");
+        let os = OptionShbOs::new_option("a");
+        let mut options = Options::new();
+        options.add_option(&comment);
+        options.add_option(&os);
+        let copy = options.clone();
+        assert_eq!(copy, options);
+        options.add_option(&comment);
+        assert_ne!(copy, options);
+
+        let set: HashSet<_> = vec![comment.clone(), comment, os].into_iter().collect();
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn isb_times() {
+        let opt = OptionIsbEndTime::new_option(Timestamp::from_parts(1, 2));
+        assert_eq!(opt.code(), 3);
+        let mut buf = vec![];
+        opt.encode::<BigEndian>(&mut buf).unwrap();
+        assert_eq!(buf, [0, 3, 0, 8, 0, 0, 0, 1, 0, 0, 0, 2]);
+        let mut buf = vec![];
+        opt.encode::<LittleEndian>(&mut buf).unwrap();
+        assert_eq!(buf, [3, 0, 8, 0, 1, 0, 0, 0, 2, 0, 0, 0]);
+        let start = OptionIsbStartTime::new(Timestamp::new(5));
+        assert_eq!(start.timestamp().ticks(), 5);
+    }
+
+    #[test]
+    fn fcs_len() {
+        assert_eq!(FcsLen::new(15).unwrap().bytes(), 15);
+        assert_eq!(FcsLen::new(16), Err(OptionError::InvalidFcsLen(16)));
+        assert!(FcsLen::NONE.is_none());
+        let opt = OptionIfFcsLen::new_option(FcsLen::new(4).unwrap());
+        let mut buf = vec![];
+        opt.encode::<BigEndian>(&mut buf).unwrap();
+        assert_eq!(buf, [0, 13, 0, 1, 4, 0, 0, 0]);
+    }
+
+    #[test]
+    fn if_filter() {
+        let opt = OptionIfFilter::new_option("tcp");
+        let mut buf = vec![];
+        opt.encode::<LittleEndian>(&mut buf).unwrap();
+        assert_eq!(buf, [11, 0, 4, 0, 0, b't', b'c', b'p']);
+        let filter = OptionIfFilter::with_data(FilterType::Bpf, &[6, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(filter.filter_type().code(), 1);
+        assert_eq!(filter.expression(), None);
+        assert_eq!(OptionIfFilter::new("udp").expression(), Some("udp"));
+        let expression = "x".repeat(OPTION_MAX_LEN);
+        assert_eq!(
+            OptionIfFilter::try_new(&expression).unwrap_err(),
+            OptionError::ValueTooLong(OPTION_MAX_LEN + 1)
+        );
+    }
+
+    #[test]
+    fn unsupported_len() {
+        // size queries never panic, the block is refused by the checks
+        let mut options = Options::new();
+        options.add_owned_option(BlockOption::IfTZone);
+        assert_eq!(options.encoded_len(), 4);
+        assert_eq!(options.length(), 4);
+        assert_eq!(options.check_lengths(), Err(OptionError::Unsupported(10)));
+    }
+
+    #[test]
+    fn opt_comment_too_long() {
+        let comment = "x".repeat(OPTION_MAX_LEN + 1);
+        assert_eq!(
+            OptionComment::try_new(&comment).unwrap_err(),
+            OptionError::ValueTooLong(OPTION_MAX_LEN + 1)
+        );
+        assert!(OptionComment::try_new(&comment[1..]).is_ok());
+    }
+
+    #[test]
+    fn opt_comment_split() {
+        // 'é' is two bytes long, so the first split point falls in
+        // the middle of a character
+        let comment = "é".repeat(40_000);
+        let opts = OptionComment::new_options(&comment);
+        assert_eq!(opts.len(), 2);
+        let mut joined = String::new();
+        for opt in &opts {
+            if let BlockOption::OptComment(c) = opt {
+                assert!(c.comment.len() <= OPTION_MAX_LEN);
+                joined.push_str(&c.comment);
+            } else {
+                panic!();
+            }
+        }
+        assert_eq!(joined, comment);
+
+        let long = OptionComment::new_option(&comment);
+        let mut buf = vec![];
+        assert!(matches!(
+            long.encode::<LittleEndian>(&mut buf),
+            Err(crate::Error::Option(OptionError::ValueTooLong(80_000)))
+        ));
+
+        let mut options = Options::new();
+        options.add_options(&opts);
+        let mut buf = vec![];
+        options.encode::<LittleEndian>(&mut buf).unwrap();
+        assert_eq!(buf.len() as u32, options.length());
+        assert_eq!(OptionComment::new_options("short").len(), 1);
+    }
 }
@@ -2,10 +2,11 @@ use crate::blocks::options::Options;
 use crate::blocks::Block;
 use crate::constants::*;
 use crate::enums::*;
-use crate::writer::Encodable;
-use byteorder::{ByteOrder, WriteBytesExt};
-use std::io;
-use std::io::Write;
+use crate::error::Result;
+use crate::io::{Write, WriteBytesExt};
+use crate::writer::{Encodable, EncodedLen};
+use alloc::borrow::Cow;
+use byteorder::ByteOrder;
 
 /*
        0                   1                   2                   3
@@ -34,13 +35,14 @@ use std::io::Write;
 */
 
 /// Represents a [Section Header Block](https://tools.ietf.org/html/draft-tuexen-opsawg-pcapng-02#section-4.1).
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct SectionHeaderBlock<'a> {
     byte_order_magic: u32,
     major_version: u16,
     minor_version: u16,
     section_length: u64,
-    options: &'a Options<'a>,
+    options: Cow<'a, Options<'a>>,
 }
 
 impl<'a> SectionHeaderBlock<'a> {
@@ -57,7 +59,7 @@ impl<'a> SectionHeaderBlock<'a> {
             major_version,
             minor_version,
             section_length: section_length.value(),
-            options,
+            options: Cow::Borrowed(options),
         }
     }
 
@@ -74,17 +76,36 @@ impl<'a> SectionHeaderBlock<'a> {
     }
 }
 
+#[cfg(feature = "host-info")]
+impl SectionHeaderBlock<'_> {
+    /// Describes the current machine, see `host_info::HostInfo::current`.
+    /// Its `shb_options` are the `shb_os` and `shb_hardware` options
+    /// dumpcap writes.
+    pub fn host_info() -> crate::host_info::HostInfo {
+        crate::host_info::HostInfo::current()
+    }
+}
+
+push_option!(SectionHeaderBlock);
+
 impl Block for SectionHeaderBlock<'_> {
     const TYPE: BlockType = BlockType::SectionHeader;
 
-    fn length(&self) -> u32 {
-        BLOCK_COMMON_LEN + 4 + 2 + 2 + 8 + self.options.length()
+    fn length(&self) -> usize {
+        BLOCK_COMMON_LEN + 4 + 2 + 2 + 8 + self.options.encoded_len()
+    }
+}
+
+impl EncodedLen for SectionHeaderBlock<'_> {
+    fn encoded_len(&self) -> usize {
+        self.length()
     }
 }
 
 impl<W: Write> Encodable<W> for SectionHeaderBlock<'_> {
-    fn encode<B: ByteOrder>(&self, w: &mut W) -> io::Result<()> {
-        let total_length = self.length();
+    fn encode<B: ByteOrder>(&self, w: &mut W) -> Result<()> {
+        let total_length = self.total_length()?;
+        self.options.check_lengths()?;
         w.write_u32::<B>(Self::TYPE.value())?;
         w.write_u32::<B>(total_length)?;
         w.write_u32::<B>(self.byte_order_magic)?;
@@ -95,14 +116,22 @@ impl<W: Write> Encodable<W> for SectionHeaderBlock<'_> {
         w.write_u32::<B>(total_length)?;
         Ok(())
     }
+
+    fn validate(&self) -> Result<()> {
+        self.options.check_lengths()?;
+        Ok(self.options.check_order()?)
+    }
+
+    fn block_type(&self) -> Option<u32> {
+        Some(Self::TYPE.value())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::reader::{Block, SliceReader};
     use byteorder::{BigEndian, LittleEndian};
-    use nom::IResult;
-    use pcapng;
 
     #[test]
     fn new_shb() {
@@ -124,25 +153,13 @@ mod tests {
         let shb = SectionHeaderBlock::new_with_defaults(&opts);
         let mut buf = vec![];
         shb.encode::<LittleEndian>(&mut buf).unwrap();
-        if let IResult::Done(_, blocks) = pcapng::block::parse_blocks(&buf[..]) {
-            for raw in blocks {
-                if let IResult::Done(_, block) = raw.parse() {
-                    if let pcapng::block::Block::SectionHeader(parsed_shb) = block {
-                        assert_eq!(parsed_shb.major_version, 1);
-                        assert_eq!(parsed_shb.minor_version, 0);
-                        assert_eq!(
-                            parsed_shb.section_length,
-                            pcapng::blocks::section_header::SectionLength::Unspecified
-                        );
-                    } else {
-                        panic!()
-                    }
-                } else {
-                    panic!();
-                }
+        match SliceReader::new(&buf).next().unwrap().unwrap() {
+            Block::SectionHeader(parsed_shb) => {
+                assert_eq!(parsed_shb.major_version(), 1);
+                assert_eq!(parsed_shb.minor_version(), 0);
+                assert_eq!(parsed_shb.section_length(), None);
             }
-        } else {
-            panic!();
+            _ => panic!(),
         }
     }
 }
@@ -1,10 +1,11 @@
-use crate::blocks::Block;
+use crate::blocks::{write_all_vectored, Block, PADDING};
 use crate::constants::*;
 use crate::enums::*;
-use crate::writer::Encodable;
-use byteorder::{ByteOrder, WriteBytesExt};
-use std::io;
-use std::io::Write;
+use crate::error::Result;
+use crate::io::Write;
+use crate::utils::pad_to_32;
+use crate::writer::{Encodable, EncodedLen};
+use byteorder::ByteOrder;
 
 /*
 
@@ -29,53 +30,84 @@ use std::io::Write;
 */
 
 /// Represents a [Simple Header Block](https://tools.ietf.org/html/draft-tuexen-opsawg-pcapng-02#section-4.4).
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct SimplePacketBlock<'a> {
     orig_packet_len: u32,
     packet_data: &'a [u8],
 }
 
 impl<'a> SimplePacketBlock<'a> {
-    pub fn new(orig_len: u32, packet_data: &'a [u8]) -> SimplePacketBlock {
+    pub fn new(orig_len: u32, packet_data: &'a [u8]) -> SimplePacketBlock<'a> {
         SimplePacketBlock {
             orig_packet_len: orig_len,
             packet_data,
         }
     }
 
-    fn data_padding(&self) -> Vec<u8> {
-        let n = self.packet_data.len() % 4;
-        vec![0u8; n]
+    pub fn orig_len(&self) -> u32 {
+        self.orig_packet_len
+    }
+
+    pub fn packet_data(&self) -> &'a [u8] {
+        self.packet_data
     }
 }
 
 impl<'a> Block for SimplePacketBlock<'a> {
     const TYPE: BlockType = BlockType::SimplePacket;
 
-    fn length(&self) -> u32 {
-        BLOCK_COMMON_LEN + 4 + self.packet_data.len() as u32 + self.data_padding().len() as u32
+    fn length(&self) -> usize {
+        BLOCK_COMMON_LEN + 4 + self.packet_data.len() + pad_to_32(self.packet_data.len())
+    }
+}
+
+impl EncodedLen for SimplePacketBlock<'_> {
+    fn encoded_len(&self) -> usize {
+        self.length()
     }
 }
 
 impl<W: Write> Encodable<W> for SimplePacketBlock<'_> {
-    fn encode<B: ByteOrder>(&self, w: &mut W) -> io::Result<()> {
-        let total_length = self.length();
-        w.write_u32::<B>(Self::TYPE.value())?;
-        w.write_u32::<B>(total_length)?;
-        w.write_u32::<B>(self.orig_packet_len)?;
-        w.write_all(self.packet_data)?;
-        w.write_all(&self.data_padding())?;
-        w.write_u32::<B>(total_length)?;
+    fn encode<B: ByteOrder>(&self, w: &mut W) -> Result<()> {
+        let total_length = self.total_length()?;
+        let mut header = [0u8; 12];
+        B::write_u32_into(
+            &[Self::TYPE.value(), total_length, self.orig_packet_len],
+            &mut header,
+        );
+        let mut trailer = [0u8; 4];
+        B::write_u32(&mut trailer, total_length);
+        write_all_vectored(
+            w,
+            [
+                &header,
+                self.packet_data,
+                &PADDING[..pad_to_32(self.packet_data.len())],
+                &trailer,
+            ],
+        )?;
         Ok(())
     }
+
+    fn block_type(&self) -> Option<u32> {
+        Some(Self::TYPE.value())
+    }
+
+    /// Simple Packet Blocks belong to the first interface of the
+    /// section.
+    fn interface_id(&self) -> Option<u32> {
+        Some(0)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::blocks::options::Options;
+    use crate::blocks::SectionHeaderBlock;
+    use crate::reader::{Block, SliceReader};
     use byteorder::{BigEndian, LittleEndian};
-    use nom::IResult;
-    use pcapng;
 
     #[test]
     fn new_spb() {
@@ -98,27 +130,32 @@ mod tests {
         assert_eq!(&buf[22..24], &[0, 0]);
     }
 
+    #[test]
+    fn odd_padding() {
+        for (len, total) in [(1, 20), (3, 20), (5, 24)] {
+            let data = vec![9; len];
+            let spb = SimplePacketBlock::new(len as u32, &data);
+            let mut buf = vec![];
+            spb.encode::<BigEndian>(&mut buf).unwrap();
+            assert_eq!(buf.len(), total);
+            assert_eq!(&buf[4..8], &(total as u32).to_be_bytes());
+        }
+    }
+
     #[test]
     fn round_trip() {
         let spb = SimplePacketBlock::new(10, &[9; 10]);
         let mut buf = vec![];
+        SectionHeaderBlock::new_with_defaults(&Options::new())
+            .encode::<LittleEndian>(&mut buf)
+            .unwrap();
         spb.encode::<LittleEndian>(&mut buf).unwrap();
-        if let IResult::Done(_, blocks) = pcapng::block::parse_blocks(&buf[..]) {
-            for raw in blocks {
-                if let IResult::Done(_, block) = raw.parse() {
-                    if let pcapng::block::Block::UnknownBlock(r) = block {
-                        // pcapng-rs doesn't have a parser for Simple
-                        // Packet Block. We just check the type.
-                        assert_eq!(r.ty, BlockType::SimplePacket.value());
-                    } else {
-                        panic!()
-                    }
-                } else {
-                    panic!();
-                }
+        match SliceReader::new(&buf).nth(1).unwrap().unwrap() {
+            Block::SimplePacket(parsed_spb) => {
+                assert_eq!(parsed_spb.orig_len(), 10);
+                assert_eq!(parsed_spb.packet_data(), &[9; 10]);
             }
-        } else {
-            panic!();
+            _ => panic!(),
         }
     }
 }
@@ -0,0 +1,363 @@
+//! Capturing from the BPF devices of macOS and the BSDs.
+//!
+//! `BpfDevice` opens a `/dev/bpf*` device, attaches it to an
+//! interface in immediate mode and implements `LiveCapture`, so its
+//! packets can be written with `live::record`:
+//!
+//! ```no_run
+//! # #[cfg(any(
+//! #     target_os = "macos",
+//! #     target_os = "ios",
+//! #     target_os = "freebsd",
+//! #     target_os = "dragonfly",
+//! #     target_os = "netbsd",
+//! #     target_os = "openbsd"
+//! # ))]
+//! # fn main() -> pcapng_writer::Result<()> {
+//! use pcapng_writer::bpf::BpfDevice;
+//! use pcapng_writer::capture::CaptureWriter;
+//! use pcapng_writer::live;
+//! use std::fs::File;
+//!
+//! let mut device = BpfDevice::open("en0")?;
+//! let mut capture = CaptureWriter::new(File::create("en0.pcapng")?)?;
+//! let en0 = capture.add_interface(device.link_type(), 0)?;
+//! live::record(&mut device, &mut capture, en0)?;
+//! # Ok(())
+//! # }
+//! # #[cfg(not(any(
+//! #     target_os = "macos",
+//! #     target_os = "ios",
+//! #     target_os = "freebsd",
+//! #     target_os = "dragonfly",
+//! #     target_os = "netbsd",
+//! #     target_os = "openbsd"
+//! # )))]
+//! # fn main() {}
+//! ```
+//!
+//! `records` parses the buffers read from a BPF device, for programs
+//! that set the device up themselves.
+
+use crate::framing::invalid;
+use crate::live::LivePacket;
+use byteorder::{ByteOrder, NativeEndian};
+use std::io;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// The size of the fields of a `struct timeval` in a BPF header,
+/// which is also the alignment of the records in a buffer.
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "openbsd"))]
+const WORD: usize = 4;
+#[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "openbsd")))]
+const WORD: usize = std::mem::size_of::<std::os::raw::c_long>();
+
+/// Returns an iterator over the packets in a buffer read from a BPF
+/// device.
+pub fn records(buf: &[u8]) -> Records<'_> {
+    Records::with_word(buf, WORD)
+}
+
+/// An iterator over the packets in a buffer read from a BPF device,
+/// see `records`.
+#[derive(Debug)]
+pub struct Records<'a> {
+    buf: &'a [u8],
+    word: usize,
+}
+
+impl<'a> Records<'a> {
+    fn with_word(buf: &'a [u8], word: usize) -> Self {
+        Self { buf, word }
+    }
+
+    /// Parses the record at the start of the buffer. Returns the
+    /// packet and the length of the record, including its padding.
+    fn parse(&self) -> io::Result<(LivePacket<'a>, usize)> {
+        let w = self.word;
+        // bh_tstamp, bh_caplen, bh_datalen and bh_hdrlen
+        if self.buf.len() < 2 * w + 4 + 4 + 2 {
+            return Err(invalid("BPF header is truncated"));
+        }
+        let (secs, micros) = if w == 4 {
+            let secs = NativeEndian::read_u32(self.buf).into();
+            (secs, NativeEndian::read_u32(&self.buf[4..]).into())
+        } else {
+            let secs = NativeEndian::read_u64(self.buf);
+            (secs, NativeEndian::read_u64(&self.buf[8..]))
+        };
+        let cap_len = NativeEndian::read_u32(&self.buf[2 * w..]) as usize;
+        let orig_len = NativeEndian::read_u32(&self.buf[2 * w + 4..]);
+        let hdr_len = NativeEndian::read_u16(&self.buf[2 * w + 8..]) as usize;
+        let data = hdr_len
+            .checked_add(cap_len)
+            .and_then(|end| self.buf.get(hdr_len..end))
+            .ok_or_else(|| invalid("BPF record is truncated"))?;
+        let timestamp = UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_micros(micros);
+        let len = (hdr_len + cap_len).div_ceil(w) * w;
+        Ok((LivePacket::new(timestamp, orig_len, data), len))
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = io::Result<LivePacket<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        match self.parse() {
+            Ok((packet, len)) => {
+                self.buf = self.buf.get(len..).unwrap_or_default();
+                Some(Ok(packet))
+            }
+            Err(e) => {
+                self.buf = &[];
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+pub use self::device::BpfDevice;
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+mod device {
+    use super::Records;
+    use crate::enums::LinkType;
+    use crate::live::{LiveCapture, LivePacket, LiveStats};
+    use libc::{c_char, c_uint, c_ulong};
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Read};
+    use std::os::unix::io::AsRawFd;
+
+    const fn ioc(inout: c_ulong, num: c_ulong, len: usize) -> c_ulong {
+        inout | ((len as c_ulong & 0x1fff) << 16) | ((b'B' as c_ulong) << 8) | num
+    }
+
+    const IOC_VOID: c_ulong = 0x2000_0000;
+    const IOC_OUT: c_ulong = 0x4000_0000;
+    const IOC_IN: c_ulong = 0x8000_0000;
+    const BIOCGBLEN: c_ulong = ioc(IOC_OUT, 102, 4);
+    const BIOCPROMISC: c_ulong = ioc(IOC_VOID, 105, 0);
+    const BIOCGDLT: c_ulong = ioc(IOC_OUT, 106, 4);
+    const BIOCSETIF: c_ulong = ioc(IOC_IN, 108, std::mem::size_of::<IfReq>());
+    const BIOCGSTATS: c_ulong = ioc(IOC_OUT, 111, std::mem::size_of::<BpfStat>());
+    const BIOCIMMEDIATE: c_ulong = ioc(IOC_IN, 112, 4);
+
+    #[repr(C)]
+    struct IfReq {
+        name: [c_char; 16],
+        data: [u8; 16],
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct BpfStat {
+        recv: c_uint,
+        drop: c_uint,
+    }
+
+    /// A BPF device attached to a network interface.
+    #[derive(Debug)]
+    pub struct BpfDevice {
+        file: File,
+        link_type: LinkType,
+        buf: Vec<u8>,
+        len: usize,
+        pos: usize,
+    }
+
+    impl BpfDevice {
+        /// Opens the first available BPF device and attaches it to
+        /// `interface`, e.g. "en0", in immediate mode, so packets are
+        /// delivered as soon as they arrive.
+        pub fn open(interface: &str) -> io::Result<Self> {
+            let file = open_device()?;
+            let mut ifreq = IfReq {
+                name: [0; 16],
+                data: [0; 16],
+            };
+            if interface.len() >= ifreq.name.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "interface name is too long",
+                ));
+            }
+            for (dst, src) in ifreq.name.iter_mut().zip(interface.bytes()) {
+                *dst = src as c_char;
+            }
+            let mut buf_len: c_uint = 0;
+            let mut dlt: c_uint = 0;
+            let mut immediate: c_uint = 1;
+            // SAFETY: each request is passed a pointer to a value of
+            // the size encoded in it.
+            unsafe {
+                ioctl(&file, BIOCSETIF, &mut ifreq as *mut IfReq as *mut _)?;
+                ioctl(
+                    &file,
+                    BIOCIMMEDIATE,
+                    &mut immediate as *mut c_uint as *mut _,
+                )?;
+                ioctl(&file, BIOCGBLEN, &mut buf_len as *mut c_uint as *mut _)?;
+                ioctl(&file, BIOCGDLT, &mut dlt as *mut c_uint as *mut _)?;
+            }
+            Ok(Self {
+                file,
+                link_type: link_type_from_dlt(dlt),
+                buf: vec![0; buf_len as usize],
+                len: 0,
+                pos: 0,
+            })
+        }
+
+        /// Returns the link type of the interface.
+        pub fn link_type(&self) -> LinkType {
+            self.link_type
+        }
+
+        /// Puts the interface in promiscuous mode.
+        pub fn set_promiscuous(&self) -> io::Result<()> {
+            // SAFETY: BIOCPROMISC takes no argument.
+            unsafe { ioctl(&self.file, BIOCPROMISC, std::ptr::null_mut()) }
+        }
+    }
+
+    impl LiveCapture for BpfDevice {
+        type Error = io::Error;
+
+        /// Returns the next packet, reading from the device when the
+        /// packets of the last read are used up. Never returns
+        /// `None`; stop recording by returning an error, e.g. on
+        /// `ErrorKind::Interrupted`.
+        fn next_packet(&mut self) -> io::Result<Option<LivePacket<'_>>> {
+            while self.pos >= self.len {
+                self.len = self.file.read(&mut self.buf)?;
+                self.pos = 0;
+            }
+            let records = Records::with_word(&self.buf[self.pos..self.len], super::WORD);
+            let (packet, len) = records.parse()?;
+            self.pos += len;
+            Ok(Some(packet))
+        }
+
+        fn stats(&mut self) -> io::Result<LiveStats> {
+            let mut stat = BpfStat::default();
+            // SAFETY: BIOCGSTATS writes a struct bpf_stat.
+            unsafe { ioctl(&self.file, BIOCGSTATS, &mut stat as *mut BpfStat as *mut _)? };
+            Ok(LiveStats::new(stat.recv.into(), stat.drop.into(), 0))
+        }
+    }
+
+    unsafe fn ioctl(file: &File, request: c_ulong, arg: *mut libc::c_void) -> io::Result<()> {
+        if libc::ioctl(file.as_raw_fd(), request, arg) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Opens `/dev/bpf`, which clones a new device on systems that
+    /// support it, or else the first of `/dev/bpf0`, `/dev/bpf1`, ...
+    /// that is not busy.
+    fn open_device() -> io::Result<File> {
+        let open = |path: &str| OpenOptions::new().read(true).open(path);
+        match open("/dev/bpf") {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            result => return result,
+        }
+        for i in 0..256 {
+            match open(&format!("/dev/bpf{}", i)) {
+                Err(e) if e.raw_os_error() == Some(libc::EBUSY) => continue,
+                result => return result,
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no BPF device is available",
+        ))
+    }
+
+    /// Maps a DLT_ value to a LINKTYPE_ value, where they differ.
+    fn link_type_from_dlt(dlt: c_uint) -> LinkType {
+        match dlt {
+            #[cfg(target_os = "openbsd")]
+            12 => LinkType::Loop,
+            #[cfg(target_os = "openbsd")]
+            14 => LinkType::Raw,
+            #[cfg(not(target_os = "openbsd"))]
+            12 => LinkType::Raw,
+            _ => LinkType::from(dlt as u16),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn record(word: usize, secs: u64, micros: u64, data: &[u8]) -> Vec<u8> {
+        let mut buf = vec![];
+        if word == 4 {
+            buf.extend_from_slice(&(secs as u32).to_ne_bytes());
+            buf.extend_from_slice(&(micros as u32).to_ne_bytes());
+        } else {
+            buf.extend_from_slice(&secs.to_ne_bytes());
+            buf.extend_from_slice(&micros.to_ne_bytes());
+        }
+        buf.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+        buf.extend_from_slice(&1500u32.to_ne_bytes());
+        let hdr_len = (buf.len() + 2).div_ceil(word) * word;
+        buf.extend_from_slice(&(hdr_len as u16).to_ne_bytes());
+        buf.resize(hdr_len, 0);
+        buf.extend_from_slice(data);
+        buf.resize(buf.len().div_ceil(word) * word, 0);
+        buf
+    }
+
+    fn at(secs: u64, micros: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_micros(micros)
+    }
+
+    #[test]
+    fn parse_records() {
+        for &word in &[4, 8] {
+            let mut buf = record(word, 1_600_000_000, 250, &[1; 61]);
+            buf.extend(record(word, 1_600_000_001, 0, &[2; 3]));
+            let packets: Vec<_> = Records::with_word(&buf, word).map(|p| p.unwrap()).collect();
+            assert_eq!(packets.len(), 2);
+            assert_eq!(packets[0].timestamp(), at(1_600_000_000, 250));
+            assert_eq!(packets[0].orig_len(), 1500);
+            assert_eq!(packets[0].data(), &[1; 61][..]);
+            assert_eq!(packets[1].timestamp(), at(1_600_000_001, 0));
+            assert_eq!(packets[1].data(), &[2; 3][..]);
+        }
+    }
+
+    #[test]
+    fn truncated_record() {
+        let buf = record(8, 0, 0, &[1; 20]);
+        let mut iter = Records::with_word(&buf[..30], 8);
+        assert_eq!(
+            iter.next().unwrap().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert!(iter.next().is_none());
+        assert_eq!(records(&[]).count(), 0);
+    }
+}
@@ -0,0 +1,714 @@
+use crate::blocks::options::{
+    BlockOption, OptionIfTsResol, OptionIsbEndTime, OptionIsbFilterAccept, OptionIsbIfDrop,
+    OptionIsbIfRecv, OptionIsbStartTime, Options,
+};
+use crate::blocks::InterfaceStatisticsBlock;
+use crate::enums::LinkType;
+use crate::error::{Error, Result};
+use crate::utils::{nanos_since_epoch, Timestamp, TimestampResolution, NANO_SECOND_TSRES};
+use crate::writer::{Endianness, InterfaceId, PacketRecord, PcapNgWriter};
+use std::convert::TryFrom;
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The packet counters of an interface, written to its Interface
+/// Statistics Blocks by `CaptureWriter`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InterfaceCounters {
+    received: u64,
+    dropped: u64,
+    accepted: u64,
+}
+
+impl InterfaceCounters {
+    pub fn new(received: u64, dropped: u64, accepted: u64) -> Self {
+        Self {
+            received,
+            dropped,
+            accepted,
+        }
+    }
+
+    /// Returns the number of packets received from the interface,
+    /// written as `isb_ifrecv`.
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// Returns the number of packets dropped by the interface for
+    /// lack of resources, written as `isb_ifdrop`.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Returns the number of packets accepted by the capture filter,
+    /// written as `isb_filteraccept`.
+    pub fn accepted(&self) -> u64 {
+        self.accepted
+    }
+}
+
+/// The packets written on an interface by `CaptureWriter`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InterfaceSummary {
+    packets: u64,
+    bytes: u64,
+    first: Option<SystemTime>,
+    last: Option<SystemTime>,
+}
+
+impl InterfaceSummary {
+    /// Returns the number of packets written.
+    pub fn packets(&self) -> u64 {
+        self.packets
+    }
+
+    /// Returns the number of bytes of the packets written, before
+    /// they are truncated to the snap length.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns the timestamp of the earliest packet written, if any.
+    pub fn first_timestamp(&self) -> Option<SystemTime> {
+        self.first
+    }
+
+    /// Returns the timestamp of the latest packet written, if any.
+    pub fn last_timestamp(&self) -> Option<SystemTime> {
+        self.last
+    }
+
+    fn record(&mut self, timestamp: Option<SystemTime>, len: usize) {
+        self.packets += 1;
+        self.bytes += len as u64;
+        if let Some(timestamp) = timestamp {
+            self.first = Some(self.first.map_or(timestamp, |first| first.min(timestamp)));
+            self.last = Some(self.last.map_or(timestamp, |last| last.max(timestamp)));
+        }
+    }
+}
+
+/// A summary of the packets written by a `CaptureWriter`, for each
+/// interface it described. It does not depend on the Interface
+/// Statistics Blocks written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CaptureStats {
+    interfaces: Vec<(InterfaceId, InterfaceSummary)>,
+}
+
+impl CaptureStats {
+    /// Returns the summary of an interface described by the capture
+    /// writer.
+    pub fn interface(&self, interface: InterfaceId) -> Option<&InterfaceSummary> {
+        self.interfaces
+            .iter()
+            .find(|(id, _)| *id == interface)
+            .map(|(_, summary)| summary)
+    }
+
+    /// Returns the summaries of the interfaces, in the order of their
+    /// IDs.
+    pub fn interfaces(&self) -> impl Iterator<Item = (InterfaceId, &InterfaceSummary)> {
+        self.interfaces.iter().map(|(id, summary)| (*id, summary))
+    }
+
+    /// Returns the number of packets written on all the interfaces.
+    pub fn total_packets(&self) -> u64 {
+        self.interfaces.iter().map(|(_, s)| s.packets).sum()
+    }
+
+    /// Returns the number of bytes of the packets written on all the
+    /// interfaces.
+    pub fn total_bytes(&self) -> u64 {
+        self.interfaces.iter().map(|(_, s)| s.bytes).sum()
+    }
+
+    /// Returns the timestamp of the earliest packet written, if any.
+    pub fn first_timestamp(&self) -> Option<SystemTime> {
+        self.interfaces.iter().filter_map(|(_, s)| s.first).min()
+    }
+
+    /// Returns the timestamp of the latest packet written, if any.
+    pub fn last_timestamp(&self) -> Option<SystemTime> {
+        self.interfaces.iter().filter_map(|(_, s)| s.last).max()
+    }
+}
+
+/// The start time, counters and summary of an interface.
+#[derive(Debug)]
+struct InterfaceState {
+    id: InterfaceId,
+    start: SystemTime,
+    counters: InterfaceCounters,
+    summary: InterfaceSummary,
+}
+
+/// A high-level writer for recording packets.
+///
+/// `CaptureWriter` writes the Section Header Block when it is
+/// created, describes interfaces, and converts packet timestamps to
+/// the resolution of the interface they were captured on.
+///
+/// It also keeps `InterfaceCounters` for each interface it described:
+/// packets written are counted as received and accepted, and the
+/// capture backend can report its own counters with `set_counters`
+/// and `add_dropped`. The counters are written in Interface
+/// Statistics Blocks, along with the time the interface was added
+/// and the current time, by `write_statistics`, periodically with
+/// `set_statistics_interval`, and at close with
+/// `set_final_statistics`. `stats` returns a summary of the packets
+/// written on each interface, whether or not statistics are written.
+///
+/// Dropping the capture writer closes it, ignoring errors, like
+/// `PcapNgWriter`; call `close` to know whether closing succeeded.
+///
+/// ```
+/// use pcapng_writer::capture::CaptureWriter;
+/// use pcapng_writer::enums::LinkType;
+/// use std::time::SystemTime;
+///
+/// let mut buf: Vec<u8> = vec![];
+/// let mut capture = CaptureWriter::new(&mut buf).unwrap();
+/// let eth0 = capture.add_interface(LinkType::Ethernet, 65535).unwrap();
+/// let payload = b"\x00\x11\x22\x33\x44\x01\x00\x11\x22\x33\x44\x02\x08\x00";
+/// capture
+///     .write_packet(eth0, SystemTime::now(), &payload[..])
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct CaptureWriter<W: Write> {
+    /// Only taken by `into_inner`.
+    writer: Option<PcapNgWriter<W>>,
+    /// The interfaces described by the capture writer, by ID.
+    interfaces: Vec<Option<InterfaceState>>,
+    statistics_interval: Option<Duration>,
+    last_statistics: SystemTime,
+    final_statistics: bool,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Creates a little-endian capture writer, and writes a Section
+    /// Header Block without options.
+    pub fn new(writer: W) -> Result<Self> {
+        Self::with_section_options(Endianness::Little, writer, &Options::new())
+    }
+
+    /// Creates a capture writer, and writes a Section Header Block
+    /// with the given options.
+    pub fn with_section_options(
+        endianness: Endianness,
+        writer: W,
+        options: &Options,
+    ) -> Result<Self> {
+        Self::from_writer(PcapNgWriter::new(endianness, writer), options)
+    }
+
+    /// Creates a capture writer whose output only depends on the
+    /// packets and counters written, for golden-file tests, and
+    /// writes a Section Header Block without options. The clock of
+    /// the underlying writer is stopped at the Unix epoch, see
+    /// `PcapNgWriter::new_deterministic`, so the times of the
+    /// Interface Statistics Blocks are zero.
+    pub fn new_deterministic(writer: W) -> Result<Self> {
+        let mut writer = PcapNgWriter::new(Endianness::Little, writer);
+        writer.set_clock(|| UNIX_EPOCH);
+        Self::from_writer(writer, &Options::new())
+    }
+
+    fn from_writer(mut writer: PcapNgWriter<W>, options: &Options) -> Result<Self> {
+        writer.start_new_section(options)?;
+        Ok(Self {
+            last_statistics: writer.now(),
+            writer: Some(writer),
+            interfaces: vec![],
+            statistics_interval: None,
+            final_statistics: false,
+        })
+    }
+
+    /// Describes a new interface with nanosecond timestamp
+    /// resolution.
+    pub fn add_interface(&mut self, link_type: LinkType, snap_len: u32) -> Result<InterfaceId> {
+        self.add_interface_with_options(link_type, snap_len, NANO_SECOND_TSRES, &Options::new())
+    }
+
+    /// Describes a new interface. An `if_tsresol` option for
+    /// `tsresol` is written along with `options`, replacing any
+    /// `if_tsresol` option they contain.
+    pub fn add_interface_with_options(
+        &mut self,
+        link_type: LinkType,
+        snap_len: u32,
+        tsresol: &TimestampResolution,
+        options: &Options,
+    ) -> Result<InterfaceId> {
+        let tsresol_opt = OptionIfTsResol::new_option(tsresol);
+        let mut idb_options = Options::new();
+        idb_options.add_option(&tsresol_opt);
+        for opt in options.iter() {
+            if !matches!(opt, BlockOption::IfTsResol(_)) {
+                idb_options.add_option(opt);
+            }
+        }
+        let id = self
+            .get_writer_mut()
+            .add_interface(link_type, snap_len, &idb_options)?;
+        let index = id.value() as usize;
+        if self.interfaces.len() <= index {
+            self.interfaces.resize_with(index + 1, || None);
+        }
+        self.interfaces[index] = Some(InterfaceState {
+            id,
+            start: self.get_writer().now(),
+            counters: InterfaceCounters::default(),
+            summary: InterfaceSummary::default(),
+        });
+        Ok(id)
+    }
+
+    /// Writes a packet captured on `interface` at `timestamp`.
+    pub fn write_packet(
+        &mut self,
+        interface: InterfaceId,
+        timestamp: SystemTime,
+        data: &[u8],
+    ) -> Result<()> {
+        let nanos = nanos_since_epoch(timestamp)?;
+        self.get_writer_mut().write_packet(interface, nanos, data)?;
+        self.packet_written(interface, Some(timestamp), data.len())
+    }
+
+    /// Writes a packet captured on `interface` at `timestamp`, along
+    /// with its original length on the wire and options.
+    pub fn write_packet_with_options(
+        &mut self,
+        interface: InterfaceId,
+        timestamp: SystemTime,
+        orig_len: u32,
+        data: &[u8],
+        options: &Options,
+    ) -> Result<()> {
+        let nanos = nanos_since_epoch(timestamp)?;
+        self.get_writer_mut()
+            .write_packet_with_options(interface, nanos, orig_len, data, options)?;
+        self.packet_written(interface, Some(timestamp), data.len())
+    }
+
+    /// Writes a packet record, see `PcapNgWriter::write_record`.
+    pub fn write_record(&mut self, record: PacketRecord) -> Result<()> {
+        self.get_writer_mut().write_record(record)?;
+        let timestamp = record
+            .timestamp_nanos()
+            .and_then(|nanos| u64::try_from(nanos).ok())
+            .map(|nanos| UNIX_EPOCH + Duration::from_nanos(nanos));
+        self.packet_written(record.interface(), timestamp, record.data().len())
+    }
+
+    /// Returns the counters of an interface described by the capture
+    /// writer.
+    pub fn counters(&self, interface: InterfaceId) -> Option<InterfaceCounters> {
+        self.state(interface).map(|state| state.counters)
+    }
+
+    /// Returns a summary of the packets written so far on each
+    /// interface described by the capture writer.
+    pub fn stats(&self) -> CaptureStats {
+        CaptureStats {
+            interfaces: self
+                .interfaces
+                .iter()
+                .flatten()
+                .map(|state| (state.id, state.summary))
+                .collect(),
+        }
+    }
+
+    /// Replaces the counters of `interface`, e.g. with the totals
+    /// reported by the capture backend. Packets written afterwards
+    /// are still counted.
+    pub fn set_counters(
+        &mut self,
+        interface: InterfaceId,
+        counters: InterfaceCounters,
+    ) -> Result<()> {
+        self.state_mut(interface)?.counters = counters;
+        Ok(())
+    }
+
+    /// Counts `count` more packets dropped by `interface`.
+    pub fn add_dropped(&mut self, interface: InterfaceId, count: u64) -> Result<()> {
+        self.state_mut(interface)?.counters.dropped += count;
+        Ok(())
+    }
+
+    /// Sets how often Interface Statistics Blocks are written: when a
+    /// packet is written at least `interval` after the last ones.
+    /// They are not written periodically if `interval` is `None`, the
+    /// default.
+    pub fn set_statistics_interval(&mut self, interval: Option<Duration>) {
+        self.statistics_interval = interval;
+    }
+
+    /// Sets whether `close` writes Interface Statistics Blocks.
+    pub fn set_final_statistics(&mut self, enabled: bool) {
+        self.final_statistics = enabled;
+    }
+
+    /// Writes an Interface Statistics Block with the current time and
+    /// counters for each interface described by the capture writer.
+    /// The time is read from the clock of the underlying writer, see
+    /// `PcapNgWriter::set_clock`.
+    pub fn write_statistics(&mut self) -> Result<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .expect("writer is only taken by into_inner");
+        let now = writer.now();
+        let end = nanos_since_epoch(now)?;
+        for (id, state) in self.interfaces.iter().enumerate() {
+            let state = match state {
+                Some(state) => state,
+                None => continue,
+            };
+            let tsresol = match writer.interface(state.id) {
+                Some(info) => *info.tsresol(),
+                // a section was started on the underlying writer
+                None => continue,
+            };
+            let start = Timestamp::from_nanoseconds(&tsresol, nanos_since_epoch(state.start)?)?;
+            let end = Timestamp::from_nanoseconds(&tsresol, end)?;
+            let counters = state.counters;
+            let isb_options = [
+                OptionIsbStartTime::new_option(start),
+                OptionIsbEndTime::new_option(end),
+                OptionIsbIfRecv::new_option(counters.received),
+                OptionIsbIfDrop::new_option(counters.dropped),
+                OptionIsbFilterAccept::new_option(counters.accepted),
+            ];
+            let mut options = Options::new();
+            options.add_options(&isb_options);
+            let isb = InterfaceStatisticsBlock::new(id as u32, end.high(), end.low(), &options);
+            writer.write(&isb)?;
+        }
+        self.last_statistics = now;
+        Ok(())
+    }
+
+    fn state(&self, interface: InterfaceId) -> Option<&InterfaceState> {
+        self.interfaces.get(interface.value() as usize)?.as_ref()
+    }
+
+    fn state_mut(&mut self, interface: InterfaceId) -> Result<&mut InterfaceState> {
+        self.interfaces
+            .get_mut(interface.value() as usize)
+            .and_then(Option::as_mut)
+            .ok_or_else(|| Error::UnknownInterface(interface.value()))
+    }
+
+    /// Counts a packet written on `interface`, and writes the
+    /// statistics if they are due.
+    fn packet_written(
+        &mut self,
+        interface: InterfaceId,
+        timestamp: Option<SystemTime>,
+        len: usize,
+    ) -> Result<()> {
+        if let Ok(state) = self.state_mut(interface) {
+            state.counters.received += 1;
+            state.counters.accepted += 1;
+            state.summary.record(timestamp, len);
+        }
+        let due = match self.statistics_interval {
+            Some(interval) => self
+                .get_writer()
+                .now()
+                .duration_since(self.last_statistics)
+                .is_ok_and(|elapsed| elapsed >= interval),
+            None => false,
+        };
+        if due {
+            self.write_statistics()?;
+        }
+        Ok(())
+    }
+
+    /// Returns an immutable reference to the underlying pcapng
+    /// writer.
+    pub fn get_writer(&self) -> &PcapNgWriter<W> {
+        self.writer
+            .as_ref()
+            .expect("writer is only taken by into_inner")
+    }
+
+    /// Returns a mutable reference to the underlying pcapng writer,
+    /// e.g. to write blocks `CaptureWriter` has no method for.
+    pub fn get_writer_mut(&mut self) -> &mut PcapNgWriter<W> {
+        self.writer
+            .as_mut()
+            .expect("writer is only taken by into_inner")
+    }
+
+    /// Finishes the capture, see `PcapNgWriter::close`, after writing
+    /// the statistics if `set_final_statistics` is enabled.
+    pub fn close(&mut self) -> Result<()> {
+        if self.final_statistics && !self.get_writer().is_closed() {
+            self.write_statistics()?;
+        }
+        self.get_writer_mut().close()
+    }
+
+    /// Consumes the capture writer, returning the underlying pcapng
+    /// writer.
+    pub fn into_inner(mut self) -> PcapNgWriter<W> {
+        self.writer
+            .take()
+            .expect("writer is only taken by into_inner")
+    }
+}
+
+impl<W: Write> Drop for CaptureWriter<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::OptionComment;
+    use crate::reader::{Block, SliceReader};
+    use crate::utils::MICRO_SECOND_TSRES;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn write_packet() {
+        let mut buf = vec![];
+        let mut capture = CaptureWriter::new(&mut buf).unwrap();
+        let id = capture.add_interface(LinkType::Ethernet, 1500).unwrap();
+        let ts = UNIX_EPOCH + Duration::from_nanos(0x1_0000_0002);
+        capture.write_packet(id, ts, &[9; 10]).unwrap();
+        // SHB (28 bytes), IDB with if_tsresol (28 bytes), then the EPB
+        drop(capture);
+        assert_eq!(buf.len(), 28 + 28 + 44);
+        // if_tsresol
+        assert_eq!(&buf[44..49], &[9, 0, 1, 0, 9]);
+        let epb = &buf[56..];
+        assert_eq!(&epb[..4], &[6, 0, 0, 0]);
+        // timestamp (high, low)
+        assert_eq!(&epb[12..20], &[1, 0, 0, 0, 2, 0, 0, 0]);
+        // captured and original length
+        assert_eq!(&epb[20..28], &[10, 0, 0, 0, 10, 0, 0, 0]);
+    }
+
+    #[test]
+    fn tsresol_option_replaced() {
+        let mut buf = vec![];
+        let mut capture = CaptureWriter::new(&mut buf).unwrap();
+        let nano = OptionIfTsResol::new_option(NANO_SECOND_TSRES);
+        let comment = OptionComment::new_option("eth0");
+        let mut opts = Options::new();
+        opts.add_option(&nano);
+        opts.add_option(&comment);
+        let id = capture
+            .add_interface_with_options(LinkType::Ethernet, 1500, MICRO_SECOND_TSRES, &opts)
+            .unwrap();
+        let info = capture.get_writer().interface(id).unwrap();
+        assert_eq!(info.tsresol(), MICRO_SECOND_TSRES);
+        let ts = UNIX_EPOCH + Duration::from_micros(3);
+        capture.write_packet(id, ts, &[9; 4]).unwrap();
+        // SHB (28), IDB with if_tsresol and opt_comment (36), EPB
+        drop(capture);
+        assert_eq!(&buf[28 + 36 + 16..28 + 36 + 20], &[3, 0, 0, 0]);
+    }
+
+    /// Returns the interface ID and options of the Interface
+    /// Statistics Blocks in `data`.
+    fn statistics(data: &[u8]) -> Vec<(u32, Vec<u64>)> {
+        SliceReader::new(data)
+            .filter_map(|block| match block.unwrap() {
+                Block::InterfaceStatistics(isb) => {
+                    let options = isb.options();
+                    let values = options.iter().map(|option| match option {
+                        BlockOption::IsbStartTime(o) => o.timestamp().ticks(),
+                        BlockOption::IsbEndTime(o) => {
+                            assert_eq!(o.timestamp().ticks(), isb.timestamp());
+                            o.timestamp().ticks()
+                        }
+                        BlockOption::IsbIfRecv(o) => o.count(),
+                        BlockOption::IsbIfDrop(o) => o.count(),
+                        BlockOption::IsbFilterAccept(o) => o.count(),
+                        _ => panic!("unexpected option"),
+                    });
+                    Some((isb.interface_id(), values.collect()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn final_statistics() {
+        let mut buf = vec![];
+        let mut capture = CaptureWriter::new(&mut buf).unwrap();
+        capture.set_final_statistics(true);
+        let eth0 = capture.add_interface(LinkType::Ethernet, 1500).unwrap();
+        let eth1 = capture
+            .add_interface_with_options(
+                LinkType::Ethernet,
+                1500,
+                MICRO_SECOND_TSRES,
+                &Options::new(),
+            )
+            .unwrap();
+        for _ in 0..3 {
+            capture.write_packet(eth0, UNIX_EPOCH, &[0; 4]).unwrap();
+        }
+        capture.write_packet(eth1, UNIX_EPOCH, &[0; 4]).unwrap();
+        capture.add_dropped(eth0, 2).unwrap();
+        assert_eq!(
+            capture.counters(eth0),
+            Some(InterfaceCounters::new(3, 2, 3))
+        );
+        capture
+            .set_counters(eth1, InterfaceCounters::new(10, 1, 8))
+            .unwrap();
+        capture.write_packet(eth1, UNIX_EPOCH, &[0; 4]).unwrap();
+        capture.close().unwrap();
+        // closing again writes nothing
+        capture.close().unwrap();
+        drop(capture);
+
+        let isbs = statistics(&buf);
+        assert_eq!(isbs.len(), 2);
+        for (id, values) in &isbs {
+            // the start time is before the end time
+            assert!(values[0] <= values[1]);
+            let counters = &values[2..];
+            match id {
+                0 => assert_eq!(counters, [3, 2, 3]),
+                _ => assert_eq!(counters, [11, 1, 9]),
+            }
+        }
+        // the same end time, in nanoseconds and microseconds
+        assert_eq!(isbs[0].1[1] / 1000, isbs[1].1[1]);
+    }
+
+    #[test]
+    fn close_on_drop() {
+        let mut buf = vec![];
+        let mut capture = CaptureWriter::new_deterministic(&mut buf).unwrap();
+        capture.set_final_statistics(true);
+        let eth0 = capture.add_interface(LinkType::Ethernet, 1500).unwrap();
+        capture.write_packet(eth0, UNIX_EPOCH, &[0; 4]).unwrap();
+        drop(capture);
+        assert_eq!(statistics(&buf), [(0, vec![0, 0, 1, 0, 1])]);
+
+        // the pcapng writer taken back is not closed by the capture
+        // writer
+        let mut capture = CaptureWriter::new_deterministic(vec![]).unwrap();
+        capture.set_final_statistics(true);
+        capture.add_interface(LinkType::Ethernet, 1500).unwrap();
+        let writer = capture.into_inner();
+        assert!(statistics(writer.get_writer()).is_empty());
+    }
+
+    #[test]
+    fn periodic_statistics() {
+        let mut buf = vec![];
+        let mut capture = CaptureWriter::new(&mut buf).unwrap();
+        let eth0 = capture.add_interface(LinkType::Ethernet, 1500).unwrap();
+        capture.set_statistics_interval(Some(Duration::from_secs(0)));
+        capture.write_packet(eth0, UNIX_EPOCH, &[0; 4]).unwrap();
+        capture.write_packet(eth0, UNIX_EPOCH, &[0; 4]).unwrap();
+        capture.set_statistics_interval(None);
+        capture.write_packet(eth0, UNIX_EPOCH, &[0; 4]).unwrap();
+        // not described by the capture writer
+        let other = capture
+            .get_writer_mut()
+            .add_interface(LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        assert!(matches!(
+            capture.add_dropped(other, 1),
+            Err(Error::UnknownInterface(1))
+        ));
+        drop(capture);
+        let isbs = statistics(&buf);
+        assert_eq!(isbs.len(), 2);
+        assert_eq!(isbs[0].1[2..], [1, 0, 1]);
+        assert_eq!(isbs[1].1[2..], [2, 0, 2]);
+    }
+
+    #[test]
+    fn deterministic() {
+        let record = || {
+            let mut capture = CaptureWriter::new_deterministic(vec![]).unwrap();
+            capture.set_final_statistics(true);
+            let eth0 = capture.add_interface(LinkType::Ethernet, 1500).unwrap();
+            let ts = UNIX_EPOCH + Duration::from_secs(1);
+            capture.write_packet(eth0, ts, &[0; 4]).unwrap();
+            capture.close().unwrap();
+            capture.into_inner().get_writer().clone()
+        };
+        let buf = record();
+        assert_eq!(record(), buf);
+        assert_eq!(statistics(&buf), [(0, vec![0, 0, 1, 0, 1])]);
+    }
+
+    #[test]
+    fn stats() {
+        let mut capture = CaptureWriter::new(vec![]).unwrap();
+        let eth0 = capture.add_interface(LinkType::Ethernet, 4).unwrap();
+        let eth1 = capture.add_interface(LinkType::Ethernet, 1500).unwrap();
+        assert_eq!(capture.stats().total_packets(), 0);
+        assert_eq!(capture.stats().first_timestamp(), None);
+        let ts = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        capture.write_packet(eth0, ts(5), &[0; 10]).unwrap();
+        capture.write_packet(eth0, ts(3), &[0; 20]).unwrap();
+        capture.write_packet(eth1, ts(7), &[0; 5]).unwrap();
+        capture.add_dropped(eth1, 4).unwrap();
+
+        let stats = capture.stats();
+        let summary = stats.interface(eth0).unwrap();
+        assert_eq!((summary.packets(), summary.bytes()), (2, 30));
+        assert_eq!(summary.first_timestamp(), Some(ts(3)));
+        assert_eq!(summary.last_timestamp(), Some(ts(5)));
+        assert_eq!(stats.interface(eth1).unwrap().packets(), 1);
+        assert_eq!((stats.total_packets(), stats.total_bytes()), (3, 35));
+        assert_eq!(stats.first_timestamp(), Some(ts(3)));
+        assert_eq!(stats.last_timestamp(), Some(ts(7)));
+        let ids: Vec<_> = stats.interfaces().map(|(id, _)| id).collect();
+        assert_eq!(ids, [eth0, eth1]);
+        // no statistics were written
+        assert!(statistics(capture.get_writer().get_writer()).is_empty());
+
+        let nanos = ts(1).duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        capture
+            .write_record(PacketRecord::new(eth1, nanos, &[0; 5]))
+            .unwrap();
+        capture
+            .write_record(PacketRecord::untimed(eth1, &[0; 5]))
+            .unwrap();
+        let summary = *capture.stats().interface(eth1).unwrap();
+        assert_eq!((summary.packets(), summary.bytes()), (3, 15));
+        assert_eq!(summary.first_timestamp(), Some(ts(1)));
+        assert_eq!(summary.last_timestamp(), Some(ts(7)));
+    }
+
+    #[test]
+    fn unknown_interface() {
+        let mut buf = vec![];
+        let mut capture = CaptureWriter::new(&mut buf).unwrap();
+        let id = capture.add_interface(LinkType::Ethernet, 1500).unwrap();
+        capture
+            .get_writer_mut()
+            .start_new_section(&Options::new())
+            .unwrap();
+        assert!(matches!(
+            capture.write_packet(id, SystemTime::now(), &[0; 4]),
+            Err(Error::UnknownInterface(0))
+        ));
+    }
+}
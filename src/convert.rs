@@ -0,0 +1,454 @@
+//! Conversion between pcap and pcapng.
+//!
+//! `pcap_to_pcapng` writes the packets of a pcap file as a pcapng
+//! section, and `PcapConverter` writes pcapng blocks as a pcap file.
+//! For example:
+//!
+//! ```
+//! use pcapng_writer::convert::{pcap_to_pcapng, pcapng_to_pcap};
+//! use pcapng_writer::enums::LinkType;
+//! use pcapng_writer::pcap::{FileHeader, PcapWriter, Precision};
+//! use pcapng_writer::writer::{Endianness, PcapNgWriter};
+//!
+//! let header = FileHeader::new(Precision::Micro, LinkType::Ethernet, 0);
+//! let mut pcap = PcapWriter::new(Endianness::Little, vec![], &header).unwrap();
+//! pcap.write_packet(0, &[0; 60]).unwrap();
+//! let pcap = pcap.into_inner();
+//!
+//! let mut writer = PcapNgWriter::new_le(vec![]);
+//! assert_eq!(pcap_to_pcapng(&pcap[..], &mut writer).unwrap(), 1);
+//! assert_eq!(writer.stats().total_packets(), 1);
+//!
+//! let pcapng = writer.get_writer().clone();
+//! let (pcap, warnings) = pcapng_to_pcap(&pcapng[..], vec![], Precision::Micro).unwrap();
+//! assert_eq!(pcap.len(), 24 + 16 + 60);
+//! assert!(warnings.is_empty());
+//! ```
+
+use crate::blocks::options::{BlockOption, FcsLen, OptionIfFcsLen, OptionIfTsResol, Options};
+use crate::blocks::{EnhancedPacketBlock, InterfaceDescriptionBlock, SimplePacketBlock};
+use crate::enums::LinkType;
+use crate::error::{Error, Result};
+use crate::pcap::{FileHeader, PcapReader, PcapWriter, Precision};
+use crate::reader::{Block, PcapNgReader};
+use crate::utils::{TimestampResolution, DEFAULT_TSRES, NANO_SECOND_TSRES};
+use crate::writer::{Endianness, PcapNgWriter};
+use std::fmt;
+use std::io::{Read, Write};
+
+/// Reads a pcap file from `reader` and writes its packets to
+/// `writer`, in a new section with a single interface. Returns the
+/// number of packets written.
+///
+/// The interface has the link type and snap length of the pcap file.
+/// Files with nanosecond timestamps get an `if_tsresol` of 10^-9, and
+/// the FCS length found in the link type field, if any, is written as
+/// `if_fcslen`.
+pub fn pcap_to_pcapng<R: Read, W: Write>(reader: R, writer: &mut PcapNgWriter<W>) -> Result<u64> {
+    let mut reader = PcapReader::new(reader)?;
+    let header = reader.header().clone();
+    let mut idb_options = Vec::new();
+    if header.precision() == Precision::Nano {
+        idb_options.push(OptionIfTsResol::new_option(NANO_SECOND_TSRES));
+    }
+    if let Some(fcs_len) = header.fcs_len().and_then(|n| FcsLen::new(n).ok()) {
+        idb_options.push(OptionIfFcsLen::new_option(fcs_len));
+    }
+    let mut options = Options::new();
+    options.add_options(&idb_options);
+    writer.start_new_section(&Options::new())?;
+    let idb = InterfaceDescriptionBlock::new(
+        LinkType::from(header.link_type() as u16),
+        header.snap_len(),
+        &options,
+    );
+    let interface = writer.add_interface_block(&idb)?;
+
+    let nanos_per_unit = u128::from(1_000_000_000 / header.precision().units_per_second());
+    let mut packets = 0;
+    while let Some(record) = reader.next_record()? {
+        let nanos = u128::from(record.ts_sec()) * 1_000_000_000
+            + u128::from(record.ts_frac()) * nanos_per_unit;
+        writer.write_packet_with_options(
+            interface,
+            nanos,
+            record.orig_len(),
+            record.data(),
+            &Options::new(),
+        )?;
+        packets += 1;
+    }
+    Ok(packets)
+}
+
+/// A pcapng feature that a pcap file cannot represent, reported by
+/// `PcapConverter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Warning {
+    /// The link type of the interface differs from that of the
+    /// first interface. Its packets were dropped.
+    LinkTypeMismatch { interface_id: u32, link_type: u16 },
+    /// The packets of several interfaces were written to the file,
+    /// which cannot tell them apart.
+    MultipleInterfaces,
+    /// The timestamp resolution of the interface is finer than the
+    /// precision of the file. Its timestamps were truncated.
+    PrecisionLoss { interface_id: u32 },
+    /// Simple Packet Blocks have no timestamp. They were written
+    /// with a timestamp of 0.
+    MissingTimestamp,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LinkTypeMismatch {
+                interface_id,
+                link_type,
+            } => write!(
+                f,
+                "dropped packets of interface {} with link type {}",
+                interface_id, link_type
+            ),
+            Self::MultipleInterfaces => write!(f, "merged packets of several interfaces"),
+            Self::PrecisionLoss { interface_id } => {
+                write!(f, "truncated timestamps of interface {}", interface_id)
+            }
+            Self::MissingTimestamp => write!(f, "wrote simple packets without timestamps"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Interface {
+    tsresol: TimestampResolution,
+    keep: bool,
+}
+
+/// Writes pcapng blocks as a pcap file.
+///
+/// The pcap file header is written when the first interface is
+/// seen, with its link type and snap length. The packets of later
+/// interfaces with the same link type are written too, and those of
+/// interfaces with another link type are dropped. Options and blocks
+/// other than packets are ignored. Features that pcap cannot represent
+/// are reported as `Warning`s, once per interface.
+#[derive(Debug)]
+pub struct PcapConverter<W: Write> {
+    endianness: Endianness,
+    precision: Precision,
+    writer: Option<W>,
+    pcap: Option<PcapWriter<W>>,
+    link_type: u16,
+    interfaces: Vec<Interface>,
+    warnings: Vec<Warning>,
+}
+
+impl<W: Write> PcapConverter<W> {
+    pub fn new(endianness: Endianness, writer: W, precision: Precision) -> Self {
+        Self {
+            endianness,
+            precision,
+            writer: Some(writer),
+            pcap: None,
+            link_type: 0,
+            interfaces: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Converts a block read by the `reader` module.
+    pub fn push(&mut self, block: &Block) -> Result<()> {
+        match block {
+            Block::SectionHeader(_) => self.start_section(),
+            Block::InterfaceDescription(idb) => {
+                let options = idb.options();
+                self.push_idb(&idb.to_block(&options))?;
+            }
+            Block::EnhancedPacket(epb) => {
+                let options = Options::new();
+                self.push_epb(&epb.to_block(&options))?;
+            }
+            Block::SimplePacket(spb) => self.push_spb(spb)?,
+            Block::InterfaceStatistics(_) | Block::Unknown(_) => (),
+        }
+        Ok(())
+    }
+
+    /// Starts a new section: the interfaces described so far can no
+    /// longer be used.
+    pub fn start_section(&mut self) {
+        self.interfaces.clear();
+    }
+
+    pub fn push_idb(&mut self, idb: &InterfaceDescriptionBlock) -> Result<()> {
+        let interface_id = self.interfaces.len() as u32;
+        let tsresol = idb
+            .options()
+            .iter()
+            .find_map(|opt| match opt {
+                BlockOption::IfTsResol(o) => Some(o.resolution()),
+                _ => None,
+            })
+            .unwrap_or(*DEFAULT_TSRES);
+        let keep = match self.writer.take() {
+            Some(writer) => {
+                let header = FileHeader::with_link_type_value(
+                    self.precision,
+                    idb.link_type().into(),
+                    idb.snap_len(),
+                );
+                self.pcap = Some(PcapWriter::new(self.endianness, writer, &header)?);
+                self.link_type = idb.link_type();
+                true
+            }
+            None if idb.link_type() == self.link_type => {
+                self.warn(Warning::MultipleInterfaces);
+                true
+            }
+            None => {
+                self.warn(Warning::LinkTypeMismatch {
+                    interface_id,
+                    link_type: idb.link_type(),
+                });
+                false
+            }
+        };
+        if keep && finer_than(&tsresol, self.precision) {
+            self.warn(Warning::PrecisionLoss { interface_id });
+        }
+        self.interfaces.push(Interface { tsresol, keep });
+        Ok(())
+    }
+
+    pub fn push_epb(&mut self, epb: &EnhancedPacketBlock) -> Result<()> {
+        let interface = self
+            .interfaces
+            .get(epb.interface_id() as usize)
+            .ok_or(Error::UnknownInterface(epb.interface_id()))?;
+        if !interface.keep {
+            return Ok(());
+        }
+        let nanos = interface.tsresol.nanoseconds_from_ticks(epb.timestamp());
+        self.pcap_writer()
+            .write_packet_with_orig_len(nanos, epb.orig_len(), epb.packet_data())
+    }
+
+    pub fn push_spb(&mut self, spb: &SimplePacketBlock) -> Result<()> {
+        match self.interfaces.first() {
+            Some(interface) if interface.keep => (),
+            Some(_) => return Ok(()),
+            None => return Err(Error::UnknownInterface(0)),
+        }
+        self.warn(Warning::MissingTimestamp);
+        self.pcap_writer()
+            .write_packet_with_orig_len(0, spb.orig_len(), spb.packet_data())
+    }
+
+    /// Returns the warnings reported so far.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Returns the underlying writer. If no interface was seen, a
+    /// file header with the `Null` link type is written first, so the
+    /// output is a valid empty pcap file.
+    pub fn finish(self) -> Result<W> {
+        match (self.pcap, self.writer) {
+            (Some(pcap), _) => Ok(pcap.into_inner()),
+            (None, Some(writer)) => {
+                let header = FileHeader::new(self.precision, LinkType::Null, 0);
+                Ok(PcapWriter::new(self.endianness, writer, &header)?.into_inner())
+            }
+            (None, None) => unreachable!("the writer is moved to the pcap writer"),
+        }
+    }
+
+    fn pcap_writer(&mut self) -> &mut PcapWriter<W> {
+        self.pcap
+            .as_mut()
+            .expect("packets are only written after an interface")
+    }
+
+    fn warn(&mut self, warning: Warning) {
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+}
+
+/// Returns true if `tsresol` has more units per second than
+/// `precision`.
+fn finer_than(tsresol: &TimestampResolution, precision: Precision) -> bool {
+    let units = u128::from(precision.units_per_second());
+    match *tsresol {
+        TimestampResolution::PowerOfTen(power) => {
+            10u128.checked_pow(power.into()).is_none_or(|u| u > units)
+        }
+        TimestampResolution::PowerOfTwo(power) => {
+            1u128.checked_shl(power.into()).is_none_or(|u| u > units)
+        }
+    }
+}
+
+/// Reads a pcapng stream from `reader` and writes its packets to
+/// `writer` as a pcap file in the host byte order, see
+/// `PcapConverter`. Returns the writer and the warnings.
+pub fn pcapng_to_pcap<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    precision: Precision,
+) -> Result<(W, Vec<Warning>)> {
+    let mut reader = PcapNgReader::new(reader);
+    let mut converter = PcapConverter::new(Endianness::native(), writer, precision);
+    while let Some(block) = reader.next_block()? {
+        converter.push(&block)?;
+    }
+    let warnings = converter.warnings().to_vec();
+    Ok((converter.finish()?, warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enums::LinkType;
+    use crate::pcap::{FileHeader, PcapWriter, Record};
+    use crate::reader::{Block, SliceReader};
+    use crate::utils::{TimestampResolution, DEFAULT_TSRES};
+    use crate::writer::Endianness;
+
+    fn convert(header: &FileHeader) -> Vec<u8> {
+        let mut pcap = PcapWriter::new(Endianness::Big, vec![], header).unwrap();
+        pcap.write(&Record::new(1, 2, 60, &[1; 4])).unwrap();
+        pcap.write(&Record::new(3, 4, 5, &[2; 5])).unwrap();
+        let pcap = pcap.into_inner();
+        let mut writer = PcapNgWriter::new_le(vec![]);
+        assert_eq!(pcap_to_pcapng(&pcap[..], &mut writer).unwrap(), 2);
+        writer.get_writer().clone()
+    }
+
+    /// Link type, snap length, resolution and FCS length.
+    type Interface = (u16, u32, TimestampResolution, Option<u8>);
+
+    fn blocks(data: &[u8]) -> (Interface, Vec<(u64, u32, Vec<u8>)>) {
+        let mut interface = None;
+        let mut packets = vec![];
+        for block in SliceReader::new(data) {
+            match block.unwrap() {
+                Block::SectionHeader(_) => (),
+                Block::InterfaceDescription(idb) => {
+                    let fcs_len = idb.options().iter().find_map(|o| match o {
+                        BlockOption::IfFcsLen(o) => Some(o.fcs_len().bytes()),
+                        _ => None,
+                    });
+                    interface = Some((idb.link_type(), idb.snap_len(), idb.tsresol(), fcs_len));
+                }
+                Block::EnhancedPacket(epb) => {
+                    packets.push((epb.timestamp(), epb.orig_len(), epb.packet_data().to_vec()))
+                }
+                b => panic!("{:?}", b),
+            }
+        }
+        (interface.unwrap(), packets)
+    }
+
+    #[test]
+    fn micro() {
+        let header = FileHeader::new(Precision::Micro, LinkType::Ethernet, 65535);
+        let (interface, packets) = blocks(&convert(&header));
+        assert_eq!(interface, (1, 65535, *DEFAULT_TSRES, None));
+        assert_eq!(
+            packets,
+            [(1_000_002, 60, vec![1; 4]), (3_000_004, 5, vec![2; 5])]
+        );
+    }
+
+    #[test]
+    fn nano() {
+        let header = FileHeader::with_link_type_value(Precision::Nano, 0x2400_0069, 0);
+        let (interface, packets) = blocks(&convert(&header));
+        assert_eq!(interface, (105, 0, *NANO_SECOND_TSRES, Some(4)));
+        assert_eq!(packets[0].0, 1_000_000_002);
+        assert_eq!(packets[1].0, 3_000_000_004);
+    }
+
+    #[test]
+    fn round_trip() {
+        let header = FileHeader::new(Precision::Nano, LinkType::Ethernet, 1500);
+        let mut pcap = PcapWriter::new(Endianness::native(), vec![], &header).unwrap();
+        pcap.write(&Record::new(1, 2, 60, &[1; 4])).unwrap();
+        pcap.write(&Record::new(3, 4, 5, &[2; 5])).unwrap();
+        let pcap = pcap.into_inner();
+        let mut writer = PcapNgWriter::new_be(vec![]);
+        pcap_to_pcapng(&pcap[..], &mut writer).unwrap();
+        let pcapng = writer.get_writer().clone();
+        let (converted, warnings) = pcapng_to_pcap(&pcapng[..], vec![], Precision::Nano).unwrap();
+        assert_eq!(converted, pcap);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn lossy() {
+        let opts = Options::new();
+        let tsresol = [OptionIfTsResol::new_option(NANO_SECOND_TSRES)];
+        let mut nano = Options::new();
+        nano.add_options(&tsresol);
+        let mut converter = PcapConverter::new(Endianness::Little, vec![], Precision::Micro);
+        converter.start_section();
+        let eth = InterfaceDescriptionBlock::new(LinkType::Ethernet, 0, &nano);
+        let raw = InterfaceDescriptionBlock::new(LinkType::Raw, 0, &opts);
+        converter.push_idb(&eth).unwrap();
+        converter.push_idb(&raw).unwrap();
+        converter.push_idb(&eth).unwrap();
+        for id in 0..3 {
+            let data = [id as u8];
+            let epb = EnhancedPacketBlock::new(id, 0, 1_234_567, 1, 1, &data, &opts);
+            converter.push_epb(&epb).unwrap();
+        }
+        converter
+            .push_spb(&SimplePacketBlock::new(1, &[3]))
+            .unwrap();
+        let epb = EnhancedPacketBlock::new(3, 0, 0, 0, 0, &[], &opts);
+        assert!(matches!(
+            converter.push_epb(&epb),
+            Err(Error::UnknownInterface(3))
+        ));
+        assert_eq!(
+            converter.warnings(),
+            [
+                Warning::PrecisionLoss { interface_id: 0 },
+                Warning::LinkTypeMismatch {
+                    interface_id: 1,
+                    link_type: 101
+                },
+                Warning::MultipleInterfaces,
+                Warning::PrecisionLoss { interface_id: 2 },
+                Warning::MissingTimestamp,
+            ]
+        );
+        let pcap = converter.finish().unwrap();
+        let mut reader = PcapReader::new(&pcap[..]).unwrap();
+        assert_eq!(reader.header().link_type(), 1);
+        assert_eq!(
+            reader.next_record().unwrap(),
+            Some(Record::new(0, 1234, 1, &[0]))
+        );
+        assert_eq!(
+            reader.next_record().unwrap(),
+            Some(Record::new(0, 1234, 1, &[2]))
+        );
+        assert_eq!(
+            reader.next_record().unwrap(),
+            Some(Record::new(0, 0, 1, &[3]))
+        );
+        assert_eq!(reader.next_record().unwrap(), None);
+    }
+
+    #[test]
+    fn empty() {
+        let (pcap, _) = pcapng_to_pcap(&[][..], vec![], Precision::Micro).unwrap();
+        let reader = PcapReader::new(&pcap[..]).unwrap();
+        assert_eq!(reader.header().link_type(), 0);
+    }
+}
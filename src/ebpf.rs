@@ -0,0 +1,343 @@
+//! Recording packets sent by eBPF programs.
+//!
+//! eBPF programs attached to TC or XDP hooks can copy packets into a
+//! ring buffer or perf buffer. `EventWriter` writes each packet event
+//! read from the buffer as an Enhanced Packet Block, with the
+//! `epb_queue` option for the receive queue and the `epb_verdict`
+//! option for the return value of the program.
+//!
+//! The crate does not depend on an eBPF library: events are the raw
+//! bytes that `aya::maps::RingBuf` or the callbacks of `libbpf-rs`
+//! hand out. Each event starts with this header, in host byte order,
+//! followed by the captured bytes of the packet:
+//!
+//! ```c
+//! struct pcapng_event {
+//!     __u64 timestamp_ns; /* bpf_ktime_get_ns() */
+//!     __u64 verdict;      /* TC_ACT_* or XDP_* value */
+//!     __u32 ifindex;
+//!     __u32 orig_len;
+//!     __u32 cap_len;
+//!     __u32 queue;        /* skb->queue_mapping or rx_queue_index */
+//!     __u8  hook;         /* 0: no verdict, 1: TC, 2: XDP */
+//!     __u8  pad[7];
+//! };
+//! ```
+//!
+//! Events can be parsed into a `PacketEvent` and written, e.g. from
+//! the callback of a `libbpf_rs::RingBufferBuilder`:
+//!
+//! ```no_run
+//! use pcapng_writer::capture::CaptureWriter;
+//! use pcapng_writer::ebpf::{EventWriter, PacketEvent};
+//! use std::fs::File;
+//!
+//! # fn next_event() -> Option<Vec<u8>> { None }
+//! # fn main() -> pcapng_writer::Result<()> {
+//! # let boot_time = std::time::SystemTime::UNIX_EPOCH;
+//! let capture = CaptureWriter::new(File::create("ebpf.pcapng")?)?;
+//! let mut events = EventWriter::new(capture, boot_time);
+//! while let Some(data) = next_event() {
+//!     let event = PacketEvent::parse(&data)?;
+//!     // skip the loopback interface
+//!     if event.ifindex() != 1 {
+//!         events.write(&event)?;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::blocks::options::{
+    BlockOption, OptionEpbQueue, OptionEpbVerdict, OptionIfName, Options, VerdictType,
+};
+use crate::capture::CaptureWriter;
+use crate::enums::LinkType;
+use crate::error::Result;
+use crate::framing::invalid;
+use crate::utils::{nanos_since_epoch, NANO_SECOND_TSRES};
+use crate::writer::{InterfaceId, PacketRecord};
+use byteorder::{ByteOrder, NativeEndian};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime};
+
+/// The length of the event header.
+pub const EVENT_HEADER_LEN: usize = 40;
+
+/// A packet event sent by an eBPF program.
+#[derive(Debug, Clone, Copy)]
+pub struct PacketEvent<'a> {
+    timestamp_ns: u64,
+    verdict: Option<(u8, u64)>,
+    ifindex: u32,
+    orig_len: u32,
+    queue: u32,
+    data: &'a [u8],
+}
+
+impl<'a> PacketEvent<'a> {
+    /// Parses an event. Bytes after the captured packet, such as the
+    /// padding of perf buffer records, are ignored.
+    pub fn parse(event: &'a [u8]) -> io::Result<Self> {
+        if event.len() < EVENT_HEADER_LEN {
+            return Err(invalid("eBPF event shorter than its header"));
+        }
+        let cap_len = NativeEndian::read_u32(&event[24..]) as usize;
+        let data = event[EVENT_HEADER_LEN..]
+            .get(..cap_len)
+            .ok_or_else(|| invalid("eBPF event shorter than its packet"))?;
+        let verdict = NativeEndian::read_u64(&event[8..]);
+        let verdict = match event[32] {
+            0 => None,
+            hook @ 1..=2 => Some((hook, verdict)),
+            _ => return Err(invalid("unknown eBPF hook")),
+        };
+        Ok(Self {
+            timestamp_ns: NativeEndian::read_u64(event),
+            verdict,
+            ifindex: NativeEndian::read_u32(&event[16..]),
+            orig_len: NativeEndian::read_u32(&event[20..]),
+            queue: NativeEndian::read_u32(&event[28..]),
+            data,
+        })
+    }
+
+    /// Returns the timestamp of the event, in nanoseconds of the
+    /// clock of `bpf_ktime_get_ns`.
+    pub fn timestamp_ns(&self) -> u64 {
+        self.timestamp_ns
+    }
+
+    /// Returns the verdict of the TC or XDP program, if any.
+    pub fn verdict(&self) -> Option<VerdictType> {
+        self.verdict.map(|(hook, value)| match hook {
+            1 => VerdictType::LinuxEbpfTc(value),
+            _ => VerdictType::LinuxEbpfXdp(value),
+        })
+    }
+
+    pub fn ifindex(&self) -> u32 {
+        self.ifindex
+    }
+
+    pub fn orig_len(&self) -> u32 {
+        self.orig_len
+    }
+
+    pub fn queue(&self) -> u32 {
+        self.queue
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns the record of the packet, as captured on `interface`.
+    /// `boot_time` is the time of the clock's zero.
+    pub fn to_record(
+        &self,
+        interface: InterfaceId,
+        boot_time: SystemTime,
+    ) -> Result<PacketRecord<'a>> {
+        let timestamp = boot_time + Duration::from_nanos(self.timestamp_ns);
+        let nanos = nanos_since_epoch(timestamp)?;
+        Ok(PacketRecord::new(interface, nanos, self.data).with_orig_len(self.orig_len))
+    }
+}
+
+/// Writes packet events to a `CaptureWriter`.
+///
+/// Each network interface index gets its own interface. Interfaces
+/// can be described beforehand with `add_interface`; otherwise they
+/// are described as Ethernet interfaces when their first packet is
+/// written, with their name on Linux.
+#[derive(Debug)]
+pub struct EventWriter<W: Write> {
+    capture: CaptureWriter<W>,
+    boot_time: SystemTime,
+    interfaces: HashMap<u32, InterfaceId>,
+    events: u64,
+}
+
+impl<W: Write> EventWriter<W> {
+    /// Creates an event writer. `boot_time` is the time at which the
+    /// clock of `bpf_ktime_get_ns`, `CLOCK_MONOTONIC`, was zero:
+    /// `CLOCK_REALTIME` minus `CLOCK_MONOTONIC`.
+    pub fn new(capture: CaptureWriter<W>, boot_time: SystemTime) -> Self {
+        Self {
+            capture,
+            boot_time,
+            interfaces: HashMap::new(),
+            events: 0,
+        }
+    }
+
+    /// Describes the interface with index `ifindex`.
+    pub fn add_interface(
+        &mut self,
+        ifindex: u32,
+        link_type: LinkType,
+        snap_len: u32,
+        options: &Options,
+    ) -> Result<InterfaceId> {
+        let id = self.capture.add_interface_with_options(
+            link_type,
+            snap_len,
+            NANO_SECOND_TSRES,
+            options,
+        )?;
+        self.interfaces.insert(ifindex, id);
+        Ok(id)
+    }
+
+    /// Parses `event` and writes its packet.
+    pub fn write_event(&mut self, event: &[u8]) -> Result<()> {
+        let event = PacketEvent::parse(event)?;
+        self.write(&event)
+    }
+
+    /// Writes the packet of `event`.
+    pub fn write(&mut self, event: &PacketEvent) -> Result<()> {
+        let interface = match self.interfaces.get(&event.ifindex()) {
+            Some(&id) => id,
+            None => {
+                let name: Vec<BlockOption> = interface_name(event.ifindex())
+                    .and_then(|name| OptionIfName::try_new_option(&name).ok())
+                    .into_iter()
+                    .collect();
+                let mut options = Options::new();
+                options.add_options(&name);
+                self.add_interface(event.ifindex(), LinkType::Ethernet, 0, &options)?
+            }
+        };
+        let queue = OptionEpbQueue::new_option(event.queue());
+        let verdict = event.verdict().map(OptionEpbVerdict::new_option);
+        let mut options = Options::new();
+        options.add_option(&queue);
+        if let Some(verdict) = &verdict {
+            options.add_option(verdict);
+        }
+        let record = event.to_record(interface, self.boot_time)?;
+        self.capture.write_record(record.with_options(&options))?;
+        self.events += 1;
+        Ok(())
+    }
+
+    /// Returns the number of events written.
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+
+    pub fn get_capture(&self) -> &CaptureWriter<W> {
+        &self.capture
+    }
+
+    pub fn get_capture_mut(&mut self) -> &mut CaptureWriter<W> {
+        &mut self.capture
+    }
+
+    pub fn into_inner(self) -> CaptureWriter<W> {
+        self.capture
+    }
+}
+
+/// Returns the name of the interface with index `ifindex`.
+#[cfg(target_os = "linux")]
+fn interface_name(ifindex: u32) -> Option<String> {
+    std::fs::read_dir("/sys/class/net")
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .find(|entry| {
+            std::fs::read_to_string(entry.path().join("ifindex"))
+                .is_ok_and(|index| index.trim().parse() == Ok(ifindex))
+        })
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn interface_name(_ifindex: u32) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::reader::{Block, SliceReader};
+    use std::time::UNIX_EPOCH;
+
+    fn raw_event(hook: u8, data: &[u8]) -> Vec<u8> {
+        let mut event = vec![0; EVENT_HEADER_LEN];
+        NativeEndian::write_u64(&mut event[0..], 1_500);
+        NativeEndian::write_u64(&mut event[8..], 2);
+        NativeEndian::write_u32(&mut event[16..], 7);
+        NativeEndian::write_u32(&mut event[20..], 1_514);
+        NativeEndian::write_u32(&mut event[24..], data.len() as u32);
+        NativeEndian::write_u32(&mut event[28..], 3);
+        event[32] = hook;
+        event.extend_from_slice(data);
+        event
+    }
+
+    #[test]
+    fn parse_event() {
+        let mut raw = raw_event(2, &[1, 2, 3]);
+        raw.extend_from_slice(&[0; 5]);
+        let event = PacketEvent::parse(&raw).unwrap();
+        assert_eq!(event.timestamp_ns(), 1_500);
+        assert_eq!(event.verdict(), Some(VerdictType::xdp_pass()));
+        assert_eq!(event.ifindex(), 7);
+        assert_eq!(event.orig_len(), 1_514);
+        assert_eq!(event.queue(), 3);
+        assert_eq!(event.data(), [1, 2, 3]);
+
+        assert!(PacketEvent::parse(&raw[..EVENT_HEADER_LEN + 2]).is_err());
+        assert!(PacketEvent::parse(&raw[..16]).is_err());
+        assert!(PacketEvent::parse(&raw_event(3, &[])).is_err());
+        assert_eq!(
+            PacketEvent::parse(&raw_event(0, &[])).unwrap().verdict(),
+            None
+        );
+    }
+
+    #[test]
+    fn write_events() {
+        let capture = CaptureWriter::new(vec![]).unwrap();
+        let boot_time = UNIX_EPOCH + Duration::from_secs(10);
+        let mut writer = EventWriter::new(capture, boot_time);
+        writer
+            .add_interface(7, LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        writer.write_event(&raw_event(1, &[1, 2, 3])).unwrap();
+        assert_eq!(writer.events(), 1);
+        assert!(matches!(writer.write_event(&[0; 8]), Err(Error::Io(_))));
+        let buf = writer.into_inner().into_inner().get_writer().clone();
+        let blocks: Vec<_> = SliceReader::new(&buf).map(|b| b.unwrap()).collect();
+        assert_eq!(blocks.len(), 3);
+        match &blocks[2] {
+            Block::EnhancedPacket(epb) => {
+                assert_eq!(epb.timestamp(), 10_000_001_500);
+                assert_eq!(epb.orig_len(), 1_514);
+                assert_eq!(epb.packet_data(), [1, 2, 3]);
+                let options = epb.options();
+                let options: Vec<_> = options.iter().collect();
+                assert!(matches!(options[0], BlockOption::EpbQueue(q) if q.queue() == 3));
+                assert!(matches!(
+                    options[1],
+                    BlockOption::EpbVerdict(v) if *v.verdict() == VerdictType::LinuxEbpfTc(2)
+                ));
+            }
+            b => panic!("{:?}", b),
+        }
+    }
+
+    #[test]
+    fn unknown_ifindex() {
+        let capture = CaptureWriter::new(vec![]).unwrap();
+        let mut writer = EventWriter::new(capture, UNIX_EPOCH);
+        writer.write_event(&raw_event(0, &[])).unwrap();
+        writer.write_event(&raw_event(0, &[])).unwrap();
+        assert_eq!(writer.get_capture().get_writer().interfaces().len(), 1);
+    }
+}
@@ -1,7 +1,9 @@
 use crate::constants::SHB_UNSPECIFIED_LENGTH;
+use core::ops::{BitAnd, BitOr, BitOrAssign};
 
 /// pcapng block types
 #[derive(Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BlockType {
     SectionHeader,
     InterfaceDescription,
@@ -9,6 +11,11 @@ pub enum BlockType {
     NameResolution,
     InterfaceStatistics,
     EnhancedPacket,
+    DecryptionSecrets,
+    /// A Custom Block that may be copied to other files.
+    Custom,
+    /// A Custom Block that must not be copied to other files.
+    CustomNoCopy,
     Unknown(u32),
 }
 
@@ -21,11 +28,42 @@ impl BlockType {
             Self::NameResolution => 0x00000004,
             Self::InterfaceStatistics => 0x00000005,
             Self::EnhancedPacket => 0x00000006,
+            Self::DecryptionSecrets => 0x0000000A,
+            Self::Custom => 0x00000BAD,
+            Self::CustomNoCopy => 0x40000BAD,
             Self::Unknown(x) => *x,
         }
     }
 }
 
+/// Types of the secrets of a Decryption Secrets Block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SecretsType {
+    /// An NSS key log, the format of `SSLKEYLOGFILE`.
+    TlsKeyLog,
+    WireGuardKeyLog,
+    ZigBeeNwkKey,
+    ZigBeeApsKey,
+    SshKeyLog,
+    OpcUaKeyLog,
+    Custom(u32),
+}
+
+impl SecretsType {
+    pub fn value(&self) -> u32 {
+        match self {
+            Self::TlsKeyLog => 0x544C534B,
+            Self::WireGuardKeyLog => 0x57474B4C,
+            Self::ZigBeeNwkKey => 0x5A4E574B,
+            Self::ZigBeeApsKey => 0x5A415053,
+            Self::SshKeyLog => 0x5353484B,
+            Self::OpcUaKeyLog => 0x55414B4C,
+            Self::Custom(x) => *x,
+        }
+    }
+}
+
 pub enum SectionHeaderSectionLength {
     Unspecified,
     Bytes(u64),
@@ -40,273 +78,192 @@ impl SectionHeaderSectionLength {
     }
 }
 
-/// Link types as specified by <http://www.tcpdump.org/linktypes.html>.
-pub enum LinkType {
-    Null,
-    Ethernet,
-    Ax25,
-    Ieee8025,
-    ArcnetBsd,
-    Slip,
-    Ppp,
-    Fddi,
-    PppHdlc,
-    PppEther,
-    AtmRfc1483,
-    Raw,
-    CHdlc,
-    Ieee80211,
-    Frelay,
-    Loop,
-    LinuxSll,
-    Ltalk,
-    Pflog,
-    Ieee80211Prism,
-    IpOverFc,
-    Sunatm,
-    Ieee80211Radiotap,
-    ArcnetLinux,
-    AppleIpOverIeee1394,
-    Mtp2WithPhdr,
-    Mtp2,
-    Mtp3,
-    Sccp,
-    Docsis,
-    LinuxIrda,
-    User0,
-    User1,
-    User2,
-    User3,
-    User4,
-    User5,
-    User6,
-    User7,
-    User8,
-    User9,
-    User10,
-    User11,
-    User12,
-    User13,
-    User14,
-    User15,
-    Ieee80211Avs,
-    BacnetMsTp,
-    PppPppd,
-    GprsLlc,
-    GpfT,
-    GpfF,
-    LinuxLapd,
-    Mfr,
-    BluetoothHciH4,
-    UsbLinux,
-    Ppi,
-    Ieee802154Withfcs,
-    Sita,
-    Erf,
-    BluetoothHciH4WithPhdr,
-    Ax25Kiss,
-    Lapd,
-    PppWithDir,
-    CHdlcWithDir,
-    FrelayWithDir,
-    LapbWithDir,
-    IpmbLinux,
-    Ieee802154NonaskPhy,
-    UsbLinuxMmapped,
-    Fc2,
-    Fc2WithFrameDelims,
-    Ipnet,
-    CanSocketcan,
-    Ipv4,
-    Ipv6,
-    Ieee802154Nofcs,
-    Dbus,
-    DvbCi,
-    Mux27010,
-    Stanag5066DPdu,
-    Nflog,
-    Netanalyzer,
-    NetanalyzerTransparent,
-    Ipoib,
-    Mpeg2Ts,
-    Ng40,
-    NfcLlcp,
-    Infiniband,
-    Sctp,
-    Usbpcap,
-    RtacSerial,
-    BluetoothLeLl,
-    Netlink,
-    BluetoothLinuxMonitor,
-    BluetoothBredrBb,
-    BluetoothLeLlWithPhdr,
-    ProfibusDl,
-    Pktap,
-    Epon,
-    IpmiHpm2,
-    ZwaveR1R2,
-    ZwaveR3,
-    WattstopperDlm,
-    Iso14443,
-    Rds,
-    UsbDarwin,
-    Sdlc,
-    Loratap,
-    Vsock,
-    NordicBle,
-    Docsis31Xra31,
-    EthernetMpacket,
-    DisplayportAux,
-    LinuxSll2,
-    Openvizsla,
-    Ebhscr,
-    VppDispatch,
-    DsaTagBrcm,
-    DsaTagBrcmPrepend,
-    Ieee802154Tap,
-    DsaTagDsa,
-    DsaTagEdsa,
-    Elee,
-    ZWaveSerial,
-    Usb20,
-    AtscAlp,
-}
+macro_rules! link_types {
+    ($($name:ident = $value:literal,)*) => {
+        /// Link types as specified by <http://www.tcpdump.org/linktypes.html>.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        pub enum LinkType {
+            $($name,)*
+            /// A link type that is not in the registry, e.g. one of
+            /// proprietary framing.
+            Custom(u16),
+        }
 
-impl LinkType {
-    pub fn value(&self) -> u16 {
-        match self {
-            Self::Null => 0,
-            Self::Ethernet => 1,
-            Self::Ax25 => 3,
-            Self::Ieee8025 => 6,
-            Self::ArcnetBsd => 7,
-            Self::Slip => 8,
-            Self::Ppp => 9,
-            Self::Fddi => 10,
-            Self::PppHdlc => 50,
-            Self::PppEther => 51,
-            Self::AtmRfc1483 => 100,
-            Self::Raw => 101,
-            Self::CHdlc => 104,
-            Self::Ieee80211 => 105,
-            Self::Frelay => 107,
-            Self::Loop => 108,
-            Self::LinuxSll => 113,
-            Self::Ltalk => 114,
-            Self::Pflog => 117,
-            Self::Ieee80211Prism => 119,
-            Self::IpOverFc => 122,
-            Self::Sunatm => 123,
-            Self::Ieee80211Radiotap => 127,
-            Self::ArcnetLinux => 129,
-            Self::AppleIpOverIeee1394 => 138,
-            Self::Mtp2WithPhdr => 139,
-            Self::Mtp2 => 140,
-            Self::Mtp3 => 141,
-            Self::Sccp => 142,
-            Self::Docsis => 143,
-            Self::LinuxIrda => 144,
-            Self::User0 => 147,
-            Self::User1 => 148,
-            Self::User2 => 149,
-            Self::User3 => 150,
-            Self::User4 => 151,
-            Self::User5 => 152,
-            Self::User6 => 153,
-            Self::User7 => 154,
-            Self::User8 => 155,
-            Self::User9 => 156,
-            Self::User10 => 157,
-            Self::User11 => 158,
-            Self::User12 => 159,
-            Self::User13 => 160,
-            Self::User14 => 161,
-            Self::User15 => 162,
-            Self::Ieee80211Avs => 163,
-            Self::BacnetMsTp => 165,
-            Self::PppPppd => 166,
-            Self::GprsLlc => 169,
-            Self::GpfT => 170,
-            Self::GpfF => 171,
-            Self::LinuxLapd => 177,
-            Self::Mfr => 182,
-            Self::BluetoothHciH4 => 187,
-            Self::UsbLinux => 189,
-            Self::Ppi => 192,
-            Self::Ieee802154Withfcs => 195,
-            Self::Sita => 196,
-            Self::Erf => 197,
-            Self::BluetoothHciH4WithPhdr => 201,
-            Self::Ax25Kiss => 202,
-            Self::Lapd => 203,
-            Self::PppWithDir => 204,
-            Self::CHdlcWithDir => 205,
-            Self::FrelayWithDir => 206,
-            Self::LapbWithDir => 207,
-            Self::IpmbLinux => 209,
-            Self::Ieee802154NonaskPhy => 215,
-            Self::UsbLinuxMmapped => 220,
-            Self::Fc2 => 224,
-            Self::Fc2WithFrameDelims => 225,
-            Self::Ipnet => 226,
-            Self::CanSocketcan => 227,
-            Self::Ipv4 => 228,
-            Self::Ipv6 => 229,
-            Self::Ieee802154Nofcs => 230,
-            Self::Dbus => 231,
-            Self::DvbCi => 235,
-            Self::Mux27010 => 236,
-            Self::Stanag5066DPdu => 237,
-            Self::Nflog => 239,
-            Self::Netanalyzer => 240,
-            Self::NetanalyzerTransparent => 241,
-            Self::Ipoib => 242,
-            Self::Mpeg2Ts => 243,
-            Self::Ng40 => 244,
-            Self::NfcLlcp => 245,
-            Self::Infiniband => 247,
-            Self::Sctp => 248,
-            Self::Usbpcap => 249,
-            Self::RtacSerial => 250,
-            Self::BluetoothLeLl => 251,
-            Self::Netlink => 253,
-            Self::BluetoothLinuxMonitor => 254,
-            Self::BluetoothBredrBb => 255,
-            Self::BluetoothLeLlWithPhdr => 256,
-            Self::ProfibusDl => 257,
-            Self::Pktap => 258,
-            Self::Epon => 259,
-            Self::IpmiHpm2 => 260,
-            Self::ZwaveR1R2 => 261,
-            Self::ZwaveR3 => 262,
-            Self::WattstopperDlm => 263,
-            Self::Iso14443 => 264,
-            Self::Rds => 265,
-            Self::UsbDarwin => 266,
-            Self::Sdlc => 268,
-            Self::Loratap => 270,
-            Self::Vsock => 271,
-            Self::NordicBle => 272,
-            Self::Docsis31Xra31 => 273,
-            Self::EthernetMpacket => 274,
-            Self::DisplayportAux => 275,
-            Self::LinuxSll2 => 276,
-            Self::Openvizsla => 278,
-            Self::Ebhscr => 279,
-            Self::VppDispatch => 280,
-            Self::DsaTagBrcm => 281,
-            Self::DsaTagBrcmPrepend => 282,
-            Self::Ieee802154Tap => 283,
-            Self::DsaTagDsa => 284,
-            Self::DsaTagEdsa => 285,
-            Self::Elee => 286,
-            Self::ZWaveSerial => 287,
-            Self::Usb20 => 288,
-            Self::AtscAlp => 289,
+        impl LinkType {
+            pub fn value(&self) -> u16 {
+                match self {
+                    $(Self::$name => $value,)*
+                    Self::Custom(value) => *value,
+                }
+            }
+
+            /// Returns the registered link type with the given value,
+            /// if any. See also `LinkType::from`, which falls back to
+            /// `Custom`.
+            pub fn from_value(value: u16) -> Option<Self> {
+                match value {
+                    $($value => Some(Self::$name),)*
+                    _ => None,
+                }
+            }
         }
+    };
+}
+
+impl From<u16> for LinkType {
+    fn from(value: u16) -> Self {
+        Self::from_value(value).unwrap_or(Self::Custom(value))
     }
 }
 
+link_types! {
+    Null = 0,
+    Ethernet = 1,
+    Ax25 = 3,
+    Ieee8025 = 6,
+    ArcnetBsd = 7,
+    Slip = 8,
+    Ppp = 9,
+    Fddi = 10,
+    PppHdlc = 50,
+    PppEther = 51,
+    AtmRfc1483 = 100,
+    Raw = 101,
+    CHdlc = 104,
+    Ieee80211 = 105,
+    Frelay = 107,
+    Loop = 108,
+    LinuxSll = 113,
+    Ltalk = 114,
+    Pflog = 117,
+    Ieee80211Prism = 119,
+    IpOverFc = 122,
+    Sunatm = 123,
+    Ieee80211Radiotap = 127,
+    ArcnetLinux = 129,
+    AppleIpOverIeee1394 = 138,
+    Mtp2WithPhdr = 139,
+    Mtp2 = 140,
+    Mtp3 = 141,
+    Sccp = 142,
+    Docsis = 143,
+    LinuxIrda = 144,
+    User0 = 147,
+    User1 = 148,
+    User2 = 149,
+    User3 = 150,
+    User4 = 151,
+    User5 = 152,
+    User6 = 153,
+    User7 = 154,
+    User8 = 155,
+    User9 = 156,
+    User10 = 157,
+    User11 = 158,
+    User12 = 159,
+    User13 = 160,
+    User14 = 161,
+    User15 = 162,
+    Ieee80211Avs = 163,
+    BacnetMsTp = 165,
+    PppPppd = 166,
+    GprsLlc = 169,
+    GpfT = 170,
+    GpfF = 171,
+    LinuxLapd = 177,
+    Mfr = 182,
+    BluetoothHciH4 = 187,
+    UsbLinux = 189,
+    Ppi = 192,
+    Ieee802154Withfcs = 195,
+    Sita = 196,
+    Erf = 197,
+    BluetoothHciH4WithPhdr = 201,
+    Ax25Kiss = 202,
+    Lapd = 203,
+    PppWithDir = 204,
+    CHdlcWithDir = 205,
+    FrelayWithDir = 206,
+    LapbWithDir = 207,
+    IpmbLinux = 209,
+    Flexray = 210,
+    Lin = 212,
+    Ieee802154NonaskPhy = 215,
+    UsbLinuxMmapped = 220,
+    Fc2 = 224,
+    Fc2WithFrameDelims = 225,
+    Ipnet = 226,
+    CanSocketcan = 227,
+    Ipv4 = 228,
+    Ipv6 = 229,
+    Ieee802154Nofcs = 230,
+    Dbus = 231,
+    DvbCi = 235,
+    Mux27010 = 236,
+    Stanag5066DPdu = 237,
+    Nflog = 239,
+    Netanalyzer = 240,
+    NetanalyzerTransparent = 241,
+    Ipoib = 242,
+    Mpeg2Ts = 243,
+    Ng40 = 244,
+    NfcLlcp = 245,
+    Infiniband = 247,
+    Sctp = 248,
+    Usbpcap = 249,
+    RtacSerial = 250,
+    BluetoothLeLl = 251,
+    Netlink = 253,
+    BluetoothLinuxMonitor = 254,
+    BluetoothBredrBb = 255,
+    BluetoothLeLlWithPhdr = 256,
+    ProfibusDl = 257,
+    Pktap = 258,
+    Epon = 259,
+    IpmiHpm2 = 260,
+    ZwaveR1R2 = 261,
+    ZwaveR3 = 262,
+    WattstopperDlm = 263,
+    Iso14443 = 264,
+    Rds = 265,
+    UsbDarwin = 266,
+    Sdlc = 268,
+    Loratap = 270,
+    Vsock = 271,
+    NordicBle = 272,
+    Docsis31Xra31 = 273,
+    EthernetMpacket = 274,
+    DisplayportAux = 275,
+    LinuxSll2 = 276,
+    Openvizsla = 278,
+    Ebhscr = 279,
+    VppDispatch = 280,
+    DsaTagBrcm = 281,
+    DsaTagBrcmPrepend = 282,
+    Ieee802154Tap = 283,
+    DsaTagDsa = 284,
+    DsaTagEdsa = 285,
+    Elee = 286,
+    ZWaveSerial = 287,
+    Usb20 = 288,
+    AtscAlp = 289,
+    Etw = 290,
+    NetanalyzerNg = 291,
+    ZbossNcp = 292,
+    Usb20LowSpeed = 293,
+    Usb20FullSpeed = 294,
+    Usb20HighSpeed = 295,
+    AuerswaldLog = 296,
+    ZwaveTap = 297,
+    SilabsDebugChannel = 298,
+    FiraUci = 299,
+    Mdb = 300,
+    DectNr = 301,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PacketDirection {
     Unavailable,
     Inbound,
@@ -321,8 +278,19 @@ impl PacketDirection {
             Self::Outbound => 2,
         }
     }
+
+    pub fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Unavailable),
+            1 => Some(Self::Inbound),
+            2 => Some(Self::Outbound),
+            _ => None,
+        }
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ReceptionType {
     Unspecified,
     Unicast,
@@ -341,4 +309,153 @@ impl ReceptionType {
             Self::Promiscuous => 4,
         }
     }
+
+    pub fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Unspecified),
+            1 => Some(Self::Unicast),
+            2 => Some(Self::Multicast),
+            3 => Some(Self::Broadcast),
+            4 => Some(Self::Promiscuous),
+            _ => None,
+        }
+    }
+}
+
+/// The link-layer dependent errors in the upper 16 bits of the
+/// `epb_flags` option, as specified by the pcapng standard section
+/// 4.3.1.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EpbErrorFlags(u16);
+
+impl EpbErrorFlags {
+    pub const CRC: Self = Self(1 << 8);
+    pub const PACKET_TOO_LONG: Self = Self(1 << 9);
+    pub const PACKET_TOO_SHORT: Self = Self(1 << 10);
+    pub const WRONG_INTER_FRAME_GAP: Self = Self(1 << 11);
+    pub const UNALIGNED_FRAME: Self = Self(1 << 12);
+    pub const START_FRAME_DELIMITER: Self = Self(1 << 13);
+    pub const PREAMBLE: Self = Self(1 << 14);
+    pub const SYMBOL: Self = Self(1 << 15);
+
+    /// The flags defined above, in the order of their bits.
+    const ALL: [(Self, &'static str); 8] = [
+        (Self::CRC, "CRC"),
+        (Self::PACKET_TOO_LONG, "PACKET_TOO_LONG"),
+        (Self::PACKET_TOO_SHORT, "PACKET_TOO_SHORT"),
+        (Self::WRONG_INTER_FRAME_GAP, "WRONG_INTER_FRAME_GAP"),
+        (Self::UNALIGNED_FRAME, "UNALIGNED_FRAME"),
+        (Self::START_FRAME_DELIMITER, "START_FRAME_DELIMITER"),
+        (Self::PREAMBLE, "PREAMBLE"),
+        (Self::SYMBOL, "SYMBOL"),
+    ];
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Creates flags from the upper 16 bits of `epb_flags`. Reserved
+    /// bits are kept.
+    pub const fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(&self) -> u16 {
+        self.0
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns `true` if all the flags in `other` are set.
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+
+    /// Returns the names of the defined flags that are set.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> {
+        let bits = *self;
+        Self::ALL
+            .iter()
+            .filter(move |(flag, _)| bits.contains(*flag))
+            .map(|(_, name)| *name)
+    }
+}
+
+impl BitOr for EpbErrorFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for EpbErrorFlags {
+    fn bitor_assign(&mut self, other: Self) {
+        self.insert(other);
+    }
+}
+
+impl BitAnd for EpbErrorFlags {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+impl From<u16> for EpbErrorFlags {
+    fn from(bits: u16) -> Self {
+        Self::from_bits(bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn link_type_values() {
+        assert_eq!(LinkType::Ethernet.value(), 1);
+        assert_eq!(LinkType::Ieee80211Radiotap.value(), 127);
+        assert_eq!(LinkType::LinuxSll2.value(), 276);
+        assert_eq!(LinkType::from_value(227), Some(LinkType::CanSocketcan));
+        assert_eq!(LinkType::from_value(301), Some(LinkType::DectNr));
+        assert_eq!(LinkType::from_value(2), None);
+        assert_eq!(LinkType::from(2), LinkType::Custom(2));
+        assert_eq!(LinkType::from(1), LinkType::Ethernet);
+        assert_eq!(LinkType::Custom(65000).value(), 65000);
+    }
+
+    #[test]
+    fn epb_error_flags() {
+        let mut flags = EpbErrorFlags::CRC | EpbErrorFlags::SYMBOL;
+        assert_eq!(flags.bits(), 0x8100);
+        assert!(flags.contains(EpbErrorFlags::CRC));
+        assert!(!flags.contains(EpbErrorFlags::CRC | EpbErrorFlags::PREAMBLE));
+        flags.remove(EpbErrorFlags::CRC);
+        flags |= EpbErrorFlags::PACKET_TOO_SHORT;
+        assert_eq!(
+            flags.names().collect::<Vec<_>>(),
+            ["PACKET_TOO_SHORT", "SYMBOL"]
+        );
+        assert!(EpbErrorFlags::empty().is_empty());
+        assert_eq!(EpbErrorFlags::from(0x0001).names().count(), 0);
+        for value in 0..=u16::MAX {
+            if let Some(link_type) = LinkType::from_value(value) {
+                assert_eq!(link_type.value(), value);
+            }
+        }
+    }
 }
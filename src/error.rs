@@ -0,0 +1,88 @@
+use crate::blocks::options::OptionError;
+use crate::io;
+use core::fmt;
+
+/// Errors that can occur while encoding or writing pcapng data.
+#[derive(Debug)]
+pub enum Error {
+    /// The underlying writer returned an error.
+    Io(io::Error),
+    /// An option is invalid, e.g. its value is too long.
+    Option(OptionError),
+    /// The encoded block would be longer than the 32-bit Block Total
+    /// Length field allows. Contains the actual length.
+    BlockTooLong(usize),
+    /// The timestamp cannot be represented with 64 bits at the
+    /// requested resolution.
+    TimestampOverflow,
+    /// The timestamp is earlier than the Unix epoch.
+    TimestampBeforeEpoch,
+    /// No interface with this ID has been described in the current
+    /// section.
+    UnknownInterface(u32),
+    /// The buffer passed to `encode_to_slice` is too small. Contains
+    /// the length of the buffer.
+    BufferTooSmall(usize),
+    /// The packet data is longer than the snap length of its
+    /// interface. Contains the length of the data.
+    SnapLenExceeded(usize),
+    /// The captured length of a packet block does not match the
+    /// length of its data.
+    CapLenMismatch { declared: u32, actual: usize },
+    /// A block was written before the first Section Header Block.
+    MissingSectionHeader,
+    /// The Block Total Length of a raw block is not a multiple of 4,
+    /// or does not match the size of the block or its trailing copy.
+    /// Contains the declared length.
+    InvalidBlockLength(u32),
+}
+
+/// A `Result` alias where the `Err` case is `pcapng_writer::Error`.
+pub type Result<T> = core::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+            Self::Option(e) => write!(f, "invalid option: {}", e),
+            Self::BlockTooLong(len) => write!(f, "block is too long ({} bytes)", len),
+            Self::TimestampOverflow => write!(f, "timestamp does not fit in 64 bits"),
+            Self::TimestampBeforeEpoch => write!(f, "timestamp is earlier than the Unix epoch"),
+            Self::UnknownInterface(id) => write!(f, "unknown interface ID {}", id),
+            Self::BufferTooSmall(len) => write!(f, "buffer is too small ({} bytes)", len),
+            Self::SnapLenExceeded(len) => {
+                write!(f, "packet is longer than the snap length ({} bytes)", len)
+            }
+            Self::CapLenMismatch { declared, actual } => write!(
+                f,
+                "captured length is declared as {} but the data is {} bytes long",
+                declared, actual
+            ),
+            Self::MissingSectionHeader => write!(f, "block written before a section header"),
+            Self::InvalidBlockLength(len) => write!(f, "invalid block total length {}", len),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Option(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<OptionError> for Error {
+    fn from(e: OptionError) -> Self {
+        Self::Option(e)
+    }
+}
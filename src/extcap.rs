@@ -0,0 +1,499 @@
+//! Writing Wireshark extcap programs.
+//!
+//! Wireshark runs extcap programs to list their interfaces, link
+//! types and settings, and to capture, reading pcapng from a FIFO.
+//! `Extcap` answers the listing requests and opens the FIFO, so a
+//! program only has to write packets:
+//!
+//! ```no_run
+//! use pcapng_writer::enums::LinkType;
+//! use pcapng_writer::extcap::{Extcap, ExtcapArg, ExtcapInterface};
+//! use std::time::SystemTime;
+//!
+//! let mut extcap = Extcap::new("1.0");
+//! extcap.add_interface(ExtcapInterface::new("demo", "Demo capture", LinkType::Ethernet));
+//! extcap.add_arg(ExtcapArg::new("--count", "Packet count", "integer"));
+//! extcap
+//!     .run(std::env::args().skip(1), |request, capture, interface| {
+//!         let count = request.arg("--count").unwrap_or("10").parse().unwrap_or(10);
+//!         for _ in 0..count {
+//!             capture.write_packet(interface, SystemTime::now(), &[0; 60])?;
+//!         }
+//!         Ok(())
+//!     })
+//!     .unwrap();
+//! ```
+
+use crate::blocks::options::{OptionIfDescription, OptionIfName, Options};
+use crate::capture::CaptureWriter;
+use crate::enums::LinkType;
+use crate::error::{Error, Result};
+use crate::sink::{BufferedWriter, FlushPolicy};
+use crate::utils::NANO_SECOND_TSRES;
+use crate::writer::InterfaceId;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// An interface of an extcap program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtcapInterface {
+    value: String,
+    display: String,
+    link_type: LinkType,
+}
+
+impl ExtcapInterface {
+    /// Creates an interface. `value` identifies it on the command
+    /// line, and `display` is shown in the interface list.
+    pub fn new(value: &str, display: &str, link_type: LinkType) -> Self {
+        Self {
+            value: value.to_string(),
+            display: display.to_string(),
+            link_type,
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn display(&self) -> &str {
+        &self.display
+    }
+
+    pub fn link_type(&self) -> LinkType {
+        self.link_type
+    }
+}
+
+/// A setting of an extcap program, shown by Wireshark in the
+/// interface options.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtcapArg {
+    call: String,
+    display: String,
+    arg_type: String,
+    tooltip: Option<String>,
+    default: Option<String>,
+    required: bool,
+}
+
+impl ExtcapArg {
+    /// Creates a setting passed as `call`, e.g. `--delay`. `arg_type`
+    /// is an extcap argument type such as `string`, `integer` or
+    /// `boolflag`.
+    pub fn new(call: &str, display: &str, arg_type: &str) -> Self {
+        Self {
+            call: call.to_string(),
+            display: display.to_string(),
+            arg_type: arg_type.to_string(),
+            tooltip: None,
+            default: None,
+            required: false,
+        }
+    }
+
+    pub fn set_tooltip(&mut self, tooltip: &str) {
+        self.tooltip = Some(tooltip.to_string());
+    }
+
+    pub fn set_default(&mut self, default: &str) {
+        self.default = Some(default.to_string());
+    }
+
+    pub fn set_required(&mut self, required: bool) {
+        self.required = required;
+    }
+
+    pub fn call(&self) -> &str {
+        &self.call
+    }
+
+    /// Returns true if the setting is a flag without value.
+    pub fn is_flag(&self) -> bool {
+        self.arg_type == "boolflag"
+    }
+}
+
+/// A request of Wireshark, parsed from the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExtcapRequest {
+    /// `--extcap-interfaces`
+    Interfaces,
+    /// `--extcap-dlts`
+    Dlts(String),
+    /// `--extcap-config`
+    Config(String),
+    /// `--capture`
+    Capture(CaptureRequest),
+}
+
+/// The parameters of a capture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRequest {
+    interface: String,
+    fifo: PathBuf,
+    filter: Option<String>,
+    args: Vec<(String, String)>,
+}
+
+impl CaptureRequest {
+    pub fn interface(&self) -> &str {
+        &self.interface
+    }
+
+    pub fn fifo(&self) -> &Path {
+        &self.fifo
+    }
+
+    /// Returns the capture filter entered by the user, if any.
+    pub fn filter(&self) -> Option<&str> {
+        self.filter.as_deref()
+    }
+
+    /// Returns the value of the setting passed as `call`. Flags that
+    /// are set have an empty value.
+    pub fn arg(&self, call: &str) -> Option<&str> {
+        self.args
+            .iter()
+            .find(|(c, _)| c == call)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// The sink of an extcap capture: the FIFO, flushed after every
+/// block so Wireshark shows packets as they arrive.
+pub type ExtcapSink<W = File> = BufferedWriter<W>;
+
+/// An extcap program.
+#[derive(Debug, Clone, Default)]
+pub struct Extcap {
+    version: String,
+    help: Option<String>,
+    interfaces: Vec<ExtcapInterface>,
+    args: Vec<ExtcapArg>,
+}
+
+impl Extcap {
+    /// Creates an extcap program with the given version.
+    pub fn new(version: &str) -> Self {
+        Self {
+            version: version.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Sets the help URL shown by Wireshark.
+    pub fn set_help(&mut self, help: &str) {
+        self.help = Some(help.to_string());
+    }
+
+    pub fn add_interface(&mut self, interface: ExtcapInterface) {
+        self.interfaces.push(interface);
+    }
+
+    pub fn add_arg(&mut self, arg: ExtcapArg) {
+        self.args.push(arg);
+    }
+
+    pub fn interfaces(&self) -> &[ExtcapInterface] {
+        &self.interfaces
+    }
+
+    /// Returns the interface identified by `value`.
+    pub fn interface(&self, value: &str) -> Result<&ExtcapInterface> {
+        self.interfaces
+            .iter()
+            .find(|i| i.value == value)
+            .ok_or_else(|| usage("unknown extcap interface"))
+    }
+
+    /// Parses the command line arguments, without the program name.
+    /// Settings that were not added with `add_arg` are rejected.
+    pub fn parse_args<I: IntoIterator<Item = String>>(&self, args: I) -> Result<ExtcapRequest> {
+        let mut args = args.into_iter();
+        let mut request = None;
+        let mut interface = None;
+        let mut fifo = None;
+        let mut filter = None;
+        let mut values = Vec::new();
+        while let Some(arg) = args.next() {
+            let (name, inline) = match arg.find('=') {
+                Some(i) => (arg[..i].to_string(), Some(arg[i + 1..].to_string())),
+                None => (arg, None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| usage("missing extcap argument value"))
+            };
+            match name.as_str() {
+                "--extcap-interfaces" | "--extcap-dlts" | "--extcap-config" | "--capture" => {
+                    request = Some(name)
+                }
+                "--extcap-version" => (),
+                "--extcap-interface" => interface = Some(value()?),
+                "--fifo" => fifo = Some(PathBuf::from(value()?)),
+                "--extcap-capture-filter" => filter = Some(value()?),
+                "--extcap-control-in" | "--extcap-control-out" => {
+                    value()?;
+                }
+                _ => match self.args.iter().find(|a| a.call == name) {
+                    Some(a) if a.is_flag() => values.push((name, String::new())),
+                    Some(_) => {
+                        let value = value()?;
+                        values.push((name, value));
+                    }
+                    None => return Err(usage("unknown extcap argument")),
+                },
+            }
+        }
+        let interface = || {
+            interface
+                .clone()
+                .ok_or_else(|| usage("missing --extcap-interface"))
+        };
+        match request.as_deref() {
+            Some("--extcap-interfaces") => Ok(ExtcapRequest::Interfaces),
+            Some("--extcap-dlts") => Ok(ExtcapRequest::Dlts(interface()?)),
+            Some("--extcap-config") => Ok(ExtcapRequest::Config(interface()?)),
+            Some(_) => Ok(ExtcapRequest::Capture(CaptureRequest {
+                interface: interface()?,
+                fifo: fifo.ok_or_else(|| usage("missing --fifo"))?,
+                filter,
+                args: values,
+            })),
+            None => Err(usage("missing extcap request")),
+        }
+    }
+
+    /// Writes the `--extcap-interfaces` answer.
+    pub fn write_interfaces<O: Write>(&self, out: &mut O) -> io::Result<()> {
+        write!(out, "extcap {{version={}}}", self.version)?;
+        if let Some(help) = &self.help {
+            write!(out, "{{help={}}}", help)?;
+        }
+        writeln!(out)?;
+        for interface in &self.interfaces {
+            writeln!(
+                out,
+                "interface {{value={}}}{{display={}}}",
+                interface.value, interface.display
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the `--extcap-dlts` answer for `interface`.
+    pub fn write_dlts<O: Write>(&self, out: &mut O, interface: &str) -> Result<()> {
+        let interface = self.interface(interface)?;
+        writeln!(
+            out,
+            "dlt {{number={}}}{{name={}}}{{display={}}}",
+            interface.link_type.value(),
+            interface.value,
+            interface.display
+        )?;
+        Ok(())
+    }
+
+    /// Writes the `--extcap-config` answer.
+    pub fn write_config<O: Write>(&self, out: &mut O) -> io::Result<()> {
+        for (number, arg) in self.args.iter().enumerate() {
+            write!(
+                out,
+                "arg {{number={}}}{{call={}}}{{display={}}}{{type={}}}",
+                number, arg.call, arg.display, arg.arg_type
+            )?;
+            if let Some(tooltip) = &arg.tooltip {
+                write!(out, "{{tooltip={}}}", tooltip)?;
+            }
+            if let Some(default) = &arg.default {
+                write!(out, "{{default={}}}", default)?;
+            }
+            if arg.required {
+                write!(out, "{{required=true}}")?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Starts a capture on `sink`: writes the Section Header Block
+    /// and describes the interface of `request`, with `if_name` and
+    /// `if_description` options.
+    pub fn start_capture<W: Write>(
+        &self,
+        request: &CaptureRequest,
+        sink: W,
+    ) -> Result<(CaptureWriter<ExtcapSink<W>>, InterfaceId)> {
+        let interface = self.interface(&request.interface)?;
+        let sink = BufferedWriter::new(sink, FlushPolicy::EveryBlock);
+        let mut capture = CaptureWriter::new(sink)?;
+        let name = [
+            OptionIfName::new_option(&interface.value),
+            OptionIfDescription::new_option(&interface.display),
+        ];
+        let mut options = Options::new();
+        options.add_options(&name);
+        let id = capture.add_interface_with_options(
+            interface.link_type,
+            0,
+            NANO_SECOND_TSRES,
+            &options,
+        )?;
+        capture.get_writer_mut().get_writer_mut().flush()?;
+        Ok((capture, id))
+    }
+
+    /// Handles the request of `args`, writing the answers of listing
+    /// requests to stdout. For captures, opens the FIFO and calls
+    /// `capture` to write the packets.
+    pub fn run<I, F>(&self, args: I, capture: F) -> Result<()>
+    where
+        I: IntoIterator<Item = String>,
+        F: FnOnce(&CaptureRequest, &mut CaptureWriter<ExtcapSink>, InterfaceId) -> Result<()>,
+    {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        match self.parse_args(args)? {
+            ExtcapRequest::Interfaces => self.write_interfaces(&mut out)?,
+            ExtcapRequest::Dlts(interface) => self.write_dlts(&mut out, &interface)?,
+            ExtcapRequest::Config(interface) => {
+                self.interface(&interface)?;
+                self.write_config(&mut out)?;
+            }
+            ExtcapRequest::Capture(request) => {
+                let fifo = OpenOptions::new().write(true).open(&request.fifo)?;
+                let (mut writer, id) = self.start_capture(&request, fifo)?;
+                capture(&request, &mut writer, id)?;
+                writer.close()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn usage(msg: &'static str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidInput, msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{Block, SliceReader};
+
+    fn extcap() -> Extcap {
+        let mut extcap = Extcap::new("1.0");
+        extcap.set_help("https://example.com");
+        extcap.add_interface(ExtcapInterface::new(
+            "demo",
+            "Demo capture",
+            LinkType::Custom(147),
+        ));
+        let mut count = ExtcapArg::new("--count", "Packet count", "integer");
+        count.set_default("10");
+        count.set_required(true);
+        extcap.add_arg(count);
+        extcap.add_arg(ExtcapArg::new("--verbose", "Verbose", "boolflag"));
+        extcap
+    }
+
+    fn args(s: &str) -> Vec<String> {
+        s.split(' ').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn listings() {
+        let extcap = extcap();
+        let mut out = vec![];
+        extcap.write_interfaces(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "extcap {version=1.0}{help=https://example.com}\n\
+             interface {value=demo}{display=Demo capture}\n"
+        );
+        let mut out = vec![];
+        extcap.write_dlts(&mut out, "demo").unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "dlt {number=147}{name=demo}{display=Demo capture}\n"
+        );
+        assert!(extcap.write_dlts(&mut vec![], "other").is_err());
+        let mut out = vec![];
+        extcap.write_config(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "arg {number=0}{call=--count}{display=Packet count}{type=integer}\
+             {default=10}{required=true}\n\
+             arg {number=1}{call=--verbose}{display=Verbose}{type=boolflag}\n"
+        );
+    }
+
+    #[test]
+    fn parse_args() {
+        let extcap = extcap();
+        assert_eq!(
+            extcap
+                .parse_args(args("--extcap-interfaces --extcap-version=4.2"))
+                .unwrap(),
+            ExtcapRequest::Interfaces
+        );
+        assert_eq!(
+            extcap
+                .parse_args(args("--extcap-dlts --extcap-interface demo"))
+                .unwrap(),
+            ExtcapRequest::Dlts("demo".to_string())
+        );
+        let request = extcap.parse_args(args(
+            "--capture --extcap-interface=demo --fifo /tmp/fifo --verbose --count 3 \
+             --extcap-capture-filter port",
+        ));
+        match request.unwrap() {
+            ExtcapRequest::Capture(request) => {
+                assert_eq!(request.interface(), "demo");
+                assert_eq!(request.fifo(), Path::new("/tmp/fifo"));
+                assert_eq!(request.filter(), Some("port"));
+                assert_eq!(request.arg("--count"), Some("3"));
+                assert_eq!(request.arg("--verbose"), Some(""));
+                assert_eq!(request.arg("--other"), None);
+            }
+            r => panic!("{:?}", r),
+        }
+        assert!(extcap
+            .parse_args(args("--capture --extcap-interface demo"))
+            .is_err());
+        assert!(extcap.parse_args(args("--extcap-config")).is_err());
+        assert!(extcap
+            .parse_args(args("--extcap-interfaces --bogus"))
+            .is_err());
+    }
+
+    #[test]
+    fn start_capture() {
+        let extcap = extcap();
+        let request = match extcap
+            .parse_args(args("--capture --extcap-interface demo --fifo x"))
+            .unwrap()
+        {
+            ExtcapRequest::Capture(request) => request,
+            r => panic!("{:?}", r),
+        };
+        let (capture, _) = extcap.start_capture(&request, vec![]).unwrap();
+        // the header blocks reach the sink before any packet
+        let sink = capture.get_writer().get_writer();
+        assert!(sink.buffer().is_empty());
+        let blocks: Vec<_> = SliceReader::new(sink.get_ref())
+            .map(|b| b.unwrap())
+            .collect();
+        match &blocks[1] {
+            Block::InterfaceDescription(idb) => {
+                assert_eq!(idb.link_type(), 147);
+                assert_eq!(idb.options().iter().count(), 3);
+            }
+            b => panic!("{:?}", b),
+        }
+    }
+}
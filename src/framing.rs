@@ -0,0 +1,342 @@
+use crate::constants::{BLOCK_COMMON_LEN, BYTE_ORDER_MAGIC};
+use crate::enums::BlockType;
+use crate::io;
+use crate::writer::Endianness;
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+/// Splits a pcapng byte stream into complete blocks.
+///
+/// Bytes can be pushed in chunks of any size. The byte order of each
+/// section is taken from the magic of its Section Header Block, so
+/// the stream must start with one.
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub(crate) struct BlockFramer {
+    buf: Vec<u8>,
+    endianness: Option<Endianness>,
+}
+
+/// A complete block, as found in the stream.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub(crate) struct Frame {
+    pub block_type: u32,
+    pub bytes: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl Frame {
+    pub fn is(&self, block_type: BlockType) -> bool {
+        self.block_type == block_type.value()
+    }
+}
+
+#[cfg(feature = "std")]
+impl BlockFramer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates a framer for a stream that may not start with a
+    /// Section Header Block.
+    pub fn with_endianness(endianness: Endianness) -> Self {
+        Self {
+            endianness: Some(endianness),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the byte order of the current section, once known.
+    pub fn endianness(&self) -> Option<Endianness> {
+        self.endianness
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Returns the next complete block, if enough bytes have been
+    /// pushed.
+    pub fn next_frame(&mut self) -> io::Result<Option<Frame>> {
+        if self.buf.len() < BLOCK_COMMON_LEN {
+            return Ok(None);
+        }
+        let (block_type, total_length) = parse_header(&mut self.endianness, &self.buf)?;
+        if self.buf.len() < total_length {
+            return Ok(None);
+        }
+        let rest = self.buf.split_off(total_length);
+        let bytes = std::mem::replace(&mut self.buf, rest);
+        Ok(Some(Frame { block_type, bytes }))
+    }
+
+    /// Puts back a block returned by `next_frame`, e.g. one that could
+    /// not be written, so that it is returned again.
+    pub fn unread(&mut self, frame: Frame) {
+        let rest = std::mem::replace(&mut self.buf, frame.bytes);
+        self.buf.extend_from_slice(&rest);
+    }
+}
+
+/// Finds block boundaries in a pcapng byte stream without keeping a
+/// copy of the data.
+#[derive(Debug, Default)]
+pub(crate) struct BlockScanner {
+    header: [u8; BLOCK_COMMON_LEN],
+    header_len: usize,
+    remaining: usize,
+    endianness: Option<Endianness>,
+    block_type: u32,
+    first_word: u32,
+}
+
+impl BlockScanner {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates a scanner for a stream that may not start with a
+    /// Section Header Block.
+    pub fn with_endianness(endianness: Endianness) -> Self {
+        Self {
+            endianness: Some(endianness),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the type and the first 32-bit word of the body of the
+    /// last block whose header has been scanned.
+    pub fn block(&self) -> (u32, u32) {
+        (self.block_type, self.first_word)
+    }
+
+    /// Scans the next chunk of the stream. Returns the offset in
+    /// `data` right after the last block that ends in this chunk, if
+    /// any.
+    #[cfg(feature = "std")]
+    pub fn scan(&mut self, data: &[u8]) -> io::Result<Option<usize>> {
+        let mut pos = 0;
+        let mut last_end = None;
+        while let Some(end) = self.next_boundary(&data[pos..])? {
+            pos += end;
+            last_end = Some(pos);
+        }
+        Ok(last_end)
+    }
+
+    /// Scans `data` up to the end of the first block that ends in it,
+    /// and returns the offset right after that block. Returns `None`
+    /// if all of `data` was scanned without reaching the end of a
+    /// block.
+    pub fn next_boundary(&mut self, data: &[u8]) -> io::Result<Option<usize>> {
+        let mut pos = 0;
+        while pos < data.len() {
+            if self.remaining > 0 {
+                let n = self.remaining.min(data.len() - pos);
+                pos += n;
+                self.remaining -= n;
+                if self.remaining == 0 {
+                    return Ok(Some(pos));
+                }
+                continue;
+            }
+            let n = (BLOCK_COMMON_LEN - self.header_len).min(data.len() - pos);
+            self.header[self.header_len..self.header_len + n].copy_from_slice(&data[pos..pos + n]);
+            self.header_len += n;
+            pos += n;
+            if self.header_len == BLOCK_COMMON_LEN {
+                let (block_type, total_length) = parse_header(&mut self.endianness, &self.header)?;
+                self.block_type = block_type;
+                self.first_word = match self.endianness {
+                    Some(Endianness::Big) => BigEndian::read_u32(&self.header[8..]),
+                    _ => LittleEndian::read_u32(&self.header[8..]),
+                };
+                self.header_len = 0;
+                self.remaining = total_length - BLOCK_COMMON_LEN;
+                if self.remaining == 0 {
+                    return Ok(Some(pos));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Reads the block type and total length from the first 12 bytes of
+/// a block. Section Header Blocks update `endianness`.
+pub(crate) fn parse_header(
+    endianness: &mut Option<Endianness>,
+    header: &[u8],
+) -> io::Result<(u32, usize)> {
+    let shb = BlockType::SectionHeader.value().to_be_bytes();
+    if header[..4] == shb {
+        *endianness = Some(match &header[8..12] {
+            m if BigEndian::read_u32(m) == BYTE_ORDER_MAGIC => Endianness::Big,
+            m if LittleEndian::read_u32(m) == BYTE_ORDER_MAGIC => Endianness::Little,
+            _ => return Err(invalid("invalid byte-order magic")),
+        });
+    }
+    let (block_type, total_length) = match endianness {
+        Some(Endianness::Big) => (
+            BigEndian::read_u32(&header[..4]),
+            BigEndian::read_u32(&header[4..8]),
+        ),
+        Some(Endianness::Little) => (
+            LittleEndian::read_u32(&header[..4]),
+            LittleEndian::read_u32(&header[4..8]),
+        ),
+        None => return Err(invalid("stream does not start with a Section Header Block")),
+    };
+    let total_length = total_length as usize;
+    if total_length < BLOCK_COMMON_LEN || total_length & 3 != 0 {
+        return Err(invalid("invalid block total length"));
+    }
+    Ok((block_type, total_length))
+}
+
+/// Returns the abbreviation of a block type, e.g. "EPB".
+pub(crate) fn block_abbreviation(block_type: u32) -> Option<&'static str> {
+    match block_type {
+        t if t == BlockType::SectionHeader.value() => Some("SHB"),
+        t if t == BlockType::InterfaceDescription.value() => Some("IDB"),
+        t if t == BlockType::SimplePacket.value() => Some("SPB"),
+        t if t == BlockType::NameResolution.value() => Some("NRB"),
+        t if t == BlockType::InterfaceStatistics.value() => Some("ISB"),
+        t if t == BlockType::EnhancedPacket.value() => Some("EPB"),
+        t if t == BlockType::DecryptionSecrets.value() => Some("DSB"),
+        t if t == BlockType::Custom.value() || t == BlockType::CustomNoCopy.value() => Some("CB"),
+        _ => None,
+    }
+}
+
+/// The fixed fields of the body of a block type, by name and size.
+pub(crate) fn fixed_fields(block_type: u32) -> &'static [(&'static str, usize)] {
+    match block_type {
+        t if t == BlockType::SectionHeader.value() => &[
+            ("byte_order_magic", 4),
+            ("major_version", 2),
+            ("minor_version", 2),
+            ("section_length", 8),
+        ],
+        t if t == BlockType::InterfaceDescription.value() => {
+            &[("link_type", 2), ("reserved", 2), ("snap_len", 4)]
+        }
+        t if t == BlockType::SimplePacket.value() => &[("original_len", 4)],
+        t if t == BlockType::InterfaceStatistics.value() => &[
+            ("interface_id", 4),
+            ("timestamp_high", 4),
+            ("timestamp_low", 4),
+        ],
+        t if t == BlockType::EnhancedPacket.value() => &[
+            ("interface_id", 4),
+            ("timestamp_high", 4),
+            ("timestamp_low", 4),
+            ("captured_len", 4),
+            ("original_len", 4),
+        ],
+        _ => &[],
+    }
+}
+
+/// Reads a 2, 4 or 8 byte unsigned integer.
+pub(crate) fn read(endianness: Endianness, bytes: &[u8]) -> u64 {
+    match (endianness, bytes.len()) {
+        (Endianness::Big, 2) => BigEndian::read_u16(bytes).into(),
+        (Endianness::Big, 4) => BigEndian::read_u32(bytes).into(),
+        (Endianness::Big, _) => BigEndian::read_u64(bytes),
+        (Endianness::Little, 2) => LittleEndian::read_u16(bytes).into(),
+        (Endianness::Little, 4) => LittleEndian::read_u32(bytes).into(),
+        (Endianness::Little, _) => LittleEndian::read_u64(bytes),
+    }
+}
+
+pub(crate) fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Returns the types of the complete blocks of `data`, for tests.
+#[cfg(all(test, feature = "std"))]
+pub(crate) fn block_types(data: &[u8]) -> Vec<u32> {
+    let mut framer = BlockFramer::new();
+    framer.push(data);
+    let mut types = vec![];
+    while let Some(frame) = framer.next_frame().unwrap() {
+        types.push(frame.block_type);
+    }
+    types
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::blocks::options::Options;
+    use crate::blocks::{EnhancedPacketBlock, SectionHeaderBlock};
+    use crate::writer::PcapNgWriter;
+
+    #[test]
+    fn split_blocks() {
+        for endianness in [Endianness::Big, Endianness::Little] {
+            let opts = Options::new();
+            let mut buf = vec![];
+            let mut writer = PcapNgWriter::new(endianness, &mut buf);
+            writer
+                .write(&SectionHeaderBlock::new_with_defaults(&opts))
+                .unwrap();
+            writer
+                .write(&EnhancedPacketBlock::new(0, 0, 0, 5, 5, &[1; 5], &opts))
+                .unwrap();
+
+            let mut framer = BlockFramer::new();
+            let mut frames = vec![];
+            // feed the stream one byte at a time
+            drop(writer);
+            for b in &buf {
+                framer.push(&[*b]);
+                while let Some(frame) = framer.next_frame().unwrap() {
+                    frames.push(frame);
+                }
+            }
+            assert!(framer.buf.is_empty());
+            assert_eq!(frames.len(), 2);
+            assert!(frames[0].is(BlockType::SectionHeader));
+            assert_eq!(frames[0].bytes.len(), 28);
+            assert!(frames[1].is(BlockType::EnhancedPacket));
+            assert_eq!(frames[1].bytes, &buf[28..]);
+        }
+    }
+
+    #[test]
+    fn scan_boundaries() {
+        let opts = Options::new();
+        let mut buf = vec![];
+        let mut writer = PcapNgWriter::new_be(&mut buf);
+        writer
+            .write(&SectionHeaderBlock::new_with_defaults(&opts))
+            .unwrap();
+        writer
+            .write(&EnhancedPacketBlock::new(0, 0, 0, 5, 5, &[1; 5], &opts))
+            .unwrap();
+
+        let mut scanner = BlockScanner::new();
+        drop(writer);
+        assert_eq!(scanner.scan(&buf[..10]).unwrap(), None);
+        assert_eq!(scanner.scan(&buf[10..30]).unwrap(), Some(18));
+        assert_eq!(scanner.scan(&buf[30..]).unwrap(), Some(buf.len() - 30));
+        let mut scanner = BlockScanner::new();
+        assert_eq!(scanner.scan(&buf).unwrap(), Some(buf.len()));
+    }
+
+    #[test]
+    fn missing_shb() {
+        let opts = Options::new();
+        let mut buf = vec![];
+        PcapNgWriter::new_le(&mut buf)
+            .write(&EnhancedPacketBlock::new(0, 0, 0, 5, 5, &[1; 5], &opts))
+            .unwrap();
+        let mut framer = BlockFramer::new();
+        framer.push(&buf);
+        assert!(framer.next_frame().is_err());
+    }
+}
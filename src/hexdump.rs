@@ -0,0 +1,474 @@
+//! Annotated hex dumps of encoded blocks.
+//!
+//! `Hexdump` displays pcapng data as a hex dump, with the offset and
+//! the name of each field, option codes and lengths, and padding
+//! marked, e.g. to find out why a parser rejects the output:
+//!
+//! ```text
+//! EPB (block 1, 36 bytes)
+//!   00000000  06 00 00 00                                      block_type = 0x00000006
+//!   00000004  24 00 00 00                                      block_total_length = 36
+//!   00000008  00 00 00 00                                      interface_id = 0
+//!   ...
+//! ```
+//!
+//! `dump` encodes a single block and returns its dump:
+//!
+//! ```
+//! use pcapng_writer::blocks::options::Options;
+//! use pcapng_writer::blocks::EnhancedPacketBlock;
+//! use pcapng_writer::hexdump;
+//! use pcapng_writer::writer::Endianness;
+//!
+//! let options = Options::new();
+//! let epb = EnhancedPacketBlock::new(0, 0, 0, 3, 3, &[1, 2, 3], &options);
+//! let dump = hexdump::dump(&epb, Endianness::Little)?;
+//! assert!(dump.contains("packet_data (3 bytes)"));
+//! # Ok::<(), pcapng_writer::error::Error>(())
+//! ```
+
+use crate::constants::BLOCK_COMMON_LEN;
+use crate::enums::BlockType;
+use crate::error::Result;
+use crate::framing::{block_abbreviation, fixed_fields, parse_header, read};
+use crate::utils::pad_to_32;
+use crate::writer::{Encodable, Endianness};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use byteorder::{BigEndian, LittleEndian};
+use core::fmt;
+
+/// The number of bytes on a line of the dump.
+const BYTES_PER_LINE: usize = 16;
+
+/// Displays pcapng data as an annotated hex dump, block by block.
+///
+/// The data is dumped as far as it can be parsed. Malformed data, e.g.
+/// a truncated block or an option longer than the block, is reported
+/// and the rest of it is dumped without annotations.
+#[derive(Debug, Clone, Copy)]
+pub struct Hexdump<'a> {
+    data: &'a [u8],
+    endianness: Option<Endianness>,
+}
+
+impl<'a> Hexdump<'a> {
+    /// Creates a dump of a stream that starts with a Section Header
+    /// Block.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            endianness: None,
+        }
+    }
+
+    /// Creates a dump of blocks in the given byte order, e.g. a block
+    /// that is not a Section Header Block.
+    pub fn with_endianness(endianness: Endianness, data: &'a [u8]) -> Self {
+        Self {
+            data,
+            endianness: Some(endianness),
+        }
+    }
+}
+
+/// Encodes `block` and returns its annotated hex dump.
+pub fn dump<T: Encodable<Vec<u8>>>(block: &T, endianness: Endianness) -> Result<String> {
+    let mut buf = Vec::new();
+    match endianness {
+        Endianness::Big => block.encode::<BigEndian>(&mut buf)?,
+        Endianness::Little => block.encode::<LittleEndian>(&mut buf)?,
+    }
+    Ok(Hexdump::with_endianness(endianness, &buf).to_string())
+}
+
+impl fmt::Display for Hexdump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut endianness = self.endianness;
+        let mut offset = 0;
+        let mut index = 0;
+        while offset < self.data.len() {
+            let data = &self.data[offset..];
+            if index > 0 {
+                writeln!(f)?;
+            }
+            index += 1;
+            let mut lines = Lines {
+                f: &mut *f,
+                data,
+                offset,
+                pos: 0,
+            };
+            if data.len() < BLOCK_COMMON_LEN {
+                writeln!(lines.f, "{} trailing bytes", data.len())?;
+                return lines.rest("");
+            }
+            let (block_type, total_length) = match parse_header(&mut endianness, data) {
+                Ok(header) => header,
+                Err(e) => {
+                    writeln!(lines.f, "block {}: {}", index, e)?;
+                    return lines.rest("");
+                }
+            };
+            if data.len() < total_length {
+                writeln!(
+                    lines.f,
+                    "block {}: truncated, total_length {} with {} bytes left",
+                    index,
+                    total_length,
+                    data.len()
+                )?;
+                return lines.rest("");
+            }
+            match block_abbreviation(block_type) {
+                Some(name) => writeln!(
+                    lines.f,
+                    "{} (block {}, {} bytes)",
+                    name, index, total_length
+                )?,
+                None => writeln!(
+                    lines.f,
+                    "block type {:#010x} (block {}, {} bytes)",
+                    block_type, index, total_length
+                )?,
+            }
+            lines.data = &data[..total_length];
+            // set by parse_header
+            dump_block(&mut lines, endianness.unwrap(), block_type)?;
+            offset += total_length;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the lines of the dump of a block, or of the rest of the data.
+struct Lines<'a, 'b> {
+    f: &'a mut fmt::Formatter<'b>,
+    data: &'a [u8],
+    /// The offset of `data` in the dumped data.
+    offset: usize,
+    /// The position of the next field in `data`.
+    pos: usize,
+}
+
+impl Lines<'_, '_> {
+    /// Writes the next `len` bytes, with `label` on the first line.
+    fn field(&mut self, len: usize, label: fmt::Arguments<'_>) -> fmt::Result {
+        let end = self.pos + len;
+        for (i, chunk) in self.data[self.pos..end].chunks(BYTES_PER_LINE).enumerate() {
+            write!(
+                self.f,
+                "  {:08x} ",
+                self.offset + self.pos + i * BYTES_PER_LINE
+            )?;
+            for b in chunk {
+                write!(self.f, " {:02x}", b)?;
+            }
+            if i == 0 {
+                let indent = 3 * (BYTES_PER_LINE - chunk.len()) + 2;
+                write!(self.f, "{:indent$}{}", "", label, indent = indent)?;
+            }
+            writeln!(self.f)?;
+        }
+        self.pos = end;
+        Ok(())
+    }
+
+    /// Writes the next `len` bytes as an integer field, and returns
+    /// its value.
+    fn int(
+        &mut self,
+        endianness: Endianness,
+        len: usize,
+        name: &str,
+    ) -> core::result::Result<u64, fmt::Error> {
+        let value = read(endianness, &self.data[self.pos..self.pos + len]);
+        match name {
+            "block_type" | "byte_order_magic" | "secrets_type" => {
+                self.field(len, format_args!("{} = {:#010x}", name, value))?
+            }
+            // -1 if the length is not specified
+            "section_length" => self.field(len, format_args!("{} = {}", name, value as i64))?,
+            _ => self.field(len, format_args!("{} = {}", name, value))?,
+        }
+        Ok(value)
+    }
+
+    /// Writes the next `len` bytes, followed by the padding to 32 bits
+    /// if it fits in `end`.
+    fn padded(&mut self, len: usize, end: usize, label: fmt::Arguments<'_>) -> fmt::Result {
+        if len > 0 {
+            self.field(len, label)?;
+        }
+        let padding = pad_to_32(len).min(end - self.pos);
+        if padding > 0 {
+            self.field(padding, format_args!("padding"))?;
+        }
+        Ok(())
+    }
+
+    /// Writes the rest of the data.
+    fn rest(&mut self, label: &str) -> fmt::Result {
+        if self.pos < self.data.len() {
+            self.field(self.data.len() - self.pos, format_args!("{}", label))?;
+        }
+        Ok(())
+    }
+
+    /// Writes the rest of the body of a block, up to `end`.
+    fn rest_of_body(&mut self, end: usize, label: &str) -> fmt::Result {
+        let len = self.remaining(end);
+        if len > 0 {
+            self.field(len, format_args!("{} ({} bytes)", label, len))?;
+        }
+        Ok(())
+    }
+
+    fn remaining(&self, end: usize) -> usize {
+        end - self.pos
+    }
+}
+
+fn dump_block(lines: &mut Lines<'_, '_>, endianness: Endianness, block_type: u32) -> fmt::Result {
+    let end = lines.data.len() - 4;
+    lines.int(endianness, 4, "block_type")?;
+    lines.int(endianness, 4, "block_total_length")?;
+
+    let mut captured_len = None;
+    for &(name, size) in fixed_fields(block_type) {
+        if lines.remaining(end) < size {
+            break;
+        }
+        let value = lines.int(endianness, size, name)?;
+        if name == "captured_len" {
+            captured_len = Some(value as usize);
+        }
+    }
+    let has_options = match block_type {
+        t if t == BlockType::EnhancedPacket.value() => {
+            let len = captured_len.unwrap_or(0).min(lines.remaining(end));
+            lines.padded(len, end, format_args!("packet_data ({} bytes)", len))?;
+            true
+        }
+        t if t == BlockType::SimplePacket.value() => {
+            lines.rest_of_body(end, "packet_data")?;
+            false
+        }
+        t if t == BlockType::NameResolution.value() => {
+            dump_records(lines, endianness, end)?;
+            true
+        }
+        t if t == BlockType::DecryptionSecrets.value() => {
+            if lines.remaining(end) >= 8 {
+                lines.int(endianness, 4, "secrets_type")?;
+                let len = lines.int(endianness, 4, "secrets_length")? as usize;
+                let len = len.min(lines.remaining(end));
+                lines.padded(len, end, format_args!("secrets_data ({} bytes)", len))?;
+            }
+            true
+        }
+        t if t == BlockType::Custom.value() || t == BlockType::CustomNoCopy.value() => {
+            if lines.remaining(end) >= 4 {
+                lines.int(endianness, 4, "private_enterprise_number")?;
+            }
+            lines.rest_of_body(end, "custom_data")?;
+            false
+        }
+        t if fixed_fields(t).is_empty() => {
+            lines.rest_of_body(end, "body")?;
+            false
+        }
+        _ => true,
+    };
+    if has_options {
+        dump_options(lines, endianness, block_type, end)?;
+    }
+    lines.rest_of_body(end, "unparsed")?;
+    lines.int(endianness, 4, "block_total_length")?;
+    Ok(())
+}
+
+/// Writes the records of a Name Resolution Block, up to the end
+/// record.
+fn dump_records(lines: &mut Lines<'_, '_>, endianness: Endianness, end: usize) -> fmt::Result {
+    while lines.remaining(end) >= 4 {
+        let record_type = read(endianness, &lines.data[lines.pos..lines.pos + 2]);
+        let len = read(endianness, &lines.data[lines.pos + 2..lines.pos + 4]) as usize;
+        let name = match record_type {
+            0 => "nrb_record_end",
+            1 => "nrb_record_ipv4",
+            2 => "nrb_record_ipv6",
+            3 => "nrb_record_eui48",
+            4 => "nrb_record_eui64",
+            _ => "record",
+        };
+        lines.field(
+            4,
+            format_args!("{} (type {}), length {}", name, record_type, len),
+        )?;
+        if record_type == 0 {
+            break;
+        }
+        if len > lines.remaining(end) {
+            return Ok(());
+        }
+        lines.padded(len, end, format_args!("{} value", name))?;
+    }
+    Ok(())
+}
+
+/// Writes the options of a block, up to the end of options.
+fn dump_options(
+    lines: &mut Lines<'_, '_>,
+    endianness: Endianness,
+    block_type: u32,
+    end: usize,
+) -> fmt::Result {
+    while lines.remaining(end) >= 4 {
+        let code = read(endianness, &lines.data[lines.pos..lines.pos + 2]) as u16;
+        let len = read(endianness, &lines.data[lines.pos + 2..lines.pos + 4]) as usize;
+        let name = option_name(block_type, code);
+        lines.field(
+            4,
+            format_args!("option {} (code {}), length {}", name, code, len),
+        )?;
+        if code == 0 {
+            break;
+        }
+        if len > lines.remaining(end) {
+            return Ok(());
+        }
+        let value = &lines.data[lines.pos..lines.pos + len];
+        match core::str::from_utf8(value) {
+            Ok(s) if !s.is_empty() && !s.chars().any(char::is_control) => {
+                lines.padded(len, end, format_args!("{} = {:?}", name, s))?
+            }
+            _ => lines.padded(len, end, format_args!("{} value", name))?,
+        }
+    }
+    Ok(())
+}
+
+/// Returns the name of an option in the pcapng specification.
+fn option_name(block_type: u32, code: u16) -> &'static str {
+    let names: &[&str] = match block_type {
+        t if t == BlockType::SectionHeader.value() => &["shb_hardware", "shb_os", "shb_userappl"],
+        t if t == BlockType::InterfaceDescription.value() => &[
+            "if_name",
+            "if_description",
+            "if_IPv4addr",
+            "if_IPv6addr",
+            "if_MACaddr",
+            "if_EUIaddr",
+            "if_speed",
+            "if_tsresol",
+            "if_tzone",
+            "if_filter",
+            "if_os",
+            "if_fcslen",
+            "if_tsoffset",
+            "if_hardware",
+            "if_txspeed",
+            "if_rxspeed",
+        ],
+        t if t == BlockType::EnhancedPacket.value() => &[
+            "epb_flags",
+            "epb_hash",
+            "epb_dropcount",
+            "epb_packetid",
+            "epb_queue",
+            "epb_verdict",
+        ],
+        t if t == BlockType::NameResolution.value() => {
+            &["ns_dnsname", "ns_dnsIP4addr", "ns_dnsIP6addr"]
+        }
+        t if t == BlockType::InterfaceStatistics.value() => &[
+            "isb_starttime",
+            "isb_endtime",
+            "isb_ifrecv",
+            "isb_ifdrop",
+            "isb_filteraccept",
+            "isb_osdrop",
+            "isb_usrdeliv",
+        ],
+        _ => &[],
+    };
+    match code {
+        0 => "opt_endofopt",
+        1 => "opt_comment",
+        2988 | 2989 | 19372 | 19373 => "opt_custom",
+        _ => names
+            .get(usize::from(code) - 2)
+            .copied()
+            .unwrap_or("unknown"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::{OptionComment, OptionEpbQueue, Options};
+    use crate::blocks::{EnhancedPacketBlock, SectionHeaderBlock};
+    use crate::writer::PcapNgWriter;
+    use alloc::format;
+    use alloc::vec;
+
+    #[test]
+    fn epb() {
+        let comment = OptionComment::new_option("hello");
+        let mut options = Options::new();
+        options.add_option(&comment);
+        let epb = EnhancedPacketBlock::new(1, 0, 2, 5, 60, &[0xaa; 5], &options);
+        let dump = dump(&epb, Endianness::Big).unwrap();
+        let expected = "\
+EPB (block 1, 52 bytes)
+  00000000  00 00 00 06                                      block_type = 0x00000006
+  00000004  00 00 00 34                                      block_total_length = 52
+  00000008  00 00 00 01                                      interface_id = 1
+  0000000c  00 00 00 00                                      timestamp_high = 0
+  00000010  00 00 00 02                                      timestamp_low = 2
+  00000014  00 00 00 05                                      captured_len = 5
+  00000018  00 00 00 3c                                      original_len = 60
+  0000001c  aa aa aa aa aa                                   packet_data (5 bytes)
+  00000021  00 00 00                                         padding
+  00000024  00 01 00 05                                      option opt_comment (code 1), length 5
+  00000028  68 65 6c 6c 6f                                   opt_comment = \"hello\"
+  0000002d  00 00 00                                         padding
+  00000030  00 00 00 34                                      block_total_length = 52
+";
+        assert_eq!(dump, expected);
+    }
+
+    #[test]
+    fn stream() {
+        let queue = OptionEpbQueue::new_option(1);
+        let mut options = Options::new();
+        options.add_option(&queue);
+        let mut writer = PcapNgWriter::new_le(vec![]);
+        writer
+            .write(&SectionHeaderBlock::new_with_defaults(&Options::new()))
+            .unwrap();
+        writer
+            .write(&EnhancedPacketBlock::new(
+                0, 0, 0, 20, 20, &[1; 20], &options,
+            ))
+            .unwrap();
+        let data = writer.get_writer();
+        let dump = format!("{}", Hexdump::new(data));
+        assert!(dump.starts_with("SHB (block 1, 28 bytes)\n"));
+        assert!(dump.contains("byte_order_magic = 0x1a2b3c4d"));
+        assert!(dump.contains("section_length = -1"));
+        assert!(dump.contains("\n\nEPB (block 2, 60 bytes)\n"));
+        assert!(dump.contains(
+            "  00000038  01 01 01 01 01 01 01 01 01 01 01 01 01 01 01 01  packet_data (20 bytes)\n  \
+             00000048  01 01 01 01\n"
+        ));
+        assert!(dump.contains("option epb_queue (code 6), length 4"));
+        assert!(dump.contains("epb_queue value"));
+
+        let dump = format!("{}", Hexdump::new(&data[..40]));
+        assert!(dump.contains("block 2: truncated, total_length 60 with 12 bytes left"));
+        let dump = format!("{}", Hexdump::new(&data[28..]));
+        assert!(dump.starts_with("block 1: stream does not start with a Section Header Block\n"));
+    }
+}
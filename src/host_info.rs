@@ -0,0 +1,210 @@
+//! Describing the machine that writes a capture.
+//!
+//! `HostInfo::current`, or `SectionHeaderBlock::host_info`, returns
+//! the `shb_os` and `shb_hardware` values dumpcap writes for the
+//! current machine: the name and release of the operating system
+//! from `uname`, e.g. "Linux 6.1.0-13-amd64", and the model of the
+//! CPU, e.g. "Intel(R) Xeon(R) CPU E5-2680 v4 @ 2.40GHz (with
+//! SSE4.2)", or its architecture if the model is unknown:
+//!
+//! ```
+//! use pcapng_writer::blocks::options::Options;
+//! use pcapng_writer::blocks::SectionHeaderBlock;
+//! use pcapng_writer::writer::{Endianness, PcapNgWriter};
+//!
+//! let shb_options = SectionHeaderBlock::host_info().shb_options();
+//! let mut options = Options::new();
+//! options.add_options(&shb_options);
+//! let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+//! writer.start_new_section(&options)?;
+//! # Ok::<(), pcapng_writer::error::Error>(())
+//! ```
+
+use crate::blocks::options::{BlockOption, OptionShbHardware, OptionShbOs};
+
+/// The operating system and hardware of a machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostInfo {
+    os: String,
+    hardware: String,
+}
+
+impl HostInfo {
+    pub fn new(os: &str, hardware: &str) -> Self {
+        Self {
+            os: os.to_string(),
+            hardware: hardware.to_string(),
+        }
+    }
+
+    /// Describes the current machine. Falls back to the target
+    /// operating system and architecture of the crate for what can't
+    /// be read.
+    pub fn current() -> Self {
+        let uname = sys::uname();
+        let os = match &uname {
+            Some((sysname, release, _)) => format!("{} {}", sysname, release),
+            None => std::env::consts::OS.to_string(),
+        };
+        let hardware = match sys::cpu_model() {
+            Some(model) => format!("{}{}", model.trim(), sse42()),
+            None => match uname {
+                Some((_, _, machine)) => machine,
+                None => std::env::consts::ARCH.to_string(),
+            },
+        };
+        Self { os, hardware }
+    }
+
+    pub fn os(&self) -> &str {
+        &self.os
+    }
+
+    pub fn hardware(&self) -> &str {
+        &self.hardware
+    }
+
+    /// Returns the `shb_os` and `shb_hardware` options.
+    pub fn shb_options(&self) -> Vec<BlockOption> {
+        vec![
+            OptionShbHardware::new_option(&self.hardware),
+            OptionShbOs::new_option(&self.os),
+        ]
+    }
+}
+
+/// The suffix dumpcap adds to the model of x86 CPUs with SSE 4.2.
+fn sse42() -> &'static str {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("sse4.2") {
+            return " (with SSE4.2)";
+        }
+    }
+    ""
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::CStr;
+    use std::mem::MaybeUninit;
+    use std::os::raw::c_char;
+
+    /// Returns the sysname, release and machine fields of `uname`.
+    pub fn uname() -> Option<(String, String, String)> {
+        let mut uts = MaybeUninit::<libc::utsname>::uninit();
+        // SAFETY: uname fills uts on success.
+        if unsafe { libc::uname(uts.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        // SAFETY: checked above.
+        let uts = unsafe { uts.assume_init() };
+        let field = |f: &[c_char]| {
+            // SAFETY: the fields are zero-terminated.
+            unsafe { CStr::from_ptr(f.as_ptr()) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        Some((
+            field(&uts.sysname),
+            field(&uts.release),
+            field(&uts.machine),
+        ))
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn cpu_model() -> Option<String> {
+        let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").ok()?;
+        cpuinfo.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            (key.trim() == "model name").then(|| value.trim().to_string())
+        })
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub fn cpu_model() -> Option<String> {
+        let name = b"machdep.cpu.brand_string\0";
+        let mut buf = [0u8; 256];
+        let mut len = buf.len();
+        // SAFETY: buf holds len bytes.
+        let result = unsafe {
+            libc::sysctlbyname(
+                name.as_ptr() as *const c_char,
+                buf.as_mut_ptr() as *mut libc::c_void,
+                &mut len,
+                std::ptr::null_mut(),
+                0,
+            )
+        };
+        if result != 0 {
+            return None;
+        }
+        let model = CStr::from_bytes_until_nul(&buf[..len]).ok()?;
+        Some(model.to_string_lossy().into_owned())
+    }
+
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios"
+    )))]
+    pub fn cpu_model() -> Option<String> {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    pub fn uname() -> Option<(String, String, String)> {
+        None
+    }
+
+    pub fn cpu_model() -> Option<String> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::Options;
+    use crate::blocks::SectionHeaderBlock;
+    use crate::reader::{Block, SliceReader};
+    use crate::writer::{Endianness, PcapNgWriter};
+
+    #[test]
+    fn shb_options() {
+        let host = HostInfo::new("Linux 6.1.0", "x86_64");
+        let shb_options = host.shb_options();
+        let mut options = Options::new();
+        options.add_options(&shb_options);
+        let mut writer = PcapNgWriter::new(Endianness::Big, vec![]);
+        writer
+            .write(&SectionHeaderBlock::new_with_defaults(&options))
+            .unwrap();
+        let block = SliceReader::new(writer.get_writer()).next().unwrap();
+        let shb = match block.unwrap() {
+            Block::SectionHeader(shb) => shb,
+            _ => panic!("not a section header"),
+        };
+        let values: Vec<_> = shb
+            .options()
+            .iter()
+            .map(|o| match o {
+                BlockOption::ShbHardware(o) => o.hardware().to_string(),
+                BlockOption::ShbOs(o) => o.os().to_string(),
+                _ => String::new(),
+            })
+            .collect();
+        assert_eq!(values, ["x86_64", "Linux 6.1.0"]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn current() {
+        let host = HostInfo::current();
+        assert!(host.os().starts_with("Linux "));
+        assert!(!host.hardware().is_empty());
+    }
+}
@@ -0,0 +1,289 @@
+//! Seek indexes of captures.
+//!
+//! `SeekIndexer` records the file offset of every n-th packet while a
+//! capture is written, and writes the entries as Custom Blocks of the
+//! organization with the given Private Enterprise Number.
+//! `read_index` collects them again, reading only the headers of the
+//! other blocks, so that a reader can seek close to a given time in a
+//! large capture instead of reading it from the start:
+//!
+//! ```
+//! use pcapng_writer::blocks::options::Options;
+//! use pcapng_writer::enums::LinkType;
+//! use pcapng_writer::index::{read_index, SeekIndexer};
+//! use pcapng_writer::writer::{Endianness, PcapNgWriter};
+//! use std::io::Cursor;
+//!
+//! let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+//! let eth = writer.add_interface(LinkType::Ethernet, 0, &Options::new()).unwrap();
+//! let mut indexer = SeekIndexer::new(32473);
+//! indexer.set_interval(100);
+//! for i in 0..1000 {
+//!     indexer.write_packet(&mut writer, eth, i * 1_000, &[0; 60]).unwrap();
+//! }
+//! indexer.flush(&mut writer).unwrap();
+//!
+//! let index = read_index(Cursor::new(writer.get_writer()), 32473).unwrap();
+//! assert_eq!(index.len(), 10);
+//! assert_eq!(index[1].timestamp(), 100_000);
+//! ```
+//!
+//! Offsets are counted from the first byte written by the
+//! `PcapNgWriter`, so the writer must start at the beginning of the
+//! file.
+
+use crate::blocks::options::Options;
+use crate::blocks::CustomBlock;
+use crate::constants::BLOCK_COMMON_LEN;
+use crate::enums::BlockType;
+use crate::error::Result;
+use crate::framing::{invalid, parse_header};
+use crate::writer::{Endianness, InterfaceId, PcapNgWriter};
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use std::convert::TryFrom;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// Length of an encoded entry: timestamp, interface ID, reserved
+/// word and offset.
+const ENTRY_LEN: usize = 24;
+
+/// The position of a packet in a capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexEntry {
+    timestamp: u64,
+    interface_id: u32,
+    offset: u64,
+}
+
+impl IndexEntry {
+    pub fn new(timestamp: u64, interface_id: u32, offset: u64) -> Self {
+        Self {
+            timestamp,
+            interface_id,
+            offset,
+        }
+    }
+
+    /// Returns the timestamp of the packet, in nanoseconds since the
+    /// Unix epoch.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn interface_id(&self) -> u32 {
+        self.interface_id
+    }
+
+    /// Returns the offset of the block of the packet in the file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+/// Records the offsets of packets and writes them as Custom Blocks.
+#[derive(Debug, Clone)]
+pub struct SeekIndexer {
+    pen: u32,
+    interval: u64,
+    block_entries: usize,
+    packets: u64,
+    pending: Vec<IndexEntry>,
+}
+
+impl SeekIndexer {
+    /// Creates an indexer writing blocks under the Private Enterprise
+    /// Number `pen`. By default, every 1000th packet is indexed and a
+    /// block is written every 64 entries.
+    pub fn new(pen: u32) -> Self {
+        Self {
+            pen,
+            interval: 1000,
+            block_entries: 64,
+            packets: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn pen(&self) -> u32 {
+        self.pen
+    }
+
+    /// Sets the number of packets between two entries.
+    pub fn set_interval(&mut self, packets: u64) -> &mut Self {
+        self.interval = packets.max(1);
+        self
+    }
+
+    /// Sets the number of entries written per block.
+    pub fn set_block_entries(&mut self, entries: usize) -> &mut Self {
+        self.block_entries = entries.max(1);
+        self
+    }
+
+    /// Returns the entries that have not been written yet.
+    pub fn pending(&self) -> &[IndexEntry] {
+        &self.pending
+    }
+
+    /// Counts a packet about to be written to `writer`, and records
+    /// the offset of its block if it is due for an entry.
+    pub fn record<W: Write>(
+        &mut self,
+        writer: &PcapNgWriter<W>,
+        interface_id: InterfaceId,
+        timestamp_nanos: u128,
+    ) {
+        if self.packets.is_multiple_of(self.interval) {
+            self.pending.push(IndexEntry::new(
+                u64::try_from(timestamp_nanos).unwrap_or(u64::MAX),
+                interface_id.value(),
+                writer.stats().bytes(),
+            ));
+        }
+        self.packets += 1;
+    }
+
+    /// Writes a packet with `PcapNgWriter::write_packet`, recording
+    /// it, and writes an index block once enough entries are pending.
+    pub fn write_packet<W: Write>(
+        &mut self,
+        writer: &mut PcapNgWriter<W>,
+        interface_id: InterfaceId,
+        timestamp_nanos: u128,
+        data: &[u8],
+    ) -> Result<()> {
+        self.record(writer, interface_id, timestamp_nanos);
+        writer.write_packet(interface_id, timestamp_nanos, data)?;
+        if self.pending.len() >= self.block_entries {
+            self.flush(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the pending entries as a Custom Block. The block is not
+    /// copyable, as its offsets only hold in this file. Call it before
+    /// closing the writer so the last entries are not lost.
+    pub fn flush<W: Write>(&mut self, writer: &mut PcapNgWriter<W>) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let data = match writer.endianness() {
+            Endianness::Big => encode_entries::<BigEndian>(&self.pending),
+            Endianness::Little => encode_entries::<LittleEndian>(&self.pending),
+        };
+        let options = Options::new();
+        writer.write(&CustomBlock::new(false, self.pen, &data, &options))?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+/// Encodes the number of entries followed by the entries.
+fn encode_entries<B: ByteOrder>(entries: &[IndexEntry]) -> Vec<u8> {
+    let mut data = vec![0; 4 + entries.len() * ENTRY_LEN];
+    B::write_u32(&mut data, entries.len() as u32);
+    for (entry, buf) in entries.iter().zip(data[4..].chunks_exact_mut(ENTRY_LEN)) {
+        B::write_u64(buf, entry.timestamp);
+        B::write_u32(&mut buf[8..], entry.interface_id);
+        B::write_u64(&mut buf[16..], entry.offset);
+    }
+    data
+}
+
+fn decode_entries<B: ByteOrder>(data: &[u8], entries: &mut Vec<IndexEntry>) -> io::Result<()> {
+    let count = B::read_u32(data) as usize;
+    let data = data[4..]
+        .get(..count.saturating_mul(ENTRY_LEN))
+        .ok_or_else(|| invalid("truncated index block"))?;
+    entries.extend(data.chunks_exact(ENTRY_LEN).map(|buf| {
+        IndexEntry::new(
+            B::read_u64(buf),
+            B::read_u32(&buf[8..]),
+            B::read_u64(&buf[16..]),
+        )
+    }));
+    Ok(())
+}
+
+/// Reads the entries of the index blocks written under `pen` in a
+/// capture, in the order they were written. The bodies of the other
+/// blocks are skipped.
+pub fn read_index<R: Read + Seek>(mut reader: R, pen: u32) -> Result<Vec<IndexEntry>> {
+    let mut entries = Vec::new();
+    let mut endianness = None;
+    let mut header = [0u8; BLOCK_COMMON_LEN];
+    loop {
+        match reader.read_exact(&mut header) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+        let (block_type, total_length) = parse_header(&mut endianness, &header)?;
+        let rest = total_length - BLOCK_COMMON_LEN;
+        let is_custom = block_type == BlockType::Custom.value()
+            || block_type == BlockType::CustomNoCopy.value();
+        // the first word of the body of Custom Blocks is the PEN
+        let block_pen = match endianness {
+            Some(Endianness::Big) => BigEndian::read_u32(&header[8..]),
+            _ => LittleEndian::read_u32(&header[8..]),
+        };
+        if !is_custom || block_pen != pen {
+            reader.seek(SeekFrom::Current(rest as i64))?;
+            continue;
+        }
+        let mut body = vec![0; rest];
+        reader.read_exact(&mut body)?;
+        if body.len() < 8 {
+            return Err(invalid("truncated index block").into());
+        }
+        // without the trailing Block Total Length
+        let body = &body[..rest - 4];
+        match endianness {
+            Some(Endianness::Big) => decode_entries::<BigEndian>(body, &mut entries)?,
+            _ => decode_entries::<LittleEndian>(body, &mut entries)?,
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enums::LinkType;
+    use crate::reader::{Block, SliceReader};
+    use std::io::Cursor;
+
+    #[test]
+    fn offsets() {
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Big, vec![]);
+        let eth = writer
+            .add_interface(LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        let mut indexer = SeekIndexer::new(7);
+        indexer.set_interval(2).set_block_entries(2);
+        for i in 0..5 {
+            indexer
+                .write_packet(&mut writer, eth, i * 1_000, &[i as u8; 10])
+                .unwrap();
+        }
+        assert_eq!(indexer.pending().len(), 1);
+        indexer.flush(&mut writer).unwrap();
+        let data = writer.get_writer().clone();
+
+        let index = read_index(Cursor::new(&data), 7).unwrap();
+        assert_eq!(index.len(), 3);
+        assert!(read_index(Cursor::new(&data), 8).unwrap().is_empty());
+        for (entry, packet) in index.iter().zip([0, 2, 4]) {
+            assert_eq!(entry.timestamp(), packet * 1_000);
+            assert_eq!(entry.interface_id(), 0);
+            let block = &data[entry.offset() as usize..];
+            assert_eq!(&block[..4], &[0, 0, 0, 6]);
+            // packet data after the 28 bytes of the EPB header
+            assert_eq!(block[28], packet as u8);
+        }
+        let custom = SliceReader::new(&data)
+            .filter(|b| matches!(b, Ok(Block::Unknown(raw)) if raw.block_type() == 0x40000BAD))
+            .count();
+        assert_eq!(custom, 2);
+    }
+}
@@ -0,0 +1,336 @@
+//! Summaries of captures, like capinfos.
+//!
+//! `capture_info` reads a pcapng stream and counts its packets and
+//! bytes, per interface and in total, along with the time span of
+//! the packets:
+//!
+//! ```
+//! use pcapng_writer::blocks::options::Options;
+//! use pcapng_writer::enums::LinkType;
+//! use pcapng_writer::info::capture_info;
+//! use pcapng_writer::writer::{Endianness, PcapNgWriter};
+//!
+//! let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+//! let eth = writer.add_interface(LinkType::Ethernet, 0, &Options::new()).unwrap();
+//! writer.write_packet(eth, 1_000_000, &[0; 60]).unwrap();
+//! writer.write_packet(eth, 3_000_000, &[0; 40]).unwrap();
+//!
+//! let info = capture_info(&writer.get_writer()[..]).unwrap();
+//! assert_eq!(info.packets(), 2);
+//! assert_eq!(info.bytes(), 100);
+//! assert_eq!(info.duration_nanos(), Some(2_000_000));
+//! println!("{}", info);
+//! ```
+
+use crate::blocks::options::BlockOption;
+use crate::error::{Error, Result};
+use crate::reader::{Block, PcapNgReader};
+use crate::utils::TimestampResolution;
+use std::fmt;
+use std::io::Read;
+
+/// The summary of an interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceInfo {
+    section: u64,
+    id: u32,
+    link_type: u16,
+    snap_len: u32,
+    tsresol: TimestampResolution,
+    name: Option<String>,
+    packets: u64,
+    bytes: u64,
+    orig_bytes: u64,
+    received: Option<u64>,
+    dropped: Option<u64>,
+}
+
+impl InterfaceInfo {
+    /// Returns the index of the section of the interface, from 0.
+    pub fn section(&self) -> u64 {
+        self.section
+    }
+
+    /// Returns the ID of the interface in its section.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn link_type(&self) -> u16 {
+        self.link_type
+    }
+
+    pub fn snap_len(&self) -> u32 {
+        self.snap_len
+    }
+
+    pub fn tsresol(&self) -> TimestampResolution {
+        self.tsresol
+    }
+
+    /// Returns the `if_name` option of the interface.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn packets(&self) -> u64 {
+        self.packets
+    }
+
+    /// Returns the number of captured bytes.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns the number of bytes of the packets on the wire.
+    pub fn orig_bytes(&self) -> u64 {
+        self.orig_bytes
+    }
+
+    /// Returns the `isb_ifrecv` count of the last statistics of the
+    /// interface.
+    pub fn received(&self) -> Option<u64> {
+        self.received
+    }
+
+    /// Returns the sum of the `isb_ifdrop` and `isb_osdrop` counts of
+    /// the last statistics of the interface.
+    pub fn dropped(&self) -> Option<u64> {
+        self.dropped
+    }
+}
+
+/// The summary of a capture, see `capture_info`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CaptureInfo {
+    sections: u64,
+    interfaces: Vec<InterfaceInfo>,
+    first: Option<u128>,
+    last: Option<u128>,
+}
+
+impl CaptureInfo {
+    pub fn sections(&self) -> u64 {
+        self.sections
+    }
+
+    pub fn interfaces(&self) -> &[InterfaceInfo] {
+        &self.interfaces
+    }
+
+    pub fn packets(&self) -> u64 {
+        self.interfaces.iter().map(|i| i.packets).sum()
+    }
+
+    /// Returns the number of captured bytes.
+    pub fn bytes(&self) -> u64 {
+        self.interfaces.iter().map(|i| i.bytes).sum()
+    }
+
+    /// Returns the timestamp of the earliest packet, in nanoseconds
+    /// since the Unix epoch. Simple Packet Blocks have no timestamp.
+    pub fn first_timestamp(&self) -> Option<u128> {
+        self.first
+    }
+
+    /// Returns the timestamp of the latest packet.
+    pub fn last_timestamp(&self) -> Option<u128> {
+        self.last
+    }
+
+    /// Returns the time between the earliest and the latest packet,
+    /// in nanoseconds.
+    pub fn duration_nanos(&self) -> Option<u128> {
+        Some(self.last? - self.first?)
+    }
+
+    fn add_timestamp(&mut self, nanos: u128) {
+        self.first = Some(self.first.map_or(nanos, |first| first.min(nanos)));
+        self.last = Some(self.last.map_or(nanos, |last| last.max(nanos)));
+    }
+}
+
+impl fmt::Display for CaptureInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Sections:       {}", self.sections)?;
+        writeln!(f, "Interfaces:     {}", self.interfaces.len())?;
+        writeln!(f, "Packets:        {}", self.packets())?;
+        writeln!(f, "Bytes:          {}", self.bytes())?;
+        if let (Some(first), Some(duration)) = (self.first, self.duration_nanos()) {
+            writeln!(
+                f,
+                "First packet:   {}.{:09}",
+                first / 1_000_000_000,
+                first % 1_000_000_000
+            )?;
+            writeln!(
+                f,
+                "Duration:       {}.{:09} s",
+                duration / 1_000_000_000,
+                duration % 1_000_000_000
+            )?;
+        }
+        for i in &self.interfaces {
+            write!(
+                f,
+                "Interface {}.{}:  link type {}, ",
+                i.section, i.id, i.link_type
+            )?;
+            match i.tsresol {
+                TimestampResolution::PowerOfTen(p) => write!(f, "resolution 10^-{}", p)?,
+                TimestampResolution::PowerOfTwo(p) => write!(f, "resolution 2^-{}", p)?,
+            }
+            if let Some(name) = &i.name {
+                write!(f, ", name {}", name)?;
+            }
+            write!(f, ", {} packets, {} bytes", i.packets, i.bytes)?;
+            if let Some(dropped) = i.dropped {
+                write!(f, ", {} dropped", dropped)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the pcapng stream of `reader` and summarizes it.
+///
+/// Packets of interfaces that are not described are an error, as
+/// their timestamps cannot be interpreted.
+pub fn capture_info<R: Read>(reader: R) -> Result<CaptureInfo> {
+    let mut reader = PcapNgReader::new(reader);
+    let mut info = CaptureInfo::default();
+    // the index in `info.interfaces` of the first interface of the
+    // current section
+    let mut section_start = 0;
+    while let Some(block) = reader.next_block()? {
+        match block {
+            Block::SectionHeader(_) => {
+                section_start = info.interfaces.len();
+                info.sections += 1;
+            }
+            Block::InterfaceDescription(idb) => {
+                let options = idb.options();
+                let name = options.iter().find_map(|o| match o {
+                    BlockOption::IfName(o) => Some(o.name().to_string()),
+                    _ => None,
+                });
+                info.interfaces.push(InterfaceInfo {
+                    section: info.sections.saturating_sub(1),
+                    id: (info.interfaces.len() - section_start) as u32,
+                    link_type: idb.link_type(),
+                    snap_len: idb.snap_len(),
+                    tsresol: idb.tsresol(),
+                    name,
+                    packets: 0,
+                    bytes: 0,
+                    orig_bytes: 0,
+                    received: None,
+                    dropped: None,
+                });
+            }
+            Block::EnhancedPacket(epb) => {
+                let interface = interface(&mut info, section_start, epb.interface_id())?;
+                interface.packets += 1;
+                interface.bytes += epb.packet_data().len() as u64;
+                interface.orig_bytes += u64::from(epb.orig_len());
+                let nanos = interface.tsresol.nanoseconds_from_ticks(epb.timestamp());
+                info.add_timestamp(nanos);
+            }
+            Block::SimplePacket(spb) => {
+                let interface = interface(&mut info, section_start, 0)?;
+                interface.packets += 1;
+                interface.bytes += spb.packet_data().len() as u64;
+                interface.orig_bytes += u64::from(spb.orig_len());
+            }
+            Block::InterfaceStatistics(isb) => {
+                let interface = interface(&mut info, section_start, isb.interface_id())?;
+                let options = isb.options();
+                let mut dropped = None;
+                for option in options.iter() {
+                    match option {
+                        BlockOption::IsbIfRecv(o) => interface.received = Some(o.count()),
+                        BlockOption::IsbIfDrop(o) => {
+                            dropped = Some(dropped.unwrap_or(0) + o.count())
+                        }
+                        BlockOption::IsbOsDrop(o) => {
+                            dropped = Some(dropped.unwrap_or(0) + o.count())
+                        }
+                        _ => (),
+                    }
+                }
+                if dropped.is_some() {
+                    interface.dropped = dropped;
+                }
+            }
+            Block::Unknown(_) => (),
+        }
+    }
+    Ok(info)
+}
+
+fn interface(info: &mut CaptureInfo, section_start: usize, id: u32) -> Result<&mut InterfaceInfo> {
+    info.interfaces[section_start..]
+        .get_mut(id as usize)
+        .ok_or(Error::UnknownInterface(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::{OptionIfName, OptionIsbIfDrop, OptionIsbOsDrop, Options};
+    use crate::blocks::{InterfaceStatisticsBlock, SimplePacketBlock};
+    use crate::enums::LinkType;
+    use crate::writer::{Endianness, PcapNgWriter};
+
+    #[test]
+    fn sections_and_interfaces() {
+        let name = [OptionIfName::new_option("eth0")];
+        let mut named = Options::new();
+        named.add_options(&name);
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        let eth = writer.add_interface(LinkType::Ethernet, 0, &named).unwrap();
+        writer.write_packet(eth, 5_000, &[0; 10]).unwrap();
+        writer.write(&SimplePacketBlock::new(20, &[0; 8])).unwrap();
+        let drops = [
+            OptionIsbIfDrop::new_option(1),
+            OptionIsbOsDrop::new_option(2),
+        ];
+        let mut isb_options = Options::new();
+        isb_options.add_options(&drops);
+        writer
+            .write(&InterfaceStatisticsBlock::new(0, 0, 9, &isb_options))
+            .unwrap();
+        writer.start_new_section(&Options::new()).unwrap();
+        let raw = writer
+            .add_interface(LinkType::Raw, 0, &Options::new())
+            .unwrap();
+        writer.write_packet(raw, 2_000, &[0; 4]).unwrap();
+
+        let info = capture_info(&writer.get_writer()[..]).unwrap();
+        assert_eq!(info.sections(), 2);
+        assert_eq!(info.packets(), 3);
+        assert_eq!(info.bytes(), 22);
+        assert_eq!(info.first_timestamp(), Some(2_000));
+        assert_eq!(info.duration_nanos(), Some(3_000));
+        let interfaces = info.interfaces();
+        assert_eq!(interfaces.len(), 2);
+        assert_eq!(interfaces[0].name(), Some("eth0"));
+        assert_eq!(interfaces[0].packets(), 2);
+        assert_eq!(interfaces[0].orig_bytes(), 30);
+        assert_eq!(interfaces[0].dropped(), Some(3));
+        assert_eq!(interfaces[0].received(), None);
+        assert_eq!((interfaces[1].section(), interfaces[1].id()), (1, 0));
+        assert!(info.to_string().contains("Interface 0.0:  link type 1"));
+    }
+
+    #[test]
+    fn unknown_interface() {
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        writer.write(&SimplePacketBlock::new(1, &[0])).unwrap();
+        assert!(matches!(
+            capture_info(&writer.get_writer()[..]),
+            Err(Error::UnknownInterface(0))
+        ));
+    }
+}
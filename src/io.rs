@@ -0,0 +1,229 @@
+//! The I/O traits the encoder writes to.
+//!
+//! With the `std` feature (enabled by default), these are the
+//! `std::io` types and byteorder's `WriteBytesExt`. Without it, a
+//! minimal `Write` trait is provided instead. It is implemented for
+//! byte slices and `Vec<u8>`, and with the `embedded-io` feature,
+//! `EmbeddedWriter` adapts an `embedded_io::Write` to it.
+
+#[cfg(feature = "std")]
+pub use byteorder::WriteBytesExt;
+#[cfg(feature = "std")]
+pub use std::io::{Error, ErrorKind, Result, Write};
+
+#[cfg(not(feature = "std"))]
+pub use self::core_io::*;
+
+#[cfg(not(feature = "std"))]
+mod core_io {
+    use alloc::vec::Vec;
+    use byteorder::ByteOrder;
+    use core::fmt;
+
+    /// The kind of an I/O `Error`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum ErrorKind {
+        /// The writer did not accept any more data.
+        WriteZero,
+        /// The data is not valid pcapng.
+        InvalidData,
+        Other,
+    }
+
+    /// An I/O error.
+    #[derive(Debug)]
+    pub struct Error {
+        kind: ErrorKind,
+        msg: &'static str,
+    }
+
+    impl Error {
+        pub fn new(kind: ErrorKind, msg: &'static str) -> Self {
+            Self { kind, msg }
+        }
+
+        pub fn kind(&self) -> ErrorKind {
+            self.kind
+        }
+    }
+
+    impl From<ErrorKind> for Error {
+        fn from(kind: ErrorKind) -> Self {
+            Self::new(kind, "")
+        }
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            if self.msg.is_empty() {
+                write!(f, "{:?}", self.kind)
+            } else {
+                f.write_str(self.msg)
+            }
+        }
+    }
+
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    /// A minimal version of `std::io::Write`.
+    pub trait Write {
+        fn write(&mut self, buf: &[u8]) -> Result<usize>;
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.write(buf)? {
+                    0 => return Err(ErrorKind::WriteZero.into()),
+                    n => buf = &buf[n..],
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl<W: Write + ?Sized> Write for &mut W {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            (**self).write(buf)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            (**self).flush()
+        }
+    }
+
+    impl Write for &mut [u8] {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            let n = buf.len().min(self.len());
+            let (head, tail) = core::mem::take(self).split_at_mut(n);
+            head.copy_from_slice(&buf[..n]);
+            *self = tail;
+            Ok(n)
+        }
+    }
+
+    impl Write for Vec<u8> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
+
+    /// Writes integers with a given byte order, like byteorder's
+    /// `WriteBytesExt`.
+    pub trait WriteBytesExt: Write {
+        fn write_u8(&mut self, n: u8) -> Result<()> {
+            self.write_all(&[n])
+        }
+
+        fn write_u16<B: ByteOrder>(&mut self, n: u16) -> Result<()> {
+            let mut buf = [0; 2];
+            B::write_u16(&mut buf, n);
+            self.write_all(&buf)
+        }
+
+        fn write_u32<B: ByteOrder>(&mut self, n: u32) -> Result<()> {
+            let mut buf = [0; 4];
+            B::write_u32(&mut buf, n);
+            self.write_all(&buf)
+        }
+
+        fn write_u64<B: ByteOrder>(&mut self, n: u64) -> Result<()> {
+            let mut buf = [0; 8];
+            B::write_u64(&mut buf, n);
+            self.write_all(&buf)
+        }
+    }
+
+    impl<W: Write + ?Sized> WriteBytesExt for W {}
+}
+
+/// Adapts an `embedded_io::Write`, e.g. a serial port or a flash
+/// driver, to the `Write` trait of this module.
+#[cfg(feature = "embedded-io")]
+#[derive(Debug)]
+pub struct EmbeddedWriter<W>(pub W);
+
+#[cfg(feature = "embedded-io")]
+impl<W: embedded_io::Write> Write for EmbeddedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0.write(buf).map_err(embedded_error)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.0.flush().map_err(embedded_error)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+fn embedded_error<E: embedded_io::Error>(e: E) -> Error {
+    let kind = match e.kind() {
+        embedded_io::ErrorKind::WriteZero => ErrorKind::WriteZero,
+        embedded_io::ErrorKind::InvalidData => ErrorKind::InvalidData,
+        _ => ErrorKind::Other,
+    };
+    Error::new(kind, "embedded-io write error")
+}
+
+#[cfg(all(test, feature = "embedded-io"))]
+mod tests {
+    use super::*;
+    use crate::blocks::options::Options;
+    use crate::blocks::EnhancedPacketBlock;
+    use crate::writer::PcapNgWriter;
+
+    /// A fixed-size "flash" that fails when full.
+    struct Flash {
+        data: [u8; 64],
+        len: usize,
+    }
+
+    #[derive(Debug)]
+    struct Full;
+
+    impl embedded_io::Error for Full {
+        fn kind(&self) -> embedded_io::ErrorKind {
+            embedded_io::ErrorKind::OutOfMemory
+        }
+    }
+
+    impl embedded_io::ErrorType for Flash {
+        type Error = Full;
+    }
+
+    impl embedded_io::Write for Flash {
+        fn write(&mut self, buf: &[u8]) -> core::result::Result<usize, Full> {
+            if self.len == self.data.len() {
+                return Err(Full);
+            }
+            let n = buf.len().min(self.data.len() - self.len);
+            self.data[self.len..self.len + n].copy_from_slice(&buf[..n]);
+            self.len += n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> core::result::Result<(), Full> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn embedded_writer() {
+        let opts = Options::new();
+        let flash = Flash {
+            data: [0; 64],
+            len: 0,
+        };
+        let mut writer = PcapNgWriter::new_auto_section(
+            crate::writer::Endianness::Little,
+            EmbeddedWriter(flash),
+        );
+        let epb = EnhancedPacketBlock::new(0, 0, 0, 4, 4, &[1; 4], &opts);
+        writer.write(&epb).unwrap();
+        assert_eq!(writer.get_writer().0.len, 28 + 36);
+        assert!(writer.write(&epb).is_err());
+    }
+}
@@ -0,0 +1,336 @@
+//! Embedding TLS key logs in captures.
+//!
+//! TLS libraries write their session secrets to the file named by the
+//! `SSLKEYLOGFILE` environment variable. `KeyLogTail` follows such a
+//! file and writes the lines appended to it as Decryption Secrets
+//! Blocks, so Wireshark can decrypt the capture without the key log
+//! file. Calling `write_secrets` before each batch of packets puts the
+//! secrets ahead of the packets they decrypt:
+//!
+//! ```no_run
+//! use pcapng_writer::keylog::KeyLogTail;
+//! # use pcapng_writer::writer::PcapNgWriter;
+//! # let mut writer = PcapNgWriter::new_le(vec![]);
+//!
+//! let mut keys = KeyLogTail::open(std::env::var("SSLKEYLOGFILE").unwrap()).unwrap();
+//! loop {
+//!     keys.write_secrets(&mut writer).unwrap();
+//!     // write the packets captured since
+//! #   break;
+//! }
+//! ```
+//!
+//! With the `rustls` feature, `DsbKeyLog` receives the secrets of
+//! `rustls` sessions directly, without a key log file:
+//!
+#![cfg_attr(feature = "rustls", doc = "```no_run")]
+#![cfg_attr(not(feature = "rustls"), doc = "```ignore")]
+//! use pcapng_writer::keylog::DsbKeyLog;
+//! use pcapng_writer::writer::{Endianness, PcapNgWriter};
+//! use std::fs::File;
+//! use std::sync::{Arc, Mutex};
+//!
+//! # fn setup(config: &mut rustls::ClientConfig) -> std::io::Result<()> {
+//! let file = File::create(std::env::temp_dir().join("tls.pcapng"))?;
+//! let writer = PcapNgWriter::new_auto_section(Endianness::Little, file);
+//! let writer = Arc::new(Mutex::new(writer));
+//! config.key_log = Arc::new(DsbKeyLog::new(writer.clone()));
+//! # Ok(())
+//! # }
+//! # fn main() {}
+//! ```
+//!
+//! With the `quinn` feature, `quic_client_config` and
+//! `quic_server_config` do the same for QUIC connections of `quinn`,
+//! whose packet protection keys Wireshark derives from the TLS key
+//! log. Their blocks are tagged with a "QUIC" comment.
+
+use crate::blocks::options::Options;
+#[cfg(feature = "rustls")]
+use crate::blocks::options::{BlockOption, OptionComment};
+use crate::blocks::DecryptionSecretsBlock;
+use crate::enums::SecretsType;
+#[cfg(feature = "rustls")]
+use crate::error::Error;
+use crate::error::Result;
+#[cfg(feature = "quinn")]
+use crate::framing::invalid;
+use crate::writer::PcapNgWriter;
+#[cfg(feature = "quinn")]
+use std::convert::TryFrom;
+#[cfg(feature = "rustls")]
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+#[cfg(feature = "rustls")]
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Follows a TLS key log, e.g. an `SSLKEYLOGFILE`.
+#[derive(Debug)]
+pub struct KeyLogTail<R = File> {
+    reader: R,
+    partial: Vec<u8>,
+}
+
+impl KeyLogTail<File> {
+    /// Opens the key log file at `path`. The lines already in the
+    /// file are read by the first call to `read_lines`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> KeyLogTail<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            partial: Vec::new(),
+        }
+    }
+
+    /// Reads the lines appended to the key log since the last call.
+    /// Only complete lines are returned, a line being written is kept
+    /// for the next call. Empty lines and comments are dropped.
+    pub fn read_lines(&mut self) -> io::Result<Vec<u8>> {
+        self.reader.read_to_end(&mut self.partial)?;
+        let complete = match self.partial.iter().rposition(|&b| b == b'\n') {
+            Some(end) => end + 1,
+            None => return Ok(Vec::new()),
+        };
+        let rest = self.partial.split_off(complete);
+        let data = std::mem::replace(&mut self.partial, rest);
+        let mut lines = Vec::with_capacity(data.len());
+        for line in data.split_inclusive(|&b| b == b'\n') {
+            let text = line.trim_ascii();
+            if !text.is_empty() && !text.starts_with(b"#") {
+                lines.extend_from_slice(line);
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Writes the lines appended to the key log since the last call
+    /// as a Decryption Secrets Block, and returns whether a block was
+    /// written.
+    pub fn write_secrets<W: Write>(&mut self, writer: &mut PcapNgWriter<W>) -> Result<bool> {
+        let lines = self.read_lines()?;
+        if lines.is_empty() {
+            return Ok(false);
+        }
+        write_tls_key_log(writer, &lines)?;
+        Ok(true)
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Writes lines of a TLS key log as a Decryption Secrets Block.
+pub fn write_tls_key_log<W: Write>(writer: &mut PcapNgWriter<W>, lines: &[u8]) -> Result<()> {
+    let options = Options::new();
+    writer.write(&DecryptionSecretsBlock::new(
+        SecretsType::TlsKeyLog,
+        lines,
+        &options,
+    ))
+}
+
+/// Formats a line of an NSS key log.
+#[cfg(feature = "rustls")]
+fn key_log_line(label: &str, client_random: &[u8], secret: &[u8]) -> Vec<u8> {
+    let mut line = Vec::with_capacity(label.len() + 2 * (client_random.len() + secret.len()) + 3);
+    line.extend_from_slice(label.as_bytes());
+    for field in [client_random, secret] {
+        line.push(b' ');
+        for byte in field {
+            // writing to a Vec does not fail
+            let _ = write!(line, "{:02x}", byte);
+        }
+    }
+    line.push(b'\n');
+    line
+}
+
+/// A `rustls::KeyLog` that writes the secrets of TLS sessions to a
+/// shared writer, each as a Decryption Secrets Block.
+///
+/// `KeyLog::log` cannot fail, so the first error writing a block is
+/// kept, see `take_error`.
+#[cfg(feature = "rustls")]
+pub struct DsbKeyLog<W: Write> {
+    writer: Arc<Mutex<PcapNgWriter<W>>>,
+    comment: Option<BlockOption>,
+    error: Mutex<Option<Error>>,
+}
+
+#[cfg(feature = "rustls")]
+impl<W: Write> DsbKeyLog<W> {
+    pub fn new(writer: Arc<Mutex<PcapNgWriter<W>>>) -> Self {
+        Self {
+            writer,
+            comment: None,
+            error: Mutex::new(None),
+        }
+    }
+
+    pub fn writer(&self) -> &Arc<Mutex<PcapNgWriter<W>>> {
+        &self.writer
+    }
+
+    /// Sets an `opt_comment` option for the blocks written, e.g. to
+    /// tell the secrets of different protocols apart.
+    pub fn set_comment(&mut self, comment: &str) {
+        self.comment = Some(OptionComment::new_option(comment));
+    }
+
+    /// Returns the first error since the last call, if writing a block
+    /// failed.
+    pub fn take_error(&self) -> Option<Error> {
+        self.error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+}
+
+#[cfg(feature = "rustls")]
+impl<W: Write> fmt::Debug for DsbKeyLog<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DsbKeyLog").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "rustls")]
+impl<W: Write + Send> rustls::KeyLog for DsbKeyLog<W> {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        let line = key_log_line(label, client_random, secret);
+        let mut options = Options::new();
+        if let Some(comment) = &self.comment {
+            options.add_option(comment);
+        }
+        let dsb = DecryptionSecretsBlock::new(SecretsType::TlsKeyLog, &line, &options);
+        let mut writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Err(e) = writer.write(&dsb) {
+            let mut error = self.error.lock().unwrap_or_else(PoisonError::into_inner);
+            error.get_or_insert(e);
+        }
+    }
+}
+
+/// Creates a `quinn` client configuration for `crypto` that writes
+/// the secrets of its connections to `writer`.
+#[cfg(feature = "quinn")]
+pub fn quic_client_config<W: Write + Send + 'static>(
+    mut crypto: rustls::ClientConfig,
+    writer: Arc<Mutex<PcapNgWriter<W>>>,
+) -> Result<quinn_proto::ClientConfig> {
+    crypto.key_log = Arc::new(quic_key_log(writer));
+    let crypto = quinn_proto::crypto::rustls::QuicClientConfig::try_from(crypto)
+        .map_err(|_| invalid("no QUIC initial cipher suite"))?;
+    Ok(quinn_proto::ClientConfig::new(Arc::new(crypto)))
+}
+
+/// Creates a `quinn` server configuration for `crypto` that writes
+/// the secrets of its connections to `writer`.
+#[cfg(feature = "quinn")]
+pub fn quic_server_config<W: Write + Send + 'static>(
+    mut crypto: rustls::ServerConfig,
+    writer: Arc<Mutex<PcapNgWriter<W>>>,
+) -> Result<quinn_proto::ServerConfig> {
+    crypto.key_log = Arc::new(quic_key_log(writer));
+    let crypto = quinn_proto::crypto::rustls::QuicServerConfig::try_from(crypto)
+        .map_err(|_| invalid("no QUIC initial cipher suite"))?;
+    Ok(quinn_proto::ServerConfig::with_crypto(Arc::new(crypto)))
+}
+
+#[cfg(feature = "quinn")]
+fn quic_key_log<W: Write>(writer: Arc<Mutex<PcapNgWriter<W>>>) -> DsbKeyLog<W> {
+    let mut key_log = DsbKeyLog::new(writer);
+    key_log.set_comment("QUIC");
+    key_log
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{Block, SliceReader};
+    use crate::writer::Endianness;
+
+    #[test]
+    fn read_lines() {
+        let path =
+            std::env::temp_dir().join(format!("pcapng-writer-keylog-{}", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        let mut keys = KeyLogTail::open(&path).unwrap();
+        assert!(keys.read_lines().unwrap().is_empty());
+        file.write_all(b"# comment\nCLIENT_RANDOM 01 02\n\nSERVER_")
+            .unwrap();
+        assert_eq!(keys.read_lines().unwrap(), b"CLIENT_RANDOM 01 02\n");
+        file.write_all(b"HANDSHAKE_TRAFFIC_SECRET 03 04\n").unwrap();
+        assert_eq!(
+            keys.read_lines().unwrap(),
+            b"SERVER_HANDSHAKE_TRAFFIC_SECRET 03 04\n"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn write_secrets() {
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        let mut keys = KeyLogTail::new(&b"CLIENT_RANDOM 01 02\n"[..]);
+        assert!(keys.write_secrets(&mut writer).unwrap());
+        assert!(!keys.write_secrets(&mut writer).unwrap());
+        let data = writer.get_writer().clone();
+        let blocks: Vec<_> = SliceReader::new(&data).map(|b| b.unwrap()).collect();
+        assert_eq!(blocks.len(), 2);
+        match &blocks[1] {
+            Block::Unknown(raw) => {
+                assert_eq!(raw.block_type(), 0xA);
+                assert_eq!(&raw.body()[..4], b"KSLT");
+                assert_eq!(&raw.body()[8..28], b"CLIENT_RANDOM 01 02\n");
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn rustls_key_log() {
+        use rustls::KeyLog;
+
+        let writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        let writer = Arc::new(Mutex::new(writer));
+        let key_log = DsbKeyLog::new(writer.clone());
+        key_log.log("CLIENT_RANDOM", &[0xab, 1], &[0xff]);
+        assert!(key_log.take_error().is_none());
+        let data = writer.lock().unwrap().get_writer().clone();
+        match SliceReader::new(&data).nth(1).unwrap().unwrap() {
+            Block::Unknown(raw) => assert_eq!(&raw.body()[8..30], b"CLIENT_RANDOM ab01 ff\n"),
+            _ => panic!(),
+        }
+    }
+
+    #[cfg(feature = "quinn")]
+    #[test]
+    fn quic_key_log_comment() {
+        use rustls::KeyLog;
+
+        let writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        let writer = Arc::new(Mutex::new(writer));
+        quic_key_log(writer.clone()).log("CLIENT_TRAFFIC_SECRET_0", &[1], &[2]);
+        let data = writer.lock().unwrap().get_writer().clone();
+        match SliceReader::new(&data).nth(1).unwrap().unwrap() {
+            // 30 bytes of secrets and 2 of padding, then the comment
+            Block::Unknown(raw) => {
+                assert_eq!(&raw.body()[40..48], &[1, 0, 4, 0, b'Q', b'U', b'I', b'C'])
+            }
+            _ => panic!(),
+        }
+    }
+}
@@ -2,26 +2,86 @@
 //!
 //! This crate contains a pcapng encoder. It can be use to write the
 //! pcapng format to a file or anything that implmenets
-//! `std::io::Write`.
+//! `std::io::Write`, and to read it back.
 //!
 //! Implementation is based on the draft standard version 02
 //! ([draft-tuexen-opsawg-pcapng-02](https://tools.ietf.org/html/draft-tuexen-opsawg-pcapng-02)).
 //!
-//! # Use
+//! # Writing
 //!
-//! The `blocks` module contains types for the supported pcapng block
-//! types. pcapng "option" types are located in `blocks::options`.
+//! The [`blocks`] module contains the pcapng block types, and
+//! [`blocks::options`] the option types, which are gathered for a
+//! block in an [`Options`](blocks::options::Options) container.
 //!
-//! `writer::PcapNgWriter` can be used to write blocks and options to
-//! a file.
+//! [`PcapNgWriter`](writer::PcapNgWriter) encodes blocks and writes
+//! them. It can write the Section Header Block itself, keeps track
+//! of interface IDs, and writes
+//! [`PacketRecord`](writer::PacketRecord)s as Enhanced or Simple
+//! Packet Blocks.
+//!
+//! For the common case of just recording packets,
+//! [`CaptureWriter`](capture::CaptureWriter) takes care of the
+//! section header, interfaces and timestamp conversion, and
+//! [`live::record`] feeds it from a live capture. The [`pcap`]
+//! module writes classic libpcap files.
+//!
+//! # Sinks
+//!
+//! The [`sink`] module contains `Write` implementations for common
+//! output setups, e.g. [`RotatingWriter`](sink::RotatingWriter)
+//! splits a capture into files of limited size, and
+//! [`TcpStreamWriter`](sink::TcpStreamWriter) streams it to a remote
+//! collector. [`ThreadedWriter`](threaded::ThreadedWriter) moves a
+//! writer to a background thread, and
+//! [`RateLimitedWriter`](ratelimit::RateLimitedWriter) caps the rate
+//! of packets written.
+//!
+//! # Pipeline
+//!
+//! [`PcapNgWriter::add_filter`](writer::PcapNgWriter::add_filter)
+//! adds stages that inspect, modify or drop each block before it is
+//! written, see the [`pipeline`] module.
+//! [`PcapNgWriter::set_metrics`](writer::PcapNgWriter::set_metrics)
+//! reports what is written to a [`metrics::Metrics`] implementation,
+//! and [`PcapNgWriter::add_name`](writer::PcapNgWriter::add_name)
+//! collects Name Resolution Blocks, see the [`names`] module.
+//!
+//! # Reading
+//!
+//! The [`reader`] module parses pcapng data back into the block
+//! types. [`validate`] checks the structure of a stream,
+//! [`info::capture_info`] summarizes it like `capinfos`, and
+//! [`hexdump`] displays it as an annotated hex dump. [`merge`]
+//! merges captures in chronological order, and [`convert`] converts
+//! between pcap and pcapng.
 //!
 //! # Timestamps
 //!
-//! pcapng define two types of timestamp
-//! resolution. `utils::TimestampResolution` is an enum that
-//! represents these two types. This enum has mothods to generate data
-//! to be used in the if_tsresol option, as well as the timestamp
-//! field of the Enhanced Packet Block.
+//! pcapng defines two types of timestamp resolution, represented by
+//! [`TimestampResolution`](utils::TimestampResolution). It converts
+//! timestamps to the ticks of the Enhanced Packet Block, and gives
+//! the value of the `if_tsresol` option.
+//!
+//! # Features
+//!
+//! Without the default `std` feature the crate is `no_std` (it still
+//! needs `alloc`), and writes to the minimal [`io::Write`] trait.
+//!
+//! Other features add capture backends (`bpf`, `npcap`, `ebpf`,
+//! `xdp`), sinks (`zstd`, `encryption`, `signing`, `s3`, `wasm`,
+//! `io-uring`), an async writer (`futures-io`), descriptions of the
+//! system (`netif`, `host-info`), test helpers (`testing`) and
+//! Python bindings (`python`), described in their modules.
+//!
+//! With `chrono` and `time`, timestamps convert from and to their
+//! date types. With `serde`, options, enums and timestamps implement
+//! `Serialize` and `Deserialize`, and blocks `Serialize`. With
+//! `arbitrary`, options and the blocks read by `reader` implement
+//! `Arbitrary`, for fuzzing.
+//!
+//! Encoding and writing return [`Result`]. Besides I/O errors,
+//! encoding fails when a value does not fit in its field, e.g. an
+//! option longer than 65535 bytes.
 //!
 //! # Examples
 //!
@@ -36,7 +96,9 @@
 //! use pcapng_writer::writer::PcapNgWriter;
 //! use pcapng_writer::utils::DEFAULT_TSRES;
 //! use pcapng_writer::blocks::options::{OptionComment, OptionEndOfOpt, Options};
-//! use std::time::{SystemTime, UNIX_EPOCH};
+//! use std::time::SystemTime;
+//! # #[cfg(feature = "std")]
+//! # fn main() {
 //! // create options
 //! let comment_opt = OptionComment::new_option("Test Comment");
 //! let eoo = OptionEndOfOpt::new_option();
@@ -46,32 +108,98 @@
 //! epb_options.add_option(&comment_opt);
 //! epb_options.add_option(&eoo);
 //!
-//! // get system time in nanoseconds
-//! let nanos = SystemTime::now()
-//!     .duration_since(UNIX_EPOCH)
-//!     .unwrap()
-//!     .as_nanos();
-//!
 //! // the actual packet content
 //! let payload = b"\x00\x11\x22\x33\x44\x01\x00\x11\x22\x33\x44\x02\x08\x00";
-//! let epb = EnhancedPacketBlock::new_with_timestamp(
+//! let epb = EnhancedPacketBlock::from_systemtime(
 //!     0,
 //!     DEFAULT_TSRES,
-//!     nanos,
-//!     payload.len() as u32,
+//!     SystemTime::now(),
 //!     payload.len() as u32,
 //!     &payload[..],
 //!     &epb_options,
-//! );
+//! )
+//! .unwrap();
 //!
 //! // using a byte vector here instead of a file
 //! let mut buf: Vec<u8> = vec![];
 //! let mut writer = PcapNgWriter::new_le(&mut buf);
 //! writer.write(&epb).unwrap();
+//! # }
+//! # #[cfg(not(feature = "std"))]
+//! # fn main() {}
 //! ```
 
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+// the tests use `std` even without the `std` feature
+#[cfg(all(test, not(feature = "std")))]
+#[macro_use]
+extern crate std;
+
+#[cfg(feature = "arbitrary")]
+mod arbitrary;
+#[cfg(all(feature = "futures-io", feature = "std"))]
+pub mod async_writer;
 pub mod blocks;
+#[cfg(feature = "bpf")]
+pub mod bpf;
+#[cfg(feature = "std")]
+pub mod capture;
 pub mod constants;
+#[cfg(feature = "std")]
+pub mod convert;
+#[cfg(feature = "ebpf")]
+pub mod ebpf;
 pub mod enums;
+pub mod error;
+#[cfg(feature = "std")]
+pub mod extcap;
+mod framing;
+pub mod hexdump;
+#[cfg(feature = "host-info")]
+pub mod host_info;
+#[cfg(feature = "std")]
+pub mod index;
+#[cfg(feature = "std")]
+pub mod info;
+pub mod io;
+#[cfg(feature = "std")]
+pub mod keylog;
+#[cfg(feature = "std")]
+pub mod live;
+#[cfg(feature = "std")]
+pub mod merge;
+pub mod metrics;
+#[cfg(feature = "std")]
+pub mod names;
+#[cfg(feature = "netif")]
+pub mod netif;
+#[cfg(feature = "npcap")]
+pub mod npcap;
+pub mod pcap;
+#[cfg(feature = "std")]
+pub mod pipeline;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "std")]
+pub mod ratelimit;
+pub mod reader;
+#[cfg(feature = "std")]
+pub mod sink;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod text2pcap;
+#[cfg(feature = "std")]
+pub mod threaded;
 pub mod utils;
+#[cfg(feature = "std")]
+pub mod validator;
 pub mod writer;
+#[cfg(feature = "xdp")]
+pub mod xdp;
+
+pub use crate::error::{Error, Result};
+#[cfg(feature = "std")]
+pub use crate::validator::{validate, Report};
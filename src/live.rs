@@ -0,0 +1,325 @@
+//! Recording live captures.
+//!
+//! `LiveCapture` is implemented by sources of captured packets, e.g.
+//! a `pcap::Capture<Active>`, and `record` writes the packets of a
+//! source to a `CaptureWriter`, followed by an Interface Statistics
+//! Block with the statistics of the capture.
+//!
+//! The crate implements `LiveCapture` for `bpf::BpfDevice` on macOS
+//! and the BSDs with the `bpf` feature, and for
+//! `npcap::NpcapCapture` on Windows with the `npcap` feature.
+//!
+//! The crate does not depend on any capture library. A wrapper
+//! around a `pcap` capture takes a few lines:
+//!
+//! ```no_run
+//! # mod pcap {
+//! #     use std::marker::PhantomData;
+//! #     pub struct Active;
+//! #     pub struct Capture<T>(PhantomData<T>);
+//! #     #[derive(Clone, Copy)]
+//! #     pub struct TimeVal { pub tv_sec: i64, pub tv_usec: i64 }
+//! #     pub struct PacketHeader { pub ts: TimeVal, pub caplen: u32, pub len: u32 }
+//! #     pub struct Packet<'a> { pub header: &'a PacketHeader, pub data: &'a [u8] }
+//! #     pub struct Stat { pub received: u32, pub dropped: u32, pub if_dropped: u32 }
+//! #     #[derive(Debug)]
+//! #     pub enum Error { NoMorePackets, PcapError(String) }
+//! #     impl std::fmt::Display for Error {
+//! #         fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+//! #             write!(f, "{:?}", self)
+//! #         }
+//! #     }
+//! #     impl std::error::Error for Error {}
+//! #     impl Capture<Active> {
+//! #         pub fn next_packet(&mut self) -> Result<Packet<'_>, Error> { unimplemented!() }
+//! #         pub fn stats(&mut self) -> Result<Stat, Error> { unimplemented!() }
+//! #     }
+//! # }
+//! use pcapng_writer::capture::CaptureWriter;
+//! use pcapng_writer::enums::LinkType;
+//! use pcapng_writer::live::{self, LiveCapture, LivePacket, LiveStats};
+//! use std::fs::File;
+//! use std::time::{Duration, UNIX_EPOCH};
+//!
+//! struct Pcap(pcap::Capture<pcap::Active>);
+//!
+//! impl LiveCapture for Pcap {
+//!     type Error = pcap::Error;
+//!
+//!     fn next_packet(&mut self) -> Result<Option<LivePacket<'_>>, pcap::Error> {
+//!         match self.0.next_packet() {
+//!             Ok(packet) => {
+//!                 let ts = packet.header.ts;
+//!                 let since_epoch = Duration::new(ts.tv_sec as u64, ts.tv_usec as u32 * 1000);
+//!                 Ok(Some(LivePacket::new(
+//!                     UNIX_EPOCH + since_epoch,
+//!                     packet.header.len,
+//!                     packet.data,
+//!                 )))
+//!             }
+//!             Err(pcap::Error::NoMorePackets) => Ok(None),
+//!             Err(e) => Err(e),
+//!         }
+//!     }
+//!
+//!     fn stats(&mut self) -> Result<LiveStats, pcap::Error> {
+//!         let stat = self.0.stats()?;
+//!         Ok(LiveStats::new(
+//!             stat.received.into(),
+//!             stat.dropped.into(),
+//!             stat.if_dropped.into(),
+//!         ))
+//!     }
+//! }
+//!
+//! # fn run(capture: pcap::Capture<pcap::Active>) -> pcapng_writer::Result<()> {
+//! let mut writer = CaptureWriter::new(File::create("capture.pcapng")?)?;
+//! let eth = writer.add_interface(LinkType::Ethernet, 65535)?;
+//! live::record(&mut Pcap(capture), &mut writer, eth)?;
+//! # Ok(())
+//! # }
+//! # fn main() {}
+//! ```
+
+use crate::blocks::options::{OptionIsbIfDrop, OptionIsbIfRecv, OptionIsbOsDrop, Options};
+use crate::blocks::InterfaceStatisticsBlock;
+use crate::capture::{CaptureWriter, InterfaceCounters};
+use crate::error::{Error, Result};
+use crate::utils::nanos_since_epoch;
+use crate::writer::{InterfaceId, PacketRecord};
+use std::io::{self, Write};
+use std::time::SystemTime;
+
+/// A packet captured by a `LiveCapture`.
+#[derive(Debug, Clone, Copy)]
+pub struct LivePacket<'a> {
+    timestamp: SystemTime,
+    orig_len: u32,
+    data: &'a [u8],
+}
+
+impl<'a> LivePacket<'a> {
+    /// Creates a packet captured at `timestamp`, whose length on the
+    /// wire was `orig_len`.
+    pub fn new(timestamp: SystemTime, orig_len: u32, data: &'a [u8]) -> Self {
+        Self {
+            timestamp,
+            orig_len,
+            data,
+        }
+    }
+
+    pub fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    pub fn orig_len(&self) -> u32 {
+        self.orig_len
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns the record of the packet, as captured on `interface`.
+    pub fn to_record(&self, interface: InterfaceId) -> Result<PacketRecord<'a>> {
+        let nanos = nanos_since_epoch(self.timestamp)?;
+        Ok(PacketRecord::new(interface, nanos, self.data).with_orig_len(self.orig_len))
+    }
+}
+
+/// The statistics of a live capture, as reported by libpcap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LiveStats {
+    received: u64,
+    dropped: u64,
+    if_dropped: u64,
+}
+
+impl LiveStats {
+    /// Creates capture statistics: the number of packets received,
+    /// dropped by the operating system because there was no room in
+    /// the buffer, and dropped by the interface.
+    pub fn new(received: u64, dropped: u64, if_dropped: u64) -> Self {
+        Self {
+            received,
+            dropped,
+            if_dropped,
+        }
+    }
+
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn if_dropped(&self) -> u64 {
+        self.if_dropped
+    }
+}
+
+/// A source of live captured packets.
+pub trait LiveCapture {
+    type Error: Into<Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Returns the next captured packet, waiting for one if needed,
+    /// or `None` when the capture is over.
+    fn next_packet(&mut self) -> std::result::Result<Option<LivePacket<'_>>, Self::Error>;
+
+    /// Returns the statistics of the capture so far.
+    fn stats(&mut self) -> std::result::Result<LiveStats, Self::Error>;
+}
+
+/// Writes the packets of `source` as captured on `interface`, until
+/// `source` has no more packets, then writes an Interface Statistics
+/// Block with its statistics, and updates the `InterfaceCounters` of
+/// `interface` with them. Returns the number of packets written.
+///
+/// Errors of `source` are returned as `Error::Io`.
+pub fn record<C: LiveCapture, W: Write>(
+    source: &mut C,
+    capture: &mut CaptureWriter<W>,
+    interface: InterfaceId,
+) -> Result<u64> {
+    let mut packets = 0;
+    while let Some(packet) = source.next_packet().map_err(source_error)? {
+        capture.write_record(packet.to_record(interface)?)?;
+        packets += 1;
+    }
+    let stats = source.stats().map_err(source_error)?;
+    write_stats(capture, interface, &stats)?;
+    if let Some(counters) = capture.counters(interface) {
+        let counters =
+            InterfaceCounters::new(stats.received(), stats.if_dropped(), counters.accepted());
+        capture.set_counters(interface, counters)?;
+    }
+    Ok(packets)
+}
+
+/// Writes an Interface Statistics Block for `interface`, timestamped
+/// now, with `isb_ifrecv`, `isb_osdrop` and `isb_ifdrop` options for
+/// `stats`.
+pub fn write_stats<W: Write>(
+    capture: &mut CaptureWriter<W>,
+    interface: InterfaceId,
+    stats: &LiveStats,
+) -> Result<()> {
+    let writer = capture.get_writer_mut();
+    let tsresol = *writer
+        .interface(interface)
+        .ok_or_else(|| Error::UnknownInterface(interface.value()))?
+        .tsresol();
+    let recv = OptionIsbIfRecv::new_option(stats.received());
+    let os_drop = OptionIsbOsDrop::new_option(stats.dropped());
+    let if_drop = OptionIsbIfDrop::new_option(stats.if_dropped());
+    let mut options = Options::new();
+    options.add_option(&recv);
+    options.add_option(&os_drop);
+    options.add_option(&if_drop);
+    let isb = InterfaceStatisticsBlock::from_systemtime(
+        interface.value(),
+        &tsresol,
+        SystemTime::now(),
+        &options,
+    )?;
+    writer.write(&isb)
+}
+
+fn source_error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(e: E) -> Error {
+    Error::Io(io::Error::other(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::BlockOption;
+    use crate::enums::LinkType;
+    use crate::reader::{Block, SliceReader};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    struct Fake {
+        packets: Vec<Vec<u8>>,
+        next: usize,
+        fail: bool,
+    }
+
+    impl LiveCapture for Fake {
+        type Error = io::Error;
+
+        fn next_packet(&mut self) -> io::Result<Option<LivePacket<'_>>> {
+            if self.fail {
+                return Err(io::Error::other("device went away"));
+            }
+            let packet = self.packets.get(self.next).map(|data| {
+                let ts = UNIX_EPOCH + Duration::from_secs(self.next as u64 + 1);
+                LivePacket::new(ts, 1500, data)
+            });
+            self.next += 1;
+            Ok(packet)
+        }
+
+        fn stats(&mut self) -> io::Result<LiveStats> {
+            Ok(LiveStats::new(self.packets.len() as u64 + 3, 2, 1))
+        }
+    }
+
+    #[test]
+    fn record_packets_and_stats() {
+        let mut source = Fake {
+            packets: vec![vec![1; 60], vec![2; 70]],
+            next: 0,
+            fail: false,
+        };
+        let mut capture = CaptureWriter::new(vec![]).unwrap();
+        let eth = capture.add_interface(LinkType::Ethernet, 0).unwrap();
+        assert_eq!(record(&mut source, &mut capture, eth).unwrap(), 2);
+        let counters = InterfaceCounters::new(5, 1, 2);
+        assert_eq!(capture.counters(eth), Some(counters));
+
+        let buf = capture.into_inner().get_writer().clone();
+        let blocks: Vec<_> = SliceReader::new(&buf).map(|b| b.unwrap()).collect();
+        assert_eq!(blocks.len(), 5);
+        match &blocks[3] {
+            Block::EnhancedPacket(epb) => {
+                assert_eq!(epb.timestamp(), 2_000_000_000);
+                assert_eq!(epb.orig_len(), 1500);
+                assert_eq!(epb.packet_data(), &[2; 70][..]);
+            }
+            b => panic!("{:?}", b),
+        }
+        match &blocks[4] {
+            Block::InterfaceStatistics(isb) => {
+                let counts: Vec<_> = isb
+                    .options()
+                    .iter()
+                    .filter_map(|o| match o {
+                        BlockOption::IsbIfRecv(o) => Some(o.count()),
+                        BlockOption::IsbOsDrop(o) => Some(o.count()),
+                        BlockOption::IsbIfDrop(o) => Some(o.count()),
+                        _ => None,
+                    })
+                    .collect();
+                assert_eq!(counts, [5, 2, 1]);
+            }
+            b => panic!("{:?}", b),
+        }
+    }
+
+    #[test]
+    fn source_error() {
+        let mut source = Fake {
+            packets: vec![],
+            next: 0,
+            fail: true,
+        };
+        let mut capture = CaptureWriter::new(vec![]).unwrap();
+        let eth = capture.add_interface(LinkType::Ethernet, 0).unwrap();
+        assert!(matches!(
+            record(&mut source, &mut capture, eth),
+            Err(Error::Io(e)) if e.to_string() == "device went away"
+        ));
+    }
+}
@@ -0,0 +1,419 @@
+//! Merging captures.
+//!
+//! `Merger` reads blocks from several sources, e.g. `SliceReader`s,
+//! and writes their packets to a single section in chronological
+//! order, like mergecap. Each interface of each source section is
+//! described again in the output, with a new ID and the timestamp
+//! resolution of the merger:
+//!
+//! ```
+//! use pcapng_writer::blocks::options::Options;
+//! use pcapng_writer::enums::LinkType;
+//! use pcapng_writer::merge::Merger;
+//! use pcapng_writer::reader::SliceReader;
+//! use pcapng_writer::writer::{Endianness, PcapNgWriter};
+//!
+//! let mut inputs = vec![];
+//! for ts in &[2_000, 1_000] {
+//!     let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+//!     let eth = writer.add_interface(LinkType::Ethernet, 0, &Options::new()).unwrap();
+//!     writer.write_packet(eth, *ts, &[0; 60]).unwrap();
+//!     inputs.push(writer.get_writer().clone());
+//! }
+//!
+//! let mut output = PcapNgWriter::new_le(vec![]);
+//! let merger = Merger::new(inputs.iter().map(|input| SliceReader::new(input)));
+//! assert_eq!(merger.write_to(&mut output).unwrap(), 2);
+//! assert_eq!(output.interfaces().len(), 2);
+//! ```
+
+use crate::blocks::options::{
+    BlockOption, OptionIfTsResol, OptionIsbEndTime, OptionIsbStartTime, Options,
+};
+use crate::blocks::{InterfaceDescriptionBlock, InterfaceStatisticsBlock};
+use crate::enums::BlockType;
+use crate::error::{Error, Result};
+use crate::reader::Block;
+use crate::utils::{Timestamp, TimestampResolution, NANO_SECOND_TSRES};
+use crate::writer::{Endianness, InterfaceId, PcapNgWriter};
+use std::io::Write;
+
+/// Writes the blocks of several sources in chronological order.
+///
+/// Packets are ordered by timestamp; packets with the same timestamp
+/// are written in the order of their sources. Within a source, the
+/// order is kept, so sources should be sorted themselves. Simple
+/// Packet Blocks, which have no timestamp, get the timestamp of the
+/// previous packet of their source, and are written as Enhanced
+/// Packet Blocks.
+///
+/// Interface Statistics Blocks are merged like packets. Their
+/// `isb_starttime` and `isb_endtime` options are converted to the
+/// resolution of the merger; other options are copied as they are.
+///
+/// Blocks of unknown types are written as soon as they are read. Their
+/// bodies cannot be converted, so they are skipped when their section
+/// has a different byte order than the output.
+#[derive(Debug)]
+pub struct Merger<'a, I> {
+    sources: Vec<Source<'a, I>>,
+    tsresol: TimestampResolution,
+}
+
+#[derive(Debug)]
+struct Source<'a, I> {
+    blocks: I,
+    /// The next packet or statistics block, and its timestamp in
+    /// nanoseconds.
+    head: Option<(u128, Block<'a>)>,
+    /// The byte order of the current section.
+    endianness: Option<Endianness>,
+    /// The resolution and the output ID of the interfaces of the
+    /// current section.
+    interfaces: Vec<(TimestampResolution, InterfaceId)>,
+    last_timestamp: u128,
+}
+
+impl<'a, I: Iterator<Item = Result<Block<'a>>>> Merger<'a, I> {
+    /// Creates a merger for `sources`. The output has a nanosecond
+    /// resolution.
+    pub fn new<S: IntoIterator<Item = I>>(sources: S) -> Self {
+        Self {
+            sources: sources
+                .into_iter()
+                .map(|blocks| Source {
+                    blocks,
+                    head: None,
+                    endianness: None,
+                    interfaces: Vec::new(),
+                    last_timestamp: 0,
+                })
+                .collect(),
+            tsresol: *NANO_SECOND_TSRES,
+        }
+    }
+
+    /// Sets the timestamp resolution of the output interfaces.
+    pub fn set_tsresol(&mut self, tsresol: TimestampResolution) {
+        self.tsresol = tsresol;
+    }
+
+    /// Merges the sources into `writer`, and returns the number of
+    /// packets written. A section is started if nothing has been
+    /// written yet.
+    pub fn write_to<W: Write>(mut self, writer: &mut PcapNgWriter<W>) -> Result<u64> {
+        if writer.section_count() == 0 && writer.stats().blocks(BlockType::SectionHeader) == 0 {
+            writer.start_new_section(&Options::new())?;
+        }
+        for i in 0..self.sources.len() {
+            self.fill(i, writer)?;
+        }
+        let mut packets = 0;
+        loop {
+            let next = self
+                .sources
+                .iter()
+                .enumerate()
+                .filter_map(|(i, source)| source.head.as_ref().map(|(ts, _)| (*ts, i)))
+                .min();
+            let i = match next {
+                Some((_, i)) => i,
+                None => return Ok(packets),
+            };
+            let (timestamp, block) = self.sources[i].head.take().expect("head was found");
+            if self.write_block(i, timestamp, &block, writer)? {
+                packets += 1;
+            }
+            self.fill(i, writer)?;
+        }
+    }
+
+    /// Reads blocks from source `i` until its next timestamped block.
+    /// Interface descriptions are written on the way.
+    fn fill<W: Write>(&mut self, i: usize, writer: &mut PcapNgWriter<W>) -> Result<()> {
+        let tsresol = self.tsresol;
+        let source = &mut self.sources[i];
+        while let Some(block) = source.blocks.next() {
+            let block = block?;
+            let timestamp = match &block {
+                Block::SectionHeader(shb) => {
+                    source.endianness = Some(shb.endianness());
+                    source.interfaces.clear();
+                    continue;
+                }
+                Block::InterfaceDescription(idb) => {
+                    let options = idb.options();
+                    let mut out_options: Vec<_> = options
+                        .iter()
+                        .filter(|o| !matches!(o, BlockOption::IfTsResol(_)))
+                        .collect();
+                    let tsresol_option = OptionIfTsResol::new_option(&tsresol);
+                    // opt_endofopt, if any, stays last
+                    let end = match out_options.last() {
+                        Some(BlockOption::OptEndOfOpt(_)) => out_options.len() - 1,
+                        _ => out_options.len(),
+                    };
+                    out_options.insert(end, &tsresol_option);
+                    let mut out = Options::new();
+                    for option in out_options {
+                        out.add_option(option);
+                    }
+                    let out_idb = InterfaceDescriptionBlock::new(
+                        idb.link_type().into(),
+                        idb.snap_len(),
+                        &out,
+                    );
+                    let id = writer.add_interface_block(&out_idb)?;
+                    source.interfaces.push((idb.tsresol(), id));
+                    continue;
+                }
+                Block::EnhancedPacket(epb) => {
+                    let (tsresol, _) = source.interface(epb.interface_id())?;
+                    tsresol.nanoseconds_from_ticks(epb.timestamp())
+                }
+                Block::InterfaceStatistics(isb) => {
+                    let (tsresol, _) = source.interface(isb.interface_id())?;
+                    tsresol.nanoseconds_from_ticks(isb.timestamp())
+                }
+                Block::SimplePacket(_) => {
+                    source.interface(0)?;
+                    source.last_timestamp
+                }
+                Block::Unknown(raw) => {
+                    if source.endianness == Some(writer.endianness()) {
+                        writer.write(raw)?;
+                    }
+                    continue;
+                }
+            };
+            source.last_timestamp = timestamp;
+            source.head = Some((timestamp, block));
+            return Ok(());
+        }
+        Ok(())
+    }
+
+    /// Writes a timestamped block of source `i`. Returns true for
+    /// packets.
+    fn write_block<W: Write>(
+        &self,
+        i: usize,
+        timestamp: u128,
+        block: &Block,
+        writer: &mut PcapNgWriter<W>,
+    ) -> Result<bool> {
+        let source = &self.sources[i];
+        match block {
+            Block::EnhancedPacket(epb) => {
+                let (_, id) = source.interface(epb.interface_id())?;
+                let options = epb.options();
+                writer.write_packet_with_options(
+                    id,
+                    timestamp,
+                    epb.orig_len(),
+                    epb.packet_data(),
+                    &options,
+                )?;
+                Ok(true)
+            }
+            Block::SimplePacket(spb) => {
+                let (_, id) = source.interface(0)?;
+                writer.write_packet_with_options(
+                    id,
+                    timestamp,
+                    spb.orig_len(),
+                    spb.packet_data(),
+                    &Options::new(),
+                )?;
+                Ok(true)
+            }
+            Block::InterfaceStatistics(isb) => {
+                let (tsresol, id) = source.interface(isb.interface_id())?;
+                let (ts_high, ts_low) = self.tsresol.try_ts_from_nanoseconds(timestamp)?;
+                let options = isb
+                    .options()
+                    .iter()
+                    .map(|option| self.rescale(&tsresol, option))
+                    .collect::<Result<Options>>()?;
+                writer.write(&InterfaceStatisticsBlock::new(
+                    id.value(),
+                    ts_high,
+                    ts_low,
+                    &options,
+                ))?;
+                Ok(false)
+            }
+            _ => unreachable!("only timestamped blocks are merged"),
+        }
+    }
+
+    /// Converts a timestamp option from `tsresol` to the resolution of
+    /// the merger.
+    fn rescale(&self, tsresol: &TimestampResolution, option: &BlockOption) -> Result<BlockOption> {
+        let rescale = |timestamp: Timestamp| {
+            Timestamp::from_nanoseconds(&self.tsresol, timestamp.to_nanoseconds(tsresol))
+        };
+        Ok(match option {
+            BlockOption::IsbStartTime(o) => OptionIsbStartTime::new_option(rescale(o.timestamp())?),
+            BlockOption::IsbEndTime(o) => OptionIsbEndTime::new_option(rescale(o.timestamp())?),
+            option => option.clone(),
+        })
+    }
+}
+
+impl<I> Source<'_, I> {
+    fn interface(&self, id: u32) -> Result<(TimestampResolution, InterfaceId)> {
+        self.interfaces
+            .get(id as usize)
+            .copied()
+            .ok_or(Error::UnknownInterface(id))
+    }
+}
+
+/// Merges `sources` into `writer`, see `Merger`. Returns the number of
+/// packets written.
+pub fn merge<'a, I, S, W>(sources: S, writer: &mut PcapNgWriter<W>) -> Result<u64>
+where
+    I: Iterator<Item = Result<Block<'a>>>,
+    S: IntoIterator<Item = I>,
+    W: Write,
+{
+    Merger::new(sources).write_to(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::OptionIfName;
+    use crate::blocks::{EnhancedPacketBlock, RawBlock, SimplePacketBlock};
+    use crate::enums::LinkType;
+    use crate::reader::SliceReader;
+    use crate::utils::MICRO_SECOND_TSRES;
+    use crate::writer::Endianness;
+
+    /// Returns the interface and timestamp of each packet of `data`.
+    fn packets(data: &[u8]) -> Vec<(u32, u64)> {
+        SliceReader::new(data)
+            .filter_map(|block| match block.unwrap() {
+                Block::EnhancedPacket(epb) => Some((epb.interface_id(), epb.timestamp())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merge_sources() {
+        let opts = Options::new();
+        let name = [OptionIfName::new_option("a")];
+        let mut named = Options::new();
+        named.add_options(&name);
+        // microseconds, packets at 1, 4 and 4 us, then a simple packet
+        let mut a = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        let a0 = a.add_interface(LinkType::Ethernet, 0, &named).unwrap();
+        for ts in &[1_000, 4_000, 4_000] {
+            a.write_packet(a0, *ts, &[1]).unwrap();
+        }
+        a.write(&RawBlock::new(0x0bad, 12, 12, &[])).unwrap();
+        a.write(&SimplePacketBlock::new(1, &[2])).unwrap();
+        // nanoseconds, then a new section in big endian
+        let tsresol = [OptionIfTsResol::new_option(NANO_SECOND_TSRES)];
+        let mut nano = Options::new();
+        nano.add_options(&tsresol);
+        let mut b = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        let b0 = b.add_interface(LinkType::Raw, 0, &opts).unwrap();
+        let b1 = b.add_interface(LinkType::Raw, 0, &nano).unwrap();
+        b.write_packet(b1, 2_500, &[3]).unwrap();
+        b.write_packet(b0, 4_000, &[4]).unwrap();
+        b.start_new_section_with_endianness(Endianness::Big, &opts)
+            .unwrap();
+        let b2 = b.add_interface(LinkType::Raw, 0, &opts).unwrap();
+        b.write_packet(b2, 5_000, &[5]).unwrap();
+        // the start time is in microseconds
+        let start = [OptionIsbStartTime::new_option(Timestamp::new(4))];
+        let mut isb_opts = Options::new();
+        isb_opts.add_options(&start);
+        let isb = InterfaceStatisticsBlock::new(0, 0, 5, &isb_opts);
+        b.write(&isb).unwrap();
+        // not written, as the output is little endian
+        b.write(&RawBlock::new(0x0bad, 12, 12, &[])).unwrap();
+
+        let inputs = [a.get_writer().clone(), b.get_writer().clone()];
+        let mut output = PcapNgWriter::new_le(vec![]);
+        let packet_count = merge(
+            inputs.iter().map(|input| SliceReader::new(input)),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(packet_count, 7);
+        let interfaces = output.interfaces();
+        assert_eq!(interfaces.len(), 4);
+        assert!(interfaces.iter().all(|i| i.tsresol() == NANO_SECOND_TSRES));
+        let data = output.get_writer().clone();
+        // a0 = 0, b0 = 1, b1 = 2 and b2 = 3; the simple packet of a
+        // is the third one at 4 us
+        assert_eq!(
+            packets(&data),
+            [
+                (0, 1_000),
+                (2, 2_500),
+                (0, 4_000),
+                (0, 4_000),
+                (0, 4_000),
+                (1, 4_000),
+                (3, 5_000)
+            ]
+        );
+        let blocks: Vec<_> = SliceReader::new(&data).map(|b| b.unwrap()).collect();
+        match &blocks[1] {
+            Block::InterfaceDescription(idb) => {
+                let options = idb.options();
+                let options: Vec<_> = options.iter().collect();
+                assert!(matches!(options[0], BlockOption::IfName(_)));
+                assert!(matches!(options[1], BlockOption::IfTsResol(_)));
+            }
+            b => panic!("{:?}", b),
+        }
+        let unknown = blocks.iter().filter(|b| matches!(b, Block::Unknown(_)));
+        assert_eq!(unknown.count(), 1);
+        match blocks.last().unwrap() {
+            Block::InterfaceStatistics(isb) => {
+                assert_eq!(isb.interface_id(), 3);
+                assert_eq!(isb.timestamp(), 5_000);
+                let options = isb.options();
+                assert!(matches!(
+                    options.iter().next(),
+                    Some(BlockOption::IsbStartTime(o)) if o.timestamp().ticks() == 4_000
+                ));
+            }
+            b => panic!("{:?}", b),
+        }
+    }
+
+    #[test]
+    fn set_tsresol() {
+        let opts = Options::new();
+        let mut a = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        let a0 = a.add_interface(LinkType::Ethernet, 0, &opts).unwrap();
+        a.write_packet(a0, 1_234_567, &[1]).unwrap();
+        let input = a.get_writer().clone();
+        let mut merger = Merger::new(vec![SliceReader::new(&input)]);
+        merger.set_tsresol(*MICRO_SECOND_TSRES);
+        let mut output = PcapNgWriter::new_le(vec![]);
+        merger.write_to(&mut output).unwrap();
+        assert_eq!(packets(output.get_writer()), [(0, 1_234)]);
+    }
+
+    #[test]
+    fn unknown_interface() {
+        let opts = Options::new();
+        let mut a = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        a.write(&EnhancedPacketBlock::new(0, 0, 0, 0, 0, &[], &opts))
+            .unwrap();
+        let input = a.get_writer().clone();
+        let mut output = PcapNgWriter::new_le(vec![]);
+        assert!(matches!(
+            merge(vec![SliceReader::new(&input)], &mut output),
+            Err(Error::UnknownInterface(0))
+        ));
+    }
+}
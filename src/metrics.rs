@@ -0,0 +1,260 @@
+//! Monitoring of long-running writers.
+//!
+//! A `Metrics` implementation set with `PcapNgWriter::set_metrics` is
+//! told about the packets and bytes written, the blocks dropped by the
+//! block pipeline or `RateLimitedWriter`, and the blocks that could
+//! not be written. All the methods do nothing by default, so an
+//! implementation only overrides those it exports.
+//!
+//! Metrics are shared with `Arc`, e.g. to also count in a pipeline
+//! stage, or to read the counters while the writer runs:
+//!
+//! ```
+//! use pcapng_writer::enums::LinkType;
+//! use pcapng_writer::metrics::Metrics;
+//! use pcapng_writer::writer::{Endianness, PcapNgWriter};
+//! use std::sync::atomic::{AtomicU64, Ordering};
+//! use std::sync::Arc;
+//!
+//! #[derive(Default)]
+//! struct Packets(AtomicU64);
+//!
+//! impl Metrics for Packets {
+//!     fn packets_written(&self, count: u64) {
+//!         self.0.fetch_add(count, Ordering::Relaxed);
+//!     }
+//! }
+//!
+//! let packets = Arc::new(Packets::default());
+//! let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+//! writer.set_metrics(packets.clone());
+//! let eth = writer.add_interface(LinkType::Ethernet, 65535, &Default::default())?;
+//! writer.write_packet(eth, 0, &[0; 60])?;
+//! assert_eq!(packets.0.load(Ordering::Relaxed), 1);
+//! # Ok::<(), pcapng_writer::error::Error>(())
+//! ```
+//!
+//! With the `prometheus` feature, `PrometheusMetrics` exports the
+//! counters to a `prometheus::Registry`.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::fmt;
+
+/// Receives the counts of a writer. The counts are increments, not
+/// totals.
+pub trait Metrics: Send + Sync {
+    /// Enhanced or Simple Packet Blocks were written.
+    fn packets_written(&self, count: u64) {
+        let _ = count;
+    }
+
+    /// Bytes were written, including those of blocks that are not
+    /// packets.
+    fn bytes_written(&self, count: u64) {
+        let _ = count;
+    }
+
+    /// Blocks or packets were dropped before they were written.
+    fn dropped(&self, count: u64) {
+        let _ = count;
+    }
+
+    /// Writing failed, or a block was refused by strict validation.
+    fn errors(&self, count: u64) {
+        let _ = count;
+    }
+}
+
+/// The metrics of a writer without `set_metrics`; does nothing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoMetrics;
+
+impl Metrics for NoMetrics {}
+
+impl<M: Metrics + ?Sized> Metrics for Arc<M> {
+    fn packets_written(&self, count: u64) {
+        (**self).packets_written(count)
+    }
+
+    fn bytes_written(&self, count: u64) {
+        (**self).bytes_written(count)
+    }
+
+    fn dropped(&self, count: u64) {
+        (**self).dropped(count)
+    }
+
+    fn errors(&self, count: u64) {
+        (**self).errors(count)
+    }
+}
+
+impl<M: Metrics + ?Sized> Metrics for Box<M> {
+    fn packets_written(&self, count: u64) {
+        (**self).packets_written(count)
+    }
+
+    fn bytes_written(&self, count: u64) {
+        (**self).bytes_written(count)
+    }
+
+    fn dropped(&self, count: u64) {
+        (**self).dropped(count)
+    }
+
+    fn errors(&self, count: u64) {
+        (**self).errors(count)
+    }
+}
+
+impl fmt::Debug for dyn Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Metrics")
+    }
+}
+
+/// Counters of a `prometheus::Registry`:
+/// `pcapng_packets_written_total`, `pcapng_bytes_written_total`,
+/// `pcapng_dropped_total` and `pcapng_errors_total`.
+#[cfg(all(feature = "prometheus", feature = "std"))]
+#[derive(Debug, Clone)]
+pub struct PrometheusMetrics {
+    packets: prometheus::IntCounter,
+    bytes: prometheus::IntCounter,
+    dropped: prometheus::IntCounter,
+    errors: prometheus::IntCounter,
+}
+
+#[cfg(all(feature = "prometheus", feature = "std"))]
+impl PrometheusMetrics {
+    /// Creates the counters and registers them with `registry`.
+    pub fn new(registry: &prometheus::Registry) -> prometheus::Result<Self> {
+        let counter = |name: &str, help: &str| -> prometheus::Result<prometheus::IntCounter> {
+            let counter = prometheus::IntCounter::new(name, help)?;
+            registry.register(Box::new(counter.clone()))?;
+            Ok(counter)
+        };
+        Ok(Self {
+            packets: counter("pcapng_packets_written_total", "Packets written")?,
+            bytes: counter("pcapng_bytes_written_total", "Bytes written")?,
+            dropped: counter(
+                "pcapng_dropped_total",
+                "Blocks dropped before being written",
+            )?,
+            errors: counter("pcapng_errors_total", "Blocks that could not be written")?,
+        })
+    }
+}
+
+#[cfg(all(feature = "prometheus", feature = "std"))]
+impl Metrics for PrometheusMetrics {
+    fn packets_written(&self, count: u64) {
+        self.packets.inc_by(count);
+    }
+
+    fn bytes_written(&self, count: u64) {
+        self.bytes.inc_by(count);
+    }
+
+    fn dropped(&self, count: u64) {
+        self.dropped.inc_by(count);
+    }
+
+    fn errors(&self, count: u64) {
+        self.errors.inc_by(count);
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::enums::LinkType;
+    use crate::pipeline::{EncodedBlock, Verdict};
+    use crate::writer::{Endianness, PcapNgWriter, Validation};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[derive(Debug, Default)]
+    struct Counts {
+        packets: AtomicU64,
+        bytes: AtomicU64,
+        dropped: AtomicU64,
+        errors: AtomicU64,
+    }
+
+    impl Metrics for Counts {
+        fn packets_written(&self, count: u64) {
+            self.packets.fetch_add(count, Ordering::Relaxed);
+        }
+
+        fn bytes_written(&self, count: u64) {
+            self.bytes.fetch_add(count, Ordering::Relaxed);
+        }
+
+        fn dropped(&self, count: u64) {
+            self.dropped.fetch_add(count, Ordering::Relaxed);
+        }
+
+        fn errors(&self, count: u64) {
+            self.errors.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+
+    impl Counts {
+        fn get(&self) -> [u64; 4] {
+            [
+                self.packets.load(Ordering::Relaxed),
+                self.bytes.load(Ordering::Relaxed),
+                self.dropped.load(Ordering::Relaxed),
+                self.errors.load(Ordering::Relaxed),
+            ]
+        }
+    }
+
+    #[test]
+    fn writer() {
+        let counts = Arc::new(Counts::default());
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        writer.set_metrics(counts.clone());
+        writer.add_filter(|block: &mut EncodedBlock| match block.packet_data() {
+            Some(data) if data[0] == 0 => Verdict::Drop,
+            _ => Verdict::Keep,
+        });
+        let eth = writer
+            .add_interface(LinkType::Ethernet, 65535, &Default::default())
+            .unwrap();
+        writer.write_packet(eth, 0, &[1; 60]).unwrap();
+        writer.write_packet(eth, 0, &[0; 60]).unwrap();
+        writer.set_validation(Validation::Strict);
+        writer.write_packet(eth, 0, &[1; 70000]).unwrap_err();
+        writer.close().unwrap();
+        let bytes = writer.stats().bytes();
+        assert_eq!(counts.get(), [1, bytes, 1, 1]);
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[test]
+    fn prometheus() {
+        let registry = prometheus::Registry::new();
+        let metrics = PrometheusMetrics::new(&registry).unwrap();
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        writer.set_metrics(metrics);
+        let eth = writer
+            .add_interface(LinkType::Ethernet, 65535, &Default::default())
+            .unwrap();
+        writer.write_packet(eth, 0, &[1; 60]).unwrap();
+        let families = registry.gather();
+        let value = |name: &str| {
+            families
+                .iter()
+                .find(|family| family.name() == name)
+                .map(|family| family.get_metric()[0].get_counter().get_value())
+        };
+        assert_eq!(value("pcapng_packets_written_total"), Some(1.0));
+        assert_eq!(
+            value("pcapng_bytes_written_total"),
+            Some(writer.stats().bytes() as f64)
+        );
+        assert!(PrometheusMetrics::new(&registry).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! Name resolution records for captures.
+//!
+//! `PcapNgWriter::add_name` registers the name of an address, e.g.
+//! from the application's own DNS lookups, in the writer's
+//! `NameCache`. The names are written in batches as Name Resolution
+//! Blocks, so readers such as Wireshark can show them without
+//! resolving the addresses again:
+//!
+//! ```
+//! use pcapng_writer::blocks::options::Options;
+//! use pcapng_writer::enums::LinkType;
+//! use pcapng_writer::writer::{Endianness, PcapNgWriter};
+//! use std::net::Ipv4Addr;
+//! use std::time::Duration;
+//!
+//! let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+//! writer.set_names_interval(Some(Duration::from_secs(10)));
+//! let eth = writer.add_interface(LinkType::Ethernet, 0, &Options::new()).unwrap();
+//! writer.add_name(Ipv4Addr::new(192, 0, 2, 1).into(), "example.com").unwrap();
+//! writer.write_packet(eth, 0, &[0; 60]).unwrap();
+//! // the names are written at the latest when the writer is closed
+//! writer.close().unwrap();
+//! ```
+
+use crate::blocks::options::{check_len, OptionError};
+use crate::blocks::NameRecord;
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
+
+/// The names of addresses, and which of them have been written in
+/// the current section.
+#[derive(Debug, Clone, Default)]
+pub struct NameCache {
+    entries: HashMap<IpAddr, Entry>,
+    /// The addresses with names that have not been written yet, in
+    /// the order of their records.
+    pending: BTreeSet<IpAddr>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Entry {
+    names: Vec<String>,
+    written: usize,
+}
+
+impl NameCache {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a name of `addr`. Returns whether it is new, i.e. whether
+    /// it will be written.
+    pub fn add(&mut self, addr: IpAddr, name: &str) -> Result<bool, OptionError> {
+        // the name must fit in a record by itself
+        check_len(addr_len(addr) + name.len() + 1)?;
+        let entry = self.entries.entry(addr).or_default();
+        if entry.names.iter().any(|n| n == name) {
+            return Ok(false);
+        }
+        entry.names.push(name.to_string());
+        self.pending.insert(addr);
+        Ok(true)
+    }
+
+    /// Returns the names of `addr`, in the order they were added.
+    pub fn names(&self, addr: IpAddr) -> &[String] {
+        self.entries
+            .get(&addr)
+            .map_or(&[][..], |entry| &entry.names)
+    }
+
+    /// Returns the number of addresses with names.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the number of addresses with names that have not been
+    /// written yet.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns the records of the names that have not been written
+    /// yet, and marks them as written. Names that don't fit in one
+    /// record are split into several.
+    pub fn take_records(&mut self) -> Vec<NameRecord> {
+        let mut records = vec![];
+        for addr in std::mem::take(&mut self.pending) {
+            let entry = match self.entries.get_mut(&addr) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let mut names: Vec<&str> = vec![];
+            let mut len = addr_len(addr);
+            for name in &entry.names[entry.written..] {
+                if check_len(len + name.len() + 1).is_err() {
+                    records.push(NameRecord::new(addr, &names));
+                    names.clear();
+                    len = addr_len(addr);
+                }
+                names.push(name);
+                len += name.len() + 1;
+            }
+            records.push(NameRecord::new(addr, &names));
+            entry.written = entry.names.len();
+        }
+        records
+    }
+
+    /// Marks all the names as not written, e.g. for a new section.
+    pub fn reset(&mut self) {
+        for entry in self.entries.values_mut() {
+            entry.written = 0;
+        }
+        self.pending = self.entries.keys().copied().collect();
+    }
+}
+
+fn addr_len(addr: IpAddr) -> usize {
+    match addr {
+        IpAddr::V4(_) => 4,
+        IpAddr::V6(_) => 16,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn take_records() {
+        let v4 = IpAddr::from(Ipv4Addr::new(192, 0, 2, 1));
+        let v6 = IpAddr::from(Ipv6Addr::LOCALHOST);
+        let mut cache = NameCache::new();
+        assert!(cache.add(v6, "localhost").unwrap());
+        assert!(cache.add(v4, "a.example").unwrap());
+        assert!(!cache.add(v4, "a.example").unwrap());
+        assert_eq!(cache.pending(), 2);
+        assert_eq!(
+            cache.take_records(),
+            [
+                NameRecord::new(v4, &["a.example"]),
+                NameRecord::new(v6, &["localhost"])
+            ]
+        );
+        assert!(cache.take_records().is_empty());
+
+        // only the new names are written
+        cache.add(v4, "b.example").unwrap();
+        assert_eq!(cache.take_records(), [NameRecord::new(v4, &["b.example"])]);
+        assert_eq!(cache.names(v4), ["a.example", "b.example"]);
+
+        cache.reset();
+        assert_eq!(cache.pending(), 2);
+        assert_eq!(cache.take_records().len(), 2);
+    }
+
+    #[test]
+    fn long_names() {
+        let addr = IpAddr::from(Ipv4Addr::LOCALHOST);
+        let mut cache = NameCache::new();
+        let name = "a".repeat(40_000);
+        cache.add(addr, &name).unwrap();
+        cache.add(addr, &name[1..]).unwrap();
+        assert_eq!(cache.take_records().len(), 2);
+        assert!(matches!(
+            cache.add(addr, &"a".repeat(0xFFFF)),
+            Err(OptionError::ValueTooLong(_))
+        ));
+    }
+}
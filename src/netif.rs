@@ -0,0 +1,457 @@
+//! Describing the network interfaces of the system.
+//!
+//! `SystemInterface::lookup`, or `InterfaceDescriptionBlock::from_system`,
+//! reads the MAC address, IP addresses and speed of a network
+//! interface from the operating system: with `getifaddrs` on Linux,
+//! macOS and the BSDs, and with `GetAdaptersAddresses` on Windows.
+//! `SystemInterface::idb_options` returns the `if_name`,
+//! `if_MACaddr`, `if_IPv4addr`, `if_IPv6addr` and `if_speed` options
+//! of an Interface Description Block for it:
+//!
+//! ```no_run
+//! use pcapng_writer::blocks::options::Options;
+//! use pcapng_writer::blocks::InterfaceDescriptionBlock;
+//! use pcapng_writer::enums::LinkType;
+//! use pcapng_writer::writer::{Endianness, PcapNgWriter};
+//!
+//! let eth0 = InterfaceDescriptionBlock::from_system("eth0")?;
+//! let idb_options = eth0.idb_options();
+//! let mut options = Options::new();
+//! options.add_options(&idb_options);
+//! let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+//! let id = writer.add_interface(LinkType::Ethernet, 65535, &options)?;
+//! # Ok::<(), pcapng_writer::error::Error>(())
+//! ```
+//!
+//! Everything but `lookup` is available on all platforms. The speed
+//! is only read on Linux and Windows.
+
+use crate::blocks::options::{
+    BlockOption, OptionIfIpv4Addr, OptionIfIpv6Addr, OptionIfMacAddr, OptionIfName, OptionIfSpeed,
+};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// A network interface of the system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemInterface {
+    name: String,
+    mac_addr: Option<[u8; 6]>,
+    ipv4_addrs: Vec<(Ipv4Addr, Ipv4Addr)>,
+    ipv6_addrs: Vec<(Ipv6Addr, u8)>,
+    speed: Option<u64>,
+}
+
+impl SystemInterface {
+    /// Creates an interface without addresses.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            mac_addr: None,
+            ipv4_addrs: Vec::new(),
+            ipv6_addrs: Vec::new(),
+            speed: None,
+        }
+    }
+
+    /// Reads the interface `name` from the operating system. On
+    /// Windows, `name` is the friendly name of an adapter, e.g.
+    /// "Ethernet", or its GUID. Returns an error of kind `NotFound`
+    /// if there is no such interface.
+    #[cfg(any(unix, windows))]
+    pub fn lookup(name: &str) -> std::io::Result<Self> {
+        sys::lookup(name)
+    }
+
+    pub fn set_mac_addr(&mut self, mac_addr: [u8; 6]) {
+        self.mac_addr = Some(mac_addr);
+    }
+
+    pub fn add_ipv4_addr(&mut self, ip: Ipv4Addr, netmask: Ipv4Addr) {
+        self.ipv4_addrs.push((ip, netmask));
+    }
+
+    pub fn add_ipv6_addr(&mut self, ip: Ipv6Addr, prefix_len: u8) {
+        self.ipv6_addrs.push((ip, prefix_len));
+    }
+
+    /// Sets the speed of the interface, in bits per second.
+    pub fn set_speed(&mut self, speed: u64) {
+        self.speed = Some(speed);
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn mac_addr(&self) -> Option<[u8; 6]> {
+        self.mac_addr
+    }
+
+    pub fn ipv4_addrs(&self) -> &[(Ipv4Addr, Ipv4Addr)] {
+        &self.ipv4_addrs
+    }
+
+    pub fn ipv6_addrs(&self) -> &[(Ipv6Addr, u8)] {
+        &self.ipv6_addrs
+    }
+
+    /// Returns the speed of the interface in bits per second, if
+    /// known.
+    pub fn speed(&self) -> Option<u64> {
+        self.speed
+    }
+
+    /// Returns the options of an Interface Description Block for the
+    /// interface. IPv6 addresses with an invalid prefix length are
+    /// left out.
+    pub fn idb_options(&self) -> Vec<BlockOption> {
+        let mut options = vec![OptionIfName::new_option(&self.name)];
+        if let Some(mac_addr) = self.mac_addr {
+            options.push(OptionIfMacAddr::from_bytes_option(mac_addr));
+        }
+        for &(ip, netmask) in &self.ipv4_addrs {
+            options.push(OptionIfIpv4Addr::from_addrs_option(ip, netmask));
+        }
+        for &(ip, prefix_len) in &self.ipv6_addrs {
+            if let Ok(option) = OptionIfIpv6Addr::from_addr_option(ip, prefix_len) {
+                options.push(option);
+            }
+        }
+        if let Some(speed) = self.speed {
+            options.push(OptionIfSpeed::new_option(speed));
+        }
+        options
+    }
+}
+
+fn not_found(name: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("no network interface named {}", name),
+    )
+}
+
+#[cfg(unix)]
+mod sys {
+    use super::{not_found, SystemInterface};
+    use std::convert::TryFrom;
+    use std::ffi::CStr;
+    use std::io;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::ptr;
+
+    pub fn lookup(name: &str) -> io::Result<SystemInterface> {
+        let mut interface = SystemInterface::new(name);
+        let mut found = false;
+        let mut addrs = ptr::null_mut();
+        // SAFETY: getifaddrs sets addrs to a list on success.
+        if unsafe { libc::getifaddrs(&mut addrs) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut ifa = addrs;
+        // SAFETY: the list returned by getifaddrs is valid until it is
+        // freed.
+        unsafe {
+            while !ifa.is_null() {
+                let entry = &*ifa;
+                ifa = entry.ifa_next;
+                if entry.ifa_name.is_null()
+                    || CStr::from_ptr(entry.ifa_name).to_bytes() != name.as_bytes()
+                {
+                    continue;
+                }
+                found = true;
+                if !entry.ifa_addr.is_null() {
+                    add_addr(&mut interface, entry.ifa_addr, entry.ifa_netmask);
+                }
+            }
+            libc::freeifaddrs(addrs);
+        }
+        if !found {
+            return Err(not_found(name));
+        }
+        if let Some(speed) = speed(name) {
+            interface.set_speed(speed);
+        }
+        Ok(interface)
+    }
+
+    unsafe fn add_addr(
+        interface: &mut SystemInterface,
+        addr: *const libc::sockaddr,
+        netmask: *const libc::sockaddr,
+    ) {
+        match i32::from((*addr).sa_family) {
+            libc::AF_INET if !netmask.is_null() => {
+                let ip = (*(addr as *const libc::sockaddr_in)).sin_addr.s_addr;
+                let netmask = (*(netmask as *const libc::sockaddr_in)).sin_addr.s_addr;
+                interface.add_ipv4_addr(
+                    Ipv4Addr::from(ip.to_ne_bytes()),
+                    Ipv4Addr::from(netmask.to_ne_bytes()),
+                );
+            }
+            libc::AF_INET6 if !netmask.is_null() => {
+                let ip = (*(addr as *const libc::sockaddr_in6)).sin6_addr.s6_addr;
+                let netmask = (*(netmask as *const libc::sockaddr_in6)).sin6_addr.s6_addr;
+                let prefix_len = netmask.iter().map(|b| b.count_ones()).sum::<u32>();
+                interface.add_ipv6_addr(Ipv6Addr::from(ip), prefix_len as u8);
+            }
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            libc::AF_PACKET => {
+                let ll = &*(addr as *const libc::sockaddr_ll);
+                if ll.sll_halen == 6 {
+                    set_mac_addr(interface, &ll.sll_addr[..6]);
+                }
+            }
+            #[cfg(any(
+                target_os = "macos",
+                target_os = "ios",
+                target_os = "freebsd",
+                target_os = "openbsd",
+                target_os = "netbsd",
+                target_os = "dragonfly"
+            ))]
+            libc::AF_LINK => {
+                let dl = addr as *const libc::sockaddr_dl;
+                if (*dl).sdl_alen == 6 {
+                    // the address follows the name, and may extend past
+                    // the end of sdl_data
+                    let data = (*dl).sdl_data.as_ptr() as *const u8;
+                    let mac =
+                        ptr::read_unaligned(data.add((*dl).sdl_nlen.into()) as *const [u8; 6]);
+                    set_mac_addr(interface, &mac);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Sets the MAC address, unless it is all zeros as for loopback
+    /// interfaces.
+    #[allow(dead_code)]
+    fn set_mac_addr(interface: &mut SystemInterface, mac: &[u8]) {
+        if let Ok(mac) = <[u8; 6]>::try_from(mac) {
+            if mac != [0; 6] {
+                interface.set_mac_addr(mac);
+            }
+        }
+    }
+
+    /// Returns the speed of the interface in bits per second, from
+    /// its speed in Mbit/s in sysfs, which is -1 or can't be read if
+    /// unknown.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn speed(name: &str) -> Option<u64> {
+        let path = format!("/sys/class/net/{}/speed", name);
+        let mbps: u64 = std::fs::read_to_string(path).ok()?.trim().parse().ok()?;
+        mbps.checked_mul(1_000_000).filter(|&speed| speed > 0)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn speed(_name: &str) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use super::{not_found, SystemInterface};
+    use std::convert::TryInto;
+    use std::ffi::CStr;
+    use std::io;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::os::raw::{c_char, c_int, c_uchar, c_ulong, c_ushort, c_void};
+    use std::ptr;
+
+    const AF_INET: c_ushort = 2;
+    const AF_INET6: c_ushort = 23;
+    const ERROR_BUFFER_OVERFLOW: c_ulong = 111;
+
+    /// `IP_ADAPTER_ADDRESSES`, up to the link speeds.
+    #[repr(C)]
+    struct IpAdapterAddresses {
+        length: c_ulong,
+        if_index: c_ulong,
+        next: *mut IpAdapterAddresses,
+        adapter_name: *mut c_char,
+        first_unicast_address: *mut IpAdapterUnicastAddress,
+        first_anycast_address: *mut c_void,
+        first_multicast_address: *mut c_void,
+        first_dns_server_address: *mut c_void,
+        dns_suffix: *mut u16,
+        description: *mut u16,
+        friendly_name: *mut u16,
+        physical_address: [c_uchar; 8],
+        physical_address_length: c_ulong,
+        flags: c_ulong,
+        mtu: c_ulong,
+        if_type: c_ulong,
+        oper_status: c_int,
+        ipv6_if_index: c_ulong,
+        zone_indices: [c_ulong; 16],
+        first_prefix: *mut c_void,
+        transmit_link_speed: u64,
+        receive_link_speed: u64,
+    }
+
+    /// `IP_ADAPTER_UNICAST_ADDRESS`, up to the prefix length.
+    #[repr(C)]
+    struct IpAdapterUnicastAddress {
+        length: c_ulong,
+        flags: c_ulong,
+        next: *mut IpAdapterUnicastAddress,
+        sockaddr: *mut SockAddr,
+        sockaddr_length: c_int,
+        prefix_origin: c_int,
+        suffix_origin: c_int,
+        dad_state: c_int,
+        valid_lifetime: c_ulong,
+        preferred_lifetime: c_ulong,
+        lease_lifetime: c_ulong,
+        on_link_prefix_length: u8,
+    }
+
+    /// The start of `sockaddr_in` and `sockaddr_in6`.
+    #[repr(C)]
+    struct SockAddr {
+        family: c_ushort,
+        port: c_ushort,
+        data: [u8; 24],
+    }
+
+    #[link(name = "iphlpapi")]
+    extern "system" {
+        fn GetAdaptersAddresses(
+            family: c_ulong,
+            flags: c_ulong,
+            reserved: *mut c_void,
+            addresses: *mut IpAdapterAddresses,
+            size: *mut c_ulong,
+        ) -> c_ulong;
+    }
+
+    pub fn lookup(name: &str) -> io::Result<SystemInterface> {
+        let mut size: c_ulong = 16 * 1024;
+        loop {
+            // u64 for the alignment of the structure
+            let mut buf = vec![0u64; size as usize / 8 + 1];
+            let first = buf.as_mut_ptr() as *mut IpAdapterAddresses;
+            // SAFETY: buf holds at least size bytes.
+            let result = unsafe { GetAdaptersAddresses(0, 0, ptr::null_mut(), first, &mut size) };
+            if result == ERROR_BUFFER_OVERFLOW {
+                continue;
+            }
+            if result != 0 {
+                return Err(io::Error::from_raw_os_error(result as i32));
+            }
+            let mut adapter = first;
+            // SAFETY: the list is in buf, which is still alive.
+            unsafe {
+                while !adapter.is_null() {
+                    let a = &*adapter;
+                    if matches(a, name) {
+                        return Ok(interface(a, name));
+                    }
+                    adapter = a.next;
+                }
+            }
+            return Err(not_found(name));
+        }
+    }
+
+    unsafe fn matches(adapter: &IpAdapterAddresses, name: &str) -> bool {
+        let guid = !adapter.adapter_name.is_null()
+            && CStr::from_ptr(adapter.adapter_name).to_bytes() == name.as_bytes();
+        guid || wide_string(adapter.friendly_name).as_deref() == Some(name)
+    }
+
+    unsafe fn interface(adapter: &IpAdapterAddresses, name: &str) -> SystemInterface {
+        let mut interface = SystemInterface::new(name);
+        if adapter.physical_address_length == 6 {
+            interface.set_mac_addr(adapter.physical_address[..6].try_into().unwrap());
+        }
+        let mut unicast = adapter.first_unicast_address;
+        while !unicast.is_null() {
+            let u = &*unicast;
+            unicast = u.next;
+            if u.sockaddr.is_null() {
+                continue;
+            }
+            let addr = &*u.sockaddr;
+            let prefix_len = u.on_link_prefix_length;
+            match addr.family {
+                AF_INET => {
+                    let ip: [u8; 4] = addr.data[..4].try_into().unwrap();
+                    let netmask = u32::MAX
+                        .checked_shl(32 - u32::from(prefix_len.min(32)))
+                        .unwrap_or(0);
+                    interface.add_ipv4_addr(Ipv4Addr::from(ip), Ipv4Addr::from(netmask));
+                }
+                AF_INET6 => {
+                    // sin6_flowinfo comes before sin6_addr
+                    let ip: [u8; 16] = addr.data[4..20].try_into().unwrap();
+                    interface.add_ipv6_addr(Ipv6Addr::from(ip), prefix_len);
+                }
+                _ => (),
+            }
+        }
+        // u64::MAX if the speed is unknown
+        if adapter.transmit_link_speed != 0 && adapter.transmit_link_speed != u64::MAX {
+            interface.set_speed(adapter.transmit_link_speed);
+        }
+        interface
+    }
+
+    unsafe fn wide_string(s: *const u16) -> Option<String> {
+        if s.is_null() {
+            return None;
+        }
+        let mut len = 0;
+        while *s.add(len) != 0 {
+            len += 1;
+        }
+        Some(String::from_utf16_lossy(std::slice::from_raw_parts(s, len)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::Options;
+    use crate::blocks::InterfaceDescriptionBlock;
+    use crate::enums::LinkType;
+    use crate::writer::EncodedLen;
+
+    #[test]
+    fn idb_options() {
+        let mut eth0 = SystemInterface::new("eth0");
+        eth0.set_mac_addr([0, 1, 2, 3, 4, 5]);
+        eth0.add_ipv4_addr(Ipv4Addr::new(192, 0, 2, 2), Ipv4Addr::new(255, 255, 255, 0));
+        eth0.add_ipv6_addr(Ipv6Addr::LOCALHOST, 128);
+        eth0.add_ipv6_addr(Ipv6Addr::LOCALHOST, 129);
+        eth0.set_speed(1_000_000_000);
+        let idb_options = eth0.idb_options();
+        let codes: Vec<_> = idb_options.iter().map(|o| o.code()).collect();
+        assert_eq!(codes, [2, 6, 4, 5, 8]);
+        let mut options = Options::new();
+        options.add_options(&idb_options);
+        let idb = InterfaceDescriptionBlock::new(LinkType::Ethernet, 0, &options);
+        // if_name, if_MACaddr, if_IPv4addr, if_IPv6addr and if_speed
+        assert_eq!(idb.encoded_len(), 20 + 8 + 12 + 12 + 24 + 12);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn lookup() {
+        let lo = InterfaceDescriptionBlock::from_system("lo").unwrap();
+        assert_eq!(lo.name(), "lo");
+        assert_eq!(lo.mac_addr(), None);
+        assert!(lo
+            .ipv4_addrs()
+            .contains(&(Ipv4Addr::LOCALHOST, Ipv4Addr::new(255, 0, 0, 0))));
+        assert!(matches!(
+            SystemInterface::lookup("no-such-interface"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound
+        ));
+    }
+}
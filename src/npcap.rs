@@ -0,0 +1,508 @@
+//! Capturing with Npcap on Windows.
+//!
+//! `adapters` lists the capture adapters, with the addresses Npcap
+//! reports and the MAC address Windows reports for them. An
+//! `Adapter` describes an interface with `if_name`,
+//! `if_description`, `if_MACaddr`, `if_IPv4addr` and `if_IPv6addr`
+//! options, and `NpcapCapture` streams its packets to `live::record`:
+//!
+#![cfg_attr(windows, doc = "```no_run")]
+#![cfg_attr(not(windows), doc = "```ignore")]
+//! use pcapng_writer::capture::CaptureWriter;
+//! use pcapng_writer::live;
+//! use pcapng_writer::npcap::{self, NpcapCapture};
+//!
+//! # fn main() -> pcapng_writer::Result<()> {
+//! let adapter = npcap::adapters()?.remove(0);
+//! let mut source = NpcapCapture::open(&adapter, 65535, true)?;
+//! let mut capture = CaptureWriter::new(std::fs::File::create("out.pcapng")?)?;
+//! let id = npcap::add_interface(&mut capture, &adapter, source.link_type(), 65535)?;
+//! live::record(&mut source, &mut capture, id)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Npcap installs `wpcap.dll` in `System32\Npcap`, which programs
+//! have to add to the DLL search path, e.g. with `SetDllDirectoryW`,
+//! or load with delay-loading.
+//!
+//! Everything but `adapters` and `NpcapCapture` is available on all
+//! platforms.
+
+use crate::blocks::options::{
+    BlockOption, OptionIfDescription, OptionIfIpv4Addr, OptionIfIpv6Addr, OptionIfMacAddr,
+    OptionIfName, Options,
+};
+use crate::capture::CaptureWriter;
+use crate::enums::LinkType;
+use crate::error::Result;
+use crate::utils::MICRO_SECOND_TSRES;
+use crate::writer::InterfaceId;
+use std::io::Write;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// A capture adapter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Adapter {
+    name: String,
+    description: Option<String>,
+    mac_addr: Option<[u8; 6]>,
+    ipv4_addrs: Vec<(Ipv4Addr, Ipv4Addr)>,
+    ipv6_addrs: Vec<(Ipv6Addr, u8)>,
+}
+
+impl Adapter {
+    /// Creates an adapter without addresses. `name` is the name
+    /// passed to Npcap, e.g. `\Device\NPF_{...}`.
+    pub fn new(name: &str, description: Option<&str>) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.map(str::to_string),
+            mac_addr: None,
+            ipv4_addrs: Vec::new(),
+            ipv6_addrs: Vec::new(),
+        }
+    }
+
+    pub fn set_mac_addr(&mut self, mac_addr: [u8; 6]) {
+        self.mac_addr = Some(mac_addr);
+    }
+
+    pub fn add_ipv4_addr(&mut self, ip: Ipv4Addr, netmask: Ipv4Addr) {
+        self.ipv4_addrs.push((ip, netmask));
+    }
+
+    pub fn add_ipv6_addr(&mut self, ip: Ipv6Addr, prefix_len: u8) {
+        self.ipv6_addrs.push((ip, prefix_len));
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn mac_addr(&self) -> Option<[u8; 6]> {
+        self.mac_addr
+    }
+
+    pub fn ipv4_addrs(&self) -> &[(Ipv4Addr, Ipv4Addr)] {
+        &self.ipv4_addrs
+    }
+
+    pub fn ipv6_addrs(&self) -> &[(Ipv6Addr, u8)] {
+        &self.ipv6_addrs
+    }
+
+    /// Returns the options of an Interface Description Block for the
+    /// adapter. IPv6 addresses with an invalid prefix length are
+    /// left out.
+    pub fn idb_options(&self) -> Vec<BlockOption> {
+        let mut options = vec![OptionIfName::new_option(&self.name)];
+        if let Some(description) = &self.description {
+            options.push(OptionIfDescription::new_option(description));
+        }
+        if let Some(mac_addr) = self.mac_addr {
+            options.push(OptionIfMacAddr::from_bytes_option(mac_addr));
+        }
+        for &(ip, netmask) in &self.ipv4_addrs {
+            options.push(OptionIfIpv4Addr::from_addrs_option(ip, netmask));
+        }
+        for &(ip, prefix_len) in &self.ipv6_addrs {
+            if let Ok(option) = OptionIfIpv6Addr::from_addr_option(ip, prefix_len) {
+                options.push(option);
+            }
+        }
+        options
+    }
+}
+
+/// Describes `adapter` as a new interface of `capture`, with the
+/// options of `Adapter::idb_options` and the microsecond timestamp
+/// resolution of Npcap.
+pub fn add_interface<W: Write>(
+    capture: &mut CaptureWriter<W>,
+    adapter: &Adapter,
+    link_type: LinkType,
+    snap_len: u32,
+) -> Result<InterfaceId> {
+    let idb_options = adapter.idb_options();
+    let mut options = Options::new();
+    options.add_options(&idb_options);
+    capture.add_interface_with_options(link_type, snap_len, MICRO_SECOND_TSRES, &options)
+}
+
+#[cfg(windows)]
+pub use self::ffi::{adapters, NpcapCapture};
+
+#[cfg(windows)]
+mod ffi {
+    use super::Adapter;
+    use crate::enums::LinkType;
+    use crate::live::{LiveCapture, LivePacket, LiveStats};
+    use std::convert::{TryFrom, TryInto};
+    use std::ffi::{CStr, CString};
+    use std::io;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::os::raw::{c_char, c_int, c_long, c_uchar, c_uint, c_ulong, c_ushort, c_void};
+    use std::ptr;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    const PCAP_ERRBUF_SIZE: usize = 256;
+    const AF_INET: c_ushort = 2;
+    const AF_INET6: c_ushort = 23;
+    const ERROR_BUFFER_OVERFLOW: c_ulong = 111;
+
+    #[repr(C)]
+    struct PcapIf {
+        next: *mut PcapIf,
+        name: *mut c_char,
+        description: *mut c_char,
+        addresses: *mut PcapAddr,
+        flags: c_uint,
+    }
+
+    #[repr(C)]
+    struct PcapAddr {
+        next: *mut PcapAddr,
+        addr: *mut SockAddr,
+        netmask: *mut SockAddr,
+        broadaddr: *mut SockAddr,
+        dstaddr: *mut SockAddr,
+    }
+
+    /// The start of `sockaddr_in` and `sockaddr_in6`.
+    #[repr(C)]
+    struct SockAddr {
+        family: c_ushort,
+        port: c_ushort,
+        data: [u8; 24],
+    }
+
+    #[repr(C)]
+    struct PcapPkthdr {
+        tv_sec: c_long,
+        tv_usec: c_long,
+        caplen: c_uint,
+        len: c_uint,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct PcapStat {
+        ps_recv: c_uint,
+        ps_drop: c_uint,
+        ps_ifdrop: c_uint,
+        ps_capt: c_uint,
+        ps_sent: c_uint,
+        ps_netdrop: c_uint,
+    }
+
+    /// The start of `IP_ADAPTER_ADDRESSES`, up to the physical
+    /// address.
+    #[repr(C)]
+    struct IpAdapterAddresses {
+        length: c_ulong,
+        if_index: c_ulong,
+        next: *mut IpAdapterAddresses,
+        adapter_name: *mut c_char,
+        first_unicast_address: *mut c_void,
+        first_anycast_address: *mut c_void,
+        first_multicast_address: *mut c_void,
+        first_dns_server_address: *mut c_void,
+        dns_suffix: *mut u16,
+        description: *mut u16,
+        friendly_name: *mut u16,
+        physical_address: [c_uchar; 8],
+        physical_address_length: c_ulong,
+    }
+
+    enum Pcap {}
+
+    #[link(name = "wpcap")]
+    extern "C" {
+        fn pcap_findalldevs(alldevs: *mut *mut PcapIf, errbuf: *mut c_char) -> c_int;
+        fn pcap_freealldevs(alldevs: *mut PcapIf);
+        fn pcap_open_live(
+            device: *const c_char,
+            snaplen: c_int,
+            promisc: c_int,
+            to_ms: c_int,
+            errbuf: *mut c_char,
+        ) -> *mut Pcap;
+        fn pcap_next_ex(
+            p: *mut Pcap,
+            header: *mut *mut PcapPkthdr,
+            data: *mut *const c_uchar,
+        ) -> c_int;
+        fn pcap_datalink(p: *mut Pcap) -> c_int;
+        fn pcap_stats(p: *mut Pcap, stats: *mut PcapStat) -> c_int;
+        fn pcap_geterr(p: *mut Pcap) -> *mut c_char;
+        fn pcap_close(p: *mut Pcap);
+    }
+
+    #[link(name = "iphlpapi")]
+    extern "system" {
+        fn GetAdaptersAddresses(
+            family: c_ulong,
+            flags: c_ulong,
+            reserved: *mut c_void,
+            addresses: *mut IpAdapterAddresses,
+            size: *mut c_ulong,
+        ) -> c_ulong;
+    }
+
+    /// Lists the adapters Npcap can capture on.
+    pub fn adapters() -> io::Result<Vec<Adapter>> {
+        let mut errbuf = [0 as c_char; PCAP_ERRBUF_SIZE];
+        let mut alldevs = ptr::null_mut();
+        // SAFETY: errbuf has the size libpcap requires.
+        if unsafe { pcap_findalldevs(&mut alldevs, errbuf.as_mut_ptr()) } != 0 {
+            return Err(pcap_error(errbuf.as_ptr()));
+        }
+        let macs = mac_addrs().unwrap_or_default();
+        let mut adapters = Vec::new();
+        let mut dev = alldevs;
+        // SAFETY: the list returned by pcap_findalldevs is valid
+        // until it is freed.
+        unsafe {
+            while !dev.is_null() {
+                let name = string((*dev).name).unwrap_or_default();
+                let description = string((*dev).description);
+                let mut adapter = Adapter::new(&name, description.as_deref());
+                if let Some((_, mac)) = macs.iter().find(|(guid, _)| name.ends_with(guid)) {
+                    adapter.set_mac_addr(*mac);
+                }
+                let mut addr = (*dev).addresses;
+                while !addr.is_null() {
+                    add_addr(&mut adapter, &*addr);
+                    addr = (*addr).next;
+                }
+                adapters.push(adapter);
+                dev = (*dev).next;
+            }
+            pcap_freealldevs(alldevs);
+        }
+        Ok(adapters)
+    }
+
+    unsafe fn add_addr(adapter: &mut Adapter, addr: &PcapAddr) {
+        if addr.addr.is_null() || addr.netmask.is_null() {
+            return;
+        }
+        let (ip, netmask) = (&*addr.addr, &*addr.netmask);
+        match ip.family {
+            AF_INET => {
+                let ip: [u8; 4] = ip.data[..4].try_into().unwrap();
+                let netmask: [u8; 4] = netmask.data[..4].try_into().unwrap();
+                adapter.add_ipv4_addr(Ipv4Addr::from(ip), Ipv4Addr::from(netmask));
+            }
+            AF_INET6 => {
+                // sin6_flowinfo comes before sin6_addr
+                let ip: [u8; 16] = ip.data[4..20].try_into().unwrap();
+                let netmask = &netmask.data[4..20];
+                let prefix_len = netmask.iter().map(|b| b.count_ones()).sum::<u32>();
+                adapter.add_ipv6_addr(Ipv6Addr::from(ip), prefix_len as u8);
+            }
+            _ => (),
+        }
+    }
+
+    /// Returns the GUIDs of the network adapters, which end the
+    /// Npcap adapter names, and their MAC addresses.
+    fn mac_addrs() -> io::Result<Vec<(String, [u8; 6])>> {
+        let mut size: c_ulong = 16 * 1024;
+        loop {
+            // u64 for the alignment of the structure
+            let mut buf = vec![0u64; size as usize / 8 + 1];
+            let first = buf.as_mut_ptr() as *mut IpAdapterAddresses;
+            // SAFETY: buf holds at least size bytes.
+            let result = unsafe { GetAdaptersAddresses(0, 0, ptr::null_mut(), first, &mut size) };
+            if result == ERROR_BUFFER_OVERFLOW {
+                continue;
+            }
+            if result != 0 {
+                return Err(io::Error::from_raw_os_error(result as i32));
+            }
+            let mut macs = Vec::new();
+            let mut adapter = first;
+            // SAFETY: the list is in buf, which is still alive.
+            unsafe {
+                while !adapter.is_null() {
+                    let a = &*adapter;
+                    if a.physical_address_length == 6 {
+                        if let Some(guid) = string(a.adapter_name) {
+                            let mac = a.physical_address[..6].try_into().unwrap();
+                            macs.push((guid, mac));
+                        }
+                    }
+                    adapter = a.next;
+                }
+            }
+            return Ok(macs);
+        }
+    }
+
+    unsafe fn string(s: *const c_char) -> Option<String> {
+        if s.is_null() {
+            return None;
+        }
+        Some(CStr::from_ptr(s).to_string_lossy().into_owned())
+    }
+
+    fn pcap_error(msg: *const c_char) -> io::Error {
+        // SAFETY: libpcap error messages are NUL-terminated.
+        let msg = unsafe { string(msg) }.unwrap_or_default();
+        io::Error::other(msg)
+    }
+
+    /// A live capture on an Npcap adapter.
+    #[derive(Debug)]
+    pub struct NpcapCapture {
+        handle: *mut Pcap,
+        link_type: LinkType,
+    }
+
+    impl NpcapCapture {
+        /// Opens `adapter` for capturing packets of up to `snap_len`
+        /// bytes.
+        pub fn open(adapter: &Adapter, snap_len: u32, promiscuous: bool) -> io::Result<Self> {
+            let name = CString::new(adapter.name())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let mut errbuf = [0 as c_char; PCAP_ERRBUF_SIZE];
+            let snap_len = c_int::try_from(snap_len).unwrap_or(c_int::MAX);
+            // SAFETY: name is NUL-terminated and errbuf has the size
+            // libpcap requires.
+            let handle = unsafe {
+                pcap_open_live(
+                    name.as_ptr(),
+                    snap_len,
+                    promiscuous.into(),
+                    100,
+                    errbuf.as_mut_ptr(),
+                )
+            };
+            if handle.is_null() {
+                return Err(pcap_error(errbuf.as_ptr()));
+            }
+            // SAFETY: handle is open.
+            let dlt = unsafe { pcap_datalink(handle) };
+            Ok(Self {
+                handle,
+                link_type: LinkType::from(dlt as u16),
+            })
+        }
+
+        /// Returns the link type of the adapter.
+        pub fn link_type(&self) -> LinkType {
+            self.link_type
+        }
+
+        fn error(&self) -> io::Error {
+            // SAFETY: handle is open.
+            pcap_error(unsafe { pcap_geterr(self.handle) })
+        }
+    }
+
+    impl LiveCapture for NpcapCapture {
+        type Error = io::Error;
+
+        /// Returns the next packet, waiting for one. Never returns
+        /// `None`.
+        fn next_packet(&mut self) -> io::Result<Option<LivePacket<'_>>> {
+            let mut header = ptr::null_mut();
+            let mut data = ptr::null();
+            loop {
+                // SAFETY: handle is open.
+                match unsafe { pcap_next_ex(self.handle, &mut header, &mut data) } {
+                    1 => break,
+                    0 => continue,
+                    _ => return Err(self.error()),
+                }
+            }
+            // SAFETY: the header and data are valid until the next
+            // call, which takes &mut self.
+            let (header, data) = unsafe {
+                let header = &*header;
+                let data = std::slice::from_raw_parts(data, header.caplen as usize);
+                (header, data)
+            };
+            let timestamp = UNIX_EPOCH
+                + Duration::from_secs(header.tv_sec as u64)
+                + Duration::from_micros(header.tv_usec as u64);
+            Ok(Some(LivePacket::new(timestamp, header.len, data)))
+        }
+
+        fn stats(&mut self) -> io::Result<LiveStats> {
+            let mut stat = PcapStat::default();
+            // SAFETY: handle is open.
+            if unsafe { pcap_stats(self.handle, &mut stat) } != 0 {
+                return Err(self.error());
+            }
+            Ok(LiveStats::new(
+                stat.ps_recv.into(),
+                stat.ps_drop.into(),
+                stat.ps_ifdrop.into(),
+            ))
+        }
+    }
+
+    impl Drop for NpcapCapture {
+        fn drop(&mut self) {
+            // SAFETY: handle is open, and not used after this.
+            unsafe { pcap_close(self.handle) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{Block, SliceReader};
+
+    fn adapter() -> Adapter {
+        let mut adapter = Adapter::new(
+            r"\Device\NPF_{6A0E8C1B-3F0B-4D3A-9F5C-0E1D2C3B4A59}",
+            Some("Intel(R) Ethernet Connection"),
+        );
+        adapter.set_mac_addr([0, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]);
+        adapter.add_ipv4_addr(
+            Ipv4Addr::new(192, 168, 1, 10),
+            Ipv4Addr::new(255, 255, 255, 0),
+        );
+        adapter.add_ipv6_addr("fe80::1".parse().unwrap(), 64);
+        adapter.add_ipv6_addr("fe80::2".parse().unwrap(), 200);
+        adapter
+    }
+
+    #[test]
+    fn idb_options() {
+        let codes: Vec<_> = adapter().idb_options().iter().map(|o| o.code()).collect();
+        assert_eq!(codes, [2, 3, 6, 4, 5]);
+        let bare = Adapter::new("lo", None);
+        assert_eq!(bare.idb_options().len(), 1);
+    }
+
+    #[test]
+    fn add_adapter_interface() {
+        let mut capture = CaptureWriter::new(vec![]).unwrap();
+        let id = add_interface(&mut capture, &adapter(), LinkType::Ethernet, 65535).unwrap();
+        let info = capture.get_writer().interface(id).unwrap();
+        assert_eq!(info.tsresol(), MICRO_SECOND_TSRES);
+        let buf = capture.into_inner().get_writer().clone();
+        match SliceReader::new(&buf).nth(1).unwrap().unwrap() {
+            Block::InterfaceDescription(idb) => {
+                let options = idb.options();
+                let mac = options.iter().find_map(|o| match o {
+                    BlockOption::IfMacAddr(o) => Some(o.mac_addr()),
+                    _ => None,
+                });
+                assert_eq!(mac, Some([0, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]));
+                assert_eq!(options.iter().count(), 6);
+            }
+            b => panic!("{:?}", b),
+        }
+    }
+}
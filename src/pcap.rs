@@ -0,0 +1,496 @@
+//! Writing classic libpcap (.pcap) files.
+//!
+//! Some tools only read the original libpcap format. A pcap file is a
+//! `FileHeader` followed by one `Record` per packet; both are
+//! `Encodable`, like the pcapng blocks, and `PcapWriter` writes them
+//! with a fixed byte order.
+//!
+//! ```
+//! use pcapng_writer::enums::LinkType;
+//! use pcapng_writer::pcap::{FileHeader, PcapWriter, Precision};
+//! use pcapng_writer::writer::Endianness;
+//!
+//! let header = FileHeader::new(Precision::Nano, LinkType::Ethernet, 65535);
+//! let mut writer = PcapWriter::new(Endianness::Little, vec![], &header).unwrap();
+//! writer.write_packet(1_500_000_000, &[0; 60]).unwrap();
+//! assert_eq!(writer.get_writer().len(), 24 + 16 + 60);
+//! ```
+//!
+//! With the `std` feature, `PcapReader` reads pcap files back.
+
+use crate::blocks::write_all_vectored;
+use crate::enums::LinkType;
+use crate::error::{Error, Result};
+use crate::io::Write;
+use crate::utils::packet_len;
+use crate::writer::{Encodable, EncodedLen, Endianness};
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use core::convert::TryInto;
+#[cfg(feature = "std")]
+use {crate::framing::invalid, alloc::vec::Vec, std::io::Read};
+
+/// Magic number of files with microsecond timestamps.
+pub const MICROSECOND_MAGIC: u32 = 0xA1B2_C3D4;
+/// Magic number of files with nanosecond timestamps.
+pub const NANOSECOND_MAGIC: u32 = 0xA1B2_3C4D;
+
+/// The resolution of the timestamps in a pcap file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+    Micro,
+    Nano,
+}
+
+impl Precision {
+    /// Returns the magic number of files with this precision.
+    pub fn magic(&self) -> u32 {
+        match self {
+            Self::Micro => MICROSECOND_MAGIC,
+            Self::Nano => NANOSECOND_MAGIC,
+        }
+    }
+
+    /// Returns the precision and the byte order of a file from the
+    /// first four bytes of its header.
+    pub fn from_magic(magic: [u8; 4]) -> Option<(Self, Endianness)> {
+        let le = LittleEndian::read_u32(&magic);
+        let be = BigEndian::read_u32(&magic);
+        [Self::Micro, Self::Nano]
+            .iter()
+            .find_map(|&precision| match precision.magic() {
+                m if m == le => Some((precision, Endianness::Little)),
+                m if m == be => Some((precision, Endianness::Big)),
+                _ => None,
+            })
+    }
+
+    /// Returns the number of fraction units in a second.
+    pub fn units_per_second(&self) -> u32 {
+        match self {
+            Self::Micro => 1_000_000,
+            Self::Nano => 1_000_000_000,
+        }
+    }
+
+    /// Splits a timestamp in nanoseconds since the epoch into the
+    /// seconds and fraction fields of a record.
+    pub fn ts_from_nanoseconds(&self, nanos: u128) -> Result<(u32, u32)> {
+        let secs = (nanos / 1_000_000_000)
+            .try_into()
+            .map_err(|_| Error::TimestampOverflow)?;
+        let frac = (nanos % 1_000_000_000) as u32 / (1_000_000_000 / self.units_per_second());
+        Ok((secs, frac))
+    }
+}
+
+/// The global header of a pcap file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHeader {
+    precision: Precision,
+    link_type: u32,
+    snap_len: u32,
+}
+
+impl FileHeader {
+    pub fn new(precision: Precision, link_type: LinkType, snap_len: u32) -> Self {
+        Self::with_link_type_value(precision, link_type.value().into(), snap_len)
+    }
+
+    /// Creates a header with a link type given by its number.
+    pub fn with_link_type_value(precision: Precision, link_type: u32, snap_len: u32) -> Self {
+        Self {
+            precision,
+            link_type,
+            snap_len,
+        }
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    /// Returns the link-layer header type field. Besides the link
+    /// type, its upper bits can hold the FCS length, see `fcs_len`.
+    pub fn link_type(&self) -> u32 {
+        self.link_type
+    }
+
+    /// Returns the number of bytes of FCS at the end of each packet,
+    /// if the link type field specifies it.
+    pub fn fcs_len(&self) -> Option<u8> {
+        if self.link_type & 0x0400_0000 != 0 {
+            Some((self.link_type >> 28) as u8 * 2)
+        } else {
+            None
+        }
+    }
+
+    pub fn snap_len(&self) -> u32 {
+        self.snap_len
+    }
+}
+
+impl EncodedLen for FileHeader {
+    fn encoded_len(&self) -> usize {
+        24
+    }
+}
+
+impl<W: Write> Encodable<W> for FileHeader {
+    fn encode<B: ByteOrder>(&self, w: &mut W) -> Result<()> {
+        let mut buf = [0u8; 24];
+        B::write_u32(&mut buf, self.precision.magic());
+        // version 2.4
+        B::write_u16(&mut buf[4..], 2);
+        B::write_u16(&mut buf[6..], 4);
+        // thiszone and sigfigs are always 0
+        B::write_u32(&mut buf[16..], self.snap_len);
+        B::write_u32(&mut buf[20..], self.link_type);
+        w.write_all(&buf)?;
+        Ok(())
+    }
+}
+
+/// A packet record of a pcap file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record<'a> {
+    ts_sec: u32,
+    ts_frac: u32,
+    orig_len: u32,
+    data: &'a [u8],
+}
+
+impl<'a> Record<'a> {
+    /// Creates a record. `ts_frac` is in microseconds or nanoseconds,
+    /// according to the precision of the file.
+    pub fn new(ts_sec: u32, ts_frac: u32, orig_len: u32, data: &'a [u8]) -> Self {
+        Self {
+            ts_sec,
+            ts_frac,
+            orig_len,
+            data,
+        }
+    }
+
+    /// Creates a record with a timestamp in nanoseconds since the
+    /// epoch.
+    pub fn from_nanoseconds(
+        precision: Precision,
+        timestamp_nanos: u128,
+        orig_len: u32,
+        data: &'a [u8],
+    ) -> Result<Self> {
+        let (ts_sec, ts_frac) = precision.ts_from_nanoseconds(timestamp_nanos)?;
+        Ok(Self::new(ts_sec, ts_frac, orig_len, data))
+    }
+
+    pub fn ts_sec(&self) -> u32 {
+        self.ts_sec
+    }
+
+    pub fn ts_frac(&self) -> u32 {
+        self.ts_frac
+    }
+
+    pub fn orig_len(&self) -> u32 {
+        self.orig_len
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl EncodedLen for Record<'_> {
+    fn encoded_len(&self) -> usize {
+        16 + self.data.len()
+    }
+}
+
+impl<W: Write> Encodable<W> for Record<'_> {
+    fn encode<B: ByteOrder>(&self, w: &mut W) -> Result<()> {
+        let incl_len = packet_len(self.data)?;
+        let mut header = [0u8; 16];
+        B::write_u32_into(
+            &[self.ts_sec, self.ts_frac, incl_len, self.orig_len],
+            &mut header,
+        );
+        write_all_vectored(w, [&header, self.data])?;
+        Ok(())
+    }
+}
+
+/// Writes a pcap file: the header when created, then records.
+#[derive(Debug)]
+pub struct PcapWriter<W: Write> {
+    writer: W,
+    endianness: Endianness,
+    precision: Precision,
+    snap_len: u32,
+    packets: u64,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Writes `header` to `writer` and returns a writer for the
+    /// records.
+    pub fn new(endianness: Endianness, writer: W, header: &FileHeader) -> Result<Self> {
+        let mut writer = Self {
+            writer,
+            endianness,
+            precision: header.precision(),
+            snap_len: header.snap_len(),
+            packets: 0,
+        };
+        writer.encode(header)?;
+        Ok(writer)
+    }
+
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    /// Returns the number of records written.
+    pub fn packets(&self) -> u64 {
+        self.packets
+    }
+
+    /// Writes a record as is.
+    pub fn write(&mut self, record: &Record) -> Result<()> {
+        self.encode(record)?;
+        self.packets += 1;
+        Ok(())
+    }
+
+    /// Writes a packet with a timestamp in nanoseconds since the
+    /// epoch. Data longer than the snap length of the file is
+    /// truncated. A snap length of 0 means no limit.
+    pub fn write_packet(&mut self, timestamp_nanos: u128, data: &[u8]) -> Result<()> {
+        let orig_len = packet_len(data)?;
+        self.write_packet_with_orig_len(timestamp_nanos, orig_len, data)
+    }
+
+    /// Like `write_packet`, but also takes the original length of the
+    /// packet on the wire.
+    pub fn write_packet_with_orig_len(
+        &mut self,
+        timestamp_nanos: u128,
+        orig_len: u32,
+        data: &[u8],
+    ) -> Result<()> {
+        let snap_len = self.snap_len as usize;
+        let data = if snap_len != 0 && data.len() > snap_len {
+            &data[..snap_len]
+        } else {
+            data
+        };
+        let record = Record::from_nanoseconds(self.precision, timestamp_nanos, orig_len, data)?;
+        self.write(&record)
+    }
+
+    pub fn get_writer(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn get_writer_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn encode<T: Encodable<W>>(&mut self, item: &T) -> Result<()> {
+        match self.endianness {
+            Endianness::Little => item.encode::<LittleEndian>(&mut self.writer),
+            Endianness::Big => item.encode::<BigEndian>(&mut self.writer),
+        }
+    }
+}
+
+/// Reads a pcap file, one record at a time.
+///
+/// Each record is read into a buffer owned by the reader, which the
+/// returned `Record` borrows.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct PcapReader<R: Read> {
+    reader: R,
+    header: FileHeader,
+    endianness: Endianness,
+    buf: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> PcapReader<R> {
+    /// Reads the file header. Fails if `reader` does not start with a
+    /// pcap magic number.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut buf = [0u8; 24];
+        reader.read_exact(&mut buf)?;
+        let (precision, endianness) = Precision::from_magic([buf[0], buf[1], buf[2], buf[3]])
+            .ok_or_else(|| invalid("not a pcap file"))?;
+        let read_u32 = |b: &[u8]| match endianness {
+            Endianness::Little => LittleEndian::read_u32(b),
+            Endianness::Big => BigEndian::read_u32(b),
+        };
+        let header =
+            FileHeader::with_link_type_value(precision, read_u32(&buf[20..]), read_u32(&buf[16..]));
+        Ok(Self {
+            reader,
+            header,
+            endianness,
+            buf: Vec::new(),
+        })
+    }
+
+    pub fn header(&self) -> &FileHeader {
+        &self.header
+    }
+
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    /// Reads the next record. Returns `None` at the end of the file.
+    pub fn next_record(&mut self) -> Result<Option<Record<'_>>> {
+        let mut header = [0u8; 16];
+        let mut read = 0;
+        while read < header.len() {
+            match self.reader.read(&mut header[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(invalid("truncated record").into()),
+                Ok(n) => read += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let mut fields = [0u32; 4];
+        match self.endianness {
+            Endianness::Little => LittleEndian::read_u32_into(&header, &mut fields),
+            Endianness::Big => BigEndian::read_u32_into(&header, &mut fields),
+        }
+        let [ts_sec, ts_frac, incl_len, orig_len] = fields;
+        // the buffer grows as the data arrives, so a bogus length does
+        // not allocate more than the data
+        self.buf.clear();
+        let read = (&mut self.reader)
+            .take(u64::from(incl_len))
+            .read_to_end(&mut self.buf)?;
+        if read < incl_len as usize {
+            return Err(invalid("truncated record").into());
+        }
+        Ok(Some(Record::new(ts_sec, ts_frac, orig_len, &self.buf)))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_header() {
+        let header = FileHeader::new(Precision::Micro, LinkType::Ethernet, 65535);
+        let mut buf = vec![];
+        header.encode::<LittleEndian>(&mut buf).unwrap();
+        assert_eq!(
+            buf,
+            [
+                0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 1, 0,
+                0, 0
+            ]
+        );
+        let mut buf = vec![];
+        let header = FileHeader::new(Precision::Nano, LinkType::Raw, 0);
+        header.encode::<BigEndian>(&mut buf).unwrap();
+        assert_eq!(&buf[..4], &[0xa1, 0xb2, 0x3c, 0x4d]);
+        assert_eq!(&buf[20..], &[0, 0, 0, 101]);
+    }
+
+    #[test]
+    fn magic() {
+        assert_eq!(
+            Precision::from_magic([0x4d, 0x3c, 0xb2, 0xa1]),
+            Some((Precision::Nano, Endianness::Little))
+        );
+        assert_eq!(
+            Precision::from_magic([0xa1, 0xb2, 0xc3, 0xd4]),
+            Some((Precision::Micro, Endianness::Big))
+        );
+        assert_eq!(Precision::from_magic([0x0a, 0x0d, 0x0d, 0x0a]), None);
+    }
+
+    #[test]
+    fn write_packets() {
+        for &precision in &[Precision::Micro, Precision::Nano] {
+            let header = FileHeader::new(precision, LinkType::Ethernet, 4);
+            let mut writer = PcapWriter::new(Endianness::Big, vec![], &header).unwrap();
+            writer.write_packet(2_000_123_456_789, &[1; 6]).unwrap();
+            writer.write(&Record::new(3, 4, 10, &[2; 2])).unwrap();
+            assert_eq!(writer.packets(), 2);
+            let buf = writer.into_inner();
+            assert_eq!(buf.len(), 24 + 16 + 4 + 16 + 2);
+            let frac = match precision {
+                Precision::Micro => 123_456,
+                Precision::Nano => 123_456_789,
+            };
+            assert_eq!(BigEndian::read_u32(&buf[24..]), 2000);
+            assert_eq!(BigEndian::read_u32(&buf[28..]), frac);
+            // incl_len is truncated to the snap length
+            assert_eq!(BigEndian::read_u32(&buf[32..]), 4);
+            assert_eq!(BigEndian::read_u32(&buf[36..]), 6);
+            assert_eq!(&buf[40..44], &[1; 4]);
+            assert_eq!(&buf[44..52], &[0, 0, 0, 3, 0, 0, 0, 4]);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn read_records() {
+        let header = FileHeader::with_link_type_value(Precision::Nano, 0x2400_0001, 100);
+        let mut writer = PcapWriter::new(Endianness::Big, vec![], &header).unwrap();
+        writer.write(&Record::new(1, 2, 3, &[4; 3])).unwrap();
+        writer.write(&Record::new(5, 6, 70, &[8; 7])).unwrap();
+        let buf = writer.into_inner();
+
+        let mut reader = PcapReader::new(&buf[..]).unwrap();
+        assert_eq!(reader.header(), &header);
+        assert_eq!(reader.header().fcs_len(), Some(4));
+        assert_eq!(reader.endianness(), Endianness::Big);
+        assert_eq!(
+            reader.next_record().unwrap(),
+            Some(Record::new(1, 2, 3, &[4; 3]))
+        );
+        assert_eq!(
+            reader.next_record().unwrap(),
+            Some(Record::new(5, 6, 70, &[8; 7]))
+        );
+        assert_eq!(reader.next_record().unwrap(), None);
+
+        let mut reader = PcapReader::new(&buf[..buf.len() - 1]).unwrap();
+        reader.next_record().unwrap();
+        assert!(reader.next_record().is_err());
+        assert!(PcapReader::new(&[0; 24][..]).is_err());
+
+        // a huge length in a short file
+        let mut huge = buf[..24 + 16].to_vec();
+        huge[24 + 8..24 + 12].copy_from_slice(&u32::MAX.to_be_bytes());
+        let mut reader = PcapReader::new(&huge[..]).unwrap();
+        assert!(reader.next_record().is_err());
+    }
+
+    #[test]
+    fn timestamp_overflow() {
+        assert!(matches!(
+            Precision::Micro.ts_from_nanoseconds((1u128 << 32) * 1_000_000_000),
+            Err(Error::TimestampOverflow)
+        ));
+    }
+}
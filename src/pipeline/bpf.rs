@@ -0,0 +1,555 @@
+use super::{BlockFilter, EncodedBlock, Verdict};
+use crate::blocks::options::{BlockOption, FilterType, OptionIfFilter};
+use crate::enums::BlockType;
+use crate::error::{Error, Result};
+use crate::writer::Endianness;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use std::io;
+
+/// The maximum number of instructions of a program, as in the kernel.
+const MAX_INSNS: usize = 4096;
+/// The number of words of scratch memory.
+const MEM_WORDS: usize = 16;
+
+// instruction classes
+const LD: u16 = 0x00;
+const LDX: u16 = 0x01;
+const ST: u16 = 0x02;
+const STX: u16 = 0x03;
+const ALU: u16 = 0x04;
+const JMP: u16 = 0x05;
+const RET: u16 = 0x06;
+const MISC: u16 = 0x07;
+
+// load sizes
+const W: u16 = 0x00;
+const H: u16 = 0x08;
+const B: u16 = 0x10;
+
+// load modes
+const IMM: u16 = 0x00;
+const ABS: u16 = 0x20;
+const IND: u16 = 0x40;
+const MEM: u16 = 0x60;
+const LEN: u16 = 0x80;
+const MSH: u16 = 0xa0;
+
+// ALU operations
+const ADD: u16 = 0x00;
+const SUB: u16 = 0x10;
+const MUL: u16 = 0x20;
+const DIV: u16 = 0x30;
+const OR: u16 = 0x40;
+const AND: u16 = 0x50;
+const LSH: u16 = 0x60;
+const RSH: u16 = 0x70;
+const NEG: u16 = 0x80;
+const MOD: u16 = 0x90;
+const XOR: u16 = 0xa0;
+
+// jumps
+const JA: u16 = 0x00;
+const JEQ: u16 = 0x10;
+const JGT: u16 = 0x20;
+const JGE: u16 = 0x30;
+const JSET: u16 = 0x40;
+
+// operand sources
+const K: u16 = 0x00;
+const X: u16 = 0x08;
+const A: u16 = 0x10;
+
+// miscellaneous operations
+const TAX: u16 = 0x00;
+const TXA: u16 = 0x80;
+
+/// A classic BPF instruction, i.e. a `struct bpf_insn`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BpfInsn {
+    code: u16,
+    jt: u8,
+    jf: u8,
+    k: u32,
+}
+
+impl BpfInsn {
+    pub fn new(code: u16, jt: u8, jf: u8, k: u32) -> Self {
+        Self { code, jt, jf, k }
+    }
+
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    pub fn jt(&self) -> u8 {
+        self.jt
+    }
+
+    pub fn jf(&self) -> u8 {
+        self.jf
+    }
+
+    pub fn k(&self) -> u32 {
+        self.k
+    }
+}
+
+/// A classic BPF program, checked to be safe to run: jumps stay in
+/// the program, scratch memory accesses are in bounds, and the
+/// program ends with a return.
+///
+/// Programs are usually compiled by libpcap, e.g. with `tcpdump -ddd`,
+/// whose output `parse` reads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BpfProgram {
+    insns: Vec<BpfInsn>,
+}
+
+impl BpfProgram {
+    /// Checks `insns` and creates a program from them.
+    pub fn new(insns: Vec<BpfInsn>) -> Result<Self> {
+        if insns.is_empty() || insns.len() > MAX_INSNS {
+            return Err(invalid_program("invalid number of instructions"));
+        }
+        for (pc, insn) in insns.iter().enumerate() {
+            check(insn, insns.len() - pc - 1)?;
+        }
+        if insns[insns.len() - 1].code & 0x07 != RET {
+            return Err(invalid_program("program does not end with a return"));
+        }
+        Ok(Self { insns })
+    }
+
+    /// Parses a program in the format of `tcpdump -ddd`: the number of
+    /// instructions, then the code, jt, jf and k fields of each
+    /// instruction in decimal. Lines may also be separated by commas,
+    /// as in `iptables -m bpf --bytecode`.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text
+            .split(['\n', ','])
+            .map(str::trim)
+            .filter(|line| !line.is_empty());
+        let count: usize = lines
+            .next()
+            .and_then(|line| line.parse().ok())
+            .ok_or_else(|| invalid_program("missing instruction count"))?;
+        let insns = lines
+            .map(|line| {
+                let fields: Vec<u32> = line
+                    .split_whitespace()
+                    .map(str::parse)
+                    .collect::<std::result::Result<_, _>>()
+                    .map_err(|_| invalid_program("invalid instruction"))?;
+                match fields[..] {
+                    [code, jt, jf, k] if code <= 0xFFFF && jt <= 0xFF && jf <= 0xFF => {
+                        Ok(BpfInsn::new(code as u16, jt as u8, jf as u8, k))
+                    }
+                    _ => Err(invalid_program("invalid instruction")),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if insns.len() != count {
+            return Err(invalid_program("wrong instruction count"));
+        }
+        Self::new(insns)
+    }
+
+    pub fn instructions(&self) -> &[BpfInsn] {
+        &self.insns
+    }
+
+    /// Returns the program as an array of `struct bpf_insn`, e.g. for
+    /// an `if_filter` option.
+    pub fn to_bytes(&self, endianness: Endianness) -> Vec<u8> {
+        let mut bytes = vec![0; self.insns.len() * 8];
+        for (insn, buf) in self.insns.iter().zip(bytes.chunks_exact_mut(8)) {
+            match endianness {
+                Endianness::Little => {
+                    LittleEndian::write_u16(buf, insn.code);
+                    LittleEndian::write_u32(&mut buf[4..], insn.k);
+                }
+                Endianness::Big => {
+                    BigEndian::write_u16(buf, insn.code);
+                    BigEndian::write_u32(&mut buf[4..], insn.k);
+                }
+            }
+            buf[2] = insn.jt;
+            buf[3] = insn.jf;
+        }
+        bytes
+    }
+
+    /// Runs the program on a packet whose original length is
+    /// `wire_len`, and returns the number of bytes to keep: 0 if the
+    /// packet is rejected. Loads past the end of `packet` reject it,
+    /// as in libpcap.
+    pub fn run(&self, packet: &[u8], wire_len: u32) -> u32 {
+        let mut a: u32 = 0;
+        let mut x: u32 = 0;
+        let mut mem = [0u32; MEM_WORDS];
+        let mut pc = 0;
+        loop {
+            let insn = self.insns[pc];
+            pc += 1;
+            let k = insn.k;
+            match insn.code & 0x07 {
+                LD => {
+                    a = match insn.code & 0xe0 {
+                        IMM => k,
+                        MEM => mem[k as usize],
+                        LEN => wire_len,
+                        ABS => match load(packet, insn.code, Some(k)) {
+                            Some(value) => value,
+                            None => return 0,
+                        },
+                        _ => match load(packet, insn.code, k.checked_add(x)) {
+                            Some(value) => value,
+                            None => return 0,
+                        },
+                    }
+                }
+                LDX => {
+                    x = match insn.code & 0xe0 {
+                        IMM => k,
+                        MEM => mem[k as usize],
+                        LEN => wire_len,
+                        _ => match packet.get(k as usize) {
+                            Some(byte) => u32::from(byte & 0x0f) << 2,
+                            None => return 0,
+                        },
+                    }
+                }
+                ST => mem[k as usize] = a,
+                STX => mem[k as usize] = x,
+                ALU => {
+                    let operand = if insn.code & 0x08 == X { x } else { k };
+                    a = match insn.code & 0xf0 {
+                        ADD => a.wrapping_add(operand),
+                        SUB => a.wrapping_sub(operand),
+                        MUL => a.wrapping_mul(operand),
+                        DIV | MOD if operand == 0 => return 0,
+                        DIV => a / operand,
+                        MOD => a % operand,
+                        OR => a | operand,
+                        AND => a & operand,
+                        XOR => a ^ operand,
+                        LSH => a.checked_shl(operand).unwrap_or(0),
+                        RSH => a.checked_shr(operand).unwrap_or(0),
+                        _ => a.wrapping_neg(),
+                    }
+                }
+                JMP => {
+                    let operand = if insn.code & 0x08 == X { x } else { k };
+                    let taken = match insn.code & 0xf0 {
+                        JA => {
+                            pc += k as usize;
+                            continue;
+                        }
+                        JEQ => a == operand,
+                        JGT => a > operand,
+                        JGE => a >= operand,
+                        _ => a & operand != 0,
+                    };
+                    pc += usize::from(if taken { insn.jt } else { insn.jf });
+                }
+                RET => return if insn.code & 0x18 == A { a } else { k },
+                _ => {
+                    if insn.code & 0xf8 == TAX {
+                        x = a;
+                    } else {
+                        a = x;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Checks that an instruction is valid and safe to run, with
+/// `remaining` instructions after it.
+fn check(insn: &BpfInsn, remaining: usize) -> Result<()> {
+    let code = insn.code;
+    let k = insn.k as usize;
+    let valid = match code & 0x07 {
+        LD => match code & 0xe0 {
+            IMM | LEN => code & 0x18 == W,
+            ABS | IND => matches!(code & 0x18, W | H | B),
+            MEM => code & 0x18 == W && k < MEM_WORDS,
+            _ => false,
+        },
+        LDX => match code & 0xe0 {
+            IMM | LEN => code & 0x18 == W,
+            MEM => code & 0x18 == W && k < MEM_WORDS,
+            MSH => code & 0x18 == B,
+            _ => false,
+        },
+        ST | STX => code & 0xf8 == 0 && k < MEM_WORDS,
+        ALU => match code & 0xf0 {
+            NEG => code & 0x08 == K,
+            DIV | MOD => code & 0x08 == X || k != 0,
+            ADD | SUB | MUL | OR | AND | LSH | RSH | XOR => true,
+            _ => false,
+        },
+        JMP => match code & 0xf0 {
+            JA => code & 0x08 == K && k < remaining,
+            JEQ | JGT | JGE | JSET => {
+                usize::from(insn.jt) < remaining && usize::from(insn.jf) < remaining
+            }
+            _ => false,
+        },
+        RET => matches!(code & 0x18, K | A),
+        MISC => matches!(code & 0xf8, TAX | TXA),
+        _ => false,
+    };
+    // unused bits must be clear
+    if !valid || code > 0xff {
+        return Err(invalid_program("invalid instruction"));
+    }
+    Ok(())
+}
+
+/// Loads a big-endian word, half-word or byte at `offset`.
+fn load(packet: &[u8], code: u16, offset: Option<u32>) -> Option<u32> {
+    let offset = offset? as usize;
+    match code & 0x18 {
+        W => packet
+            .get(offset..offset.checked_add(4)?)
+            .map(BigEndian::read_u32),
+        H => packet
+            .get(offset..offset.checked_add(2)?)
+            .map(|data| u32::from(BigEndian::read_u16(data))),
+        _ => packet.get(offset).map(|&byte| u32::from(byte)),
+    }
+}
+
+fn invalid_program(msg: &'static str) -> Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg).into()
+}
+
+/// A pipeline stage that only keeps the packets accepted by a classic
+/// BPF program.
+///
+/// The program runs on the data of each Enhanced and Simple Packet
+/// Block, so it must have been compiled for the link type of the
+/// interfaces. Each Interface Description Block gets an `if_filter`
+/// option recording the filter, replacing any it had: the expression
+/// the program was compiled from if it was given, or else the program
+/// itself.
+///
+/// ```
+/// use pcapng_writer::blocks::options::Options;
+/// use pcapng_writer::enums::LinkType;
+/// use pcapng_writer::pipeline::{BpfFilter, BpfProgram};
+/// use pcapng_writer::writer::{Endianness, PcapNgWriter};
+///
+/// // tcpdump -ddd ip
+/// let program = BpfProgram::parse("4\n40 0 0 12\n21 0 1 2048\n6 0 0 262144\n6 0 0 0\n").unwrap();
+/// let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+/// writer.add_filter(BpfFilter::with_expression(program, "ip"));
+/// let eth = writer.add_interface(LinkType::Ethernet, 0, &Options::new()).unwrap();
+/// let mut frame = [0; 60];
+/// writer.write_packet(eth, 0, &frame).unwrap();
+/// frame[12..14].copy_from_slice(&[0x08, 0x00]);
+/// writer.write_packet(eth, 0, &frame).unwrap();
+/// assert_eq!(writer.stats().total_packets(), 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BpfFilter {
+    program: BpfProgram,
+    expression: Option<String>,
+    accepted: u64,
+    rejected: u64,
+}
+
+impl BpfFilter {
+    pub fn new(program: BpfProgram) -> Self {
+        Self {
+            program,
+            expression: None,
+            accepted: 0,
+            rejected: 0,
+        }
+    }
+
+    /// Creates a stage that records `expression`, the filter the
+    /// program was compiled from, in `if_filter`.
+    pub fn with_expression(program: BpfProgram, expression: &str) -> Self {
+        Self {
+            expression: Some(expression.to_string()),
+            ..Self::new(program)
+        }
+    }
+
+    pub fn program(&self) -> &BpfProgram {
+        &self.program
+    }
+
+    pub fn expression(&self) -> Option<&str> {
+        self.expression.as_deref()
+    }
+
+    /// Returns the number of packets the program accepted.
+    pub fn packets_accepted(&self) -> u64 {
+        self.accepted
+    }
+
+    /// Returns the number of packets the program rejected.
+    pub fn packets_rejected(&self) -> u64 {
+        self.rejected
+    }
+}
+
+impl BlockFilter for BpfFilter {
+    fn filter(&mut self, block: &mut EncodedBlock) -> Verdict {
+        if block.is(BlockType::InterfaceDescription) {
+            let filter = match &self.expression {
+                Some(expression) => OptionIfFilter::new_option(expression),
+                None => BlockOption::IfFilter(OptionIfFilter::with_data(
+                    FilterType::Bpf,
+                    &self.program.to_bytes(block.endianness()),
+                )),
+            };
+            block.remove_options(filter.code());
+            // a filter too long for an option is not recorded
+            let _ = block.add_option(&filter);
+            return Verdict::Keep;
+        }
+        let data = match block.packet_data() {
+            Some(data) => data,
+            None => return Verdict::Keep,
+        };
+        let wire_len = block.original_len().unwrap_or(data.len() as u32);
+        if self.program.run(data, wire_len) != 0 {
+            self.accepted += 1;
+            Verdict::Keep
+        } else {
+            self.rejected += 1;
+            Verdict::Drop
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::Options;
+    use crate::enums::LinkType;
+    use crate::reader::{Block, SliceReader};
+    use crate::writer::PcapNgWriter;
+
+    // tcpdump -ddd 'tcp dst port 80' on Ethernet
+    const TCP_DST_80: &str = "\
+        16\n40 0 0 12\n21 0 4 34525\n48 0 0 20\n21 0 11 6\n40 0 0 56\n\
+        21 8 9 80\n21 0 8 2048\n48 0 0 23\n21 0 6 6\n40 0 0 20\n\
+        69 4 0 8191\n177 0 0 14\n72 0 0 16\n21 0 1 80\n6 0 0 262144\n\
+        6 0 0 0\n";
+
+    fn ipv4_tcp(dst_port: u16) -> Vec<u8> {
+        let mut frame = vec![0; 54];
+        frame[12..14].copy_from_slice(&[0x08, 0x00]);
+        // IPv4, 20-byte header, TCP
+        frame[14] = 0x45;
+        frame[23] = 6;
+        frame[36..38].copy_from_slice(&dst_port.to_be_bytes());
+        frame
+    }
+
+    fn ipv6_tcp(dst_port: u16) -> Vec<u8> {
+        let mut frame = vec![0; 74];
+        frame[12..14].copy_from_slice(&[0x86, 0xdd]);
+        frame[20] = 6;
+        frame[56..58].copy_from_slice(&dst_port.to_be_bytes());
+        frame
+    }
+
+    #[test]
+    fn run() {
+        let program = BpfProgram::parse(TCP_DST_80).unwrap();
+        assert_eq!(program.instructions().len(), 16);
+        assert_eq!(program.run(&ipv4_tcp(80), 54), 262144);
+        assert_eq!(program.run(&ipv4_tcp(81), 54), 0);
+        assert_eq!(program.run(&ipv6_tcp(80), 74), 262144);
+        assert_eq!(program.run(&ipv6_tcp(443), 74), 0);
+        // truncated packets are rejected
+        assert_eq!(program.run(&ipv4_tcp(80)[..30], 54), 0);
+
+        // ld #len; ldx #3; div x; ret a
+        let program = BpfProgram::parse("4,128 0 0 0,1 0 0 3,60 0 0 0,22 0 0 0").unwrap();
+        assert_eq!(program.run(&[], 100), 33);
+        // division by zero rejects the packet
+        let program = BpfProgram::parse("3,1 0 0 0,60 0 0 0,22 0 0 0").unwrap();
+        assert_eq!(program.run(&[], 100), 0);
+    }
+
+    #[test]
+    fn invalid() {
+        for text in [
+            "",
+            "1\n6 0 0 0\n6 0 0 0",
+            "1\n6 0 0",
+            "1\n40 0 0 12",
+            "2\n21 1 0 0\n6 0 0 0",
+            "2\n5 0 0 1\n6 0 0 0",
+            "2\n96 0 0 16\n6 0 0 0",
+            "2\n52 0 0 0\n6 0 0 0",
+            "1\n65535 0 0 0",
+        ] {
+            assert!(BpfProgram::parse(text).is_err(), "{:?}", text);
+        }
+    }
+
+    fn filtered(filter: BpfFilter) -> (Vec<(FilterType, Vec<u8>)>, usize) {
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Big, vec![]);
+        writer.add_filter(filter);
+        let mut options = Options::new();
+        let old = OptionIfFilter::new_option("old");
+        options.add_option(&old);
+        let eth = writer
+            .add_interface(LinkType::Ethernet, 0, &options)
+            .unwrap();
+        for port in [80, 22, 80] {
+            writer.write_packet(eth, 0, &ipv4_tcp(port)).unwrap();
+        }
+        writer.write_packet(eth, 0, &ipv6_tcp(80)).unwrap();
+        let bpf = writer.filter::<BpfFilter>().unwrap();
+        assert_eq!((bpf.packets_accepted(), bpf.packets_rejected()), (3, 1));
+
+        let data = writer.get_writer().clone();
+        let mut filters = vec![];
+        let mut packets = 0;
+        for block in SliceReader::new(&data) {
+            match block.unwrap() {
+                Block::InterfaceDescription(idb) => {
+                    filters.extend(idb.options().iter().filter_map(|o| match o {
+                        BlockOption::IfFilter(f) => Some((f.filter_type(), f.data().to_vec())),
+                        _ => None,
+                    }))
+                }
+                Block::EnhancedPacket(_) => packets += 1,
+                _ => {}
+            }
+        }
+        (filters, packets)
+    }
+
+    #[test]
+    fn stage() {
+        let program = BpfProgram::parse(TCP_DST_80).unwrap();
+        let (filters, packets) = filtered(BpfFilter::with_expression(
+            program.clone(),
+            "tcp dst port 80",
+        ));
+        assert_eq!(packets, 3);
+        assert_eq!(
+            filters,
+            [(FilterType::Libpcap, b"tcp dst port 80".to_vec())]
+        );
+
+        let (filters, packets) = filtered(BpfFilter::new(program.clone()));
+        assert_eq!(packets, 3);
+        assert_eq!(filters.len(), 1);
+        assert_eq!(filters[0].0, FilterType::Bpf);
+        assert_eq!(filters[0].1, program.to_bytes(Endianness::Big));
+        assert_eq!(filters[0].1[..8], [0, 40, 0, 0, 0, 0, 0, 12]);
+    }
+}
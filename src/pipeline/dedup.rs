@@ -0,0 +1,231 @@
+use super::{BlockFilter, Clock, EncodedBlock, Verdict};
+use crate::enums::BlockType;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hasher;
+use std::time::Duration;
+
+/// Which earlier packets a `Deduplicator` compares each packet with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DedupWindow {
+    /// The given number of packets before it.
+    Packets(usize),
+    /// The packets captured up to the given duration before it.
+    Time(Duration),
+}
+
+/// A pipeline stage that drops duplicate packets, e.g. those of a
+/// SPAN port that mirrors both directions of a link, so each packet
+/// is captured twice.
+///
+/// A packet is a duplicate if an earlier packet in the window has the
+/// same data, on any interface. Packets are compared by a 64-bit hash
+/// of their data, as `editcap -d` compares MD5 hashes. Time windows
+/// use the timestamps of the packets, converted with the `if_tsresol`
+/// of their interface; Simple Packet Blocks, which have no timestamp,
+/// get the timestamp of the packet before them.
+///
+/// The number of duplicates dropped is returned by `duplicates`, and
+/// counted with the other filtered blocks in
+/// `WriterStats::filtered_blocks`.
+///
+/// ```
+/// use pcapng_writer::blocks::options::Options;
+/// use pcapng_writer::enums::LinkType;
+/// use pcapng_writer::pipeline::{DedupWindow, Deduplicator};
+/// use pcapng_writer::writer::{Endianness, PcapNgWriter};
+///
+/// let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+/// writer.add_filter(Deduplicator::new(DedupWindow::Packets(4)));
+/// let span = writer.add_interface(LinkType::Ethernet, 0, &Options::new()).unwrap();
+/// for packet in [[1; 60], [1; 60], [2; 60], [2; 60]].iter() {
+///     writer.write_packet(span, 0, packet).unwrap();
+/// }
+/// assert_eq!(writer.stats().total_packets(), 2);
+/// assert_eq!(writer.filter::<Deduplicator>().unwrap().duplicates(), 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Deduplicator {
+    window: DedupWindow,
+    /// The hashes and timestamps of the packets in the window.
+    recent: VecDeque<(u64, u128)>,
+    /// The number of packets in the window with each hash.
+    counts: HashMap<u64, usize>,
+    clock: Clock,
+    last_time: u128,
+    seen: u64,
+    duplicates: u64,
+}
+
+impl Deduplicator {
+    pub fn new(window: DedupWindow) -> Self {
+        Self {
+            window,
+            recent: VecDeque::new(),
+            counts: HashMap::new(),
+            clock: Clock::default(),
+            last_time: 0,
+            seen: 0,
+            duplicates: 0,
+        }
+    }
+
+    pub fn window(&self) -> DedupWindow {
+        self.window
+    }
+
+    /// Returns the number of packets the stage has seen.
+    pub fn packets_seen(&self) -> u64 {
+        self.seen
+    }
+
+    /// Returns the number of duplicate packets dropped.
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+
+    /// Returns the timestamp of a packet in nanoseconds.
+    fn time(&mut self, block: &EncodedBlock) -> u128 {
+        if let Some(nanos) = self.clock.nanos(block) {
+            self.last_time = nanos;
+        }
+        self.last_time
+    }
+
+    /// Removes the packets that are out of the window of a packet at
+    /// `time`.
+    fn evict(&mut self, time: u128) {
+        while let Some(&(hash, first)) = self.recent.front() {
+            // the window is made before the packet is added to it
+            let expired = match self.window {
+                DedupWindow::Packets(n) => self.recent.len() > n,
+                DedupWindow::Time(duration) => first + duration.as_nanos() < time,
+            };
+            if !expired {
+                break;
+            }
+            self.recent.pop_front();
+            if let Some(count) = self.counts.get_mut(&hash) {
+                *count -= 1;
+                if *count == 0 {
+                    self.counts.remove(&hash);
+                }
+            }
+        }
+    }
+}
+
+impl BlockFilter for Deduplicator {
+    fn filter(&mut self, block: &mut EncodedBlock) -> Verdict {
+        if block.is(BlockType::SectionHeader) || block.is(BlockType::InterfaceDescription) {
+            self.clock.observe(block);
+            return Verdict::Keep;
+        }
+        let mut hasher = DefaultHasher::new();
+        match block.packet_data() {
+            Some(data) => hasher.write(data),
+            None => return Verdict::Keep,
+        }
+        let hash = hasher.finish();
+        let time = self.time(block);
+        self.seen += 1;
+        self.evict(time);
+        let duplicate = self.counts.contains_key(&hash);
+        self.recent.push_back((hash, time));
+        *self.counts.entry(hash).or_default() += 1;
+        if duplicate {
+            self.duplicates += 1;
+            Verdict::Drop
+        } else {
+            Verdict::Keep
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::{OptionIfTsResol, Options};
+    use crate::blocks::SimplePacketBlock;
+    use crate::enums::LinkType;
+    use crate::reader::{Block, SliceReader};
+    use crate::utils::NANO_SECOND_TSRES;
+    use crate::writer::{Endianness, PcapNgWriter};
+
+    /// Writes `(interface, nanoseconds, first byte)` packets through a
+    /// `Deduplicator`, and returns the first bytes of those written.
+    fn dedup(window: DedupWindow, packets: &[(usize, u128, u8)]) -> Vec<u8> {
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        writer.add_filter(Deduplicator::new(window));
+        let tsresol = OptionIfTsResol::new_option(NANO_SECOND_TSRES);
+        let mut options = Options::new();
+        options.add_option(&tsresol);
+        let interfaces = [
+            writer
+                .add_interface(LinkType::Ethernet, 0, &options)
+                .unwrap(),
+            writer
+                .add_interface(LinkType::Ethernet, 0, &Options::new())
+                .unwrap(),
+        ];
+        for &(interface, nanos, byte) in packets {
+            writer
+                .write_packet(interfaces[interface], nanos, &[byte; 20])
+                .unwrap();
+        }
+        let dedup = writer.filter::<Deduplicator>().unwrap();
+        assert_eq!(dedup.packets_seen(), packets.len() as u64);
+        assert_eq!(dedup.duplicates(), writer.stats().filtered_blocks());
+
+        let data = writer.get_writer().clone();
+        SliceReader::new(&data)
+            .filter_map(|block| match block.unwrap() {
+                Block::EnhancedPacket(epb) => Some(epb.packet_data()[0]),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn packet_window() {
+        let packets: Vec<_> = [1, 1, 2, 1, 3, 4, 1, 1]
+            .iter()
+            .map(|&byte| (0, 0, byte))
+            .collect();
+        assert_eq!(dedup(DedupWindow::Packets(2), &packets), [1, 2, 3, 4, 1]);
+        assert_eq!(dedup(DedupWindow::Packets(0), &packets).len(), 8);
+        // duplicates on different interfaces
+        assert_eq!(dedup(DedupWindow::Packets(1), &[(0, 0, 1), (1, 0, 1)]), [1]);
+    }
+
+    #[test]
+    fn time_window() {
+        let ms = 1_000_000;
+        let packets = [
+            (0, 0, 1),
+            (0, 5 * ms, 1),
+            (0, 16 * ms, 1),
+            // same time on a microsecond interface
+            (1, 16 * ms, 1),
+            (1, 30 * ms, 2),
+        ];
+        assert_eq!(
+            dedup(DedupWindow::Time(Duration::from_millis(10)), &packets),
+            [1, 1, 2]
+        );
+    }
+
+    #[test]
+    fn simple_packets() {
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        writer.add_filter(Deduplicator::new(DedupWindow::Time(Duration::from_secs(1))));
+        let eth = writer
+            .add_interface(LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        writer.write_packet(eth, 5_000_000_000, &[1; 4]).unwrap();
+        writer.write(&SimplePacketBlock::new(4, &[1; 4])).unwrap();
+        writer.write(&SimplePacketBlock::new(4, &[2; 4])).unwrap();
+        assert_eq!(writer.stats().total_packets(), 2);
+        assert_eq!(writer.stats().filtered_blocks(), 1);
+    }
+}
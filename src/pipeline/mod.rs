@@ -0,0 +1,674 @@
+//! A pipeline of stages that process blocks before they are written.
+//!
+//! Stages implement `BlockFilter` and are added to a writer with
+//! `PcapNgWriter::add_filter`. Every block the writer writes, including
+//! the Section Header Blocks and Interface Description Blocks it writes
+//! itself, goes through the stages in the order they were added. Each
+//! stage sees the encoded block as an `EncodedBlock`, and can inspect
+//! it, modify its options, or drop it:
+//!
+//! ```
+//! use pcapng_writer::blocks::options::{OptionComment, Options};
+//! use pcapng_writer::enums::{BlockType, LinkType};
+//! use pcapng_writer::pipeline::{EncodedBlock, Verdict};
+//! use pcapng_writer::writer::{Endianness, PcapNgWriter};
+//!
+//! let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+//! // only keep packets of at least 60 bytes
+//! writer.add_filter(|block: &mut EncodedBlock| match block.packet_data() {
+//!     Some(data) if data.len() < 60 => Verdict::Drop,
+//!     _ => Verdict::Keep,
+//! });
+//! // and say so in the section header
+//! writer.add_filter(|block: &mut EncodedBlock| {
+//!     if block.is(BlockType::SectionHeader) {
+//!         let comment = OptionComment::new_option("packets shorter than 60 bytes dropped");
+//!         block.add_option(&comment).unwrap();
+//!     }
+//!     Verdict::Keep
+//! });
+//! let eth = writer.add_interface(LinkType::Ethernet, 0, &Options::new()).unwrap();
+//! writer.write_packet(eth, 0, &[0; 20]).unwrap();
+//! writer.write_packet(eth, 0, &[0; 60]).unwrap();
+//! assert_eq!(writer.stats().total_packets(), 1);
+//! assert_eq!(writer.stats().filtered_blocks(), 1);
+//! ```
+//!
+//! Dropping Section Header Blocks or Interface Description Blocks
+//! makes the interface IDs of the output differ from those returned
+//! by the writer, so stages normally only drop packets.
+//!
+//! A stage can also hold blocks and pass them on later, as
+//! `ReorderBuffer` does. Flushing or closing the writer passes on all
+//! the blocks held by the stages.
+
+use crate::blocks::options::BlockOption;
+use crate::enums::BlockType;
+use crate::error::{Error, Result};
+use crate::framing::BlockFramer;
+use crate::utils::{pad_to_32, Timestamp, TimestampResolution, DEFAULT_TSRES};
+use crate::writer::{Encodable, Endianness};
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use std::any::Any;
+use std::fmt;
+use std::io;
+
+#[cfg(feature = "bpf-filter")]
+mod bpf;
+mod dedup;
+mod reorder;
+mod sample;
+
+#[cfg(feature = "bpf-filter")]
+pub use self::bpf::{BpfFilter, BpfInsn, BpfProgram};
+pub use self::dedup::{DedupWindow, Deduplicator};
+pub use self::reorder::ReorderBuffer;
+pub use self::sample::{Sampler, Sampling};
+
+/// What happens to a block after a `BlockFilter` has seen it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Passes the block to the next stage, or writes it.
+    Keep,
+    /// Drops the block; later stages don't see it.
+    Drop,
+    /// Holds the block; the stage takes it and passes it on later from
+    /// `BlockFilter::release`.
+    Hold,
+}
+
+/// A stage of a writer's block pipeline.
+///
+/// Implemented for closures taking an `&mut EncodedBlock`.
+pub trait BlockFilter {
+    /// Processes a block before it is written.
+    fn filter(&mut self, block: &mut EncodedBlock) -> Verdict;
+
+    /// Returns the next held block that is ready to be passed on, or
+    /// the next held block if `flush` is set. Called after the stage
+    /// holds a block, and when the writer is flushed.
+    fn release(&mut self, flush: bool) -> Option<EncodedBlock> {
+        let _ = flush;
+        None
+    }
+}
+
+impl<F: FnMut(&mut EncodedBlock) -> Verdict> BlockFilter for F {
+    fn filter(&mut self, block: &mut EncodedBlock) -> Verdict {
+        self(block)
+    }
+}
+
+/// A complete encoded block, as seen by a `BlockFilter`.
+///
+/// The accessors decode the fields of the common block types, and
+/// return `None` for block types that don't have the field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodedBlock {
+    bytes: Vec<u8>,
+    endianness: Endianness,
+}
+
+impl EncodedBlock {
+    pub(crate) fn new(bytes: Vec<u8>, endianness: Endianness) -> Self {
+        Self { bytes, endianness }
+    }
+
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    pub fn block_type(&self) -> u32 {
+        self.read_u32(0)
+    }
+
+    pub fn is(&self, block_type: BlockType) -> bool {
+        self.block_type() == block_type.value()
+    }
+
+    /// Returns the whole block, from the Block Type to the trailing
+    /// Block Total Length.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Moves the block out, for stages that hold it.
+    fn take(&mut self) -> EncodedBlock {
+        EncodedBlock::new(std::mem::take(&mut self.bytes), self.endianness)
+    }
+
+    /// Returns the interface ID of a packet or statistics block.
+    /// Simple Packet Blocks belong to the first interface.
+    pub fn interface_id(&self) -> Option<u32> {
+        if self.is(BlockType::EnhancedPacket) || self.is(BlockType::InterfaceStatistics) {
+            Some(self.read_u32(8))
+        } else if self.is(BlockType::SimplePacket) {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the timestamp of an Enhanced Packet Block or an
+    /// Interface Statistics Block.
+    pub fn timestamp(&self) -> Option<Timestamp> {
+        if self.is(BlockType::EnhancedPacket) || self.is(BlockType::InterfaceStatistics) {
+            Some(Timestamp::from_parts(self.read_u32(12), self.read_u32(16)))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the original length of the packet of an Enhanced or
+    /// Simple Packet Block.
+    pub fn original_len(&self) -> Option<u32> {
+        if self.is(BlockType::EnhancedPacket) {
+            Some(self.read_u32(24))
+        } else if self.is(BlockType::SimplePacket) {
+            Some(self.read_u32(8))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the captured packet data of an Enhanced or Simple
+    /// Packet Block.
+    pub fn packet_data(&self) -> Option<&[u8]> {
+        let (start, len): (usize, usize) = if self.is(BlockType::EnhancedPacket) {
+            (28, self.read_u32(20) as usize)
+        } else if self.is(BlockType::SimplePacket) {
+            // the captured length is implied by the block length
+            let room = self.bytes.len().checked_sub(16)?;
+            (12, room.min(self.read_u32(8) as usize))
+        } else {
+            return None;
+        };
+        self.bytes.get(start..start.checked_add(len)?)
+    }
+
+    /// Returns the code and value of each option of the block, up to
+    /// the end-of-options option.
+    pub fn options(&self) -> Vec<(u16, &[u8])> {
+        self.option_ranges()
+            .into_iter()
+            .filter(|&(code, _, _)| code != 0)
+            .map(|(code, start, _)| {
+                let len = self.read_u16(start + 2) as usize;
+                (code, &self.bytes[start + 4..start + 4 + len])
+            })
+            .collect()
+    }
+
+    /// Returns the value of the first option with the given code.
+    pub fn option(&self, code: u16) -> Option<&[u8]> {
+        self.options()
+            .into_iter()
+            .find(|&(c, _)| c == code)
+            .map(|(_, value)| value)
+    }
+
+    /// Appends an option to the block, before the end-of-options
+    /// option if there is one. Fails for block types whose options
+    /// can't be found, e.g. Simple Packet Blocks and Custom Blocks.
+    pub fn add_option(&mut self, option: &BlockOption) -> Result<()> {
+        let ranges = self.option_ranges();
+        let at = match ranges.iter().find(|&&(code, _, _)| code == 0) {
+            Some(&(_, start, _)) => start,
+            None => match self.options_range() {
+                Some((_, end)) => end,
+                None => return Err(no_options()),
+            },
+        };
+        let mut encoded = vec![];
+        match self.endianness {
+            Endianness::Little => option.encode::<LittleEndian>(&mut encoded)?,
+            Endianness::Big => option.encode::<BigEndian>(&mut encoded)?,
+        }
+        let len = self.bytes.len() + encoded.len();
+        if len > u32::MAX as usize {
+            return Err(Error::BlockTooLong(len));
+        }
+        self.bytes.splice(at..at, encoded);
+        self.update_len();
+        Ok(())
+    }
+
+    /// Removes all the options with the given code, and returns the
+    /// number of options removed.
+    pub fn remove_options(&mut self, code: u16) -> usize {
+        let ranges: Vec<_> = self
+            .option_ranges()
+            .into_iter()
+            .filter(|&(c, _, _)| c == code && code != 0)
+            .collect();
+        for &(_, start, end) in ranges.iter().rev() {
+            self.bytes.drain(start..end);
+        }
+        if !ranges.is_empty() {
+            self.update_len();
+        }
+        ranges.len()
+    }
+
+    /// Truncates the data of an Enhanced or Simple Packet Block to
+    /// `len` bytes, keeping its original length, e.g. to only keep the
+    /// headers of packets. Simple Packet Blocks don't have a captured
+    /// length, so their data is kept up to a multiple of 4 bytes.
+    /// Returns whether data was removed.
+    pub fn truncate_packet(&mut self, len: usize) -> bool {
+        let len = if self.is(BlockType::SimplePacket) {
+            len + pad_to_32(len)
+        } else {
+            len
+        };
+        let captured = match self.packet_data() {
+            Some(data) if data.len() > len => data.len(),
+            _ => return false,
+        };
+        let start = if self.is(BlockType::EnhancedPacket) {
+            // the Captured Packet Length
+            self.write_u32(20, len as u32);
+            28
+        } else {
+            12
+        };
+        let end = start + captured + pad_to_32(captured);
+        let padding = pad_to_32(len);
+        self.bytes
+            .splice(start + len..end, std::iter::repeat_n(0, padding));
+        self.update_len();
+        true
+    }
+
+    /// Returns the offsets of the options of the block, from the
+    /// first option to the trailing Block Total Length.
+    fn options_range(&self) -> Option<(usize, usize)> {
+        let end = self.bytes.len() - 4;
+        let padded = |len: u32| len as usize + pad_to_32(len as usize);
+        let body = match self.block_type() {
+            t if t == BlockType::SectionHeader.value() => 16,
+            t if t == BlockType::InterfaceDescription.value() => 8,
+            t if t == BlockType::InterfaceStatistics.value() => 12,
+            t if t == BlockType::EnhancedPacket.value() && end >= 28 => {
+                20 + padded(self.read_u32(20))
+            }
+            t if t == BlockType::DecryptionSecrets.value() && end >= 16 => {
+                8 + padded(self.read_u32(12))
+            }
+            t if t == BlockType::NameResolution.value() => {
+                // records, up to and including the end-of-records record
+                let mut pos = 8;
+                loop {
+                    if pos + 4 > end {
+                        return None;
+                    }
+                    let record_type = self.read_u16(pos);
+                    pos += 4 + padded(self.read_u16(pos + 2) as u32);
+                    if record_type == 0 {
+                        break pos - 8;
+                    }
+                }
+            }
+            _ => return None,
+        };
+        let start = 8 + body;
+        if start > end {
+            return None;
+        }
+        Some((start, end))
+    }
+
+    /// Returns the code, start and end offsets of each option, up to
+    /// and including the end-of-options option.
+    fn option_ranges(&self) -> Vec<(u16, usize, usize)> {
+        let mut ranges = vec![];
+        let (mut pos, end) = match self.options_range() {
+            Some(range) => range,
+            None => return ranges,
+        };
+        while pos + 4 <= end {
+            let code = self.read_u16(pos);
+            let len = self.read_u16(pos + 2) as usize;
+            let next = pos + 4 + len + pad_to_32(len);
+            if next > end {
+                break;
+            }
+            ranges.push((code, pos, next));
+            if code == 0 {
+                break;
+            }
+            pos = next;
+        }
+        ranges
+    }
+
+    /// Writes the length of the block to both Block Total Length
+    /// fields.
+    fn update_len(&mut self) {
+        let len = self.bytes.len();
+        self.write_u32(4, len as u32);
+        self.write_u32(len - 4, len as u32);
+    }
+
+    fn write_u32(&mut self, at: usize, value: u32) {
+        match self.endianness {
+            Endianness::Little => LittleEndian::write_u32(&mut self.bytes[at..], value),
+            Endianness::Big => BigEndian::write_u32(&mut self.bytes[at..], value),
+        }
+    }
+
+    fn read_u16(&self, at: usize) -> u16 {
+        match self.endianness {
+            Endianness::Little => LittleEndian::read_u16(&self.bytes[at..]),
+            Endianness::Big => BigEndian::read_u16(&self.bytes[at..]),
+        }
+    }
+
+    fn read_u32(&self, at: usize) -> u32 {
+        match self.endianness {
+            Endianness::Little => LittleEndian::read_u32(&self.bytes[at..]),
+            Endianness::Big => BigEndian::read_u32(&self.bytes[at..]),
+        }
+    }
+}
+
+fn no_options() -> Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "block type has no options").into()
+}
+
+/// The timestamp resolutions of the interfaces of the current
+/// section, learned from the blocks seen by a stage.
+#[derive(Debug, Clone, Default)]
+struct Clock {
+    tsresols: Vec<TimestampResolution>,
+}
+
+impl Clock {
+    /// Updates the interfaces from a Section Header or Interface
+    /// Description Block.
+    fn observe(&mut self, block: &EncodedBlock) {
+        if block.is(BlockType::SectionHeader) {
+            self.tsresols.clear();
+        } else if block.is(BlockType::InterfaceDescription) {
+            let tsresol = match block.option(9) {
+                Some(&[value]) => TimestampResolution::from_tsresol(value),
+                _ => *DEFAULT_TSRES,
+            };
+            self.tsresols.push(tsresol);
+        }
+    }
+
+    /// Returns the timestamp of a block in nanoseconds.
+    fn nanos(&self, block: &EncodedBlock) -> Option<u128> {
+        let timestamp = block.timestamp()?;
+        let id = block.interface_id()?;
+        let tsresol = self.tsresols.get(id as usize).unwrap_or(DEFAULT_TSRES);
+        Some(timestamp.to_nanoseconds(tsresol))
+    }
+}
+
+/// A stage that can be found again by its type, to read its state.
+trait Stage: BlockFilter + Send {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<F: BlockFilter + Send + 'static> Stage for F {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// The stages of a writer, and the blocks being framed for them.
+pub(crate) struct Pipeline {
+    framer: BlockFramer,
+    filters: Vec<Box<dyn Stage>>,
+    dropped: u64,
+}
+
+impl Pipeline {
+    pub fn new(endianness: Endianness) -> Self {
+        Self {
+            framer: BlockFramer::with_endianness(endianness),
+            filters: vec![],
+            dropped: 0,
+        }
+    }
+
+    pub fn add<F: BlockFilter + Send + 'static>(&mut self, filter: F) {
+        self.filters.push(Box::new(filter));
+    }
+
+    /// Returns the first stage of type `F`.
+    pub fn get<F: 'static>(&self) -> Option<&F> {
+        self.filters
+            .iter()
+            .find_map(|filter| filter.as_any().downcast_ref())
+    }
+
+    pub fn get_mut<F: 'static>(&mut self) -> Option<&mut F> {
+        self.filters
+            .iter_mut()
+            .find_map(|filter| filter.as_any_mut().downcast_mut())
+    }
+
+    /// Returns the number of blocks dropped by the stages.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Runs the blocks completed by `data` through the stages, and
+    /// passes those that are kept to `out`.
+    pub fn process(&mut self, data: &[u8], out: &mut Output<'_>) -> io::Result<()> {
+        self.framer.push(data);
+        while let Some(frame) = self.framer.next_frame()? {
+            // the framer knows the endianness once it has a frame
+            let endianness = self.framer.endianness().unwrap_or(Endianness::Little);
+            self.run(EncodedBlock::new(frame.bytes, endianness), 0, out)?;
+        }
+        Ok(())
+    }
+
+    /// Passes all the blocks held by the stages to `out`.
+    pub fn flush(&mut self, out: &mut Output<'_>) -> io::Result<()> {
+        for stage in 0..self.filters.len() {
+            self.release(stage, true, out)?;
+        }
+        Ok(())
+    }
+
+    /// Runs a block through the stages from `stage` on.
+    fn run(
+        &mut self,
+        mut block: EncodedBlock,
+        stage: usize,
+        out: &mut Output<'_>,
+    ) -> io::Result<()> {
+        for i in stage..self.filters.len() {
+            match self.filters[i].filter(&mut block) {
+                Verdict::Keep => {}
+                Verdict::Drop => {
+                    self.dropped += 1;
+                    return Ok(());
+                }
+                Verdict::Hold => return self.release(i, false, out),
+            }
+        }
+        out(block.as_bytes())
+    }
+
+    /// Runs the blocks released by a stage through the stages after
+    /// it.
+    fn release(&mut self, stage: usize, flush: bool, out: &mut Output<'_>) -> io::Result<()> {
+        while let Some(block) = self.filters[stage].release(flush) {
+            self.run(block, stage + 1, out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a `Pipeline` passes the blocks it keeps.
+pub(crate) type Output<'a> = dyn FnMut(&[u8]) -> io::Result<()> + 'a;
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pipeline")
+            .field("filters", &self.filters.len())
+            .field("dropped", &self.dropped)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::{OptionComment, Options};
+    use crate::blocks::{RawBlock, SimplePacketBlock};
+    use crate::enums::LinkType;
+    use crate::reader::{Block, SliceReader};
+    use crate::writer::PcapNgWriter;
+
+    fn comments(block: &Block) -> Vec<String> {
+        let options = match block {
+            Block::SectionHeader(b) => b.options(),
+            Block::InterfaceDescription(b) => b.options(),
+            Block::EnhancedPacket(b) => b.options(),
+            _ => return vec![],
+        };
+        options
+            .iter()
+            .filter_map(|o| match o {
+                BlockOption::OptComment(c) => Some(c.comment().to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fields() {
+        for endianness in [Endianness::Little, Endianness::Big] {
+            let mut seen = vec![];
+            let mut writer = PcapNgWriter::new_auto_section(endianness, vec![]);
+            writer.add_filter(move |block: &mut EncodedBlock| {
+                if block.is(BlockType::EnhancedPacket) {
+                    assert_eq!(block.interface_id(), Some(0));
+                    assert_eq!(block.timestamp(), Some(Timestamp::new(5_000)));
+                    assert_eq!(block.original_len(), Some(100));
+                    assert_eq!(block.packet_data(), Some(&[7; 10][..]));
+                    assert_eq!(block.option(1), Some(&b"hi"[..]));
+                } else if block.is(BlockType::SimplePacket) {
+                    assert_eq!(block.interface_id(), Some(0));
+                    assert_eq!(block.original_len(), Some(3));
+                    assert_eq!(block.packet_data(), Some(&[8; 3][..]));
+                    assert!(block.options().is_empty());
+                }
+                seen.push(block.block_type());
+                Verdict::Keep
+            });
+            let comment = OptionComment::new_option("hi");
+            let mut options = Options::new();
+            options.add_option(&comment);
+            let eth = writer
+                .add_interface(LinkType::Ethernet, 0, &Options::new())
+                .unwrap();
+            writer
+                .write_packet_with_options(eth, 5_000_000, 100, &[7; 10], &options)
+                .unwrap();
+            writer.write(&SimplePacketBlock::new(3, &[8; 3])).unwrap();
+            assert_eq!(writer.stats().total_packets(), 2);
+        }
+    }
+
+    #[test]
+    fn modify_and_drop() {
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Big, vec![]);
+        writer.add_filter(|block: &mut EncodedBlock| {
+            if block.is(BlockType::InterfaceDescription) {
+                block.remove_options(1);
+            }
+            let comment = OptionComment::new_option("seen");
+            let _ = block.add_option(&comment);
+            Verdict::Keep
+        });
+        writer.add_filter(|block: &mut EncodedBlock| match block.packet_data() {
+            Some(data) if data[0] == 0 => Verdict::Drop,
+            _ => Verdict::Keep,
+        });
+        let scrubbed = OptionComment::new_option("private");
+        let mut options = Options::new();
+        options.add_option(&scrubbed);
+        let eth = writer
+            .add_interface(LinkType::Ethernet, 0, &options)
+            .unwrap();
+        for i in 0..4 {
+            writer.write_packet(eth, 0, &[i % 2; 10]).unwrap();
+        }
+        // a Name Resolution Block with only the end-of-records record
+        writer.write(&RawBlock::new(4, 16, 16, &[0; 4])).unwrap();
+        assert_eq!(writer.stats().filtered_blocks(), 2);
+        assert_eq!(writer.stats().total_packets(), 2);
+
+        let data = writer.get_writer().clone();
+        let blocks: Vec<_> = SliceReader::new(&data).map(|b| b.unwrap()).collect();
+        assert_eq!(blocks.len(), 5);
+        for block in &blocks[..4] {
+            assert_eq!(comments(block), ["seen"]);
+        }
+        match &blocks[4] {
+            Block::Unknown(raw) => {
+                assert!(raw.body().ends_with(&[0, 1, 0, 4, b's', b'e', b'e', b'n']))
+            }
+            _ => panic!("expected a Name Resolution Block"),
+        }
+    }
+
+    #[test]
+    fn truncate_packet() {
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        writer.add_filter(|block: &mut EncodedBlock| {
+            block.truncate_packet(6);
+            Verdict::Keep
+        });
+        let comment = OptionComment::new_option("kept");
+        let mut options = Options::new();
+        options.add_option(&comment);
+        let eth = writer
+            .add_interface(LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        writer
+            .write_packet_with_options(eth, 0, 20, &[1; 20], &options)
+            .unwrap();
+        writer.write_packet(eth, 0, &[2; 4]).unwrap();
+        writer.write(&SimplePacketBlock::new(9, &[3; 9])).unwrap();
+
+        let data = writer.get_writer().clone();
+        let blocks: Vec<_> = SliceReader::new(&data).map(|b| b.unwrap()).collect();
+        match &blocks[2] {
+            Block::EnhancedPacket(epb) => {
+                assert_eq!(epb.packet_data(), &[1; 6]);
+                assert_eq!(epb.orig_len(), 20);
+                assert_eq!(comments(&blocks[2]), ["kept"]);
+            }
+            _ => panic!("expected an Enhanced Packet Block"),
+        }
+        match &blocks[3] {
+            Block::EnhancedPacket(epb) => assert_eq!(epb.packet_data(), &[2; 4]),
+            _ => panic!("expected an Enhanced Packet Block"),
+        }
+        match &blocks[4] {
+            Block::SimplePacket(spb) => {
+                assert_eq!(spb.packet_data(), &[3; 8]);
+                assert_eq!(spb.orig_len(), 9);
+            }
+            _ => panic!("expected a Simple Packet Block"),
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use super::{BlockFilter, Clock, EncodedBlock, Verdict};
+use crate::enums::BlockType;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// A pipeline stage that sorts packets by timestamp, e.g. when the
+/// threads of a capture submit them out of order.
+///
+/// Enhanced Packet Blocks and Interface Statistics Blocks are held
+/// until a block at least `window` newer has been submitted, and then
+/// passed on in timestamp order; blocks with the same timestamp keep
+/// the order they were submitted in. A block older than one already
+/// passed on is late: it is passed on at once, and counted by
+/// `late_blocks`. Timestamps are converted with the `if_tsresol` of
+/// their interface, so packets of different interfaces are sorted
+/// together.
+///
+/// Other blocks are passed on at once, except Section Header Blocks,
+/// which are passed on after all the held blocks. Flushing or closing
+/// the writer passes on all the held blocks.
+///
+/// ```
+/// use pcapng_writer::blocks::options::Options;
+/// use pcapng_writer::enums::LinkType;
+/// use pcapng_writer::pipeline::ReorderBuffer;
+/// use pcapng_writer::writer::{Endianness, PcapNgWriter};
+/// use std::time::Duration;
+///
+/// let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+/// writer.add_filter(ReorderBuffer::new(Duration::from_millis(100)));
+/// let eth = writer.add_interface(LinkType::Ethernet, 0, &Options::new()).unwrap();
+/// for &millis in [20, 10, 30, 500].iter() {
+///     writer.write_packet(eth, millis * 1_000_000, &[0; 60]).unwrap();
+/// }
+/// // the packets within 100 ms of the last one are still held
+/// assert_eq!(writer.stats().total_packets(), 3);
+/// assert_eq!(writer.filter::<ReorderBuffer>().unwrap().held_blocks(), 1);
+/// writer.close().unwrap();
+/// assert_eq!(writer.stats().total_packets(), 4);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReorderBuffer {
+    window: Duration,
+    clock: Clock,
+    /// The held blocks by timestamp and submission order.
+    held: BTreeMap<(u128, u64), EncodedBlock>,
+    /// A Section Header Block waiting for the held blocks.
+    section: Option<EncodedBlock>,
+    submitted: u64,
+    newest: u128,
+    released: Option<u128>,
+    late: u64,
+}
+
+impl ReorderBuffer {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            clock: Clock::default(),
+            held: BTreeMap::new(),
+            section: None,
+            submitted: 0,
+            newest: 0,
+            released: None,
+            late: 0,
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Sets the window; it applies to the blocks already held too.
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+    /// Returns the number of blocks currently held.
+    pub fn held_blocks(&self) -> usize {
+        self.held.len()
+    }
+
+    /// Returns the number of blocks passed on out of order because
+    /// they were late.
+    pub fn late_blocks(&self) -> u64 {
+        self.late
+    }
+}
+
+impl BlockFilter for ReorderBuffer {
+    fn filter(&mut self, block: &mut EncodedBlock) -> Verdict {
+        if block.is(BlockType::SectionHeader) {
+            self.clock.observe(block);
+            self.section = Some(block.take());
+            return Verdict::Hold;
+        }
+        if block.is(BlockType::InterfaceDescription) {
+            self.clock.observe(block);
+            return Verdict::Keep;
+        }
+        let nanos = match self.clock.nanos(block) {
+            Some(nanos) => nanos,
+            None => return Verdict::Keep,
+        };
+        if self.released.is_some_and(|released| nanos < released) {
+            self.late += 1;
+            return Verdict::Keep;
+        }
+        self.held.insert((nanos, self.submitted), block.take());
+        self.submitted += 1;
+        self.newest = self.newest.max(nanos);
+        Verdict::Hold
+    }
+
+    fn release(&mut self, flush: bool) -> Option<EncodedBlock> {
+        // a new section starts with none of the blocks of this one
+        let flush = flush || self.section.is_some();
+        match self.held.keys().next() {
+            Some(&(nanos, order)) => {
+                if !flush && nanos + self.window.as_nanos() > self.newest {
+                    return None;
+                }
+                self.released = Some(nanos);
+                self.held.remove(&(nanos, order))
+            }
+            None => {
+                let section = self.section.take()?;
+                self.newest = 0;
+                self.released = None;
+                Some(section)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::{OptionIfTsResol, Options};
+    use crate::blocks::SimplePacketBlock;
+    use crate::enums::LinkType;
+    use crate::reader::{Block, SliceReader};
+    use crate::utils::NANO_SECOND_TSRES;
+    use crate::writer::{Endianness, PcapNgWriter};
+
+    /// Returns the first byte of the packets written, and a letter for
+    /// the other blocks.
+    fn written(writer: &PcapNgWriter<Vec<u8>>) -> Vec<u8> {
+        SliceReader::new(writer.get_writer())
+            .map(|block| match block.unwrap() {
+                Block::EnhancedPacket(epb) => epb.packet_data()[0],
+                Block::SimplePacket(spb) => spb.packet_data()[0],
+                Block::SectionHeader(_) => b'S',
+                Block::InterfaceDescription(_) => b'I',
+                _ => b'?',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reorder() {
+        let ms = 1_000_000;
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        writer.add_filter(ReorderBuffer::new(Duration::from_millis(10)));
+        let tsresol = OptionIfTsResol::new_option(NANO_SECOND_TSRES);
+        let mut options = Options::new();
+        options.add_option(&tsresol);
+        let nanos = writer
+            .add_interface(LinkType::Ethernet, 0, &options)
+            .unwrap();
+        let micros = writer
+            .add_interface(LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        writer.write_packet(nanos, 5 * ms, b"2").unwrap();
+        writer.write_packet(micros, 3 * ms, b"1").unwrap();
+        writer.write_packet(nanos, 5 * ms, b"3").unwrap();
+        writer.write(&SimplePacketBlock::new(1, b"9")).unwrap();
+        assert_eq!(written(&writer), b"SII9");
+        writer.write_packet(micros, 16 * ms, b"5").unwrap();
+        writer.write_packet(nanos, 12 * ms, b"4").unwrap();
+        assert_eq!(written(&writer), b"SII9123");
+        // late
+        writer.write_packet(nanos, 4 * ms, b"0").unwrap();
+        assert_eq!(written(&writer), b"SII91230");
+        let reorder = writer.filter::<ReorderBuffer>().unwrap();
+        assert_eq!(reorder.held_blocks(), 2);
+        assert_eq!(reorder.late_blocks(), 1);
+
+        // a new section waits for the held blocks
+        writer.start_new_section(&Options::new()).unwrap();
+        let eth = writer
+            .add_interface(LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        writer.write_packet(eth, 0, b"6").unwrap();
+        assert_eq!(written(&writer), b"SII9123045SI");
+        writer.close().unwrap();
+        assert_eq!(written(&writer), b"SII9123045SI6");
+        assert_eq!(writer.stats().total_packets(), 8);
+        assert_eq!(writer.stats().filtered_blocks(), 0);
+    }
+}
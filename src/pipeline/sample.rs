@@ -0,0 +1,208 @@
+use super::{BlockFilter, EncodedBlock, Verdict};
+use crate::blocks::options::OptionComment;
+use crate::enums::BlockType;
+
+/// How a `Sampler` picks the packets it keeps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sampling {
+    /// Keeps every Nth packet, starting with the first one.
+    EveryNth(u32),
+    /// Keeps each packet with the given probability. The random
+    /// numbers are generated from `seed`, so the same seed picks the
+    /// same packets.
+    Random { probability: f64, seed: u64 },
+}
+
+/// A pipeline stage that only keeps a sample of the packets.
+///
+/// Enhanced and Simple Packet Blocks are sampled across all
+/// interfaces; other blocks are always kept. So that the capture can
+/// be analyzed knowing it is a sample, each Section Header Block gets
+/// an `opt_comment` describing the sampling, see `description`. The
+/// stage must then be added before the section starts, e.g. to a
+/// writer created with `PcapNgWriter::new_auto_section`.
+///
+/// ```
+/// use pcapng_writer::blocks::options::Options;
+/// use pcapng_writer::enums::LinkType;
+/// use pcapng_writer::pipeline::Sampler;
+/// use pcapng_writer::writer::{Endianness, PcapNgWriter};
+///
+/// let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+/// writer.add_filter(Sampler::every_nth(10));
+/// let eth = writer.add_interface(LinkType::Ethernet, 0, &Options::new()).unwrap();
+/// for i in 0..100 {
+///     writer.write_packet(eth, i, &[0; 60]).unwrap();
+/// }
+/// let sampler = writer.filter::<Sampler>().unwrap();
+/// assert_eq!(sampler.packets_seen(), 100);
+/// assert_eq!(sampler.packets_kept(), 10);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    sampling: Sampling,
+    rng: SplitMix64,
+    seen: u64,
+    kept: u64,
+}
+
+impl Sampler {
+    pub fn new(sampling: Sampling) -> Self {
+        let seed = match sampling {
+            Sampling::Random { seed, .. } => seed,
+            Sampling::EveryNth(_) => 0,
+        };
+        Self {
+            sampling,
+            rng: SplitMix64(seed),
+            seen: 0,
+            kept: 0,
+        }
+    }
+
+    /// Keeps every `n`th packet. Every packet is kept if `n` is 0 or 1.
+    pub fn every_nth(n: u32) -> Self {
+        Self::new(Sampling::EveryNth(n))
+    }
+
+    /// Keeps each packet with probability `probability`, using random
+    /// numbers generated from `seed`.
+    pub fn random(probability: f64, seed: u64) -> Self {
+        Self::new(Sampling::Random { probability, seed })
+    }
+
+    pub fn sampling(&self) -> Sampling {
+        self.sampling
+    }
+
+    /// Returns the text of the comment added to Section Header Blocks.
+    pub fn description(&self) -> String {
+        match self.sampling {
+            Sampling::EveryNth(n) => format!("sampled: 1 in every {} packets", n.max(1)),
+            Sampling::Random { probability, seed } => format!(
+                "sampled: each packet with probability {} (random, seed {})",
+                probability, seed
+            ),
+        }
+    }
+
+    /// Returns the number of packets the stage has seen.
+    pub fn packets_seen(&self) -> u64 {
+        self.seen
+    }
+
+    /// Returns the number of packets the stage has kept.
+    pub fn packets_kept(&self) -> u64 {
+        self.kept
+    }
+
+    fn keep(&mut self) -> bool {
+        match self.sampling {
+            Sampling::EveryNth(n) => self.seen.is_multiple_of(u64::from(n.max(1))),
+            Sampling::Random { probability, .. } => self.rng.next_f64() < probability,
+        }
+    }
+}
+
+impl BlockFilter for Sampler {
+    fn filter(&mut self, block: &mut EncodedBlock) -> Verdict {
+        if block.is(BlockType::SectionHeader) {
+            let comment = OptionComment::new_option(&self.description());
+            // the description always fits in an option
+            let _ = block.add_option(&comment);
+            return Verdict::Keep;
+        }
+        if !block.is(BlockType::EnhancedPacket) && !block.is(BlockType::SimplePacket) {
+            return Verdict::Keep;
+        }
+        let keep = self.keep();
+        self.seen += 1;
+        if keep {
+            self.kept += 1;
+            Verdict::Keep
+        } else {
+            Verdict::Drop
+        }
+    }
+}
+
+/// The SplitMix64 generator: small, fast and good enough for picking
+/// packets.
+#[derive(Debug, Clone)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in [0, 1).
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::{BlockOption, Options};
+    use crate::enums::LinkType;
+    use crate::reader::{Block, SliceReader};
+    use crate::writer::{Endianness, PcapNgWriter};
+
+    /// Writes packets 0 to `count` through `sampler`, and returns the
+    /// SHB comments and the packets written.
+    fn sample(sampler: Sampler, count: u8) -> (Vec<String>, Vec<u8>) {
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        writer.add_filter(sampler);
+        let eth = writer
+            .add_interface(LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        for i in 0..count {
+            writer.write_packet(eth, 0, &[i]).unwrap();
+        }
+        let data = writer.get_writer().clone();
+        let mut comments = vec![];
+        let mut packets = vec![];
+        for block in SliceReader::new(&data) {
+            match block.unwrap() {
+                Block::SectionHeader(shb) => {
+                    comments.extend(shb.options().iter().filter_map(|o| match o {
+                        BlockOption::OptComment(c) => Some(c.comment().to_string()),
+                        _ => None,
+                    }))
+                }
+                Block::EnhancedPacket(epb) => packets.push(epb.packet_data()[0]),
+                _ => {}
+            }
+        }
+        (comments, packets)
+    }
+
+    #[test]
+    fn every_nth() {
+        let (comments, packets) = sample(Sampler::every_nth(3), 10);
+        assert_eq!(comments, ["sampled: 1 in every 3 packets"]);
+        assert_eq!(packets, [0, 3, 6, 9]);
+        assert_eq!(sample(Sampler::every_nth(0), 3).1, [0, 1, 2]);
+    }
+
+    #[test]
+    fn random() {
+        let (comments, packets) = sample(Sampler::random(0.25, 7), 200);
+        assert_eq!(
+            comments,
+            ["sampled: each packet with probability 0.25 (random, seed 7)"]
+        );
+        assert!(packets.len() > 25 && packets.len() < 75);
+        // the same seed picks the same packets
+        assert_eq!(sample(Sampler::random(0.25, 7), 200).1, packets);
+        assert_ne!(sample(Sampler::random(0.25, 8), 200).1, packets);
+        assert!(sample(Sampler::random(0.0, 7), 10).1.is_empty());
+        assert_eq!(sample(Sampler::random(1.0, 7), 10).1.len(), 10);
+    }
+}
@@ -0,0 +1,568 @@
+//! Python bindings.
+//!
+//! With the `python` feature, the crate builds a `pcapng_writer`
+//! Python extension module, e.g. with
+//! `maturin build --features python`. It exposes `PcapNgWriter`, the
+//! main block types and `CaptureWriter`:
+//!
+//! ```python
+//! import time
+//! import pcapng_writer
+//!
+//! with pcapng_writer.CaptureWriter("out.pcapng") as capture:
+//!     eth0 = capture.add_interface(1, name="eth0")
+//!     capture.write_packet(eth0, time.time_ns(), packet)
+//!
+//! writer = pcapng_writer.PcapNgWriter()
+//! eth0 = writer.write(pcapng_writer.InterfaceDescriptionBlock(1, tsresol=9))
+//! writer.write(pcapng_writer.EnhancedPacketBlock(eth0, timestamp, packet, comment="retransmission"))
+//! data = writer.getvalue()
+//! ```
+//!
+//! Writers write to a file, or to memory if no path is given.
+//! Timestamps of `write_packet` are nanoseconds since the Unix epoch,
+//! like `time.time_ns()`; those of blocks are in ticks of the
+//! interface's resolution. Errors are raised as `ValueError`, or
+//! `OSError` for I/O errors.
+
+use crate::blocks::options::{
+    BlockOption, OptionComment, OptionIfDescription, OptionIfName, OptionIfTsResol,
+    OptionIsbIfDrop, OptionIsbIfRecv, Options,
+};
+use crate::blocks::{
+    EnhancedPacketBlock as Epb, InterfaceDescriptionBlock as Idb, InterfaceStatisticsBlock as Isb,
+    SimplePacketBlock as Spb,
+};
+use crate::capture::CaptureWriter as RsCaptureWriter;
+use crate::enums::LinkType;
+use crate::error::Error;
+use crate::utils::TimestampResolution;
+use crate::writer::{Endianness, InterfaceId, PcapNgWriter as RsPcapNgWriter};
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+
+impl From<Error> for PyErr {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => PyOSError::new_err(e.to_string()),
+            e => PyValueError::new_err(e.to_string()),
+        }
+    }
+}
+
+/// The output of a writer: a file, or memory.
+#[derive(Debug)]
+enum Output {
+    File(BufWriter<File>),
+    Memory(Vec<u8>),
+}
+
+impl Output {
+    fn open(path: Option<PathBuf>) -> PyResult<Self> {
+        match path {
+            Some(path) => {
+                let file = File::create(path).map_err(Error::Io)?;
+                Ok(Self::File(BufWriter::new(file)))
+            }
+            None => Ok(Self::Memory(Vec::new())),
+        }
+    }
+
+    fn bytes<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        match self {
+            Self::Memory(data) => Ok(PyBytes::new(py, data)),
+            Self::File(_) => Err(PyValueError::new_err("the writer writes to a file")),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::File(w) => w.write(data),
+            Self::Memory(w) => w.write(data),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::File(w) => w.flush(),
+            Self::Memory(w) => w.flush(),
+        }
+    }
+}
+
+fn endianness(big_endian: bool) -> Endianness {
+    if big_endian {
+        Endianness::Big
+    } else {
+        Endianness::Little
+    }
+}
+
+fn interface(ids: &[InterfaceId], id: u32) -> PyResult<InterfaceId> {
+    ids.get(id as usize)
+        .copied()
+        .ok_or_else(|| Error::UnknownInterface(id).into())
+}
+
+fn comment_option(comment: Option<&str>) -> PyResult<Option<BlockOption>> {
+    comment
+        .map(|c| OptionComment::try_new_option(c).map_err(|e| PyValueError::new_err(e.to_string())))
+        .transpose()
+}
+
+fn idb_options(
+    name: Option<&str>,
+    description: Option<&str>,
+    tsresol: Option<u8>,
+) -> PyResult<Vec<BlockOption>> {
+    let invalid = |e: crate::blocks::options::OptionError| PyValueError::new_err(e.to_string());
+    let mut options = Vec::new();
+    if let Some(name) = name {
+        options.push(OptionIfName::try_new_option(name).map_err(invalid)?);
+    }
+    if let Some(description) = description {
+        options.push(OptionIfDescription::try_new_option(description).map_err(invalid)?);
+    }
+    if let Some(tsresol) = tsresol {
+        let tsresol = TimestampResolution::from_tsresol(tsresol);
+        options.push(OptionIfTsResol::new_option(&tsresol));
+    }
+    Ok(options)
+}
+
+fn collect(options: &[BlockOption]) -> Options<'_> {
+    let mut out = Options::new();
+    out.add_options(options);
+    out
+}
+
+fn len(data: &[u8]) -> PyResult<u32> {
+    u32::try_from(data.len()).map_err(|_| Error::BlockTooLong(data.len()).into())
+}
+
+/// An Interface Description Block.
+#[pyclass(module = "pcapng_writer")]
+#[derive(Debug)]
+pub struct InterfaceDescriptionBlock {
+    link_type: u16,
+    snap_len: u32,
+    options: Vec<BlockOption>,
+}
+
+#[pymethods]
+impl InterfaceDescriptionBlock {
+    /// `tsresol` is the value of the `if_tsresol` option, e.g. 9 for
+    /// nanoseconds.
+    #[new]
+    #[pyo3(signature = (link_type, snap_len=0, name=None, description=None, tsresol=None))]
+    fn new(
+        link_type: u16,
+        snap_len: u32,
+        name: Option<&str>,
+        description: Option<&str>,
+        tsresol: Option<u8>,
+    ) -> PyResult<Self> {
+        Ok(Self {
+            link_type,
+            snap_len,
+            options: idb_options(name, description, tsresol)?,
+        })
+    }
+}
+
+/// An Enhanced Packet Block.
+#[pyclass(module = "pcapng_writer")]
+#[derive(Debug)]
+pub struct EnhancedPacketBlock {
+    interface_id: u32,
+    timestamp: u64,
+    data: Vec<u8>,
+    orig_len: u32,
+    options: Vec<BlockOption>,
+}
+
+#[pymethods]
+impl EnhancedPacketBlock {
+    #[new]
+    #[pyo3(signature = (interface_id, timestamp, data, orig_len=None, comment=None))]
+    fn new(
+        interface_id: u32,
+        timestamp: u64,
+        data: Vec<u8>,
+        orig_len: Option<u32>,
+        comment: Option<&str>,
+    ) -> PyResult<Self> {
+        let orig_len = orig_len.map_or_else(|| len(&data), Ok)?;
+        Ok(Self {
+            interface_id,
+            timestamp,
+            data,
+            orig_len,
+            options: comment_option(comment)?.into_iter().collect(),
+        })
+    }
+}
+
+/// A Simple Packet Block.
+#[pyclass(module = "pcapng_writer")]
+#[derive(Debug)]
+pub struct SimplePacketBlock {
+    data: Vec<u8>,
+    orig_len: u32,
+}
+
+#[pymethods]
+impl SimplePacketBlock {
+    #[new]
+    #[pyo3(signature = (data, orig_len=None))]
+    fn new(data: Vec<u8>, orig_len: Option<u32>) -> PyResult<Self> {
+        let orig_len = orig_len.map_or_else(|| len(&data), Ok)?;
+        Ok(Self { data, orig_len })
+    }
+}
+
+/// An Interface Statistics Block.
+#[pyclass(module = "pcapng_writer")]
+#[derive(Debug)]
+pub struct InterfaceStatisticsBlock {
+    interface_id: u32,
+    timestamp: u64,
+    options: Vec<BlockOption>,
+}
+
+#[pymethods]
+impl InterfaceStatisticsBlock {
+    #[new]
+    #[pyo3(signature = (interface_id, timestamp, ifrecv=None, ifdrop=None, comment=None))]
+    fn new(
+        interface_id: u32,
+        timestamp: u64,
+        ifrecv: Option<u64>,
+        ifdrop: Option<u64>,
+        comment: Option<&str>,
+    ) -> PyResult<Self> {
+        let mut options: Vec<_> = comment_option(comment)?.into_iter().collect();
+        options.extend(ifrecv.map(OptionIsbIfRecv::new_option));
+        options.extend(ifdrop.map(OptionIsbIfDrop::new_option));
+        Ok(Self {
+            interface_id,
+            timestamp,
+            options,
+        })
+    }
+}
+
+/// Writes blocks to a file, or to memory.
+#[pyclass(name = "PcapNgWriter", module = "pcapng_writer", unsendable)]
+#[derive(Debug)]
+pub struct PyPcapNgWriter {
+    writer: RsPcapNgWriter<Output>,
+    interfaces: Vec<InterfaceId>,
+}
+
+#[pymethods]
+impl PyPcapNgWriter {
+    /// Creates a writer that starts a section itself.
+    #[new]
+    #[pyo3(signature = (path=None, big_endian=false))]
+    fn new(path: Option<PathBuf>, big_endian: bool) -> PyResult<Self> {
+        let output = Output::open(path)?;
+        Ok(Self {
+            writer: RsPcapNgWriter::new_auto_section(endianness(big_endian), output),
+            interfaces: Vec::new(),
+        })
+    }
+
+    /// Starts a new section. Interface IDs start at 0 again.
+    #[pyo3(signature = (comment=None))]
+    fn start_new_section(&mut self, comment: Option<&str>) -> PyResult<()> {
+        let comment = comment_option(comment)?;
+        let options: Vec<_> = comment.into_iter().collect();
+        self.writer.start_new_section(&collect(&options))?;
+        self.interfaces.clear();
+        Ok(())
+    }
+
+    /// Describes an interface, and returns its ID.
+    #[pyo3(signature = (link_type, snap_len=0, name=None, description=None, tsresol=None))]
+    fn add_interface(
+        &mut self,
+        link_type: u16,
+        snap_len: u32,
+        name: Option<&str>,
+        description: Option<&str>,
+        tsresol: Option<u8>,
+    ) -> PyResult<u32> {
+        let idb = InterfaceDescriptionBlock::new(link_type, snap_len, name, description, tsresol)?;
+        self.write_idb(&idb)
+    }
+
+    /// Writes a packet captured at `timestamp_ns` nanoseconds since
+    /// the Unix epoch.
+    #[pyo3(signature = (interface_id, timestamp_ns, data, orig_len=None, comment=None))]
+    fn write_packet(
+        &mut self,
+        interface_id: u32,
+        timestamp_ns: u128,
+        data: &[u8],
+        orig_len: Option<u32>,
+        comment: Option<&str>,
+    ) -> PyResult<()> {
+        let id = interface(&self.interfaces, interface_id)?;
+        let orig_len = orig_len.map_or_else(|| len(data), Ok)?;
+        let comment: Vec<_> = comment_option(comment)?.into_iter().collect();
+        self.writer.write_packet_with_options(
+            id,
+            timestamp_ns,
+            orig_len,
+            data,
+            &collect(&comment),
+        )?;
+        Ok(())
+    }
+
+    /// Writes a block. Returns the ID of the interface for Interface
+    /// Description Blocks, and `None` otherwise.
+    fn write(&mut self, block: &Bound<'_, PyAny>) -> PyResult<Option<u32>> {
+        if let Ok(idb) = block.cast::<InterfaceDescriptionBlock>() {
+            return self.write_idb(&idb.borrow()).map(Some);
+        }
+        if let Ok(epb) = block.cast::<EnhancedPacketBlock>() {
+            let epb = epb.borrow();
+            let options = collect(&epb.options);
+            self.writer.write(&Epb::new(
+                epb.interface_id,
+                (epb.timestamp >> 32) as u32,
+                epb.timestamp as u32,
+                len(&epb.data)?,
+                epb.orig_len,
+                &epb.data,
+                &options,
+            ))?;
+        } else if let Ok(spb) = block.cast::<SimplePacketBlock>() {
+            let spb = spb.borrow();
+            self.writer.write(&Spb::new(spb.orig_len, &spb.data))?;
+        } else if let Ok(isb) = block.cast::<InterfaceStatisticsBlock>() {
+            let isb = isb.borrow();
+            let options = collect(&isb.options);
+            self.writer.write(&Isb::new(
+                isb.interface_id,
+                (isb.timestamp >> 32) as u32,
+                isb.timestamp as u32,
+                &options,
+            ))?;
+        } else {
+            return Err(PyValueError::new_err("not a pcapng_writer block"));
+        }
+        Ok(None)
+    }
+
+    /// Returns the data written so far, for writers without a file.
+    fn getvalue<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        self.writer.get_writer().bytes(py)
+    }
+
+    /// Finishes the capture and flushes the file.
+    fn close(&mut self) -> PyResult<()> {
+        self.writer.close()?;
+        Ok(())
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) -> PyResult<bool> {
+        self.close()?;
+        Ok(false)
+    }
+}
+
+impl PyPcapNgWriter {
+    fn write_idb(&mut self, idb: &InterfaceDescriptionBlock) -> PyResult<u32> {
+        let options = collect(&idb.options);
+        let block = Idb::new(LinkType::from(idb.link_type), idb.snap_len, &options);
+        let id = self.writer.add_interface_block(&block)?;
+        self.interfaces.push(id);
+        Ok(id.value())
+    }
+}
+
+/// Records packets, see `capture::CaptureWriter`.
+#[pyclass(name = "CaptureWriter", module = "pcapng_writer", unsendable)]
+#[derive(Debug)]
+pub struct PyCaptureWriter {
+    capture: RsCaptureWriter<Output>,
+    interfaces: Vec<InterfaceId>,
+}
+
+#[pymethods]
+impl PyCaptureWriter {
+    #[new]
+    #[pyo3(signature = (path=None, big_endian=false))]
+    fn new(path: Option<PathBuf>, big_endian: bool) -> PyResult<Self> {
+        let output = Output::open(path)?;
+        let capture =
+            RsCaptureWriter::with_section_options(endianness(big_endian), output, &Options::new())?;
+        Ok(Self {
+            capture,
+            interfaces: Vec::new(),
+        })
+    }
+
+    /// Describes an interface with nanosecond timestamps, and returns
+    /// its ID.
+    #[pyo3(signature = (link_type, snap_len=0, name=None, description=None))]
+    fn add_interface(
+        &mut self,
+        link_type: u16,
+        snap_len: u32,
+        name: Option<&str>,
+        description: Option<&str>,
+    ) -> PyResult<u32> {
+        let options = idb_options(name, description, None)?;
+        let id = self.capture.add_interface_with_options(
+            LinkType::from(link_type),
+            snap_len,
+            crate::utils::NANO_SECOND_TSRES,
+            &collect(&options),
+        )?;
+        self.interfaces.push(id);
+        Ok(id.value())
+    }
+
+    /// Writes a packet captured at `timestamp_ns` nanoseconds since
+    /// the Unix epoch.
+    #[pyo3(signature = (interface_id, timestamp_ns, data, orig_len=None))]
+    fn write_packet(
+        &mut self,
+        interface_id: u32,
+        timestamp_ns: u64,
+        data: &[u8],
+        orig_len: Option<u32>,
+    ) -> PyResult<()> {
+        let id = interface(&self.interfaces, interface_id)?;
+        let orig_len = orig_len.map_or_else(|| len(data), Ok)?;
+        let timestamp = UNIX_EPOCH + Duration::from_nanos(timestamp_ns);
+        self.capture
+            .write_packet_with_options(id, timestamp, orig_len, data, &Options::new())?;
+        Ok(())
+    }
+
+    fn getvalue<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        self.capture.get_writer().get_writer().bytes(py)
+    }
+
+    fn close(&mut self) -> PyResult<()> {
+        self.capture.close()?;
+        Ok(())
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) -> PyResult<bool> {
+        self.close()?;
+        Ok(false)
+    }
+}
+
+/// The `pcapng_writer` Python module.
+#[pymodule]
+fn pcapng_writer(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyPcapNgWriter>()?;
+    m.add_class::<PyCaptureWriter>()?;
+    m.add_class::<InterfaceDescriptionBlock>()?;
+    m.add_class::<EnhancedPacketBlock>()?;
+    m.add_class::<SimplePacketBlock>()?;
+    m.add_class::<InterfaceStatisticsBlock>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{Block, SliceReader};
+    use pyo3::types::PyDict;
+
+    fn run(code: &str) -> Vec<u8> {
+        Python::initialize();
+        Python::attach(|py| {
+            let module = PyModule::new(py, "pcapng_writer").unwrap();
+            pcapng_writer(&module).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("pcapng_writer", module).unwrap();
+            let code = std::ffi::CString::new(code).unwrap();
+            py.run(&code, None, Some(&locals)).unwrap();
+            locals.get_item("data").unwrap().unwrap().extract().unwrap()
+        })
+    }
+
+    #[test]
+    fn pcapng_writer_blocks() {
+        let data = run(r#"
+w = pcapng_writer.PcapNgWriter()
+eth = w.write(pcapng_writer.InterfaceDescriptionBlock(1, name="eth0", tsresol=9))
+w.write(pcapng_writer.EnhancedPacketBlock(eth, 5, b"abc", comment="hi"))
+w.write(pcapng_writer.SimplePacketBlock(b"de"))
+w.write(pcapng_writer.InterfaceStatisticsBlock(eth, 6, ifrecv=2))
+w.write_packet(eth, 7, b"f")
+try:
+    w.write_packet(1, 0, b"")
+except ValueError:
+    pass
+data = w.getvalue()
+"#);
+        let blocks: Vec<_> = SliceReader::new(&data).map(|b| b.unwrap()).collect();
+        assert_eq!(blocks.len(), 6);
+        match &blocks[2] {
+            Block::EnhancedPacket(epb) => {
+                assert_eq!(epb.timestamp(), 5);
+                assert_eq!(epb.packet_data(), b"abc");
+                assert_eq!(epb.options().iter().count(), 1);
+            }
+            b => panic!("{:?}", b),
+        }
+        match &blocks[5] {
+            Block::EnhancedPacket(epb) => assert_eq!(epb.timestamp(), 7),
+            b => panic!("{:?}", b),
+        }
+    }
+
+    #[test]
+    fn capture_writer() {
+        let data = run(r#"
+with pcapng_writer.CaptureWriter(big_endian=True) as c:
+    eth = c.add_interface(1, name="eth0")
+    c.write_packet(eth, 1_500, b"abc", orig_len=60)
+data = c.getvalue()
+"#);
+        let blocks: Vec<_> = SliceReader::new(&data).map(|b| b.unwrap()).collect();
+        match &blocks[2] {
+            Block::EnhancedPacket(epb) => {
+                assert_eq!(epb.timestamp(), 1_500);
+                assert_eq!(epb.orig_len(), 60);
+            }
+            b => panic!("{:?}", b),
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! Rate-limited writing of packets.
+//!
+//! `RateLimitedWriter` wraps a `PcapNgWriter` and keeps the packets
+//! written to it under a maximum bandwidth and/or packet rate, e.g.
+//! to protect a shared disk from capture bursts. Packets over the
+//! limits are either dropped, or wait until they fit:
+//!
+//! ```
+//! use pcapng_writer::blocks::options::Options;
+//! use pcapng_writer::enums::LinkType;
+//! use pcapng_writer::ratelimit::{OverflowPolicy, RateLimitedWriter};
+//! use pcapng_writer::writer::{Endianness, PcapNgWriter};
+//!
+//! let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+//! let eth = writer.add_interface(LinkType::Ethernet, 0, &Options::new()).unwrap();
+//! let mut limited = RateLimitedWriter::new(writer, OverflowPolicy::Drop);
+//! limited.set_max_packets_per_sec(Some(100));
+//! let written = (0..1000)
+//!     .filter(|_| limited.write_packet(eth, 0, &[0; 60]).unwrap())
+//!     .count();
+//! assert!(written < 1000);
+//! assert_eq!(limited.dropped(eth) as usize, 1000 - written);
+//! ```
+//!
+//! Dropped packets are accounted for in the file: the next packet
+//! written on the interface has an `epb_dropcount` option with the
+//! number of packets dropped before it, and `write_statistics` writes
+//! an Interface Statistics Block with the total in `isb_osdrop`.
+
+use crate::blocks::options::{OptionEpbDropCount, OptionIsbOsDrop, Options};
+use crate::blocks::InterfaceStatisticsBlock;
+use crate::constants::BLOCK_COMMON_LEN;
+use crate::error::{Error, Result};
+use crate::utils::{pad_to_32, Timestamp};
+use crate::writer::{EncodedLen, InterfaceId, PcapNgWriter};
+use std::convert::TryFrom;
+use std::io::Write;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What `RateLimitedWriter` does with packets over the limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drops the packet and counts it.
+    Drop,
+    /// Waits until the packet fits within the limits.
+    Block,
+}
+
+/// A token bucket that allows `rate` units per second, in bursts of
+/// up to one second's worth.
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64) -> Self {
+        let rate = rate.max(1) as f64;
+        Self {
+            rate,
+            tokens: rate,
+            last: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last = now;
+    }
+
+    /// Returns how long to wait until `cost` units can be taken.
+    /// Costs larger than a burst only need a full bucket.
+    fn wait_time(&self, cost: u64) -> Duration {
+        let needed = (cost as f64).min(self.rate) - self.tokens;
+        if needed <= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64(needed / self.rate)
+        }
+    }
+
+    fn take(&mut self, cost: u64) {
+        self.tokens -= cost as f64;
+    }
+}
+
+/// Drop counters of an interface.
+#[derive(Debug, Clone, Copy, Default)]
+struct Drops {
+    /// Since the last packet written.
+    pending: u64,
+    total: u64,
+}
+
+/// A `PcapNgWriter` wrapper that limits the rate of packets written.
+#[derive(Debug)]
+pub struct RateLimitedWriter<W: Write> {
+    writer: PcapNgWriter<W>,
+    policy: OverflowPolicy,
+    bytes: Option<TokenBucket>,
+    packets: Option<TokenBucket>,
+    drops: Vec<Drops>,
+}
+
+impl<W: Write> RateLimitedWriter<W> {
+    /// Wraps `writer`, without limits until they are set.
+    pub fn new(writer: PcapNgWriter<W>, policy: OverflowPolicy) -> Self {
+        Self {
+            writer,
+            policy,
+            bytes: None,
+            packets: None,
+            drops: Vec::new(),
+        }
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: OverflowPolicy) {
+        self.policy = policy;
+    }
+
+    /// Limits the bytes of Enhanced Packet Blocks written per second,
+    /// or removes the limit with `None`.
+    pub fn set_max_bytes_per_sec(&mut self, bytes: Option<u64>) {
+        self.bytes = bytes.map(TokenBucket::new);
+    }
+
+    /// Limits the packets written per second, or removes the limit
+    /// with `None`.
+    pub fn set_max_packets_per_sec(&mut self, packets: Option<u64>) {
+        self.packets = packets.map(TokenBucket::new);
+    }
+
+    /// Returns the number of packets dropped on `interface_id` so far.
+    pub fn dropped(&self, interface_id: InterfaceId) -> u64 {
+        self.drops
+            .get(interface_id.value() as usize)
+            .map_or(0, |drops| drops.total)
+    }
+
+    pub fn get_writer(&self) -> &PcapNgWriter<W> {
+        &self.writer
+    }
+
+    pub fn get_writer_mut(&mut self) -> &mut PcapNgWriter<W> {
+        &mut self.writer
+    }
+
+    pub fn into_inner(self) -> PcapNgWriter<W> {
+        self.writer
+    }
+
+    /// Writes a packet with `PcapNgWriter::write_packet` if it fits
+    /// within the limits. Returns whether it was written.
+    pub fn write_packet(
+        &mut self,
+        interface_id: InterfaceId,
+        timestamp_nanos: u128,
+        data: &[u8],
+    ) -> Result<bool> {
+        let orig_len = u32::try_from(data.len()).unwrap_or(u32::MAX);
+        self.write_packet_with_options(
+            interface_id,
+            timestamp_nanos,
+            orig_len,
+            data,
+            &Options::new(),
+        )
+    }
+
+    /// Writes a packet with `PcapNgWriter::write_packet_with_options`
+    /// if it fits within the limits. Returns whether it was written.
+    pub fn write_packet_with_options(
+        &mut self,
+        interface_id: InterfaceId,
+        timestamp_nanos: u128,
+        orig_len: u32,
+        data: &[u8],
+        options: &Options,
+    ) -> Result<bool> {
+        let index = interface_id.value() as usize;
+        if self.drops.len() <= index {
+            self.drops.resize(index + 1, Drops::default());
+        }
+        let drop_count = OptionEpbDropCount::new_option(self.drops[index].pending);
+        let mut epb_options = Options::new();
+        if self.drops[index].pending > 0 {
+            epb_options.add_option(&drop_count);
+        }
+        for opt in options.iter() {
+            epb_options.add_option(opt);
+        }
+        // Enhanced Packet Block header and trailer
+        let len = BLOCK_COMMON_LEN + 20 + data.len() + pad_to_32(data.len());
+        if !self.acquire((len + epb_options.encoded_len()) as u64) {
+            self.drops[index].pending += 1;
+            self.drops[index].total += 1;
+            self.writer.metrics().dropped(1);
+            return Ok(false);
+        }
+        self.writer.write_packet_with_options(
+            interface_id,
+            timestamp_nanos,
+            orig_len,
+            data,
+            &epb_options,
+        )?;
+        self.drops[index].pending = 0;
+        Ok(true)
+    }
+
+    /// Writes an Interface Statistics Block for `interface_id` with
+    /// the number of packets dropped so far in `isb_osdrop`.
+    pub fn write_statistics(
+        &mut self,
+        interface_id: InterfaceId,
+        timestamp_nanos: u128,
+    ) -> Result<()> {
+        let info = self
+            .writer
+            .interface(interface_id)
+            .ok_or_else(|| Error::UnknownInterface(interface_id.value()))?;
+        let timestamp = Timestamp::from_nanoseconds(info.tsresol(), timestamp_nanos)?;
+        let os_drop = OptionIsbOsDrop::new_option(self.dropped(interface_id));
+        let mut options = Options::new();
+        options.add_option(&os_drop);
+        let isb = InterfaceStatisticsBlock::new(
+            interface_id.value(),
+            timestamp.high(),
+            timestamp.low(),
+            &options,
+        );
+        self.writer.write(&isb)
+    }
+
+    /// Takes `bytes` and one packet from the buckets, waiting for
+    /// them with `OverflowPolicy::Block`. Returns false if the packet
+    /// must be dropped.
+    fn acquire(&mut self, bytes: u64) -> bool {
+        loop {
+            let now = Instant::now();
+            let mut wait = Duration::from_secs(0);
+            if let Some(bucket) = self.bytes.as_mut() {
+                bucket.refill(now);
+                wait = wait.max(bucket.wait_time(bytes));
+            }
+            if let Some(bucket) = self.packets.as_mut() {
+                bucket.refill(now);
+                wait = wait.max(bucket.wait_time(1));
+            }
+            if wait == Duration::from_secs(0) {
+                break;
+            }
+            match self.policy {
+                OverflowPolicy::Drop => return false,
+                OverflowPolicy::Block => thread::sleep(wait),
+            }
+        }
+        if let Some(bucket) = self.bytes.as_mut() {
+            bucket.take(bytes);
+        }
+        if let Some(bucket) = self.packets.as_mut() {
+            bucket.take(1);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::BlockOption;
+    use crate::enums::LinkType;
+    use crate::reader::{Block, SliceReader};
+    use crate::writer::Endianness;
+
+    fn limited(policy: OverflowPolicy) -> (RateLimitedWriter<Vec<u8>>, InterfaceId) {
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        let eth = writer
+            .add_interface(LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        (RateLimitedWriter::new(writer, policy), eth)
+    }
+
+    #[test]
+    fn bucket() {
+        let mut bucket = TokenBucket::new(100);
+        let start = bucket.last;
+        assert_eq!(bucket.wait_time(100), Duration::from_secs(0));
+        bucket.take(100);
+        assert_eq!(bucket.wait_time(50), Duration::from_millis(500));
+        // larger than a burst
+        assert_eq!(bucket.wait_time(1000), Duration::from_secs(1));
+        bucket.refill(start + Duration::from_millis(250));
+        assert_eq!(bucket.wait_time(50), Duration::from_millis(250));
+        bucket.refill(start + Duration::from_secs(10));
+        assert_eq!(bucket.tokens, 100.0);
+    }
+
+    #[test]
+    fn drop_and_count() {
+        let (mut limited, eth) = limited(OverflowPolicy::Drop);
+        // 44-byte blocks, so two fit in the first second
+        limited.set_max_bytes_per_sec(Some(100));
+        let written: Vec<_> = (0..5)
+            .map(|i| limited.write_packet(eth, 0, &[i; 10]).unwrap())
+            .collect();
+        assert_eq!(written, [true, true, false, false, false]);
+        assert_eq!(limited.dropped(eth), 3);
+        limited.set_max_bytes_per_sec(None);
+        assert!(limited.write_packet(eth, 0, &[5; 10]).unwrap());
+        limited.write_statistics(eth, 0).unwrap();
+
+        let data = limited.into_inner().get_writer().clone();
+        let mut drop_counts = vec![];
+        let mut os_drop = None;
+        for block in SliceReader::new(&data) {
+            match block.unwrap() {
+                Block::EnhancedPacket(epb) => drop_counts.push(
+                    epb.options()
+                        .iter()
+                        .find_map(|o| match o {
+                            BlockOption::EpbDropCount(o) => Some(o.count()),
+                            _ => None,
+                        })
+                        .unwrap_or(0),
+                ),
+                Block::InterfaceStatistics(isb) => {
+                    os_drop = isb.options().iter().find_map(|o| match o {
+                        BlockOption::IsbOsDrop(o) => Some(o.count()),
+                        _ => None,
+                    })
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(drop_counts, [0, 0, 3]);
+        assert_eq!(os_drop, Some(3));
+    }
+
+    #[test]
+    fn block() {
+        let (mut limited, eth) = limited(OverflowPolicy::Block);
+        limited.set_max_packets_per_sec(Some(100));
+        let start = Instant::now();
+        for _ in 0..110 {
+            assert!(limited.write_packet(eth, 0, &[0; 10]).unwrap());
+        }
+        // a burst of 100, then 10 packets at 100 per second
+        assert!(start.elapsed() >= Duration::from_millis(90));
+        assert_eq!(limited.dropped(eth), 0);
+    }
+}
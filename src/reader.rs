@@ -0,0 +1,853 @@
+//! Reading pcapng data.
+//!
+//! The reader parses the blocks this crate writes back into its own
+//! types: packet data and unknown blocks borrow from the input, and
+//! options are parsed into `BlockOption`s. Options of types without a
+//! dedicated `BlockOption` variant, or whose value is malformed, are
+//! returned as `BlockOption::Raw`; their value is kept in the byte
+//! order of the section they were read from.
+//!
+//! Blocks can be written again as they are, since `Block` is
+//! `Encodable`, or modified with the `to_block` methods, e.g.:
+//!
+//! ```
+//! use pcapng_writer::reader::{Block, SliceReader};
+//! # use pcapng_writer::blocks::options::Options;
+//! # use pcapng_writer::writer::PcapNgWriter;
+//! # let mut writer = PcapNgWriter::new_le(vec![]);
+//! # writer.start_new_section(&Options::new()).unwrap();
+//! # writer.add_interface(pcapng_writer::enums::LinkType::Ethernet, 0, &Options::new()).unwrap();
+//! # let data = writer.get_writer().clone();
+//!
+//! for block in SliceReader::new(&data) {
+//!     if let Block::InterfaceDescription(idb) = block.unwrap() {
+//!         let options = idb.options();
+//!         let block = idb.to_block(&options);
+//!         assert_eq!(block.link_type(), 1);
+//!     }
+//! }
+//! ```
+
+use crate::blocks::options::{
+    BlockOption, FcsLen, FilterType, HashAlgorithm, OptionComment, OptionEndOfOpt,
+    OptionEpbDropCount, OptionEpbFlags, OptionEpbHash, OptionEpbQueue, OptionEpbVerdict,
+    OptionIfDescription, OptionIfFcsLen, OptionIfFilter, OptionIfIpv4Addr, OptionIfIpv6Addr,
+    OptionIfMacAddr, OptionIfName, OptionIfSpeed, OptionIfTsResol, OptionIsbEndTime,
+    OptionIsbFilterAccept, OptionIsbIfDrop, OptionIsbIfRecv, OptionIsbOsDrop, OptionIsbStartTime,
+    OptionIsbUsrDeliv, OptionShbHardware, OptionShbOs, OptionShbUserAppl, Options, RawOption,
+    VerdictType,
+};
+use crate::blocks::{
+    EnhancedPacketBlock, InterfaceDescriptionBlock, InterfaceStatisticsBlock, RawBlock,
+    SectionHeaderBlock, SimplePacketBlock,
+};
+use crate::constants::{BLOCK_COMMON_LEN, BYTE_ORDER_MAGIC, SHB_UNSPECIFIED_LENGTH};
+use crate::enums::{BlockType, SectionHeaderSectionLength};
+use crate::error::Result;
+use crate::framing::{invalid, parse_header};
+use crate::io::Write;
+use crate::utils::{pad_to_32, Timestamp, TimestampResolution, DEFAULT_TSRES};
+use crate::writer::{Encodable, Endianness};
+use alloc::vec::Vec;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use core::convert::TryInto;
+use core::net::{Ipv4Addr, Ipv6Addr};
+use core::str;
+
+/// A block read from a pcapng stream.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Block<'a> {
+    SectionHeader(SectionHeader),
+    InterfaceDescription(InterfaceDescription),
+    EnhancedPacket(EnhancedPacket<'a>),
+    SimplePacket(SimplePacketBlock<'a>),
+    InterfaceStatistics(InterfaceStatistics),
+    /// A block of a type the reader does not parse, e.g. a Name
+    /// Resolution Block.
+    Unknown(RawBlock<'a>),
+}
+
+/// A Section Header Block that has been read.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SectionHeader {
+    endianness: Endianness,
+    major_version: u16,
+    minor_version: u16,
+    section_length: u64,
+    options: Vec<BlockOption>,
+}
+
+impl SectionHeader {
+    /// Returns the byte order of the section.
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    pub fn major_version(&self) -> u16 {
+        self.major_version
+    }
+
+    pub fn minor_version(&self) -> u16 {
+        self.minor_version
+    }
+
+    /// Returns the Section Length, or `None` if it is unspecified.
+    pub fn section_length(&self) -> Option<u64> {
+        match self.section_length {
+            SHB_UNSPECIFIED_LENGTH => None,
+            len => Some(len),
+        }
+    }
+
+    pub fn options(&self) -> Options<'_> {
+        collect_options(&self.options)
+    }
+
+    pub fn to_block<'b>(&self, options: &'b Options<'b>) -> SectionHeaderBlock<'b> {
+        let section_length = match self.section_length() {
+            Some(len) => SectionHeaderSectionLength::Bytes(len),
+            None => SectionHeaderSectionLength::Unspecified,
+        };
+        SectionHeaderBlock::new(
+            BYTE_ORDER_MAGIC,
+            self.major_version,
+            self.minor_version,
+            section_length,
+            options,
+        )
+    }
+}
+
+/// An Interface Description Block that has been read.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InterfaceDescription {
+    link_type: u16,
+    snap_len: u32,
+    options: Vec<BlockOption>,
+}
+
+impl InterfaceDescription {
+    pub fn link_type(&self) -> u16 {
+        self.link_type
+    }
+
+    pub fn snap_len(&self) -> u32 {
+        self.snap_len
+    }
+
+    /// Returns the timestamp resolution set by the `if_tsresol`
+    /// option, or the default of 10^-6 if the option is absent.
+    pub fn tsresol(&self) -> TimestampResolution {
+        self.options
+            .iter()
+            .find_map(|opt| match opt {
+                BlockOption::IfTsResol(o) => Some(o.resolution()),
+                _ => None,
+            })
+            .unwrap_or(*DEFAULT_TSRES)
+    }
+
+    pub fn options(&self) -> Options<'_> {
+        collect_options(&self.options)
+    }
+
+    pub fn to_block<'b>(&self, options: &'b Options<'b>) -> InterfaceDescriptionBlock<'b> {
+        InterfaceDescriptionBlock::new(self.link_type.into(), self.snap_len, options)
+    }
+}
+
+/// An Enhanced Packet Block that has been read.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EnhancedPacket<'a> {
+    interface_id: u32,
+    ts_high: u32,
+    ts_low: u32,
+    orig_len: u32,
+    packet_data: &'a [u8],
+    options: Vec<BlockOption>,
+}
+
+impl<'a> EnhancedPacket<'a> {
+    pub fn interface_id(&self) -> u32 {
+        self.interface_id
+    }
+
+    /// Returns the timestamp, in units of the interface's timestamp
+    /// resolution.
+    pub fn timestamp(&self) -> u64 {
+        (self.ts_high as u64) << 32 | self.ts_low as u64
+    }
+
+    pub fn orig_len(&self) -> u32 {
+        self.orig_len
+    }
+
+    /// Returns the captured packet data, without padding.
+    pub fn packet_data(&self) -> &'a [u8] {
+        self.packet_data
+    }
+
+    pub fn options(&self) -> Options<'_> {
+        collect_options(&self.options)
+    }
+
+    pub fn to_block<'b>(&'b self, options: &'b Options<'b>) -> EnhancedPacketBlock<'b> {
+        EnhancedPacketBlock::new(
+            self.interface_id,
+            self.ts_high,
+            self.ts_low,
+            self.packet_data.len() as u32,
+            self.orig_len,
+            self.packet_data,
+            options,
+        )
+    }
+}
+
+/// An Interface Statistics Block that has been read.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InterfaceStatistics {
+    interface_id: u32,
+    ts_high: u32,
+    ts_low: u32,
+    options: Vec<BlockOption>,
+}
+
+impl InterfaceStatistics {
+    pub fn interface_id(&self) -> u32 {
+        self.interface_id
+    }
+
+    /// Returns the timestamp, in units of the interface's timestamp
+    /// resolution.
+    pub fn timestamp(&self) -> u64 {
+        (self.ts_high as u64) << 32 | self.ts_low as u64
+    }
+
+    pub fn options(&self) -> Options<'_> {
+        collect_options(&self.options)
+    }
+
+    pub fn to_block<'b>(&self, options: &'b Options<'b>) -> InterfaceStatisticsBlock<'b> {
+        InterfaceStatisticsBlock::new(self.interface_id, self.ts_high, self.ts_low, options)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Block<'a> {
+    /// Creates a valid block, which is read back as the same bytes
+    /// once written. Unknown blocks are Name Resolution, Decryption
+    /// Secrets or Custom Blocks, with an arbitrary body.
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        use crate::arbitrary::block_options;
+        Ok(match u.int_in_range(0..=5)? {
+            0 => Block::SectionHeader(SectionHeader {
+                endianness: u.arbitrary()?,
+                major_version: 1,
+                minor_version: 0,
+                section_length: u.arbitrary()?,
+                options: block_options(u, BlockType::SectionHeader)?,
+            }),
+            1 => Block::InterfaceDescription(InterfaceDescription {
+                link_type: u.arbitrary()?,
+                snap_len: u.arbitrary()?,
+                options: block_options(u, BlockType::InterfaceDescription)?,
+            }),
+            2 => {
+                let packet_data: &[u8] = u.arbitrary()?;
+                Block::EnhancedPacket(EnhancedPacket {
+                    interface_id: u.arbitrary()?,
+                    ts_high: u.arbitrary()?,
+                    ts_low: u.arbitrary()?,
+                    orig_len: u.int_in_range(packet_data.len() as u32..=u32::MAX)?,
+                    packet_data,
+                    options: block_options(u, BlockType::EnhancedPacket)?,
+                })
+            }
+            3 => {
+                let packet_data: &[u8] = u.arbitrary()?;
+                let orig_len = u.int_in_range(packet_data.len() as u32..=u32::MAX)?;
+                Block::SimplePacket(SimplePacketBlock::new(orig_len, packet_data))
+            }
+            4 => Block::InterfaceStatistics(InterfaceStatistics {
+                interface_id: u.arbitrary()?,
+                ts_high: u.arbitrary()?,
+                ts_low: u.arbitrary()?,
+                options: block_options(u, BlockType::InterfaceStatistics)?,
+            }),
+            _ => {
+                let block_type = u.choose(&[
+                    BlockType::NameResolution,
+                    BlockType::DecryptionSecrets,
+                    BlockType::Custom,
+                    BlockType::CustomNoCopy,
+                ])?;
+                let body: &[u8] = u.arbitrary()?;
+                let body = &body[..body.len() / 4 * 4];
+                let len = (BLOCK_COMMON_LEN + body.len()) as u32;
+                Block::Unknown(RawBlock::new(block_type.value(), len, len, body))
+            }
+        })
+    }
+}
+
+fn collect_options(options: &[BlockOption]) -> Options<'_> {
+    let mut opts = Options::new();
+    opts.add_options(options);
+    opts
+}
+
+/// Calls `$f` with the writable block for `$block`.
+macro_rules! with_block {
+    ($block:expr, |$b:ident| $f:expr) => {
+        match $block {
+            Block::SectionHeader(shb) => {
+                let options = shb.options();
+                let $b = &shb.to_block(&options);
+                $f
+            }
+            Block::InterfaceDescription(idb) => {
+                let options = idb.options();
+                let $b = &idb.to_block(&options);
+                $f
+            }
+            Block::EnhancedPacket(epb) => {
+                let options = epb.options();
+                let $b = &epb.to_block(&options);
+                $f
+            }
+            Block::InterfaceStatistics(isb) => {
+                let options = isb.options();
+                let $b = &isb.to_block(&options);
+                $f
+            }
+            Block::SimplePacket($b) => $f,
+            Block::Unknown($b) => $f,
+        }
+    };
+}
+
+impl<W: Write> Encodable<W> for Block<'_> {
+    fn encode<B: ByteOrder>(&self, w: &mut W) -> Result<()> {
+        with_block!(self, |b| Encodable::<W>::encode::<B>(b, w))
+    }
+
+    fn validate(&self) -> Result<()> {
+        with_block!(self, |b| Encodable::<W>::validate(b))
+    }
+
+    fn block_type(&self) -> Option<u32> {
+        with_block!(self, |b| Encodable::<W>::block_type(b))
+    }
+
+    fn interface_id(&self) -> Option<u32> {
+        with_block!(self, |b| Encodable::<W>::interface_id(b))
+    }
+}
+
+/// Reads blocks from a byte slice.
+///
+/// The slice must start with a Section Header Block. Iteration stops
+/// after the first error.
+#[derive(Debug)]
+pub struct SliceReader<'a> {
+    data: &'a [u8],
+    endianness: Option<Endianness>,
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            endianness: None,
+        }
+    }
+
+    /// Returns the byte order of the current section, once its
+    /// Section Header Block has been read.
+    pub fn endianness(&self) -> Option<Endianness> {
+        self.endianness
+    }
+
+    /// Returns the data that has not been read yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.data
+    }
+
+    fn next_block(&mut self) -> Result<Block<'a>> {
+        if self.data.len() < BLOCK_COMMON_LEN {
+            return Err(invalid("truncated block").into());
+        }
+        let (block_type, total_length) = parse_header(&mut self.endianness, self.data)?;
+        if self.data.len() < total_length {
+            return Err(invalid("truncated block").into());
+        }
+        let (block, rest) = self.data.split_at(total_length);
+        self.data = rest;
+        parse_block(self.endianness, block_type, block)
+    }
+}
+
+impl<'a> Iterator for SliceReader<'a> {
+    type Item = Result<Block<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let block = self.next_block();
+        if block.is_err() {
+            self.data = &[];
+        }
+        Some(block)
+    }
+}
+
+/// Reads blocks from a `std::io::Read`, one at a time.
+///
+/// Each block is read into a buffer owned by the reader, which the
+/// returned `Block` borrows.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct PcapNgReader<R: std::io::Read> {
+    reader: R,
+    buf: Vec<u8>,
+    endianness: Option<Endianness>,
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> PcapNgReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: Vec::new(),
+            endianness: None,
+        }
+    }
+
+    /// Returns the byte order of the current section, once its
+    /// Section Header Block has been read.
+    pub fn endianness(&self) -> Option<Endianness> {
+        self.endianness
+    }
+
+    /// Reads the next block. Returns `None` at the end of the stream.
+    pub fn next_block(&mut self) -> Result<Option<Block<'_>>> {
+        self.buf.resize(BLOCK_COMMON_LEN, 0);
+        let mut read = 0;
+        while read < BLOCK_COMMON_LEN {
+            match self.reader.read(&mut self.buf[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(invalid("truncated block").into()),
+                Ok(n) => read += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let (block_type, total_length) = parse_header(&mut self.endianness, &self.buf)?;
+        // the buffer grows as the body arrives, so a bogus length does
+        // not allocate more than the data
+        let rest = (total_length - BLOCK_COMMON_LEN) as u64;
+        let mut body = std::io::Read::take(&mut self.reader, rest);
+        let read = std::io::Read::read_to_end(&mut body, &mut self.buf)?;
+        if (read as u64) < rest {
+            return Err(invalid("truncated block").into());
+        }
+        parse_block(self.endianness, block_type, &self.buf).map(Some)
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Parses a complete block, whose header has been checked by
+/// `parse_header`.
+fn parse_block(endianness: Option<Endianness>, block_type: u32, block: &[u8]) -> Result<Block<'_>> {
+    match endianness {
+        Some(Endianness::Big) => parse_block_with::<BigEndian>(Endianness::Big, block_type, block),
+        _ => parse_block_with::<LittleEndian>(Endianness::Little, block_type, block),
+    }
+}
+
+fn parse_block_with<B: ByteOrder>(
+    endianness: Endianness,
+    block_type: u32,
+    block: &[u8],
+) -> Result<Block<'_>> {
+    let total_length = block.len();
+    if B::read_u32(&block[total_length - 4..]) as usize != total_length {
+        return Err(invalid("block total lengths differ").into());
+    }
+    let body = &block[8..total_length - 4];
+    let block = match block_type {
+        t if t == BlockType::SectionHeader.value() => {
+            let (fixed, options) = split(body, 16)?;
+            Block::SectionHeader(SectionHeader {
+                endianness,
+                major_version: B::read_u16(&fixed[4..]),
+                minor_version: B::read_u16(&fixed[6..]),
+                section_length: B::read_u64(&fixed[8..]),
+                options: parse_options::<B>(block_type, options)?,
+            })
+        }
+        t if t == BlockType::InterfaceDescription.value() => {
+            let (fixed, options) = split(body, 8)?;
+            Block::InterfaceDescription(InterfaceDescription {
+                link_type: B::read_u16(fixed),
+                snap_len: B::read_u32(&fixed[4..]),
+                options: parse_options::<B>(block_type, options)?,
+            })
+        }
+        t if t == BlockType::EnhancedPacket.value() => {
+            let (fixed, rest) = split(body, 20)?;
+            let cap_len = B::read_u32(&fixed[12..]) as usize;
+            let padded_len = cap_len + pad_to_32(cap_len);
+            if rest.len() < padded_len {
+                return Err(invalid("captured length exceeds the block").into());
+            }
+            Block::EnhancedPacket(EnhancedPacket {
+                interface_id: B::read_u32(fixed),
+                ts_high: B::read_u32(&fixed[4..]),
+                ts_low: B::read_u32(&fixed[8..]),
+                orig_len: B::read_u32(&fixed[16..]),
+                packet_data: &rest[..cap_len],
+                options: parse_options::<B>(block_type, &rest[padded_len..])?,
+            })
+        }
+        t if t == BlockType::SimplePacket.value() => {
+            let (fixed, rest) = split(body, 4)?;
+            let orig_len = B::read_u32(fixed);
+            let cap_len = rest.len().min(orig_len as usize);
+            Block::SimplePacket(SimplePacketBlock::new(orig_len, &rest[..cap_len]))
+        }
+        t if t == BlockType::InterfaceStatistics.value() => {
+            let (fixed, options) = split(body, 12)?;
+            Block::InterfaceStatistics(InterfaceStatistics {
+                interface_id: B::read_u32(fixed),
+                ts_high: B::read_u32(&fixed[4..]),
+                ts_low: B::read_u32(&fixed[8..]),
+                options: parse_options::<B>(block_type, options)?,
+            })
+        }
+        _ => {
+            let len = total_length as u32;
+            Block::Unknown(RawBlock::new(block_type, len, len, body))
+        }
+    };
+    Ok(block)
+}
+
+fn split(body: &[u8], fixed_len: usize) -> Result<(&[u8], &[u8])> {
+    if body.len() < fixed_len {
+        return Err(invalid("block is too short").into());
+    }
+    Ok(body.split_at(fixed_len))
+}
+
+fn parse_options<B: ByteOrder>(block_type: u32, mut data: &[u8]) -> Result<Vec<BlockOption>> {
+    let mut options = Vec::new();
+    while !data.is_empty() {
+        if data.len() < 4 {
+            return Err(invalid("truncated option").into());
+        }
+        let code = B::read_u16(data);
+        let length = B::read_u16(&data[2..]);
+        let padded_len = length as usize + pad_to_32(length as usize);
+        if data.len() < 4 + padded_len {
+            return Err(invalid("truncated option").into());
+        }
+        if code == 0 {
+            options.push(OptionEndOfOpt::new_option());
+            break;
+        }
+        let value = &data[4..4 + length as usize];
+        options.push(
+            parse_option::<B>(block_type, code, value)
+                .unwrap_or_else(|| BlockOption::Raw(RawOption::new(code, length, value.to_vec()))),
+        );
+        data = &data[4 + padded_len..];
+    }
+    Ok(options)
+}
+
+/// Parses the options that have a `BlockOption` variant. Returns
+/// `None` for other options and malformed values.
+fn parse_option<B: ByteOrder>(block_type: u32, code: u16, value: &[u8]) -> Option<BlockOption> {
+    let text = || str::from_utf8(value).ok();
+    if code == 1 {
+        return OptionComment::try_new_option(text()?).ok();
+    }
+    match block_type {
+        t if t == BlockType::SectionHeader.value() => match code {
+            2 => OptionShbHardware::try_new_option(text()?).ok(),
+            3 => OptionShbOs::try_new_option(text()?).ok(),
+            4 => OptionShbUserAppl::try_new_option(text()?).ok(),
+            _ => None,
+        },
+        t if t == BlockType::InterfaceDescription.value() => match (code, value.len()) {
+            (2, _) => OptionIfName::try_new_option(text()?).ok(),
+            (3, _) => OptionIfDescription::try_new_option(text()?).ok(),
+            (4, 8) => {
+                let ip: [u8; 4] = value[..4].try_into().ok()?;
+                let netmask: [u8; 4] = value[4..].try_into().ok()?;
+                Some(OptionIfIpv4Addr::from_addrs_option(
+                    Ipv4Addr::from(ip),
+                    Ipv4Addr::from(netmask),
+                ))
+            }
+            (5, 17) => {
+                let ip: [u8; 16] = value[..16].try_into().ok()?;
+                OptionIfIpv6Addr::from_addr_option(Ipv6Addr::from(ip), value[16]).ok()
+            }
+            (6, 6) => Some(OptionIfMacAddr::from_bytes_option(value.try_into().ok()?)),
+            (8, 8) => Some(OptionIfSpeed::new_option(B::read_u64(value))),
+            (9, 1) => Some(BlockOption::IfTsResol(OptionIfTsResol::new(value[0]))),
+            (11, n) if n > 0 => {
+                let filter_type = FilterType::from_code(value[0])?;
+                Some(BlockOption::IfFilter(OptionIfFilter::with_data(
+                    filter_type,
+                    &value[1..],
+                )))
+            }
+            (13, 1) => Some(OptionIfFcsLen::new_option(FcsLen::new(value[0]).ok()?)),
+            _ => None,
+        },
+        t if t == BlockType::InterfaceStatistics.value() => match (code, value.len()) {
+            (2, 8) => Some(OptionIsbStartTime::new_option(Timestamp::from_parts(
+                B::read_u32(value),
+                B::read_u32(&value[4..]),
+            ))),
+            (3, 8) => Some(OptionIsbEndTime::new_option(Timestamp::from_parts(
+                B::read_u32(value),
+                B::read_u32(&value[4..]),
+            ))),
+            (4, 8) => Some(OptionIsbIfRecv::new_option(B::read_u64(value))),
+            (5, 8) => Some(OptionIsbIfDrop::new_option(B::read_u64(value))),
+            (6, 8) => Some(OptionIsbFilterAccept::new_option(B::read_u64(value))),
+            (7, 8) => Some(OptionIsbOsDrop::new_option(B::read_u64(value))),
+            (8, 8) => Some(OptionIsbUsrDeliv::new_option(B::read_u64(value))),
+            _ => None,
+        },
+        t if t == BlockType::EnhancedPacket.value() => match (code, value.len()) {
+            (2, 4) => Some(BlockOption::EpbFlags(OptionEpbFlags::from_u32(
+                B::read_u32(value),
+            ))),
+            (3, n) if n > 0 => {
+                let algorithm = HashAlgorithm::from_code(value[0])?;
+                OptionEpbHash::try_new_option(algorithm, &value[1..]).ok()
+            }
+            (4, 8) => Some(OptionEpbDropCount::new_option(B::read_u64(value))),
+            (6, 4) => Some(OptionEpbQueue::new_option(B::read_u32(value))),
+            (7, _) => Some(OptionEpbVerdict::new_option(
+                VerdictType::from_payload::<B>(value)?,
+            )),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::OptionIfMacAddr;
+    use crate::enums::{EpbErrorFlags, LinkType, PacketDirection, ReceptionType};
+    use crate::writer::PcapNgWriter;
+
+    fn capture(endianness: Endianness) -> Vec<u8> {
+        let mut shb_opts = Options::new();
+        let comment = OptionComment::new_option("section");
+        let eoo = OptionEndOfOpt::new_option();
+        shb_opts.add_option(&comment);
+        shb_opts.add_option(&eoo);
+        let mut idb_opts = Options::new();
+        let name = OptionIfName::new_option("eth0");
+        let ipv4 = OptionIfIpv4Addr::new_option("192.168.1.10", "255.255.255.0");
+        let ipv6 = OptionIfIpv6Addr::new_option("2001:db8::1", 64);
+        let mac = OptionIfMacAddr::new_option("00:11:22:33:44:55");
+        let custom = BlockOption::Raw(RawOption::new(2988, 3, vec![1, 2, 3]));
+        let idb_opt_list = [name, ipv4, ipv6, mac, custom];
+        idb_opts.add_options(&idb_opt_list);
+        let mut epb_opts = Options::new();
+        let flags = OptionEpbFlags::new_option(
+            PacketDirection::Inbound,
+            ReceptionType::Unicast,
+            FcsLen::new(4).ok(),
+            EpbErrorFlags::empty(),
+        );
+        epb_opts.add_option(&flags);
+
+        let mut writer = PcapNgWriter::new(endianness, vec![]);
+        writer.start_new_section(&shb_opts).unwrap();
+        writer
+            .add_interface(LinkType::Ethernet, 1500, &idb_opts)
+            .unwrap();
+        let epb = EnhancedPacketBlock::new(0, 1, 2, 5, 60, &[7; 5], &epb_opts);
+        writer.write(&epb).unwrap();
+        writer.write(&SimplePacketBlock::new(3, &[8; 3])).unwrap();
+        writer
+            .write(&InterfaceStatisticsBlock::new(0, 3, 4, &Options::new()))
+            .unwrap();
+        writer
+            .write(&RawBlock::new(0x0bad, 16, 16, &[9; 4]))
+            .unwrap();
+        writer.get_writer().clone()
+    }
+
+    #[test]
+    fn read_blocks() {
+        for &endianness in &[Endianness::Little, Endianness::Big] {
+            let data = capture(endianness);
+            let mut reader = SliceReader::new(&data);
+            assert_eq!(reader.endianness(), None);
+            match reader.next().unwrap().unwrap() {
+                Block::SectionHeader(shb) => {
+                    assert_eq!(shb.endianness(), endianness);
+                    assert_eq!(shb.options().iter().count(), 2);
+                }
+                b => panic!("{:?}", b),
+            }
+            assert_eq!(reader.endianness(), Some(endianness));
+            match reader.next().unwrap().unwrap() {
+                Block::InterfaceDescription(idb) => {
+                    assert_eq!(idb.link_type(), 1);
+                    assert_eq!(idb.snap_len(), 1500);
+                    assert_eq!(idb.tsresol(), *DEFAULT_TSRES);
+                    let options = idb.options();
+                    let options: Vec<_> = options.iter().collect();
+                    match (options[0], options[3], options[4]) {
+                        (
+                            BlockOption::IfName(name),
+                            BlockOption::IfMacAddr(mac),
+                            BlockOption::Raw(raw),
+                        ) => {
+                            assert_eq!(name.name(), "eth0");
+                            assert_eq!(mac.mac_addr(), [0, 0x11, 0x22, 0x33, 0x44, 0x55]);
+                            assert_eq!((raw.code(), raw.value()), (2988, &[1, 2, 3][..]));
+                        }
+                        o => panic!("{:?}", o),
+                    }
+                }
+                b => panic!("{:?}", b),
+            }
+            match reader.next().unwrap().unwrap() {
+                Block::EnhancedPacket(epb) => {
+                    assert_eq!(epb.timestamp(), 1 << 32 | 2);
+                    assert_eq!(epb.orig_len(), 60);
+                    assert_eq!(epb.packet_data(), &[7; 5]);
+                    assert!(matches!(
+                        epb.options().iter().next(),
+                        Some(BlockOption::EpbFlags(_))
+                    ));
+                }
+                b => panic!("{:?}", b),
+            }
+            match reader.next().unwrap().unwrap() {
+                Block::SimplePacket(spb) => assert_eq!(spb.packet_data(), &[8; 3]),
+                b => panic!("{:?}", b),
+            }
+            match reader.next().unwrap().unwrap() {
+                Block::InterfaceStatistics(isb) => assert_eq!(isb.timestamp(), 3 << 32 | 4),
+                b => panic!("{:?}", b),
+            }
+            match reader.next().unwrap().unwrap() {
+                Block::Unknown(raw) => {
+                    assert_eq!(raw.block_type(), 0x0bad);
+                    assert_eq!(raw.body(), &[9; 4]);
+                }
+                b => panic!("{:?}", b),
+            }
+            assert!(reader.next().is_none());
+        }
+    }
+
+    #[test]
+    fn write_read_blocks() {
+        for &endianness in &[Endianness::Little, Endianness::Big] {
+            let data = capture(endianness);
+            let mut writer = PcapNgWriter::new(endianness, vec![]);
+            for block in SliceReader::new(&data) {
+                writer.write(&block.unwrap()).unwrap();
+            }
+            assert_eq!(writer.get_writer(), &data);
+        }
+    }
+
+    #[test]
+    fn modify_blocks() {
+        let data = capture(Endianness::Little);
+        let mut writer = PcapNgWriter::new_be(vec![]);
+        for block in SliceReader::new(&data) {
+            match block.unwrap() {
+                Block::InterfaceDescription(idb) => {
+                    let options = Options::new();
+                    writer.write(&idb.to_block(&options)).unwrap();
+                }
+                block => writer.write(&block).unwrap(),
+            }
+        }
+        let mut reader = SliceReader::new(writer.get_writer());
+        reader.next();
+        match reader.next().unwrap().unwrap() {
+            Block::InterfaceDescription(idb) => {
+                assert_eq!(idb.snap_len(), 1500);
+                assert!(idb.options().iter().next().is_none());
+            }
+            b => panic!("{:?}", b),
+        }
+        assert_eq!(reader.endianness(), Some(Endianness::Big));
+        assert_eq!(reader.count(), 4);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn stream_reader() {
+        let data = capture(Endianness::Big);
+        let mut reader = PcapNgReader::new(&data[..]);
+        let mut count = 0;
+        while let Some(block) = reader.next_block().unwrap() {
+            if let Block::EnhancedPacket(epb) = block {
+                assert_eq!(epb.packet_data(), &[7; 5]);
+            }
+            count += 1;
+        }
+        assert_eq!(count, 6);
+        assert_eq!(reader.endianness(), Some(Endianness::Big));
+
+        let mut reader = PcapNgReader::new(&data[..data.len() - 50]);
+        assert!((0..6).any(|_| reader.next_block().is_err()));
+
+        // a huge length in a short stream
+        let shb = BigEndian::read_u32(&data[4..]) as usize;
+        let mut huge = data[..shb + 12].to_vec();
+        huge[shb + 4..shb + 8].copy_from_slice(&0xFFFF_FFFCu32.to_be_bytes());
+        let mut reader = PcapNgReader::new(&huge[..]);
+        reader.next_block().unwrap();
+        assert!(reader.next_block().is_err());
+    }
+
+    #[test]
+    fn invalid_data() {
+        let data = capture(Endianness::Little);
+        // truncated SPB
+        let truncated = &data[..data.len() - 50];
+        assert!(SliceReader::new(truncated).any(|b| b.is_err()));
+        // trailing length of the last block
+        let mut corrupted = data.clone();
+        let len = corrupted.len();
+        corrupted[len - 4] = 12;
+        let results: Vec<_> = SliceReader::new(&corrupted).collect();
+        assert_eq!(results.len(), 6);
+        assert!(results[5].is_err());
+        // no section header
+        assert!(SliceReader::new(&data[data.len() - 16..])
+            .next()
+            .unwrap()
+            .is_err());
+    }
+}
@@ -0,0 +1,229 @@
+use crate::framing::BlockScanner;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Decides when a `BufferedWriter` passes its data on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// After every complete block.
+    EveryBlock,
+    /// Once at least this many bytes of complete blocks are buffered.
+    Bytes(usize),
+    /// Once this much time has passed since the last flush. The time
+    /// is only checked when a block is written.
+    Interval(Duration),
+    /// Only when `flush` is called. The buffer grows until then.
+    Manual,
+}
+
+/// A sink that buffers blocks and writes them to the inner writer
+/// according to a `FlushPolicy`.
+///
+/// Unlike `std::io::BufWriter`, data is only passed on at block
+/// boundaries, and the inner writer is flushed each time, so readers
+/// following the output (e.g. `tail -f` or a pipe to Wireshark) never
+/// see partial blocks. Calling `flush` writes everything buffered.
+///
+/// ```
+/// use pcapng_writer::sink::{BufferedWriter, FlushPolicy};
+/// use pcapng_writer::writer::PcapNgWriter;
+///
+/// let sink = BufferedWriter::new(vec![], FlushPolicy::Bytes(64 * 1024));
+/// let mut writer = PcapNgWriter::new_le(sink);
+/// ```
+#[derive(Debug)]
+pub struct BufferedWriter<W: Write> {
+    inner: W,
+    policy: FlushPolicy,
+    buf: Vec<u8>,
+    complete: usize,
+    scanner: BlockScanner,
+    /// Only kept for `FlushPolicy::Interval`, as reading the clock
+    /// panics on `wasm32-unknown-unknown`.
+    last_flush: Option<Instant>,
+}
+
+impl<W: Write> BufferedWriter<W> {
+    pub fn new(inner: W, policy: FlushPolicy) -> Self {
+        Self {
+            inner,
+            policy,
+            buf: vec![],
+            complete: 0,
+            scanner: BlockScanner::new(),
+            last_flush: interval_start(policy),
+        }
+    }
+
+    pub fn policy(&self) -> FlushPolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: FlushPolicy) {
+        self.policy = policy;
+        if self.last_flush.is_none() {
+            self.last_flush = interval_start(policy);
+        }
+    }
+
+    /// Returns the data that has not been passed on yet.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner writer. Writing to it
+    /// directly may interleave with buffered blocks.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    fn should_flush(&self) -> bool {
+        if self.complete == 0 {
+            return false;
+        }
+        match self.policy {
+            FlushPolicy::EveryBlock => true,
+            FlushPolicy::Bytes(n) => self.complete >= n,
+            FlushPolicy::Interval(interval) => self
+                .last_flush
+                .is_none_or(|last_flush| last_flush.elapsed() >= interval),
+            FlushPolicy::Manual => false,
+        }
+    }
+
+    fn flush_upto(&mut self, len: usize) -> io::Result<()> {
+        self.inner.write_all(&self.buf[..len])?;
+        self.buf.drain(..len);
+        self.complete -= len.min(self.complete);
+        self.inner.flush()?;
+        self.last_flush = interval_start(self.policy);
+        Ok(())
+    }
+}
+
+fn interval_start(policy: FlushPolicy) -> Option<Instant> {
+    match policy {
+        FlushPolicy::Interval(_) => Some(Instant::now()),
+        _ => None,
+    }
+}
+
+impl<W: Write> Write for BufferedWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let end = self.scanner.scan(data)?;
+        let start = self.buf.len();
+        self.buf.extend_from_slice(data);
+        if let Some(end) = end {
+            self.complete = start + end;
+        }
+        if self.should_flush() {
+            self.flush_upto(self.complete)?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_upto(self.buf.len())
+    }
+}
+
+impl<W: Write> Drop for BufferedWriter<W> {
+    fn drop(&mut self) {
+        // errors cannot be reported here; call `flush` to handle them
+        if !self.buf.is_empty() {
+            let _ = self.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::Options;
+    use crate::enums::LinkType;
+    use crate::writer::{Endianness, PcapNgWriter};
+
+    #[derive(Default)]
+    struct Recorder {
+        data: Vec<u8>,
+        flushes: usize,
+    }
+
+    impl Write for &mut Recorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    fn write_blocks(policy: FlushPolicy, out: &mut Recorder) {
+        let opts = Options::new();
+        let sink = BufferedWriter::new(out, policy);
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, sink);
+        let id = writer
+            .add_interface(LinkType::Ethernet, 0xFFFF, &opts)
+            .unwrap();
+        // SHB (28) + IDB (20) = 48 bytes so far
+        assert_eq!(writer.get_writer().buffer().len() % 48, 0);
+        for _ in 0..3 {
+            writer.write_packet(id, 0, &[0; 32]).unwrap();
+        }
+        // 240 bytes in total
+        let sink = writer.get_writer();
+        assert_eq!(sink.buffer().len() + sink.get_ref().data.len(), 240);
+    }
+
+    #[test]
+    fn every_block() {
+        let mut out = Recorder::default();
+        write_blocks(FlushPolicy::EveryBlock, &mut out);
+        // one per block and one when the writer is closed
+        assert_eq!(out.flushes, 6);
+        assert_eq!(out.data.len(), 240);
+    }
+
+    #[test]
+    fn bytes() {
+        let mut out = Recorder::default();
+        {
+            let opts = Options::new();
+            let sink = BufferedWriter::new(&mut out, FlushPolicy::Bytes(100));
+            let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, sink);
+            let id = writer
+                .add_interface(LinkType::Ethernet, 0xFFFF, &opts)
+                .unwrap();
+            writer.write_packet(id, 0, &[0; 32]).unwrap();
+            assert_eq!(writer.get_writer().get_ref().data.len(), 112);
+            writer.write_packet(id, 0, &[0; 32]).unwrap();
+            assert_eq!(writer.get_writer().buffer().len(), 64);
+            assert_eq!(writer.get_writer().get_ref().data.len(), 112);
+        }
+        // closing the writer writes the rest
+        assert_eq!(out.data.len(), 176);
+        assert_eq!(out.flushes, 2);
+    }
+
+    #[test]
+    fn manual() {
+        let mut out = Recorder::default();
+        write_blocks(FlushPolicy::Manual, &mut out);
+        assert_eq!(out.flushes, 1);
+        assert_eq!(out.data.len(), 240);
+    }
+
+    #[test]
+    fn clock_only_for_interval() {
+        let mut sink = BufferedWriter::new(vec![], FlushPolicy::EveryBlock);
+        assert!(sink.last_flush.is_none());
+        sink.set_policy(FlushPolicy::Interval(Duration::from_secs(1)));
+        assert!(sink.last_flush.is_some());
+    }
+}
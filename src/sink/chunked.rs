@@ -0,0 +1,233 @@
+use crate::framing::BlockScanner;
+use std::io::{self, Write};
+
+/// A destination that stores a capture as a series of chunks, e.g.
+/// the parts of a multipart upload to an object store.
+pub trait ChunkSink {
+    /// Stores the next chunk. Chunks are passed in order, and all
+    /// but the last one are at least as large as the chunk size of
+    /// the `ChunkedWriter`.
+    fn write_chunk(&mut self, chunk: &[u8]) -> io::Result<()>;
+
+    /// Completes the capture after the last chunk.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+impl<S: ChunkSink + ?Sized> ChunkSink for &mut S {
+    fn write_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+        (**self).write_chunk(chunk)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        (**self).finish()
+    }
+}
+
+impl<S: ChunkSink + ?Sized> ChunkSink for Box<S> {
+    fn write_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+        (**self).write_chunk(chunk)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        (**self).finish()
+    }
+}
+
+/// A sink that splits the output into chunks of whole blocks and
+/// hands them to a `ChunkSink`, so a long capture can be streamed to
+/// an object store without a local spool file.
+///
+/// Blocks are buffered until they add up to the chunk size, at which
+/// point they are passed on as one chunk. A chunk is therefore larger
+/// than the chunk size by less than one block, and only the last one
+/// may be smaller.
+///
+/// `finish` must be called to write the last chunk and complete the
+/// capture. Dropping the sink does it too, but ignores errors.
+///
+/// ```
+/// use pcapng_writer::capture::CaptureWriter;
+/// use pcapng_writer::enums::LinkType;
+/// use pcapng_writer::sink::{ChunkSink, ChunkedWriter};
+/// use std::io;
+///
+/// #[derive(Default)]
+/// struct Parts(Vec<Vec<u8>>);
+///
+/// impl ChunkSink for Parts {
+///     fn write_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+///         self.0.push(chunk.to_vec());
+///         Ok(())
+///     }
+///
+///     fn finish(&mut self) -> io::Result<()> {
+///         Ok(())
+///     }
+/// }
+///
+/// let mut sink = ChunkedWriter::new(Parts::default(), 8 << 20);
+/// {
+///     let mut capture = CaptureWriter::new(&mut sink).unwrap();
+///     let eth = capture.add_interface(LinkType::Ethernet, 0xFFFF).unwrap();
+///     // write packets
+/// }
+/// let parts = sink.finish().unwrap();
+/// assert_eq!(parts.0.len(), 1);
+/// ```
+pub struct ChunkedWriter<S: ChunkSink> {
+    sink: Option<S>,
+    chunk_size: usize,
+    chunks: u64,
+    buf: Vec<u8>,
+    complete: usize,
+    scanner: BlockScanner,
+}
+
+impl<S: ChunkSink> ChunkedWriter<S> {
+    /// Creates a sink writing chunks of at least `chunk_size` bytes.
+    pub fn new(sink: S, chunk_size: usize) -> Self {
+        Self {
+            sink: Some(sink),
+            chunk_size: chunk_size.max(1),
+            chunks: 0,
+            buf: vec![],
+            complete: 0,
+            scanner: BlockScanner::new(),
+        }
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Returns the number of chunks written so far.
+    pub fn chunks(&self) -> u64 {
+        self.chunks
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.sink
+            .as_ref()
+            .expect("sink is only taken when finishing")
+    }
+
+    /// Writes the data buffered as the last chunk, completes the
+    /// capture and returns the sink.
+    pub fn finish(mut self) -> io::Result<S> {
+        self.finish_sink()?;
+        Ok(self.sink.take().expect("sink is only taken when finishing"))
+    }
+
+    fn finish_sink(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            self.write_chunk(self.buf.len())?;
+        }
+        self.sink
+            .as_mut()
+            .expect("sink is only taken when finishing")
+            .finish()
+    }
+
+    fn write_chunk(&mut self, len: usize) -> io::Result<()> {
+        self.sink
+            .as_mut()
+            .expect("sink is only taken when finishing")
+            .write_chunk(&self.buf[..len])?;
+        self.buf.drain(..len);
+        self.complete -= len.min(self.complete);
+        self.chunks += 1;
+        Ok(())
+    }
+}
+
+impl<S: ChunkSink> Write for ChunkedWriter<S> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let end = self.scanner.scan(data)?;
+        let start = self.buf.len();
+        self.buf.extend_from_slice(data);
+        if let Some(end) = end {
+            self.complete = start + end;
+        }
+        if self.complete >= self.chunk_size {
+            self.write_chunk(self.complete)?;
+        }
+        Ok(data.len())
+    }
+
+    /// Does nothing, as chunks smaller than the chunk size can only
+    /// be written last.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: ChunkSink> Drop for ChunkedWriter<S> {
+    fn drop(&mut self) {
+        if self.sink.is_some() {
+            let _ = self.finish_sink();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::Options;
+    use crate::enums::LinkType;
+    use crate::writer::{Endianness, PcapNgWriter};
+
+    #[derive(Default)]
+    struct Parts {
+        chunks: Vec<Vec<u8>>,
+        finished: bool,
+    }
+
+    impl ChunkSink for Parts {
+        fn write_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+            assert!(!self.finished);
+            self.chunks.push(chunk.to_vec());
+            Ok(())
+        }
+
+        fn finish(&mut self) -> io::Result<()> {
+            self.finished = true;
+            Ok(())
+        }
+    }
+
+    fn write(out: &mut dyn Write) {
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, out);
+        let eth = writer
+            .add_interface(LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        for i in 0..10u8 {
+            writer.write_packet(eth, 0, &[i; 100]).unwrap();
+        }
+    }
+
+    #[test]
+    fn chunks() {
+        let mut plain = vec![];
+        write(&mut plain);
+        // SHB and IDB are 48 bytes, EPBs 132 bytes
+        let mut sink = ChunkedWriter::new(Parts::default(), 300);
+        write(&mut sink);
+        assert_eq!(sink.chunks(), 3);
+        let parts = sink.finish().unwrap();
+        assert!(parts.finished);
+        let lens: Vec<_> = parts.chunks.iter().map(Vec::len).collect();
+        assert_eq!(lens, [312, 396, 396, 264]);
+        assert_eq!(parts.chunks.concat(), plain);
+    }
+
+    #[test]
+    fn finish_on_drop() {
+        let mut parts = Parts::default();
+        {
+            let mut sink = ChunkedWriter::new(&mut parts, 1 << 20);
+            write(&mut sink);
+        }
+        assert!(parts.finished);
+        assert_eq!(parts.chunks.len(), 1);
+    }
+}
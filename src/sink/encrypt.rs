@@ -0,0 +1,403 @@
+use crate::framing::{invalid, BlockScanner};
+use aes_gcm::aead::consts::U12;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use std::io::{self, Read, Write};
+
+/// The first bytes of an encrypted capture.
+const MAGIC: &[u8; 8] = b"PCAPNGE1";
+
+/// Length of the random part of the nonces.
+const NONCE_PREFIX_LEN: usize = 8;
+
+/// Length of the authentication tag of a chunk.
+const TAG_LEN: usize = 16;
+
+/// Additional authenticated data of the last chunk and of the
+/// others, so a truncated file is detected.
+const LAST_CHUNK: &[u8] = &[1];
+const MORE_CHUNKS: &[u8] = &[0];
+
+/// A sink that encrypts the output with AES-256-GCM, so the capture
+/// is never stored in plaintext.
+///
+/// The output starts with the magic `PCAPNGE1` and an 8-byte random
+/// nonce prefix. It is followed by chunks of whole blocks, each
+/// stored as the big endian length of its ciphertext and the
+/// ciphertext. The nonce of a chunk is the prefix followed by the big
+/// endian chunk number, and the last chunk is marked in its
+/// additional data, so reordered, dropped or truncated chunks fail to
+/// decrypt. `DecryptingReader` reads the plaintext back.
+///
+/// A chunk is written once the complete blocks buffered reach the
+/// chunk size, 1 MiB by default, or when `flush` is called.
+/// `finish` must be called to write the last chunk. Dropping the sink
+/// writes it too, but ignores errors.
+///
+/// Once writing a chunk fails, the sink refuses to write anything
+/// else, as the output is incomplete and a chunk number is never
+/// used twice.
+///
+/// ```
+/// use pcapng_writer::sink::{DecryptingReader, EncryptingWriter};
+/// use pcapng_writer::writer::{Endianness, PcapNgWriter};
+/// use std::io::Read;
+///
+/// let key = [0x42; 32];
+/// let mut sink = EncryptingWriter::new(vec![], &key).unwrap();
+/// {
+///     let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, &mut sink);
+///     // add interfaces and write packets
+/// }
+/// let encrypted = sink.finish().unwrap();
+///
+/// let mut plain = vec![];
+/// DecryptingReader::new(&encrypted[..], &key)
+///     .read_to_end(&mut plain)
+///     .unwrap();
+/// ```
+pub struct EncryptingWriter<W: Write> {
+    inner: Option<W>,
+    cipher: Aes256Gcm,
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+    chunks: u32,
+    chunk_size: usize,
+    buf: Vec<u8>,
+    complete: usize,
+    scanner: BlockScanner,
+    failed: bool,
+}
+
+impl<W: Write> EncryptingWriter<W> {
+    /// Creates a sink encrypting with the 256-bit `key`, and writes
+    /// the header of the output.
+    pub fn new(mut inner: W, key: &[u8; 32]) -> io::Result<Self> {
+        let random = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut nonce_prefix = [0; NONCE_PREFIX_LEN];
+        nonce_prefix.copy_from_slice(&random[..NONCE_PREFIX_LEN]);
+        inner.write_all(MAGIC)?;
+        inner.write_all(&nonce_prefix)?;
+        Ok(Self {
+            inner: Some(inner),
+            cipher: Aes256Gcm::new(key.into()),
+            nonce_prefix,
+            chunks: 0,
+            chunk_size: 1 << 20,
+            buf: vec![],
+            complete: 0,
+            scanner: BlockScanner::new(),
+            failed: false,
+        })
+    }
+
+    /// Sets the size of plaintext from which a chunk is written.
+    pub fn set_chunk_size(&mut self, bytes: usize) {
+        self.chunk_size = bytes.max(1);
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner
+            .as_ref()
+            .expect("inner writer is only taken when finishing")
+    }
+
+    /// Writes the last chunk, with the data buffered, and returns the
+    /// inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_chunk(self.buf.len(), true)?;
+        let mut inner = self
+            .inner
+            .take()
+            .expect("inner writer is only taken when finishing");
+        inner.flush()?;
+        Ok(inner)
+    }
+
+    fn write_chunk(&mut self, len: usize, last: bool) -> io::Result<()> {
+        if self.failed {
+            return Err(io::Error::other("an earlier chunk could not be written"));
+        }
+        // the nonce is used up, even if the chunk is not written
+        let nonce = chunk_nonce(&self.nonce_prefix, self.chunks)?;
+        self.chunks += 1;
+        self.failed = true;
+        let aad = if last { LAST_CHUNK } else { MORE_CHUNKS };
+        let payload = Payload {
+            msg: &self.buf[..len],
+            aad,
+        };
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, payload)
+            .map_err(|_| invalid("chunk too long to encrypt"))?;
+        let inner = self
+            .inner
+            .as_mut()
+            .expect("inner writer is only taken when finishing");
+        inner.write_all(&(ciphertext.len() as u32).to_be_bytes())?;
+        inner.write_all(&ciphertext)?;
+        self.failed = false;
+        self.buf.drain(..len);
+        self.complete -= len.min(self.complete);
+        Ok(())
+    }
+}
+
+/// Returns the nonce of chunk number `chunk`.
+fn chunk_nonce(prefix: &[u8; NONCE_PREFIX_LEN], chunk: u32) -> io::Result<Nonce<U12>> {
+    if chunk == u32::MAX {
+        return Err(invalid("too many chunks"));
+    }
+    let mut nonce = [0; 12];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LEN..].copy_from_slice(&chunk.to_be_bytes());
+    Ok(nonce.into())
+}
+
+impl<W: Write> Write for EncryptingWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let end = self.scanner.scan(data)?;
+        let start = self.buf.len();
+        self.buf.extend_from_slice(data);
+        if let Some(end) = end {
+            self.complete = start + end;
+        }
+        if self.complete >= self.chunk_size {
+            self.write_chunk(self.complete, false)?;
+        }
+        Ok(data.len())
+    }
+
+    /// Writes the complete blocks buffered as a chunk. A block being
+    /// written stays buffered.
+    fn flush(&mut self) -> io::Result<()> {
+        if self.complete > 0 {
+            self.write_chunk(self.complete, false)?;
+        }
+        self.inner
+            .as_mut()
+            .expect("inner writer is only taken when finishing")
+            .flush()
+    }
+}
+
+impl<W: Write> Drop for EncryptingWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() && !self.failed {
+            let _ = self.write_chunk(self.buf.len(), true);
+        }
+    }
+}
+
+/// Reads the plaintext of the output of an `EncryptingWriter`.
+///
+/// Reading fails if the data was modified, or if it ends before the
+/// last chunk.
+pub struct DecryptingReader<R: Read> {
+    inner: R,
+    cipher: Aes256Gcm,
+    nonce_prefix: Option<[u8; NONCE_PREFIX_LEN]>,
+    chunks: u32,
+    plain: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    pub fn new(inner: R, key: &[u8; 32]) -> Self {
+        Self {
+            inner,
+            cipher: Aes256Gcm::new(key.into()),
+            nonce_prefix: None,
+            chunks: 0,
+            plain: vec![],
+            pos: 0,
+            done: false,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn read_header(&mut self) -> io::Result<[u8; NONCE_PREFIX_LEN]> {
+        let mut header = [0; 8 + NONCE_PREFIX_LEN];
+        self.inner.read_exact(&mut header)?;
+        if &header[..8] != MAGIC {
+            return Err(invalid("not an encrypted capture"));
+        }
+        let mut prefix = [0; NONCE_PREFIX_LEN];
+        prefix.copy_from_slice(&header[8..]);
+        Ok(prefix)
+    }
+
+    /// Decrypts the next chunk.
+    fn read_chunk(&mut self) -> io::Result<()> {
+        let prefix = match self.nonce_prefix {
+            Some(prefix) => prefix,
+            None => {
+                let prefix = self.read_header()?;
+                self.nonce_prefix = Some(prefix);
+                prefix
+            }
+        };
+        let mut len = [0; 4];
+        self.inner.read_exact(&mut len).map_err(truncated)?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len < TAG_LEN {
+            return Err(invalid("invalid chunk length"));
+        }
+        let mut ciphertext = vec![];
+        self.inner
+            .by_ref()
+            .take(len as u64)
+            .read_to_end(&mut ciphertext)?;
+        if ciphertext.len() < len {
+            return Err(invalid("truncated encrypted capture"));
+        }
+        let nonce = chunk_nonce(&prefix, self.chunks)?;
+        for aad in [MORE_CHUNKS, LAST_CHUNK] {
+            let payload = Payload {
+                msg: &ciphertext,
+                aad,
+            };
+            if let Ok(plain) = self.cipher.decrypt(&nonce, payload) {
+                self.plain = plain;
+                self.pos = 0;
+                self.chunks += 1;
+                self.done = aad == LAST_CHUNK;
+                return Ok(());
+            }
+        }
+        Err(invalid("chunk fails to decrypt"))
+    }
+}
+
+fn truncated(e: io::Error) -> io::Error {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        invalid("truncated encrypted capture")
+    } else {
+        e
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.plain.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.read_chunk()?;
+        }
+        let n = buf.len().min(self.plain.len() - self.pos);
+        buf[..n].copy_from_slice(&self.plain[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::Options;
+    use crate::enums::LinkType;
+    use crate::writer::{Endianness, PcapNgWriter};
+    use std::convert::TryInto;
+
+    const KEY: [u8; 32] = [9; 32];
+
+    fn encrypt(chunk_size: usize) -> (Vec<u8>, Vec<u8>) {
+        let mut sink = EncryptingWriter::new(vec![], &KEY).unwrap();
+        sink.set_chunk_size(chunk_size);
+        let mut plain = vec![];
+        for out in [&mut plain as &mut dyn Write, &mut sink] {
+            let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, out);
+            let eth = writer
+                .add_interface(LinkType::Ethernet, 0, &Options::new())
+                .unwrap();
+            for i in 0..10u8 {
+                writer.write_packet(eth, 0, &[i; 100]).unwrap();
+            }
+        }
+        (plain, sink.finish().unwrap())
+    }
+
+    fn decrypt(data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut plain = vec![];
+        DecryptingReader::new(data, &KEY).read_to_end(&mut plain)?;
+        Ok(plain)
+    }
+
+    #[test]
+    fn round_trip() {
+        for chunk_size in [1, 500, 1 << 20] {
+            let (plain, encrypted) = encrypt(chunk_size);
+            assert_eq!(&encrypted[..8], MAGIC);
+            assert!(encrypted.windows(100).all(|w| w != [1; 100]));
+            assert_eq!(decrypt(&encrypted).unwrap(), plain);
+        }
+    }
+
+    #[test]
+    fn chunks_of_blocks() {
+        let (_, encrypted) = encrypt(1);
+        // SHB, IDB, 10 EPBs and the empty last chunk
+        let mut chunks = 0;
+        let mut rest = &encrypted[16..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            rest = &rest[4 + len..];
+            chunks += 1;
+        }
+        assert_eq!(chunks, 13);
+    }
+
+    #[test]
+    fn write_error() {
+        /// Fails the fourth write, the ciphertext of the first chunk.
+        struct FailOnce(Vec<u8>, usize);
+
+        impl Write for FailOnce {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.1 += 1;
+                if self.1 == 4 {
+                    return Err(io::ErrorKind::Other.into());
+                }
+                self.0.write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut plain = vec![];
+        PcapNgWriter::new_auto_section(Endianness::Little, &mut plain)
+            .add_interface(LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        let mut sink = EncryptingWriter::new(FailOnce(vec![], 0), &KEY).unwrap();
+        sink.write_all(&plain).unwrap();
+        assert!(sink.flush().is_err());
+        // nothing else is encrypted, so no nonce is reused
+        let len = sink.get_ref().0.len();
+        sink.write_all(&plain).unwrap();
+        assert!(sink.flush().is_err());
+        assert_eq!(sink.get_ref().0.len(), len);
+        assert!(sink.finish().is_err());
+    }
+
+    #[test]
+    fn tampering() {
+        let (_, mut encrypted) = encrypt(500);
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 1;
+        assert!(decrypt(&encrypted).is_err());
+        let (_, encrypted) = encrypt(500);
+        // without the last chunk
+        let first = 16 + 4 + u32::from_be_bytes(encrypted[16..20].try_into().unwrap()) as usize;
+        assert!(decrypt(&encrypted[..first]).is_err());
+        assert!(DecryptingReader::new(&encrypted[..], &[0; 32])
+            .read_to_end(&mut vec![])
+            .is_err());
+    }
+}
@@ -0,0 +1,248 @@
+use crate::framing::BlockScanner;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// A sink for feeding a live view, e.g. Wireshark reading from a
+/// named pipe with `wireshark -k -i /tmp/pipe`.
+///
+/// Data are passed straight to the pipe without buffering, and the
+/// pipe is flushed after every complete block, so the reader sees
+/// each block as soon as it is written. Used with a `CaptureWriter`,
+/// the Section Header Block is sent as soon as the writer is created,
+/// and each Interface Description Block when the interface is added,
+/// so the reader can start decoding before the first packet.
+///
+/// Once the reader closes the pipe, writes fail with
+/// `io::ErrorKind::BrokenPipe`. With the `fifo` feature, `create`
+/// creates the pipe on Unix. On Windows, `create_named_pipe` creates
+/// a pipe such as `\\.\pipe\pcapng`, which Wireshark reads with
+/// `wireshark -k -i \\.\pipe\pcapng`.
+///
+/// ```no_run
+/// use pcapng_writer::capture::CaptureWriter;
+/// use pcapng_writer::enums::LinkType;
+/// use pcapng_writer::sink::FifoWriter;
+/// use std::time::SystemTime;
+///
+/// // after `mkfifo /tmp/pipe`; blocks until Wireshark opens the pipe
+/// let sink = FifoWriter::open("/tmp/pipe").unwrap();
+/// let mut capture = CaptureWriter::new(sink).unwrap();
+/// let eth = capture.add_interface(LinkType::Ethernet, 0xFFFF).unwrap();
+/// capture.write_packet(eth, SystemTime::now(), b"...").unwrap();
+/// ```
+#[derive(Debug)]
+pub struct FifoWriter<W: Write = File> {
+    inner: W,
+    scanner: BlockScanner,
+}
+
+impl FifoWriter<File> {
+    /// Opens an existing named pipe for writing. Opening blocks until
+    /// a reader opens the pipe.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().write(true).open(path)?;
+        Ok(Self::new(file))
+    }
+
+    /// Creates a named pipe at `path`, unless it already exists, and
+    /// opens it for writing. Opening blocks until a reader opens the
+    /// pipe.
+    #[cfg(all(unix, feature = "fifo"))]
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = path.as_ref();
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // SAFETY: `c_path` is a valid NUL-terminated string.
+        if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } == -1 {
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::AlreadyExists {
+                return Err(e);
+            }
+        }
+        Self::open(path)
+    }
+}
+
+#[cfg(windows)]
+impl FifoWriter<File> {
+    /// Creates the Windows named pipe `name`, e.g.
+    /// `\\.\pipe\pcapng`, and waits for a reader to connect to it.
+    /// Only one reader, on the local machine, can connect.
+    pub fn create_named_pipe(name: &str) -> io::Result<Self> {
+        self::windows::create_named_pipe(name).map(Self::new)
+    }
+}
+
+impl<W: Write> FifoWriter<W> {
+    /// Creates a sink writing to an open pipe, or to any other
+    /// writer.
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            scanner: BlockScanner::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for FifoWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        // only the bytes accepted by the pipe are scanned
+        let n = self.inner.write(data)?;
+        if self.scanner.scan(&data[..n])?.is_some() {
+            self.inner.flush()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::OsStr;
+    use std::fs::File;
+    use std::io;
+    use std::os::raw::c_void;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::{FromRawHandle, RawHandle};
+    use std::ptr;
+
+    const PIPE_ACCESS_OUTBOUND: u32 = 0x0000_0002;
+    const FILE_FLAG_FIRST_PIPE_INSTANCE: u32 = 0x0008_0000;
+    const PIPE_TYPE_BYTE: u32 = 0x0000_0000;
+    const PIPE_WAIT: u32 = 0x0000_0000;
+    const PIPE_REJECT_REMOTE_CLIENTS: u32 = 0x0000_0008;
+    const INVALID_HANDLE_VALUE: isize = -1;
+    const ERROR_PIPE_CONNECTED: i32 = 535;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn CreateNamedPipeW(
+            name: *const u16,
+            open_mode: u32,
+            pipe_mode: u32,
+            max_instances: u32,
+            out_buffer_size: u32,
+            in_buffer_size: u32,
+            default_timeout: u32,
+            security_attributes: *mut c_void,
+        ) -> RawHandle;
+        fn ConnectNamedPipe(pipe: RawHandle, overlapped: *mut c_void) -> i32;
+    }
+
+    pub fn create_named_pipe(name: &str) -> io::Result<File> {
+        let wide: Vec<u16> = OsStr::new(name).encode_wide().chain(Some(0)).collect();
+        // SAFETY: `wide` is a NUL-terminated UTF-16 string, and null
+        // security attributes are allowed.
+        let handle = unsafe {
+            CreateNamedPipeW(
+                wide.as_ptr(),
+                PIPE_ACCESS_OUTBOUND | FILE_FLAG_FIRST_PIPE_INSTANCE,
+                PIPE_TYPE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                1,
+                64 * 1024,
+                0,
+                0,
+                ptr::null_mut(),
+            )
+        };
+        if handle as isize == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the handle is valid and owned by nothing else; the
+        // file closes it, also if connecting fails.
+        let file = unsafe { File::from_raw_handle(handle) };
+        // SAFETY: the pipe was opened without FILE_FLAG_OVERLAPPED.
+        if unsafe { ConnectNamedPipe(handle, ptr::null_mut()) } == 0 {
+            // a reader may connect between the two calls
+            let e = io::Error::last_os_error();
+            if e.raw_os_error() != Some(ERROR_PIPE_CONNECTED) {
+                return Err(e);
+            }
+        }
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::CaptureWriter;
+    use crate::enums::LinkType;
+    use std::time::SystemTime;
+
+    #[derive(Default)]
+    struct Recorder {
+        data: Vec<u8>,
+        flushed: Vec<usize>,
+    }
+
+    impl Write for &mut Recorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushed.push(self.data.len());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn flush_every_block() {
+        let mut out = Recorder::default();
+        let mut capture = CaptureWriter::new(FifoWriter::new(&mut out)).unwrap();
+        assert_eq!(capture.get_writer().get_writer().get_ref().flushed, [28]);
+        let eth = capture.add_interface(LinkType::Ethernet, 0xFFFF).unwrap();
+        capture
+            .write_packet(eth, SystemTime::now(), &[0; 32])
+            .unwrap();
+        drop(capture);
+        // SHB, IDB with if_tsresol and EPB
+        assert_eq!(out.flushed[..3], [28, 56, 120]);
+        assert_eq!(out.data.len(), 120);
+    }
+
+    #[cfg(all(unix, feature = "fifo"))]
+    #[test]
+    fn named_pipe() {
+        use std::io::Read;
+
+        let path = std::env::temp_dir().join(format!("pcapng-writer-fifo-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let reader = {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                // wait for the pipe to be created
+                while !path.exists() {
+                    std::thread::yield_now();
+                }
+                let mut data = vec![];
+                File::open(&path).unwrap().read_to_end(&mut data).unwrap();
+                data
+            })
+        };
+        {
+            let mut capture = CaptureWriter::new(FifoWriter::create(&path).unwrap()).unwrap();
+            capture.add_interface(LinkType::Ethernet, 0xFFFF).unwrap();
+        }
+        let data = reader.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(&data[..4], &[0x0a, 0x0d, 0x0d, 0x0a]);
+        assert_eq!(data.len(), 56);
+    }
+}
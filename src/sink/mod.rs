@@ -0,0 +1,64 @@
+//! Sinks that a `PcapNgWriter` can write to.
+//!
+//! Sinks implement `std::io::Write` and can be passed to any of the
+//! writers in this crate. They split the written stream into blocks,
+//! so they never cut a block in half, regardless of how the writer
+//! chunks its output.
+//!
+//! `RotatingWriter` splits a capture into files of limited size or
+//! duration. `TcpStreamWriter` streams it to a remote collector,
+//! reconnecting when needed, over TLS with the `rustls` feature.
+//! `UnixSocketWriter` streams it to local analyzers, and `FifoWriter`
+//! feeds a live view, e.g. Wireshark reading from a named pipe.
+//! `ChunkedWriter` hands the output to a `ChunkSink` in chunks of
+//! whole blocks.
+//!
+//! With the `io-uring` feature, `UringWriter` writes to a file with
+//! io_uring on Linux; with `wasm`, `WritableStreamSink` writes to a
+//! JavaScript `WritableStream`; with `signing`, `SigningWriter` signs
+//! each section; with `encryption`, `EncryptingWriter` encrypts the
+//! output with AES-GCM; with `zstd`, `ZstdWriter` compresses it; and
+//! with `s3`, `S3MultipartSink` uploads the chunks of a
+//! `ChunkedWriter` to S3.
+
+mod buffered;
+mod chunked;
+#[cfg(feature = "encryption")]
+mod encrypt;
+mod fifo;
+mod rotate;
+#[cfg(feature = "s3")]
+mod s3;
+#[cfg(feature = "signing")]
+mod signing;
+#[cfg(feature = "wasm")]
+mod stream;
+mod tcp;
+#[cfg(unix)]
+mod unix;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
+#[cfg(feature = "zstd")]
+mod zstd;
+
+pub use self::buffered::{BufferedWriter, FlushPolicy};
+pub use self::chunked::{ChunkSink, ChunkedWriter};
+#[cfg(feature = "encryption")]
+pub use self::encrypt::{DecryptingReader, EncryptingWriter};
+pub use self::fifo::FifoWriter;
+pub use self::rotate::RotatingWriter;
+#[cfg(feature = "s3")]
+pub use self::s3::S3MultipartSink;
+#[cfg(feature = "signing")]
+pub use self::signing::{
+    verify_sections, Ed25519Signer, SectionSignature, SectionSigner, SigningWriter,
+};
+#[cfg(feature = "wasm")]
+pub use self::stream::WritableStreamSink;
+pub use self::tcp::TcpStreamWriter;
+#[cfg(unix)]
+pub use self::unix::UnixSocketWriter;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub use self::uring::UringWriter;
+#[cfg(feature = "zstd")]
+pub use self::zstd::ZstdWriter;
@@ -0,0 +1,638 @@
+use crate::enums::BlockType;
+use crate::framing::{BlockFramer, Frame};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A sink that writes to a series of files, starting a new file when
+/// the current one reaches a size limit or a time interval elapses.
+///
+/// By default, files are named after `path` with a sequence number
+/// inserted before the extension, i.e. `capture.pcapng` is written as
+/// `capture_00001.pcapng`, `capture_00002.pcapng`, etc. If the file
+/// name contains `%`, it is used as a template instead, and the
+/// following conversions are replaced with the (UTC) time the file is
+/// created:
+///
+/// | Conversion | Replaced with                          |
+/// | ---------- | -------------------------------------- |
+/// | `%Y`       | year, e.g. `2021`                      |
+/// | `%m`       | month, `01` to `12`                    |
+/// | `%d`       | day of the month, `01` to `31`         |
+/// | `%H`       | hour, `00` to `23`                     |
+/// | `%M`       | minute, `00` to `59`                   |
+/// | `%S`       | second, `00` to `60`                   |
+/// | `%s`       | seconds since the Unix epoch           |
+/// | `%i`       | sequence number of the file, `00001`.. |
+/// | `%%`       | a literal `%`                          |
+///
+/// Existing files are never overwritten. If the name is already
+/// taken, e.g. because the template has no `%i` and two files are
+/// created within its time granularity, the sequence number is
+/// appended to it, as in `capture-2021-06-01T13_00002.pcapng`.
+///
+/// With `set_max_files`, only the most recent files are kept and older
+/// ones are deleted, like the ring buffer of `dumpcap -b files:N`.
+///
+/// Each new file starts with the Section Header Block and the
+/// Interface Description Blocks of the current section, so every file
+/// can be read on its own.
+///
+/// Files are only switched between blocks. A file that holds a single
+/// block larger than the size limit is therefore larger than the
+/// limit.
+///
+/// Blocks are written to the file as soon as they are complete.
+/// Wrap the `RotatingWriter` in a `BufWriter` to reduce the number of
+/// system calls. Once data is accepted by `write`, it is kept until
+/// its blocks are written: a block that fails to be written is tried
+/// again by the next call to `write` or `flush`, which report the
+/// error if it persists.
+///
+/// ```no_run
+/// use pcapng_writer::capture::CaptureWriter;
+/// use pcapng_writer::enums::LinkType;
+/// use pcapng_writer::sink::RotatingWriter;
+/// use std::time::{Duration, SystemTime};
+///
+/// // hourly files, e.g. capture-2021-06-01T13.pcapng
+/// let sink = RotatingWriter::new_timed(
+///     "capture-%Y-%m-%dT%H.pcapng",
+///     Duration::from_secs(3600),
+/// );
+/// let mut capture = CaptureWriter::new(sink).unwrap();
+/// let eth = capture.add_interface(LinkType::Ethernet, 0xFFFF).unwrap();
+/// capture.write_packet(eth, SystemTime::now(), b"...").unwrap();
+/// ```
+#[derive(Debug)]
+pub struct RotatingWriter {
+    path: PathBuf,
+    max_bytes: Option<u64>,
+    interval: Option<Duration>,
+    max_files: Option<usize>,
+    files: VecDeque<PathBuf>,
+    index: u32,
+    file: Option<File>,
+    current_path: Option<PathBuf>,
+    file_len: u64,
+    deadline: Option<SystemTime>,
+    has_data: bool,
+    headers: Vec<u8>,
+    framer: BlockFramer,
+    clock: fn() -> SystemTime,
+}
+
+impl RotatingWriter {
+    /// Creates a sink that starts a new file once the current one
+    /// would grow larger than `max_bytes`. No file is created until
+    /// the first block is written.
+    pub fn new<P: AsRef<Path>>(path: P, max_bytes: u64) -> Self {
+        Self {
+            max_bytes: Some(max_bytes),
+            ..Self::unlimited(path.as_ref())
+        }
+    }
+
+    /// Creates a sink that starts a new file every `interval`.
+    ///
+    /// Intervals are aligned to the Unix epoch, so an interval of one
+    /// hour switches files on the hour. No file is created until the
+    /// first block is written, and no empty files are created for
+    /// intervals without blocks.
+    pub fn new_timed<P: AsRef<Path>>(path: P, interval: Duration) -> Self {
+        let mut writer = Self::unlimited(path.as_ref());
+        writer.set_interval(Some(interval));
+        writer
+    }
+
+    fn unlimited(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            max_bytes: None,
+            interval: None,
+            max_files: None,
+            files: VecDeque::new(),
+            index: 0,
+            file: None,
+            current_path: None,
+            file_len: 0,
+            deadline: None,
+            has_data: false,
+            headers: vec![],
+            framer: BlockFramer::new(),
+            clock: SystemTime::now,
+        }
+    }
+
+    /// Sets the size limit of a file, or removes it with `None`.
+    pub fn set_max_bytes(&mut self, max_bytes: Option<u64>) {
+        self.max_bytes = max_bytes;
+    }
+
+    /// Sets the rotation interval, or removes it with `None`. A zero
+    /// interval is treated like `None`. Takes effect from the next
+    /// file.
+    pub fn set_interval(&mut self, interval: Option<Duration>) {
+        self.interval = interval.filter(|i| *i > Duration::from_secs(0));
+    }
+
+    /// Keeps at most `max_files` files, deleting the oldest file when a
+    /// new one is created. `None` keeps all files, and zero is treated
+    /// like one. Only files created by this writer are deleted.
+    pub fn set_max_files(&mut self, max_files: Option<usize>) {
+        self.max_files = max_files.map(|n| n.max(1));
+    }
+
+    /// Returns the path of the file currently being written, if any.
+    pub fn current_path(&self) -> Option<&Path> {
+        self.current_path.as_deref()
+    }
+
+    /// Returns the number of files created so far.
+    pub fn file_count(&self) -> u32 {
+        self.index
+    }
+
+    /// Writes the complete blocks pushed to the framer. A block that
+    /// fails to be written is put back.
+    fn write_frames(&mut self) -> io::Result<()> {
+        while let Some(frame) = self.framer.next_frame()? {
+            if let Err(e) = self.write_frame(&frame) {
+                self.framer.unread(frame);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        let is_shb = frame.is(BlockType::SectionHeader);
+        let is_header = is_shb || frame.is(BlockType::InterfaceDescription);
+        if is_shb {
+            self.headers.clear();
+        }
+        let len = frame.bytes.len() as u64;
+        let full = match self.max_bytes {
+            Some(max) => self.has_data && self.file_len + len > max,
+            None => false,
+        };
+        let now = (self.clock)();
+        let expired = match self.deadline {
+            Some(deadline) => now >= deadline,
+            None => false,
+        };
+        if self.file.is_none() || full || expired {
+            self.open_next(now, !is_shb)?;
+        }
+        if let Some(file) = self.file.as_mut() {
+            file.write_all(&frame.bytes)?;
+        }
+        self.file_len += len;
+        if is_header {
+            self.headers.extend_from_slice(&frame.bytes);
+        } else {
+            self.has_data = true;
+        }
+        Ok(())
+    }
+
+    fn open_next(&mut self, now: SystemTime, with_headers: bool) -> io::Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+        }
+        let (mut file, path) = self.create_file(self.index + 1, now)?;
+        self.index += 1;
+        if !self.files.contains(&path) {
+            self.files.push_back(path.clone());
+        }
+        self.current_path = Some(path);
+        self.remove_old_files()?;
+        self.file_len = 0;
+        self.has_data = false;
+        self.deadline = self.interval.map(|interval| next_boundary(now, interval));
+        if with_headers {
+            file.write_all(&self.headers)?;
+            self.file_len = self.headers.len() as u64;
+        }
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn remove_old_files(&mut self) -> io::Result<()> {
+        let max_files = match self.max_files {
+            Some(n) => n,
+            None => return Ok(()),
+        };
+        while self.files.len() > max_files {
+            let path = match self.files.pop_front() {
+                Some(path) => path,
+                None => break,
+            };
+            // a path listed again later is still in use, and the file
+            // being written is never removed
+            if self.files.contains(&path) {
+                continue;
+            }
+            if self.current_path.as_ref() == Some(&path) {
+                self.files.push_back(path);
+                continue;
+            }
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the next file, with the sequence number appended to its
+    /// name if the name is taken.
+    fn create_file(&self, index: u32, now: SystemTime) -> io::Result<(File, PathBuf)> {
+        let path = self.file_path(index, now);
+        let mut candidate = path.clone();
+        let mut suffix = index;
+        loop {
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&candidate)
+            {
+                Ok(file) => return Ok((file, candidate)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    candidate = numbered_path(&path, suffix);
+                    suffix += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn file_path(&self, index: u32, now: SystemTime) -> PathBuf {
+        match self.path.file_name().and_then(|name| name.to_str()) {
+            Some(name) if name.contains('%') => {
+                self.path.with_file_name(format_template(name, index, now))
+            }
+            _ => numbered_path(&self.path, index),
+        }
+    }
+}
+
+impl Write for RotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // blocks left by a failed write go first, and `buf` is only
+        // accepted once they are written
+        self.write_frames()?;
+        self.framer.push(buf);
+        // `buf` is kept by the framer, so an error is reported by the
+        // next call instead, which tries the block again
+        let _ = self.write_frames();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_frames()?;
+        match self.file.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Inserts `_NNNNN` between the file stem and the extension.
+fn numbered_path(path: &Path, index: u32) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!("_{:05}", index));
+    if let Some(ext) = path.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    path.with_file_name(name)
+}
+
+/// Returns the first multiple of `interval` (counted from the Unix
+/// epoch) after `now`.
+fn next_boundary(now: SystemTime, interval: Duration) -> SystemTime {
+    let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let interval_ns = interval.as_nanos();
+    let next = (since_epoch.as_nanos() / interval_ns + 1) * interval_ns;
+    let secs = (next / 1_000_000_000) as u64;
+    let nanos = (next % 1_000_000_000) as u32;
+    UNIX_EPOCH + Duration::new(secs, nanos)
+}
+
+/// Replaces the conversions listed in the `RotatingWriter` docs.
+/// Unknown conversions are kept as they are.
+fn format_template(template: &str, index: u32, time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let secs_of_day = secs % 86400;
+    let mut out = String::with_capacity(template.len() + 16);
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", year)),
+            Some('m') => out.push_str(&format!("{:02}", month)),
+            Some('d') => out.push_str(&format!("{:02}", day)),
+            Some('H') => out.push_str(&format!("{:02}", secs_of_day / 3600)),
+            Some('M') => out.push_str(&format!("{:02}", secs_of_day / 60 % 60)),
+            Some('S') => out.push_str(&format!("{:02}", secs_of_day % 60)),
+            Some('s') => out.push_str(&secs.to_string()),
+            Some('i') => out.push_str(&format!("{:05}", index)),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+/// Converts days since the Unix epoch to a (year, month, day) date in
+/// the proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::Options;
+    use crate::blocks::{EnhancedPacketBlock, InterfaceDescriptionBlock};
+    use crate::enums::LinkType;
+    use crate::framing::block_types;
+    use crate::writer::{Endianness, PcapNgWriter};
+    use std::fs;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("pcapng-writer-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn numbered_paths() {
+        assert_eq!(
+            numbered_path(Path::new("/tmp/capture.pcapng"), 1),
+            Path::new("/tmp/capture_00001.pcapng")
+        );
+        assert_eq!(
+            numbered_path(Path::new("capture"), 12),
+            Path::new("capture_00012")
+        );
+    }
+
+    #[test]
+    fn templates() {
+        // 2021-06-01T13:45:30Z
+        let time = UNIX_EPOCH + Duration::from_secs(1_622_555_130);
+        assert_eq!(
+            format_template("cap-%Y%m%d-%H%M%S-%i.pcapng", 3, time),
+            "cap-20210601-134530-00003.pcapng"
+        );
+        assert_eq!(format_template("%s %% %q %", 1, time), "1622555130 % %q %");
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn rotate_by_size() {
+        let dir = temp_dir("rotate-size");
+        let opts = Options::new();
+        // SHB (28) + IDB (20) + two 64-byte EPBs fit in 180 bytes
+        let sink = RotatingWriter::new(dir.join("capture.pcapng"), 180);
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, sink);
+        let id = writer
+            .add_interface(LinkType::Ethernet, 0xFFFF, &opts)
+            .unwrap();
+        for _ in 0..5 {
+            writer.write_packet(id, 0, &[0; 32]).unwrap();
+        }
+        let sink = writer.get_writer();
+        assert_eq!(sink.file_count(), 3);
+        assert_eq!(
+            sink.current_path().unwrap(),
+            dir.join("capture_00003.pcapng")
+        );
+
+        let shb = BlockType::SectionHeader.value();
+        let idb = BlockType::InterfaceDescription.value();
+        let epb = BlockType::EnhancedPacket.value();
+        for (i, count) in [(1, 2), (2, 2), (3, 1)].iter() {
+            let data = fs::read(dir.join(format!("capture_0000{}.pcapng", i))).unwrap();
+            let mut expected = vec![shb, idb];
+            expected.resize(2 + count, epb);
+            assert_eq!(block_types(&data), expected);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ring_buffer() {
+        let dir = temp_dir("rotate-ring");
+        let opts = Options::new();
+        // one packet per file
+        let mut sink = RotatingWriter::new(dir.join("capture.pcapng"), 100);
+        sink.set_max_files(Some(2));
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, sink);
+        let id = writer
+            .add_interface(LinkType::Ethernet, 0xFFFF, &opts)
+            .unwrap();
+        for _ in 0..5 {
+            writer.write_packet(id, 0, &[0; 32]).unwrap();
+        }
+        assert_eq!(writer.get_writer().file_count(), 5);
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["capture_00004.pcapng", "capture_00005.pcapng"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ring_buffer_current_file() {
+        let dir = temp_dir("rotate-ring-current");
+        let mut sink = RotatingWriter::new(dir.join("capture.pcapng"), 100);
+        sink.set_max_files(Some(1));
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, sink);
+        let id = writer
+            .add_interface(LinkType::Ethernet, 0xFFFF, &Options::new())
+            .unwrap();
+        writer.write_packet(id, 0, &[0; 32]).unwrap();
+        // the current path is listed before older files, e.g. after
+        // its name was reused
+        let sink = writer.get_writer_mut();
+        let current = sink.current_path().unwrap().to_path_buf();
+        sink.files.push_front(current.clone());
+        sink.files.push_back(dir.join("older.pcapng"));
+        sink.remove_old_files().unwrap();
+        assert!(current.exists());
+        assert_eq!(sink.files, [current]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn oversized_block() {
+        let dir = temp_dir("rotate-oversized");
+        let opts = Options::new();
+        let sink = RotatingWriter::new(dir.join("capture.pcapng"), 16);
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, sink);
+        writer
+            .write(&InterfaceDescriptionBlock::new(
+                LinkType::Ethernet,
+                0xFFFF,
+                &opts,
+            ))
+            .unwrap();
+        for _ in 0..2 {
+            writer
+                .write(&EnhancedPacketBlock::new(0, 0, 0, 4, 4, &[0; 4], &opts))
+                .unwrap();
+        }
+        // headers alone are larger than the limit; each file holds
+        // exactly one packet
+        assert_eq!(writer.get_writer().file_count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_write() {
+        let dir = temp_dir("rotate-failed");
+        let mut data = vec![];
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, &mut data);
+        let id = writer
+            .add_interface(LinkType::Ethernet, 0xFFFF, &Options::new())
+            .unwrap();
+        writer.write_packet(id, 0, &[0; 4]).unwrap();
+        drop(writer);
+
+        // the directory is missing, so no file can be created
+        let mut sink = RotatingWriter::new(dir.join("missing/capture.pcapng"), 1000);
+        assert_eq!(sink.write(&data[..28]).unwrap(), 28);
+        assert!(sink.write(&data[28..]).is_err());
+        assert!(sink.flush().is_err());
+        assert_eq!(sink.file_count(), 0);
+        fs::create_dir(dir.join("missing")).unwrap();
+        // the accepted data is written once
+        sink.flush().unwrap();
+        assert_eq!(sink.file_count(), 1);
+        sink.write_all(&data[28..]).unwrap();
+        let written = fs::read(dir.join("missing/capture_00001.pcapng")).unwrap();
+        assert_eq!(written, data);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    static NOW: AtomicU64 = AtomicU64::new(0);
+
+    fn fake_clock() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(NOW.load(Ordering::SeqCst))
+    }
+
+    #[test]
+    fn same_second() {
+        fn fixed_clock() -> SystemTime {
+            UNIX_EPOCH + Duration::from_secs(1_622_555_130)
+        }
+
+        let dir = temp_dir("rotate-same-second");
+        // an earlier capture with the same name is kept
+        fs::write(dir.join("cap-134530.pcapng"), b"earlier").unwrap();
+        let mut sink = RotatingWriter::new(dir.join("cap-%H%M%S.pcapng"), 100);
+        sink.clock = fixed_clock;
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, sink);
+        let id = writer
+            .add_interface(LinkType::Ethernet, 0xFFFF, &Options::new())
+            .unwrap();
+        for _ in 0..3 {
+            writer.write_packet(id, 0, &[0; 32]).unwrap();
+        }
+        assert_eq!(writer.get_writer().file_count(), 3);
+        assert_eq!(
+            writer.get_writer().current_path().unwrap(),
+            dir.join("cap-134530_00003.pcapng")
+        );
+
+        let epb = BlockType::EnhancedPacket.value();
+        assert_eq!(fs::read(dir.join("cap-134530.pcapng")).unwrap(), b"earlier");
+        for name in [
+            "cap-134530_00001.pcapng",
+            "cap-134530_00002.pcapng",
+            "cap-134530_00003.pcapng",
+        ]
+        .iter()
+        {
+            let types = block_types(&fs::read(dir.join(name)).unwrap());
+            assert_eq!(types.iter().filter(|&&t| t == epb).count(), 1);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotate_by_time() {
+        let dir = temp_dir("rotate-time");
+        let opts = Options::new();
+        let mut sink =
+            RotatingWriter::new_timed(dir.join("cap-%H%M.pcapng"), Duration::from_secs(60));
+        sink.clock = fake_clock;
+        NOW.store(1_622_555_130, Ordering::SeqCst);
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, sink);
+        let id = writer
+            .add_interface(LinkType::Ethernet, 0xFFFF, &opts)
+            .unwrap();
+        writer.write_packet(id, 0, &[0; 4]).unwrap();
+        // same minute
+        NOW.store(1_622_555_159, Ordering::SeqCst);
+        writer.write_packet(id, 0, &[0; 4]).unwrap();
+        assert_eq!(writer.get_writer().file_count(), 1);
+        // next minute
+        NOW.store(1_622_555_160, Ordering::SeqCst);
+        writer.write_packet(id, 0, &[0; 4]).unwrap();
+        // an idle minute does not create a file
+        NOW.store(1_622_555_290, Ordering::SeqCst);
+        writer.write_packet(id, 0, &[0; 4]).unwrap();
+        assert_eq!(writer.get_writer().file_count(), 3);
+
+        let shb = BlockType::SectionHeader.value();
+        let idb = BlockType::InterfaceDescription.value();
+        let epb = BlockType::EnhancedPacket.value();
+        for (name, count) in [
+            ("cap-1345.pcapng", 2),
+            ("cap-1346.pcapng", 1),
+            ("cap-1348.pcapng", 1),
+        ]
+        .iter()
+        {
+            let data = fs::read(dir.join(name)).unwrap();
+            let mut expected = vec![shb, idb];
+            expected.resize(2 + count, epb);
+            assert_eq!(block_types(&data), expected);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
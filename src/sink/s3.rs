@@ -0,0 +1,172 @@
+use crate::sink::ChunkSink;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use aws_sdk_s3::Client;
+use std::io;
+use tokio::runtime::Handle;
+
+/// A `ChunkSink` that uploads a capture to Amazon S3 (or a compatible
+/// object store) with a multipart upload, one part per chunk.
+///
+/// The upload is started with the first chunk and completed by
+/// `finish`. If the capture is not completed, e.g. after an error,
+/// call `abort` so the store discards the parts already uploaded.
+///
+/// S3 rejects parts smaller than `MIN_PART_SIZE`, other than the last
+/// one, so the `ChunkedWriter` must use a chunk size of at least that.
+///
+/// The SDK is asynchronous: the requests are run on the Tokio runtime
+/// of `handle` and the sink blocks until they complete. It must
+/// therefore be used outside of the runtime, e.g. from a thread of its
+/// own or in `tokio::task::spawn_blocking`.
+///
+/// ```no_run
+/// use pcapng_writer::capture::CaptureWriter;
+/// use pcapng_writer::enums::LinkType;
+/// use pcapng_writer::sink::{ChunkedWriter, S3MultipartSink};
+/// use std::time::SystemTime;
+///
+/// # async fn upload(client: aws_sdk_s3::Client) {
+/// let handle = tokio::runtime::Handle::current();
+/// tokio::task::spawn_blocking(move || {
+///     let s3 = S3MultipartSink::new(client, handle, "captures", "eth0.pcapng");
+///     let mut sink = ChunkedWriter::new(s3, 16 << 20);
+///     {
+///         let mut capture = CaptureWriter::new(&mut sink).unwrap();
+///         let eth = capture.add_interface(LinkType::Ethernet, 0xFFFF).unwrap();
+///         capture.write_packet(eth, SystemTime::now(), b"...").unwrap();
+///     }
+///     sink.finish().unwrap();
+/// })
+/// .await
+/// .unwrap();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct S3MultipartSink {
+    client: Client,
+    handle: Handle,
+    bucket: String,
+    key: String,
+    upload_id: Option<String>,
+    parts: Vec<CompletedPart>,
+}
+
+impl S3MultipartSink {
+    /// The minimum size of all parts but the last.
+    pub const MIN_PART_SIZE: usize = 5 << 20;
+
+    /// Creates a sink uploading to the object `key` in `bucket`.
+    pub fn new(client: Client, handle: Handle, bucket: &str, key: &str) -> Self {
+        Self {
+            client,
+            handle,
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            upload_id: None,
+            parts: vec![],
+        }
+    }
+
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Returns the ID of the multipart upload, once started.
+    pub fn upload_id(&self) -> Option<&str> {
+        self.upload_id.as_deref()
+    }
+
+    /// Returns the number of parts uploaded.
+    pub fn parts(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// Aborts the multipart upload, if started, so the parts
+    /// uploaded are discarded.
+    pub fn abort(&mut self) -> io::Result<()> {
+        let upload_id = match self.upload_id.take() {
+            Some(upload_id) => upload_id,
+            None => return Ok(()),
+        };
+        self.parts.clear();
+        let request = self
+            .client
+            .abort_multipart_upload()
+            .bucket(&self.bucket)
+            .key(&self.key)
+            .upload_id(upload_id)
+            .send();
+        self.handle.block_on(request).map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn upload_id_or_start(&mut self) -> io::Result<String> {
+        if let Some(upload_id) = &self.upload_id {
+            return Ok(upload_id.clone());
+        }
+        let request = self
+            .client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(&self.key)
+            .content_type("application/vnd.tcpdump.pcap")
+            .send();
+        let output = self.handle.block_on(request).map_err(io::Error::other)?;
+        let upload_id = output
+            .upload_id()
+            .ok_or_else(|| io::Error::other("no upload ID in the response"))?;
+        Ok(self.upload_id.insert(upload_id.to_string()).clone())
+    }
+}
+
+impl ChunkSink for S3MultipartSink {
+    fn write_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+        let upload_id = self.upload_id_or_start()?;
+        // part numbers start at 1
+        let part_number = self.parts.len() as i32 + 1;
+        let request = self
+            .client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(&self.key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(chunk.to_vec()))
+            .send();
+        let output = self.handle.block_on(request).map_err(io::Error::other)?;
+        let mut part = CompletedPart::builder().part_number(part_number);
+        if let Some(e_tag) = output.e_tag() {
+            part = part.e_tag(e_tag);
+        }
+        self.parts.push(part.build());
+        Ok(())
+    }
+
+    /// Completes the multipart upload. An empty capture is uploaded
+    /// as a single empty part.
+    fn finish(&mut self) -> io::Result<()> {
+        if self.parts.is_empty() {
+            self.write_chunk(&[])?;
+        }
+        let upload_id = self.upload_id_or_start()?;
+        let upload = CompletedMultipartUpload::builder()
+            .set_parts(Some(std::mem::take(&mut self.parts)))
+            .build();
+        let request = self
+            .client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(&self.key)
+            .upload_id(upload_id)
+            .multipart_upload(upload)
+            .send();
+        self.handle.block_on(request).map_err(io::Error::other)?;
+        self.upload_id = None;
+        Ok(())
+    }
+}
@@ -0,0 +1,343 @@
+use crate::blocks::options::Options;
+use crate::blocks::CustomBlock;
+use crate::constants::BYTE_ORDER_MAGIC;
+use crate::enums::BlockType;
+use crate::framing::{invalid, BlockFramer, Frame};
+use crate::writer::{Encodable, Endianness};
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use ed25519_dalek::{Signer, SigningKey};
+use sha2::{Digest, Sha256};
+use std::convert::TryInto;
+use std::io::{self, Write};
+
+/// Signs the SHA-256 digests of sections for a `SigningWriter`.
+pub trait SectionSigner {
+    /// Identifies the signature algorithm in the signature blocks.
+    fn algorithm(&self) -> u32;
+
+    /// Returns the signature of the digest of a section.
+    fn sign(&mut self, digest: &[u8; 32]) -> io::Result<Vec<u8>>;
+}
+
+/// Signs sections with an Ed25519 key.
+pub struct Ed25519Signer {
+    key: SigningKey,
+}
+
+impl Ed25519Signer {
+    /// The algorithm number of Ed25519 signatures.
+    pub const ALGORITHM: u32 = 1;
+
+    pub fn new(key: SigningKey) -> Self {
+        Self { key }
+    }
+
+    pub fn key(&self) -> &SigningKey {
+        &self.key
+    }
+}
+
+impl SectionSigner for Ed25519Signer {
+    fn algorithm(&self) -> u32 {
+        Self::ALGORITHM
+    }
+
+    fn sign(&mut self, digest: &[u8; 32]) -> io::Result<Vec<u8>> {
+        Ok(self.key.sign(digest).to_bytes().to_vec())
+    }
+}
+
+/// A signature block, as found by `verify_sections`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionSignature {
+    algorithm: u32,
+    digest: [u8; 32],
+    signature: Vec<u8>,
+}
+
+impl SectionSignature {
+    pub fn algorithm(&self) -> u32 {
+        self.algorithm
+    }
+
+    /// Returns the SHA-256 digest of the section.
+    pub fn digest(&self) -> &[u8; 32] {
+        &self.digest
+    }
+
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+}
+
+/// A sink that signs each section, for tamper evidence.
+///
+/// The sink hashes every section with SHA-256 and, when the next
+/// section starts or the sink is finished, appends a Custom Block of
+/// the organization with the Private Enterprise Number `pen` that
+/// holds the signature of the digest. The block data are, in the
+/// byte order of the section, the algorithm number, the 32 bytes of
+/// the digest, the length of the signature and the signature; the
+/// digest covers all the bytes of the section before the block.
+///
+/// `finish` must be called to sign the last section. Dropping the
+/// sink signs it too, but ignores errors.
+///
+/// ```
+/// use ed25519_dalek::SigningKey;
+/// use pcapng_writer::sink::{verify_sections, Ed25519Signer, SigningWriter};
+/// use pcapng_writer::writer::{Endianness, PcapNgWriter};
+///
+/// let key = SigningKey::from_bytes(&[7; 32]);
+/// let public = key.verifying_key();
+/// let mut sink = SigningWriter::new(vec![], Ed25519Signer::new(key), 32473);
+/// {
+///     let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, &mut sink);
+///     // add interfaces and write packets
+/// #   writer.start_new_section(&pcapng_writer::blocks::options::Options::new()).unwrap();
+/// }
+/// let signed = sink.finish().unwrap();
+///
+/// let sections = verify_sections(&signed, 32473, |s| {
+///     let signature = ed25519_dalek::Signature::from_slice(s.signature()).unwrap();
+///     public.verify_strict(s.digest(), &signature).is_ok()
+/// })
+/// .unwrap();
+/// assert_eq!(sections, 1);
+/// ```
+pub struct SigningWriter<W: Write, S: SectionSigner> {
+    inner: Option<W>,
+    signer: S,
+    pen: u32,
+    hasher: Sha256,
+    endianness: Option<Endianness>,
+    framer: BlockFramer,
+}
+
+impl<W: Write, S: SectionSigner> SigningWriter<W, S> {
+    pub fn new(inner: W, signer: S, pen: u32) -> Self {
+        Self {
+            inner: Some(inner),
+            signer,
+            pen,
+            hasher: Sha256::new(),
+            endianness: None,
+            framer: BlockFramer::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner
+            .as_ref()
+            .expect("inner writer is only taken when finishing")
+    }
+
+    /// Signs the last section and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.sign_section()?;
+        let mut inner = self
+            .inner
+            .take()
+            .expect("inner writer is only taken when finishing");
+        inner.flush()?;
+        Ok(inner)
+    }
+
+    fn inner_mut(&mut self) -> &mut W {
+        self.inner
+            .as_mut()
+            .expect("inner writer is only taken when finishing")
+    }
+
+    fn write_frame(&mut self, frame: Frame) -> io::Result<()> {
+        if frame.is(BlockType::SectionHeader) {
+            self.sign_section()?;
+            self.endianness = Some(match LittleEndian::read_u32(&frame.bytes[8..]) {
+                BYTE_ORDER_MAGIC => Endianness::Little,
+                _ => Endianness::Big,
+            });
+        }
+        self.hasher.update(&frame.bytes);
+        self.inner_mut().write_all(&frame.bytes)
+    }
+
+    /// Writes the signature block of the current section, if any.
+    fn sign_section(&mut self) -> io::Result<()> {
+        let endianness = match self.endianness.take() {
+            Some(endianness) => endianness,
+            None => return Ok(()),
+        };
+        let digest: [u8; 32] = std::mem::take(&mut self.hasher).finalize().into();
+        let signature = SectionSignature {
+            algorithm: self.signer.algorithm(),
+            digest,
+            signature: self.signer.sign(&digest)?,
+        };
+        let block = match endianness {
+            Endianness::Big => signature_block::<BigEndian>(&signature, self.pen),
+            Endianness::Little => signature_block::<LittleEndian>(&signature, self.pen),
+        }?;
+        self.inner_mut().write_all(&block)
+    }
+}
+
+/// Encodes the Custom Block of a signature.
+fn signature_block<B: ByteOrder>(signature: &SectionSignature, pen: u32) -> io::Result<Vec<u8>> {
+    let mut data = vec![0; 4 + 32 + 4];
+    B::write_u32(&mut data, signature.algorithm);
+    data[4..36].copy_from_slice(&signature.digest);
+    B::write_u32(&mut data[36..], signature.signature.len() as u32);
+    data.extend_from_slice(&signature.signature);
+    let options = Options::new();
+    let mut block = vec![];
+    CustomBlock::new(false, pen, &data, &options)
+        .encode::<B>(&mut block)
+        .map_err(|_| invalid("signature too long"))?;
+    Ok(block)
+}
+
+impl<W: Write, S: SectionSigner> Write for SigningWriter<W, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.framer.push(buf);
+        while let Some(frame) = self.framer.next_frame()? {
+            self.write_frame(frame)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner_mut().flush()
+    }
+}
+
+impl<W: Write, S: SectionSigner> Drop for SigningWriter<W, S> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.sign_section();
+        }
+    }
+}
+
+/// Checks the signature blocks written under `pen` in `data`, and
+/// returns the number of signed sections. Every section must end with
+/// a signature block whose digest matches the section and that
+/// `verify` accepts.
+pub fn verify_sections<F>(data: &[u8], pen: u32, mut verify: F) -> io::Result<u64>
+where
+    F: FnMut(&SectionSignature) -> bool,
+{
+    let mut framer = BlockFramer::new();
+    framer.push(data);
+    let mut hasher = None;
+    let mut endianness = Endianness::Little;
+    let mut sections = 0;
+    while let Some(frame) = framer.next_frame()? {
+        if frame.is(BlockType::SectionHeader) {
+            if hasher.is_some() {
+                return Err(invalid("unsigned section"));
+            }
+            hasher = Some(Sha256::new());
+            endianness = match LittleEndian::read_u32(&frame.bytes[8..]) {
+                BYTE_ORDER_MAGIC => Endianness::Little,
+                _ => Endianness::Big,
+            };
+        }
+        let signature = match endianness {
+            Endianness::Big => parse_signature::<BigEndian>(&frame, pen),
+            Endianness::Little => parse_signature::<LittleEndian>(&frame, pen),
+        };
+        match (signature, hasher.take()) {
+            (Some(signature), Some(section)) => {
+                let digest: [u8; 32] = section.finalize().into();
+                if signature.digest != digest {
+                    return Err(invalid("section digest mismatch"));
+                }
+                if !verify(&signature) {
+                    return Err(invalid("invalid section signature"));
+                }
+                sections += 1;
+            }
+            (Some(_), None) => return Err(invalid("signature outside a section")),
+            (None, Some(mut section)) => {
+                section.update(&frame.bytes);
+                hasher = Some(section);
+            }
+            (None, None) => return Err(invalid("block after a signature block")),
+        }
+    }
+    if hasher.is_some() {
+        return Err(invalid("unsigned section"));
+    }
+    Ok(sections)
+}
+
+fn parse_signature<B: ByteOrder>(frame: &Frame, pen: u32) -> Option<SectionSignature> {
+    let bytes = &frame.bytes;
+    if frame.block_type != BlockType::CustomNoCopy.value() || B::read_u32(&bytes[8..]) != pen {
+        return None;
+    }
+    // block type, length, PEN, algorithm, digest, signature length
+    let data = bytes.get(12..bytes.len() - 4)?;
+    let len = B::read_u32(data.get(36..40)?) as usize;
+    Some(SectionSignature {
+        algorithm: B::read_u32(data),
+        digest: data[4..36].try_into().ok()?,
+        signature: data.get(40..40 + len)?.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enums::LinkType;
+    use crate::writer::PcapNgWriter;
+    use ed25519_dalek::{Signature, VerifyingKey};
+
+    fn signed(endianness: Endianness) -> (Vec<u8>, VerifyingKey) {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let public = key.verifying_key();
+        let mut sink = SigningWriter::new(vec![], Ed25519Signer::new(key), 9);
+        {
+            let mut writer = PcapNgWriter::new_auto_section(endianness, &mut sink);
+            let eth = writer
+                .add_interface(LinkType::Ethernet, 0, &Options::new())
+                .unwrap();
+            writer.write_packet(eth, 0, &[1; 20]).unwrap();
+            writer.start_new_section(&Options::new()).unwrap();
+            let eth = writer
+                .add_interface(LinkType::Ethernet, 0, &Options::new())
+                .unwrap();
+            writer.write_packet(eth, 0, &[2; 20]).unwrap();
+        }
+        (sink.finish().unwrap(), public)
+    }
+
+    fn verify(data: &[u8], public: &VerifyingKey) -> io::Result<u64> {
+        verify_sections(data, 9, |s| {
+            let signature = Signature::from_slice(s.signature()).unwrap();
+            s.algorithm() == Ed25519Signer::ALGORITHM
+                && public.verify_strict(s.digest(), &signature).is_ok()
+        })
+    }
+
+    #[test]
+    fn sign_sections() {
+        for endianness in [Endianness::Little, Endianness::Big] {
+            let (data, public) = signed(endianness);
+            assert_eq!(verify(&data, &public).unwrap(), 2);
+        }
+    }
+
+    #[test]
+    fn tampering() {
+        let (mut data, public) = signed(Endianness::Little);
+        // a byte of the first packet
+        let pos = data.windows(20).position(|w| w == [1; 20]).unwrap();
+        data[pos] = 3;
+        assert!(verify(&data, &public).is_err());
+
+        let other = SigningKey::from_bytes(&[2; 32]).verifying_key();
+        let (data, _) = signed(Endianness::Little);
+        assert!(verify(&data, &other).is_err());
+    }
+}
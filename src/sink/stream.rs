@@ -0,0 +1,134 @@
+use crate::framing::BlockScanner;
+use js_sys::{Promise, Uint8Array};
+use std::io::{self, Write};
+use web_sys::{WritableStream, WritableStreamDefaultWriter};
+
+/// A sink that writes to a JavaScript `WritableStream`, e.g. the
+/// stream of a file picked with `showSaveFilePicker` or one piped to
+/// a download, for browser-based tools built for
+/// `wasm32-unknown-unknown`.
+///
+/// Complete blocks are buffered and written to the stream as
+/// `Uint8Array` chunks of at least the chunk size, 64 KiB by default.
+/// Writes do not wait for the stream: the stream queues the chunks,
+/// and its errors are reported by the promise of `closed`. `ready`
+/// resolves once the queue has room again.
+///
+/// `close` must be called to write the rest of the buffer and close
+/// the stream.
+///
+/// ```no_run
+/// use js_sys::Promise;
+/// use pcapng_writer::sink::WritableStreamSink;
+/// use pcapng_writer::writer::{Endianness, PcapNgWriter};
+/// use web_sys::WritableStream;
+///
+/// /// Writes a capture to `stream`, and returns the promise of its
+/// /// closing, e.g. to await with `wasm_bindgen_futures::JsFuture`.
+/// fn save(stream: &WritableStream) -> pcapng_writer::Result<Promise> {
+///     let mut sink = WritableStreamSink::new(stream)?;
+///     {
+///         let writer = PcapNgWriter::new_auto_section(Endianness::Little, &mut sink);
+///         // add interfaces and write packets
+/// #       drop(writer);
+///     }
+///     Ok(sink.close()?)
+/// }
+/// # fn main() {}
+/// ```
+///
+/// `SystemTime::now` panics on `wasm32-unknown-unknown`, so packet
+/// timestamps have to come from JavaScript, e.g. `Date.now()`.
+#[derive(Debug)]
+pub struct WritableStreamSink {
+    writer: WritableStreamDefaultWriter,
+    buf: Vec<u8>,
+    complete: usize,
+    chunk_size: usize,
+    scanner: BlockScanner,
+}
+
+impl WritableStreamSink {
+    /// Creates a sink that locks `stream` to write to it.
+    pub fn new(stream: &WritableStream) -> io::Result<Self> {
+        let writer = stream.get_writer().map_err(js_error)?;
+        Ok(Self::with_writer(writer))
+    }
+
+    /// Creates a sink that writes with an existing stream writer.
+    pub fn with_writer(writer: WritableStreamDefaultWriter) -> Self {
+        Self {
+            writer,
+            buf: vec![],
+            complete: 0,
+            chunk_size: 64 * 1024,
+            scanner: BlockScanner::new(),
+        }
+    }
+
+    /// Sets the size above which buffered blocks are written to the
+    /// stream. With 0, every block is written as it is completed.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = chunk_size;
+    }
+
+    /// Returns a promise that resolves when the queue of the stream
+    /// has room for more chunks.
+    pub fn ready(&self) -> Promise {
+        self.writer.ready()
+    }
+
+    /// Returns a promise that resolves when the stream is closed, or
+    /// rejects with the error of a failed write.
+    pub fn closed(&self) -> Promise {
+        self.writer.closed()
+    }
+
+    /// Writes the rest of the buffer and closes the stream. The
+    /// returned promise resolves once everything is written.
+    pub fn close(mut self) -> io::Result<Promise> {
+        self.flush()?;
+        Ok(self.writer.close())
+    }
+
+    pub fn get_ref(&self) -> &WritableStreamDefaultWriter {
+        &self.writer
+    }
+
+    fn send(&mut self, len: usize) {
+        if len == 0 {
+            return;
+        }
+        let chunk = Uint8Array::new_from_slice(&self.buf[..len]);
+        // failures are reported by the `closed` promise
+        let _ = self.writer.write_with_chunk(&chunk);
+        self.buf.drain(..len);
+        self.complete -= len.min(self.complete);
+    }
+}
+
+impl Write for WritableStreamSink {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let end = self.scanner.scan(data)?;
+        let start = self.buf.len();
+        self.buf.extend_from_slice(data);
+        if let Some(end) = end {
+            self.complete = start + end;
+        }
+        if self.complete > 0 && self.complete >= self.chunk_size {
+            self.send(self.complete);
+        }
+        Ok(data.len())
+    }
+
+    /// Writes all buffered data to the stream, without waiting for
+    /// it to be written.
+    fn flush(&mut self) -> io::Result<()> {
+        self.send(self.buf.len());
+        Ok(())
+    }
+}
+
+fn js_error(e: js_sys::wasm_bindgen::JsValue) -> io::Error {
+    io::Error::other(format!("{:?}", e))
+}
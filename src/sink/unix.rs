@@ -0,0 +1,257 @@
+use crate::enums::BlockType;
+use crate::framing::{BlockFramer, Frame};
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+/// A sink that streams blocks over a Unix domain socket, for local
+/// IPC between a capture daemon and analyzers.
+///
+/// The sink either connects to a consumer listening on a socket, with
+/// `connect`, or listens on a socket and waits for a consumer, with
+/// `listen`. When the consumer goes away, the sink connects again, or
+/// accepts the next consumer, on a later write; each new consumer
+/// first receives the Section Header Block and the Interface
+/// Description Blocks of the section in progress, so it always reads
+/// a valid pcapng stream.
+///
+/// Blocks written while no consumer is connected are dropped and
+/// counted by `dropped`. Socket errors are never returned by `write`,
+/// so the capture goes on without a consumer.
+///
+/// ```no_run
+/// use pcapng_writer::capture::CaptureWriter;
+/// use pcapng_writer::enums::LinkType;
+/// use pcapng_writer::sink::UnixSocketWriter;
+/// use std::time::SystemTime;
+///
+/// // blocks until an analyzer connects, e.g. `nc -U /run/capture.sock`
+/// let sink = UnixSocketWriter::listen("/run/capture.sock").unwrap();
+/// let mut capture = CaptureWriter::new(sink).unwrap();
+/// let eth = capture.add_interface(LinkType::Ethernet, 0xFFFF).unwrap();
+/// capture.write_packet(eth, SystemTime::now(), b"...").unwrap();
+/// ```
+#[derive(Debug)]
+pub struct UnixSocketWriter {
+    path: PathBuf,
+    listener: Option<UnixListener>,
+    stream: Option<UnixStream>,
+    headers: Vec<u8>,
+    consumers: u64,
+    dropped: u64,
+    framer: BlockFramer,
+}
+
+impl UnixSocketWriter {
+    /// Connects to the consumer listening at `path`. An error is
+    /// returned if the first connection fails.
+    pub fn connect<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let stream = UnixStream::connect(&path)?;
+        Ok(Self::new(path.as_ref(), None, stream))
+    }
+
+    /// Listens at `path` and waits for the first consumer to connect.
+    /// The socket file is removed when the sink is dropped.
+    pub fn listen<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let listener = UnixListener::bind(&path)?;
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                let _ = fs::remove_file(&path);
+                return Err(e);
+            }
+        };
+        // later consumers are accepted without waiting for them
+        listener.set_nonblocking(true)?;
+        Ok(Self::new(path.as_ref(), Some(listener), stream))
+    }
+
+    fn new(path: &Path, listener: Option<UnixListener>, stream: UnixStream) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            listener,
+            stream: Some(stream),
+            headers: vec![],
+            consumers: 1,
+            dropped: 0,
+            framer: BlockFramer::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Returns the number of consumers connected so far.
+    pub fn consumers(&self) -> u64 {
+        self.consumers
+    }
+
+    /// Returns the number of blocks dropped while no consumer was
+    /// connected.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    fn write_frame(&mut self, frame: Frame) -> io::Result<()> {
+        let is_shb = frame.is(BlockType::SectionHeader);
+        let is_header = is_shb || frame.is(BlockType::InterfaceDescription);
+        if is_shb {
+            self.headers.clear();
+        }
+        if is_header {
+            self.headers.extend_from_slice(&frame.bytes);
+        }
+        if self.stream.is_none() {
+            self.next_consumer();
+            // the new consumer already received the headers
+            if self.stream.is_some() && is_header {
+                return Ok(());
+            }
+        }
+        let sent = match self.stream.as_mut() {
+            Some(stream) => stream.write_all(&frame.bytes).is_ok(),
+            None => false,
+        };
+        if !sent {
+            self.stream = None;
+            if !is_header {
+                self.dropped += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Connects to or accepts the next consumer, if any, and sends it
+    /// the headers of the section.
+    fn next_consumer(&mut self) {
+        let stream = match &self.listener {
+            Some(listener) => listener.accept().map(|(stream, _)| stream),
+            None => UnixStream::connect(&self.path),
+        };
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => return,
+        };
+        if stream.set_nonblocking(false).is_err() || stream.write_all(&self.headers).is_err() {
+            return;
+        }
+        self.stream = Some(stream);
+        self.consumers += 1;
+    }
+}
+
+impl Write for UnixSocketWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.framer.push(buf);
+        while let Some(frame) = self.framer.next_frame()? {
+            self.write_frame(frame)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for UnixSocketWriter {
+    fn drop(&mut self) {
+        if self.listener.is_some() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::Options;
+    use crate::enums::LinkType;
+    use crate::framing::block_types;
+    use crate::writer::{Endianness, PcapNgWriter};
+    use std::io::Read;
+
+    fn socket_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "pcapng-writer-{}-{}.sock",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    const SHB: u32 = 0x0A0D0D0A;
+    const IDB: u32 = 1;
+    const EPB: u32 = 6;
+
+    #[test]
+    fn listen() {
+        let path = socket_path("listen");
+        let consumer = {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                let stream = loop {
+                    if let Ok(stream) = UnixStream::connect(&path) {
+                        break stream;
+                    }
+                    std::thread::yield_now();
+                };
+                // reads the headers and the first packet, then leaves
+                let mut data = vec![0; 28 + 20 + 44];
+                (&stream).read_exact(&mut data).unwrap();
+                data
+            })
+        };
+        let mut writer = PcapNgWriter::new_auto_section(
+            Endianness::Little,
+            UnixSocketWriter::listen(&path).unwrap(),
+        );
+        let eth = writer
+            .add_interface(LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        writer.write_packet(eth, 0, &[1; 10]).unwrap();
+        assert_eq!(block_types(&consumer.join().unwrap()), [SHB, IDB, EPB]);
+
+        // writing to the closed socket fails once the consumer is gone
+        while writer.get_writer().is_connected() {
+            writer.write_packet(eth, 0, &[2; 10]).unwrap();
+        }
+        let dropped = writer.get_writer().dropped();
+        assert!(dropped > 0);
+
+        let mut next = UnixStream::connect(&path).unwrap();
+        writer.write_packet(eth, 0, &[3; 10]).unwrap();
+        drop(writer);
+        let mut data = vec![];
+        next.read_to_end(&mut data).unwrap();
+        assert_eq!(block_types(&data), [SHB, IDB, EPB]);
+        assert_eq!(data[data.len() - 16], 3);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn connect() {
+        let path = socket_path("connect");
+        let listener = UnixListener::bind(&path).unwrap();
+        let mut writer = PcapNgWriter::new_auto_section(
+            Endianness::Little,
+            UnixSocketWriter::connect(&path).unwrap(),
+        );
+        let eth = writer
+            .add_interface(LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        writer.write_packet(eth, 0, &[1; 10]).unwrap();
+        drop(writer);
+        let mut data = vec![];
+        listener.accept().unwrap().0.read_to_end(&mut data).unwrap();
+        assert_eq!(block_types(&data), [SHB, IDB, EPB]);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,332 @@
+use io_uring::{opcode, types, IoUring};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Seek, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/// The default size of the buffers of a `UringWriter`.
+const DEFAULT_BUFFER_SIZE: usize = 1 << 20;
+
+/// The default number of buffers of a `UringWriter`.
+const DEFAULT_QUEUE_DEPTH: usize = 8;
+
+/// A buffer of a `UringWriter`, being filled or written.
+#[derive(Debug)]
+struct Buffer {
+    data: Vec<u8>,
+    /// The offset in the file of the start of `data`.
+    offset: u64,
+    /// The bytes of `data` written so far, while in flight.
+    written: usize,
+    in_flight: bool,
+}
+
+/// A sink that writes to a file with io_uring, on Linux.
+///
+/// The data is collected in buffers, and each full buffer is
+/// submitted as a write at its offset in the file, without waiting
+/// for it to complete. While the kernel writes a buffer, the next
+/// ones are filled; `write` only blocks when all the buffers are in
+/// flight. This keeps sustained multi-gigabit captures off the
+/// blocking write path.
+///
+/// Writes may complete out of order, so readers following the file
+/// can see gaps until `flush` returns. `flush` submits the buffered
+/// data and waits for all the writes; errors of writes that
+/// completed in the background are returned by the next `write` or
+/// `flush`. Dropping the writer flushes it, ignoring errors.
+///
+/// ```no_run
+/// use pcapng_writer::capture::CaptureWriter;
+/// use pcapng_writer::enums::LinkType;
+/// use pcapng_writer::sink::UringWriter;
+/// use std::time::SystemTime;
+///
+/// let sink = UringWriter::create("capture.pcapng")?;
+/// let mut capture = CaptureWriter::new(sink)?;
+/// let eth = capture.add_interface(LinkType::Ethernet, 0xFFFF)?;
+/// capture.write_packet(eth, SystemTime::now(), b"...")?;
+/// capture.close()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct UringWriter {
+    ring: IoUring,
+    file: File,
+    buffers: Vec<Buffer>,
+    buffer_size: usize,
+    /// The buffer being filled.
+    current: usize,
+    /// The offset in the file of the next buffer submitted.
+    offset: u64,
+    in_flight: usize,
+    error: Option<io::Error>,
+}
+
+impl UringWriter {
+    /// Creates or truncates the file at `path`, and writes to it with
+    /// 8 buffers of 1 MiB.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(File::create(path)?)
+    }
+
+    /// Writes to `file` from its current position, with 8 buffers of
+    /// 1 MiB.
+    pub fn new(file: File) -> io::Result<Self> {
+        Self::with_buffers(file, DEFAULT_BUFFER_SIZE, DEFAULT_QUEUE_DEPTH)
+    }
+
+    /// Writes to `file` from its current position, with
+    /// `queue_depth` buffers of `buffer_size` bytes, i.e. up to
+    /// `queue_depth` writes in flight. Fails if io_uring is not
+    /// available, e.g. on kernels older than 5.6.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer_size` or `queue_depth` is zero, or if
+    /// `buffer_size` does not fit in 32 bits.
+    pub fn with_buffers(
+        mut file: File,
+        buffer_size: usize,
+        queue_depth: usize,
+    ) -> io::Result<Self> {
+        assert!(buffer_size > 0 && u32::try_from(buffer_size).is_ok());
+        assert!(queue_depth > 0);
+        let entries = u32::try_from(queue_depth).unwrap_or(u32::MAX);
+        let ring = IoUring::new(entries)?;
+        let offset = file.stream_position()?;
+        let buffers = (0..queue_depth)
+            .map(|_| Buffer {
+                data: Vec::with_capacity(buffer_size),
+                offset: 0,
+                written: 0,
+                in_flight: false,
+            })
+            .collect();
+        Ok(Self {
+            ring,
+            file,
+            buffers,
+            buffer_size,
+            current: 0,
+            offset,
+            in_flight: 0,
+            error: None,
+        })
+    }
+
+    pub fn get_ref(&self) -> &File {
+        &self.file
+    }
+
+    /// Returns the number of writes that have not completed yet.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// Flushes the writer, and waits until the data of the file
+    /// reaches the disk.
+    pub fn sync_data(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.file.sync_data()
+    }
+
+    /// Submits the buffer being filled, if it is not empty, and
+    /// switches to a free buffer, waiting for one if needed.
+    fn submit_current(&mut self) -> io::Result<()> {
+        let index = self.current;
+        let buffer = &mut self.buffers[index];
+        if buffer.data.is_empty() {
+            return Ok(());
+        }
+        buffer.offset = self.offset;
+        buffer.written = 0;
+        buffer.in_flight = true;
+        self.offset += buffer.data.len() as u64;
+        self.push(index)?;
+        self.in_flight += 1;
+        self.ring.submit()?;
+        loop {
+            self.reap()?;
+            if let Some(free) = self.buffers.iter().position(|b| !b.in_flight) {
+                self.current = free;
+                return Ok(());
+            }
+            self.wait()?;
+        }
+    }
+
+    /// Queues a write of the rest of a buffer.
+    fn push(&mut self, index: usize) -> io::Result<()> {
+        let buffer = &self.buffers[index];
+        let rest = &buffer.data[buffer.written..];
+        let entry = opcode::Write::new(
+            types::Fd(self.file.as_raw_fd()),
+            rest.as_ptr(),
+            rest.len() as u32,
+        )
+        .offset(buffer.offset + buffer.written as u64)
+        .build()
+        .user_data(index as u64);
+        // SAFETY: the buffer is not modified, moved or freed until the
+        // write completes, see `reap` and `drop`.
+        unsafe { self.ring.submission().push(&entry) }
+            .map_err(|_| io::Error::other("io_uring submission queue is full"))
+    }
+
+    /// Handles the writes that completed. Short writes are submitted
+    /// again for the rest of their buffer.
+    fn reap(&mut self) -> io::Result<()> {
+        let mut resubmit = false;
+        loop {
+            let (index, result) = match self.ring.completion().next() {
+                Some(cqe) => (cqe.user_data() as usize, cqe.result()),
+                None => break,
+            };
+            let buffer = &mut self.buffers[index];
+            let error = match result {
+                r if r < 0 => Some(io::Error::from_raw_os_error(-r)),
+                0 => Some(io::ErrorKind::WriteZero.into()),
+                r => {
+                    buffer.written += r as usize;
+                    None
+                }
+            };
+            if error.is_none() && buffer.written < buffer.data.len() {
+                self.push(index)?;
+                resubmit = true;
+                continue;
+            }
+            buffer.data.clear();
+            buffer.in_flight = false;
+            self.in_flight -= 1;
+            if let Some(e) = error {
+                self.error.get_or_insert(e);
+            }
+        }
+        if resubmit {
+            self.ring.submit()?;
+        }
+        Ok(())
+    }
+
+    /// Waits for at least one write to complete.
+    fn wait(&mut self) -> io::Result<()> {
+        match self.ring.submit_and_wait(1) {
+            Err(e) if e.kind() != io::ErrorKind::Interrupted => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Waits for all the writes to complete.
+    fn wait_all(&mut self) -> io::Result<()> {
+        while self.in_flight > 0 {
+            self.wait()?;
+            self.reap()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the error of a write that completed in the background.
+    fn take_error(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Write for UringWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.take_error()?;
+        let mut rest = data;
+        while !rest.is_empty() {
+            let buffer = &mut self.buffers[self.current];
+            let n = (self.buffer_size - buffer.data.len()).min(rest.len());
+            buffer.data.extend_from_slice(&rest[..n]);
+            rest = &rest[n..];
+            if buffer.data.len() == self.buffer_size {
+                self.submit_current()?;
+            }
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.submit_current()?;
+        self.wait_all()?;
+        self.take_error()
+    }
+}
+
+impl Drop for UringWriter {
+    fn drop(&mut self) {
+        // errors cannot be reported here; call `flush` to handle them
+        let _ = self.submit_current();
+        if self.wait_all().is_err() {
+            // the kernel may still be writing from the buffers
+            std::mem::forget(std::mem::take(&mut self.buffers));
+        }
+    }
+}
+
+impl fmt::Debug for UringWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UringWriter")
+            .field("file", &self.file)
+            .field("buffer_size", &self.buffer_size)
+            .field("buffers", &self.buffers.len())
+            .field("offset", &self.offset)
+            .field("in_flight", &self.in_flight)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enums::LinkType;
+    use crate::writer::{Endianness, PcapNgWriter};
+    use std::fs;
+
+    fn capture<W: Write>(writer: W) {
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, writer);
+        let eth = writer
+            .add_interface(LinkType::Ethernet, 65535, &Default::default())
+            .unwrap();
+        for i in 0..100u8 {
+            writer
+                .write_packet(eth, u128::from(i), &vec![i; usize::from(i) * 3])
+                .unwrap();
+        }
+        writer.close().unwrap();
+    }
+
+    #[test]
+    fn write_file() {
+        let path = std::env::temp_dir().join(format!("pcapng-writer-uring-{}", std::process::id()));
+        let file = File::create(&path).unwrap();
+        let sink = match UringWriter::with_buffers(file, 100, 2) {
+            Ok(sink) => sink,
+            // io_uring is disabled in some containers
+            Err(_) => return fs::remove_file(&path).unwrap(),
+        };
+        capture(sink);
+        let mut expected = vec![];
+        capture(&mut expected);
+        assert_eq!(fs::read(&path).unwrap(), expected);
+
+        // from the current position of the file
+        let mut file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(io::SeekFrom::End(0)).unwrap();
+        let mut sink = UringWriter::new(file).unwrap();
+        sink.write_all(b"more").unwrap();
+        assert_eq!(sink.in_flight(), 0);
+        sink.sync_data().unwrap();
+        drop(sink);
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(&data[expected.len()..], b"more");
+    }
+}
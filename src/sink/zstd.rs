@@ -0,0 +1,166 @@
+use crate::framing::BlockScanner;
+use std::io::{self, Write};
+use zstd::stream::write::Encoder;
+
+/// A sink that compresses the output with zstd.
+///
+/// By default, the whole output is a single zstd frame. With
+/// `set_blocks_per_frame`, a new frame is started every N pcapng
+/// blocks, so the output can be decompressed from any frame boundary
+/// on, at a small cost in compression ratio.
+///
+/// `finish` must be called to end the last frame. Dropping the sink
+/// ends it too, but ignores errors.
+///
+/// ```
+/// use pcapng_writer::sink::ZstdWriter;
+/// use pcapng_writer::writer::{Endianness, PcapNgWriter};
+///
+/// let mut sink = ZstdWriter::new(vec![], 3).unwrap();
+/// sink.set_blocks_per_frame(Some(1000));
+/// {
+///     let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, &mut sink);
+///     // add interfaces and write packets
+/// }
+/// let compressed: Vec<u8> = sink.finish().unwrap();
+/// ```
+pub struct ZstdWriter<W: Write> {
+    encoder: Option<Encoder<'static, W>>,
+    level: i32,
+    blocks_per_frame: Option<usize>,
+    blocks_in_frame: usize,
+    scanner: BlockScanner,
+}
+
+impl<W: Write> ZstdWriter<W> {
+    /// Creates a sink with the given compression level. Level 0 uses
+    /// zstd's default.
+    pub fn new(inner: W, level: i32) -> io::Result<Self> {
+        Ok(Self {
+            encoder: Some(Encoder::new(inner, level)?),
+            level,
+            blocks_per_frame: None,
+            blocks_in_frame: 0,
+            scanner: BlockScanner::new(),
+        })
+    }
+
+    /// Starts a new zstd frame every `blocks` pcapng blocks, or never
+    /// with `None`.
+    pub fn set_blocks_per_frame(&mut self, blocks: Option<usize>) {
+        self.blocks_per_frame = blocks.filter(|n| *n > 0);
+    }
+
+    /// Ends the current frame and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.take_encoder().finish()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.encoder().get_ref()
+    }
+
+    fn encoder(&self) -> &Encoder<'static, W> {
+        self.encoder
+            .as_ref()
+            .expect("encoder is only taken when finishing")
+    }
+
+    fn encoder_mut(&mut self) -> &mut Encoder<'static, W> {
+        self.encoder
+            .as_mut()
+            .expect("encoder is only taken when finishing")
+    }
+
+    fn take_encoder(&mut self) -> Encoder<'static, W> {
+        self.encoder
+            .take()
+            .expect("encoder is only taken when finishing")
+    }
+
+    fn end_frame(&mut self) -> io::Result<()> {
+        let inner = self.take_encoder().finish()?;
+        self.encoder = Some(Encoder::new(inner, self.level)?);
+        self.blocks_in_frame = 0;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ZstdWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while let Some(end) = self.scanner.next_boundary(rest)? {
+            self.encoder_mut().write_all(&rest[..end])?;
+            rest = &rest[end..];
+            self.blocks_in_frame += 1;
+            if Some(self.blocks_in_frame) == self.blocks_per_frame {
+                self.end_frame()?;
+            }
+        }
+        self.encoder_mut().write_all(rest)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder_mut().flush()
+    }
+}
+
+impl<W: Write> Drop for ZstdWriter<W> {
+    fn drop(&mut self) {
+        if let Some(encoder) = self.encoder.take() {
+            let _ = encoder.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::Options;
+    use crate::enums::LinkType;
+    use crate::writer::{Endianness, PcapNgWriter};
+
+    fn compress(blocks_per_frame: Option<usize>) -> (Vec<u8>, Vec<u8>) {
+        let opts = Options::new();
+        let mut plain = vec![];
+        let mut sink = ZstdWriter::new(vec![], 3).unwrap();
+        sink.set_blocks_per_frame(blocks_per_frame);
+        {
+            let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, &mut plain);
+            let id = writer
+                .add_interface(LinkType::Ethernet, 0xFFFF, &opts)
+                .unwrap();
+            for i in 0..10u8 {
+                writer.write_packet(id, 0, &[i; 100]).unwrap();
+            }
+        }
+        sink.write_all(&plain).unwrap();
+        (plain, sink.finish().unwrap())
+    }
+
+    #[test]
+    fn single_frame() {
+        let (plain, compressed) = compress(None);
+        assert!(compressed.len() < plain.len() / 4);
+        assert_eq!(zstd::decode_all(&compressed[..]).unwrap(), plain);
+    }
+
+    #[test]
+    fn frame_per_blocks() {
+        let (plain, single) = compress(None);
+        let (_, framed) = compress(Some(4));
+        assert!(framed.len() > single.len());
+        assert_eq!(zstd::decode_all(&framed[..]).unwrap(), plain);
+
+        // 12 blocks in frames of 4, plus an empty last frame
+        let mut frames = 0;
+        let mut rest = &framed[..];
+        while !rest.is_empty() {
+            let len = zstd::zstd_safe::find_frame_compressed_size(rest).unwrap();
+            rest = &rest[len..];
+            frames += 1;
+        }
+        assert_eq!(frames, 4);
+    }
+}
@@ -0,0 +1,492 @@
+//! Helpers for golden-file tests of applications that write pcapng.
+//!
+//! `CaptureBuilder` builds small reference captures, and `compare`
+//! reports the differences between two captures block by block, e.g.
+//! "EPB #3 (block 5): total_length mismatch: expected 48, got 52",
+//! instead of the first differing byte. `assert_same_capture` and
+//! `assert_golden` panic with these differences:
+//!
+//! ```
+//! use pcapng_writer::enums::LinkType;
+//! use pcapng_writer::testing::{assert_same_capture, CaptureBuilder};
+//! use pcapng_writer::writer::PcapNgWriter;
+//!
+//! let mut writer = PcapNgWriter::new_deterministic(vec![]);
+//! let eth = writer.add_interface(LinkType::Ethernet, 65535, &Default::default())?;
+//! writer.write_packet(eth, 1_000, &[0; 60])?;
+//!
+//! let expected = CaptureBuilder::new()
+//!     .interface(LinkType::Ethernet, 65535)
+//!     .packet(0, 1_000, &[0; 60])
+//!     .build();
+//! assert_same_capture(writer.get_writer(), &expected);
+//! # Ok::<(), pcapng_writer::error::Error>(())
+//! ```
+//!
+//! `assert_golden` compares a capture with a fixture file. If the
+//! `PCAPNG_UPDATE_GOLDEN` environment variable is set, it writes the
+//! fixture instead, e.g. after an intended change of the output.
+
+use crate::blocks::options::Options;
+use crate::constants::BLOCK_COMMON_LEN;
+use crate::enums::{BlockType, LinkType};
+use crate::framing::{block_abbreviation, fixed_fields, parse_header, read};
+use crate::utils::pad_to_32;
+use crate::writer::{Endianness, PcapNgWriter};
+use std::fmt;
+use std::path::Path;
+
+/// Builds a capture with a `PcapNgWriter::new_deterministic` writer,
+/// for the expected output of tests.
+#[derive(Debug, Clone)]
+pub struct CaptureBuilder {
+    endianness: Endianness,
+    interfaces: Vec<(LinkType, u32)>,
+    packets: Vec<(usize, u128, Vec<u8>)>,
+}
+
+impl CaptureBuilder {
+    pub fn new() -> Self {
+        Self {
+            endianness: Endianness::Little,
+            interfaces: vec![],
+            packets: vec![],
+        }
+    }
+
+    /// Sets the byte order of the capture, little-endian by default.
+    pub fn endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Adds an interface without options. Interfaces are numbered
+    /// from 0 in the order they are added.
+    pub fn interface(mut self, link_type: LinkType, snap_len: u32) -> Self {
+        self.interfaces.push((link_type, snap_len));
+        self
+    }
+
+    /// Adds a packet of `interface`, with a timestamp in nanoseconds
+    /// since the epoch.
+    pub fn packet(mut self, interface: usize, timestamp_nanos: u128, data: &[u8]) -> Self {
+        self.packets
+            .push((interface, timestamp_nanos, data.to_vec()));
+        self
+    }
+
+    /// Writes the interfaces, then the packets, and returns the
+    /// capture.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a packet refers to an interface that was not added,
+    /// or can't be written.
+    pub fn build(&self) -> Vec<u8> {
+        let mut writer = PcapNgWriter::new_deterministic(vec![]);
+        if self.endianness != Endianness::Little {
+            writer
+                .start_new_section_with_endianness(self.endianness, &Options::new())
+                .unwrap();
+        }
+        let ids: Vec<_> = self
+            .interfaces
+            .iter()
+            .map(|&(link_type, snap_len)| {
+                writer
+                    .add_interface(link_type, snap_len, &Options::new())
+                    .unwrap()
+            })
+            .collect();
+        for (interface, timestamp_nanos, data) in &self.packets {
+            writer
+                .write_packet(ids[*interface], *timestamp_nanos, data)
+                .unwrap();
+        }
+        writer.close().unwrap();
+        writer.get_writer().clone()
+    }
+}
+
+impl Default for CaptureBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The differences between two captures found by `compare`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    differences: Vec<String>,
+}
+
+impl Mismatch {
+    /// Returns the differences, one per block at most.
+    pub fn differences(&self) -> &[String] {
+        &self.differences
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "captures differ:")?;
+        for difference in &self.differences {
+            write!(f, "\n  {}", difference)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+/// Compares two captures block by block. For each block that
+/// differs, the first differing field is reported, e.g. a fixed field
+/// of the block, the packet data or an option.
+pub fn compare(expected: &[u8], actual: &[u8]) -> Result<(), Mismatch> {
+    let expected_blocks = split(expected);
+    let actual_blocks = split(actual);
+    let mut differences = vec![];
+    let mut counts = std::collections::HashMap::new();
+    for (index, (e, a)) in expected_blocks.iter().zip(&actual_blocks).enumerate() {
+        let name = match e {
+            Ok(e) => {
+                let count = counts.entry(e.block_type).or_insert(0);
+                *count += 1;
+                format!(
+                    "{} #{} (block {})",
+                    block_name(e.block_type),
+                    count,
+                    index + 1
+                )
+            }
+            Err(_) => format!("block {}", index + 1),
+        };
+        if let Some(difference) = compare_block(e, a) {
+            differences.push(format!("{}: {}", name, difference));
+        }
+    }
+    if expected_blocks.len() != actual_blocks.len() {
+        differences.push(format!(
+            "expected {} blocks, got {}",
+            expected_blocks.len(),
+            actual_blocks.len()
+        ));
+    }
+    if differences.is_empty() {
+        Ok(())
+    } else {
+        Err(Mismatch { differences })
+    }
+}
+
+/// Panics with the differences found by `compare` if the captures
+/// differ.
+#[track_caller]
+pub fn assert_same_capture(actual: &[u8], expected: &[u8]) {
+    if let Err(mismatch) = compare(expected, actual) {
+        panic!("{}", mismatch);
+    }
+}
+
+/// Compares `actual` with the capture in the fixture file at `path`,
+/// and panics with the differences found by `compare`. If the
+/// `PCAPNG_UPDATE_GOLDEN` environment variable is set, writes `actual`
+/// to the file instead.
+#[track_caller]
+pub fn assert_golden<P: AsRef<Path>>(path: P, actual: &[u8]) {
+    let path = path.as_ref();
+    if std::env::var_os("PCAPNG_UPDATE_GOLDEN").is_some() {
+        if let Err(e) = std::fs::write(path, actual) {
+            panic!("can't write {}: {}", path.display(), e);
+        }
+        return;
+    }
+    let expected = match std::fs::read(path) {
+        Ok(expected) => expected,
+        Err(e) => panic!(
+            "can't read {}: {} (set PCAPNG_UPDATE_GOLDEN to create it)",
+            path.display(),
+            e
+        ),
+    };
+    if let Err(mismatch) = compare(&expected, actual) {
+        panic!("{} does not match: {}", path.display(), mismatch);
+    }
+}
+
+/// A block of a capture, with the byte order of its section.
+#[derive(Debug)]
+struct RawBlock<'a> {
+    block_type: u32,
+    endianness: Endianness,
+    bytes: &'a [u8],
+}
+
+/// Splits a capture into blocks. Trailing bytes that don't form a
+/// block are an error.
+fn split(mut data: &[u8]) -> Vec<Result<RawBlock<'_>, String>> {
+    let mut blocks = vec![];
+    let mut endianness = None;
+    while !data.is_empty() {
+        if data.len() < BLOCK_COMMON_LEN {
+            blocks.push(Err(format!("{} trailing bytes", data.len())));
+            break;
+        }
+        let (block_type, total_length) = match parse_header(&mut endianness, data) {
+            Ok(header) => header,
+            Err(e) => {
+                blocks.push(Err(e.to_string()));
+                break;
+            }
+        };
+        if data.len() < total_length {
+            blocks.push(Err(format!(
+                "truncated: total_length {}, {} bytes left",
+                total_length,
+                data.len()
+            )));
+            break;
+        }
+        let (bytes, rest) = data.split_at(total_length);
+        blocks.push(Ok(RawBlock {
+            block_type,
+            // set by parse_header
+            endianness: endianness.unwrap(),
+            bytes,
+        }));
+        data = rest;
+    }
+    blocks
+}
+
+fn block_name(block_type: u32) -> String {
+    match block_abbreviation(block_type) {
+        Some(name) => name.to_string(),
+        None => format!("block type {:#010x}", block_type),
+    }
+}
+
+/// Returns the first difference between two blocks.
+fn compare_block(
+    expected: &Result<RawBlock<'_>, String>,
+    actual: &Result<RawBlock<'_>, String>,
+) -> Option<String> {
+    let (e, a) = match (expected, actual) {
+        (Ok(e), Ok(a)) => (e, a),
+        (Err(e), Err(a)) if e == a => return None,
+        (Err(e), Err(a)) => return Some(format!("expected \"{}\", got \"{}\"", e, a)),
+        (Err(e), Ok(_)) => return Some(format!("expected \"{}\", got a block", e)),
+        (Ok(_), Err(a)) => return Some(a.clone()),
+    };
+    if e.bytes == a.bytes {
+        return None;
+    }
+    if e.block_type != a.block_type {
+        return Some(format!("got {}", block_name(a.block_type)));
+    }
+    if e.endianness != a.endianness {
+        return Some(format!(
+            "byte order mismatch: expected {:?}, got {:?}",
+            e.endianness, a.endianness
+        ));
+    }
+    if e.bytes.len() != a.bytes.len() {
+        return Some(format!(
+            "total_length mismatch: expected {}, got {}",
+            e.bytes.len(),
+            a.bytes.len()
+        ));
+    }
+    let (e_body, a_body) = (body(e), body(a));
+    let mut pos = 0;
+    for &(field, size) in fixed_fields(e.block_type) {
+        if e_body.len() < pos + size {
+            break;
+        }
+        let (ev, av) = (
+            read(e.endianness, &e_body[pos..pos + size]),
+            read(a.endianness, &a_body[pos..pos + size]),
+        );
+        if ev != av {
+            return Some(format!("{} mismatch: expected {}, got {}", field, ev, av));
+        }
+        pos += size;
+    }
+    let packet_len = match e.block_type {
+        t if t == BlockType::EnhancedPacket.value() => read(e.endianness, &e_body[12..16]) as usize,
+        t if t == BlockType::SimplePacket.value() => e_body.len() - pos,
+        _ => 0,
+    };
+    let packet_end = (pos + packet_len).min(e_body.len());
+    if let Some(offset) = first_difference(&e_body[pos..packet_end], &a_body[pos..packet_end]) {
+        return Some(format!("packet data differs at byte {}", offset));
+    }
+    pos = (packet_end + pad_to_32(packet_len)).min(e_body.len());
+    if fixed_fields(e.block_type).is_empty() {
+        // the records of NRBs and the bodies of other blocks are not
+        // parsed
+        let offset = first_difference(e_body, a_body).unwrap_or(0);
+        return Some(format!("body differs at byte {}", offset));
+    }
+    Some(compare_options(
+        e.endianness,
+        &e_body[pos..],
+        &a_body[pos..],
+    ))
+}
+
+fn compare_options(endianness: Endianness, expected: &[u8], actual: &[u8]) -> String {
+    let (e_options, a_options) = (options(endianness, expected), options(endianness, actual));
+    for (index, (e, a)) in e_options.iter().zip(&a_options).enumerate() {
+        if e.0 != a.0 {
+            return format!(
+                "option {} mismatch: expected code {}, got code {}",
+                index + 1,
+                e.0,
+                a.0
+            );
+        }
+        if e.1 != a.1 {
+            return format!("option {} (code {}) value differs", index + 1, e.0);
+        }
+    }
+    if e_options.len() != a_options.len() {
+        return format!(
+            "expected {} options, got {}",
+            e_options.len(),
+            a_options.len()
+        );
+    }
+    let offset = first_difference(expected, actual).unwrap_or(0);
+    format!("options differ at byte {}", offset)
+}
+
+/// Splits options into their codes and values, up to the first
+/// malformed one.
+fn options(endianness: Endianness, mut data: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut options = vec![];
+    while data.len() >= 4 {
+        let code = read(endianness, &data[..2]) as u16;
+        let len = read(endianness, &data[2..4]) as usize;
+        let end = 4 + len + pad_to_32(len);
+        if data.len() < end {
+            break;
+        }
+        options.push((code, &data[4..4 + len]));
+        data = &data[end..];
+    }
+    options
+}
+
+fn body<'a>(block: &RawBlock<'a>) -> &'a [u8] {
+    &block.bytes[8..block.bytes.len() - 4]
+}
+
+fn first_difference(expected: &[u8], actual: &[u8]) -> Option<usize> {
+    expected.iter().zip(actual).position(|(e, a)| e != a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::OptionComment;
+
+    fn capture() -> CaptureBuilder {
+        CaptureBuilder::new()
+            .interface(LinkType::Ethernet, 65535)
+            .packet(0, 1_000, &[1; 60])
+            .packet(0, 2_000, &[2; 60])
+            .packet(0, 3_000, &[3; 60])
+    }
+
+    fn differences(expected: &[u8], actual: &[u8]) -> Vec<String> {
+        compare(expected, actual)
+            .unwrap_err()
+            .differences()
+            .to_vec()
+    }
+
+    #[test]
+    fn same() {
+        let expected = capture().build();
+        assert_eq!(compare(&expected, &capture().build()), Ok(()));
+        assert_same_capture(&capture().build(), &expected);
+    }
+
+    #[test]
+    fn fields() {
+        let expected = capture().build();
+        let longer = capture().packet(0, 4_000, &[4; 60]).build();
+        assert_eq!(
+            differences(&expected, &longer),
+            ["expected 5 blocks, got 6"]
+        );
+
+        let actual = CaptureBuilder::new()
+            .interface(LinkType::Ethernet, 1500)
+            .packet(0, 1_000, &[1; 60])
+            .packet(0, 4_000, &[2; 60])
+            .packet(0, 3_000, &[3; 64])
+            .build();
+        assert_eq!(
+            differences(&expected, &actual),
+            [
+                "IDB #1 (block 2): snap_len mismatch: expected 65535, got 1500",
+                "EPB #2 (block 4): timestamp_low mismatch: expected 2, got 4",
+                "EPB #3 (block 5): total_length mismatch: expected 92, got 96",
+            ]
+        );
+
+        let big = capture().endianness(Endianness::Big).build();
+        assert_eq!(
+            differences(&expected, &big)[0],
+            "SHB #1 (block 1): byte order mismatch: expected Little, got Big"
+        );
+    }
+
+    #[test]
+    fn packet_data_and_options() {
+        let expected = capture().build();
+        let mut actual = expected.clone();
+        // the 11th byte of the first packet
+        let first_epb = 28 + 20;
+        actual[first_epb + 28 + 10] = 0;
+        assert_eq!(
+            differences(&expected, &actual),
+            ["EPB #1 (block 3): packet data differs at byte 10"]
+        );
+
+        let write = |comment| {
+            let option = OptionComment::new_option(comment);
+            let mut options = Options::new();
+            options.add_option(&option);
+            let mut writer = PcapNgWriter::new_deterministic(vec![]);
+            writer.start_new_section(&options).unwrap();
+            writer.get_writer().clone()
+        };
+        assert_eq!(
+            differences(&write("abcd"), &write("abce")),
+            ["SHB #1 (block 1): option 1 (code 1) value differs"]
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let expected = capture().build();
+        let actual = &expected[..expected.len() - 8];
+        assert_eq!(
+            differences(&expected, actual),
+            ["EPB #3 (block 5): truncated: total_length 92, 84 bytes left"]
+        );
+    }
+
+    #[test]
+    fn golden() {
+        let path = std::env::temp_dir().join(format!("pcapng-golden-{}", std::process::id()));
+        std::fs::write(&path, capture().build()).unwrap();
+        assert_golden(&path, &capture().build());
+        let result = std::panic::catch_unwind(|| assert_golden(&path, &[]));
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+}
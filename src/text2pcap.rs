@@ -0,0 +1,244 @@
+//! Converting text hex dumps, like text2pcap.
+//!
+//! `HexDumpReader` reads the packets of a hex dump, and `Text2Pcapng`
+//! writes them as Enhanced Packet Blocks with synthetic timestamps:
+//!
+//! ```
+//! use pcapng_writer::enums::LinkType;
+//! use pcapng_writer::text2pcap::Text2Pcapng;
+//! use pcapng_writer::writer::PcapNgWriter;
+//!
+//! let dump = "\
+//! 0000   ff ff ff ff ff ff 00 11 22 33 44 55 08 06 00 01   ........\"3DU....
+//! 0010   08 00 06 04 00 01                                 ......
+//! ";
+//! let mut writer = PcapNgWriter::new_le(vec![]);
+//! let packets = Text2Pcapng::new(LinkType::Ethernet)
+//!     .convert(dump.as_bytes(), &mut writer)
+//!     .unwrap();
+//! assert_eq!(packets, 1);
+//! ```
+//!
+//! Each line starts with the hexadecimal offset of its first byte in
+//! the packet, optionally followed by a colon, and a packet starts
+//! at offset 0. The bytes are pairs of hex digits separated by one or
+//! two spaces; they end at a token that is not a byte or at a wider
+//! gap, so the text dumps of `text2pcap`, Wireshark's "Copy as Hex
+//! Dump" and `hexdump -C` are read. Bytes beyond the offset of the
+//! next line are dropped, as they belong to a text dump. Other lines
+//! and lines starting with `#` are ignored.
+
+use crate::blocks::options::{OptionIfTsResol, Options};
+use crate::enums::{BlockType, LinkType};
+use crate::error::Result;
+use crate::utils::NANO_SECOND_TSRES;
+use crate::writer::PcapNgWriter;
+use std::io::{self, BufRead, Write};
+use std::time::Duration;
+
+/// Reads the packets of a text hex dump.
+#[derive(Debug)]
+pub struct HexDumpReader<R> {
+    lines: io::Lines<R>,
+    packet: Vec<u8>,
+    done: bool,
+}
+
+impl<R: BufRead> HexDumpReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            packet: Vec::new(),
+            done: false,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for HexDumpReader<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let line = match self.lines.next() {
+                Some(Ok(line)) => line,
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.done = true;
+                    break;
+                }
+            };
+            let (offset, bytes) = match parse_line(&line) {
+                Some(parsed) => parsed,
+                None => continue,
+            };
+            if offset == 0 && !self.packet.is_empty() {
+                let packet = std::mem::replace(&mut self.packet, bytes);
+                return Some(Ok(packet));
+            }
+            if offset <= self.packet.len() {
+                self.packet.truncate(offset);
+                self.packet.extend_from_slice(&bytes);
+            }
+        }
+        if self.packet.is_empty() {
+            None
+        } else {
+            Some(Ok(std::mem::take(&mut self.packet)))
+        }
+    }
+}
+
+/// Parses the offset and the bytes of a line.
+fn parse_line(line: &str) -> Option<(usize, Vec<u8>)> {
+    let line = line.trim_start();
+    if line.starts_with('#') {
+        return None;
+    }
+    let end = line.find(char::is_whitespace).unwrap_or(line.len());
+    let offset = line[..end].strip_suffix(':').unwrap_or(&line[..end]);
+    if offset.len() < 2 {
+        return None;
+    }
+    let offset = usize::from_str_radix(offset, 16).ok()?;
+    let mut rest = line[end..].trim_start();
+    let mut bytes = Vec::new();
+    while rest.len() >= 2 && rest.is_char_boundary(2) {
+        let (byte, after) = rest.split_at(2);
+        let byte = match u8::from_str_radix(byte, 16) {
+            Ok(byte) if !after.starts_with(|c: char| !c.is_whitespace()) => byte,
+            _ => break,
+        };
+        bytes.push(byte);
+        let gap = after.len() - after.trim_start().len();
+        if gap > 2 {
+            break;
+        }
+        rest = after.trim_start();
+    }
+    Some((offset, bytes))
+}
+
+/// Writes the packets of a hex dump as Enhanced Packet Blocks.
+#[derive(Debug, Clone)]
+pub struct Text2Pcapng {
+    link_type: LinkType,
+    start: u128,
+    interval: Duration,
+}
+
+impl Text2Pcapng {
+    /// Creates a converter for packets of `link_type`. The first
+    /// packet is timestamped at the Unix epoch, and the next ones
+    /// one microsecond apart.
+    pub fn new(link_type: LinkType) -> Self {
+        Self {
+            link_type,
+            start: 0,
+            interval: Duration::from_micros(1),
+        }
+    }
+
+    /// Sets the timestamp of the first packet, in nanoseconds since
+    /// the Unix epoch.
+    pub fn set_start(&mut self, nanos: u128) -> &mut Self {
+        self.start = nanos;
+        self
+    }
+
+    /// Sets the time between two packets.
+    pub fn set_interval(&mut self, interval: Duration) -> &mut Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Writes the packets of the hex dump read from `input` on a new
+    /// interface with nanosecond timestamps, and returns the number
+    /// of packets written. A section is started if nothing has been
+    /// written yet.
+    pub fn convert<R: BufRead, W: Write>(
+        &self,
+        input: R,
+        writer: &mut PcapNgWriter<W>,
+    ) -> Result<u64> {
+        if writer.section_count() == 0 && writer.stats().blocks(BlockType::SectionHeader) == 0 {
+            writer.start_new_section(&Options::new())?;
+        }
+        let tsresol = [OptionIfTsResol::new_option(NANO_SECOND_TSRES)];
+        let mut options = Options::new();
+        options.add_options(&tsresol);
+        let interface = writer.add_interface(self.link_type, 0, &options)?;
+        let mut timestamp = self.start;
+        let mut packets = 0;
+        for packet in HexDumpReader::new(input) {
+            writer.write_packet(interface, timestamp, &packet?)?;
+            timestamp += self.interval.as_nanos();
+            packets += 1;
+        }
+        Ok(packets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{Block, SliceReader};
+
+    fn packets(dump: &str) -> Vec<Vec<u8>> {
+        HexDumpReader::new(dump.as_bytes())
+            .map(|p| p.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parse_lines() {
+        assert_eq!(parse_line("0000 01 02 03"), Some((0, vec![1, 2, 3])));
+        assert_eq!(parse_line("0010: 0a 0B"), Some((16, vec![10, 11])));
+        assert_eq!(
+            parse_line("00000000  01 02  03 04  |....|"),
+            Some((0, vec![1, 2, 3, 4]))
+        );
+        assert_eq!(parse_line("0000   01 02   ab"), Some((0, vec![1, 2])));
+        assert_eq!(parse_line("0000 01 023"), Some((0, vec![1])));
+        assert_eq!(parse_line("# 0000 01"), None);
+        assert_eq!(parse_line("hello world"), None);
+        assert_eq!(parse_line("0 01"), None);
+        assert_eq!(parse_line(""), None);
+    }
+
+    #[test]
+    fn read_packets() {
+        let dump = "\
+# two packets
+0000  01 02 03 04 41 42  ....AB
+0004  05 06
+some text
+000a  07
+
+0000  08 09 0a
+";
+        // the ASCII dump "41 42" is dropped by the next offset, and
+        // the line at 0x0a does not follow the packet
+        assert_eq!(packets(dump), [vec![1, 2, 3, 4, 5, 6], vec![8, 9, 10]]);
+        assert!(packets("").is_empty());
+    }
+
+    #[test]
+    fn convert() {
+        let dump = "0000 01 02\n0000 03\n0000 04\n";
+        let mut writer = PcapNgWriter::new_le(vec![]);
+        let packets = Text2Pcapng::new(LinkType::Raw)
+            .set_start(1_000)
+            .set_interval(Duration::from_millis(1))
+            .convert(dump.as_bytes(), &mut writer)
+            .unwrap();
+        assert_eq!(packets, 3);
+        let data = writer.get_writer().clone();
+        let timestamps: Vec<_> = SliceReader::new(&data)
+            .filter_map(|b| match b.unwrap() {
+                Block::EnhancedPacket(epb) => Some(epb.timestamp()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(timestamps, [1_000, 1_001_000, 2_001_000]);
+    }
+}
@@ -0,0 +1,342 @@
+//! Writing on a background thread.
+//!
+//! `ThreadedWriter` moves a `PcapNgWriter` to a dedicated thread and
+//! sends it owned data over a bounded channel, so the threads
+//! producing packets never wait for the disk.
+
+use crate::blocks::options::Options;
+use crate::error::{Error, Result};
+use crate::writer::{Encodable, Endianness, InterfaceId, PcapNgWriter, Validation};
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use std::convert::TryInto;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+
+/// What a `ThreadedWriter` does with a packet when its channel is
+/// full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Wait until the writer thread catches up.
+    Block,
+    /// Drop the packet and count it, see `ThreadedWriter::dropped`.
+    Drop,
+}
+
+enum Command {
+    Block(Encoded),
+    Packet {
+        interface_id: InterfaceId,
+        timestamp_nanos: u128,
+        orig_len: u32,
+        data: Vec<u8>,
+    },
+    Flush,
+}
+
+/// An already encoded block, written as is.
+struct Encoded {
+    bytes: Vec<u8>,
+    block_type: Option<u32>,
+    interface_id: Option<u32>,
+}
+
+impl<W: Write> Encodable<W> for Encoded {
+    fn encode<B: ByteOrder>(&self, w: &mut W) -> Result<()> {
+        w.write_all(&self.bytes)?;
+        Ok(())
+    }
+
+    fn block_type(&self) -> Option<u32> {
+        self.block_type
+    }
+
+    fn interface_id(&self) -> Option<u32> {
+        self.interface_id
+    }
+}
+
+/// A pcapng writer that performs I/O on a dedicated thread.
+///
+/// Blocks passed to `write` are encoded on the calling thread and
+/// sent to the writer thread, along with packets passed to
+/// `write_packet`. The channel holds at most `capacity` blocks; when
+/// it is full, `write` waits, and `write_packet` waits or drops the
+/// packet according to the `Backpressure` setting. Blocks other than
+/// packets are never dropped, as a missing header would make the
+/// output unreadable.
+///
+/// Blocks are validated on the calling thread if the writer is in
+/// `Validation::Strict` mode. The checks that depend on what has been
+/// written, and warnings, are handled by the writer thread.
+///
+/// The writer thread stops at the first error. The sending methods
+/// then fail, and `finish` returns the error.
+///
+/// ```
+/// use pcapng_writer::enums::LinkType;
+/// use pcapng_writer::blocks::options::Options;
+/// use pcapng_writer::threaded::{Backpressure, ThreadedWriter};
+/// use pcapng_writer::writer::{Endianness, PcapNgWriter};
+///
+/// let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+/// let eth = writer
+///     .add_interface(LinkType::Ethernet, 0, &Options::new())
+///     .unwrap();
+/// let threaded = ThreadedWriter::spawn(writer, 1024, Backpressure::Block).unwrap();
+/// threaded.write_packet(eth, 0, vec![0; 60]).unwrap();
+/// let writer = threaded.finish().unwrap();
+/// assert_eq!(writer.stats().total_packets(), 1);
+/// ```
+#[derive(Debug)]
+pub struct ThreadedWriter<W: Write + Send + 'static> {
+    endianness: Endianness,
+    validation: Validation,
+    backpressure: Backpressure,
+    sender: Option<SyncSender<Command>>,
+    handle: Option<JoinHandle<Result<PcapNgWriter<W>>>>,
+    dropped: AtomicU64,
+}
+
+impl<W: Write + Send + 'static> ThreadedWriter<W> {
+    /// Moves `writer` to a new thread. `capacity` is the number of
+    /// blocks that can be queued.
+    pub fn spawn(
+        writer: PcapNgWriter<W>,
+        capacity: usize,
+        backpressure: Backpressure,
+    ) -> io::Result<Self> {
+        let endianness = writer.endianness();
+        let validation = writer.validation();
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let handle = thread::Builder::new()
+            .name("pcapng-writer".into())
+            .spawn(move || {
+                let mut writer = writer;
+                for command in receiver {
+                    match command {
+                        Command::Block(block) => writer.write(&block)?,
+                        Command::Packet {
+                            interface_id,
+                            timestamp_nanos,
+                            orig_len,
+                            data,
+                        } => writer.write_packet_with_options(
+                            interface_id,
+                            timestamp_nanos,
+                            orig_len,
+                            &data,
+                            &Options::new(),
+                        )?,
+                        Command::Flush => writer.get_writer_mut().flush()?,
+                    }
+                }
+                writer.get_writer_mut().flush()?;
+                Ok(writer)
+            })?;
+        Ok(Self {
+            endianness,
+            validation,
+            backpressure,
+            sender: Some(sender),
+            handle: Some(handle),
+            dropped: Default::default(),
+        })
+    }
+
+    /// Encodes a block and queues it, waiting if the channel is full.
+    pub fn write<T: Encodable<Vec<u8>>>(&self, block: &T) -> Result<()> {
+        if self.validation == Validation::Strict {
+            block.validate()?;
+        }
+        let mut buf = vec![];
+        match self.endianness {
+            Endianness::Little => block.encode::<LittleEndian>(&mut buf)?,
+            Endianness::Big => block.encode::<BigEndian>(&mut buf)?,
+        }
+        self.send(Command::Block(Encoded {
+            bytes: buf,
+            block_type: block.block_type(),
+            interface_id: block.interface_id(),
+        }))
+    }
+
+    /// Queues a packet captured on a registered interface, see
+    /// `PcapNgWriter::write_packet`.
+    pub fn write_packet(
+        &self,
+        interface_id: InterfaceId,
+        timestamp_nanos: u128,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let orig_len = data
+            .len()
+            .try_into()
+            .map_err(|_| Error::BlockTooLong(data.len()))?;
+        self.write_packet_with_orig_len(interface_id, timestamp_nanos, orig_len, data)
+    }
+
+    /// Like `write_packet`, but also takes the original length of the
+    /// packet on the wire.
+    pub fn write_packet_with_orig_len(
+        &self,
+        interface_id: InterfaceId,
+        timestamp_nanos: u128,
+        orig_len: u32,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let command = Command::Packet {
+            interface_id,
+            timestamp_nanos,
+            orig_len,
+            data,
+        };
+        match self.backpressure {
+            Backpressure::Block => self.send(command),
+            Backpressure::Drop => match self.sender().try_send(command) {
+                Ok(()) => Ok(()),
+                Err(TrySendError::Full(_)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    Ok(())
+                }
+                Err(TrySendError::Disconnected(_)) => Err(stopped()),
+            },
+        }
+    }
+
+    /// Asks the writer thread to flush the underlying writer once it
+    /// has written the blocks queued so far.
+    pub fn flush(&self) -> Result<()> {
+        self.send(Command::Flush)
+    }
+
+    /// Returns the number of packets dropped because the channel was
+    /// full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Waits for the writer thread to write all the queued blocks and
+    /// returns the `PcapNgWriter`, or the error that stopped the
+    /// thread.
+    pub fn finish(mut self) -> Result<PcapNgWriter<W>> {
+        self.join().expect("writer thread is joined only once")
+    }
+
+    fn join(&mut self) -> Option<Result<PcapNgWriter<W>>> {
+        self.sender = None;
+        let handle = self.handle.take()?;
+        Some(
+            handle
+                .join()
+                .unwrap_or_else(|_| Err(Error::Io(io::Error::other("writer thread panicked")))),
+        )
+    }
+
+    fn sender(&self) -> &SyncSender<Command> {
+        self.sender.as_ref().expect("sender is only taken on drop")
+    }
+
+    fn send(&self, command: Command) -> Result<()> {
+        self.sender().send(command).map_err(|_| stopped())
+    }
+}
+
+impl<W: Write + Send + 'static> Drop for ThreadedWriter<W> {
+    /// Waits for the queued blocks to be written. Errors are ignored;
+    /// use `finish` to check them.
+    fn drop(&mut self) {
+        let _ = self.join();
+    }
+}
+
+fn stopped() -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::BrokenPipe,
+        "writer thread stopped",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::InterfaceStatisticsBlock;
+    use crate::enums::{BlockType, LinkType};
+    use std::sync::{Arc, Mutex};
+
+    /// A writer that waits for the lock before each write.
+    struct Gate(Arc<Mutex<()>>, Vec<u8>);
+
+    impl Write for Gate {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let _open = self.0.lock().unwrap();
+            self.1.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct Broken;
+
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::Other.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write() {
+        let opts = Options::new();
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Big, vec![]);
+        let id = writer.add_interface(LinkType::Ethernet, 0, &opts).unwrap();
+        let threaded = ThreadedWriter::spawn(writer, 4, Backpressure::Block).unwrap();
+        for i in 0..100 {
+            threaded.write_packet(id, i, vec![i as u8; 60]).unwrap();
+        }
+        let isb = InterfaceStatisticsBlock::new(0, 0, 0, &opts);
+        threaded.write(&isb).unwrap();
+        let writer = threaded.finish().unwrap();
+        assert_eq!(writer.stats().total_packets(), 100);
+        assert_eq!(writer.stats().blocks(BlockType::InterfaceStatistics), 1);
+        assert_eq!(writer.get_writer().len() as u64, writer.position());
+    }
+
+    #[test]
+    fn drop_on_backpressure() {
+        let lock = Arc::new(Mutex::new(()));
+        let mut writer =
+            PcapNgWriter::new_auto_section(Endianness::Little, Gate(Arc::clone(&lock), vec![]));
+        let id = writer
+            .add_interface(LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        let closed = lock.lock().unwrap();
+        let threaded = ThreadedWriter::spawn(writer, 2, Backpressure::Drop).unwrap();
+        for i in 0..10 {
+            threaded.write_packet(id, i, vec![0; 60]).unwrap();
+        }
+        // at most one packet is being written and two are queued
+        let dropped = threaded.dropped();
+        assert!(dropped >= 7);
+        drop(closed);
+        let writer = threaded.finish().unwrap();
+        assert_eq!(writer.stats().total_packets() + dropped, 10);
+    }
+
+    #[test]
+    fn stopped_thread() {
+        let writer = PcapNgWriter::new_le(Broken);
+        let threaded = ThreadedWriter::spawn(writer, 1, Backpressure::Block).unwrap();
+        let opts = Options::new();
+        let isb = InterfaceStatisticsBlock::new(0, 0, 0, &opts);
+        assert!((0..100).any(|_| threaded.write(&isb).is_err()));
+        assert!(matches!(threaded.finish(), Err(Error::Io(_))));
+    }
+}
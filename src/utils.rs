@@ -1,15 +1,46 @@
+use crate::error::{Error, Result};
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+use core::convert::TryInto;
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(feature = "time")]
+use time::OffsetDateTime;
+
+pub const SECOND_TSRES: &TimestampResolution = &TimestampResolution::PowerOfTen(0);
+pub const MILLI_SECOND_TSRES: &TimestampResolution = &TimestampResolution::PowerOfTen(3);
 pub const MICRO_SECOND_TSRES: &TimestampResolution = &TimestampResolution::PowerOfTen(6);
 pub const NANO_SECOND_TSRES: &TimestampResolution = &TimestampResolution::PowerOfTen(9);
 pub const DEFAULT_TSRES: &TimestampResolution = MICRO_SECOND_TSRES;
 
 /// Represents a timestamp resolution as specified by the pcapng
 /// standard section 4.2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TimestampResolution {
     PowerOfTen(u8),
     PowerOfTwo(u8),
 }
 
 impl TimestampResolution {
+    pub const fn seconds() -> Self {
+        Self::PowerOfTen(0)
+    }
+
+    pub const fn milliseconds() -> Self {
+        Self::PowerOfTen(3)
+    }
+
+    pub const fn microseconds() -> Self {
+        Self::PowerOfTen(6)
+    }
+
+    pub const fn nanoseconds() -> Self {
+        Self::PowerOfTen(9)
+    }
+
     /// Returns a value that can be used in the `if_tsresol` option of
     /// the Interface Description Block.
     pub fn to_tsresol(&self) -> u8 {
@@ -19,28 +50,263 @@ impl TimestampResolution {
         }
     }
 
+    /// Parses the value of an `if_tsresol` option, the inverse of
+    /// `to_tsresol`.
+    pub fn from_tsresol(tsresol: u8) -> Self {
+        if tsresol & (1u8 << 7) == 0 {
+            Self::PowerOfTen(tsresol)
+        } else {
+            Self::PowerOfTwo(tsresol & !(1u8 << 7))
+        }
+    }
+
     /// Returns a tuple of integers that can be used in "Timestamp
     /// (High)" and "Timestamp (Low)" of the Enhanced Packet Block
     /// respectively.
+    ///
+    /// Timestamps that do not fit in 64 bits are truncated; see
+    /// `try_ts_from_nanoseconds`.
     pub fn ts_from_nanoseconds(&self, nanos: u128) -> (u32, u32) {
-        let high: u32;
-        let low: u32;
+        let t = self.ticks_from_nanoseconds(nanos);
+        ((t >> 32) as u32, (t & 0xffff_ffff) as u32)
+    }
+
+    /// Like `ts_from_nanoseconds`, but returns an error if the
+    /// timestamp does not fit in 64 bits.
+    pub fn try_ts_from_nanoseconds(&self, nanos: u128) -> Result<(u32, u32)> {
+        if self.ticks_from_nanoseconds(nanos) > u64::MAX.into() {
+            return Err(Error::TimestampOverflow);
+        }
+        Ok(self.ts_from_nanoseconds(nanos))
+    }
+
+    /// Like `try_ts_from_nanoseconds`, but takes the time since the
+    /// Unix epoch as a `Duration`.
+    pub fn ts_from_duration(&self, since_epoch: Duration) -> Result<(u32, u32)> {
+        self.try_ts_from_nanoseconds(since_epoch.as_nanos())
+    }
+
+    /// Like `try_ts_from_nanoseconds`, but takes a `SystemTime`.
+    /// Returns an error if it is earlier than the Unix epoch.
+    #[cfg(feature = "std")]
+    pub fn ts_from_systemtime(&self, time: SystemTime) -> Result<(u32, u32)> {
+        self.try_ts_from_nanoseconds(nanos_since_epoch(time)?)
+    }
+
+    /// Like `try_ts_from_nanoseconds`, but takes a `chrono`
+    /// `DateTime`. Returns an error if it is earlier than the Unix
+    /// epoch.
+    #[cfg(feature = "chrono")]
+    pub fn ts_from_datetime(&self, time: &DateTime<Utc>) -> Result<(u32, u32)> {
+        let secs: u64 = time
+            .timestamp()
+            .try_into()
+            .map_err(|_| Error::TimestampBeforeEpoch)?;
+        let nanos = u128::from(secs) * 1_000_000_000 + u128::from(time.timestamp_subsec_nanos());
+        self.try_ts_from_nanoseconds(nanos)
+    }
+
+    /// Converts a timestamp in units of this resolution, e.g. the
+    /// Timestamp (High) and (Low) fields of an Enhanced Packet Block
+    /// as a single number, to a `chrono` `DateTime`. Returns `None` if
+    /// it is out of the range of `DateTime`.
+    #[cfg(feature = "chrono")]
+    pub fn datetime_from_ticks(&self, ticks: u64) -> Option<DateTime<Utc>> {
+        let nanos = self.nanoseconds_from_ticks(ticks);
+        let secs = (nanos / 1_000_000_000).try_into().ok()?;
+        DateTime::from_timestamp(secs, (nanos % 1_000_000_000) as u32)
+    }
+
+    /// Like `try_ts_from_nanoseconds`, but takes a `time`
+    /// `OffsetDateTime`. Returns an error if it is earlier than the
+    /// Unix epoch.
+    #[cfg(feature = "time")]
+    pub fn ts_from_offset_datetime(&self, time: OffsetDateTime) -> Result<(u32, u32)> {
+        let nanos = time
+            .unix_timestamp_nanos()
+            .try_into()
+            .map_err(|_| Error::TimestampBeforeEpoch)?;
+        self.try_ts_from_nanoseconds(nanos)
+    }
+
+    /// Converts a timestamp in units of this resolution to a `time`
+    /// `OffsetDateTime` in UTC. Returns `None` if it is out of the
+    /// range of `OffsetDateTime`.
+    #[cfg(feature = "time")]
+    pub fn offset_datetime_from_ticks(&self, ticks: u64) -> Option<OffsetDateTime> {
+        let nanos = self.nanoseconds_from_ticks(ticks).try_into().ok()?;
+        OffsetDateTime::from_unix_timestamp_nanos(nanos).ok()
+    }
+
+    /// Converts a timestamp in units of this resolution to
+    /// nanoseconds since the Unix epoch, rounding down.
+    pub fn nanoseconds_from_ticks(&self, ticks: u64) -> u128 {
+        let ticks = u128::from(ticks);
+        match *self {
+            Self::PowerOfTen(power) if power <= 9 => ticks * 10u128.pow(9 - power as u32),
+            Self::PowerOfTen(power) => 10u128
+                .checked_pow(power as u32 - 9)
+                .map_or(0, |divisor| ticks / divisor),
+            Self::PowerOfTwo(power) => (ticks * 1_000_000_000)
+                .checked_shr(power as u32)
+                .unwrap_or(0),
+        }
+    }
+
+    fn ticks_from_nanoseconds(&self, nanos: u128) -> u128 {
         match *self {
-            Self::PowerOfTen(power) => {
-                let t: u128 = nanos / ((10u128).pow(9 - power as u32));
-                high = (t >> 32) as u32;
-                low = (t & 0xffff_ffff) as u32;
+            Self::PowerOfTen(power) if power > 9 => {
+                nanos.saturating_mul(10u128.saturating_pow(power as u32 - 9))
             }
+            Self::PowerOfTen(power) => nanos / ((10u128).pow(9 - power as u32)),
             Self::PowerOfTwo(power) => {
-                let t: u128 = (nanos / 1_000_000_000) * (2u128).pow(power as u32);
-                high = (t >> 32) as u32;
-                low = (t & 0xffff_ffff) as u32;
+                let scale = match 2u128.checked_pow(power as u32) {
+                    Some(scale) => scale,
+                    None if nanos == 0 => return 0,
+                    None => return u128::MAX,
+                };
+                // scale the whole seconds and the remainder separately,
+                // so the fraction of a second is kept without overflowing
+                let secs = nanos / 1_000_000_000;
+                let frac = (nanos % 1_000_000_000)
+                    .checked_mul(scale)
+                    .map_or(u128::MAX, |n| n / 1_000_000_000);
+                secs.saturating_mul(scale).saturating_add(frac)
             }
         }
-        (high, low)
     }
 }
 
+/// A timestamp as a number of ticks of an interface's timestamp
+/// resolution, i.e. the Timestamp (High) and (Low) fields of an
+/// Enhanced Packet Block as a single number.
+///
+/// Capture hardware that counts in its own ticks can build these
+/// directly, which skips the conversion from nanoseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    pub const fn new(ticks: u64) -> Self {
+        Self(ticks)
+    }
+
+    /// Creates a `Timestamp` from the Timestamp (High) and (Low)
+    /// fields of a block.
+    pub const fn from_parts(high: u32, low: u32) -> Self {
+        Self((high as u64) << 32 | low as u64)
+    }
+
+    /// Converts a number of nanoseconds since the Unix epoch to ticks
+    /// of `ts_res`. Returns an error if it does not fit in 64 bits.
+    pub fn from_nanoseconds(ts_res: &TimestampResolution, nanos: u128) -> Result<Self> {
+        let (high, low) = ts_res.try_ts_from_nanoseconds(nanos)?;
+        Ok(Self::from_parts(high, low))
+    }
+
+    pub const fn ticks(&self) -> u64 {
+        self.0
+    }
+
+    /// Returns the value of the Timestamp (High) field.
+    pub const fn high(&self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+
+    /// Returns the value of the Timestamp (Low) field.
+    pub const fn low(&self) -> u32 {
+        self.0 as u32
+    }
+
+    /// Converts the timestamp to nanoseconds since the Unix epoch,
+    /// assuming it is in units of `ts_res`.
+    pub fn to_nanoseconds(&self, ts_res: &TimestampResolution) -> u128 {
+        ts_res.nanoseconds_from_ticks(self.0)
+    }
+
+    pub fn checked_add(self, ticks: u64) -> Option<Self> {
+        self.0.checked_add(ticks).map(Self)
+    }
+
+    pub fn checked_sub(self, ticks: u64) -> Option<Self> {
+        self.0.checked_sub(ticks).map(Self)
+    }
+
+    /// Returns the number of ticks from `earlier` to `self`, or
+    /// `None` if `earlier` is later.
+    pub fn checked_duration_since(self, earlier: Self) -> Option<u64> {
+        self.0.checked_sub(earlier.0)
+    }
+}
+
+impl From<u64> for Timestamp {
+    fn from(ticks: u64) -> Self {
+        Self(ticks)
+    }
+}
+
+impl From<Timestamp> for u64 {
+    fn from(ts: Timestamp) -> Self {
+        ts.0
+    }
+}
+
+impl Add<u64> for Timestamp {
+    type Output = Self;
+
+    fn add(self, ticks: u64) -> Self {
+        Self(self.0 + ticks)
+    }
+}
+
+impl AddAssign<u64> for Timestamp {
+    fn add_assign(&mut self, ticks: u64) {
+        self.0 += ticks;
+    }
+}
+
+impl Sub<u64> for Timestamp {
+    type Output = Self;
+
+    fn sub(self, ticks: u64) -> Self {
+        Self(self.0 - ticks)
+    }
+}
+
+impl SubAssign<u64> for Timestamp {
+    fn sub_assign(&mut self, ticks: u64) {
+        self.0 -= ticks;
+    }
+}
+
+impl Sub for Timestamp {
+    type Output = u64;
+
+    /// Returns the number of ticks between two timestamps.
+    fn sub(self, other: Self) -> u64 {
+        self.0 - other.0
+    }
+}
+
+/// Returns the number of nanoseconds between the Unix epoch and
+/// `time`.
+#[cfg(feature = "std")]
+pub(crate) fn nanos_since_epoch(time: SystemTime) -> Result<u128> {
+    Ok(time
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| Error::TimestampBeforeEpoch)?
+        .as_nanos())
+}
+
+/// Returns the length of packet data, as written in the length fields
+/// of packet blocks and records.
+pub(crate) fn packet_len(data: &[u8]) -> Result<u32> {
+    data.len()
+        .try_into()
+        .map_err(|_| Error::BlockTooLong(data.len()))
+}
+
 pub fn pad_to_32(n: usize) -> usize {
     let mut m = n % 4;
     if m > 0 {
@@ -100,6 +366,133 @@ mod tests {
         );
     }
 
+    #[test]
+    fn from_nanos_power_of_two_fraction() {
+        let ts_2_20 = TimestampResolution::PowerOfTwo(20);
+        // 1.5 s is 1.5 * 2^20 ticks
+        assert_eq!(ts_2_20.ts_from_nanoseconds(1_500_000_000), (0, 1_572_864));
+        // a tick is about 953.67 ns
+        assert_eq!(ts_2_20.ts_from_nanoseconds(953), (0, 0));
+        assert_eq!(ts_2_20.ts_from_nanoseconds(954), (0, 1));
+        assert_eq!(ts_2_20.ts_from_nanoseconds(1_000_000_954), (0, 1_048_577));
+
+        let ts_2_32 = TimestampResolution::PowerOfTwo(32);
+        assert_eq!(
+            ts_2_32.ts_from_nanoseconds(1_600_000_000_250_000_000),
+            (1_600_000_000, 1 << 30)
+        );
+        let ticks = (1_600_000_000u64 << 32) | (1 << 30);
+        assert_eq!(
+            ts_2_32.nanoseconds_from_ticks(ticks),
+            1_600_000_000_250_000_000
+        );
+
+        assert!(matches!(
+            TimestampResolution::PowerOfTwo(127).try_ts_from_nanoseconds(1),
+            Err(Error::TimestampOverflow)
+        ));
+        assert_eq!(
+            TimestampResolution::PowerOfTwo(200).ts_from_nanoseconds(0),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn from_nanos_overflow() {
+        let ts_nano = TimestampResolution::PowerOfTen(9);
+        let max = u64::MAX as u128;
+        assert_eq!(
+            ts_nano.try_ts_from_nanoseconds(max).unwrap(),
+            (0xffff_ffff, 0xffff_ffff)
+        );
+        assert!(matches!(
+            ts_nano.try_ts_from_nanoseconds(max + 1),
+            Err(Error::TimestampOverflow)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn from_duration_and_systemtime() {
+        let ts_micro = TimestampResolution::PowerOfTen(6);
+        let since_epoch = Duration::new(0x1_0000_0000, 1_999);
+        assert_eq!(
+            ts_micro.ts_from_duration(since_epoch).unwrap(),
+            ts_micro.ts_from_nanoseconds(since_epoch.as_nanos())
+        );
+        assert_eq!(
+            ts_micro
+                .ts_from_systemtime(UNIX_EPOCH + since_epoch)
+                .unwrap(),
+            ts_micro.ts_from_nanoseconds(since_epoch.as_nanos())
+        );
+        assert!(matches!(
+            ts_micro.ts_from_systemtime(UNIX_EPOCH - Duration::from_secs(1)),
+            Err(Error::TimestampBeforeEpoch)
+        ));
+        assert!(matches!(
+            TimestampResolution::PowerOfTen(9).ts_from_duration(Duration::MAX),
+            Err(Error::TimestampOverflow)
+        ));
+    }
+
+    #[test]
+    fn from_tsresol() {
+        for &tsresol in &[0, 3, 6, 9, 12, 0x80, 0x8a, 0xff] {
+            assert_eq!(
+                TimestampResolution::from_tsresol(tsresol).to_tsresol(),
+                tsresol
+            );
+        }
+        assert_eq!(
+            TimestampResolution::from_tsresol(0x94),
+            TimestampResolution::PowerOfTwo(20)
+        );
+        assert_eq!(&TimestampResolution::milliseconds(), MILLI_SECOND_TSRES);
+    }
+
+    #[test]
+    fn seconds_and_milliseconds() {
+        let nanos = 1_500_000_000_123_456_789;
+        assert_eq!(SECOND_TSRES.ts_from_nanoseconds(nanos), (0, 1_500_000_000));
+        let ms = 1_500_000_000_123u64;
+        assert_eq!(
+            MILLI_SECOND_TSRES.ts_from_nanoseconds(nanos),
+            ((ms >> 32) as u32, ms as u32)
+        );
+        assert_eq!(
+            MILLI_SECOND_TSRES.nanoseconds_from_ticks(ms),
+            1_500_000_000_123_000_000
+        );
+        // finer than nanoseconds
+        let ps = TimestampResolution::PowerOfTen(12);
+        assert_eq!(ps.try_ts_from_nanoseconds(1).unwrap(), (0, 1_000));
+        assert_eq!(ps.nanoseconds_from_ticks(1_999), 1);
+    }
+
+    #[test]
+    fn timestamp() {
+        let ts = Timestamp::from_parts(0x0005_f9a2, 0x1234_5678);
+        assert_eq!(ts.ticks(), 0x0005_f9a2_1234_5678);
+        assert_eq!((ts.high(), ts.low()), (0x0005_f9a2, 0x1234_5678));
+        assert_eq!(
+            Timestamp::from_nanoseconds(MICRO_SECOND_TSRES, 1_500_000_001_000).unwrap(),
+            Timestamp::new(1_500_000_001)
+        );
+        let ts_2_20 = TimestampResolution::PowerOfTwo(20);
+        let ts = Timestamp::new(3 << 19);
+        assert_eq!(ts.to_nanoseconds(&ts_2_20), 1_500_000_000);
+
+        let mut ts = Timestamp::new(100);
+        ts += 50;
+        assert_eq!(ts - Timestamp::new(30), 120);
+        assert_eq!((ts - 150).ticks(), 0);
+        assert_eq!(ts.checked_sub(151), None);
+        assert_eq!(Timestamp::new(u64::MAX).checked_add(1), None);
+        assert_eq!(Timestamp::new(1).checked_duration_since(ts), None);
+        assert!(Timestamp::from_nanoseconds(NANO_SECOND_TSRES, u128::MAX).is_err());
+    }
+
     #[test]
     fn padding() {
         assert_eq!(pad_to_32(0), 0);
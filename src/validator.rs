@@ -0,0 +1,561 @@
+//! Structural validation of pcapng byte streams.
+//!
+//! `validate` walks a stream block by block and reports the problems
+//! it finds, instead of stopping at the first one like the reader
+//! does. It is meant for testing tools that produce pcapng data:
+//!
+//! ```
+//! use pcapng_writer::blocks::options::Options;
+//! use pcapng_writer::enums::LinkType;
+//! use pcapng_writer::writer::{Endianness, PcapNgWriter};
+//!
+//! let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+//! let eth = writer.add_interface(LinkType::Ethernet, 0, &Options::new()).unwrap();
+//! writer.write_packet(eth, 0, &[0; 60]).unwrap();
+//!
+//! let report = pcapng_writer::validate(&writer.get_writer()[..]).unwrap();
+//! assert!(report.is_valid(), "{}", report);
+//! assert_eq!(report.blocks(), 3);
+//! ```
+//!
+//! Framing problems (a bad Block Total Length, a truncated block or a
+//! stream that does not start with a Section Header Block) end the
+//! validation, as the following blocks cannot be located. Other
+//! problems are reported and the validation continues with the next
+//! block.
+
+use crate::constants::{BLOCK_COMMON_LEN, BYTE_ORDER_MAGIC};
+use crate::enums::BlockType;
+use crate::error::Result;
+use crate::utils::pad_to_32;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use std::fmt;
+use std::io::{self, Read};
+
+/// A problem found by `validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Problem {
+    /// The stream, or the data after a section, does not start with a
+    /// Section Header Block.
+    MissingSectionHeader,
+    /// The Byte-Order Magic of a Section Header Block is not valid.
+    /// Contains the magic, read as big-endian.
+    InvalidByteOrderMagic(u32),
+    /// The major version of a section is not 1.
+    UnsupportedVersion { major: u16, minor: u16 },
+    /// The Block Total Length is not a multiple of 4 or is too small
+    /// for the block type.
+    InvalidBlockLength(u32),
+    /// The trailing Block Total Length differs from the leading one.
+    BlockLengthMismatch { leading: u32, trailing: u32 },
+    /// The stream ends in the middle of a block. Contains the number
+    /// of missing bytes.
+    Truncated(u64),
+    /// The captured length of a packet block exceeds its body.
+    CapLenExceedsBlock(u32),
+    /// A padding byte is not zero.
+    NonZeroPadding,
+    /// An option extends past the end of its block.
+    OptionOverrun { code: u16 },
+    /// An `opt_endofopt` has a non-zero length.
+    InvalidEndOfOpt(u16),
+    /// Options follow an `opt_endofopt`.
+    EndOfOptNotLast,
+    /// A block refers to an interface that has not been described in
+    /// the current section.
+    UnknownInterface(u32),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingSectionHeader => write!(f, "missing section header block"),
+            Self::InvalidByteOrderMagic(magic) => {
+                write!(f, "invalid byte-order magic {:#010x}", magic)
+            }
+            Self::UnsupportedVersion { major, minor } => {
+                write!(f, "unsupported version {}.{}", major, minor)
+            }
+            Self::InvalidBlockLength(len) => write!(f, "invalid block total length {}", len),
+            Self::BlockLengthMismatch { leading, trailing } => write!(
+                f,
+                "block total lengths differ ({} and {})",
+                leading, trailing
+            ),
+            Self::Truncated(missing) => write!(f, "truncated block ({} bytes missing)", missing),
+            Self::CapLenExceedsBlock(len) => {
+                write!(f, "captured length {} exceeds the block", len)
+            }
+            Self::NonZeroPadding => write!(f, "non-zero padding"),
+            Self::OptionOverrun { code } => write!(f, "option {} overruns the block", code),
+            Self::InvalidEndOfOpt(len) => write!(f, "opt_endofopt has length {}", len),
+            Self::EndOfOptNotLast => write!(f, "options follow opt_endofopt"),
+            Self::UnknownInterface(id) => write!(f, "unknown interface ID {}", id),
+        }
+    }
+}
+
+/// A problem and where it was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    offset: u64,
+    block_type: Option<u32>,
+    problem: Problem,
+}
+
+impl Diagnostic {
+    /// Returns the offset of the block in the stream.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the type of the block, if its header could be read.
+    pub fn block_type(&self) -> Option<u32> {
+        self.block_type
+    }
+
+    pub fn problem(&self) -> &Problem {
+        &self.problem
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "offset {}", self.offset)?;
+        if let Some(block_type) = self.block_type {
+            write!(f, ", block type {:#010x}", block_type)?;
+        }
+        write!(f, ": {}", self.problem)
+    }
+}
+
+/// The result of `validate`.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    bytes: u64,
+    blocks: u64,
+    sections: u64,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    /// Returns true if no problem was found.
+    pub fn is_valid(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Returns the number of bytes read.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns the number of complete blocks.
+    pub fn blocks(&self) -> u64 {
+        self.blocks
+    }
+
+    pub fn sections(&self) -> u64 {
+        self.sections
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes, {} blocks in {} sections, {} problems",
+            self.bytes,
+            self.blocks,
+            self.sections,
+            self.diagnostics.len()
+        )?;
+        for diagnostic in &self.diagnostics {
+            write!(f, "\n{}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the structure of the pcapng stream in `reader`: block
+/// framing, the consistency of the Block Total Length fields,
+/// padding, option lists, and the presence of a Section Header Block.
+///
+/// Only I/O errors of `reader` are returned as errors; problems in
+/// the data are listed in the `Report`.
+pub fn validate<R: Read>(reader: R) -> Result<Report> {
+    let mut validator = Validator {
+        reader,
+        report: Default::default(),
+        big_endian: None,
+        interfaces: 0,
+        buf: Vec::new(),
+    };
+    validator.run()?;
+    Ok(validator.report)
+}
+
+struct Validator<R> {
+    reader: R,
+    report: Report,
+    big_endian: Option<bool>,
+    interfaces: u32,
+    buf: Vec<u8>,
+}
+
+impl<R: Read> Validator<R> {
+    fn run(&mut self) -> io::Result<()> {
+        loop {
+            let offset = self.report.bytes;
+            let mut header = [0; BLOCK_COMMON_LEN];
+            let read = self.read(&mut header)?;
+            if read == 0 {
+                return Ok(());
+            }
+            let report = |v: &mut Self, block_type, problem| {
+                v.report.diagnostics.push(Diagnostic {
+                    offset,
+                    block_type,
+                    problem,
+                })
+            };
+            if read < 8 {
+                report(self, None, Problem::Truncated((8 - read) as u64));
+                return Ok(());
+            }
+            // the SHB block type reads the same in both byte orders
+            let is_shb = BigEndian::read_u32(&header) == BlockType::SectionHeader.value();
+            if is_shb {
+                if read < BLOCK_COMMON_LEN {
+                    report(
+                        self,
+                        Some(BlockType::SectionHeader.value()),
+                        Problem::Truncated((BLOCK_COMMON_LEN - read) as u64),
+                    );
+                    return Ok(());
+                }
+                let magic = BigEndian::read_u32(&header[8..]);
+                self.big_endian = match magic {
+                    BYTE_ORDER_MAGIC => Some(true),
+                    m if m == BYTE_ORDER_MAGIC.swap_bytes() => Some(false),
+                    _ => {
+                        report(
+                            self,
+                            Some(BlockType::SectionHeader.value()),
+                            Problem::InvalidByteOrderMagic(magic),
+                        );
+                        return Ok(());
+                    }
+                };
+                self.report.sections += 1;
+                self.interfaces = 0;
+            }
+            let big_endian = match self.big_endian {
+                Some(big_endian) => big_endian,
+                None => {
+                    report(self, None, Problem::MissingSectionHeader);
+                    return Ok(());
+                }
+            };
+            let (block_type, total_length) = if big_endian {
+                (
+                    BigEndian::read_u32(&header),
+                    BigEndian::read_u32(&header[4..]),
+                )
+            } else {
+                (
+                    LittleEndian::read_u32(&header),
+                    LittleEndian::read_u32(&header[4..]),
+                )
+            };
+            let min_length = if is_shb { 28 } else { BLOCK_COMMON_LEN as u32 };
+            if total_length < min_length || total_length % 4 != 0 {
+                report(
+                    self,
+                    Some(block_type),
+                    Problem::InvalidBlockLength(total_length),
+                );
+                return Ok(());
+            }
+            self.buf.clear();
+            self.buf.extend_from_slice(&header[..read]);
+            let rest = self.read_body(total_length as usize - read)?;
+            if read + rest < total_length as usize {
+                let missing = total_length as usize - read - rest;
+                report(self, Some(block_type), Problem::Truncated(missing as u64));
+                return Ok(());
+            }
+            let problems = if big_endian {
+                self.check_block::<BigEndian>(block_type)
+            } else {
+                self.check_block::<LittleEndian>(block_type)
+            };
+            let framing_error =
+                matches!(problems.first(), Some(Problem::BlockLengthMismatch { .. }));
+            for problem in problems {
+                report(self, Some(block_type), problem);
+            }
+            if framing_error {
+                return Ok(());
+            }
+            self.report.blocks += 1;
+        }
+    }
+
+    /// Checks the block in `buf`, whose framing fields have been
+    /// checked, except for the trailing Block Total Length.
+    fn check_block<B: ByteOrder>(&mut self, block_type: u32) -> Vec<Problem> {
+        let block = &self.buf[..];
+        let total_length = block.len();
+        let leading = B::read_u32(&block[4..]);
+        let trailing = B::read_u32(&block[total_length - 4..]);
+        if leading != trailing {
+            return vec![Problem::BlockLengthMismatch { leading, trailing }];
+        }
+        let body = &block[8..total_length - 4];
+        let mut problems = vec![];
+        let (fixed_len, options) = match block_type {
+            t if t == BlockType::SectionHeader.value() => {
+                let major = B::read_u16(&body[4..]);
+                let minor = B::read_u16(&body[6..]);
+                if major != 1 {
+                    problems.push(Problem::UnsupportedVersion { major, minor });
+                }
+                (16, true)
+            }
+            t if t == BlockType::InterfaceDescription.value() => {
+                self.interfaces = self.interfaces.saturating_add(1);
+                (8, true)
+            }
+            t if t == BlockType::EnhancedPacket.value() => {
+                if body.len() < 20 {
+                    problems.push(Problem::InvalidBlockLength(leading));
+                    return problems;
+                }
+                self.check_interface(B::read_u32(body), &mut problems);
+                let cap_len = B::read_u32(&body[12..]);
+                let padded_len = cap_len as usize + pad_to_32(cap_len as usize);
+                if body.len() - 20 < padded_len {
+                    problems.push(Problem::CapLenExceedsBlock(cap_len));
+                    return problems;
+                }
+                check_padding(&body[20 + cap_len as usize..20 + padded_len], &mut problems);
+                (20 + padded_len, true)
+            }
+            t if t == BlockType::SimplePacket.value() => {
+                if body.len() < 4 {
+                    problems.push(Problem::InvalidBlockLength(leading));
+                    return problems;
+                }
+                self.check_interface(0, &mut problems);
+                (4, false)
+            }
+            t if t == BlockType::InterfaceStatistics.value() => {
+                if body.len() >= 4 {
+                    self.check_interface(B::read_u32(body), &mut problems);
+                }
+                (12, true)
+            }
+            _ => (0, false),
+        };
+        if body.len() < fixed_len {
+            problems.push(Problem::InvalidBlockLength(leading));
+        } else if options {
+            check_options::<B>(&body[fixed_len..], &mut problems);
+        }
+        problems
+    }
+
+    fn check_interface(&self, id: u32, problems: &mut Vec<Problem>) {
+        if id >= self.interfaces {
+            problems.push(Problem::UnknownInterface(id));
+        }
+    }
+
+    /// Reads into `buf[from..]` until it is full or the stream ends,
+    /// and returns the number of bytes read.
+    /// Appends up to `len` bytes to `buf`. The buffer only grows as
+    /// bytes arrive, so a bogus Block Total Length does not allocate
+    /// more than the data.
+    fn read_body(&mut self, len: usize) -> io::Result<usize> {
+        let read = (&mut self.reader)
+            .take(len as u64)
+            .read_to_end(&mut self.buf)?;
+        self.report.bytes += read as u64;
+        Ok(read)
+    }
+
+    fn read(&mut self, mut buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        while !buf.is_empty() {
+            match self.reader.read(buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    read += n;
+                    buf = &mut buf[n..];
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        self.report.bytes += read as u64;
+        Ok(read)
+    }
+}
+
+fn check_padding(padding: &[u8], problems: &mut Vec<Problem>) {
+    if padding.iter().any(|&b| b != 0) && !problems.contains(&Problem::NonZeroPadding) {
+        problems.push(Problem::NonZeroPadding);
+    }
+}
+
+fn check_options<B: ByteOrder>(mut data: &[u8], problems: &mut Vec<Problem>) {
+    while !data.is_empty() {
+        if data.len() < 4 {
+            problems.push(Problem::OptionOverrun { code: 0 });
+            return;
+        }
+        let code = B::read_u16(data);
+        let length = B::read_u16(&data[2..]) as usize;
+        let padded_len = length + pad_to_32(length);
+        if data.len() - 4 < padded_len {
+            problems.push(Problem::OptionOverrun { code });
+            return;
+        }
+        check_padding(&data[4 + length..4 + padded_len], problems);
+        data = &data[4 + padded_len..];
+        if code == 0 {
+            if length != 0 {
+                problems.push(Problem::InvalidEndOfOpt(length as u16));
+            }
+            if !data.is_empty() {
+                problems.push(Problem::EndOfOptNotLast);
+            }
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::{OptionComment, Options};
+    use crate::blocks::RawBlock;
+    use crate::enums::LinkType;
+    use crate::writer::{Endianness, PcapNgWriter};
+
+    fn capture(endianness: Endianness) -> Vec<u8> {
+        let comment = OptionComment::new_option("abc");
+        let mut opts = Options::new();
+        opts.add_option(&comment);
+        let mut writer = PcapNgWriter::new_auto_section(endianness, vec![]);
+        let id = writer.add_interface(LinkType::Ethernet, 0, &opts).unwrap();
+        writer
+            .write_packet_with_options(id, 0, 5, &[1; 5], &opts)
+            .unwrap();
+        writer.get_writer().clone()
+    }
+
+    fn problems(data: &[u8]) -> Vec<Problem> {
+        validate(data)
+            .unwrap()
+            .diagnostics()
+            .iter()
+            .map(|d| d.problem().clone())
+            .collect()
+    }
+
+    #[test]
+    fn valid() {
+        for &endianness in &[Endianness::Little, Endianness::Big] {
+            let data = capture(endianness);
+            let report = validate(&data[..]).unwrap();
+            assert!(report.is_valid(), "{}", report);
+            assert_eq!(report.blocks(), 3);
+            assert_eq!(report.sections(), 1);
+            assert_eq!(report.bytes(), data.len() as u64);
+        }
+        assert!(validate(&[][..]).unwrap().is_valid());
+    }
+
+    #[test]
+    fn framing() {
+        let data = capture(Endianness::Little);
+        // SHB 28, IDB 20 + 8 bytes of comment
+        let epb = 56;
+        assert_eq!(problems(&data[epb..]), [Problem::MissingSectionHeader]);
+        let report = validate(&data[..data.len() - 6]).unwrap();
+        assert_eq!(report.blocks(), 2);
+        assert_eq!(report.diagnostics()[0].offset(), epb as u64);
+        assert_eq!(
+            report.diagnostics()[0].block_type(),
+            Some(BlockType::EnhancedPacket.value())
+        );
+        assert_eq!(report.diagnostics()[0].problem(), &Problem::Truncated(6));
+
+        // a huge length in a short stream
+        let mut huge = data[..epb + 12].to_vec();
+        huge[epb + 4..epb + 8].copy_from_slice(&0xFFFF_FFFCu32.to_le_bytes());
+        assert_eq!(problems(&huge), [Problem::Truncated(0xFFFF_FFFC - 12)]);
+
+        let mut bad = data.clone();
+        bad[epb + 4] += 1;
+        assert_eq!(problems(&bad), [Problem::InvalidBlockLength(49)]);
+        let mut bad = data.clone();
+        let len = bad.len();
+        bad[len - 4] = 12;
+        assert_eq!(
+            problems(&bad),
+            [Problem::BlockLengthMismatch {
+                leading: 48,
+                trailing: 12
+            }]
+        );
+        let mut bad = data;
+        bad[8] = 0;
+        assert_eq!(problems(&bad), [Problem::InvalidByteOrderMagic(0x3c2b1a)]);
+    }
+
+    #[test]
+    fn contents() {
+        let data = capture(Endianness::Little);
+        let epb = 56;
+        let mut bad = data.clone();
+        // packet padding and option padding
+        bad[epb + 28 + 5] = 1;
+        let len = bad.len();
+        bad[len - 5] = 1;
+        assert_eq!(problems(&bad), [Problem::NonZeroPadding]);
+        let mut bad = data.clone();
+        // comment length
+        bad[epb + 36 + 2] = 5;
+        assert_eq!(problems(&bad), [Problem::OptionOverrun { code: 1 }]);
+        let mut bad = data.clone();
+        // interface ID, and continue to the next block
+        bad[epb + 8] = 1;
+        bad.extend_from_slice(&data[epb..]);
+        let report = validate(&bad[..]).unwrap();
+        assert_eq!(report.blocks(), 4);
+        assert_eq!(problems(&bad), [Problem::UnknownInterface(1)]);
+        let mut bad = data;
+        // captured length
+        bad[epb + 20] = 17;
+        assert_eq!(problems(&bad), [Problem::CapLenExceedsBlock(17)]);
+    }
+
+    #[test]
+    fn end_of_opt() {
+        let mut data = capture(Endianness::Big);
+        // opt_endofopt followed by an option, in an IDB
+        let body = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0];
+        let mut writer = PcapNgWriter::new_be(&mut data);
+        let raw = RawBlock::new(1, 28, 28, &body);
+        writer.write(&raw).unwrap();
+        drop(writer);
+        assert_eq!(problems(&data), [Problem::EndOfOptNotLast]);
+    }
+}
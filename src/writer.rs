@@ -1,33 +1,729 @@
+use crate::blocks::options::{BlockOption, Options};
+use crate::blocks::{
+    EnhancedPacketBlock, InterfaceDescriptionBlock, SectionHeaderBlock, SimplePacketBlock,
+};
+#[cfg(feature = "std")]
+use crate::blocks::{InterfaceStatisticsBlock, NameResolutionBlock};
+use crate::enums::{BlockType, LinkType};
+use crate::error::{Error, Result};
+use crate::framing::BlockScanner;
+use crate::io::{self, Write};
+use crate::metrics::{Metrics, NoMetrics};
+#[cfg(feature = "std")]
+use crate::names::NameCache;
+#[cfg(feature = "std")]
+use crate::pipeline::{BlockFilter, Output, Pipeline};
+use crate::utils::{packet_len, Timestamp, TimestampResolution, DEFAULT_TSRES};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use byteorder::WriteBytesExt;
 use byteorder::{BigEndian, ByteOrder, LittleEndian};
-use std::io;
-use std::io::Write;
+#[cfg(feature = "std")]
+use std::any::Any;
+#[cfg(feature = "std")]
+use std::io::{IoSlice, Seek, SeekFrom};
+#[cfg(feature = "std")]
+use std::net::IpAddr;
+#[cfg(feature = "std")]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Represents the endiannes of data in a pcapng file
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Endianness {
     Big,
     Little,
 }
 
+impl Endianness {
+    /// Returns the byte order of the host.
+    pub fn native() -> Self {
+        if cfg!(target_endian = "big") {
+            Self::Big
+        } else {
+            Self::Little
+        }
+    }
+}
+
 /// A trait for encoding (serializing) data
 pub trait Encodable<W: Write> {
-    /// Serializes the object and appends it to the `std::io::Write`
+    /// Serializes the object and appends it to the `io::Write`
     /// provided
-    fn encode<B: ByteOrder>(&self, w: &mut W) -> io::Result<()>;
+    fn encode<B: ByteOrder>(&self, w: &mut W) -> Result<()>;
+
+    /// Checks that the object is consistent, e.g. that the lengths it
+    /// declares match its data. Writers that validate blocks call this
+    /// before writing, see `Validation`.
+    fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns the block type code if the object is a block.
+    fn block_type(&self) -> Option<u32> {
+        None
+    }
+
+    /// Returns the ID of the interface the object refers to, if any.
+    /// Writers that validate blocks check that the interface has been
+    /// described in the current section.
+    fn interface_id(&self) -> Option<u32> {
+        None
+    }
+}
+
+/// How thoroughly a `PcapNgWriter` checks what it writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Validation {
+    /// Write blocks as they are. Packets longer than the snap length
+    /// of their interface are truncated.
+    Permissive,
+    /// Write blocks as they are, but collect the problems found, see
+    /// `PcapNgWriter::warnings`.
+    Warn,
+    /// Refuse to write invalid blocks and packets longer than the
+    /// snap length.
+    Strict,
+}
+
+/// The number of bytes an object takes when encoded, e.g. to
+/// preallocate a buffer, enforce a quota, or decide whether a block
+/// still fits in a file before writing it.
+pub trait EncodedLen {
+    fn encoded_len(&self) -> usize;
+}
+
+/// Encoding into a preallocated byte slice, e.g. a buffer shared with
+/// another process. Implemented for everything that is `Encodable`.
+pub trait EncodeToSlice {
+    /// Serializes the object to the start of `buf` and returns the
+    /// number of bytes written. Returns `Error::BufferTooSmall` if the
+    /// encoded object does not fit, in which case the contents of
+    /// `buf` are unspecified.
+    fn encode_to_slice<B: ByteOrder>(&self, buf: &mut [u8]) -> Result<usize>;
+}
+
+impl<T> EncodeToSlice for T
+where
+    T: for<'a> Encodable<&'a mut [u8]>,
+{
+    fn encode_to_slice<B: ByteOrder>(&self, buf: &mut [u8]) -> Result<usize> {
+        let available = buf.len();
+        let mut rest = buf;
+        match self.encode::<B>(&mut rest) {
+            Ok(()) => Ok(available - rest.len()),
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::WriteZero => {
+                Err(Error::BufferTooSmall(available))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Serializes blocks into a reusable buffer.
+///
+/// Blocks encode their fields with many small writes. Encoding them
+/// into an `Encoder` first turns those into one `write_all` of the
+/// whole block, and the buffer is kept for the next block, so it is
+/// only allocated again when a block is larger than all the previous
+/// ones. `PcapNgWriter::encode_reuse` writes blocks this way.
+#[derive(Debug, Clone, Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an encoder whose buffer holds `capacity` bytes before
+    /// it grows.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buf: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Serializes `object`, replacing the previous contents of the
+    /// buffer, and returns the encoded bytes.
+    pub fn encode<T: Encodable<Vec<u8>>>(
+        &mut self,
+        endianness: Endianness,
+        object: &T,
+    ) -> Result<&[u8]> {
+        self.buf.clear();
+        match endianness {
+            Endianness::Little => object.encode::<LittleEndian>(&mut self.buf)?,
+            Endianness::Big => object.encode::<BigEndian>(&mut self.buf)?,
+        }
+        Ok(&self.buf)
+    }
+
+    /// Serializes `object` after the previous contents of the buffer,
+    /// e.g. to write several blocks at once.
+    pub fn append<T: Encodable<Vec<u8>>>(
+        &mut self,
+        endianness: Endianness,
+        object: &T,
+    ) -> Result<()> {
+        match endianness {
+            Endianness::Little => object.encode::<LittleEndian>(&mut self.buf),
+            Endianness::Big => object.encode::<BigEndian>(&mut self.buf),
+        }
+    }
+
+    /// Returns the contents of the buffer.
+    pub fn bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Empties the buffer, keeping its capacity.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    /// Returns the number of bytes the buffer holds before it grows.
+    pub fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+}
+
+/// A captured packet, independent of the block it is written as.
+///
+/// Capture sources produce records and `PcapNgWriter::write_record`
+/// or `write_packets` write them, as an Enhanced Packet Block, or as a
+/// Simple Packet Block when the record needs nothing more, see
+/// `write_record`.
+#[derive(Debug, Clone, Copy)]
+pub struct PacketRecord<'a> {
+    interface: InterfaceId,
+    timestamp_nanos: Option<u128>,
+    orig_len: Option<u32>,
+    data: &'a [u8],
+    options: Option<&'a Options<'a>>,
+}
+
+impl<'a> PacketRecord<'a> {
+    /// Creates a record of a packet captured on `interface`, at
+    /// `timestamp_nanos` nanoseconds since the Unix epoch.
+    pub fn new(interface: InterfaceId, timestamp_nanos: u128, data: &'a [u8]) -> Self {
+        Self {
+            interface,
+            timestamp_nanos: Some(timestamp_nanos),
+            orig_len: None,
+            data,
+            options: None,
+        }
+    }
+
+    /// Creates a record of a packet captured on `interface`, without
+    /// a timestamp, e.g. from a source that does not timestamp
+    /// packets.
+    pub fn untimed(interface: InterfaceId, data: &'a [u8]) -> Self {
+        Self {
+            interface,
+            timestamp_nanos: None,
+            orig_len: None,
+            data,
+            options: None,
+        }
+    }
+
+    /// Sets the original length of the packet on the wire, the length
+    /// of its data by default.
+    pub fn with_orig_len(mut self, orig_len: u32) -> Self {
+        self.orig_len = Some(orig_len);
+        self
+    }
+
+    /// Sets the options of the packet's block.
+    pub fn with_options(mut self, options: &'a Options<'a>) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    pub fn interface(&self) -> InterfaceId {
+        self.interface
+    }
+
+    pub fn timestamp_nanos(&self) -> Option<u128> {
+        self.timestamp_nanos
+    }
+
+    pub fn orig_len(&self) -> Option<u32> {
+        self.orig_len
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn options(&self) -> Option<&'a Options<'a>> {
+        self.options
+    }
+}
+
+/// The block a `PacketRecord` is written as.
+enum PacketBlock<'a> {
+    Enhanced(EnhancedPacketBlock<'a>),
+    Simple(SimplePacketBlock<'a>),
+}
+
+impl<W: Write> Encodable<W> for PacketBlock<'_> {
+    fn encode<B: ByteOrder>(&self, w: &mut W) -> Result<()> {
+        match self {
+            Self::Enhanced(epb) => epb.encode::<B>(w),
+            Self::Simple(spb) => spb.encode::<B>(w),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        match self {
+            Self::Enhanced(epb) => Encodable::<W>::validate(epb),
+            Self::Simple(spb) => Encodable::<W>::validate(spb),
+        }
+    }
+
+    fn block_type(&self) -> Option<u32> {
+        match self {
+            Self::Enhanced(epb) => Encodable::<W>::block_type(epb),
+            Self::Simple(spb) => Encodable::<W>::block_type(spb),
+        }
+    }
+
+    fn interface_id(&self) -> Option<u32> {
+        match self {
+            Self::Enhanced(epb) => Encodable::<W>::interface_id(epb),
+            Self::Simple(spb) => Encodable::<W>::interface_id(spb),
+        }
+    }
+}
+
+/// Counters of the data written by a `PcapNgWriter`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriterStats {
+    bytes: u64,
+    blocks: BTreeMap<u32, u64>,
+    packets: BTreeMap<u32, u64>,
+    filtered: u64,
+}
+
+impl WriterStats {
+    /// Returns the total number of bytes written.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns the number of blocks of the given type written.
+    pub fn blocks(&self, block_type: BlockType) -> u64 {
+        self.blocks
+            .get(&block_type.value())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns the total number of blocks written.
+    pub fn total_blocks(&self) -> u64 {
+        self.blocks.values().sum()
+    }
+
+    /// Returns the number of Enhanced and Simple Packet Blocks written
+    /// for the interface with the given ID. Interfaces of different
+    /// sections that share an ID are counted together.
+    pub fn packets(&self, interface_id: InterfaceId) -> u64 {
+        self.packets
+            .get(&interface_id.0)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns the total number of packets written.
+    pub fn total_packets(&self) -> u64 {
+        self.packets.values().sum()
+    }
+
+    /// Returns the number of blocks dropped by the block pipeline, see
+    /// `PcapNgWriter::add_filter`. They are not counted as written.
+    pub fn filtered_blocks(&self) -> u64 {
+        self.filtered
+    }
+
+    /// Counts a block, and returns whether it is a packet.
+    fn record_block(&mut self, block_type: u32, first_word: u32) -> bool {
+        *self.blocks.entry(block_type).or_default() += 1;
+        let interface_id = match block_type {
+            t if t == BlockType::EnhancedPacket.value() => first_word,
+            t if t == BlockType::SimplePacket.value() => 0,
+            _ => return false,
+        };
+        *self.packets.entry(interface_id).or_default() += 1;
+        true
+    }
+}
+
+/// A `Write` adapter that counts the bytes and blocks written through
+/// it.
+///
+/// `PcapNgWriter` encodes blocks into a `CountingWriter` around the
+/// writer it was created with, so it knows the offset of each block.
+/// Blocks are counted by their headers, so blocks that are not
+/// written in one piece are counted too. Counting blocks stops if the
+/// data is not a valid pcapng stream.
+#[derive(Debug)]
+pub struct CountingWriter<W: Write> {
+    inner: W,
+    stats: WriterStats,
+    scanner: Option<BlockScanner>,
+    metrics: Box<dyn Metrics>,
+    #[cfg(feature = "std")]
+    pipeline: Option<Pipeline>,
+}
+
+impl<W: Write> CountingWriter<W> {
+    /// Creates a `CountingWriter` for a stream that starts with a
+    /// Section Header Block.
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            stats: Default::default(),
+            scanner: Some(BlockScanner::new()),
+            metrics: Box::new(NoMetrics),
+            #[cfg(feature = "std")]
+            pipeline: None,
+        }
+    }
+
+    /// Creates a `CountingWriter` for a stream with the given
+    /// endianness, that may not start with a Section Header Block.
+    pub fn with_endianness(inner: W, endianness: Endianness) -> Self {
+        Self {
+            scanner: Some(BlockScanner::with_endianness(endianness)),
+            ..Self::new(inner)
+        }
+    }
+
+    /// Returns the number of bytes written so far.
+    pub fn count(&self) -> u64 {
+        self.stats.bytes
+    }
+
+    pub fn stats(&self) -> &WriterStats {
+        &self.stats
+    }
+
+    /// Sets the metrics told about the data written, see the
+    /// `metrics` module.
+    pub fn set_metrics<M: Metrics + 'static>(&mut self, metrics: M) {
+        self.metrics = Box::new(metrics);
+    }
+
+    pub fn metrics(&self) -> &dyn Metrics {
+        &*self.metrics
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        #[cfg(feature = "std")]
+        if self.pipeline.is_some() {
+            self.write_filtered(buf)?;
+            return Ok(buf.len());
+        }
+        let n = self.inner.write(buf)?;
+        self.record(&buf[..n]);
+        Ok(n)
+    }
+
+    #[cfg(feature = "std")]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        if self.pipeline.is_some() {
+            for buf in bufs {
+                self.write_filtered(buf)?;
+            }
+            return Ok(bufs.iter().map(|buf| buf.len()).sum());
+        }
+        let n = self.inner.write_vectored(bufs)?;
+        let mut left = n;
+        for buf in bufs {
+            let len = left.min(buf.len());
+            self.record(&buf[..len]);
+            left -= len;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // blocks held by the pipeline are written first
+        #[cfg(feature = "std")]
+        self.flush_pipeline()?;
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> CountingWriter<W> {
+    /// Adds a stage to the pipeline that the blocks go through before
+    /// they are written and counted.
+    pub(crate) fn add_filter<F>(&mut self, endianness: Endianness, filter: F)
+    where
+        F: BlockFilter + Send + 'static,
+    {
+        self.pipeline
+            .get_or_insert_with(|| Pipeline::new(endianness))
+            .add(filter);
+    }
+
+    fn filter<F: Any>(&self) -> Option<&F> {
+        self.pipeline.as_ref()?.get()
+    }
+
+    fn filter_mut<F: Any>(&mut self) -> Option<&mut F> {
+        self.pipeline.as_mut()?.get_mut()
+    }
+
+    fn write_filtered(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.run_pipeline(|pipeline, out| pipeline.process(buf, out))
+    }
+
+    /// Writes the blocks held by the stages of the pipeline.
+    fn flush_pipeline(&mut self) -> io::Result<()> {
+        self.run_pipeline(|pipeline, out| pipeline.flush(out))
+    }
+
+    /// Runs `f` with the pipeline and an output that writes and
+    /// counts blocks.
+    fn run_pipeline<F>(&mut self, f: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut Pipeline, &mut Output<'_>) -> io::Result<()>,
+    {
+        let mut pipeline = match self.pipeline.take() {
+            Some(pipeline) => pipeline,
+            None => return Ok(()),
+        };
+        let dropped = pipeline.dropped();
+        let result = f(&mut pipeline, &mut |block| {
+            self.inner.write_all(block)?;
+            self.record(block);
+            Ok(())
+        });
+        let dropped = pipeline.dropped() - dropped;
+        if dropped > 0 {
+            self.stats.filtered += dropped;
+            self.metrics.dropped(dropped);
+        }
+        self.pipeline = Some(pipeline);
+        result
+    }
+}
+
+impl<W: Write> CountingWriter<W> {
+    fn record(&mut self, data: &[u8]) {
+        self.stats.bytes += data.len() as u64;
+        if !data.is_empty() {
+            self.metrics.bytes_written(data.len() as u64);
+        }
+        let mut rest = data;
+        while let Some(scanner) = self.scanner.as_mut() {
+            match scanner.next_boundary(rest) {
+                Ok(Some(end)) => {
+                    let (block_type, first_word) = scanner.block();
+                    if self.stats.record_block(block_type, first_word) {
+                        self.metrics.packets_written(1);
+                    }
+                    rest = &rest[end..];
+                }
+                Ok(None) => break,
+                Err(_) => self.scanner = None,
+            }
+        }
+    }
+}
+
+/// Identifies an interface described by an Interface Description
+/// Block. Packets refer to their interface by this ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InterfaceId(u32);
+
+impl InterfaceId {
+    pub fn value(&self) -> u32 {
+        self.0
+    }
+}
+
+/// An interface registered with a `PcapNgWriter`.
+#[derive(Debug)]
+pub struct InterfaceInfo {
+    id: InterfaceId,
+    link_type: u16,
+    snap_len: u32,
+    tsresol: TimestampResolution,
+}
+
+impl InterfaceInfo {
+    pub fn id(&self) -> InterfaceId {
+        self.id
+    }
+
+    pub fn link_type(&self) -> u16 {
+        self.link_type
+    }
+
+    pub fn snap_len(&self) -> u32 {
+        self.snap_len
+    }
+
+    /// Returns the timestamp resolution set by the interface's
+    /// `if_tsresol` option, or the default of 10^-6 if the option is
+    /// absent.
+    pub fn tsresol(&self) -> &TimestampResolution {
+        &self.tsresol
+    }
 }
 
 /// The `PcapNgWriter` manages serialization of data with the
 /// speicified endiannes.
+///
+/// The writer keeps a registry of the interfaces added with
+/// `add_interface`, so the interface IDs used by packets don't have
+/// to be tracked by hand. Interface Description Blocks written with
+/// `write` are not registered, but they take up their ID, so the IDs
+/// of registered interfaces always match the blocks in the section.
+/// A Section Header Block, however it is written, clears the
+/// registry.
+///
+/// Writers created with `new_auto_section` write a Section Header
+/// Block themselves, so the output always starts with one.
+///
+/// Packets written with `write_packet` that are longer than the snap
+/// length of their interface are truncated, keeping their original
+/// length. `set_slice_len` truncates them further, e.g. to only keep
+/// their headers.
+///
+/// Unless the validation level is `Validation::Permissive` (the
+/// default), blocks passed to `write` are checked before they are
+/// written: the stream must start with a Section Header Block, packet
+/// and statistics blocks must refer to an interface described by an
+/// Interface Description Block in the current section (whether it was
+/// written with `add_interface` or `write`), and `Encodable::validate`
+/// must succeed, which checks e.g. the order of options and the
+/// lengths of raw blocks.
+///
+/// Each section may use a different endianness, see
+/// `start_new_section_with_endianness`.
+///
+/// If the underlying writer implements `Seek`, `finalize_section` can
+/// fill in the Section Length of sections started by the writer.
+///
+/// The writer keeps track of the number of bytes it has written, so
+/// `position` and `last_block_offset` can be used to build an index
+/// of the blocks in the output.
+///
+/// Names of addresses added with `add_name` are written in Name
+/// Resolution Blocks, see the `names` module.
+///
+/// `close` finishes the capture: it writes the pending names, can
+/// write a final Interface Statistics Block for each interface and
+/// fill in the Section Length, then flushes the underlying writer.
+/// Dropping the writer closes it too, ignoring errors, so a capture
+/// that ends abruptly is still as complete as possible.
 #[derive(Debug)]
 pub struct PcapNgWriter<W: Write> {
     endianness: Endianness,
-    writer: W,
+    writer: CountingWriter<W>,
+    encoder: Encoder,
+    interfaces: Vec<InterfaceInfo>,
+    auto_section: bool,
+    validation: Validation,
+    warnings: Vec<Error>,
+    sections: u32,
+    described_interfaces: u32,
+    shb_offset: Option<u64>,
+    section_body_offset: u64,
+    last_block_offset: Option<u64>,
+    closed: bool,
+    slice_len: Option<u32>,
+    #[cfg(feature = "std")]
+    final_statistics: bool,
+    #[cfg(feature = "std")]
+    names: NameCache,
+    #[cfg(feature = "std")]
+    names_interval: Option<Duration>,
+    #[cfg(feature = "std")]
+    names_written: SystemTime,
+    #[cfg(feature = "std")]
+    clock: fn() -> SystemTime,
+    patch_section: Option<fn(&mut Self) -> Result<()>>,
 }
 
 impl<W: Write> PcapNgWriter<W> {
     /// Creates a new pcapng writer.
     pub fn new(endianness: Endianness, writer: W) -> Self {
-        Self { endianness, writer }
+        Self {
+            endianness,
+            writer: CountingWriter::with_endianness(writer, endianness),
+            encoder: Encoder::new(),
+            interfaces: Vec::new(),
+            auto_section: false,
+            validation: Validation::Permissive,
+            warnings: Vec::new(),
+            sections: 0,
+            described_interfaces: 0,
+            shb_offset: None,
+            section_body_offset: 0,
+            last_block_offset: None,
+            closed: false,
+            slice_len: None,
+            #[cfg(feature = "std")]
+            final_statistics: false,
+            #[cfg(feature = "std")]
+            names: NameCache::new(),
+            #[cfg(feature = "std")]
+            names_interval: None,
+            #[cfg(feature = "std")]
+            names_written: SystemTime::now(),
+            #[cfg(feature = "std")]
+            clock: SystemTime::now,
+            patch_section: None,
+        }
+    }
+
+    /// Creates a new pcapng writer that writes a Section Header Block
+    /// with default values and no options before the first block.
+    /// Use `start_new_section` to write a header with options.
+    ///
+    /// Section Header Blocks should not be passed to `write` in this
+    /// mode.
+    pub fn new_auto_section(endianness: Endianness, writer: W) -> Self {
+        let mut pcapng_writer = Self::new(endianness, writer);
+        pcapng_writer.auto_section = true;
+        pcapng_writer
+    }
+
+    /// Creates a new pcapng writer whose output only depends on the
+    /// blocks written, for golden-file tests: it is little-endian,
+    /// writes a Section Header Block without options like
+    /// `new_auto_section`, and its clock is stopped at the Unix epoch,
+    /// so the timestamps it makes up itself are zero. See `set_clock`.
+    pub fn new_deterministic(writer: W) -> Self {
+        #[allow(unused_mut)]
+        let mut pcapng_writer = Self::new_auto_section(Endianness::Little, writer);
+        #[cfg(feature = "std")]
+        pcapng_writer.set_clock(|| UNIX_EPOCH);
+        pcapng_writer
     }
 
     /// Creates a new little-endian pcapng writer.
@@ -40,39 +736,749 @@ impl<W: Write> PcapNgWriter<W> {
         Self::new(Endianness::Big, writer)
     }
 
+    /// Creates a new pcapng writer with the byte order of the host.
+    pub fn new_native(writer: W) -> Self {
+        Self::new(Endianness::native(), writer)
+    }
+
+    pub fn validation(&self) -> Validation {
+        self.validation
+    }
+
+    pub fn set_validation(&mut self, validation: Validation) {
+        self.validation = validation;
+    }
+
+    /// Returns the problems found in `Validation::Warn` mode.
+    pub fn warnings(&self) -> &[Error] {
+        &self.warnings
+    }
+
+    /// Returns the problems found in `Validation::Warn` mode and
+    /// clears them.
+    pub fn take_warnings(&mut self) -> Vec<Error> {
+        core::mem::take(&mut self.warnings)
+    }
+
+    /// Returns the endianness of the current section.
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    pub fn slice_len(&self) -> Option<u32> {
+        self.slice_len
+    }
+
+    /// Sets the number of bytes of each packet written with
+    /// `write_packet` and the like that are kept, or keeps whole
+    /// packets with `None`. Sliced packets keep their original length.
+    /// Unlike packets longer than the snap length, they are not
+    /// reported by validation.
+    pub fn set_slice_len(&mut self, slice_len: Option<u32>) {
+        self.slice_len = slice_len;
+    }
+
     /// Serializes and writes a block to the underlying "write".
-    pub fn write<T: Encodable<W>>(&mut self, block: &T) -> io::Result<()> {
-        match self.endianness {
-            Endianness::Little => block.encode::<LittleEndian>(self.get_writer_mut()),
-            Endianness::Big => block.encode::<BigEndian>(self.get_writer_mut()),
+    pub fn write<T: Encodable<CountingWriter<W>>>(&mut self, block: &T) -> Result<()> {
+        self.prepare::<CountingWriter<W>, _>(block)?;
+        self.encode(block)
+    }
+
+    /// Like `write`, but serializes the block into a buffer that is
+    /// reused for all the blocks, and writes it to the underlying
+    /// writer at once, see `Encoder`.
+    pub fn encode_reuse<T: Encodable<Vec<u8>>>(&mut self, block: &T) -> Result<()> {
+        self.prepare::<Vec<u8>, _>(block)?;
+        let mut encoder = core::mem::take(&mut self.encoder);
+        let result = match encoder.encode(self.endianness, block) {
+            Ok(bytes) => self.encode_with(|w, _| Ok(w.write_all(bytes)?)),
+            Err(e) => Err(e),
+        };
+        self.encoder = encoder;
+        result
+    }
+
+    /// Starts the first section if needed, and validates `block`.
+    fn prepare<V: Write, T: Encodable<V>>(&mut self, block: &T) -> Result<()> {
+        if self.auto_section && self.sections == 0 {
+            self.start_new_section(&Options::new())?;
+        }
+        if self.validation != Validation::Permissive {
+            if let Err(e) = self.check(block) {
+                self.report(e)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check<V: Write, T: Encodable<V>>(&self, block: &T) -> Result<()> {
+        let is_shb = block.block_type() == Some(BlockType::SectionHeader.value());
+        if !is_shb && self.writer.stats().blocks(BlockType::SectionHeader) == 0 {
+            return Err(Error::MissingSectionHeader);
+        }
+        match block.interface_id() {
+            Some(id) if id >= self.described_interfaces => Err(Error::UnknownInterface(id)),
+            _ => block.validate(),
         }
     }
 
+    /// Fails in strict mode, or records the problem in warn mode.
+    fn report(&mut self, e: Error) -> Result<()> {
+        match self.validation {
+            Validation::Strict => {
+                self.writer.metrics().errors(1);
+                Err(e)
+            }
+            Validation::Warn => {
+                self.warnings.push(e);
+                Ok(())
+            }
+            Validation::Permissive => Ok(()),
+        }
+    }
+
+    /// Writes a Section Header Block with default values and the
+    /// given options. The interface registry is cleared, as interface
+    /// IDs are scoped to a section.
+    pub fn start_new_section(&mut self, options: &Options) -> Result<()> {
+        let shb = SectionHeaderBlock::new_with_defaults(options);
+        // the blocks of the previous section go before it
+        #[cfg(feature = "std")]
+        self.writer.flush_pipeline()?;
+        let shb_offset = self.writer.count();
+        self.encode(&shb)?;
+        self.shb_offset = Some(shb_offset);
+        self.section_body_offset = self.writer.count();
+        self.sections += 1;
+        // interface IDs and names are scoped to a section, even if a
+        // block filter held the header back
+        self.interfaces.clear();
+        #[cfg(feature = "std")]
+        self.names.reset();
+        Ok(())
+    }
+
+    /// Like `start_new_section`, but switches to `endianness` first.
+    /// The new section and all the blocks after it are encoded with
+    /// this endianness.
+    pub fn start_new_section_with_endianness(
+        &mut self,
+        endianness: Endianness,
+        options: &Options,
+    ) -> Result<()> {
+        self.endianness = endianness;
+        self.start_new_section(options)
+    }
+
+    /// Returns the number of sections started by this writer, either
+    /// with `start_new_section` or automatically. Section Header
+    /// Blocks passed to `write` are not counted.
+    pub fn section_count(&self) -> u32 {
+        self.sections
+    }
+
+    fn encode<T: Encodable<CountingWriter<W>>>(&mut self, block: &T) -> Result<()> {
+        self.encode_with(|w, endianness| match endianness {
+            Endianness::Little => block.encode::<LittleEndian>(w),
+            Endianness::Big => block.encode::<BigEndian>(w),
+        })
+    }
+
+    /// Writes a block with `write`, keeping track of its offset and
+    /// of the interfaces described.
+    fn encode_with<F>(&mut self, write: F) -> Result<()>
+    where
+        F: FnOnce(&mut CountingWriter<W>, Endianness) -> Result<()>,
+    {
+        self.closed = false;
+        self.last_block_offset = Some(self.writer.count());
+        let shbs = self.writer.stats().blocks(BlockType::SectionHeader);
+        let idbs = self.writer.stats().blocks(BlockType::InterfaceDescription);
+        let result = write(&mut self.writer, self.endianness);
+        // keep track of the interfaces described in the current
+        // section, however their blocks were written
+        let stats = self.writer.stats();
+        if stats.blocks(BlockType::SectionHeader) > shbs {
+            // `start_new_section` sets it again for its own headers
+            self.shb_offset = None;
+            self.described_interfaces = 0;
+            self.interfaces.clear();
+            #[cfg(feature = "std")]
+            self.names.reset();
+        }
+        self.described_interfaces += (stats.blocks(BlockType::InterfaceDescription) - idbs) as u32;
+        if result.is_err() {
+            self.writer.metrics().errors(1);
+        }
+        result
+    }
+
+    /// Writes an Interface Description Block and registers the
+    /// interface. Returns the ID to be used by packets captured on
+    /// this interface.
+    pub fn add_interface(
+        &mut self,
+        link_type: LinkType,
+        snap_len: u32,
+        options: &Options,
+    ) -> Result<InterfaceId> {
+        let idb = InterfaceDescriptionBlock::new(link_type, snap_len, options);
+        self.add_interface_block(&idb)
+    }
+
+    /// Writes an already constructed Interface Description Block and
+    /// registers the interface. Its ID is its index among the
+    /// Interface Description Blocks of the section.
+    pub fn add_interface_block(&mut self, idb: &InterfaceDescriptionBlock) -> Result<InterfaceId> {
+        self.write(idb)?;
+        let id = InterfaceId(self.described_interfaces.saturating_sub(1));
+        let tsresol = idb
+            .options()
+            .iter()
+            .find_map(|opt| match opt {
+                BlockOption::IfTsResol(o) => Some(o.resolution()),
+                _ => None,
+            })
+            .unwrap_or(*DEFAULT_TSRES);
+        self.interfaces.push(InterfaceInfo {
+            id,
+            link_type: idb.link_type(),
+            snap_len: idb.snap_len(),
+            tsresol,
+        });
+        Ok(id)
+    }
+
+    /// Writes an Enhanced Packet Block for a packet captured on a
+    /// registered interface. `timestamp_nanos` is the number of
+    /// nanoseconds since the Unix epoch, and is converted to the
+    /// interface's timestamp resolution.
+    pub fn write_packet(
+        &mut self,
+        interface_id: InterfaceId,
+        timestamp_nanos: u128,
+        data: &[u8],
+    ) -> Result<()> {
+        let orig_len = packet_len(data)?;
+        self.write_packet_with_options(
+            interface_id,
+            timestamp_nanos,
+            orig_len,
+            data,
+            &Options::new(),
+        )
+    }
+
+    /// Like `write_packet`, but also takes the original length of the
+    /// packet on the wire and the options of the block. Truncating
+    /// the packet to the snap length does not change `orig_len`.
+    pub fn write_packet_with_options(
+        &mut self,
+        interface_id: InterfaceId,
+        timestamp_nanos: u128,
+        orig_len: u32,
+        data: &[u8],
+        options: &Options,
+    ) -> Result<()> {
+        let info = self
+            .interface(interface_id)
+            .ok_or_else(|| Error::UnknownInterface(interface_id.value()))?;
+        let timestamp = Timestamp::from_nanoseconds(info.tsresol(), timestamp_nanos)?;
+        self.write_packet_with_ticks(interface_id, timestamp, orig_len, data, options)
+    }
+
+    /// Writes an Enhanced Packet Block for a packet that `build`
+    /// writes to a buffer. Packet builders that write to an
+    /// `io::Write` can be used without an intermediate buffer of their
+    /// own.
+    ///
+    /// There is no `etherparse` feature, as the crate is not a
+    /// dependency. An `etherparse::PacketBuilder` is used the same way,
+    /// with `|buf| builder.write(buf, &payload)`; the example below
+    /// uses a builder of its own instead:
+    ///
+    /// ```no_run
+    /// # use pcapng_writer::blocks::options::Options;
+    /// # use pcapng_writer::enums::LinkType;
+    /// # use pcapng_writer::writer::{Endianness, PcapNgWriter};
+    /// use std::io::{self, Write};
+    ///
+    /// /// Prepends an Ethernet header to a payload.
+    /// struct EthernetBuilder {
+    ///     ether_type: u16,
+    /// }
+    ///
+    /// impl EthernetBuilder {
+    ///     fn write<W: Write>(&self, w: &mut W, payload: &[u8]) -> io::Result<()> {
+    ///         w.write_all(&[0xff; 12])?;
+    ///         w.write_all(&self.ether_type.to_be_bytes())?;
+    ///         w.write_all(payload)
+    ///     }
+    /// }
+    ///
+    /// # fn main() -> pcapng_writer::Result<()> {
+    /// let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+    /// let eth = writer.add_interface(LinkType::Ethernet, 0, &Options::new())?;
+    /// let builder = EthernetBuilder { ether_type: 0x0800 };
+    /// writer.write_packet_with(eth, 1_000, |buf| builder.write(buf, &[0; 46]))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// An error returned by `build` is returned as an `Error::Io`.
+    #[cfg(feature = "std")]
+    pub fn write_packet_with<F, E>(
+        &mut self,
+        interface_id: InterfaceId,
+        timestamp_nanos: u128,
+        build: F,
+    ) -> Result<()>
+    where
+        F: FnOnce(&mut Vec<u8>) -> core::result::Result<(), E>,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let mut data = Vec::new();
+        build(&mut data).map_err(io::Error::other)?;
+        self.write_packet(interface_id, timestamp_nanos, &data)
+    }
+
+    /// Like `write_packet_with_options`, but takes the timestamp in
+    /// ticks of the interface's timestamp resolution, for sources that
+    /// already count in those units.
+    pub fn write_packet_with_ticks(
+        &mut self,
+        interface_id: InterfaceId,
+        timestamp: Timestamp,
+        orig_len: u32,
+        data: &[u8],
+        options: &Options,
+    ) -> Result<()> {
+        let data = self.truncate(interface_id, data)?;
+        let epb = EnhancedPacketBlock::new(
+            interface_id.value(),
+            timestamp.high(),
+            timestamp.low(),
+            packet_len(data)?,
+            orig_len,
+            data,
+            options,
+        );
+        self.write(&epb)?;
+        #[cfg(feature = "std")]
+        self.write_names_if_due()?;
+        Ok(())
+    }
+
+    /// Writes a packet record. The record is written as a Simple
+    /// Packet Block if it has no timestamp and no options, it was
+    /// captured on the first interface, and its data is its original
+    /// length truncated to the snap length, which is all a Simple
+    /// Packet Block holds. Otherwise it is written as an Enhanced
+    /// Packet Block, with a zero timestamp if it has none.
+    ///
+    /// ```
+    /// use pcapng_writer::enums::LinkType;
+    /// use pcapng_writer::writer::{Endianness, PacketRecord, PcapNgWriter};
+    ///
+    /// let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+    /// let eth = writer.add_interface(LinkType::Ethernet, 65535, &Default::default())?;
+    /// writer.write_record(PacketRecord::new(eth, 1_000, &[0; 60]))?;
+    /// writer.write_record(PacketRecord::untimed(eth, &[0; 60]))?;
+    /// # Ok::<(), pcapng_writer::error::Error>(())
+    /// ```
+    pub fn write_record(&mut self, record: PacketRecord) -> Result<()> {
+        let no_options = Options::new();
+        let block = self.packet_block(&record, &no_options)?;
+        self.write(&block)?;
+        #[cfg(feature = "std")]
+        self.write_names_if_due()?;
+        Ok(())
+    }
+
+    /// Writes a batch of packet records, like `write_record`, but
+    /// encodes them into one buffer and writes it at once, see
+    /// `Encoder`. If a packet can't be
+    /// written, e.g. because its interface is unknown, none of the
+    /// batch is written, and no warnings are recorded for it.
+    ///
+    /// ```
+    /// use pcapng_writer::enums::LinkType;
+    /// use pcapng_writer::writer::{Endianness, PacketRecord, PcapNgWriter};
+    ///
+    /// let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+    /// let eth = writer.add_interface(LinkType::Ethernet, 65535, &Default::default())?;
+    /// let packets: Vec<(u128, Vec<u8>)> = vec![(1_000, vec![0; 60]), (2_000, vec![1; 60])];
+    /// writer.write_packets(
+    ///     packets
+    ///         .iter()
+    ///         .map(|(timestamp, data)| PacketRecord::new(eth, *timestamp, data)),
+    /// )?;
+    /// assert_eq!(writer.stats().total_packets(), 2);
+    /// # Ok::<(), pcapng_writer::error::Error>(())
+    /// ```
+    pub fn write_packets<'a, I>(&mut self, packets: I) -> Result<()>
+    where
+        I: IntoIterator<Item = PacketRecord<'a>>,
+    {
+        let warnings = self.warnings.len();
+        let mut encoder = core::mem::take(&mut self.encoder);
+        encoder.clear();
+        let result = self.encode_packets(&mut encoder, packets);
+        let result = match result {
+            Ok(Some(last)) => {
+                let start = self.writer.count();
+                let result = self.encode_with(|w, _| Ok(w.write_all(encoder.bytes())?));
+                self.last_block_offset = Some(start + last as u64);
+                result
+            }
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+        self.encoder = encoder;
+        if result.is_err() {
+            // the warnings are about blocks that were not written
+            self.warnings.truncate(warnings);
+        }
+        result?;
+        #[cfg(feature = "std")]
+        self.write_names_if_due()?;
+        Ok(())
+    }
+
+    /// Encodes the blocks of `packets` into `encoder`, and returns the
+    /// offset of the last one in the buffer, if any.
+    fn encode_packets<'a, I>(&mut self, encoder: &mut Encoder, packets: I) -> Result<Option<usize>>
+    where
+        I: IntoIterator<Item = PacketRecord<'a>>,
+    {
+        let no_options = Options::new();
+        let mut last = None;
+        for packet in packets {
+            let block = self.packet_block(&packet, &no_options)?;
+            self.prepare::<Vec<u8>, _>(&block)?;
+            last = Some(encoder.bytes().len());
+            encoder.append(self.endianness, &block)?;
+        }
+        Ok(last)
+    }
+
+    /// Returns the block of a packet record, see `write_record`.
+    fn packet_block<'r>(
+        &mut self,
+        record: &PacketRecord<'r>,
+        no_options: &'r Options<'r>,
+    ) -> Result<PacketBlock<'r>> {
+        let id = record.interface;
+        let info = self
+            .interface(id)
+            .ok_or_else(|| Error::UnknownInterface(id.value()))?;
+        let (tsresol, snap_len) = (*info.tsresol(), info.snap_len());
+        let orig_len = match record.orig_len {
+            Some(orig_len) => orig_len,
+            None => packet_len(record.data)?,
+        };
+        let data = self.truncate(id, record.data)?;
+        let cap_len = packet_len(data)?;
+        let options = record.options.unwrap_or(no_options);
+        let timestamp_nanos = match record.timestamp_nanos {
+            Some(nanos) => nanos,
+            None => {
+                let snapped = match snap_len {
+                    0 => orig_len,
+                    snap_len => orig_len.min(snap_len),
+                };
+                if id.value() == 0 && options.iter().next().is_none() && cap_len == snapped {
+                    return Ok(PacketBlock::Simple(SimplePacketBlock::new(orig_len, data)));
+                }
+                0
+            }
+        };
+        let timestamp = Timestamp::from_nanoseconds(&tsresol, timestamp_nanos)?;
+        Ok(PacketBlock::Enhanced(EnhancedPacketBlock::new(
+            id.value(),
+            timestamp.high(),
+            timestamp.low(),
+            cap_len,
+            orig_len,
+            data,
+            options,
+        )))
+    }
+
+    /// Truncates the data of a packet to the snap length of its
+    /// interface, and to the slice length. Packets longer than the
+    /// snap length are reported by validation.
+    fn truncate<'d>(&mut self, interface_id: InterfaceId, data: &'d [u8]) -> Result<&'d [u8]> {
+        let info = self
+            .interface(interface_id)
+            .ok_or_else(|| Error::UnknownInterface(interface_id.value()))?;
+        let snap_len = info.snap_len() as usize;
+        let data = if snap_len != 0 && data.len() > snap_len {
+            self.report(Error::SnapLenExceeded(data.len()))?;
+            &data[..snap_len]
+        } else {
+            data
+        };
+        Ok(match self.slice_len {
+            Some(len) if data.len() > len as usize => &data[..len as usize],
+            _ => data,
+        })
+    }
+
+    /// Returns the registered interface with the given ID.
+    pub fn interface(&self, id: InterfaceId) -> Option<&InterfaceInfo> {
+        self.interfaces.iter().find(|info| info.id == id)
+    }
+
+    /// Returns all the registered interfaces, in the order of their
+    /// IDs.
+    pub fn interfaces(&self) -> &[InterfaceInfo] {
+        &self.interfaces
+    }
+
+    /// Returns the number of bytes written so far, i.e. the offset at
+    /// which the next block starts. Offsets are relative to the
+    /// position of the underlying writer when the `PcapNgWriter` was
+    /// created.
+    pub fn position(&self) -> u64 {
+        self.writer.count()
+    }
+
+    /// Returns the offset of the last block written, including blocks
+    /// written automatically, e.g. the Section Header Block in auto
+    /// section mode.
+    pub fn last_block_offset(&self) -> Option<u64> {
+        self.last_block_offset
+    }
+
+    /// Returns the counters of the data written so far. Data written
+    /// directly to the underlying writer is not counted.
+    pub fn stats(&self) -> &WriterStats {
+        self.writer.stats()
+    }
+
+    /// Sets the metrics told about the packets and bytes written, the
+    /// blocks dropped and the errors, see the `metrics` module.
+    pub fn set_metrics<M: Metrics + 'static>(&mut self, metrics: M) {
+        self.writer.set_metrics(metrics);
+    }
+
+    /// Returns the metrics set with `set_metrics`, e.g. for wrappers
+    /// that drop packets themselves.
+    pub fn metrics(&self) -> &dyn Metrics {
+        self.writer.metrics()
+    }
+
+    /// Adds a stage to the block pipeline of the writer, see the
+    /// `pipeline` module. Blocks go through the stages in the order
+    /// they were added, before they are written and counted in
+    /// `stats`.
+    #[cfg(feature = "std")]
+    pub fn add_filter<F: BlockFilter + Send + 'static>(&mut self, filter: F) {
+        self.writer.add_filter(self.endianness, filter);
+    }
+
+    /// Returns the first stage of the block pipeline of type `F`,
+    /// e.g. to read its counters.
+    #[cfg(feature = "std")]
+    pub fn filter<F: BlockFilter + 'static>(&self) -> Option<&F> {
+        self.writer.filter()
+    }
+
+    /// Returns the first stage of the block pipeline of type `F`, e.g.
+    /// to change its settings.
+    #[cfg(feature = "std")]
+    pub fn filter_mut<F: BlockFilter + 'static>(&mut self) -> Option<&mut F> {
+        self.writer.filter_mut()
+    }
+
+    /// Adds a name of `addr` to the name cache. New names are written
+    /// in a Name Resolution Block by `flush_names`, `close`, or once
+    /// the interval set with `set_names_interval` has passed.
+    #[cfg(feature = "std")]
+    pub fn add_name(&mut self, addr: IpAddr, name: &str) -> Result<()> {
+        self.names.add(addr, name)?;
+        self.write_names_if_due()
+    }
+
+    /// Sets how often new names are written: when a name is added or
+    /// a packet is written at least `interval` after the last Name
+    /// Resolution Block. If `interval` is `None`, the default, they
+    /// are only written by `flush_names` and `close`.
+    #[cfg(feature = "std")]
+    pub fn set_names_interval(&mut self, interval: Option<Duration>) {
+        self.names_interval = interval;
+    }
+
+    /// Writes the names that have not been written in the current
+    /// section yet, if any, in a Name Resolution Block.
+    #[cfg(feature = "std")]
+    pub fn flush_names(&mut self) -> Result<()> {
+        self.names_written = self.now();
+        let records = self.names.take_records();
+        if records.is_empty() {
+            return Ok(());
+        }
+        self.write(&NameResolutionBlock::new(&records, &Options::new()))
+    }
+
+    /// Sets the clock of the writer, `SystemTime::now` by default. It
+    /// timestamps the Interface Statistics Blocks of
+    /// `set_final_statistics`, and times `set_names_interval`.
+    #[cfg(feature = "std")]
+    pub fn set_clock(&mut self, clock: fn() -> SystemTime) {
+        self.clock = clock;
+        self.names_written = clock();
+    }
+
+    /// Returns the current time of the writer's clock.
+    #[cfg(feature = "std")]
+    pub fn now(&self) -> SystemTime {
+        (self.clock)()
+    }
+
+    /// Returns the names added with `add_name`.
+    #[cfg(feature = "std")]
+    pub fn name_cache(&self) -> &NameCache {
+        &self.names
+    }
+
+    #[cfg(feature = "std")]
+    fn write_names_if_due(&mut self) -> Result<()> {
+        let due = match self.names_interval {
+            Some(interval) => {
+                self.names.pending() > 0
+                    && self
+                        .now()
+                        .duration_since(self.names_written)
+                        .is_ok_and(|elapsed| elapsed >= interval)
+            }
+            None => false,
+        };
+        if due {
+            self.flush_names()?;
+        }
+        Ok(())
+    }
+
+    /// Sets whether `close` writes an Interface Statistics Block,
+    /// timestamped with the current time of the clock, for each registered
+    /// interface.
+    #[cfg(feature = "std")]
+    pub fn set_final_statistics(&mut self, enabled: bool) {
+        self.final_statistics = enabled;
+    }
+
+    /// Finishes the capture and flushes the underlying writer. Writing
+    /// more blocks afterwards is allowed; the writer is then closed
+    /// again when it is dropped.
+    pub fn close(&mut self) -> Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        let result = self.finish_capture();
+        self.closed = true;
+        result
+    }
+
+    /// Returns whether the writer was closed, and nothing was written
+    /// since.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    fn finish_capture(&mut self) -> Result<()> {
+        #[cfg(feature = "std")]
+        self.flush_names()?;
+        #[cfg(feature = "std")]
+        if self.final_statistics {
+            let now = self.now();
+            let options = Options::new();
+            for i in 0..self.interfaces.len() {
+                let info = &self.interfaces[i];
+                let (ts_high, ts_low) = info.tsresol.ts_from_systemtime(now)?;
+                let isb = InterfaceStatisticsBlock::new(info.id.0, ts_high, ts_low, &options);
+                self.write(&isb)?;
+            }
+        }
+        if let Some(patch) = self.patch_section {
+            patch(self)?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
     /// Returns an immutable reference to the underlying writer.
     pub fn get_writer(&self) -> &W {
-        &self.writer
+        self.writer.get_ref()
     }
 
     /// Returns a mutable reference to the underlying writer.
     pub fn get_writer_mut(&mut self) -> &mut W {
-        &mut self.writer
+        self.writer.get_mut()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write + Seek> PcapNgWriter<W> {
+    /// Rewrites the Section Length field of the current Section
+    /// Header Block with the number of bytes written after it, so
+    /// readers can skip the section. Call this after the last block of
+    /// the section, e.g. before `start_new_section`.
+    ///
+    /// Only sections started by the writer (with `start_new_section`
+    /// or automatically) are patched; otherwise this does nothing.
+    /// Blocks held by the pipeline are written first.
+    /// Data written directly to the underlying writer is not counted.
+    pub fn finalize_section(&mut self) -> Result<()> {
+        let shb_offset = match self.shb_offset {
+            Some(offset) => offset,
+            None => return Ok(()),
+        };
+        // blocks held by the pipeline belong to the section
+        self.writer.flush_pipeline()?;
+        let end = self.writer.count();
+        let section_length = end - self.section_body_offset;
+        // the Section Length field follows Block Type, Block Total
+        // Length, Byte-Order Magic and the two version fields
+        let back = (end - shb_offset - 16) as i64;
+        let inner = self.writer.get_mut();
+        inner.seek(SeekFrom::Current(-back))?;
+        match self.endianness {
+            Endianness::Little => inner.write_u64::<LittleEndian>(section_length)?,
+            Endianness::Big => inner.write_u64::<BigEndian>(section_length)?,
+        }
+        inner.seek(SeekFrom::Current(back - 8))?;
+        Ok(())
+    }
+
+    /// Sets whether `close` calls `finalize_section`.
+    pub fn set_finalize_on_close(&mut self, enabled: bool) {
+        self.patch_section = if enabled {
+            Some(Self::finalize_section)
+        } else {
+            None
+        };
+    }
+}
+
+impl<W: Write> Drop for PcapNgWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.close();
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::blocks::options::Options;
+    use crate::blocks::options::{OptionComment, OptionError, Options};
     use crate::blocks::EnhancedPacketBlock;
     use crate::blocks::InterfaceDescriptionBlock;
     use crate::blocks::SectionHeaderBlock;
     use crate::enums;
+    use crate::reader::{Block, SliceReader};
     use crate::writer::PcapNgWriter;
-    use nom::IResult;
-    use pcapng;
     use std::collections::HashMap;
-    use std::fs::File;
-    use std::path::Path;
 
     #[test]
     fn new_le() {
@@ -88,6 +1494,824 @@ mod tests {
         assert_eq!(writer.endianness, Endianness::Big);
     }
 
+    #[test]
+    fn add_interface() {
+        let opts = Options::new();
+        let mut buf = vec![];
+        let mut writer = PcapNgWriter::new_le(&mut buf);
+        let eth = writer
+            .add_interface(enums::LinkType::Ethernet, 1500, &opts)
+            .unwrap();
+        let idb = InterfaceDescriptionBlock::new(enums::LinkType::Raw, 65535, &opts);
+        let raw = writer.add_interface_block(&idb).unwrap();
+        assert_eq!(eth.value(), 0);
+        assert_eq!(raw.value(), 1);
+        assert_eq!(writer.interfaces().len(), 2);
+        assert_eq!(writer.interface(raw).unwrap().link_type(), 101);
+        assert_eq!(writer.interface(raw).unwrap().snap_len(), 65535);
+        assert_eq!(
+            writer.interface(raw).unwrap().tsresol(),
+            &TimestampResolution::PowerOfTen(6)
+        );
+        drop(writer);
+        assert_eq!(buf.len(), 2 * 20);
+        assert_eq!(&buf[..4], &[1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn write_packet() {
+        use crate::blocks::options::OptionIfTsResol;
+        use crate::utils::NANO_SECOND_TSRES;
+        let tsresol = OptionIfTsResol::new_option(NANO_SECOND_TSRES);
+        let mut idb_opts = Options::new();
+        idb_opts.add_option(&tsresol);
+        let mut buf = vec![];
+        let mut writer = PcapNgWriter::new_be(&mut buf);
+        let micro = writer
+            .add_interface(enums::LinkType::Ethernet, 1500, &Options::new())
+            .unwrap();
+        let nano = writer
+            .add_interface(enums::LinkType::Ethernet, 1500, &idb_opts)
+            .unwrap();
+        writer.write_packet(micro, 5_000, &[9; 3]).unwrap();
+        writer.write_packet(nano, 5_000, &[9; 3]).unwrap();
+        assert!(matches!(
+            writer.write_packet(InterfaceId(2), 5_000, &[9; 3]),
+            Err(Error::UnknownInterface(2))
+        ));
+        drop(writer);
+        let epbs = &buf[20 + 28..];
+        assert_eq!(epbs.len(), 2 * 36);
+        // timestamp (low) and captured/original lengths
+        assert_eq!(&epbs[16..28], &[0, 0, 0, 5, 0, 0, 0, 3, 0, 0, 0, 3]);
+        assert_eq!(
+            &epbs[36 + 8..36 + 20],
+            &[0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0x13, 0x88]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn write_packet_with() {
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Big, vec![]);
+        let id = writer
+            .add_interface(enums::LinkType::Raw, 0, &Options::new())
+            .unwrap();
+        writer
+            .write_packet_with(id, 0, |buf| {
+                buf.extend_from_slice(&[0x45, 0, 0, 20]);
+                buf.write_all(&[0; 16])
+            })
+            .unwrap();
+        assert_eq!(writer.stats().total_packets(), 1);
+        let result = writer.write_packet_with(id, 0, |_| Err("checksum failed"));
+        assert!(matches!(result, Err(Error::Io(e)) if e.to_string() == "checksum failed"));
+        assert_eq!(writer.stats().total_packets(), 1);
+    }
+
+    #[test]
+    fn write_packet_with_ticks() {
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Big, vec![]);
+        let id = writer
+            .add_interface(enums::LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        let ts = Timestamp::from_parts(7, 0x0102_0304);
+        writer
+            .write_packet_with_ticks(id, ts, 60, &[9; 3], &Options::new())
+            .unwrap();
+        let epb = &writer.get_writer()[28 + 20..];
+        assert_eq!(&epb[12..20], &[0, 0, 0, 7, 1, 2, 3, 4]);
+        assert_eq!(&epb[20..28], &[0, 0, 0, 3, 0, 0, 0, 60]);
+    }
+
+    #[test]
+    fn snap_len() {
+        let opts = Options::new();
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        let short = writer
+            .add_interface(enums::LinkType::Ethernet, 4, &opts)
+            .unwrap();
+        let unlimited = writer
+            .add_interface(enums::LinkType::Ethernet, 0, &opts)
+            .unwrap();
+        writer.write_packet(short, 0, &[9; 6]).unwrap();
+        writer.write_packet(unlimited, 0, &[9; 6]).unwrap();
+        writer.set_validation(Validation::Strict);
+        writer.write_packet(short, 0, &[9; 4]).unwrap();
+        assert!(matches!(
+            writer.write_packet(short, 0, &[9; 6]),
+            Err(Error::SnapLenExceeded(6))
+        ));
+
+        let epbs = &writer.get_writer()[28 + 2 * 20..];
+        // captured and original length, then the data
+        assert_eq!(&epbs[20..32], &[4, 0, 0, 0, 6, 0, 0, 0, 9, 9, 9, 9]);
+        assert_eq!(&epbs[36 + 20..36 + 28], &[6, 0, 0, 0, 6, 0, 0, 0]);
+        assert_eq!(epbs.len(), 36 + 40 + 36);
+    }
+
+    #[test]
+    fn slice_len() {
+        let opts = Options::new();
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        writer.set_validation(Validation::Strict);
+        writer.set_slice_len(Some(5));
+        let eth = writer
+            .add_interface(enums::LinkType::Ethernet, 8, &opts)
+            .unwrap();
+        writer.write_packet(eth, 0, &[9; 3]).unwrap();
+        writer.write_packet(eth, 0, &[9; 6]).unwrap();
+        writer.set_slice_len(None);
+        writer.write_packet(eth, 0, &[9; 6]).unwrap();
+
+        let epbs = &writer.get_writer()[28 + 20..];
+        assert_eq!(&epbs[20..28], &[3, 0, 0, 0, 3, 0, 0, 0]);
+        assert_eq!(&epbs[36 + 20..36 + 28], &[5, 0, 0, 0, 6, 0, 0, 0]);
+        assert_eq!(&epbs[36 + 40 + 20..36 + 40 + 28], &[6, 0, 0, 0, 6, 0, 0, 0]);
+        assert_eq!(epbs.len(), 36 + 40 + 40);
+    }
+
+    #[test]
+    fn strict_cap_len() {
+        let opts = Options::new();
+        let epb = EnhancedPacketBlock::new(0, 0, 0, 10, 10, &[9; 4], &opts);
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        writer
+            .add_interface(enums::LinkType::Ethernet, 0, &opts)
+            .unwrap();
+        writer.write(&epb).unwrap();
+        writer.set_validation(Validation::Strict);
+        assert!(matches!(
+            writer.write(&epb),
+            Err(Error::CapLenMismatch {
+                declared: 10,
+                actual: 4
+            })
+        ));
+        assert_eq!(writer.stats().total_blocks(), 3);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn option_too_long() {
+        let comment = OptionComment::new_option(&"a".repeat(70_000));
+        let mut long = Options::new();
+        long.add_option(&comment);
+        for filtered in [false, true] {
+            let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+            if filtered {
+                writer.add_filter(|_: &mut crate::pipeline::EncodedBlock| {
+                    crate::pipeline::Verdict::Keep
+                });
+            }
+            let eth = writer
+                .add_interface(enums::LinkType::Ethernet, 0, &Options::new())
+                .unwrap();
+            let len = writer.get_writer().len();
+            assert!(matches!(
+                writer.write_packet_with_options(eth, 0, 4, &[9; 4], &long),
+                Err(Error::Option(OptionError::ValueTooLong(70_000)))
+            ));
+            assert!(matches!(
+                writer.add_interface(enums::LinkType::Ethernet, 0, &long),
+                Err(Error::Option(OptionError::ValueTooLong(70_000)))
+            ));
+            assert!(matches!(
+                writer.start_new_section(&long),
+                Err(Error::Option(OptionError::ValueTooLong(70_000)))
+            ));
+            assert_eq!(writer.get_writer().len(), len);
+
+            writer.write_packet(eth, 0, &[9; 4]).unwrap();
+            let blocks: Vec<_> = SliceReader::new(writer.get_writer())
+                .map(|block| block.unwrap())
+                .collect();
+            assert_eq!(blocks.len(), 3);
+        }
+    }
+
+    #[test]
+    fn interface_ids() {
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        writer
+            .write(&InterfaceDescriptionBlock::new(
+                enums::LinkType::Raw,
+                0,
+                &Options::new(),
+            ))
+            .unwrap();
+        let eth = writer
+            .add_interface(enums::LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        assert_eq!(eth.value(), 1);
+        assert!(writer.interface(InterfaceId(0)).is_none());
+        assert_eq!(
+            writer.interface(eth).unwrap().link_type(),
+            enums::LinkType::Ethernet.value()
+        );
+        // Simple Packet Blocks refer to the raw interface
+        writer
+            .write_record(PacketRecord::untimed(eth, &[0; 4]))
+            .unwrap();
+        assert_eq!(writer.stats().blocks(BlockType::SimplePacket), 0);
+
+        writer
+            .write(&SectionHeaderBlock::new_with_defaults(&Options::new()))
+            .unwrap();
+        assert!(writer.interfaces().is_empty());
+        let eth = writer
+            .add_interface(enums::LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        assert_eq!(eth.value(), 0);
+    }
+
+    #[test]
+    fn strict_interface_id() {
+        use crate::blocks::{InterfaceStatisticsBlock, SimplePacketBlock};
+
+        let opts = Options::new();
+        let mut writer = PcapNgWriter::new_le(vec![]);
+        writer.set_validation(Validation::Strict);
+        let shb = SectionHeaderBlock::new_with_defaults(&opts);
+        let idb = InterfaceDescriptionBlock::new(enums::LinkType::Ethernet, 0, &opts);
+        let epb = |id| EnhancedPacketBlock::new(id, 0, 0, 4, 4, &[9; 4], &opts);
+        let spb = SimplePacketBlock::new(4, &[9; 4]);
+        writer.write(&shb).unwrap();
+        assert!(matches!(
+            writer.write(&spb),
+            Err(Error::UnknownInterface(0))
+        ));
+        writer.write(&idb).unwrap();
+        writer.write(&epb(0)).unwrap();
+        writer.write(&spb).unwrap();
+        let eth = writer
+            .add_interface(enums::LinkType::Ethernet, 0, &opts)
+            .unwrap();
+        writer.write(&epb(1)).unwrap();
+        assert!(matches!(
+            writer.write(&epb(2)),
+            Err(Error::UnknownInterface(2))
+        ));
+        assert!(matches!(
+            writer.write(&InterfaceStatisticsBlock::new(2, 0, 0, &opts)),
+            Err(Error::UnknownInterface(2))
+        ));
+        // interface IDs are scoped to a section
+        writer.write(&shb).unwrap();
+        assert!(matches!(
+            writer.write(&epb(0)),
+            Err(Error::UnknownInterface(0))
+        ));
+        writer.start_new_section(&opts).unwrap();
+        assert!(writer.write_packet(eth, 0, &[9; 4]).is_err());
+    }
+
+    #[test]
+    fn validation() {
+        use crate::blocks::options::OptionEndOfOpt;
+        use crate::blocks::RawBlock;
+
+        let comment = OptionComment::new_option("abc");
+        let end = OptionEndOfOpt::new_option();
+        let mut bad_opts = Options::new();
+        bad_opts.add_option(&end);
+        bad_opts.add_option(&comment);
+        let opts = Options::new();
+        let idb = InterfaceDescriptionBlock::new(enums::LinkType::Ethernet, 0, &opts);
+        let bad_idb = InterfaceDescriptionBlock::new(enums::LinkType::Ethernet, 0, &bad_opts);
+        let raw = RawBlock::new(0xBAD, 16, 16, &[0; 4]);
+        let bad_raw = RawBlock::new(0xBAD, 15, 15, &[0; 3]);
+
+        let mut writer = PcapNgWriter::new_le(vec![]);
+        writer.set_validation(Validation::Strict);
+        assert!(matches!(
+            writer.write(&idb),
+            Err(Error::MissingSectionHeader)
+        ));
+        writer
+            .write(&SectionHeaderBlock::new_with_defaults(&opts))
+            .unwrap();
+        assert!(matches!(
+            writer.write(&bad_idb),
+            Err(Error::Option(OptionError::EndOfOptNotLast))
+        ));
+        writer.write(&raw).unwrap();
+        assert!(matches!(
+            writer.write(&bad_raw),
+            Err(Error::InvalidBlockLength(15))
+        ));
+        assert_eq!(writer.stats().total_blocks(), 2);
+
+        let mut writer = PcapNgWriter::new_le(vec![]);
+        writer.set_validation(Validation::Warn);
+        writer.write(&idb).unwrap();
+        writer
+            .write(&SectionHeaderBlock::new_with_defaults(&opts))
+            .unwrap();
+        writer.write(&bad_raw).unwrap();
+        assert_eq!(writer.position(), 20 + 28 + 15);
+        let warnings = writer.take_warnings();
+        assert!(matches!(
+            warnings[..],
+            [Error::MissingSectionHeader, Error::InvalidBlockLength(15)]
+        ));
+        assert!(writer.warnings().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn sections_with_endianness() {
+        use crate::enums::BlockType;
+        use crate::framing::BlockFramer;
+
+        let opts = Options::new();
+        let mut buf = vec![];
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, &mut buf);
+        let id = writer
+            .add_interface(enums::LinkType::Ethernet, 0xFFFF, &opts)
+            .unwrap();
+        writer.write_packet(id, 0, &[1; 4]).unwrap();
+        writer
+            .start_new_section_with_endianness(Endianness::Big, &opts)
+            .unwrap();
+        assert_eq!(writer.section_count(), 2);
+        assert!(writer.interfaces().is_empty());
+        assert!(writer.write_packet(id, 0, &[2; 4]).is_err());
+        let id = writer
+            .add_interface(enums::LinkType::Raw, 0xFFFF, &opts)
+            .unwrap();
+        assert_eq!(id.value(), 0);
+        writer.write_packet(id, 0, &[2; 4]).unwrap();
+
+        let mut framer = BlockFramer::new();
+        drop(writer);
+        framer.push(&buf);
+        let mut frames = vec![];
+        while let Some(frame) = framer.next_frame().unwrap() {
+            frames.push(frame);
+        }
+        let types: Vec<_> = frames.iter().map(|f| f.block_type).collect();
+        let shb = BlockType::SectionHeader.value();
+        let idb = BlockType::InterfaceDescription.value();
+        let epb = BlockType::EnhancedPacket.value();
+        assert_eq!(types, vec![shb, idb, epb, shb, idb, epb]);
+        assert_eq!(&frames[0].bytes[8..12], b"\x4D\x3C\x2B\x1A");
+        assert_eq!(&frames[3].bytes[8..12], b"\x1A\x2B\x3C\x4D");
+        assert_eq!(&frames[4].bytes[8..10], b"\x00\x65");
+    }
+
+    #[test]
+    fn endianness() {
+        let writer = PcapNgWriter::new_native(vec![]);
+        #[cfg(target_endian = "little")]
+        assert_eq!(writer.endianness(), Endianness::Little);
+        #[cfg(target_endian = "big")]
+        assert_eq!(writer.endianness(), Endianness::Big);
+
+        let mut writer = PcapNgWriter::new_be(vec![]);
+        assert_eq!(writer.endianness(), Endianness::Big);
+        writer
+            .start_new_section_with_endianness(Endianness::Little, &Options::new())
+            .unwrap();
+        assert_eq!(writer.endianness(), Endianness::Little);
+    }
+
+    #[test]
+    fn encode_reuse() {
+        let comment = OptionComment::new_option("comment");
+        let mut opts = Options::new();
+        opts.add_option(&comment);
+        let write = |reuse: bool| {
+            let mut writer = PcapNgWriter::new_auto_section(Endianness::Big, vec![]);
+            writer.set_validation(Validation::Strict);
+            let eth = writer
+                .add_interface(LinkType::Ethernet, 100, &Options::new())
+                .unwrap();
+            for len in [100, 3, 50] {
+                let epb = EnhancedPacketBlock::new(
+                    eth.value(),
+                    0,
+                    7,
+                    len,
+                    len,
+                    &[1; 100][..len as usize],
+                    &opts,
+                );
+                if reuse {
+                    writer.encode_reuse(&epb).unwrap();
+                } else {
+                    writer.write(&epb).unwrap();
+                }
+            }
+            let unknown = EnhancedPacketBlock::new(1, 0, 0, 1, 1, &[1], &opts);
+            let result = if reuse {
+                writer.encode_reuse(&unknown)
+            } else {
+                writer.write(&unknown)
+            };
+            assert!(matches!(result, Err(Error::UnknownInterface(1))));
+            assert!(writer.encoder.capacity() >= if reuse { 144 } else { 0 });
+            assert_eq!(writer.stats().total_packets(), 3);
+            writer.get_writer().clone()
+        };
+        assert_eq!(write(true), write(false));
+
+        let mut encoder = Encoder::with_capacity(64);
+        let sized = encoder
+            .encode(
+                Endianness::Little,
+                &SectionHeaderBlock::new_with_defaults(&opts),
+            )
+            .unwrap()
+            .len();
+        assert_eq!(sized, 40);
+        assert_eq!(encoder.capacity(), 64);
+    }
+
+    #[test]
+    fn write_packets() {
+        let comment = OptionComment::new_option("comment");
+        let mut opts = Options::new();
+        opts.add_option(&comment);
+        let packets: Vec<(u128, Vec<u8>)> = (0..5u8)
+            .map(|i| (u128::from(i) * 1000, vec![i; usize::from(i) + 60]))
+            .collect();
+
+        let mut expected = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        let eth = expected
+            .add_interface(LinkType::Ethernet, 62, &Options::new())
+            .unwrap();
+        for (ts, data) in &packets {
+            expected
+                .write_packet_with_options(eth, *ts, 100, data, &opts)
+                .unwrap();
+        }
+
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        let eth = writer
+            .add_interface(LinkType::Ethernet, 62, &Options::new())
+            .unwrap();
+        writer.write_packets(Vec::new()).unwrap();
+        let offset = writer.position();
+        writer
+            .write_packets(packets.iter().map(|(ts, data)| {
+                PacketRecord::new(eth, *ts, data)
+                    .with_orig_len(100)
+                    .with_options(&opts)
+            }))
+            .unwrap();
+        assert_eq!(writer.get_writer(), expected.get_writer());
+        assert_eq!(writer.last_block_offset(), expected.last_block_offset());
+        assert!(writer.last_block_offset().unwrap() > offset);
+        assert_eq!(writer.stats().total_packets(), 5);
+
+        // nothing is written if a packet fails
+        writer.set_validation(Validation::Strict);
+        let len = writer.get_writer().len();
+        let records = [
+            PacketRecord::new(eth, 0, &[0; 10]),
+            PacketRecord::new(eth, 0, &[0; 70]),
+        ];
+        assert!(matches!(
+            writer.write_packets(records.iter().copied()),
+            Err(Error::SnapLenExceeded(70))
+        ));
+        assert_eq!(writer.get_writer().len(), len);
+
+        // nor are the warnings of the packets before it
+        writer.set_validation(Validation::Warn);
+        let records = [
+            PacketRecord::new(eth, 0, &[0; 70]),
+            PacketRecord::new(InterfaceId(1), 0, &[0; 10]),
+        ];
+        assert!(matches!(
+            writer.write_packets(records.iter().copied()),
+            Err(Error::UnknownInterface(1))
+        ));
+        assert_eq!(writer.get_writer().len(), len);
+        assert!(writer.warnings().is_empty());
+        writer.write_packets(records[..1].iter().copied()).unwrap();
+        assert!(matches!(writer.warnings(), [Error::SnapLenExceeded(70)]));
+    }
+
+    #[test]
+    fn write_record() {
+        let comment = OptionComment::new_option("comment");
+        let mut opts = Options::new();
+        opts.add_option(&comment);
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        let eth = writer
+            .add_interface(LinkType::Ethernet, 64, &Options::new())
+            .unwrap();
+        let lo = writer
+            .add_interface(LinkType::Null, 64, &Options::new())
+            .unwrap();
+        let blocks = |writer: &PcapNgWriter<Vec<u8>>| {
+            let stats = writer.stats();
+            (
+                stats.blocks(BlockType::SimplePacket),
+                stats.blocks(BlockType::EnhancedPacket),
+            )
+        };
+
+        writer
+            .write_record(PacketRecord::untimed(eth, &[0; 60]))
+            .unwrap();
+        writer
+            .write_record(PacketRecord::untimed(eth, &[0; 60]).with_orig_len(100))
+            .unwrap();
+        writer
+            .write_record(PacketRecord::untimed(eth, &[0; 70]).with_orig_len(100))
+            .unwrap();
+        assert_eq!(blocks(&writer), (2, 1));
+        writer
+            .write_record(PacketRecord::new(eth, 5, &[0; 60]))
+            .unwrap();
+        writer
+            .write_record(PacketRecord::untimed(eth, &[0; 60]).with_options(&opts))
+            .unwrap();
+        writer
+            .write_record(PacketRecord::untimed(lo, &[0; 60]))
+            .unwrap();
+        assert_eq!(blocks(&writer), (2, 4));
+        writer.set_slice_len(Some(32));
+        writer
+            .write_record(PacketRecord::untimed(eth, &[0; 60]))
+            .unwrap();
+        assert_eq!(blocks(&writer), (2, 5));
+        assert_eq!(writer.stats().total_packets(), 7);
+
+        let data = writer.get_writer();
+        let spbs: Vec<_> = SliceReader::new(data)
+            .filter_map(|block| match block.unwrap() {
+                Block::SimplePacket(spb) => Some((spb.orig_len(), spb.packet_data().len())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(spbs, [(60, 60), (100, 64)]);
+    }
+
+    #[test]
+    fn encode_to_slice() {
+        use crate::blocks::{InterfaceStatisticsBlock, SimplePacketBlock};
+
+        fn check<T: EncodeToSlice + EncodedLen + Encodable<Vec<u8>>>(block: &T) {
+            let mut expected = vec![];
+            block.encode::<BigEndian>(&mut expected).unwrap();
+            assert_eq!(block.encoded_len(), expected.len());
+            let mut buf = [0xFF; 256];
+            let n = block.encode_to_slice::<BigEndian>(&mut buf).unwrap();
+            assert_eq!(&buf[..n], &expected[..]);
+            assert!(matches!(
+                block.encode_to_slice::<BigEndian>(&mut buf[..n - 1]),
+                Err(Error::BufferTooSmall(len)) if len == n - 1
+            ));
+        }
+
+        let comment = OptionComment::new_option("comment");
+        let mut opts = Options::new();
+        opts.add_option(&comment);
+        check(&SectionHeaderBlock::new_with_defaults(&opts));
+        check(&InterfaceDescriptionBlock::new(
+            enums::LinkType::Ethernet,
+            0xFFFF,
+            &opts,
+        ));
+        check(&EnhancedPacketBlock::new(0, 1, 2, 5, 5, &[1; 5], &opts));
+        check(&SimplePacketBlock::new(5, &[1; 5]));
+        check(&InterfaceStatisticsBlock::new(0, 1, 2, &opts));
+        check(&crate::blocks::RawBlock::new(0x0BAD, 16, 16, &[0; 4]));
+        check(&comment);
+        check(&opts);
+    }
+
+    #[test]
+    fn offsets() {
+        let opts = Options::new();
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        assert_eq!(writer.position(), 0);
+        assert_eq!(writer.last_block_offset(), None);
+        let id = writer
+            .add_interface(enums::LinkType::Ethernet, 0xFFFF, &opts)
+            .unwrap();
+        assert_eq!(writer.last_block_offset(), Some(28));
+        let mut index = vec![];
+        for i in 0..3u8 {
+            writer.write_packet(id, 0, &[i; 4]).unwrap();
+            index.push(writer.last_block_offset().unwrap());
+        }
+        assert_eq!(index, vec![48, 84, 120]);
+        assert_eq!(writer.position(), 156);
+        let buf = writer.get_writer();
+        assert_eq!(buf.len(), 156);
+        // the last byte of the packet data of the second packet
+        assert_eq!(buf[index[1] as usize + 28 + 3], 1);
+    }
+
+    #[test]
+    fn stats() {
+        use crate::blocks::RawBlock;
+        use crate::enums::BlockType;
+
+        let opts = Options::new();
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Big, vec![]);
+        let eth = writer
+            .add_interface(enums::LinkType::Ethernet, 0xFFFF, &opts)
+            .unwrap();
+        let raw = writer
+            .add_interface(enums::LinkType::Raw, 0xFFFF, &opts)
+            .unwrap();
+        writer.write_packet(eth, 0, &[1; 4]).unwrap();
+        writer.write_packet(raw, 0, &[2; 4]).unwrap();
+        writer.write_packet(raw, 0, &[3; 4]).unwrap();
+        writer
+            .write(&RawBlock::new(0x0BAD, 16, 16, &[0; 4]))
+            .unwrap();
+
+        let stats = writer.stats();
+        assert_eq!(stats.bytes(), writer.get_writer().len() as u64);
+        assert_eq!(stats.blocks(BlockType::SectionHeader), 1);
+        assert_eq!(stats.blocks(BlockType::InterfaceDescription), 2);
+        assert_eq!(stats.blocks(BlockType::EnhancedPacket), 3);
+        assert_eq!(stats.blocks(BlockType::Unknown(0x0BAD)), 1);
+        assert_eq!(stats.total_blocks(), 7);
+        assert_eq!(stats.packets(eth), 1);
+        assert_eq!(stats.packets(raw), 2);
+        assert_eq!(stats.total_packets(), 3);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn finalize_section() {
+        use std::io::Cursor;
+
+        let opts = Options::new();
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Big, Cursor::new(vec![]));
+        let id = writer
+            .add_interface(enums::LinkType::Ethernet, 0xFFFF, &opts)
+            .unwrap();
+        writer.write_packet(id, 0, &[1; 4]).unwrap();
+        writer.finalize_section().unwrap();
+        writer
+            .start_new_section_with_endianness(Endianness::Little, &opts)
+            .unwrap();
+        writer.finalize_section().unwrap();
+        // writing continues at the end
+        writer
+            .write(&SectionHeaderBlock::new_with_defaults(&opts))
+            .unwrap();
+
+        let buf = writer.get_writer().get_ref();
+        assert_eq!(buf.len(), 28 + 20 + 36 + 28 + 28);
+        // IDB + EPB
+        assert_eq!(&buf[16..24], &56u64.to_be_bytes());
+        // empty section
+        assert_eq!(&buf[84 + 16..84 + 24], &0u64.to_le_bytes());
+        // SHB written with `write` is left alone
+        assert_eq!(&buf[112 + 16..112 + 24], &[0xFF; 8]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn finalize_section_after_written_shb() {
+        use std::io::Cursor;
+
+        let opts = Options::new();
+        let mut writer = PcapNgWriter::new_le(Cursor::new(vec![]));
+        writer.start_new_section(&opts).unwrap();
+        writer
+            .write(&SectionHeaderBlock::new_with_defaults(&opts))
+            .unwrap();
+        writer
+            .add_interface(enums::LinkType::Ethernet, 0xFFFF, &opts)
+            .unwrap();
+        writer.finalize_section().unwrap();
+
+        // the section was not started by the writer, so neither
+        // header is patched
+        let buf = writer.get_writer().get_ref();
+        assert_eq!(buf.len(), 28 + 28 + 20);
+        assert_eq!(&buf[16..24], &[0xFF; 8]);
+        assert_eq!(&buf[28 + 16..28 + 24], &[0xFF; 8]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn finalize_section_with_held_blocks() {
+        use crate::pipeline::ReorderBuffer;
+        use std::io::Cursor;
+        use std::time::Duration;
+
+        let opts = Options::new();
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, Cursor::new(vec![]));
+        writer.add_filter(ReorderBuffer::new(Duration::from_secs(1)));
+        writer.set_finalize_on_close(true);
+        let id = writer
+            .add_interface(enums::LinkType::Ethernet, 0xFFFF, &opts)
+            .unwrap();
+        writer.write_packet(id, 20, &[1; 4]).unwrap();
+        writer.write_packet(id, 10, &[2; 4]).unwrap();
+        writer.close().unwrap();
+
+        // the packets held by the buffer are part of the section
+        let buf = writer.get_writer().get_ref();
+        assert_eq!(buf.len(), 28 + 20 + 2 * 36);
+        assert_eq!(&buf[16..24], &92u64.to_le_bytes());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn close() {
+        use std::io::Cursor;
+
+        let opts = Options::new();
+        let mut buf = Cursor::new(vec![]);
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, &mut buf);
+        writer.set_final_statistics(true);
+        writer.set_finalize_on_close(true);
+        let id = writer
+            .add_interface(enums::LinkType::Ethernet, 0xFFFF, &opts)
+            .unwrap();
+        writer.write_packet(id, 0, &[1; 4]).unwrap();
+        writer.close().unwrap();
+        let len = writer.position();
+        // closing again does nothing
+        writer.close().unwrap();
+        assert_eq!(writer.position(), len);
+        assert_eq!(
+            writer.stats().blocks(enums::BlockType::InterfaceStatistics),
+            1
+        );
+        // writing reopens the capture, dropping closes it again
+        writer.write_packet(id, 0, &[1; 4]).unwrap();
+        drop(writer);
+
+        let buf = buf.into_inner();
+        // SHB, IDB, EPB, ISB, EPB, ISB
+        assert_eq!(buf.len(), 28 + 20 + 36 + 24 + 36 + 24);
+        assert_eq!(&buf[16..24], &(buf.len() as u64 - 28).to_le_bytes());
+        assert_eq!(&buf[buf.len() - 24..buf.len() - 20], &[5, 0, 0, 0]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn names() {
+        use std::net::Ipv4Addr;
+
+        let addr = IpAddr::from(Ipv4Addr::new(192, 0, 2, 1));
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        let eth = writer
+            .add_interface(enums::LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        writer.add_name(addr, "a.example").unwrap();
+        writer.write_packet(eth, 0, &[0; 4]).unwrap();
+        // no interval: batched until flushed
+        assert_eq!(writer.stats().blocks(BlockType::NameResolution), 0);
+        writer.flush_names().unwrap();
+        writer.set_names_interval(Some(Duration::from_secs(0)));
+        writer.add_name(addr, "a.example").unwrap();
+        writer.add_name(addr, "b.example").unwrap();
+        assert_eq!(writer.stats().blocks(BlockType::NameResolution), 2);
+        // all the names are written again in a new section
+        writer.start_new_section(&Options::new()).unwrap();
+        writer.close().unwrap();
+        assert_eq!(writer.name_cache().names(addr), ["a.example", "b.example"]);
+
+        let nrbs: Vec<_> = SliceReader::new(writer.get_writer())
+            .filter_map(|block| match block.unwrap() {
+                Block::Unknown(raw) if raw.block_type() == 4 => Some(raw.body().to_vec()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(nrbs.len(), 3);
+        assert_eq!(&nrbs[0][..4], &[1, 0, 14, 0]);
+        assert_eq!(&nrbs[0][4..18], b"\xc0\x00\x02\x01a.example\0");
+        assert_eq!(&nrbs[1][8..18], b"b.example\0");
+        assert_eq!(&nrbs[2][..4], &[1, 0, 24, 0]);
+    }
+
+    #[test]
+    fn auto_section() {
+        let opts = Options::new();
+        let mut buf = vec![];
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, &mut buf);
+        writer
+            .add_interface(enums::LinkType::Ethernet, 1500, &opts)
+            .unwrap();
+        writer.start_new_section(&opts).unwrap();
+        assert!(writer.interfaces().is_empty());
+        let id = writer
+            .add_interface(enums::LinkType::Ethernet, 1500, &opts)
+            .unwrap();
+        assert_eq!(id.value(), 0);
+        // SHB, IDB, SHB, IDB
+        drop(writer);
+        assert_eq!(buf.len(), 2 * 28 + 2 * 20);
+        assert_eq!(&buf[..4], &[0xa, 0xd, 0xd, 0xa]);
+        assert_eq!(&buf[28..32], &[1, 0, 0, 0]);
+        assert_eq!(&buf[48..52], &[0xa, 0xd, 0xd, 0xa]);
+    }
+
     #[test]
     fn round_trip_le() {
         let opts = Options::new();
@@ -111,36 +2335,26 @@ mod tests {
             writer.write(&ehp).unwrap();
         }
 
-        if let IResult::Done(_, blocks) = pcapng::block::parse_blocks(&buf[..]) {
-            for raw in blocks {
-                if let IResult::Done(_, block) = raw.parse() {
-                    match block {
-                        pcapng::block::Block::SectionHeader(parsed_shb) => {
-                            *counts.entry(enums::BlockType::SectionHeader).or_insert(0) += 1;
-                            assert_eq!(
-                                parsed_shb.section_length,
-                                pcapng::blocks::section_header::SectionLength::Unspecified
-                            );
-                        }
-                        pcapng::block::Block::InterfaceDescription(_) => {
-                            *counts
-                                .entry(enums::BlockType::InterfaceDescription)
-                                .or_insert(0) += 1;
-                        }
-                        pcapng::block::Block::EnhancedPacket(parsed_epb) => {
-                            *counts.entry(enums::BlockType::EnhancedPacket).or_insert(0) += 1;
-                            assert_eq!(parsed_epb.interface_id, 1);
-                        }
-                        _ => {
-                            panic!();
-                        }
-                    }
-                } else {
-                    panic!()
+        drop(writer);
+        for block in SliceReader::new(&buf) {
+            match block.unwrap() {
+                Block::SectionHeader(parsed_shb) => {
+                    *counts.entry(enums::BlockType::SectionHeader).or_insert(0) += 1;
+                    assert_eq!(parsed_shb.section_length(), None);
+                }
+                Block::InterfaceDescription(_) => {
+                    *counts
+                        .entry(enums::BlockType::InterfaceDescription)
+                        .or_insert(0) += 1;
+                }
+                Block::EnhancedPacket(parsed_epb) => {
+                    *counts.entry(enums::BlockType::EnhancedPacket).or_insert(0) += 1;
+                    assert_eq!(parsed_epb.interface_id(), 1);
+                }
+                _ => {
+                    panic!();
                 }
             }
-        } else {
-            panic!()
         }
         assert_eq!(counts[&enums::BlockType::SectionHeader], 1);
         assert_eq!(counts[&enums::BlockType::InterfaceDescription], 2);
@@ -148,10 +2362,11 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "std")]
     fn new_pcapng_file() {
         let opts = Options::new();
         use crate::blocks::options::{OptionComment, OptionEndOfOpt, OptionEpbFlags};
-        use crate::enums::{PacketDirection, ReceptionType};
+        use crate::enums::{EpbErrorFlags, PacketDirection, ReceptionType};
         let shb = SectionHeaderBlock::new_with_defaults(&opts);
         let p = b"\x00\x11\x22\x33\x44\x01\x00\x11\x22\x33\x44\x02\x08\x00\x45\x00\
                   \x00\x42\x88\x1f\x40\x00\x40\x11\x2f\x30\xc0\xa8\x01\x0a\xc0\xa8\
@@ -165,7 +2380,7 @@ mod tests {
             PacketDirection::Inbound,
             ReceptionType::Promiscuous,
             None,
-            0,
+            EpbErrorFlags::empty(),
         );
         let mut epb_options = Options::new();
         epb_options.add_option(&comment_opt);
@@ -181,8 +2396,8 @@ mod tests {
             &epb_options,
         );
         let idb = InterfaceDescriptionBlock::new(enums::LinkType::Ethernet, 1500, &opts);
-        let path = Path::new("/tmp/z.pcap");
-        let mut file = File::create(&path).unwrap();
+        let path = std::path::Path::new("/tmp/z.pcap");
+        let mut file = std::fs::File::create(path).unwrap();
         let mut writer = PcapNgWriter::new(Endianness::Big, &mut file);
         writer.write(&shb).unwrap();
         writer.write(&idb).unwrap();
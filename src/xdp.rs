@@ -0,0 +1,209 @@
+//! Recording AF_XDP frames.
+//!
+//! An AF_XDP socket receives packets in a umem, a memory region
+//! shared with the kernel, and hands out descriptors of the frames in
+//! its RX ring. `FrameWriter` writes the frames of those descriptors
+//! straight from the umem to a `PcapNgWriter`, so the payload is only
+//! copied once, into the output.
+//!
+//! The crate does not depend on an AF_XDP library; descriptors are
+//! built from the `addr` and `len` fields of `struct xdp_desc`, e.g.
+//! as read by `xsk-rs` or `libxdp`:
+//!
+//! ```no_run
+//! use pcapng_writer::blocks::options::Options;
+//! use pcapng_writer::enums::LinkType;
+//! use pcapng_writer::writer::{Endianness, PcapNgWriter};
+//! use pcapng_writer::xdp::{FrameWriter, XdpFrame};
+//! use std::time::{SystemTime, UNIX_EPOCH};
+//!
+//! # struct XdpDesc { addr: u64, len: u32 }
+//! # fn peek(_descs: &mut [XdpDesc]) -> usize { 0 }
+//! # fn release(_count: usize) {}
+//! # fn main() -> pcapng_writer::Result<()> {
+//! # let umem = vec![0; 4096 * 2048];
+//! # let mut descs: Vec<XdpDesc> = Vec::new();
+//! let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+//! let eth0 = writer.add_interface(LinkType::Ethernet, 0, &Options::new())?;
+//! // the umem area registered with the socket
+//! let frames = FrameWriter::new(&umem, eth0);
+//! let received = peek(&mut descs);
+//! let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+//! frames.write_frames(
+//!     &mut writer,
+//!     descs[..received].iter().map(|d| XdpFrame::new(d.addr, d.len, None)),
+//!     now,
+//! )?;
+//! release(received);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::Result;
+use crate::framing::invalid;
+use crate::writer::{InterfaceId, PacketRecord, PcapNgWriter};
+use std::convert::TryFrom;
+use std::io::Write;
+
+/// In unaligned chunk mode, the offset of the frame in its chunk is
+/// stored in the upper bits of the address.
+const UNALIGNED_OFFSET_SHIFT: u32 = 48;
+
+/// A frame of an AF_XDP RX descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XdpFrame {
+    addr: u64,
+    len: u32,
+    timestamp: Option<u64>,
+}
+
+impl XdpFrame {
+    /// Creates a frame from the address and length of a descriptor.
+    /// `timestamp` is the hardware receive timestamp of the packet,
+    /// if the XDP program stored one, in nanoseconds since the Unix
+    /// epoch.
+    pub fn new(addr: u64, len: u32, timestamp: Option<u64>) -> Self {
+        Self {
+            addr,
+            len,
+            timestamp,
+        }
+    }
+
+    pub fn addr(&self) -> u64 {
+        self.addr
+    }
+
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+
+    /// Returns the offset of the frame in the umem. Addresses of the
+    /// unaligned chunk mode are resolved.
+    pub fn offset(&self) -> u64 {
+        let base = self.addr & ((1 << UNALIGNED_OFFSET_SHIFT) - 1);
+        base + (self.addr >> UNALIGNED_OFFSET_SHIFT)
+    }
+}
+
+/// Writes AF_XDP frames of a umem as Enhanced Packet Blocks.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameWriter<'a> {
+    umem: &'a [u8],
+    interface: InterfaceId,
+}
+
+impl<'a> FrameWriter<'a> {
+    /// Creates a frame writer for the frames of `umem`, received on
+    /// `interface`.
+    pub fn new(umem: &'a [u8], interface: InterfaceId) -> Self {
+        Self { umem, interface }
+    }
+
+    pub fn interface(&self) -> InterfaceId {
+        self.interface
+    }
+
+    /// Returns the data of `frame`, or an error if it is not in the
+    /// umem.
+    pub fn frame_data(&self, frame: &XdpFrame) -> Result<&'a [u8]> {
+        let data = usize::try_from(frame.offset())
+            .ok()
+            .and_then(|start| self.umem.get(start..)?.get(..frame.len() as usize))
+            .ok_or_else(|| invalid("AF_XDP frame outside the umem"))?;
+        Ok(data)
+    }
+
+    /// Returns the record of `frame`. Frames without a hardware
+    /// timestamp are timestamped `now_nanos`, in nanoseconds since the
+    /// Unix epoch.
+    pub fn to_record(&self, frame: &XdpFrame, now_nanos: u128) -> Result<PacketRecord<'a>> {
+        let data = self.frame_data(frame)?;
+        let timestamp = frame.timestamp().map_or(now_nanos, u128::from);
+        Ok(PacketRecord::new(self.interface, timestamp, data))
+    }
+
+    /// Writes `frame`, see `to_record`.
+    pub fn write_frame<W: Write>(
+        &self,
+        writer: &mut PcapNgWriter<W>,
+        frame: &XdpFrame,
+        now_nanos: u128,
+    ) -> Result<()> {
+        writer.write_record(self.to_record(frame, now_nanos)?)
+    }
+
+    /// Writes a batch of frames, e.g. the descriptors of one RX ring
+    /// peek, and returns the number of frames written. Frames without
+    /// a hardware timestamp share `now_nanos`.
+    pub fn write_frames<W: Write, I: IntoIterator<Item = XdpFrame>>(
+        &self,
+        writer: &mut PcapNgWriter<W>,
+        frames: I,
+        now_nanos: u128,
+    ) -> Result<u64> {
+        let mut count = 0;
+        for frame in frames {
+            self.write_frame(writer, &frame, now_nanos)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::options::Options;
+    use crate::enums::LinkType;
+    use crate::error::Error;
+    use crate::reader::{Block, SliceReader};
+    use crate::writer::Endianness;
+
+    #[test]
+    fn unaligned_offset() {
+        assert_eq!(XdpFrame::new(4096, 60, None).offset(), 4096);
+        assert_eq!(XdpFrame::new((256 << 48) | 4096, 60, None).offset(), 4352);
+    }
+
+    #[test]
+    fn write_frames() {
+        let mut umem = vec![0; 3 * 2048];
+        umem[2048..2052].copy_from_slice(&[1, 2, 3, 4]);
+        umem[4352..4354].copy_from_slice(&[5, 6]);
+        let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, vec![]);
+        let eth = writer
+            .add_interface(LinkType::Ethernet, 0, &Options::new())
+            .unwrap();
+        let frames = FrameWriter::new(&umem, eth);
+        let batch = [
+            XdpFrame::new(2048, 4, None),
+            XdpFrame::new((256 << 48) | 4096, 2, Some(7)),
+        ];
+        let record = frames.to_record(&batch[1], 1_000).unwrap();
+        assert_eq!(record.timestamp_nanos(), Some(7));
+        assert_eq!(record.data(), [5, 6]);
+        assert_eq!(frames.write_frames(&mut writer, batch, 1_000).unwrap(), 2);
+        assert!(matches!(
+            frames.write_frame(&mut writer, &XdpFrame::new(6000, 500, None), 0),
+            Err(Error::Io(_))
+        ));
+        let buf = writer.get_writer().clone();
+        let packets: Vec<_> = SliceReader::new(&buf)
+            .filter_map(|b| match b.unwrap() {
+                Block::EnhancedPacket(epb) => Some((epb.timestamp(), epb.packet_data().to_vec())),
+                _ => None,
+            })
+            .collect();
+        // microsecond resolution by default
+        assert_eq!(packets, [(1, vec![1, 2, 3, 4]), (0, vec![5, 6])]);
+    }
+}
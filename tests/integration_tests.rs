@@ -1,14 +1,19 @@
+//! Writes capture files with `std::fs`.
+
+#![cfg(feature = "std")]
+
 use pcapng_writer::blocks::options::{
     OptionComment, OptionEndOfOpt, OptionEpbFlags, OptionIfTsResol, Options,
 };
 use pcapng_writer::blocks::{EnhancedPacketBlock, InterfaceDescriptionBlock, SectionHeaderBlock};
-use pcapng_writer::enums::{LinkType, PacketDirection, ReceptionType};
+use pcapng_writer::enums::{EpbErrorFlags, LinkType, PacketDirection, ReceptionType};
 use pcapng_writer::utils::DEFAULT_TSRES;
 use pcapng_writer::writer::*;
 use std::fs::{metadata, remove_file, File};
 use std::path::PathBuf;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+#[allow(clippy::needless_return)]
 fn get_test_dir() -> PathBuf {
     let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     d.push("tests");
@@ -35,7 +40,7 @@ fn pcapng_file_from_bytes() {
         PacketDirection::Inbound,
         ReceptionType::Promiscuous,
         None,
-        0,
+        EpbErrorFlags::empty(),
     );
 
     let mut epb_options = Options::new();
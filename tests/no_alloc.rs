@@ -0,0 +1,71 @@
+//! Checks that writing packets does not allocate once the writer has
+//! been set up.
+
+use pcapng_writer::blocks::options::{OptionComment, OptionEpbFlags, Options};
+use pcapng_writer::blocks::{EnhancedPacketBlock, SimplePacketBlock};
+use pcapng_writer::enums::LinkType;
+use pcapng_writer::writer::{Endianness, PcapNgWriter};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(|n| n.get())
+}
+
+#[test]
+fn write_packets_without_allocating() {
+    let comment = OptionComment::new_option("a comment");
+    let flags = OptionEpbFlags::new_option(
+        pcapng_writer::enums::PacketDirection::Inbound,
+        pcapng_writer::enums::ReceptionType::Unicast,
+        None,
+        pcapng_writer::enums::EpbErrorFlags::empty(),
+    );
+    let mut epb_opts = Options::new();
+    epb_opts.add_option(&comment);
+    epb_opts.add_option(&flags);
+    let no_opts = Options::new();
+    let data = [0xAB; 61];
+
+    let buf = Vec::with_capacity(1 << 20);
+    let mut writer = PcapNgWriter::new_auto_section(Endianness::Little, buf);
+    let id = writer
+        .add_interface(LinkType::Ethernet, 0xFFFF, &no_opts)
+        .unwrap();
+    // count each block type once, so the counters are allocated
+    writer.write_packet(id, 0, &data).unwrap();
+    writer.write(&SimplePacketBlock::new(61, &data)).unwrap();
+
+    let before = allocations();
+    for i in 0..100 {
+        writer.write_packet(id, i, &data).unwrap();
+        writer
+            .write_packet_with_options(id, i, 100, &data, &epb_opts)
+            .unwrap();
+        writer
+            .write(&EnhancedPacketBlock::new(0, 0, 0, 61, 61, &data, &epb_opts))
+            .unwrap();
+        writer.write(&SimplePacketBlock::new(61, &data)).unwrap();
+    }
+    assert_eq!(allocations(), before);
+}